@@ -1,12 +1,16 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::fmt::{self, Display, Formatter};
-use std::fs::{self, File};
-use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
-
-use super::summary::{parse_summary, Link, SectionNumber, Summary, SummaryItem};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use super::frontmatter::split_frontmatter;
+use super::summary::{
+    parse_summary, restart_section_numbers_per_part, Link, SectionNumber, Summary, SummaryItem,
+};
 use crate::config::BuildConfig;
 use crate::errors::*;
+use crate::utils;
 
 /// Load a book into memory from its `src/` directory.
 pub fn load_book<P: AsRef<Path>>(src_dir: P, cfg: &BuildConfig) -> Result<Book> {
@@ -18,43 +22,97 @@ pub fn load_book<P: AsRef<Path>>(src_dir: P, cfg: &BuildConfig) -> Result<Book>
         .with_context(|| "Couldn't open SUMMARY.md")?
         .read_to_string(&mut summary_content)?;
 
-    let summary = parse_summary(&summary_content).with_context(|| "Summary parsing failed")?;
+    let mut summary = parse_summary(&summary_content).with_context(|| "Summary parsing failed")?;
+
+    if cfg.restart_numbering_per_part {
+        restart_section_numbers_per_part(&mut summary.numbered_chapters);
+    }
+
+    let links = chapter_links(&summary);
+    for link in &links {
+        check_chapter_location(link)?;
+    }
 
     if cfg.create_missing {
-        create_missing(&src_dir, &summary).with_context(|| "Unable to create missing chapters")?;
+        create_missing(src_dir, &links).with_context(|| "Unable to create missing chapters")?;
+    } else {
+        for link in &links {
+            if let Some(ref location) = link.location {
+                if !src_dir.join(location).exists() {
+                    bail!(
+                        "{}Chapter file not found for \"{}\", {}",
+                        summary_line(link),
+                        link.name,
+                        location.display()
+                    );
+                }
+            }
+        }
     }
 
     load_book_from_disk(&summary, src_dir)
 }
 
-fn create_missing(src_dir: &Path, summary: &Summary) -> Result<()> {
+/// Every chapter linked to by the summary, parents before their children.
+fn chapter_links(summary: &Summary) -> Vec<&Link> {
     let mut items: Vec<_> = summary
         .prefix_chapters
         .iter()
         .chain(summary.numbered_chapters.iter())
         .chain(summary.suffix_chapters.iter())
+        .rev()
         .collect();
+    let mut links = Vec::new();
 
-    while !items.is_empty() {
-        let next = items.pop().expect("already checked");
-
+    while let Some(next) = items.pop() {
         if let SummaryItem::Link(ref link) = *next {
-            if let Some(ref location) = link.location {
-                let filename = src_dir.join(location);
-                if !filename.exists() {
-                    if let Some(parent) = filename.parent() {
-                        if !parent.exists() {
-                            fs::create_dir_all(parent)?;
-                        }
-                    }
-                    debug!("Creating missing file {}", filename.display());
-
-                    let mut f = File::create(&filename)?;
-                    writeln!(f, "# {}", link.name)?;
-                }
-            }
+            links.push(link);
+            items.extend(link.nested_items.iter().rev());
+        }
+    }
 
-            items.extend(&link.nested_items);
+    links
+}
+
+/// Chapters have to be inside the source directory, no matter whether they
+/// already exist or would be created.
+fn check_chapter_location(link: &Link) -> Result<()> {
+    let location = match link.location {
+        Some(ref location) => location,
+        None => return Ok(()),
+    };
+
+    let inside = location.components().all(|c| match c {
+        Component::Normal(_) | Component::CurDir => true,
+        _ => false,
+    });
+    ensure!(
+        inside,
+        "{}The chapter \"{}\" ({}) isn't inside the source directory",
+        summary_line(link),
+        link.name,
+        location.display()
+    );
+
+    Ok(())
+}
+
+/// Where the link to a chapter is in `SUMMARY.md`, as a prefix for error
+/// messages.
+fn summary_line(link: &Link) -> String {
+    link.line
+        .map(|line| format!("SUMMARY.md line {}: ", line))
+        .unwrap_or_default()
+}
+
+fn create_missing(src_dir: &Path, links: &[&Link]) -> Result<()> {
+    for link in links {
+        if let Some(ref location) = link.location {
+            let filename = src_dir.join(location);
+            if !filename.exists() {
+                debug!("Creating missing file {}", filename.display());
+                utils::fs::write_file(src_dir, location, format!("# {}\n", link.name).as_bytes())?;
+            }
         }
     }
 
@@ -90,8 +148,14 @@ impl Book {
         }
     }
 
+    /// Mutable access to the top-level sections of the book.
+    pub fn sections_mut(&mut self) -> &mut Vec<BookItem> {
+        &mut self.sections
+    }
+
     /// Recursively apply a closure to each item in the book, allowing you to
-    /// mutate them.
+    /// mutate them. The sub-items of a chapter are visited before the chapter
+    /// itself.
     ///
     /// # Note
     ///
@@ -105,11 +169,45 @@ impl Book {
         for_each_mut(&mut func, &mut self.sections);
     }
 
+    /// Like [`for_each_mut()`], but stops at the first item the closure fails
+    /// on and returns its error.
+    ///
+    /// [`for_each_mut()`]: #method.for_each_mut
+    pub fn try_for_each_mut<F>(&mut self, mut func: F) -> Result<()>
+    where
+        F: FnMut(&mut BookItem) -> Result<()>,
+    {
+        try_for_each_mut(&mut func, &mut self.sections)
+    }
+
     /// Append a `BookItem` to the `Book`.
     pub fn push_item<I: Into<BookItem>>(&mut self, item: I) -> &mut Self {
         self.sections.push(item.into());
         self
     }
+
+    /// A copy of the book without the chapters whose frontmatter sets
+    /// `draft = true` (see [`Chapter::is_draft()`]), or their sub-chapters.
+    /// This is the book the renderers get.
+    ///
+    /// [`Chapter::is_draft()`]: struct.Chapter.html#method.is_draft
+    pub fn without_drafts(&self) -> Book {
+        fn is_marked_draft(item: &BookItem) -> bool {
+            match *item {
+                BookItem::Chapter(ref ch) => ch.is_draft(),
+                _ => false,
+            }
+        }
+
+        let mut book = self.clone();
+        book.for_each_mut(|item| {
+            if let BookItem::Chapter(ref mut ch) = *item {
+                ch.sub_items.retain(|sub_item| !is_marked_draft(sub_item));
+            }
+        });
+        book.sections.retain(|item| !is_marked_draft(item));
+        book
+    }
 }
 
 pub fn for_each_mut<'a, F, I>(func: &mut F, items: I)
@@ -126,6 +224,22 @@ where
     }
 }
 
+fn try_for_each_mut<'a, F, I>(func: &mut F, items: I) -> Result<()>
+where
+    F: FnMut(&mut BookItem) -> Result<()>,
+    I: IntoIterator<Item = &'a mut BookItem>,
+{
+    for item in items {
+        if let BookItem::Chapter(ch) = item {
+            try_for_each_mut(func, &mut ch.sub_items)?;
+        }
+
+        func(item)?;
+    }
+
+    Ok(())
+}
+
 /// Enum representing any type of item which can be added to a book.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BookItem {
@@ -157,8 +271,18 @@ pub struct Chapter {
     pub sub_items: Vec<BookItem>,
     /// The chapter's location, relative to the `SUMMARY.md` file.
     pub path: Option<PathBuf>,
+    /// The chapter's source file, relative to the `SUMMARY.md` file. Unlike
+    /// `path`, preprocessors shouldn't change this (e.g. when turning a
+    /// `README.md` into `index.md`).
+    pub source_path: Option<PathBuf>,
     /// An ordered list of the names of each chapter above this one, in the hierarchy.
     pub parent_names: Vec<String>,
+    /// The keys of the TOML frontmatter (delimited by `+++` lines) the
+    /// chapter's file started with, if any. The frontmatter itself isn't part
+    /// of `content`. The `links` preprocessor sets the `title` of chapters
+    /// with a `{{#title ...}}`.
+    #[serde(default)]
+    pub metadata: BTreeMap<String, serde_json::Value>,
 }
 
 impl Chapter {
@@ -169,10 +293,12 @@ impl Chapter {
         path: P,
         parent_names: Vec<String>,
     ) -> Chapter {
+        let path: PathBuf = path.into();
         Chapter {
             name: name.to_string(),
             content,
-            path: Some(path.into()),
+            path: Some(path.clone()),
+            source_path: Some(path),
             parent_names,
             ..Default::default()
         }
@@ -185,6 +311,7 @@ impl Chapter {
             name: name.to_string(),
             content: String::new(),
             path: None,
+            source_path: None,
             parent_names,
             ..Default::default()
         }
@@ -192,10 +319,13 @@ impl Chapter {
 
     /// Check if the chapter is a draft chapter, meaning it has no path to a source markdown file
     pub fn is_draft_chapter(&self) -> bool {
-        match self.path {
-            Some(_) => false,
-            None => true,
-        }
+        self.path.is_none()
+    }
+
+    /// Check if the chapter's frontmatter sets `draft = true`, which leaves it
+    /// and its sub-chapters out of the rendered book
+    pub fn is_draft(&self) -> bool {
+        self.metadata.get("draft") == Some(&serde_json::Value::Bool(true))
     }
 }
 
@@ -264,11 +394,26 @@ fn load_chapter<P: AsRef<Path>>(
             format!("Unable to read \"{}\" ({})", link.name, location.display())
         })?;
 
+        let (metadata, content) = split_frontmatter(&content).with_context(|| {
+            format!(
+                "Unable to parse the frontmatter of \"{}\" ({})",
+                link.name,
+                location.display()
+            )
+        })?;
+
         let stripped = location
-            .strip_prefix(&src_dir)
+            .strip_prefix(src_dir)
             .expect("Chapters are always inside a book");
 
-        Chapter::new(&link.name, content, stripped, parent_names.clone())
+        let mut ch = Chapter::new(
+            &link.name,
+            content.to_string(),
+            stripped,
+            parent_names.clone(),
+        );
+        ch.metadata = metadata;
+        ch
     } else {
         Chapter::new_draft(&link.name, parent_names.clone())
     };
@@ -307,7 +452,7 @@ impl<'a> Iterator for BookItems<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         let item = self.items.pop_front();
 
-        if let Some(&BookItem::Chapter(ref ch)) = item {
+        if let Some(BookItem::Chapter(ch)) = item {
             // if we wanted a breadth-first iterator we'd `extend()` here
             for sub_item in ch.sub_items.iter().rev() {
                 self.items.push_front(sub_item);
@@ -331,6 +476,7 @@ impl Display for Chapter {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
     use std::io::Write;
     use tempfile::{Builder as TempFileBuilder, TempDir};
 
@@ -410,7 +556,9 @@ And here is some \
             content: String::from("Hello World!"),
             number: Some(SectionNumber(vec![1, 2])),
             path: Some(PathBuf::from("second.md")),
+            source_path: Some(PathBuf::from("second.md")),
             parent_names: vec![String::from("Chapter 1")],
+            metadata: BTreeMap::new(),
             sub_items: Vec::new(),
         };
         let should_be = BookItem::Chapter(Chapter {
@@ -418,7 +566,9 @@ And here is some \
             content: String::from(DUMMY_SRC),
             number: None,
             path: Some(PathBuf::from("chapter_1.md")),
+            source_path: Some(PathBuf::from("chapter_1.md")),
             parent_names: Vec::new(),
+            metadata: BTreeMap::new(),
             sub_items: vec![
                 BookItem::Chapter(nested.clone()),
                 BookItem::Separator,
@@ -442,6 +592,7 @@ And here is some \
                 name: String::from("Chapter 1"),
                 content: String::from(DUMMY_SRC),
                 path: Some(PathBuf::from("chapter_1.md")),
+                source_path: Some(PathBuf::from("chapter_1.md")),
                 ..Default::default()
             })],
             ..Default::default()
@@ -482,7 +633,9 @@ And here is some \
                     content: String::from(DUMMY_SRC),
                     number: None,
                     path: Some(PathBuf::from("Chapter_1/index.md")),
+                    source_path: Some(PathBuf::from("Chapter_1/index.md")),
                     parent_names: Vec::new(),
+                    metadata: BTreeMap::new(),
                     sub_items: vec![
                         BookItem::Chapter(Chapter::new(
                             "Hello World",
@@ -534,7 +687,9 @@ And here is some \
                     content: String::from(DUMMY_SRC),
                     number: None,
                     path: Some(PathBuf::from("Chapter_1/index.md")),
+                    source_path: Some(PathBuf::from("Chapter_1/index.md")),
                     parent_names: Vec::new(),
+                    metadata: BTreeMap::new(),
                     sub_items: vec![
                         BookItem::Chapter(Chapter::new(
                             "Hello World",
@@ -564,6 +719,103 @@ And here is some \
         assert_eq!(visited, num_items);
     }
 
+    fn nested_book() -> Book {
+        let mut first = Chapter::new("First", String::from("first"), "first.md", Vec::new());
+        let mut nested = Chapter::new(
+            "Nested",
+            String::from("nested"),
+            "first/nested.md",
+            vec![String::from("First")],
+        );
+        nested.sub_items.push(BookItem::Chapter(Chapter::new(
+            "Deeper",
+            String::from("deeper"),
+            "first/deeper.md",
+            vec![String::from("First"), String::from("Nested")],
+        )));
+        first.sub_items.push(BookItem::Chapter(nested));
+
+        let mut book = Book::new();
+        book.push_item(first)
+            .push_item(BookItem::Separator)
+            .push_item(Chapter::new(
+                "Second",
+                String::from("second"),
+                "second.md",
+                Vec::new(),
+            ));
+        book
+    }
+
+    fn contents(book: &Book) -> Vec<String> {
+        book.iter()
+            .filter_map(|item| match *item {
+                BookItem::Chapter(ref ch) => Some(ch.content.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn for_each_mut_changes_nested_chapters() {
+        let mut book = nested_book();
+        let mut visited = Vec::new();
+
+        book.for_each_mut(|item| {
+            if let BookItem::Chapter(ref mut ch) = *item {
+                visited.push(ch.name.clone());
+                ch.content = ch.content.to_uppercase();
+            }
+        });
+
+        assert_eq!(visited, vec!["Deeper", "Nested", "First", "Second"]);
+        assert_eq!(contents(&book), vec!["FIRST", "NESTED", "DEEPER", "SECOND"]);
+    }
+
+    #[test]
+    fn try_for_each_mut_stops_at_the_first_error() {
+        let mut book = nested_book();
+
+        let got = book.try_for_each_mut(|item| {
+            if let BookItem::Chapter(ref mut ch) = *item {
+                if ch.name == "First" {
+                    bail!("Can't change {}", ch.name);
+                }
+                ch.content.push('!');
+            }
+            Ok(())
+        });
+
+        assert_eq!(got.unwrap_err().to_string(), "Can't change First");
+        assert_eq!(
+            contents(&book),
+            vec!["first", "nested!", "deeper!", "second"]
+        );
+
+        book.try_for_each_mut(|item| {
+            if let BookItem::Chapter(ref mut ch) = *item {
+                ch.content.push('?');
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            contents(&book),
+            vec!["first?", "nested!?", "deeper!?", "second?"]
+        );
+    }
+
+    #[test]
+    fn sections_can_be_changed_in_place() {
+        let mut book = nested_book();
+
+        book.sections_mut()
+            .retain(|item| *item != BookItem::Separator);
+
+        assert_eq!(book.sections.len(), 2);
+        assert_eq!(contents(&book), vec!["first", "nested", "deeper", "second"]);
+    }
+
     #[test]
     fn cant_load_chapters_with_an_empty_path() {
         let (_, temp) = dummy_link();
@@ -599,4 +851,140 @@ And here is some \
         let got = load_book_from_disk(&summary, temp.path());
         assert!(got.is_err());
     }
+
+    fn load_two_part_book(restart_numbering_per_part: bool) -> Book {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let summary = "# Summary\n\n\
+                       # User Guide\n\n\
+                       - [Install](install.md)\n    - [Linux](linux.md)\n\n\
+                       # Reference\n\n\
+                       - [Config](config.md)\n    - [Options](options.md)\n";
+        fs::write(temp.path().join("SUMMARY.md"), summary).unwrap();
+
+        let cfg = BuildConfig {
+            restart_numbering_per_part,
+            ..Default::default()
+        };
+        load_book(temp.path(), &cfg).unwrap()
+    }
+
+    fn flatten(book: &Book) -> Vec<String> {
+        book.iter()
+            .map(|item| match *item {
+                BookItem::Chapter(ref ch) => format!("{} {}", ch.number.as_ref().unwrap(), ch.name),
+                BookItem::PartTitle(ref title) => format!("# {}", title),
+                BookItem::Separator => String::from("---"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn part_numbering_continues_by_default() {
+        let book = load_two_part_book(false);
+
+        let should_be = vec![
+            "# User Guide",
+            "1. Install",
+            "1.1. Linux",
+            "# Reference",
+            "2. Config",
+            "2.1. Options",
+        ];
+        assert_eq!(flatten(&book), should_be);
+    }
+
+    #[test]
+    fn part_numbering_can_restart() {
+        let book = load_two_part_book(true);
+
+        let should_be = vec![
+            "# User Guide",
+            "1. Install",
+            "1.1. Linux",
+            "# Reference",
+            "1. Config",
+            "1.1. Options",
+        ];
+        assert_eq!(flatten(&book), should_be);
+    }
+
+    fn load_with_summary(summary: &str, create_missing: bool) -> (TempDir, Result<Book>) {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let src = temp.path().join("src");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("SUMMARY.md"), summary).unwrap();
+
+        let cfg = BuildConfig {
+            create_missing,
+            ..Default::default()
+        };
+        let got = load_book(&src, &cfg);
+        (temp, got)
+    }
+
+    #[test]
+    fn missing_chapters_are_created() {
+        let summary =
+            "# Summary\n\n- [First](first.md)\n    - [Nested](deeply/nested/chapter.md)\n";
+        let (temp, got) = load_with_summary(summary, true);
+
+        let book = got.unwrap();
+        assert_eq!(book.iter().count(), 2);
+        let nested = temp.path().join("src/deeply/nested/chapter.md");
+        assert_eq!(fs::read_to_string(nested).unwrap(), "# Nested\n");
+        let first = temp.path().join("src/first.md");
+        assert_eq!(fs::read_to_string(first).unwrap(), "# First\n");
+    }
+
+    #[test]
+    fn missing_chapters_are_an_error_without_create_missing() {
+        let summary = "# Summary\n\n- [First](first.md)\n- [Second](second.md)\n";
+        let (temp, got) = load_with_summary(summary, false);
+
+        let err = got.unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "SUMMARY.md line 3: Chapter file not found for \"First\", first.md"
+        );
+        assert!(!temp.path().join("src/first.md").exists());
+    }
+
+    #[test]
+    fn chapters_outside_the_source_dir_are_rejected() {
+        let summary = "# Summary\n\n- [Inside](inside.md)\n- [Outside](../outside.md)\n";
+
+        for &create_missing in &[true, false] {
+            let (temp, got) = load_with_summary(summary, create_missing);
+
+            let err = got.unwrap_err().to_string();
+            assert_eq!(
+                err,
+                "SUMMARY.md line 4: The chapter \"Outside\" (../outside.md) isn't inside the source directory"
+            );
+            assert!(!temp.path().join("outside.md").exists());
+            assert!(!temp.path().join("src/inside.md").exists());
+        }
+    }
+
+    #[test]
+    fn errors_are_on_the_line_of_the_link() {
+        let summary = "# Summary\n\n<!-- - [Old](../outside.md) -->\n- [Outside](../outside.md)\n";
+        let (_temp, got) = load_with_summary(summary, false);
+        assert!(got
+            .unwrap_err()
+            .to_string()
+            .starts_with("SUMMARY.md line 4: "));
+
+        for &(link, location) in &[
+            ("[Spaced](<my chapter.md>)", "my chapter.md"),
+            ("[Escaped](my%20chapter.md)", "my%20chapter.md"),
+        ] {
+            let summary = format!("# Summary\n\n<!-- Missing -->\n- {}\n", link);
+            let (_temp, got) = load_with_summary(&summary, false);
+
+            let err = got.unwrap_err().to_string();
+            assert!(err.starts_with("SUMMARY.md line 4: "), "{}", err);
+            assert!(err.ends_with(location), "{}", err);
+        }
+    }
 }
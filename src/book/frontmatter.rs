@@ -0,0 +1,119 @@
+//! Parsing the TOML frontmatter a chapter can start with.
+
+use std::collections::BTreeMap;
+
+use crate::errors::*;
+use crate::utils::toml_ext;
+
+const DELIMITER: &str = "+++";
+
+/// Split the frontmatter (delimited by `+++` lines) off the start of a
+/// chapter, returning its keys and the rest of the chapter. Chapters without
+/// frontmatter are returned unchanged, with no keys.
+pub(crate) fn split_frontmatter(
+    content: &str,
+) -> Result<(BTreeMap<String, serde_json::Value>, &str)> {
+    let rest = match strip_delimiter_line(content.trim_start_matches('\u{feff}')) {
+        Some(rest) => rest,
+        None => return Ok((BTreeMap::new(), content)),
+    };
+
+    let mut offset = 0;
+    loop {
+        let remaining = &rest[offset..];
+        if let Some(body) = strip_delimiter_line(remaining) {
+            let table: toml::value::Table = toml::from_str(&rest[..offset])
+                .with_context(|| "Invalid TOML in the frontmatter")?;
+            let metadata = table
+                .iter()
+                .map(|(key, value)| (key.clone(), toml_ext::to_json(value)))
+                .collect();
+            return Ok((metadata, body));
+        }
+
+        match remaining.find('\n') {
+            Some(ix) => offset += ix + 1,
+            None => bail!(
+                "The frontmatter is missing its closing `{}` line",
+                DELIMITER
+            ),
+        }
+    }
+}
+
+/// If `text` starts with a line which is just the delimiter, the text after
+/// that line.
+fn strip_delimiter_line(text: &str) -> Option<&str> {
+    if !text.starts_with(DELIMITER) {
+        return None;
+    }
+
+    let after = &text[DELIMITER.len()..];
+    let end_of_line = after.find('\n').map(|ix| ix + 1).unwrap_or(after.len());
+    if after[..end_of_line].trim().is_empty() {
+        Some(&after[end_of_line..])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chapters_without_frontmatter_are_unchanged() {
+        let inputs = vec![
+            "# Chapter\n\nSome text",
+            "",
+            "++++\n",
+            "+++ not a delimiter\n+++\n",
+            "Text\n+++\ntitle = \"x\"\n+++\n",
+        ];
+
+        for input in inputs {
+            let (metadata, content) = split_frontmatter(input).unwrap();
+            assert!(metadata.is_empty(), "{:?}", input);
+            assert_eq!(content, input);
+        }
+    }
+
+    #[test]
+    fn frontmatter_is_parsed_and_stripped() {
+        let src = "+++\ntitle = \"Custom\"\ndraft = false\ntags = [\"a\", \"b\"]\n\
+                   [extra]\nweight = 3\n+++\n# Chapter\n\n+++\n";
+
+        let (metadata, content) = split_frontmatter(src).unwrap();
+
+        assert_eq!(content, "# Chapter\n\n+++\n");
+        assert_eq!(metadata["title"], "Custom");
+        assert_eq!(metadata["draft"], false);
+        assert_eq!(metadata["tags"], json!(["a", "b"]));
+        assert_eq!(metadata["extra"], json!({ "weight": 3 }));
+        assert_eq!(metadata.len(), 4);
+    }
+
+    #[test]
+    fn windows_line_endings_and_empty_frontmatter() {
+        let (metadata, content) =
+            split_frontmatter("+++\r\ntitle = \"Custom\"\r\n+++\r\n# Chapter\r\n").unwrap();
+        assert_eq!(metadata["title"], "Custom");
+        assert_eq!(content, "# Chapter\r\n");
+
+        let (metadata, content) = split_frontmatter("+++\n+++").unwrap();
+        assert!(metadata.is_empty());
+        assert_eq!(content, "");
+    }
+
+    #[test]
+    fn malformed_frontmatter_is_an_error() {
+        let err = split_frontmatter("+++\ntitle = \n+++\n# Chapter").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid TOML in the frontmatter");
+
+        let err = split_frontmatter("+++\ntitle = \"x\"\n# Chapter").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The frontmatter is missing its closing `+++` line"
+        );
+    }
+}
@@ -112,23 +112,22 @@ impl BookBuilder {
         let themedir = self
             .config
             .html_config()
-            .and_then(|html| html.theme)
-            .unwrap_or_else(|| self.config.book.src.join("theme"));
-        let themedir = self.root.join(themedir);
+            .unwrap_or_default()
+            .theme_dir(&self.root);
 
         if !themedir.exists() {
             debug!(
                 "{} does not exist, creating the directory",
                 themedir.display()
             );
-            fs::create_dir(&themedir)?;
+            fs::create_dir_all(&themedir)?;
         }
 
         let mut index = File::create(themedir.join("index.hbs"))?;
         index.write_all(theme::INDEX)?;
 
         let cssdir = themedir.join("css");
-        fs::create_dir(&cssdir)?;
+        fs::create_dir_all(&cssdir)?;
 
         let mut general_css = File::create(cssdir.join("general.css"))?;
         general_css.write_all(theme::GENERAL_CSS)?;
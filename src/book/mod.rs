@@ -7,6 +7,7 @@
 
 #[allow(clippy::module_inception)]
 mod book;
+mod frontmatter;
 mod init;
 mod summary;
 
@@ -14,22 +15,48 @@ pub use self::book::{load_book, Book, BookItem, BookItems, Chapter};
 pub use self::init::BookBuilder;
 pub use self::summary::{parse_summary, Link, SectionNumber, Summary, SummaryItem};
 
-use std::io::Write;
-use std::path::PathBuf;
+use pulldown_cmark::{Event, Parser, Tag};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::string::ToString;
 use tempfile::Builder as TempFileBuilder;
 use toml::Value;
 
+use crate::diagnostics::{Diagnostic, DiagnosticKind, Diagnostics};
 use crate::errors::*;
 use crate::preprocess::{
-    CmdPreprocessor, IndexPreprocessor, LinkPreprocessor, Preprocessor, PreprocessorContext,
+    self, CmdPreprocessor, IndexPreprocessor, LinkPreprocessor, Preprocessor, PreprocessorContext,
 };
-use crate::renderer::{CmdRenderer, HtmlHandlebars, MarkdownRenderer, RenderContext, Renderer};
+use crate::renderer::{
+    Archives, ChapterPostProcessor, CmdRenderer, HtmlHandlebars, JsonRenderer, MarkdownRenderer,
+    PostProcessors, RenderContext, Renderer, SkippedBackend,
+};
+use crate::timings::Timings;
 use crate::utils;
 
 use crate::config::{Config, RustEdition};
 
+/// What a build did.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BuildReport {
+    /// The backends which rendered the book, in the order they ran. They run
+    /// once for each translation of a book with translations.
+    pub backends: Vec<String>,
+    /// The optional backends which were skipped because they aren't
+    /// installed.
+    pub skipped: Vec<String>,
+    /// The warnings of the build.
+    pub warnings: Vec<Diagnostic>,
+    /// The archives of the rendered book written by the renderers, like the
+    /// ones of `output.html.archive`.
+    pub archives: Vec<PathBuf>,
+}
+
 /// The object used to manage and build a book.
 pub struct MDBook {
     /// The book's root directory.
@@ -42,20 +69,61 @@ pub struct MDBook {
 
     /// List of pre-processors to be run on the book
     preprocessors: Vec<Box<dyn Preprocessor>>,
+
+    /// The language this is the translation in, for one of the languages of a
+    /// book with translations.
+    language: Option<String>,
+
+    /// Whether the chapters were put together in memory, instead of loaded
+    /// from the source directory.
+    in_memory: bool,
+
+    /// The warnings of the last build, shared with its translations.
+    diagnostics: Diagnostics,
+
+    /// What the HTML renderer runs on the pages of the chapters.
+    post_processors: PostProcessors,
+
+    /// The archives the renderers wrote in the last build, shared with its
+    /// translations.
+    archives: Archives,
 }
 
 impl MDBook {
     /// Load a book from its root directory on disk.
     pub fn load<P: Into<PathBuf>>(book_root: P) -> Result<MDBook> {
         let book_root = book_root.into();
+        let config = MDBook::load_config(&book_root)?;
+
+        if log_enabled!(log::Level::Trace) {
+            for line in format!("Config: {:#?}", config).lines() {
+                trace!("{}", line);
+            }
+        }
+
+        MDBook::load_with_config(book_root, config)
+    }
+
+    /// Load the configuration of the book in `book_root`, without the book:
+    /// its `book.toml` (or legacy `book.json`) with the `MDBOOK_*`
+    /// environment variables applied over it.
+    pub fn load_config<P: AsRef<Path>>(book_root: P) -> Result<Config> {
+        let book_root = book_root.as_ref();
         let config_location = book_root.join("book.toml");
+        let legacy_location = book_root.join("book.json");
 
         // the book.json file is no longer used, so we should emit a warning to
         // let people know to migrate to book.toml
-        if book_root.join("book.json").exists() {
+        if legacy_location.exists() {
             warn!("It appears you are still using book.json for configuration.");
             warn!("This format is no longer used, so you should migrate to the");
             warn!("book.toml format.");
+            if config_location.exists() {
+                warn!("It's ignored, since there's a book.toml.");
+            } else {
+                warn!("It's read as the equivalent book.toml for now, run");
+                warn!("`mdbook build --migrate` to write that book.toml.");
+            }
             warn!("Check the user guide for migration information:");
             warn!("\thttps://rust-lang.github.io/mdBook/format/config.html");
         }
@@ -63,26 +131,57 @@ impl MDBook {
         let mut config = if config_location.exists() {
             debug!("Loading config from {}", config_location.display());
             Config::from_disk(&config_location)?
+        } else if legacy_location.exists() {
+            debug!("Loading config from {}", legacy_location.display());
+            MDBook::legacy_config(&legacy_location)?.parse()?
         } else {
             Config::default()
         };
 
-        config.update_from_env();
+        config.try_update_from_env()?;
+        Ok(config)
+    }
 
-        if log_enabled!(log::Level::Trace) {
-            for line in format!("Config: {:#?}", config).lines() {
-                trace!("{}", line);
-            }
+    /// Write the `book.toml` equivalent to the legacy `book.json` of the book
+    /// in `book_root`, when it only has a `book.json`. Returns whether it did.
+    pub fn migrate_legacy_config<P: AsRef<Path>>(book_root: P) -> Result<bool> {
+        let book_root = book_root.as_ref();
+        let config_location = book_root.join("book.toml");
+        let legacy_location = book_root.join("book.json");
+        if config_location.exists() || !legacy_location.exists() {
+            return Ok(false);
         }
 
-        MDBook::load_with_config(book_root, config)
+        let config = MDBook::legacy_config(&legacy_location)?;
+        let json = fs::read_to_string(&legacy_location)?;
+        for message in Config::legacy_json_warnings(&json) {
+            warn!("{}", message);
+        }
+        fs::write(&config_location, config)
+            .with_context(|| format!("Unable to write {}", config_location.display()))?;
+        info!(
+            "Wrote {} from book.json, which can be removed",
+            config_location.display()
+        );
+        Ok(true)
+    }
+
+    fn legacy_config(legacy_location: &Path) -> Result<String> {
+        let json = fs::read_to_string(legacy_location)
+            .with_context(|| format!("Unable to read {}", legacy_location.display()))?;
+        Config::legacy_json_to_toml(&json, legacy_location)
     }
 
     /// Load a book from its root directory using a custom config.
     pub fn load_with_config<P: Into<PathBuf>>(book_root: P, config: Config) -> Result<MDBook> {
         let root = book_root.into();
 
-        let src_dir = root.join(&config.book.src);
+        // The book of a book with translations is the one in its default
+        // language
+        let src_dir = match config.default_language() {
+            Some(language) => root.join(&config.book.src).join(language),
+            None => root.join(&config.book.src),
+        };
         let book = book::load_book(&src_dir, &config.build)?;
 
         let renderers = determine_renderers(&config);
@@ -94,6 +193,11 @@ impl MDBook {
             book,
             renderers,
             preprocessors,
+            language: None,
+            in_memory: false,
+            diagnostics: Diagnostics::new(),
+            post_processors: PostProcessors::default(),
+            archives: Archives::default(),
         })
     }
 
@@ -117,6 +221,90 @@ impl MDBook {
             book,
             renderers,
             preprocessors,
+            language: None,
+            in_memory: false,
+            diagnostics: Diagnostics::new(),
+            post_processors: PostProcessors::default(),
+            archives: Archives::default(),
+        })
+    }
+
+    /// Create a book from chapters put together in memory, like with
+    /// [`Book::push_item()`](struct.Book.html#method.push_item), instead of
+    /// the ones of its source directory. The book is still rendered to the
+    /// build directory of `book_root`, and its chapters' relative links and
+    /// includes are relative to its source directory, which doesn't have to
+    /// exist.
+    ///
+    /// For a book with translations, these are the chapters of the default
+    /// language, and the other translations are loaded from their source
+    /// directories.
+    pub fn load_with_config_and_book<P: Into<PathBuf>>(
+        book_root: P,
+        config: Config,
+        book: Book,
+    ) -> Result<MDBook> {
+        let renderers = determine_renderers(&config);
+        let preprocessors = determine_preprocessors(&config)?;
+
+        Ok(MDBook {
+            root: book_root.into(),
+            config,
+            book,
+            renderers,
+            preprocessors,
+            language: None,
+            in_memory: true,
+            diagnostics: Diagnostics::new(),
+            post_processors: PostProcessors::default(),
+            archives: Archives::default(),
+        })
+    }
+
+    /// The translation of a book with translations in the language `code`. Its
+    /// source is in the directory `code` of the book's source directory, and
+    /// it's rendered to a directory `code` in each renderer's build directory.
+    pub fn translation(&self, code: &str) -> Result<MDBook> {
+        let default_language = self.config.default_language().map(String::from);
+        let mut config = self.config.clone();
+        let language = match config.language.get(code) {
+            Some(language) => language.clone(),
+            None => bail!("The book has no `[language.{}]` table", code),
+        };
+        // The translations need to know which one is the default, whatever
+        // their own `book.language` is
+        for (other, language) in config.language.iter_mut() {
+            language.default = default_language.as_ref() == Some(other);
+        }
+        config.book.src = config.book.src.join(code);
+        config.book.language = Some(code.to_string());
+        if let Some(title) = language.title {
+            config.book.title = Some(title);
+        }
+        if let Some(authors) = language.authors {
+            config.book.authors = authors;
+        }
+
+        let in_memory =
+            self.in_memory && default_language.as_ref().map(String::as_str) == Some(code);
+        let book = if in_memory {
+            self.book.clone()
+        } else {
+            let src_dir = self.root.join(&config.book.src);
+            book::load_book(&src_dir, &config.build)?
+        };
+
+        Ok(MDBook {
+            root: self.root.clone(),
+            renderers: determine_renderers(&config),
+            preprocessors: determine_preprocessors(&config)?,
+            config,
+            book,
+            language: Some(code.to_string()),
+            in_memory,
+            diagnostics: self.diagnostics.clone(),
+            post_processors: self.post_processors.clone(),
+            archives: self.archives.clone(),
         })
     }
 
@@ -170,11 +358,92 @@ impl MDBook {
     }
 
     /// Tells the renderer to build our book and put it in the build directory.
+    ///
+    /// The warnings of the build are summarised at its end, and are available
+    /// from [`warnings()`](#method.warnings) afterwards. With
+    /// `build.deny-warnings`, a build with warnings is an error.
     pub fn build(&self) -> Result<()> {
+        self.build_with_report().map(|_| ())
+    }
+
+    /// Build the book like [`build()`](#method.build), and return its
+    /// [`BuildReport`](struct.BuildReport.html), with the backends which ran.
+    pub fn build_with_report(&self) -> Result<BuildReport> {
+        self.diagnostics.clear();
+        self.archives.clear();
+        self.check_config();
+        let mut report = BuildReport::default();
+        self.build_all(&mut report)?;
+        report.warnings = self.diagnostics.warnings();
+        report.archives = self.archives.paths();
+
+        if !self.diagnostics.is_empty() {
+            warn!("{}", self.diagnostics.summary());
+            ensure!(
+                !self.config.build.deny_warnings,
+                "The build had warnings ({}), and `build.deny-warnings` is set",
+                self.diagnostics.len()
+            );
+        }
+        Ok(report)
+    }
+
+    /// The warnings of the last build.
+    pub fn warnings(&self) -> Vec<Diagnostic> {
+        self.diagnostics.warnings()
+    }
+
+    /// Build the book, or all of its translations, with every renderer.
+    fn build_all(&self, report: &mut BuildReport) -> Result<()> {
+        if self.language.is_none() && !self.config.language.is_empty() {
+            // The default language first, the others share its theme's files
+            let default = self.config.default_language();
+            let mut codes: Vec<_> = self.config.language.keys().collect();
+            codes.sort_by_key(|&code| Some(code.as_str()) != default);
+            for code in codes {
+                info!("Building the {} translation", code);
+                self.translation(code)?.build_all(report)?;
+            }
+            return Ok(());
+        }
+
         info!("Book building has started");
 
+        let unlisted = self.unlisted_files()?;
+        if !unlisted.is_empty() && self.config.build.strict {
+            let unlisted: Vec<_> = unlisted.iter().map(|p| p.display().to_string()).collect();
+            bail!(
+                "These files aren't listed in SUMMARY.md, or included by a chapter: {}",
+                unlisted.join(", ")
+            );
+        }
+        for file in unlisted {
+            self.diagnostics.warn(
+                Diagnostic::new(
+                    DiagnosticKind::UnlistedFile,
+                    "This file isn't listed in SUMMARY.md, or included by a chapter",
+                )
+                .with_chapter(file),
+            );
+        }
+
+        let mut skipped = Vec::new();
         for renderer in &self.renderers {
-            self.execute_build_process(&**renderer)?;
+            match self.execute_build_process(&**renderer) {
+                Ok(()) => report.backends.push(renderer.name().to_string()),
+                Err(e) if e.downcast_ref::<SkippedBackend>().is_some() => {
+                    skipped.push(renderer.name());
+                    report.skipped.push(renderer.name().to_string());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        if !skipped.is_empty() {
+            warn!(
+                "Skipped the optional backends which aren't installed: {}",
+                skipped.join(", ")
+            );
         }
 
         Ok(())
@@ -182,36 +451,64 @@ impl MDBook {
 
     /// Run the entire build process for a particular `Renderer`.
     pub fn execute_build_process(&self, renderer: &dyn Renderer) -> Result<()> {
+        // Every backend measures its own preprocessing and rendering
+        let timings = if self.config.build.timings {
+            Timings::enabled()
+        } else {
+            Timings::disabled()
+        };
         let mut preprocessed_book = self.book.clone();
-        let preprocess_ctx = PreprocessorContext::new(
+        let mut preprocess_ctx = PreprocessorContext::new(
             self.root.clone(),
             self.config.clone(),
             renderer.name().to_string(),
         );
+        preprocess_ctx.timings = timings.clone();
 
+        let mut links_expanded = false;
         for preprocessor in &self.preprocessors {
             if preprocessor_should_run(&**preprocessor, renderer, &self.config) {
                 debug!("Running the {} preprocessor.", preprocessor.name());
-                preprocessed_book = preprocessor.run(&preprocess_ctx, preprocessed_book)?;
+                let step = format!("the {} preprocessor", preprocessor.name());
+                preprocessed_book = timings.time(&step, || {
+                    preprocessor.run(&preprocess_ctx, preprocessed_book)
+                })?;
+                links_expanded |= preprocessor.name() == LinkPreprocessor::NAME;
             }
         }
 
+        // The `{{#only ...}}` blocks are for every renderer, with the links
+        // preprocessor or without it
+        if !links_expanded {
+            preprocess::keep_renderer_blocks_of(&mut preprocessed_book, renderer.name())?;
+        }
+
         info!("Running the {} backend", renderer.name());
-        self.render(&preprocessed_book, renderer)?;
+        self.render(&preprocessed_book, renderer, timings)?;
 
         Ok(())
     }
 
-    fn render(&self, preprocessed_book: &Book, renderer: &dyn Renderer) -> Result<()> {
+    fn render(
+        &self,
+        preprocessed_book: &Book,
+        renderer: &dyn Renderer,
+        timings: Timings,
+    ) -> Result<()> {
         let name = renderer.name();
         let build_dir = self.build_dir_for(name);
 
-        let render_context = RenderContext::new(
+        // Every renderer leaves the same draft chapters out
+        let mut render_context = RenderContext::new(
             self.root.clone(),
-            preprocessed_book.clone(),
+            preprocessed_book.without_drafts(),
             self.config.clone(),
             build_dir,
         );
+        render_context.diagnostics = self.diagnostics.for_backend(name);
+        render_context.timings = timings;
+        render_context.post_processors = self.post_processors.clone();
+        render_context.archives = self.archives.clone();
 
         renderer
             .render(&render_context)
@@ -232,11 +529,110 @@ impl MDBook {
         self
     }
 
+    /// Register a [`ChapterPostProcessor`](../renderer/trait.ChapterPostProcessor.html)
+    /// the HTML renderer runs on the page of every chapter, after the ones of
+    /// `book.toml` and the ones registered before it.
+    pub fn with_post_processor<P: ChapterPostProcessor + 'static>(
+        &mut self,
+        post_processor: P,
+    ) -> &mut Self {
+        self.post_processors.push(post_processor);
+        self
+    }
+
+    /// Remove the rendered book, including the output of every backend and
+    /// the archive of `output.html.archive`.
+    ///
+    /// Only a build directory inside the book's root is ever removed. If the
+    /// build directory is a symlink only the link is removed, and anything
+    /// symlinked from inside it is left alone.
+    pub fn clean(&self) -> Result<()> {
+        self.remove_build_dir()?;
+
+        // The archive of a book with a single renderer is beside the build
+        // directory, the other ones are inside it
+        let format = match self.config.html_config().and_then(|html| html.archive) {
+            Some(format) => format,
+            None => return Ok(()),
+        };
+        let archive = format.archive_path(&self.build_dir_for("html"));
+        let in_root = match (archive.parent(), self.root.canonicalize()) {
+            (Some(parent), Ok(root)) => parent
+                .canonicalize()
+                .map(|parent| parent.starts_with(root))
+                .unwrap_or(false),
+            _ => false,
+        };
+        if in_root && archive.is_file() {
+            debug!("Removing {}", archive.display());
+            fs::remove_file(&archive)
+                .with_context(|| format!("Unable to remove {}", archive.display()))?;
+        }
+        Ok(())
+    }
+
+    fn remove_build_dir(&self) -> Result<()> {
+        let build_dir = self.build_dir();
+
+        let metadata = match fs::symlink_metadata(&build_dir) {
+            Ok(metadata) => metadata,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Unable to read {}", build_dir.display()))
+            }
+        };
+
+        // Resolve everything but the build directory itself, so a symlinked
+        // build directory is judged by where the link is, not where it points
+        let root = self
+            .root
+            .canonicalize()
+            .with_context(|| format!("Unable to find the book root {}", self.root.display()))?;
+        let resolved = match (build_dir.parent(), build_dir.file_name()) {
+            (Some(parent), Some(name)) => parent
+                .canonicalize()
+                .with_context(|| format!("Unable to find {}", parent.display()))?
+                .join(name),
+            _ => root.clone(),
+        };
+        if !resolved.starts_with(&root) || resolved == root {
+            bail!(
+                "Refusing to remove the build directory {}, it isn't inside the book's root {}",
+                build_dir.display(),
+                root.display()
+            );
+        }
+
+        debug!("Removing {}", resolved.display());
+        if metadata.file_type().is_symlink() || !metadata.is_dir() {
+            fs::remove_file(&resolved)
+        } else {
+            // doesn't follow symlinks, those are removed themselves
+            fs::remove_dir_all(&resolved)
+        }
+        .with_context(|| {
+            format!(
+                "Unable to remove the build directory {}",
+                resolved.display()
+            )
+        })
+    }
+
     /// Run `rustdoc` tests on the book, linking against the provided libraries.
+    /// The code blocks of a book with translations are tested in every
+    /// translation.
     pub fn test(&mut self, library_paths: Vec<&str>) -> Result<()> {
+        if self.language.is_none() && !self.config.language.is_empty() {
+            for code in self.config.language.keys() {
+                info!("Testing the {} translation", code);
+                self.translation(code)?.test(library_paths.clone())?;
+            }
+            return Ok(());
+        }
+
         let library_args: Vec<&str> = (0..library_paths.len())
             .map(|_| "-L")
-            .zip(library_paths.into_iter())
+            .zip(library_paths)
             .flat_map(|x| vec![x.0, x.1])
             .collect();
 
@@ -250,6 +646,19 @@ impl MDBook {
         // Index Preprocessor is disabled so that chapter paths continue to point to the
         // actual markdown files.
 
+        // The code blocks are reported at their lines before the includes
+        // were expanded
+        let sources: HashMap<&PathBuf, &str> = self
+            .book
+            .iter()
+            .filter_map(|item| match *item {
+                BookItem::Chapter(ref ch) => ch.path.as_ref().map(|p| (p, ch.content.as_str())),
+                _ => None,
+            })
+            .collect();
+
+        let edition = self.playpen_edition();
+        let mut failures = Vec::new();
         for item in book.iter() {
             if let BookItem::Chapter(ref ch) = *item {
                 let chapter_path = match ch.path {
@@ -257,43 +666,79 @@ impl MDBook {
                     _ => continue,
                 };
 
-                let path = self.source_dir().join(&chapter_path);
+                let path = self.source_dir().join(chapter_path);
+                if !utils::code_blocks(&ch.content)
+                    .iter()
+                    .any(|block| block.is_tested())
+                {
+                    debug!("No code blocks to test in {:?}", path);
+                    continue;
+                }
                 info!("Testing file: {:?}", path);
 
                 // write preprocessed file to tempdir
-                let path = temp_dir.path().join(&chapter_path);
+                let path = temp_dir.path().join(chapter_path);
                 let mut tmpf = utils::fs::create_file(&path)?;
                 tmpf.write_all(ch.content.as_bytes())?;
 
                 let mut cmd = Command::new("rustdoc");
                 cmd.arg(&path).arg("--test").args(&library_args);
 
-                if let Some(edition) = self.config.rust.edition {
-                    match edition {
-                        RustEdition::E2015 => {
-                            cmd.args(&["--edition", "2015"]);
-                        }
-                        RustEdition::E2018 => {
-                            cmd.args(&["--edition", "2018"]);
-                        }
-                    }
+                // The same edition as the playpen, so what runs on the page
+                // passes the tests too
+                if let Some(edition) = edition {
+                    cmd.args(&["--edition", edition.as_str()]);
                 }
 
                 let output = cmd.output()?;
 
                 if !output.status.success() {
-                    bail!(
-                        "rustdoc returned an error:\n\
+                    let stdout = String::from_utf8_lossy(&output.stdout);
+                    error!(
+                        "rustdoc returned an error for \"{}\":\n\
                         \n--- stdout\n{}\n--- stderr\n{}",
-                        String::from_utf8_lossy(&output.stdout),
+                        ch.name,
+                        stdout,
                         String::from_utf8_lossy(&output.stderr)
                     );
+
+                    let source = sources.get(chapter_path).cloned().unwrap_or_default();
+                    let lines = source_lines_of_failed_tests(&stdout, &ch.content, source);
+                    if lines.is_empty() {
+                        failures.push(format!("\"{}\" ({})", ch.name, chapter_path.display()));
+                    }
+                    for line in lines {
+                        failures.push(format!(
+                            "\"{}\" ({}), the code block at line {}",
+                            ch.name,
+                            chapter_path.display(),
+                            line
+                        ));
+                    }
                 }
             }
         }
+
+        if !failures.is_empty() {
+            bail!(
+                "Some code blocks failed to test:\n    {}",
+                failures.join("\n    ")
+            );
+        }
         Ok(())
     }
 
+    /// The edition the code blocks of the book are run with: the one of the
+    /// playpen, or else `rust.edition`.
+    fn playpen_edition(&self) -> Option<RustEdition> {
+        let playpen = self
+            .config
+            .html_config()
+            .map(|html_config| html_config.playpen)
+            .unwrap_or_default();
+        playpen.effective_edition(&self.config.rust)
+    }
+
     /// The logic for determining where a backend should put its build
     /// artefacts.
     ///
@@ -319,13 +764,101 @@ impl MDBook {
     ///     - my_awesome_book.tex
     ///
     pub fn build_dir_for(&self, backend_name: &str) -> PathBuf {
-        let build_dir = self.root.join(&self.config.build.build_dir);
+        let build_dir = self.build_dir();
 
-        if self.renderers.len() <= 1 {
+        let build_dir = if self.renderers.len() <= 1 {
             build_dir
         } else {
             build_dir.join(backend_name)
+        };
+        match self.language {
+            Some(ref language) => build_dir.join(language),
+            None => build_dir,
+        }
+    }
+
+    /// The archives of the rendered book the renderers wrote in the last
+    /// build, like the ones of `output.html.archive`.
+    pub fn archives(&self) -> Vec<PathBuf> {
+        self.archives.paths()
+    }
+
+    /// The markdown files in the source directory which aren't a chapter,
+    /// aren't included by a chapter, and aren't ignored by
+    /// `build.ignore-unlisted`. `SUMMARY.md` and the `output.html.input-404`
+    /// page count as listed. Hidden directories and the build directory are
+    /// skipped, and the directories which are linked to more than once are
+    /// only looked into once.
+    pub fn unlisted_files(&self) -> Result<Vec<PathBuf>> {
+        let src_dir = self.source_dir();
+        if !src_dir.is_dir() {
+            // A book put together in memory
+            return Ok(Vec::new());
+        }
+        let canonical = |path: &Path| path.canonicalize().ok();
+
+        let mut listed = HashSet::new();
+        for item in self.book.iter() {
+            if let BookItem::Chapter(ref ch) = *item {
+                if let Some(ref path) = ch.path {
+                    listed.extend(canonical(&src_dir.join(path)));
+
+                    let dir = src_dir.join(path.parent().unwrap_or_else(|| Path::new("")));
+                    for file in preprocess::included_files(&ch.content, &dir) {
+                        listed.extend(canonical(&file));
+                    }
+                }
+            }
+        }
+        listed.extend(canonical(&src_dir.join("SUMMARY.md")));
+        if let Some(input_404) = self.config.html_config().and_then(|html| html.input_404) {
+            listed.extend(canonical(&src_dir.join(input_404)));
         }
+
+        let ignored = self
+            .config
+            .build
+            .ignore_unlisted
+            .iter()
+            .map(|pattern| utils::fs::glob_to_regex(pattern))
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| "Invalid pattern in build.ignore-unlisted")?;
+        let build_dir = canonical(&self.build_dir());
+
+        let mut unlisted = Vec::new();
+        let mut visited = HashSet::new();
+        let mut dirs = vec![src_dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            let canonical_dir = canonical(&dir);
+            if canonical_dir == build_dir || !visited.insert(canonical_dir) {
+                continue;
+            }
+
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                if entry.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+
+                let path = entry.path();
+                let is_listed = match canonical(&path) {
+                    Some(path) => listed.contains(&path),
+                    None => false,
+                };
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if path.extension() == Some(OsStr::new("md")) && !is_listed {
+                    let relative = path.strip_prefix(&src_dir).unwrap_or(&path).to_path_buf();
+                    let name = utils::fs::normalize_path(&relative.to_string_lossy());
+                    if !ignored.iter().any(|pattern| pattern.is_match(&name)) {
+                        unlisted.push(relative);
+                    }
+                }
+            }
+        }
+
+        unlisted.sort();
+        Ok(unlisted)
     }
 
     /// Get the directory containing this book's source files.
@@ -333,6 +866,47 @@ impl MDBook {
         self.root.join(&self.config.book.src)
     }
 
+    /// The directory the book is built in, `build.build-dir` relative to the
+    /// book's root (or where it says, when it's absolute). Every backend
+    /// builds in it, or in a directory of its own inside it.
+    pub fn build_dir(&self) -> PathBuf {
+        self.root.join(&self.config.build.build_dir)
+    }
+
+    /// Warn about the keys at the top of `book.toml` mdBook doesn't know, the
+    /// ones of a legacy `book.json` it drops, the deprecated `snake_case` keys
+    /// used, and a build directory inside the source directory. That one's left out of
+    /// the book and the watcher ignores it, but other tools watching the
+    /// sources would rebuild the book every time it's built.
+    fn check_config(&self) {
+        let mut messages = self.config.unknown_top_level_keys();
+        let legacy_location = self.root.join("book.json");
+        if !self.root.join("book.toml").exists() {
+            if let Ok(json) = fs::read_to_string(&legacy_location) {
+                messages.extend(Config::legacy_json_warnings(&json));
+            }
+        }
+        messages.extend(self.config.deprecated_key_warnings());
+        for message in messages {
+            self.diagnostics
+                .warn(Diagnostic::new(DiagnosticKind::Config, message));
+        }
+
+        let src_dir = resolve_dir(&self.source_dir());
+        let build_dir = resolve_dir(&self.build_dir());
+        if build_dir.starts_with(&src_dir) {
+            self.diagnostics.warn(Diagnostic::new(
+                DiagnosticKind::Config,
+                format!(
+                    "The build directory {} is inside the source directory {}, \
+                     it should be moved out of it",
+                    self.config.build.build_dir.display(),
+                    self.config.book.src.display()
+                ),
+            ));
+        }
+    }
+
     /// Get the directory containing the theme resources for the book.
     pub fn theme_dir(&self) -> PathBuf {
         self.config
@@ -346,17 +920,23 @@ impl MDBook {
 fn determine_renderers(config: &Config) -> Vec<Box<dyn Renderer>> {
     let mut renderers = Vec::new();
 
-    if let Some(output_table) = config.get("output").and_then(Value::as_table) {
-        renderers.extend(output_table.iter().map(|(key, table)| {
-            if key == "html" {
-                Box::new(HtmlHandlebars::new()) as Box<dyn Renderer>
-            } else if key == "markdown" {
-                Box::new(MarkdownRenderer::new()) as Box<dyn Renderer>
-            } else {
-                interpret_custom_renderer(key, table)
-            }
-        }));
-    }
+    // In the order of book.toml
+    renderers.extend(
+        config
+            .ordered_entries("output")
+            .into_iter()
+            .map(|(key, table)| {
+                if key == "html" {
+                    Box::new(HtmlHandlebars::new()) as Box<dyn Renderer>
+                } else if key == "markdown" {
+                    Box::new(MarkdownRenderer::new()) as Box<dyn Renderer>
+                } else if key == "json" {
+                    Box::new(JsonRenderer::new()) as Box<dyn Renderer>
+                } else {
+                    interpret_custom_renderer(key, table)
+                }
+            }),
+    );
 
     // if we couldn't find anything, add the HTML renderer as a default
     if renderers.is_empty() {
@@ -378,28 +958,113 @@ fn is_default_preprocessor(pre: &dyn Preprocessor) -> bool {
     name == LinkPreprocessor::NAME || name == IndexPreprocessor::NAME
 }
 
-/// Look at the `MDBook` and try to figure out what preprocessors to run.
+/// Look at the `MDBook` and try to figure out what preprocessors to run, in
+/// the order given by their `before` and `after` keys.
 fn determine_preprocessors(config: &Config) -> Result<Vec<Box<dyn Preprocessor>>> {
-    let mut preprocessors = Vec::new();
+    let mut preprocessors: Vec<Box<dyn Preprocessor>> = Vec::new();
 
     if config.build.use_default_preprocessors {
         preprocessors.extend(default_preprocessors());
     }
 
-    if let Some(preprocessor_table) = config.get("preprocessor").and_then(Value::as_table) {
-        for key in preprocessor_table.keys() {
-            match key.as_ref() {
-                "links" => preprocessors.push(Box::new(LinkPreprocessor::new())),
-                "index" => preprocessors.push(Box::new(IndexPreprocessor::new())),
-                name => preprocessors.push(interpret_custom_preprocessor(
-                    name,
-                    &preprocessor_table[name],
-                )),
+    let empty_table = toml::value::Table::new();
+    let preprocessor_table = config
+        .get("preprocessor")
+        .and_then(Value::as_table)
+        .unwrap_or(&empty_table);
+    for (name, table) in config.ordered_entries("preprocessor") {
+        if preprocessors.iter().any(|pre| pre.name() == name) {
+            continue;
+        }
+        match name.as_ref() {
+            "links" => preprocessors.push(Box::new(LinkPreprocessor::new())),
+            "index" => preprocessors.push(Box::new(IndexPreprocessor::new())),
+            name => preprocessors.push(interpret_custom_preprocessor(name, table)),
+        }
+    }
+
+    order_preprocessors(preprocessors, preprocessor_table)
+}
+
+/// Sort the preprocessors so each runs after the ones in its `after` key and
+/// before the ones in its `before` key, keeping the order they are declared
+/// in otherwise.
+fn order_preprocessors(
+    preprocessors: Vec<Box<dyn Preprocessor>>,
+    preprocessor_table: &toml::value::Table,
+) -> Result<Vec<Box<dyn Preprocessor>>> {
+    let names: Vec<String> = preprocessors
+        .iter()
+        .map(|pre| pre.name().to_string())
+        .collect();
+    // the indices of the preprocessors each one has to run after
+    let mut runs_after: Vec<HashSet<usize>> = vec![HashSet::new(); names.len()];
+
+    for (index, name) in names.iter().enumerate() {
+        let table = match preprocessor_table.get(name) {
+            Some(table) => table,
+            None => continue,
+        };
+        for &key in &["before", "after"] {
+            let others = match table.get(key) {
+                Some(others) => others.as_array().with_context(|| {
+                    format!("Expected preprocessor.{}.{} to be an array", name, key)
+                })?,
+                None => continue,
+            };
+            for other in others {
+                let other = other.as_str().with_context(|| {
+                    format!("Expected preprocessor.{}.{} to contain strings", name, key)
+                })?;
+                let other_index = match names.iter().position(|n| n == other) {
+                    Some(other_index) => other_index,
+                    // the built-in preprocessors may have been disabled
+                    None if other == LinkPreprocessor::NAME || other == IndexPreprocessor::NAME => {
+                        continue
+                    }
+                    None => bail!(
+                        "preprocessor.{}.{} contains \"{}\", which isn't a preprocessor of the book",
+                        name,
+                        key,
+                        other
+                    ),
+                };
+                if key == "before" {
+                    runs_after[other_index].insert(index);
+                } else {
+                    runs_after[index].insert(other_index);
+                }
             }
         }
     }
 
-    Ok(preprocessors)
+    let mut order = Vec::with_capacity(names.len());
+    while order.len() < names.len() {
+        let next = (0..names.len()).find(|index| {
+            !order.contains(index) && runs_after[*index].iter().all(|i| order.contains(i))
+        });
+        match next {
+            Some(next) => order.push(next),
+            None => {
+                let cycle: Vec<&str> = (0..names.len())
+                    .filter(|index| !order.contains(index))
+                    .map(|index| names[index].as_str())
+                    .collect();
+                bail!(
+                    "The preprocessors {} have to run before each other, \
+                     check their `before` and `after` keys",
+                    cycle.join(", ")
+                );
+            }
+        }
+    }
+
+    let mut preprocessors: Vec<Option<Box<dyn Preprocessor>>> =
+        preprocessors.into_iter().map(Some).collect();
+    Ok(order
+        .into_iter()
+        .filter_map(|index| preprocessors[index].take())
+        .collect())
 }
 
 fn interpret_custom_preprocessor(key: &str, table: &Value) -> Box<CmdPreprocessor> {
@@ -454,12 +1119,375 @@ fn preprocessor_should_run(
     preprocessor.supports_renderer(renderer_name)
 }
 
+/// The lines in `source` of the code blocks rustdoc reported as failed, where
+/// rustdoc saw `tested` (the source with its includes expanded). The nth code
+/// block of `tested` is the nth one of `source` only if the includes didn't
+/// add or remove any, so there are none otherwise.
+fn source_lines_of_failed_tests(stdout: &str, tested: &str, source: &str) -> Vec<usize> {
+    lazy_static! {
+        static ref FAILED_TEST: Regex =
+            Regex::new(r"^---- .* \(line (\d+)\) stdout ----$").unwrap();
+    }
+
+    let tested_blocks = code_block_lines(tested);
+    let source_blocks = code_block_lines(source);
+    if tested_blocks.len() != source_blocks.len() {
+        return Vec::new();
+    }
+
+    let mut lines: Vec<_> = stdout
+        .lines()
+        .filter_map(|line| FAILED_TEST.captures(line))
+        .filter_map(|caps| caps[1].parse::<usize>().ok())
+        .filter_map(|line| {
+            tested_blocks
+                .iter()
+                .position(|&block| block == line)
+                .map(|n| source_blocks[n])
+        })
+        .collect();
+    lines.sort();
+    lines
+}
+
+/// `dir` without symlinks or `..`, for comparing directories which may not
+/// exist yet: only the part of it which exists is canonicalized.
+fn resolve_dir(dir: &Path) -> PathBuf {
+    let mut missing = Vec::new();
+    let mut existing = dir;
+    loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            return missing
+                .iter()
+                .rev()
+                .fold(canonical, |path, name| path.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return dir.to_path_buf(),
+        }
+    }
+}
+
+/// The (1-based) line each code block in the markdown starts at.
+fn code_block_lines(markdown: &str) -> Vec<usize> {
+    Parser::new(markdown)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                Some(markdown[..range.start].matches('\n').count() + 1)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::path::Path;
     use std::str::FromStr;
     use toml::value::{Table, Value};
 
+    fn book_in(root: &Path, build_dir: &str) -> MDBook {
+        let mut config = Config::default();
+        config.build.build_dir = PathBuf::from(build_dir);
+        MDBook {
+            root: root.to_path_buf(),
+            config,
+            book: Book::new(),
+            renderers: Vec::new(),
+            preprocessors: Vec::new(),
+            language: None,
+            in_memory: false,
+            diagnostics: Diagnostics::new(),
+            post_processors: PostProcessors::default(),
+            archives: Archives::default(),
+        }
+    }
+
+    #[test]
+    fn clean_removes_the_build_dir() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        fs::create_dir_all(temp.path().join("out").join("html")).unwrap();
+        fs::write(temp.path().join("out").join("html").join("index.html"), "").unwrap();
+
+        book_in(temp.path(), "out").clean().unwrap();
+
+        assert!(!temp.path().join("out").exists());
+        assert!(temp.path().exists());
+    }
+
+    #[test]
+    fn the_only_backend_builds_in_the_build_dir() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let mut md = book_in(temp.path(), "public");
+        md.with_renderer(HtmlHandlebars::new());
+        assert_eq!(md.build_dir_for("html"), temp.path().join("public"));
+
+        md.with_renderer(MarkdownRenderer::new());
+        assert_eq!(md.build_dir_for("html"), temp.path().join("public/html"));
+        assert_eq!(
+            md.build_dir_for("markdown"),
+            temp.path().join("public/markdown")
+        );
+    }
+
+    #[test]
+    fn build_dirs_are_relative_to_the_root_unless_absolute() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let elsewhere = TempFileBuilder::new().prefix("output").tempdir().unwrap();
+
+        let md = book_in(temp.path(), "../public");
+        assert_eq!(md.build_dir(), temp.path().join("../public"));
+
+        let md = book_in(temp.path(), elsewhere.path().to_str().unwrap());
+        assert_eq!(md.build_dir(), elsewhere.path());
+        assert_eq!(md.build_dir_for("html"), elsewhere.path());
+    }
+
+    #[test]
+    fn a_build_dir_inside_the_source_dir_is_a_warning() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        fs::create_dir_all(temp.path().join("docs")).unwrap();
+        let mut md = book_in(temp.path(), "docs/../docs/public");
+        md.config.book.src = PathBuf::from("docs");
+
+        md.check_config();
+        assert_eq!(
+            md.warnings(),
+            vec![Diagnostic::new(
+                DiagnosticKind::Config,
+                "The build directory docs/../docs/public is inside the source directory docs, \
+                 it should be moved out of it"
+            )]
+        );
+
+        md.diagnostics.clear();
+        md.config.build.build_dir = PathBuf::from("public");
+        md.check_config();
+        assert!(md.warnings().is_empty());
+    }
+
+    #[test]
+    fn deprecated_keys_and_dropped_book_json_keys_are_warnings() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        fs::write(
+            temp.path().join("book.json"),
+            r#"{"title": "Old", "livereload": true}"#,
+        )
+        .unwrap();
+        let mut md = book_in(temp.path(), "book");
+        md.config
+            .set("output.html.site_url", "https://example.com/")
+            .unwrap();
+
+        md.check_config();
+        assert_eq!(
+            md.warnings(),
+            vec![
+                Diagnostic::new(
+                    DiagnosticKind::Config,
+                    "`livereload` of book.json has no equivalent in book.toml, ignoring it"
+                ),
+                Diagnostic::new(
+                    DiagnosticKind::Config,
+                    "The config key `output.html.site_url` is deprecated, \
+                     use `output.html.site-url` instead"
+                ),
+            ]
+        );
+
+        // The book.json isn't read when there's a book.toml
+        md.diagnostics.clear();
+        fs::write(temp.path().join("book.toml"), "").unwrap();
+        md.check_config();
+        assert_eq!(md.warnings().len(), 1);
+    }
+
+    #[test]
+    fn cleaning_a_missing_build_dir_is_fine() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+
+        book_in(temp.path(), "book").clean().unwrap();
+    }
+
+    #[test]
+    fn clean_refuses_to_remove_anything_outside_the_root() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let root = temp.path().join("root");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(temp.path().join("sibling")).unwrap();
+
+        let outside = temp.path().join("sibling");
+        let outside = outside.to_str().unwrap();
+        for build_dir in &["..", "../sibling", "src/../..", ".", "", outside] {
+            let got = book_in(&root, build_dir).clean();
+            assert!(got.is_err(), "{:?} was removed", build_dir);
+        }
+
+        assert!(root.join("src").exists());
+        assert!(temp.path().join("sibling").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn clean_doesnt_follow_symlinks_out_of_the_root() {
+        use std::os::unix::fs::symlink;
+
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let root = temp.path().join("root");
+        let elsewhere = temp.path().join("elsewhere");
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&elsewhere).unwrap();
+        fs::write(elsewhere.join("precious.txt"), "").unwrap();
+
+        // a symlink inside the build dir
+        fs::create_dir_all(root.join("book")).unwrap();
+        symlink(&elsewhere, root.join("book").join("link")).unwrap();
+        book_in(&root, "book").clean().unwrap();
+        assert!(!root.join("book").exists());
+        assert!(elsewhere.join("precious.txt").exists());
+
+        // the build dir itself is a symlink
+        symlink(&elsewhere, root.join("book")).unwrap();
+        book_in(&root, "book").clean().unwrap();
+        assert!(fs::symlink_metadata(root.join("book")).is_err());
+        assert!(elsewhere.join("precious.txt").exists());
+
+        // a symlink to a directory outside the root as a parent
+        symlink(&elsewhere, root.join("link")).unwrap();
+        assert!(book_in(&root, "link/precious.txt").clean().is_err());
+        assert!(elsewhere.join("precious.txt").exists());
+    }
+
+    fn book_with_unlisted_files() -> (tempfile::TempDir, MDBook) {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let files = [
+            ("SUMMARY.md", "# Summary\n\n- [Chapter](chapter.md)\n"),
+            (
+                "chapter.md",
+                "# Chapter\n\n{{#include shared/snippet.md}}\n",
+            ),
+            ("shared/snippet.md", "Included {{#include nested.md}}\n"),
+            ("shared/nested.md", "Included by an included file\n"),
+            ("orphan.md", "# Forgotten\n"),
+            (".hidden/draft.md", "# Hidden\n"),
+            ("notes/todo.md", "# Ignored\n"),
+        ];
+        for &(path, content) in &files {
+            utils::fs::write_file(&temp.path().join("src"), path, content.as_bytes()).unwrap();
+        }
+        let book_toml = "[build]\nignore-unlisted = [\"notes\"]\n";
+        fs::write(temp.path().join("book.toml"), book_toml).unwrap();
+
+        let md = MDBook::load(temp.path()).unwrap();
+        (temp, md)
+    }
+
+    #[test]
+    fn files_missing_from_the_summary_are_found() {
+        let (_temp, md) = book_with_unlisted_files();
+
+        assert_eq!(
+            md.unlisted_files().unwrap(),
+            vec![PathBuf::from("orphan.md")]
+        );
+        // not an error without strict mode
+        md.build().unwrap();
+        assert_eq!(
+            md.warnings(),
+            vec![Diagnostic::new(
+                DiagnosticKind::UnlistedFile,
+                "This file isn't listed in SUMMARY.md, or included by a chapter"
+            )
+            .with_chapter("orphan.md")]
+        );
+    }
+
+    #[test]
+    fn warnings_fail_builds_which_deny_them() {
+        let (_temp, mut md) = book_with_unlisted_files();
+        md.config.build.deny_warnings = true;
+
+        let err = md.build().unwrap_err().to_string();
+
+        assert_eq!(
+            err,
+            "The build had warnings (1), and `build.deny-warnings` is set"
+        );
+        assert_eq!(md.warnings().len(), 1);
+    }
+
+    #[test]
+    fn files_missing_from_the_summary_fail_strict_builds() {
+        let (temp, mut md) = book_with_unlisted_files();
+        md.config.build.strict = true;
+
+        let err = md.build().unwrap_err().to_string();
+
+        assert_eq!(
+            err,
+            "These files aren't listed in SUMMARY.md, or included by a chapter: orphan.md"
+        );
+        assert!(!temp.path().join("book").exists());
+    }
+
+    #[test]
+    fn the_404_page_and_the_summary_are_listed() {
+        let (temp, mut md) = book_with_unlisted_files();
+        let src = temp.path().join("src");
+        fs::remove_file(src.join("orphan.md")).unwrap();
+        utils::fs::write_file(&src, "404.md", b"# Not here\n").unwrap();
+        md.config.set("output.html.input-404", "404.md").unwrap();
+        md.config.build.strict = true;
+
+        assert_eq!(md.unlisted_files().unwrap(), Vec::<PathBuf>::new());
+        md.build().unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn directories_linked_to_themselves_are_looked_into_once() {
+        use std::os::unix::fs::symlink;
+
+        let (temp, md) = book_with_unlisted_files();
+        let src = temp.path().join("src");
+        symlink(&src, src.join("shared/loop")).unwrap();
+
+        assert_eq!(
+            md.unlisted_files().unwrap(),
+            vec![PathBuf::from("orphan.md")]
+        );
+    }
+
+    #[test]
+    fn failed_tests_are_mapped_back_to_the_source() {
+        let source = "# Chapter\n\n```rust\n{{#include a.rs}}\n```\n\n```rust\nfails();\n```\n";
+        let tested = "# Chapter\n\n```rust\nfn a() {}\nfn b() {}\n```\n\n```rust\nfails();\n```\n";
+        let stdout = "running 2 tests\n\
+                      test chapter.md - Chapter (line 3) ... ok\n\
+                      test chapter.md - Chapter (line 8) ... FAILED\n\n\
+                      failures:\n\n\
+                      ---- chapter.md - Chapter (line 8) stdout ----\n\
+                      error[E0425]: cannot find function `fails` in this scope\n";
+
+        assert_eq!(code_block_lines(source), vec![3, 7]);
+        assert_eq!(code_block_lines(tested), vec![3, 8]);
+        assert_eq!(
+            source_lines_of_failed_tests(stdout, tested, source),
+            vec![7]
+        );
+
+        // An include with a code block of its own
+        let tested = "# Chapter\n\n```rust\nfn a() {}\n```\n\n```rust\nfn b() {}\n```\n\n\
+                      ```rust\nfails();\n```\n";
+        assert!(source_lines_of_failed_tests(stdout, tested, source).is_empty());
+    }
+
     #[test]
     fn config_defaults_to_html_renderer_if_empty() {
         let cfg = Config::default();
@@ -484,6 +1512,16 @@ mod tests {
         assert_eq!(got[0].name(), "random");
     }
 
+    #[test]
+    fn renderers_are_in_the_order_of_book_toml() {
+        let cfg = Config::from_str("[output.markdown]\n[output.json]\n[output.html]\n").unwrap();
+
+        let got = determine_renderers(&cfg);
+
+        let names: Vec<_> = got.iter().map(|renderer| renderer.name()).collect();
+        assert_eq!(names, ["markdown", "json", "html"]);
+    }
+
     #[test]
     fn add_a_random_renderer_with_custom_command_to_the_config() {
         let mut cfg = Config::default();
@@ -576,17 +1614,81 @@ mod tests {
             .get_preprocessor("links")
             .and_then(|links| links.get("renderers"))
             .and_then(Value::as_array)
-            .and_then(|renderers| renderers.get(0))
+            .and_then(|renderers| renderers.first())
             .and_then(Value::as_str)
             .unwrap();
         assert_eq!(html, "html");
-        let html_renderer = HtmlHandlebars::default();
+        let html_renderer = HtmlHandlebars;
         let pre = LinkPreprocessor::new();
 
         let should_run = preprocessor_should_run(&pre, &html_renderer, &cfg);
         assert!(should_run);
     }
 
+    fn preprocessor_names(cfg_str: &str) -> Result<Vec<String>> {
+        let cfg = Config::from_str(cfg_str).unwrap();
+        let preprocessors = determine_preprocessors(&cfg)?;
+        Ok(preprocessors
+            .iter()
+            .map(|pre| pre.name().to_string())
+            .collect())
+    }
+
+    #[test]
+    fn preprocessors_are_ordered_by_before_and_after() {
+        let cfg_str = r#"
+        [preprocessor.glossary]
+        after = ["links"]
+
+        [preprocessor.spelling]
+        before = ["links", "templates"]
+
+        [preprocessor.templates]
+
+        [preprocessor.index]
+        after = ["templates"]
+        "#;
+
+        assert_eq!(
+            preprocessor_names(cfg_str).unwrap(),
+            vec!["spelling", "links", "glossary", "templates", "index"]
+        );
+    }
+
+    #[test]
+    fn declaring_a_default_preprocessor_doesnt_run_it_twice() {
+        let cfg_str = r#"
+        [preprocessor.links]
+        renderers = ["html"]
+        "#;
+
+        assert_eq!(preprocessor_names(cfg_str).unwrap(), vec!["links", "index"]);
+    }
+
+    #[test]
+    fn preprocessors_cant_run_before_each_other() {
+        let cfg_str = r#"
+        [preprocessor.a]
+        before = ["b"]
+
+        [preprocessor.b]
+        before = ["a"]
+        "#;
+
+        let err = preprocessor_names(cfg_str).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The preprocessors a, b have to run before each other, \
+             check their `before` and `after` keys"
+        );
+
+        let err = preprocessor_names("[preprocessor.a]\nafter = [\"nope\"]\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "preprocessor.a.after contains \"nope\", which isn't a preprocessor of the book"
+        );
+    }
+
     struct BoolPreprocessor(bool);
     impl Preprocessor for BoolPreprocessor {
         fn name(&self) -> &str {
@@ -5,6 +5,7 @@ use std::fmt::{self, Display, Formatter};
 use std::iter::FromIterator;
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 /// Parse the text from a `SUMMARY.md` file into a sort of "recipe" to be
 /// used when loading a book from disk.
@@ -81,6 +82,10 @@ pub struct Link {
     pub number: Option<SectionNumber>,
     /// Any nested items this chapter may contain.
     pub nested_items: Vec<SummaryItem>,
+    /// The line of `SUMMARY.md` the link is on, for the error messages.
+    /// `None` for the links which weren't parsed from one.
+    #[serde(default)]
+    pub line: Option<usize>,
 }
 
 impl Link {
@@ -91,6 +96,7 @@ impl Link {
             location: Some(location.as_ref().to_path_buf()),
             number: None,
             nested_items: Vec::new(),
+            line: None,
         }
     }
 }
@@ -102,6 +108,7 @@ impl Default for Link {
             location: Some(PathBuf::new()),
             number: None,
             nested_items: Vec::new(),
+            line: None,
         }
     }
 }
@@ -222,7 +229,7 @@ impl<'a> SummaryParser<'a> {
     /// Get the current line and column to give the user more useful error
     /// messages.
     fn current_location(&self) -> (usize, usize) {
-        let previous_text = self.src[..self.offset].as_bytes();
+        let previous_text = &self.src.as_bytes()[..self.offset];
         let line = Memchr::new(b'\n', previous_text).count() + 1;
         let start_of_line = memchr::memrchr(b'\n', previous_text).unwrap_or(0);
         let col = self.src[start_of_line..self.offset].chars().count();
@@ -333,6 +340,7 @@ impl<'a> SummaryParser<'a> {
 
     /// Finishes parsing a link once the `Event::Start(Tag::Link(..))` has been opened.
     fn parse_link(&mut self, href: String) -> Link {
+        let (line, _) = self.current_location();
         let link_content = collect_events!(self.stream, end Tag::Link(..));
         let name = stringify_events(link_content);
 
@@ -347,6 +355,7 @@ impl<'a> SummaryParser<'a> {
             location: path,
             number: None,
             nested_items: Vec::new(),
+            line: Some(line),
         }
     }
 
@@ -381,7 +390,7 @@ impl<'a> SummaryParser<'a> {
                 }
                 Some(ev @ Event::Start(Tag::List(..))) => {
                     self.back(ev);
-                    let mut bunch_of_items = self.parse_nested_numbered(&root_number)?;
+                    let mut bunch_of_items = self.parse_nested_numbered(root_number)?;
 
                     // if we've resumed after something like a rule the root sections
                     // will be numbered from 1. We need to manually go back and update
@@ -548,6 +557,39 @@ fn update_section_numbers(sections: &mut [SummaryItem], level: usize, by: u32) {
     }
 }
 
+/// Restart the section numbers of the numbered chapters after each part
+/// title, so the first chapter of every part is numbered `1.`.
+pub(crate) fn restart_section_numbers_per_part(items: &mut [SummaryItem]) {
+    let mut first_in_part = None;
+
+    for item in items {
+        match *item {
+            SummaryItem::PartTitle(_) => first_in_part = None,
+            SummaryItem::Link(ref mut link) => {
+                let root_number = match link.number {
+                    Some(ref number) if !number.is_empty() => number[0],
+                    _ => continue,
+                };
+                let first = *first_in_part.get_or_insert(root_number);
+                lower_root_section_numbers(link, first - 1);
+            }
+            SummaryItem::Separator => {}
+        }
+    }
+}
+
+fn lower_root_section_numbers(link: &mut Link, by: u32) {
+    if let Some(ref mut number) = link.number {
+        number.0[0] -= by;
+    }
+
+    for item in &mut link.nested_items {
+        if let SummaryItem::Link(ref mut nested) = *item {
+            lower_root_section_numbers(nested, by);
+        }
+    }
+}
+
 /// Gets a pointer to the last `Link` in a list of `SummaryItem`s, and its
 /// index.
 fn get_last_link(links: &mut [SummaryItem]) -> Result<(usize, &mut Link)> {
@@ -555,8 +597,7 @@ fn get_last_link(links: &mut [SummaryItem]) -> Result<(usize, &mut Link)> {
         .iter_mut()
         .enumerate()
         .filter_map(|(i, item)| item.maybe_link_mut().map(|l| (i, l)))
-        .rev()
-        .next()
+        .next_back()
         .ok_or_else(||
             anyhow::anyhow!("Unable to get last link because the list of SummaryItems doesn't contain any Links")
             )
@@ -576,9 +617,29 @@ fn stringify_events(events: Vec<Event<'_>>) -> String {
 
 /// A section number like "1.2.3", basically just a newtype'd `Vec<u32>` with
 /// a pretty `Display` impl.
-#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+///
+/// Section numbers are ordered the way their chapters appear in a book, so
+/// `1.2.` comes before `1.10.` and a chapter comes before its sub-chapters.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Default, Serialize, Deserialize)]
 pub struct SectionNumber(pub Vec<u32>);
 
+impl SectionNumber {
+    /// How deeply nested the section is, where top-level chapters like `2.`
+    /// are at level 1.
+    pub fn level(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The number of the section containing this one, or `None` for a
+    /// top-level chapter (or an empty section number).
+    pub fn parent(&self) -> Option<SectionNumber> {
+        match self.0.split_last() {
+            Some((_, parent)) if !parent.is_empty() => Some(SectionNumber(parent.to_vec())),
+            _ => None,
+        }
+    }
+}
+
 impl Display for SectionNumber {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         if self.0.is_empty() {
@@ -611,6 +672,36 @@ impl FromIterator<u32> for SectionNumber {
     }
 }
 
+impl FromStr for SectionNumber {
+    type Err = Error;
+
+    /// Parse a section number like `1.2.3.` or `1.2.3`. As with the `Display`
+    /// impl, `0` is the empty section number.
+    fn from_str(s: &str) -> Result<SectionNumber> {
+        let s = s.trim();
+        if s.is_empty() || s == "0" {
+            return Ok(SectionNumber::default());
+        }
+
+        let mut parts: Vec<_> = s.split('.').collect();
+        if parts.len() > 1 && parts.last() == Some(&"") {
+            // The trailing dot
+            parts.pop();
+        }
+
+        parts
+            .into_iter()
+            .map(|part| {
+                if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                    bail!("Invalid section number \"{}\"", s);
+                }
+                part.parse()
+                    .with_context(|| format!("Invalid section number \"{}\"", s))
+            })
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -629,6 +720,88 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_section_numbers() {
+        let inputs = vec![
+            ("1.2.3.", vec![1, 2, 3]),
+            ("1.2.3", vec![1, 2, 3]),
+            ("4.", vec![4]),
+            ("4", vec![4]),
+            (" 10.20. ", vec![10, 20]),
+            ("0.", vec![0]),
+            ("0", vec![]),
+            ("", vec![]),
+        ];
+
+        for (input, should_be) in inputs {
+            let got: SectionNumber = input.parse().unwrap();
+            assert_eq!(got, SectionNumber(should_be), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn invalid_section_numbers_are_rejected() {
+        let inputs = vec![
+            ".",
+            "1..2",
+            ".1",
+            "1.2..",
+            "a.b",
+            "1.-2",
+            "+1",
+            "1 .2",
+            "99999999999",
+        ];
+
+        for input in inputs {
+            assert!(input.parse::<SectionNumber>().is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn section_numbers_round_trip_through_display() {
+        let inputs = vec![vec![], vec![1], vec![1, 3], vec![1, 2, 3]];
+
+        for input in inputs {
+            let section = SectionNumber(input);
+            assert_eq!(
+                section.to_string().parse::<SectionNumber>().unwrap(),
+                section
+            );
+        }
+    }
+
+    #[test]
+    fn section_number_level_and_parent() {
+        let empty = SectionNumber::default();
+        assert_eq!(empty.level(), 0);
+        assert_eq!(empty.parent(), None);
+
+        let top = SectionNumber(vec![3]);
+        assert_eq!(top.level(), 1);
+        assert_eq!(top.parent(), None);
+
+        let nested = SectionNumber(vec![3, 1, 4]);
+        assert_eq!(nested.level(), 3);
+        assert_eq!(nested.parent(), Some(SectionNumber(vec![3, 1])));
+        assert_eq!(
+            nested.parent().and_then(|p| p.parent()),
+            Some(SectionNumber(vec![3]))
+        );
+    }
+
+    #[test]
+    fn section_numbers_sort_in_reading_order() {
+        let mut sections: Vec<SectionNumber> = vec!["2.", "1.10.", "1.2.1.", "1.", "1.2."]
+            .into_iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        sections.sort();
+
+        let got: Vec<_> = sections.iter().map(ToString::to_string).collect();
+        assert_eq!(got, vec!["1.", "1.2.", "1.2.1.", "1.10.", "2."]);
+    }
+
     #[test]
     fn parse_initial_title() {
         let src = "# Summary";
@@ -671,11 +844,13 @@ mod tests {
             SummaryItem::Link(Link {
                 name: String::from("First"),
                 location: Some(PathBuf::from("./first.md")),
+                line: Some(1),
                 ..Default::default()
             }),
             SummaryItem::Link(Link {
                 name: String::from("Second"),
                 location: Some(PathBuf::from("./second.md")),
+                line: Some(2),
                 ..Default::default()
             }),
         ];
@@ -712,6 +887,7 @@ mod tests {
         let should_be = Link {
             name: String::from("First"),
             location: Some(PathBuf::from("./first.md")),
+            line: Some(1),
             ..Default::default()
         };
 
@@ -733,6 +909,7 @@ mod tests {
         let link = Link {
             name: String::from("First"),
             location: Some(PathBuf::from("./first.md")),
+            line: Some(1),
             number: Some(SectionNumber(vec![1])),
             ..Default::default()
         };
@@ -754,10 +931,12 @@ mod tests {
             SummaryItem::Link(Link {
                 name: String::from("First"),
                 location: Some(PathBuf::from("./first.md")),
+                line: Some(1),
                 number: Some(SectionNumber(vec![1])),
                 nested_items: vec![SummaryItem::Link(Link {
                     name: String::from("Nested"),
                     location: Some(PathBuf::from("./nested.md")),
+                    line: Some(2),
                     number: Some(SectionNumber(vec![1, 1])),
                     nested_items: Vec::new(),
                 })],
@@ -765,6 +944,7 @@ mod tests {
             SummaryItem::Link(Link {
                 name: String::from("Second"),
                 location: Some(PathBuf::from("./second.md")),
+                line: Some(3),
                 number: Some(SectionNumber(vec![2])),
                 nested_items: Vec::new(),
             }),
@@ -786,12 +966,14 @@ mod tests {
             SummaryItem::Link(Link {
                 name: String::from("First"),
                 location: Some(PathBuf::from("./first.md")),
+                line: Some(1),
                 number: Some(SectionNumber(vec![1])),
                 nested_items: Vec::new(),
             }),
             SummaryItem::Link(Link {
                 name: String::from("Second"),
                 location: Some(PathBuf::from("./second.md")),
+                line: Some(3),
                 number: Some(SectionNumber(vec![2])),
                 nested_items: Vec::new(),
             }),
@@ -814,12 +996,14 @@ mod tests {
             SummaryItem::Link(Link {
                 name: String::from("First"),
                 location: Some(PathBuf::from("./first.md")),
+                line: Some(1),
                 number: Some(SectionNumber(vec![1])),
                 nested_items: Vec::new(),
             }),
             SummaryItem::Link(Link {
                 name: String::from("Second"),
                 location: Some(PathBuf::from("./second.md")),
+                line: Some(2),
                 number: Some(SectionNumber(vec![2])),
                 nested_items: Vec::new(),
             }),
@@ -827,10 +1011,12 @@ mod tests {
             SummaryItem::Link(Link {
                 name: String::from("Third"),
                 location: Some(PathBuf::from("./third.md")),
+                line: Some(4),
                 number: Some(SectionNumber(vec![3])),
                 nested_items: vec![SummaryItem::Link(Link {
                     name: String::from("Fourth"),
                     location: Some(PathBuf::from("./fourth.md")),
+                    line: Some(5),
                     number: Some(SectionNumber(vec![3, 1])),
                     nested_items: Vec::new(),
                 })],
@@ -843,6 +1029,44 @@ mod tests {
         assert_eq!(got, should_be);
     }
 
+    #[test]
+    fn section_numbers_can_restart_for_each_part() {
+        let src = "- [First](./first.md)\n\t- [Nested](./nested.md)\n- [Second](./second.md)\n\
+                   # Part 2\n- [Third](./third.md)\n\t- [Fourth](./fourth.md)\n\
+                   # Part 3\n- [Fifth](./fifth.md)";
+
+        let mut parser = SummaryParser::new(src);
+        let mut got = parser.parse_parts().unwrap();
+        restart_section_numbers_per_part(&mut got);
+
+        fn numbers(items: &[SummaryItem], out: &mut Vec<String>) {
+            for item in items {
+                match *item {
+                    SummaryItem::Link(ref link) => {
+                        out.push(format!("{} {}", link.number.as_ref().unwrap(), link.name));
+                        numbers(&link.nested_items, out);
+                    }
+                    SummaryItem::PartTitle(ref title) => out.push(title.clone()),
+                    SummaryItem::Separator => {}
+                }
+            }
+        }
+        let mut flattened = Vec::new();
+        numbers(&got, &mut flattened);
+
+        let should_be = vec![
+            "1. First",
+            "1.1. Nested",
+            "2. Second",
+            "Part 2",
+            "1. Third",
+            "1.1. Fourth",
+            "Part 3",
+            "1. Fifth",
+        ];
+        assert_eq!(flattened, should_be);
+    }
+
     /// This test ensures the book will continue to pass because it breaks the
     /// `SUMMARY.md` up using level 2 headers ([example]).
     ///
@@ -854,12 +1078,14 @@ mod tests {
             SummaryItem::Link(Link {
                 name: String::from("First"),
                 location: Some(PathBuf::from("./first.md")),
+                line: Some(1),
                 number: Some(SectionNumber(vec![1])),
                 nested_items: Vec::new(),
             }),
             SummaryItem::Link(Link {
                 name: String::from("Second"),
                 location: Some(PathBuf::from("./second.md")),
+                line: Some(5),
                 number: Some(SectionNumber(vec![2])),
                 nested_items: Vec::new(),
             }),
@@ -882,6 +1108,7 @@ mod tests {
         let should_be = vec![SummaryItem::Link(Link {
             name: String::from("Empty"),
             location: None,
+            line: Some(1),
             number: Some(SectionNumber(vec![1])),
             nested_items: Vec::new(),
         })];
@@ -900,6 +1127,7 @@ mod tests {
             SummaryItem::Link(Link {
                 name: String::from("First"),
                 location: Some(PathBuf::from("./first.md")),
+                line: Some(1),
                 number: Some(SectionNumber(vec![1])),
                 nested_items: Vec::new(),
             }),
@@ -907,6 +1135,7 @@ mod tests {
             SummaryItem::Link(Link {
                 name: String::from("Second"),
                 location: Some(PathBuf::from("./second.md")),
+                line: Some(3),
                 number: Some(SectionNumber(vec![2])),
                 nested_items: Vec::new(),
             }),
@@ -914,6 +1143,7 @@ mod tests {
             SummaryItem::Link(Link {
                 name: String::from("Third"),
                 location: Some(PathBuf::from("./third.md")),
+                line: Some(5),
                 number: Some(SectionNumber(vec![3])),
                 nested_items: Vec::new(),
             }),
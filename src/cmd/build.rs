@@ -1,7 +1,10 @@
-use crate::{get_book_dir, open};
+use crate::{get_book_dir, open, write_json_error};
 use clap::{App, ArgMatches, SubCommand};
 use mdbook::errors::Result;
-use mdbook::MDBook;
+use mdbook::{BookItem, MDBook};
+use serde_json::json;
+use std::io;
+use std::time::Instant;
 
 // Create clap subcommand arguments
 pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
@@ -17,18 +20,85 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
              (Defaults to the Current Directory when omitted)'",
         )
         .arg_from_usage("-o, --open 'Opens the compiled book in a web browser'")
+        .arg_from_usage("--strict 'Fails the build if links between the chapters are broken'")
+        .arg_from_usage("--deny-warnings 'Fails the build if it has any warnings'")
+        .arg_from_usage(
+            "--check-external 'Checks the links of every page of the book, \
+             including the external ones'",
+        )
+        .arg_from_usage("--timings 'Reports how long each chapter and step of the build took'")
+        .arg_from_usage("--migrate 'Writes the book.toml equivalent to a legacy book.json'")
 }
 
 // Build command implementation
 pub fn execute(args: &ArgMatches) -> Result<()> {
     let book_dir = get_book_dir(args);
+    if args.is_present("migrate") {
+        MDBook::migrate_legacy_config(&book_dir)?;
+    }
     let mut book = MDBook::load(&book_dir)?;
 
     if let Some(dest_dir) = args.value_of("dest-dir") {
         book.config.build.build_dir = dest_dir.into();
     }
+    if args.is_present("strict") {
+        book.config.build.strict = true;
+    }
+    if args.is_present("deny-warnings") {
+        book.config.build.deny_warnings = true;
+    }
+    if args.is_present("timings") {
+        book.config.build.timings = true;
+    }
+    if args.is_present("check-external") {
+        book.config.set("output.html.link-check.enable", true)?;
+        book.config
+            .set("output.html.link-check.check-external", true)?;
+    }
 
-    book.build()?;
+    let start = Instant::now();
+    let result = book.build();
+    if result.is_ok() {
+        for path in book.archives() {
+            info!("Archived the book to {}", path.display());
+        }
+    }
+    if args.value_of("message-format") == Some("json") {
+        // The last message summarises the build
+        let warnings = book.warnings();
+        let chapters = book
+            .iter()
+            .filter(|item| match **item {
+                BookItem::Chapter(ref ch) => !ch.is_draft_chapter(),
+                _ => false,
+            })
+            .count();
+        let archives = if result.is_ok() {
+            book.archives()
+        } else {
+            Vec::new()
+        };
+        let summary = json!({
+            "level": "info",
+            "target": "mdbook",
+            "message": "Build summary",
+            "reason": "build-summary",
+            "success": result.is_ok(),
+            "chapters": chapters,
+            "duration": start.elapsed().as_secs_f64(),
+            "warning_count": warnings.len(),
+            "warnings": warnings,
+            "archives": archives,
+        });
+        if let Err(ref e) = result {
+            write_json_error(&mut io::stderr(), e)?;
+        }
+        eprintln!("{}", summary);
+        if result.is_err() {
+            std::process::exit(101);
+        }
+    }
+    result?;
 
     if args.is_present("open") {
         // FIXME: What's the right behaviour if we don't use the HTML renderer?
@@ -1,8 +1,6 @@
 use crate::get_book_dir;
-use anyhow::Context;
 use clap::{App, ArgMatches, SubCommand};
 use mdbook::MDBook;
-use std::fs;
 
 // Create clap subcommand arguments
 pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
@@ -23,17 +21,11 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
 // Clean command implementation
 pub fn execute(args: &ArgMatches) -> mdbook::errors::Result<()> {
     let book_dir = get_book_dir(args);
-    let book = MDBook::load(&book_dir)?;
+    let mut book = MDBook::load(&book_dir)?;
 
-    let dir_to_remove = match args.value_of("dest-dir") {
-        Some(dest_dir) => dest_dir.into(),
-        None => book.root.join(&book.config.build.build_dir),
-    };
-
-    if dir_to_remove.exists() {
-        fs::remove_dir_all(&dir_to_remove)
-            .with_context(|| "Unable to remove the build directory")?;
+    if let Some(dest_dir) = args.value_of("dest-dir") {
+        book.config.build.build_dir = dest_dir.into();
     }
 
-    Ok(())
+    book.clean()
 }
@@ -0,0 +1,295 @@
+use crate::get_book_dir;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use mdbook::config::Config;
+use mdbook::errors::Result;
+use mdbook::MDBook;
+use std::fs;
+use std::path::Path;
+use toml::value::{Table, Value};
+
+/// The keys mdBook sets itself, which aren't meant for `book.toml`.
+const INTERNAL_KEYS: &[(&str, &str)] = &[("output.html.livereload-url", "set by `mdbook serve`")];
+
+// Create clap subcommand arguments
+pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("config")
+        .about("Prints the configuration of a book, as the renderers see it")
+        .arg_from_usage(
+            "-d, --dest-dir=[dest-dir] 'Output directory for the book{n}\
+             Relative paths are interpreted relative to the book's root directory.{n}\
+             If omitted, mdBook uses build.build-dir from book.toml or defaults to `./book`.'",
+        )
+        .arg_from_usage(
+            "[dir] 'Root directory for the book{n}\
+             (Defaults to the Current Directory when omitted)'",
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["toml", "json"])
+                .default_value("toml")
+                .help("The format to print the configuration in"),
+        )
+        .arg_from_usage(
+            "--explain 'Groups the keys by where their values come from:{n}\
+             the defaults, book.toml, the environment or the command line'",
+        )
+}
+
+// Config command implementation
+pub fn execute(args: &ArgMatches) -> Result<()> {
+    let book_dir = get_book_dir(args);
+    let mut config = MDBook::load_config(&book_dir)?;
+
+    let mut from_command_line = Vec::new();
+    if let Some(dest_dir) = args.value_of("dest-dir") {
+        config.build.build_dir = dest_dir.into();
+        from_command_line.push("build.build-dir");
+    }
+
+    let effective = effective_config(&config)?;
+    if args.is_present("explain") {
+        let sources = Sources {
+            file: file_config(&book_dir)?,
+            env: Config::env_overrides(),
+            command_line: from_command_line,
+        };
+        print!("{}", explain(&effective, &sources));
+    } else if args.value_of("format") == Some("json") {
+        println!("{}", serde_json::to_string_pretty(&effective)?);
+    } else {
+        print!("{}", annotate_internal_keys(&toml::to_string(&effective)?));
+    }
+    Ok(())
+}
+
+/// The configuration as the renderers see it, with the `[output.html]` table
+/// being the HTML renderer's configuration with its defaults when the book is
+/// rendered to HTML.
+fn effective_config(config: &Config) -> Result<Value> {
+    let mut effective = Value::try_from(config)?;
+    let renders_html = match config.get("output") {
+        Some(Value::Table(output)) => output.contains_key("html"),
+        _ => true,
+    };
+
+    if renders_html {
+        let html = config.html_config_checked()?.unwrap_or_default();
+        if let Value::Table(ref mut table) = effective {
+            let output = table
+                .entry("output")
+                .or_insert_with(|| Value::Table(Table::new()));
+            if let Value::Table(output) = output {
+                output.insert("html".to_string(), Value::try_from(&html)?);
+            }
+        }
+    }
+    Ok(effective)
+}
+
+/// The `book.toml` of the book as it's written, or the one of its legacy
+/// `book.json`.
+fn file_config(book_dir: &Path) -> Result<Value> {
+    let config_location = book_dir.join("book.toml");
+    let legacy_location = book_dir.join("book.json");
+    let src = if config_location.exists() {
+        fs::read_to_string(&config_location)?
+    } else if legacy_location.exists() {
+        let json = fs::read_to_string(&legacy_location)?;
+        Config::legacy_json_to_toml(&json, &legacy_location)?
+    } else {
+        String::new()
+    };
+    Ok(toml::from_str(&src)?)
+}
+
+/// Where the values of the configuration come from.
+struct Sources<'a> {
+    file: Value,
+    /// The `MDBOOK_*` variables, with the keys they set.
+    env: Vec<(String, String)>,
+    command_line: Vec<&'a str>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    Default,
+    File,
+    Env(String),
+    CommandLine,
+}
+
+impl<'a> Sources<'a> {
+    fn of(&self, key: &str) -> Source {
+        if self.command_line.contains(&key) {
+            return Source::CommandLine;
+        }
+        let env = self
+            .env
+            .iter()
+            .rev()
+            .find(|(_, env_key)| key == env_key || key.starts_with(&format!("{}.", env_key)));
+        if let Some((var, _)) = env {
+            return Source::Env(var.clone());
+        }
+        let mut value = Some(&self.file);
+        for part in key.split('.') {
+            value = value.and_then(|value| value.get(part));
+        }
+        match value {
+            Some(_) => Source::File,
+            None => Source::Default,
+        }
+    }
+}
+
+/// Every value of `effective` as a `key = value` line, grouped by where the
+/// value comes from.
+fn explain(effective: &Value, sources: &Sources<'_>) -> String {
+    let mut values = Vec::new();
+    leaves("", effective, &mut values);
+
+    let groups = [
+        (Source::Default, "# The defaults"),
+        (Source::File, "# book.toml"),
+        (Source::Env(String::new()), "# The environment"),
+        (Source::CommandLine, "# The command line"),
+    ];
+    let mut explained = Vec::new();
+    for (group, heading) in &groups {
+        let mut lines = Vec::new();
+        for (key, value) in &values {
+            let source = sources.of(key);
+            let in_group = match (&source, group) {
+                (Source::Env(_), Source::Env(_)) => true,
+                (source, group) => source == group,
+            };
+            if !in_group {
+                continue;
+            }
+
+            let mut line = format!("{} = {}", key, value);
+            if let Source::Env(ref var) = source {
+                line.push_str(&format!(" # {}", var));
+            }
+            if let Some(&(_, note)) = INTERNAL_KEYS.iter().find(|&&(k, _)| k == key) {
+                line.push_str(&format!(" # internal, {}", note));
+            }
+            lines.push(line);
+        }
+        if !lines.is_empty() {
+            explained.push(format!("{}\n{}\n", heading, lines.join("\n")));
+        }
+    }
+    explained.join("\n")
+}
+
+/// The values of `value` which aren't tables, by their dotted keys.
+fn leaves(prefix: &str, value: &Value, values: &mut Vec<(String, String)>) {
+    match value {
+        Value::Table(table) if !table.is_empty() => {
+            for (key, value) in table {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                leaves(&key, value, values);
+            }
+        }
+        Value::Table(_) => values.push((prefix.to_string(), String::from("{}"))),
+        value => values.push((prefix.to_string(), value.to_string())),
+    }
+}
+
+/// Mark the lines of `toml` setting the internal keys.
+fn annotate_internal_keys(toml: &str) -> String {
+    let mut table = String::new();
+    let mut annotated = String::new();
+    for line in toml.lines() {
+        if line.starts_with('[') {
+            table = line.trim_matches(|c| c == '[' || c == ']').to_string();
+        }
+        annotated.push_str(line);
+        for &(key, note) in INTERNAL_KEYS {
+            let name = key.rsplit('.').next().unwrap_or(key);
+            if key == format!("{}.{}", table, name) && line.starts_with(&format!("{} =", name)) {
+                annotated.push_str(&format!(" # internal, {}", note));
+            }
+        }
+        annotated.push('\n');
+    }
+    annotated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn the_html_config_has_its_defaults() {
+        let config = Config::from_str("[book]\ntitle = \"A Book\"\n").unwrap();
+        let effective = effective_config(&config).unwrap();
+
+        assert_eq!(effective["book"]["title"], Value::from("A Book"));
+        assert_eq!(effective["build"]["build-dir"], Value::from("book"));
+        assert_eq!(effective["output"]["html"]["copy-fonts"], Value::from(true));
+
+        let config = Config::from_str("[output.markdown]\n").unwrap();
+        let effective = effective_config(&config).unwrap();
+        assert!(effective["output"].get("html").is_none());
+    }
+
+    #[test]
+    fn the_values_are_grouped_by_their_source() {
+        let mut config = Config::from_str("[book]\ntitle = \"A Book\"\n").unwrap();
+        config.set("output.html.site-url", "/docs/").unwrap();
+        config
+            .set("output.html.livereload-url", "ws://localhost:3001")
+            .unwrap();
+        config.build.build_dir = "public".into();
+        let sources = Sources {
+            file: toml::from_str("[book]\ntitle = \"A Book\"\n").unwrap(),
+            env: vec![(
+                String::from("MDBOOK_OUTPUT__HTML__SITE_URL"),
+                String::from("output.html.site-url"),
+            )],
+            command_line: vec!["build.build-dir"],
+        };
+
+        let explained = explain(&effective_config(&config).unwrap(), &sources);
+        let groups: Vec<_> = explained.split("\n\n").collect();
+        assert_eq!(groups.len(), 4, "{}", explained);
+        assert!(groups[0].starts_with("# The defaults\n"));
+        assert!(groups[0].contains("\nbook.src = \"src\"\n"));
+        assert!(groups[0].contains(
+            "\noutput.html.livereload-url = \"ws://localhost:3001\" \
+             # internal, set by `mdbook serve`"
+        ));
+        assert_eq!(groups[1], "# book.toml\nbook.title = \"A Book\"");
+        assert_eq!(
+            groups[2],
+            "# The environment\n\
+             output.html.site-url = \"/docs/\" # MDBOOK_OUTPUT__HTML__SITE_URL"
+        );
+        assert_eq!(
+            groups[3],
+            "# The command line\nbuild.build-dir = \"public\"\n"
+        );
+    }
+
+    #[test]
+    fn internal_keys_are_marked() {
+        let toml =
+            "[output.html]\nlivereload-url = \"ws://localhost:3001\"\nmathjax-support = false\n";
+
+        assert_eq!(
+            annotate_internal_keys(toml),
+            "[output.html]\n\
+             livereload-url = \"ws://localhost:3001\" # internal, set by `mdbook serve`\n\
+             mathjax-support = false\n"
+        );
+    }
+}
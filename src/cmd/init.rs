@@ -1,10 +1,12 @@
 use crate::get_book_dir;
-use clap::{App, ArgMatches, SubCommand};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use mdbook::config;
 use mdbook::errors::Result;
 use mdbook::MDBook;
+use std::fs;
 use std::io;
 use std::io::Write;
+use std::path::Path;
 use std::process::Command;
 
 // Create clap subcommand arguments
@@ -16,27 +18,50 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
             "[dir] 'Directory to create the book in{n}\
              (Defaults to the Current Directory when omitted)'",
         )
-        .arg_from_usage("--theme 'Copies the default theme into your source folder'")
-        .arg_from_usage("--force 'Skips confirmation prompts'")
+        .arg_from_usage("--theme 'Copies the default theme into your book's theme directory'")
+        .arg_from_usage(
+            "--force 'Skips confirmation prompts and creates the book{n}\
+             even if the directory isn't empty'",
+        )
+        .arg_from_usage("--title=[title] 'Sets the book title'")
+        .arg(
+            Arg::with_name("ignore")
+                .long("ignore")
+                .takes_value(true)
+                .possible_values(&["none", "git"])
+                .help("Creates a VCS ignore file (i.e. .gitignore)"),
+        )
 }
 
 // Init command implementation
 pub fn execute(args: &ArgMatches) -> Result<()> {
     let book_dir = get_book_dir(args);
-    let mut builder = MDBook::init(&book_dir);
+    let force = args.is_present("force");
     let mut config = config::Config::default();
 
-    // If flag `--theme` is present, copy theme to src
+    if !force {
+        if let Some(entry) = existing_entry(&book_dir, &config)? {
+            anyhow::bail!(
+                "{} isn't empty (it contains {}), use --force to create the book anyway",
+                book_dir.display(),
+                entry
+            );
+        }
+    }
+
+    let mut builder = MDBook::init(&book_dir);
+
+    // If flag `--theme` is present, copy theme to the theme directory
     if args.is_present("theme") {
-        config.set("output.html.theme", "src/theme")?;
+        let theme_dir = config
+            .html_config()
+            .unwrap_or_default()
+            .theme_dir(&book_dir);
         // Skip this if `--force` is present
-        if !args.is_present("force") {
+        if !force && theme_dir.exists() {
             // Print warning
             println!();
-            println!(
-                "Copying the default theme to {}",
-                builder.config().book.src.display()
-            );
+            println!("Copying the default theme to {}", theme_dir.display());
             println!("This could potentially overwrite files already present in that directory.");
             print!("\nAre you sure you want to continue? (y/n) ");
 
@@ -49,26 +74,57 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
         }
     }
 
-    println!("\nDo you want a .gitignore to be created? (y/n)");
+    match args.value_of("ignore") {
+        Some("git") => {
+            builder.create_gitignore(true);
+        }
+        Some(_) => {}
+        None => {
+            println!("\nDo you want a .gitignore to be created? (y/n)");
 
-    if confirm() {
-        builder.create_gitignore(true);
+            if confirm() {
+                builder.create_gitignore(true);
+            }
+        }
     }
 
-    config.book.title = request_book_title();
+    config.book.title = match args.value_of("title") {
+        Some(title) => Some(title.to_owned()),
+        None => request_book_title(),
+    };
 
     if let Some(author) = get_author_name() {
         debug!("Obtained user name from gitconfig: {:?}", author);
         config.book.authors.push(author);
-        builder.with_config(config);
     }
 
+    builder.with_config(config);
     builder.build()?;
     println!("\nAll done, no errors...");
 
     Ok(())
 }
 
+/// The first entry of the book's directory which would keep it from being
+/// considered empty. Hidden entries (like `.git`) and the source directory,
+/// which may already contain a `SUMMARY.md` to generate the chapters from,
+/// don't count.
+fn existing_entry(book_dir: &Path, config: &config::Config) -> Result<Option<String>> {
+    if !book_dir.exists() {
+        return Ok(None);
+    }
+
+    for entry in fs::read_dir(book_dir)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        if !name.starts_with('.') && Path::new(&*name) != config.book.src {
+            return Ok(Some(name.into_owned()));
+        }
+    }
+
+    Ok(None)
+}
+
 /// Obtains author name from git config file by running the `git config` command.
 fn get_author_name() -> Option<String> {
     let output = Command::new("git")
@@ -102,7 +158,7 @@ fn confirm() -> bool {
     io::stdout().flush().unwrap();
     let mut s = String::new();
     io::stdin().read_line(&mut s).ok();
-    match &*s.trim() {
+    match s.trim() {
         "Y" | "y" | "yes" | "Yes" => true,
         _ => false,
     }
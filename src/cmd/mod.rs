@@ -2,6 +2,7 @@
 
 pub mod build;
 pub mod clean;
+pub mod config;
 pub mod init;
 #[cfg(feature = "serve")]
 pub mod serve;
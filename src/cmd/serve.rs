@@ -1,13 +1,14 @@
 #[cfg(feature = "watch")]
 use super::watch;
 use crate::{get_book_dir, open};
+use anyhow::Context;
 use clap::{App, Arg, ArgMatches, SubCommand};
+use futures_util::future;
 use futures_util::sink::SinkExt;
 use futures_util::StreamExt;
 use mdbook::errors::*;
-use mdbook::utils;
 use mdbook::MDBook;
-use std::net::{SocketAddr, ToSocketAddrs};
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
 use std::path::PathBuf;
 use tokio::sync::broadcast;
 use warp::ws::Message;
@@ -18,7 +19,7 @@ const LIVE_RELOAD_ENDPOINT: &str = "__livereload";
 
 // Create clap subcommand arguments
 pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
-    SubCommand::with_name("serve")
+    let app = SubCommand::with_name("serve")
         .about("Serves a book at http://localhost:3000, and rebuilds it on changes")
         .arg_from_usage(
             "-d, --dest-dir=[dest-dir] 'Output directory for the book{n}\
@@ -47,7 +48,33 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
                 .empty_values(false)
                 .help("Port to use for HTTP connections"),
         )
-        .arg_from_usage("-o, --open 'Opens the book server in a web browser'")
+        .arg(
+            Arg::with_name("websocket-port")
+                .short("w")
+                .long("websocket-port")
+                .takes_value(true)
+                .empty_values(false)
+                .help(
+                    "Port to use for the websocket triggering reloads{n}\
+                     (Defaults to the HTTP port when omitted)",
+                ),
+        )
+        .arg(
+            Arg::with_name("websocket-hostname")
+                .long("websocket-hostname")
+                .takes_value(true)
+                .empty_values(false)
+                .help(
+                    "Hostname the browser connects to for the websocket{n}\
+                     (Defaults to the HTTP hostname when omitted)",
+                ),
+        )
+        .arg_from_usage("-o, --open 'Opens the book server in a web browser'");
+
+    #[cfg(feature = "watch")]
+    let app = app.arg(watch::debounce_arg());
+
+    app
 }
 
 // Serve command implementation
@@ -58,23 +85,43 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
     let port = args.value_of("port").unwrap();
     let hostname = args.value_of("hostname").unwrap();
     let open_browser = args.is_present("open");
+    #[cfg(feature = "watch")]
+    let debounce = watch::debounce(args)?;
 
     let address = format!("{}:{}", hostname, port);
-
-    let livereload_url = format!("ws://{}/{}", address, LIVE_RELOAD_ENDPOINT);
+    let sockaddr = socket_address(&address)?;
+
+    // The websocket is served next to the book, unless it was given a port of
+    // its own
+    let ws_hostname = args.value_of("websocket-hostname").unwrap_or(hostname);
+    let ws_sockaddr = match args.value_of("websocket-port") {
+        Some(ws_port) if ws_port != port => {
+            let ws_port: u16 = ws_port
+                .parse()
+                .with_context(|| format!("Invalid websocket port \"{}\"", ws_port))?;
+            Some(free_port(socket_address(&format!(
+                "{}:{}",
+                hostname, ws_port
+            ))?)?)
+        }
+        _ => None,
+    };
+    let ws_address = match ws_sockaddr {
+        Some(ws_sockaddr) => format!("{}:{}", ws_hostname, ws_sockaddr.port()),
+        None => format!("{}:{}", ws_hostname, port),
+    };
+
+    let livereload_url = format!("ws://{}/{}", ws_address, LIVE_RELOAD_ENDPOINT);
     book.config
         .set("output.html.livereload-url", &livereload_url)?;
 
-    if let Some(dest_dir) = args.value_of("dest-dir") {
-        book.config.build.build_dir = dest_dir.into();
+    let dest_dir = args.value_of("dest-dir").map(PathBuf::from);
+    if let Some(ref dest_dir) = dest_dir {
+        book.config.build.build_dir = dest_dir.clone();
     }
 
     book.build()?;
 
-    let sockaddr: SocketAddr = address
-        .to_socket_addrs()?
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("no address found for {}", address))?;
     let build_dir = book.build_dir_for("html");
 
     // A channel used to broadcast to any websockets to reload when a file changes.
@@ -82,7 +129,7 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
 
     let reload_tx = tx.clone();
     let thread_handle = std::thread::spawn(move || {
-        serve(build_dir, sockaddr, reload_tx);
+        serve(build_dir, sockaddr, ws_sockaddr, reload_tx);
     });
 
     let serving_url = format!("http://{}", address);
@@ -93,34 +140,61 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
     }
 
     #[cfg(feature = "watch")]
-    watch::trigger_on_change(&book, move |paths, book_dir| {
-        info!("Files changed: {:?}", paths);
-        info!("Building book...");
-
-        // FIXME: This area is really ugly because we need to re-set livereload :(
-        let result = MDBook::load(&book_dir)
-            .and_then(|mut b| {
-                b.config
-                    .set("output.html.livereload-url", &livereload_url)?;
-                Ok(b)
-            })
-            .and_then(|b| b.build());
-
-        if let Err(e) = result {
-            error!("Unable to load the book");
-            utils::log_backtrace(&e);
-        } else {
+    watch::rebuild_on_change(
+        &book,
+        debounce,
+        |b| {
+            b.config
+                .set("output.html.livereload-url", &livereload_url)?;
+            if let Some(ref dest_dir) = dest_dir {
+                b.config.build.build_dir = dest_dir.clone();
+            }
+            Ok(())
+        },
+        |_| {
             let _ = tx.send(Message::text("reload"));
-        }
-    });
+        },
+    );
 
     let _ = thread_handle.join();
 
     Ok(())
 }
 
+fn socket_address(address: &str) -> Result<SocketAddr> {
+    address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("no address found for {}", address))
+}
+
+/// Find the first port from `address` onwards which isn't in use yet.
+fn free_port(mut address: SocketAddr) -> Result<SocketAddr> {
+    let requested = address.port();
+    while TcpListener::bind(address).is_err() {
+        match address.port().checked_add(1) {
+            Some(port) => address.set_port(port),
+            None => anyhow::bail!("No free port found after {}", requested),
+        }
+    }
+
+    if address.port() != requested {
+        info!(
+            "Port {} is already in use, using {} for the websocket instead",
+            requested,
+            address.port()
+        );
+    }
+    Ok(address)
+}
+
 #[tokio::main]
-async fn serve(build_dir: PathBuf, address: SocketAddr, reload_tx: broadcast::Sender<Message>) {
+async fn serve(
+    build_dir: PathBuf,
+    address: SocketAddr,
+    ws_address: Option<SocketAddr>,
+    reload_tx: broadcast::Sender<Message>,
+) {
     // A warp Filter which captures `reload_tx` and provides an `rx` copy to
     // receive reload messages.
     let sender = warp::any().map(move || reload_tx.subscribe());
@@ -143,6 +217,16 @@ async fn serve(build_dir: PathBuf, address: SocketAddr, reload_tx: broadcast::Se
         });
     // A warp Filter that serves from the filesystem.
     let book_route = warp::fs::dir(build_dir);
-    let routes = livereload.or(book_route);
-    warp::serve(routes).run(address).await;
+    let routes = livereload.clone().or(book_route);
+
+    match ws_address {
+        Some(ws_address) => {
+            future::join(
+                warp::serve(routes).run(address),
+                warp::serve(livereload).run(ws_address),
+            )
+            .await;
+        }
+        None => warp::serve(routes).run(address).await,
+    }
 }
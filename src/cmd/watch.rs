@@ -1,5 +1,7 @@
 use crate::{get_book_dir, open};
-use clap::{App, ArgMatches, SubCommand};
+use anyhow::Context;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use mdbook::config::HeadExtra;
 use mdbook::errors::Result;
 use mdbook::utils;
 use mdbook::MDBook;
@@ -23,35 +25,111 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
              (Defaults to the Current Directory when omitted)'",
         )
         .arg_from_usage("-o, --open 'Open the compiled book in a web browser'")
+        .arg(debounce_arg())
+}
+
+/// The `--debounce` argument shared by the commands watching the book.
+pub fn debounce_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("debounce")
+        .long("debounce")
+        .takes_value(true)
+        .value_name("ms")
+        .default_value("300")
+        .empty_values(false)
+        .help("How long to wait for more changes before rebuilding, in milliseconds")
+}
+
+/// The value of the `--debounce` argument.
+pub fn debounce(args: &ArgMatches) -> Result<Duration> {
+    let debounce = args.value_of("debounce").unwrap();
+    let millis = debounce
+        .parse()
+        .with_context(|| format!("Invalid debounce \"{}\"", debounce))?;
+    Ok(Duration::from_millis(millis))
 }
 
 // Watch command implementation
 pub fn execute(args: &ArgMatches) -> Result<()> {
     let book_dir = get_book_dir(args);
-    let book = MDBook::load(&book_dir)?;
+    let mut book = MDBook::load(&book_dir)?;
+    let debounce = debounce(args)?;
+
+    let dest_dir = args.value_of("dest-dir").map(PathBuf::from);
+    let set_dest_dir = move |book: &mut MDBook| {
+        if let Some(ref dest_dir) = dest_dir {
+            book.config.build.build_dir = dest_dir.clone();
+        }
+        Ok(())
+    };
+    set_dest_dir(&mut book)?;
 
     if args.is_present("open") {
         book.build()?;
         open(book.build_dir_for("html").join("index.html"));
     }
 
-    trigger_on_change(&book, |paths, book_dir| {
-        info!("Files changed: {:?}\nBuilding book...\n", paths);
-        let result = MDBook::load(&book_dir).and_then(|b| b.build());
+    rebuild_on_change(&book, debounce, set_dest_dir, |_| ());
 
-        if let Err(e) = result {
-            error!("Unable to build the book");
-            utils::log_backtrace(&e);
+    Ok(())
+}
+
+/// Rebuilds the book whenever one of its source files changes, blocking
+/// indefinitely.
+///
+/// The book is loaded from disk again for every rebuild. `configure` is
+/// called on it before it's built (e.g. to re-apply command line overrides),
+/// and `on_rebuild` after each successful build. Failed builds are logged and
+/// the watching continues.
+pub fn rebuild_on_change<C, F>(book: &MDBook, debounce: Duration, configure: C, on_rebuild: F)
+where
+    C: Fn(&mut MDBook) -> Result<()>,
+    F: Fn(&MDBook),
+{
+    trigger_on_change(book, debounce, |paths, book_dir| {
+        info!("Files changed: {:?}", paths);
+        info!("Building book...");
+
+        let result = MDBook::load(book_dir).and_then(|mut b| {
+            configure(&mut b)?;
+            b.build()?;
+            Ok(b)
+        });
+
+        match result {
+            Ok(b) => on_rebuild(&b),
+            Err(e) => {
+                error!("Unable to build the book");
+                utils::log_backtrace(&e);
+            }
         }
     });
+}
 
-    Ok(())
+/// Drop the paths inside the build directory, changes to the output must not
+/// trigger another build.
+fn remove_build_dir_files(book: &MDBook, paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let build_dir = book.build_dir();
+    // the watcher reports canonical paths
+    let build_dir = build_dir.canonicalize().unwrap_or(build_dir);
+
+    paths
+        .into_iter()
+        .filter(|path| !path.starts_with(&build_dir))
+        .collect()
 }
 
-fn remove_ignored_files(book_root: &PathBuf, paths: &[PathBuf]) -> Vec<PathBuf> {
+fn remove_ignored_files(book_root: &Path, paths: &[PathBuf]) -> Vec<PathBuf> {
+    // The temporary files of the files mdBook writes, and the ones left
+    // behind when it was killed while writing them
+    let paths: Vec<_> = paths
+        .iter()
+        .filter(|path| !utils::fs::is_temp_file(path))
+        .cloned()
+        .collect();
     if paths.is_empty() {
         return vec![];
     }
+    let paths = &paths[..];
 
     match find_gitignore(book_root) {
         Some(gitignore_path) => {
@@ -71,7 +149,7 @@ fn remove_ignored_files(book_root: &PathBuf, paths: &[PathBuf]) -> Vec<PathBuf>
     }
 }
 
-fn find_gitignore(book_root: &PathBuf) -> Option<PathBuf> {
+fn find_gitignore(book_root: &Path) -> Option<PathBuf> {
     book_root
         .ancestors()
         .map(|p| p.join(".gitignore"))
@@ -96,7 +174,7 @@ fn filter_ignored_files(exclusion_checker: gitignore::File, paths: &[PathBuf]) -
 }
 
 /// Calls the closure when a book source file is changed, blocking indefinitely.
-pub fn trigger_on_change<F>(book: &MDBook, closure: F)
+fn trigger_on_change<F>(book: &MDBook, debounce: Duration, closure: F)
 where
     F: Fn(Vec<PathBuf>, &Path),
 {
@@ -106,7 +184,7 @@ where
     // Create a channel to receive the events.
     let (tx, rx) = channel();
 
-    let mut watcher = match notify::watcher(tx, Duration::from_secs(1)) {
+    let mut watcher = match notify::watcher(tx, debounce) {
         Ok(w) => w,
         Err(e) => {
             error!("Error while trying to watch the files:\n\n\t{:?}", e);
@@ -122,6 +200,15 @@ where
 
     let _ = watcher.watch(book.theme_dir(), Recursive);
 
+    if let Some(html_config) = book.config.html_config() {
+        for dir in &html_config.extra_static_dirs {
+            let _ = watcher.watch(book.root.join(dir), Recursive);
+        }
+        if let Some(HeadExtra::File { ref file }) = html_config.head_extra {
+            let _ = watcher.watch(book.root.join(file), NonRecursive);
+        }
+    }
+
     // Add the book.toml file to the watcher if it exists
     let _ = watcher.watch(book.root.join("book.toml"), NonRecursive);
 
@@ -145,6 +232,7 @@ where
             })
             .collect::<Vec<_>>();
 
+        let paths = remove_build_dir_files(book, paths);
         let paths = remove_ignored_files(&book.root, &paths[..]);
 
         if !paths.is_empty() {
@@ -152,3 +240,50 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn changes_to_the_build_dir_are_ignored() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let root = temp.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src").join("SUMMARY.md"), "# Summary\n").unwrap();
+        fs::write(root.join("book.toml"), "[build]\nbuild-dir = \"src/out\"\n").unwrap();
+        fs::create_dir_all(root.join("src").join("out")).unwrap();
+        let book = MDBook::load(&root).unwrap();
+
+        let paths = vec![
+            root.join("src").join("chapter_1.md"),
+            root.join("src").join("out").join("index.html"),
+            root.join("book.toml"),
+        ];
+        let paths = remove_build_dir_files(&book, paths);
+
+        assert_eq!(
+            paths,
+            vec![
+                root.join("src").join("chapter_1.md"),
+                root.join("book.toml")
+            ]
+        );
+    }
+
+    #[test]
+    fn the_temp_files_of_a_build_are_ignored() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let paths = vec![
+            src.join(".chapter_1.md.1234-0.tmp"),
+            src.join("chapter_1.md"),
+        ];
+
+        assert_eq!(
+            remove_ignored_files(temp.path(), &paths),
+            vec![src.join("chapter_1.md")]
+        );
+    }
+}
@@ -49,21 +49,32 @@
 
 #![deny(missing_docs)]
 
+use regex::Regex;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::env;
 use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use toml::value::Table;
 use toml::{self, Value};
+use url::Url;
 
+use crate::diagnostics::{Diagnostic, DiagnosticKind, Diagnostics};
 use crate::errors::*;
 use crate::utils::{self, toml_ext::TomlExt};
 
 /// The overall configuration object for MDBook, essentially an in-memory
 /// representation of `book.toml`.
-#[derive(Debug, Clone, PartialEq)]
+///
+/// Serializing it writes the tables in the order of the `book.toml` it was
+/// read from, with the keys of `[book]`, `[build]` and `[rust]` mdBook doesn't
+/// know about, so reading, changing and writing back a `book.toml` only
+/// changes what was changed.
+#[derive(Debug, Clone)]
 pub struct Config {
     /// Metadata about the book.
     pub book: BookConfig,
@@ -71,7 +82,59 @@ pub struct Config {
     pub build: BuildConfig,
     /// Information about Rust language support.
     pub rust: RustConfig,
+    /// The languages of a book with translations, by their language code,
+    /// from the `[language.<code>]` tables. Each one's source is in a
+    /// directory with its code in the source directory.
+    pub language: BTreeMap<String, Language>,
     rest: Value,
+    /// The top-level table of `book.toml` as it was read.
+    source: Table,
+    /// The order of the keys of `book.toml`, which its tables don't keep.
+    order: KeyOrder,
+    /// The deprecated `snake_case` keys used so far, with the `kebab-case`
+    /// ones they were taken for. Clones share them.
+    deprecated_keys: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+/// The tables of `book.toml` which are fields of [`Config`].
+const TYPED_TABLES: &[&str] = &["book", "build", "rust", "language"];
+
+/// The tables mdBook knows at the top of `book.toml`.
+const TOP_LEVEL_TABLES: &[&str] = &[
+    "book",
+    "build",
+    "rust",
+    "language",
+    "output",
+    "preprocessor",
+];
+
+/// The keys of the `book.json` of the first versions of mdBook, and where
+/// they are in `book.toml`.
+const LEGACY_JSON_KEYS: &[(&str, &str)] = &[
+    ("title", "book.title"),
+    ("author", "book.authors"),
+    ("authors", "book.authors"),
+    ("description", "book.description"),
+    ("language", "book.language"),
+    ("src", "book.src"),
+    ("source", "book.src"),
+    ("dest", "build.build-dir"),
+    ("destination", "build.build-dir"),
+    ("theme_path", "output.html.theme"),
+    ("google_analytics", "output.html.google-analytics"),
+    ("additional_css", "output.html.additional-css"),
+    ("additional_js", "output.html.additional-js"),
+];
+
+impl PartialEq for Config {
+    fn eq(&self, other: &Config) -> bool {
+        self.book == other.book
+            && self.build == other.build
+            && self.rust == other.rust
+            && self.language == other.language
+            && self.rest == other.rest
+    }
 }
 
 impl FromStr for Config {
@@ -79,20 +142,118 @@ impl FromStr for Config {
 
     /// Load a `Config` from some string.
     fn from_str(src: &str) -> Result<Self> {
-        toml::from_str(src).with_context(|| "Invalid configuration file")
+        parse(src).with_context(|| "Invalid configuration file")
     }
 }
 
 impl Config {
-    /// Load the configuration file from disk.
+    /// Load the configuration file from disk. A syntax error says where it is
+    /// in the file, like `book.toml:3:7: expected an equals, found a newline`.
     pub fn from_disk<P: AsRef<Path>>(config_file: P) -> Result<Config> {
+        let config_file = config_file.as_ref();
         let mut buffer = String::new();
         File::open(config_file)
             .with_context(|| "Unable to open the configuration file")?
             .read_to_string(&mut buffer)
             .with_context(|| "Couldn't read the file")?;
 
-        Config::from_str(&buffer)
+        parse(&buffer)
+            .map_err(|e| {
+                let location = e.line_col().map(|(line, col)| (line + 1, col + 1));
+                located_error(config_file, location, &e.to_string())
+            })
+            .with_context(|| "Invalid configuration file")
+    }
+
+    /// Convert the `book.json` of the first versions of mdBook into the
+    /// equivalent `book.toml`. The keys which don't have an equivalent are
+    /// left out, see [`legacy_json_warnings()`](#method.legacy_json_warnings).
+    pub fn legacy_json_to_toml(json: &str, json_file: &Path) -> Result<String> {
+        let json: serde_json::Value = serde_json::from_str(json)
+            .map_err(|e| located_error(json_file, Some((e.line(), e.column())), &e.to_string()))
+            .with_context(|| "Invalid configuration file")?;
+        let json = match json {
+            serde_json::Value::Object(json) => json,
+            _ => bail!("{} should be a JSON object", json_file.display()),
+        };
+
+        let mut table = Value::Table(Table::new());
+        let mut order = KeyOrder::default();
+        for &(old, new) in LEGACY_JSON_KEYS {
+            let value = match json.get(old) {
+                Some(serde_json::Value::Null) | None => continue,
+                // There was a single author
+                Some(serde_json::Value::String(author)) if old == "author" => {
+                    Value::Array(vec![Value::String(author.clone())])
+                }
+                Some(value) => Value::try_from(value)
+                    .with_context(|| format!("Unable to convert `{}` of book.json", old))?,
+            };
+            table.insert(new, value);
+            order.insert(new);
+        }
+
+        toml::to_string(&Ordered(&table, &order)).with_context(|| "Unable to write the book.toml")
+    }
+
+    /// Warnings about the keys of a legacy `book.json` which have no
+    /// equivalent in `book.toml`, and which
+    /// [`legacy_json_to_toml()`](#method.legacy_json_to_toml) leaves out.
+    pub fn legacy_json_warnings(json: &str) -> Vec<String> {
+        let json = match serde_json::from_str(json) {
+            Ok(serde_json::Value::Object(json)) => json,
+            _ => return Vec::new(),
+        };
+        json.keys()
+            .filter(|key| !LEGACY_JSON_KEYS.iter().any(|&(old, _)| old == key.as_str()))
+            .map(|key| {
+                format!(
+                    "`{}` of book.json has no equivalent in book.toml, ignoring it",
+                    key
+                )
+            })
+            .collect()
+    }
+
+    /// Warnings about the deprecated `snake_case` keys used so far, like
+    /// `output.html.site_url` in `MDBOOK_OUTPUT__HTML__SITE_URL`.
+    pub(crate) fn deprecated_key_warnings(&self) -> Vec<String> {
+        self.deprecated_keys
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, kebab)| {
+                format!(
+                    "The config key `{}` is deprecated, use `{}` instead",
+                    key, kebab
+                )
+            })
+            .collect()
+    }
+
+    /// Warnings about the keys at the top of `book.toml` which mdBook doesn't
+    /// know, with the known table they're closest to when that's likely what
+    /// was meant.
+    pub(crate) fn unknown_top_level_keys(&self) -> Vec<String> {
+        let rest = match self.rest {
+            Value::Table(ref rest) => rest,
+            _ => return Vec::new(),
+        };
+
+        rest.iter()
+            .filter(|(key, _)| !TOP_LEVEL_TABLES.contains(&key.as_str()))
+            .map(|(key, value)| {
+                let mut message = if value.is_table() {
+                    format!("Unknown table `[{}]` in book.toml, ignoring it", key)
+                } else {
+                    format!("Unknown key `{}` in book.toml, ignoring it", key)
+                };
+                if let Some(known) = closest_key(key, TOP_LEVEL_TABLES) {
+                    message.push_str(&format!(" (did you mean `[{}]`?)", known));
+                }
+                message
+            })
+            .collect()
     }
 
     /// Updates the `Config` from the available environment variables.
@@ -114,58 +275,148 @@ impl Config {
     /// override the book's title without needing to touch your `book.toml`.
     ///
     /// > **Note:** To facilitate setting more complex config items, the value
-    /// > of an environment variable is first parsed as JSON, falling back to a
-    /// > string if the parse fails.
+    /// > of an environment variable is first parsed as a TOML value, then as
+    /// > JSON, falling back to a string if both parses fail.
     /// >
     /// > This means, if you so desired, you could override all book metadata
     /// > when building the book with something like
     /// >
     /// > ```text
-    /// > $ export MDBOOK_BOOK='{"title": "My Awesome Book", "authors": ["Michael-F-Bryan"]}'
+    /// > $ export MDBOOK_BOOK='{title = "My Awesome Book", authors = ["Michael-F-Bryan"]}'
     /// > $ mdbook build
     /// > ```
     ///
     /// The latter case may be useful in situations where `mdbook` is invoked
     /// from a script or CI, where it sometimes isn't possible to update the
     /// `book.toml` before building.
+    ///
+    /// Tables are merged with the ones of `book.toml`, so `MDBOOK_BOOK` above
+    /// keeps the book's other keys. A variable setting a table to something
+    /// which isn't one, or the other way around, is logged and skipped, see
+    /// [`try_update_from_env()`](#method.try_update_from_env) for an error.
     pub fn update_from_env(&mut self) {
         debug!("Updating the config from environment variables");
+        for (var, key, value) in env_overrides(env::vars()) {
+            if let Err(e) = self.apply_env_override(&var, &key, &value) {
+                warn!("{}, skipping it", e);
+            }
+        }
+    }
+
+    /// Like [`update_from_env()`](#method.update_from_env), but a variable
+    /// which can't be applied is an error naming it.
+    pub fn try_update_from_env(&mut self) -> Result<()> {
+        debug!("Updating the config from environment variables");
+        self.update_from_vars(env::vars())
+    }
 
-        let overrides =
-            env::vars().filter_map(|(key, value)| parse_env(&key).map(|index| (index, value)));
+    /// The `MDBOOK_` variables of the environment, with the keys they
+    /// override, like `("MDBOOK_BOOK__TITLE", "book.title")`.
+    pub fn env_overrides() -> Vec<(String, String)> {
+        let mut overrides: Vec<_> = env::vars()
+            .filter_map(|(var, _)| parse_env(&var).map(|key| (var, key)))
+            .collect();
+        overrides.sort();
+        overrides
+    }
 
-        for (key, value) in overrides {
-            trace!("{} => {}", key, value);
-            let parsed_value = serde_json::from_str(&value)
-                .unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
+    /// Apply the `MDBOOK_` variables of `vars`, stopping at the first one
+    /// which can't be.
+    fn update_from_vars<I: IntoIterator<Item = (String, String)>>(
+        &mut self,
+        vars: I,
+    ) -> Result<()> {
+        for (var, key, value) in env_overrides(vars) {
+            self.apply_env_override(&var, &key, &value)?;
+        }
+        Ok(())
+    }
 
-            if key == "book" || key == "build" {
-                if let serde_json::Value::Object(ref map) = parsed_value {
-                    // To `set` each `key`, we wrap them as `prefix.key`
-                    for (k, v) in map {
-                        let full_key = format!("{}.{}", key, k);
-                        self.set(&full_key, v).expect("unreachable");
-                    }
-                    return;
-                }
+    /// Set `key` to the `value` of the variable `var`. The config is left as
+    /// it was when it fails.
+    fn apply_env_override(&mut self, var: &str, key: &str, value: &str) -> Result<()> {
+        trace!("{} => {}", key, value);
+        let value = parse_env_value(value);
+
+        match key.split('.').next().unwrap_or_default() {
+            "book" => self.book = merge_typed(&self.book, key, value, var)?,
+            "build" => self.build = merge_typed(&self.build, key, value, var)?,
+            "rust" => self.rust = merge_typed(&self.rust, key, value, var)?,
+            "language" => self.language = merge_typed(&self.language, key, value, var)?,
+            _ => {
+                // A table is merged key by key, so into a copy
+                let mut rest = self.rest.clone();
+                merge_at(&mut rest, key, 0, value, var)?;
+                self.rest = rest;
             }
-
-            self.set(key, parsed_value).expect("unreachable");
         }
+        Ok(())
     }
 
     /// Fetch an arbitrary item from the `Config` as a `toml::Value`.
     ///
     /// You can use dotted indices to access nested items (e.g.
     /// `output.html.playpen` will fetch the "playpen" out of the html output
-    /// table).
+    /// table). The `[book]`, `[build]`, `[rust]` and `[language]` tables are
+    /// fields of the `Config`, use
+    /// [`get_deserialized_opt()`](#method.get_deserialized_opt) for them.
+    ///
+    /// The keys of mdBook are `kebab-case`, and their `snake_case` spellings
+    /// (`output.html.site_url`) still work, with a deprecation warning in the
+    /// next build.
     pub fn get(&self, key: &str) -> Option<&Value> {
-        self.rest.read(key)
+        self.rest.read(&self.canonical_key(key))
     }
 
     /// Fetch a value from the `Config` so you can mutate it.
     pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
-        self.rest.read_mut(key)
+        let key = self.canonical_key(key).into_owned();
+        self.rest.read_mut(&key)
+    }
+
+    /// The entries of the table at `key`, like the `[output.*]` tables of
+    /// `output`, in the order of `book.toml`. The ones it didn't have come
+    /// after them, in alphabetical order.
+    pub(crate) fn ordered_entries(&self, key: &str) -> Vec<(&String, &Value)> {
+        let empty = KeyOrder::default();
+        match self.get(key) {
+            Some(Value::Table(table)) => self.order.read(key).unwrap_or(&empty).sort(table),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The key mdBook uses for `key`. A `snake_case` key which doesn't exist
+    /// is the `kebab-case` one when that's one of mdBook's own keys, which are
+    /// all `kebab-case`. The other keys, like the ones of the preprocessors and
+    /// renderers, are left alone, they're theirs to choose.
+    fn canonical_key<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        if !key.contains('_') || self.rest.read(key).is_some() {
+            return Cow::Borrowed(key);
+        }
+
+        let kebab = key.replace('_', "-");
+        if is_known_key(&kebab) {
+            self.deprecated_keys
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), kebab.clone());
+            Cow::Owned(kebab)
+        } else {
+            Cow::Borrowed(key)
+        }
+    }
+
+    /// The `[book]`, `[build]`, `[rust]` or `[language]` table as a
+    /// `toml::Value`.
+    fn typed_table(&self, name: &str) -> Option<Value> {
+        let value = match name {
+            "book" => Value::try_from(&self.book),
+            "build" => Value::try_from(&self.build),
+            "rust" => Value::try_from(&self.rust),
+            "language" => Value::try_from(&self.language),
+            _ => return None,
+        };
+        Some(value.expect("unreachable"))
     }
 
     /// Convenience method for getting the html renderer's configuration.
@@ -189,6 +440,85 @@ impl Config {
         }
     }
 
+    /// Get the html renderer's configuration, failing if the `[output.html]`
+    /// table exists but can't be deserialized.
+    ///
+    /// Keys in the table which mdBook doesn't know about are logged as
+    /// warnings, so typos like `mathjax-suport` don't go unnoticed.
+    pub fn html_config_checked(&self) -> Result<Option<HtmlConfig>> {
+        self.html_config_checked_with(&Diagnostics::new())
+    }
+
+    /// Like [`html_config_checked()`](#method.html_config_checked), with the
+    /// warnings reported to `diagnostics`.
+    pub fn html_config_checked_with(
+        &self,
+        diagnostics: &Diagnostics,
+    ) -> Result<Option<HtmlConfig>> {
+        let table = match self.get("output.html") {
+            Some(table) => table.clone(),
+            None => return Ok(None),
+        };
+
+        // Say which button is invalid, rather than only that one is
+        if let Some(buttons) = table.get("nav-buttons").and_then(Value::as_array) {
+            for (i, button) in buttons.iter().enumerate() {
+                ensure!(
+                    button.get("url").and_then(Value::as_str).is_some(),
+                    "`output.html.nav-buttons[{}]` needs a `url`",
+                    i
+                );
+            }
+        }
+
+        let mut unknown_keys = Vec::new();
+        let html_config: HtmlConfig = serde_ignored::deserialize(table, |path| {
+            unknown_keys.push(path.to_string());
+        })
+        .with_context(|| "Invalid configuration in [output.html]")?;
+
+        let warn = |message: String| {
+            diagnostics.warn(Diagnostic::new(DiagnosticKind::Config, message));
+        };
+        for key in unknown_keys {
+            warn(format!(
+                "Unknown key `output.html.{}` in book.toml, ignoring it",
+                key
+            ));
+        }
+
+        if let Some(ref site_url) = html_config.site_url {
+            match Url::parse(site_url) {
+                Ok(ref url) if url.scheme() == "http" || url.scheme() == "https" => {}
+                _ => warn(format!(
+                    "`output.html.site-url` should be an absolute http(s) URL, got \"{}\"",
+                    site_url
+                )),
+            }
+        }
+        if let Some(ref id) = html_config.google_analytics {
+            if google_analytics_id_kind(id).is_none() {
+                warn(format!(
+                    "`output.html.google-analytics` should be a measurement ID like \"G-XXXXXXXXXX\" \
+                     or a tracking ID like \"UA-XXXXX-Y\", got \"{}\"",
+                    id
+                ));
+            }
+        }
+
+        match html_config.math_renderer {
+            Some(MathRenderer::Katex) | Some(MathRenderer::None) if html_config.mathjax_support => {
+                bail!(
+                    "`output.html.mathjax-support` is set, but `output.html.math-renderer` \
+                     isn't \"mathjax\""
+                );
+            }
+            _ => {}
+        }
+
+        Ok(Some(html_config))
+    }
+
     /// Deprecated, use get_deserialized_opt instead.
     #[deprecated = "use get_deserialized_opt instead"]
     pub fn get_deserialized<'de, T: Deserialize<'de>, S: AsRef<str>>(&self, name: S) -> Result<T> {
@@ -201,42 +531,78 @@ impl Config {
 
     /// Convenience function to fetch a value from the config and deserialize it
     /// into some arbitrary type.
+    ///
+    /// Like [`get()`](#method.get), `name` can be a dotted path into nested
+    /// tables, and unlike it, it can be in the `[book]`, `[build]`, `[rust]`
+    /// and `[language]` tables too (e.g. `book.title`).
     pub fn get_deserialized_opt<'de, T: Deserialize<'de>, S: AsRef<str>>(
         &self,
         name: S,
     ) -> Result<Option<T>> {
-        let name = name.as_ref();
-        self.get(name)
+        let name = self.canonical_key(name.as_ref());
+        let mut parts = name.splitn(2, '.');
+        let head = parts.next().unwrap_or_default();
+        let value = match self.typed_table(head) {
+            Some(table) => match parts.next() {
+                Some(tail) => table.read(tail).cloned(),
+                None => Some(table),
+            },
+            None => self.rest.read(&name).cloned(),
+        };
+
+        value
             .map(|value| {
                 value
-                    .clone()
                     .try_into()
-                    .with_context(|| "Couldn't deserialize the value")
+                    .with_context(|| format!("Couldn't deserialize the value of `{}`", name))
             })
             .transpose()
     }
 
-    /// Set a config key, clobbering any existing values along the way.
+    /// Set a config key, clobbering any existing values along the way and
+    /// creating the tables on the way which don't exist.
     ///
     /// The only way this can fail is if we can't serialize `value` into a
     /// `toml::Value`.
     pub fn set<S: Serialize, I: AsRef<str>>(&mut self, index: I, value: S) -> Result<()> {
-        let index = index.as_ref();
+        let index = self.canonical_key(index.as_ref()).into_owned();
 
         let value = Value::try_from(value)
             .with_context(|| "Unable to represent the item as a JSON Value")?;
 
-        if index.starts_with("book.") {
-            self.book.update_value(&index[5..], value);
-        } else if index.starts_with("build.") {
-            self.build.update_value(&index[6..], value);
-        } else {
-            self.rest.insert(index, value);
+        let mut parts = index.splitn(2, '.');
+        match (parts.next().unwrap_or_default(), parts.next()) {
+            ("book", Some(key)) => self.book.update_value(key, value),
+            ("build", Some(key)) => self.build.update_value(key, value),
+            ("rust", Some(key)) => self.rust.update_value(key, value),
+            ("language", Some(key)) => self.language.update_value(key, value),
+            _ => self.rest.insert(&index, value),
         }
 
         Ok(())
     }
 
+    /// The code of the language the book is in by default, when it has
+    /// translations: the one marked as `default`, else `book.language` if it's
+    /// one of them, else the first one.
+    pub fn default_language(&self) -> Option<&str> {
+        let marked = self
+            .language
+            .iter()
+            .find(|&(_, language)| language.default)
+            .map(|(code, _)| &code[..]);
+        let book_language = self
+            .book
+            .language
+            .as_ref()
+            .filter(|code| self.language.contains_key(*code))
+            .map(|code| &code[..]);
+
+        marked
+            .or(book_language)
+            .or_else(|| self.language.keys().next().map(|code| &code[..]))
+    }
+
     /// Get the table associated with a particular renderer.
     pub fn get_renderer<I: AsRef<str>>(&self, index: I) -> Option<&Table> {
         let key = format!("output.{}", index.as_ref());
@@ -289,7 +655,11 @@ impl Default for Config {
             book: BookConfig::default(),
             build: BuildConfig::default(),
             rust: RustConfig::default(),
+            language: BTreeMap::new(),
             rest: Value::Table(Table::default()),
+            source: Table::default(),
+            order: KeyOrder::default(),
+            deprecated_keys: Arc::default(),
         }
     }
 }
@@ -309,7 +679,12 @@ impl<'de> Deserialize<'de> for Config {
             return Ok(Config::from_legacy(raw));
         }
 
-        let mut table = match raw {
+        if let Err(message) = check_editions(&raw) {
+            use serde::de::Error;
+            return Err(D::Error::custom(message));
+        }
+
+        let table = match raw {
             Value::Table(t) => t,
             _ => {
                 use serde::de::Error;
@@ -320,25 +695,51 @@ impl<'de> Deserialize<'de> for Config {
         };
 
         let book: BookConfig = table
-            .remove("book")
-            .and_then(|value| value.try_into().ok())
+            .get("book")
+            .and_then(|value| value.clone().try_into().ok())
             .unwrap_or_default();
+        if let Some(ref language) = book.language {
+            if !is_language_tag(language) {
+                warn!(
+                    "`book.language` should be a language tag like \"en\" or \"pt-BR\", got \"{}\"",
+                    language
+                );
+            }
+        }
 
         let build: BuildConfig = table
-            .remove("build")
-            .and_then(|value| value.try_into().ok())
+            .get("build")
+            .and_then(|value| value.clone().try_into().ok())
             .unwrap_or_default();
 
         let rust: RustConfig = table
-            .remove("rust")
-            .and_then(|value| value.try_into().ok())
+            .get("rust")
+            .and_then(|value| value.clone().try_into().ok())
             .unwrap_or_default();
 
+        let language: BTreeMap<String, Language> = match table.get("language") {
+            Some(value) => value.clone().try_into().map_err(|e| {
+                use serde::de::Error;
+                D::Error::custom(format!("Invalid configuration in [language]: {}", e))
+            })?,
+            None => BTreeMap::new(),
+        };
+
+        let rest = table
+            .iter()
+            .filter(|(key, _)| !TYPED_TABLES.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
         Ok(Config {
             book,
             build,
             rust,
-            rest: Value::Table(table),
+            language,
+            rest: Value::Table(rest),
+            source: table,
+            order: KeyOrder::default(),
+            deprecated_keys: Arc::default(),
         })
     }
 }
@@ -346,22 +747,262 @@ impl<'de> Deserialize<'de> for Config {
 impl Serialize for Config {
     fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
         use serde::ser::Error;
-        // TODO: This should probably be removed and use a derive instead.
 
-        let mut table = self.rest.clone();
+        let typed = |name: &str| -> std::result::Result<Option<Value>, S::Error> {
+            let source = self.source.get(name);
+            let value = match name {
+                "book" => typed_source_table(&self.book, source),
+                "build" => typed_source_table(&self.build, source),
+                "rust" => typed_source_table(&self.rust, source),
+                _ if self.language.is_empty() && source.is_none() => return Ok(None),
+                _ => typed_source_table(&self.language, source),
+            };
+            value
+                .map(|table| Some(Value::Table(table)))
+                .map_err(|_| S::Error::custom(format!("Unable to serialize the [{}] table", name)))
+        };
+        let rest = match self.rest {
+            Value::Table(ref rest) => rest.clone(),
+            _ => Table::new(),
+        };
+
+        let mut table = Table::new();
+        for (key, value) in &self.source {
+            if TYPED_TABLES.contains(&key.as_str()) {
+                if let Some(value) = typed(key)? {
+                    table.insert(key.clone(), value);
+                }
+            } else if let Some(value) = rest.get(key) {
+                table.insert(key.clone(), value.clone());
+            } else {
+                debug!("Dropping the removed `{}` = {}", key, value);
+            }
+        }
+        for &name in &TYPED_TABLES[..3] {
+            if !table.contains_key(name) {
+                if let Some(value) = typed(name)? {
+                    table.insert(name.to_string(), value);
+                }
+            }
+        }
+        for (key, value) in rest {
+            if !table.contains_key(&key) {
+                table.insert(key, value);
+            }
+        }
+        if !table.contains_key("language") {
+            if let Some(value) = typed("language")? {
+                table.insert("language".to_string(), value);
+            }
+        }
+        Ordered(&Value::Table(table), &self.order).serialize(s)
+    }
+}
+
+/// Whether the dotted `key` is a field of the `[book]`, `[build]`, `[rust]`,
+/// `[language]` or `[output.html]` tables, which mdBook reads itself.
+fn is_known_key(key: &str) -> bool {
+    fn is_field_of<T: for<'de> Deserialize<'de>>(key: &str) -> bool {
+        // Any value will do, a field of the wrong type still isn't unknown
+        let mut table = Value::Table(Table::new());
+        table.insert(key, Value::Boolean(false));
+        let mut known = true;
+        let _ = serde_ignored::deserialize::<_, _, T>(table, |_| known = false);
+        known
+    }
+
+    let mut parts = key.splitn(2, '.');
+    match (parts.next().unwrap_or_default(), parts.next()) {
+        ("book", Some(key)) => is_field_of::<BookConfig>(key),
+        ("build", Some(key)) => is_field_of::<BuildConfig>(key),
+        ("rust", Some(key)) => is_field_of::<RustConfig>(key),
+        ("language", Some(key)) => is_field_of::<BTreeMap<String, Language>>(key),
+        ("output", Some(key)) if key.starts_with("html.") => is_field_of::<HtmlConfig>(&key[5..]),
+        _ => false,
+    }
+}
+
+/// Parse a `book.toml`, remembering the order of its keys.
+fn parse(src: &str) -> std::result::Result<Config, toml::de::Error> {
+    let mut config: Config = toml::from_str(src)?;
+    config.order = toml::from_str(src)?;
+    Ok(config)
+}
+
+/// The keys of a table in the order they're written in, with the order of the
+/// keys of the tables in it.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct KeyOrder(Vec<(String, KeyOrder)>);
+
+impl KeyOrder {
+    fn get(&self, key: &str) -> Option<&KeyOrder> {
+        self.0
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, order)| order)
+    }
+
+    /// The order of the table at the dotted `key`.
+    fn read(&self, key: &str) -> Option<&KeyOrder> {
+        key.split('.').try_fold(self, |order, name| order.get(name))
+    }
+
+    /// Add the dotted `key` after the keys already in the order.
+    fn insert(&mut self, key: &str) {
+        let mut parts = key.splitn(2, '.');
+        let name = parts.next().unwrap_or_default();
+        let index = match self.0.iter().position(|(known, _)| known == name) {
+            Some(index) => index,
+            None => {
+                self.0.push((name.to_string(), KeyOrder::default()));
+                self.0.len() - 1
+            }
+        };
+        if let Some(rest) = parts.next() {
+            (self.0[index].1).insert(rest);
+        }
+    }
+
+    /// The keys of `table`, the ones in this order first and in it, then the
+    /// others in alphabetical order.
+    fn sort<'a>(&self, table: &'a Table) -> Vec<(&'a String, &'a Value)> {
+        let mut entries: Vec<_> = self
+            .0
+            .iter()
+            .filter_map(|(name, _)| table.iter().find(|&(key, _)| key == name))
+            .collect();
+        for (key, value) in table {
+            if self.get(key).is_none() {
+                entries.push((key, value));
+            }
+        }
+        entries
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyOrder {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> std::result::Result<Self, D::Error> {
+        use serde::de::{IgnoredAny, MapAccess, SeqAccess, Visitor};
+
+        struct KeyOrderVisitor;
+
+        impl<'de> Visitor<'de> for KeyOrderVisitor {
+            type Value = KeyOrder;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("a TOML value")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> std::result::Result<KeyOrder, A::Error> {
+                let mut keys = Vec::new();
+                while let Some(key) = map.next_key::<String>()? {
+                    keys.push((key, map.next_value()?));
+                }
+                Ok(KeyOrder(keys))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> std::result::Result<KeyOrder, A::Error> {
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                Ok(KeyOrder::default())
+            }
+
+            fn visit_bool<E>(self, _: bool) -> std::result::Result<KeyOrder, E> {
+                Ok(KeyOrder::default())
+            }
+
+            fn visit_i64<E>(self, _: i64) -> std::result::Result<KeyOrder, E> {
+                Ok(KeyOrder::default())
+            }
+
+            fn visit_u64<E>(self, _: u64) -> std::result::Result<KeyOrder, E> {
+                Ok(KeyOrder::default())
+            }
+
+            fn visit_f64<E>(self, _: f64) -> std::result::Result<KeyOrder, E> {
+                Ok(KeyOrder::default())
+            }
 
-        let book_config = match Value::try_from(self.book.clone()) {
-            Ok(cfg) => cfg,
-            Err(_) => {
-                return Err(S::Error::custom("Unable to serialize the BookConfig"));
+            fn visit_str<E>(self, _: &str) -> std::result::Result<KeyOrder, E> {
+                Ok(KeyOrder::default())
             }
+        }
+
+        de.deserialize_any(KeyOrderVisitor)
+    }
+}
+
+/// A value serialized with the keys of its tables in an order, the values
+/// before the tables as TOML needs.
+struct Ordered<'a>(&'a Value, &'a KeyOrder);
+
+impl Serialize for Ordered<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let table = match *self.0 {
+            Value::Table(ref table) => table,
+            ref value => return value.serialize(s),
+        };
+        let is_table = |value: &Value| match *value {
+            Value::Table(_) => true,
+            Value::Array(ref items) => items.iter().any(Value::is_table),
+            _ => false,
         };
-        let rust_config = Value::try_from(&self.rust).expect("should always be serializable");
+        let entries = self.1.sort(table);
+        let empty = KeyOrder::default();
+
+        let mut map = s.serialize_map(Some(table.len()))?;
+        for &(key, value) in entries.iter().filter(|(_, value)| !is_table(value)) {
+            map.serialize_entry(key, value)?;
+        }
+        for &(key, value) in entries.iter().filter(|(_, value)| is_table(value)) {
+            map.serialize_entry(key, &Ordered(value, self.1.get(key).unwrap_or(&empty)))?;
+        }
+        map.end()
+    }
+}
 
-        table.insert("book", book_config);
-        table.insert("rust", rust_config);
-        table.serialize(s)
+/// `value` as a table, with the keys of `source` (the table it was read from)
+/// it doesn't know about.
+fn typed_source_table<T>(
+    value: &T,
+    source: Option<&Value>,
+) -> std::result::Result<Table, toml::ser::Error>
+where
+    T: Serialize + for<'de> Deserialize<'de>,
+{
+    let current = match Value::try_from(value)? {
+        Value::Table(table) => table,
+        _ => Table::new(),
+    };
+    let source = match source {
+        Some(Value::Table(source)) => source,
+        _ => return Ok(current),
+    };
+
+    let mut unknown = Vec::new();
+    let _ = serde_ignored::deserialize::<_, _, T>(Value::Table(source.clone()), |path| {
+        unknown.push(path.to_string())
+    });
+    let is_unknown = |key: &str| {
+        unknown
+            .iter()
+            .any(|path| path == key || path.starts_with(&format!("{}.", key)))
+    };
+
+    let mut table = current;
+    for (key, raw) in source {
+        if !table.contains_key(key) && is_unknown(key) {
+            table.insert(key.clone(), raw.clone());
+        }
     }
+    Ok(table)
 }
 
 fn parse_env(key: &str) -> Option<String> {
@@ -376,6 +1017,165 @@ fn parse_env(key: &str) -> Option<String> {
     }
 }
 
+/// The value of an environment variable: a TOML value like `true` or
+/// `["a", "b"]`, else JSON, else the string itself.
+/// The `MDBOOK_` variables of `vars` with their keys, like
+/// `("MDBOOK_BOOK__TITLE", "book.title", value)`, the shorter keys first so
+/// `MDBOOK_OUTPUT__HTML` doesn't undo `MDBOOK_OUTPUT__HTML__SITE_URL`.
+fn env_overrides<I: IntoIterator<Item = (String, String)>>(
+    vars: I,
+) -> Vec<(String, String, String)> {
+    let mut overrides: Vec<_> = vars
+        .into_iter()
+        .filter_map(|(var, value)| parse_env(&var).map(|key| (var, key, value)))
+        .collect();
+    overrides.sort();
+    overrides
+}
+
+fn parse_env_value(value: &str) -> Value {
+    let toml = toml::from_str::<Table>(&format!("value = {}", value))
+        .ok()
+        .filter(|table| table.len() == 1)
+        .and_then(|mut table| table.remove("value"));
+    match toml {
+        // Dates are left as they're written, like in a title
+        Some(Value::Datetime(_)) | None => {}
+        Some(value) => return value,
+    }
+
+    serde_json::from_str::<serde_json::Value>(value)
+        .ok()
+        .and_then(|json| Value::try_from(json).ok())
+        .unwrap_or_else(|| Value::String(value.to_string()))
+}
+
+/// `current` with `value` merged in at `key`, whose first part names the
+/// table `current` is.
+fn merge_typed<T>(current: &T, key: &str, value: Value, var: &str) -> Result<T>
+where
+    T: Serialize + for<'de> Deserialize<'de>,
+{
+    let mut raw = Value::try_from(current).expect("unreachable");
+    merge_at(&mut raw, key, 1, value, var)?;
+    raw.try_into()
+        .with_context(|| format!("The environment variable {} has an invalid value", var))
+}
+
+/// Merge `value` into `root` at `key`, skipping its first `skip` parts. The
+/// tables on the way are created when they don't exist.
+fn merge_at(root: &mut Value, key: &str, skip: usize, value: Value, var: &str) -> Result<()> {
+    let segments: Vec<&str> = key.split('.').collect();
+    let mut target = root;
+    for i in skip..segments.len() {
+        let table = match target {
+            Value::Table(table) => table,
+            other => bail!(
+                "The environment variable {} sets `{}`, but `{}` is a {} in book.toml, not a table",
+                var,
+                key,
+                segments[..i].join("."),
+                other.type_str()
+            ),
+        };
+        if !table.contains_key(segments[i]) {
+            let value = segments[i + 1..]
+                .iter()
+                .rev()
+                .fold(value, |value, segment| {
+                    let mut table = Table::new();
+                    table.insert(segment.to_string(), value);
+                    Value::Table(table)
+                });
+            table.insert(segments[i].to_string(), value);
+            return Ok(());
+        }
+        target = table.get_mut(segments[i]).unwrap();
+    }
+    merge_value(target, value, var, key)
+}
+
+fn merge_value(existing: &mut Value, value: Value, var: &str, key: &str) -> Result<()> {
+    match (existing, value) {
+        (Value::Table(existing), Value::Table(new)) => {
+            for (k, v) in new {
+                let nested_key = format!("{}.{}", key, k);
+                match existing.get_mut(&k) {
+                    Some(existing) => merge_value(existing, v, var, &nested_key)?,
+                    None => {
+                        existing.insert(k, v);
+                    }
+                }
+            }
+        }
+        (existing, value) => {
+            ensure!(
+                !existing.is_table() && !value.is_table(),
+                "The environment variable {} sets `{}` to a {}, but it's a {} in book.toml",
+                var,
+                key,
+                value.type_str(),
+                existing.type_str()
+            );
+            *existing = value;
+        }
+    }
+    Ok(())
+}
+
+/// An error `message` of `file`, starting with the line and column it's at.
+/// The location at the end of the message is removed.
+fn located_error(file: &Path, location: Option<(usize, usize)>, message: &str) -> Error {
+    let message = match message.rfind(" at line ") {
+        Some(ix) => &message[..ix],
+        None => message,
+    };
+    match location {
+        Some((line, col)) => {
+            Error::msg(format!("{}:{}:{}: {}", file.display(), line, col, message))
+        }
+        None => Error::msg(format!("{}: {}", file.display(), message)),
+    }
+}
+
+/// The one of `known` which is a few edits away from `key`: at most one for
+/// every three characters of it. Keys shorter than four characters are too
+/// short to tell a typo from another word.
+fn closest_key<'a>(key: &str, known: &[&'a str]) -> Option<&'a str> {
+    let len = key.chars().count();
+    if len < 4 {
+        return None;
+    }
+    let max_distance = len / 3;
+    known
+        .iter()
+        .map(|&candidate| (edit_distance(key, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// The edit distance of `a` and `b`: the insertions, deletions, substitutions
+/// and swaps of two neighbouring characters which turn one into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows: Vec<Vec<usize>> = vec![(0..=b.len()).collect()];
+    for i in 1..=a.len() {
+        let mut row = vec![i];
+        for j in 1..=b.len() {
+            let substitution = rows[i - 1][j - 1] + if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = substitution.min(rows[i - 1][j] + 1).min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            row.push(distance);
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
 fn is_legacy_format(table: &Value) -> bool {
     let legacy_items = [
         "title",
@@ -411,6 +1211,8 @@ pub struct BookConfig {
     pub multilingual: bool,
     /// The main language of the book.
     pub language: Option<String>,
+    /// The direction the text of the book is written in. Default: `auto`.
+    pub text_direction: TextDirection,
 }
 
 impl Default for BookConfig {
@@ -422,10 +1224,78 @@ impl Default for BookConfig {
             src: PathBuf::from("src"),
             multilingual: false,
             language: Some(String::from("en")),
+            text_direction: TextDirection::Auto,
+        }
+    }
+}
+
+impl BookConfig {
+    /// The direction of the text, which is right-to-left for the languages
+    /// written that way when it's `auto`.
+    pub fn realized_text_direction(&self) -> TextDirection {
+        match self.text_direction {
+            TextDirection::Auto => {
+                let language = self.language.as_ref().map_or("", |language| &language[..]);
+                TextDirection::from_language(language)
+            }
+            direction => direction,
         }
     }
 }
 
+/// The direction of the text of a book.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextDirection {
+    /// From the book's language.
+    Auto,
+    /// Left to right.
+    Ltr,
+    /// Right to left.
+    Rtl,
+}
+
+impl TextDirection {
+    /// The direction `language` (a tag like `ar` or `he-IL`) is written in.
+    fn from_language(language: &str) -> TextDirection {
+        let primary = language
+            .split('-')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        match &primary[..] {
+            "ar" | "ckb" | "dv" | "fa" | "he" | "ps" | "sd" | "ug" | "ur" | "yi" => {
+                TextDirection::Rtl
+            }
+            _ => TextDirection::Ltr,
+        }
+    }
+
+    /// The value of the HTML `dir` attribute, `ltr`, `rtl` or `auto`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TextDirection::Auto => "auto",
+            TextDirection::Ltr => "ltr",
+            TextDirection::Rtl => "rtl",
+        }
+    }
+}
+
+/// One of the languages of a book with translations, from its
+/// `[language.<code>]` table.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Language {
+    /// The name of the language, in the language itself, like `Français`.
+    pub name: String,
+    /// The book's title in this language, instead of `book.title`.
+    pub title: Option<String>,
+    /// The book's authors in this language, instead of `book.authors`.
+    pub authors: Option<Vec<String>>,
+    /// Whether this is the language the book opens in.
+    pub default: bool,
+}
+
 /// Configuration for the build procedure.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default, rename_all = "kebab-case")]
@@ -438,6 +1308,21 @@ pub struct BuildConfig {
     /// Should the default preprocessors always be used when they are
     /// compatible with the renderer?
     pub use_default_preprocessors: bool,
+    /// Should each part of `SUMMARY.md` start numbering its chapters from 1
+    /// again, instead of continuing the numbers of the previous part?
+    pub restart_numbering_per_part: bool,
+    /// Should broken links between the chapters fail the build, instead of
+    /// only being reported as warnings?
+    pub strict: bool,
+    /// Patterns (relative to the source directory) of markdown files which
+    /// don't have to be listed in `SUMMARY.md`.
+    pub ignore_unlisted: Vec<String>,
+    /// Should the warnings of the build, like broken links or unknown keys in
+    /// `book.toml`, fail it?
+    pub deny_warnings: bool,
+    /// Should the renderers report how long each chapter and step of the
+    /// build took?
+    pub timings: bool,
 }
 
 impl Default for BuildConfig {
@@ -446,6 +1331,11 @@ impl Default for BuildConfig {
             build_dir: PathBuf::from("book"),
             create_missing: true,
             use_default_preprocessors: true,
+            restart_numbering_per_part: false,
+            strict: false,
+            ignore_unlisted: Vec::new(),
+            deny_warnings: false,
+            timings: false,
         }
     }
 }
@@ -461,6 +1351,9 @@ pub struct RustConfig {
 #[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 /// Rust edition to use for the code.
 pub enum RustEdition {
+    /// The 2021 edition of Rust
+    #[serde(rename = "2021")]
+    E2021,
     /// The 2018 edition of Rust
     #[serde(rename = "2018")]
     E2018,
@@ -469,6 +1362,51 @@ pub enum RustEdition {
     E2015,
 }
 
+impl RustEdition {
+    /// The edition as it's written in `book.toml` and passed to rustdoc and
+    /// the playground, like `2018`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RustEdition::E2021 => "2021",
+            RustEdition::E2018 => "2018",
+            RustEdition::E2015 => "2015",
+        }
+    }
+}
+
+/// The keys of `book.toml` which are a Rust edition.
+const EDITION_KEYS: &[&str] = &["rust.edition", "output.html.playpen.edition"];
+
+/// Make sure the editions in `book.toml` are ones mdBook knows, because an
+/// invalid `[rust]` table would otherwise be silently ignored.
+fn check_editions(raw: &Value) -> std::result::Result<(), String> {
+    let editions = [RustEdition::E2015, RustEdition::E2018, RustEdition::E2021];
+
+    for key in EDITION_KEYS {
+        let value = match raw.read(key) {
+            Some(value) => value,
+            None => continue,
+        };
+        let known = value.as_str().map_or(false, |edition| {
+            editions.iter().any(|e| e.as_str() == edition)
+        });
+        if !known {
+            return Err(format!(
+                "Invalid `{}` {}, expected one of: {}",
+                key,
+                value,
+                editions
+                    .iter()
+                    .map(|e| format!("\"{}\"", e.as_str()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+    }
+
+    Ok(())
+}
+
 /// Configuration for the HTML renderer.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default, rename_all = "kebab-case")]
@@ -480,10 +1418,20 @@ pub struct HtmlConfig {
     /// The theme to use if the browser requests the dark version of the site.
     /// Defaults to the same as 'default_theme'
     pub preferred_dark_theme: Option<String>,
+    /// Fail to render the pages when the theme's templates use a variable
+    /// which doesn't exist, like `{{ book_titel }}`, instead of rendering it
+    /// as nothing.
+    pub strict_templates: bool,
     /// Use "smart quotes" instead of the usual `"` character.
     pub curly_quotes: bool,
     /// Should mathjax be enabled?
     pub mathjax_support: bool,
+    /// The URL of the MathJax script, like the one of a self-hosted copy.
+    /// Defaults to MathJax 2.7.1 on cdnjs.
+    pub mathjax_source: Option<String>,
+    /// What renders the math of the chapters. Defaults to MathJax with
+    /// `mathjax-support`, and to none without it.
+    pub math_renderer: Option<MathRenderer>,
     /// Whether to fonts.css and respective font files to the output directory.
     pub copy_fonts: bool,
     /// An optional google analytics code.
@@ -493,10 +1441,32 @@ pub struct HtmlConfig {
     /// Additional JS scripts to include at the bottom of the rendered page's
     /// `<body>`.
     pub additional_js: Vec<PathBuf>,
+    /// Directories (relative to the book root) whose files are copied to the
+    /// same place in the rendered book, on top of the source directory's.
+    pub extra_static_dirs: Vec<PathBuf>,
+    /// Print settings.
+    pub print: Print,
+    /// Settings of the `book.html` with the whole book in one file.
+    pub single_file: SingleFile,
+    /// The archive of the rendered book written in its directory, like
+    /// `book.zip`. If `None`, there's no archive.
+    pub archive: Option<ArchiveFormat>,
+    /// Settings of the check of the links of every HTML file of the rendered
+    /// book, which can also be just `true` or `false` to turn it on or off.
+    #[serde(deserialize_with = "LinkCheck::from_bool_or_table")]
+    pub link_check: LinkCheck,
+    /// The transformations of the HTML of the chapters' pages, in the order
+    /// they run, before the ones registered with
+    /// `MDBook::with_post_processor()`.
+    pub post_process: Vec<PostProcess>,
     /// Fold settings.
     pub fold: Fold,
     /// Playpen settings.
     pub playpen: Playpen,
+    /// How the code blocks are highlighted.
+    pub highlight: Highlight,
+    /// Settings for highlighting the code blocks when the book is built.
+    pub syntect: Syntect,
     /// Don't render section labels.
     pub no_section_label: bool,
     /// Search settings. If `None`, the default will be used.
@@ -506,6 +1476,85 @@ pub struct HtmlConfig {
     /// FontAwesome icon class to use for the Git repository link.
     /// Defaults to `fa-github` if `None`.
     pub git_repository_icon: Option<String>,
+    /// More buttons for the menu bar, after the print and git repository
+    /// ones.
+    pub nav_buttons: Vec<NavButton>,
+    /// Template for the "edit this page" link, where `{path}` is replaced with
+    /// the chapter's source file (including the source directory).
+    /// If `None`, the edit button will not be shown.
+    pub edit_url_template: Option<String>,
+    /// Only render the chapters which changed since the previous build,
+    /// instead of starting from an empty destination directory.
+    pub incremental: bool,
+    /// Free-form values made available to the theme's templates as
+    /// `{{context.<key>}}`.
+    pub context: Table,
+    /// Start the breadcrumbs of every chapter with the book's title.
+    pub breadcrumbs_include_title: bool,
+    /// Render every chapter to an `index.html` in a directory of its own, so it
+    /// can be linked to without the `.html`.
+    pub pretty_urls: bool,
+    /// Give the code blocks a copy button. Default: `true`.
+    pub copyable_code: bool,
+    /// Number the lines of the code blocks. Default: `false`.
+    pub line_numbers: bool,
+    /// The languages of the code blocks which are left for a JavaScript
+    /// library (like mermaid) to render, as a `<pre class="language">` with
+    /// the raw code. They aren't highlighted or searched.
+    pub passthrough_code_languages: Vec<String>,
+    /// Render GitHub-style tables. Default: `true`.
+    pub tables: bool,
+    /// Render `~~text~~` as struck through text. Default: `true`.
+    pub strikethrough: bool,
+    /// Render list items starting with `[ ]` or `[x]` as checkboxes.
+    /// Default: `true`.
+    pub task_lists: bool,
+    /// Leave out the H1 a chapter starts with, using its text as the
+    /// chapter's name. Default: `false`.
+    pub strip_first_h1: bool,
+    /// How many levels the headings of the chapters are shifted down, up to
+    /// `<h6>`. Default: `0`.
+    pub heading_offset: u32,
+    /// How GitHub-style alerts (`> [!NOTE]`) are rendered.
+    pub admonitions: Admonitions,
+    /// Which headings of a chapter are listed in its `page_headings`, for an
+    /// in-page table of contents.
+    pub pagetoc: PageToc,
+    /// How the words of the chapters are counted for their reading time.
+    pub reading_time: ReadingTime,
+    /// The URL the book is published at, like `https://example.com/book/`. When
+    /// set, every page gets a canonical URL and Open Graph `<meta>` tags.
+    pub site_url: Option<String>,
+    /// Write a `sitemap.xml` listing every chapter, when `site_url` is set.
+    pub sitemap: bool,
+    /// The markdown file (relative to the source directory) rendered as the
+    /// 404 page. A built-in "page not found" page is used if it isn't set.
+    pub input_404: Option<PathBuf>,
+    /// The name of the 404 page in the output directory, `404.html` by default.
+    pub output_404: Option<String>,
+    /// Write a `.nojekyll` file, so GitHub Pages doesn't hide the files
+    /// starting with an underscore.
+    pub nojekyll: bool,
+    /// The custom domain written to a `CNAME` file for GitHub Pages, like
+    /// `docs.example.com`.
+    pub cname: Option<String>,
+    /// HTML added to the end of every page's `<head>`, like analytics scripts
+    /// or verification `<meta>` tags.
+    pub head_extra: Option<HeadExtra>,
+    /// Pages which moved, mapping their old path in the rendered book (like
+    /// `/old/page.html`) to where they are now, either relative to the old
+    /// page or absolute. A page redirecting to the new location is written at
+    /// each old path.
+    pub redirect: BTreeMap<String, String>,
+    /// Whether the `index.html` of the book is a copy of the first chapter or
+    /// redirects to it, when the first chapter isn't `index.md`.
+    pub index_page: IndexPage,
+    /// Ask git for the commit the book is built from, for the `git_commit`
+    /// and `git_dirty` of the templates.
+    pub git_metadata: bool,
+    /// Give every chapter the date its source file was last changed, from
+    /// git or the file's modification time.
+    pub last_modified: bool,
     /// This is used as a bit of a workaround for the `mdbook serve` command.
     /// Basically, because you set the websocket port from the command line, the
     /// `mdbook serve` command needs a way to let the HTML renderer know where
@@ -522,27 +1571,98 @@ impl Default for HtmlConfig {
             theme: None,
             default_theme: None,
             preferred_dark_theme: None,
+            strict_templates: false,
             curly_quotes: false,
             mathjax_support: false,
+            mathjax_source: None,
+            math_renderer: None,
             copy_fonts: true,
             google_analytics: None,
             additional_css: Vec::new(),
             additional_js: Vec::new(),
+            extra_static_dirs: Vec::new(),
+            print: Print::default(),
+            single_file: SingleFile::default(),
+            link_check: LinkCheck::default(),
+            archive: None,
+            post_process: Vec::new(),
             fold: Fold::default(),
             playpen: Playpen::default(),
+            highlight: Highlight::default(),
+            syntect: Syntect::default(),
             no_section_label: false,
             search: None,
             git_repository_url: None,
             git_repository_icon: None,
+            nav_buttons: Vec::new(),
+            edit_url_template: None,
+            incremental: false,
+            context: Table::new(),
+            breadcrumbs_include_title: false,
+            pretty_urls: false,
+            copyable_code: true,
+            line_numbers: false,
+            passthrough_code_languages: Vec::new(),
+            tables: true,
+            strikethrough: true,
+            task_lists: true,
+            strip_first_h1: false,
+            heading_offset: 0,
+            admonitions: Admonitions::default(),
+            pagetoc: PageToc::default(),
+            reading_time: ReadingTime::default(),
+            site_url: None,
+            sitemap: true,
+            input_404: None,
+            output_404: None,
+            nojekyll: true,
+            cname: None,
+            head_extra: None,
+            redirect: BTreeMap::new(),
+            index_page: IndexPage::Copy,
+            git_metadata: true,
+            last_modified: false,
             livereload_url: None,
         }
     }
 }
 
+/// The MathJax script of the default theme.
+const MATHJAX_SOURCE: &str =
+    "https://cdnjs.cloudflare.com/ajax/libs/mathjax/2.7.1/MathJax.js?config=TeX-AMS-MML_HTMLorMML";
+
 impl HtmlConfig {
+    /// Whether `google-analytics` is a Google Analytics 4 measurement ID (like
+    /// `G-XXXXXXXXXX`), which needs the gtag.js snippet instead of the
+    /// analytics.js one of tracking IDs (like `UA-XXXXX-Y`).
+    pub fn google_analytics_gtag(&self) -> bool {
+        match self.google_analytics {
+            Some(ref id) => google_analytics_id_kind(id) == Some(GoogleAnalyticsId::Measurement),
+            None => false,
+        }
+    }
+
+    /// The URL of the MathJax script, `mathjax-source` or the one on cdnjs.
+    pub fn mathjax_source(&self) -> &str {
+        match self.mathjax_source {
+            Some(ref source) => source,
+            None => MATHJAX_SOURCE,
+        }
+    }
+
+    /// What renders the math, `math-renderer` or the one `mathjax-support`
+    /// enables.
+    pub fn math_renderer(&self) -> MathRenderer {
+        match self.math_renderer {
+            Some(renderer) => renderer,
+            None if self.mathjax_support => MathRenderer::Mathjax,
+            None => MathRenderer::None,
+        }
+    }
+
     /// Returns the directory of theme from the provided root directory. If the
     /// directory is not present it will append the default directory of "theme"
-    pub fn theme_dir(&self, root: &PathBuf) -> PathBuf {
+    pub fn theme_dir(&self, root: &Path) -> PathBuf {
         match self.theme {
             Some(ref d) => root.join(d),
             None => root.join("theme"),
@@ -550,14 +1670,195 @@ impl HtmlConfig {
     }
 }
 
-/// Configuration for how to fold chapters of sidebar.
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(default, rename_all = "kebab-case")]
-pub struct Fold {
-    /// When off, all folds are open. Default: `false`.
-    pub enable: bool,
-    /// The higher the more folded regions are open. When level is 0, all folds
-    /// are closed.
+/// The HTML of `output.html.head-extra`, which is either given inline as a
+/// string, or as `{ file = "path" }` with a file relative to the book root.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HeadExtra {
+    /// The HTML itself.
+    Html(String),
+    /// A file containing the HTML, which is read on every build.
+    File {
+        /// The file's location, relative to the book root.
+        file: PathBuf,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum GoogleAnalyticsId {
+    /// A Google Analytics 4 measurement ID, like `G-XXXXXXXXXX`.
+    Measurement,
+    /// A Universal Analytics tracking ID, like `UA-XXXXX-Y`.
+    Tracking,
+}
+
+fn google_analytics_id_kind(id: &str) -> Option<GoogleAnalyticsId> {
+    lazy_static! {
+        static ref MEASUREMENT_ID: Regex = Regex::new(r"^G-[A-Z0-9]+$").unwrap();
+        static ref TRACKING_ID: Regex = Regex::new(r"^UA-\d+-\d+$").unwrap();
+    }
+
+    if MEASUREMENT_ID.is_match(id) {
+        Some(GoogleAnalyticsId::Measurement)
+    } else if TRACKING_ID.is_match(id) {
+        Some(GoogleAnalyticsId::Tracking)
+    } else {
+        None
+    }
+}
+
+/// Whether `tag` looks like a BCP 47 language tag: a language code of two or
+/// three letters, then subtags like a script or a region (as in `zh-Hant-TW`).
+fn is_language_tag(tag: &str) -> bool {
+    lazy_static! {
+        static ref LANGUAGE_TAG: Regex =
+            Regex::new(r"^[a-zA-Z]{2,3}(-[a-zA-Z0-9]{1,8})*$").unwrap();
+    }
+
+    LANGUAGE_TAG.is_match(tag)
+}
+
+/// Configuration for how to render the print icon, print.html, and print.css.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Print {
+    /// Whether print support is enabled. Default: `true`.
+    pub enable: bool,
+}
+
+impl Default for Print {
+    fn default() -> Self {
+        Self { enable: true }
+    }
+}
+
+/// Configuration of `book.html`, the whole book in a single file which needs
+/// nothing else, for reading it offline.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SingleFile {
+    /// Whether `book.html` is generated. Default: `false`.
+    pub enable: bool,
+    /// The size, in bytes, of the biggest images embedded in `book.html`. The
+    /// bigger ones are linked to instead. Default: 1 MiB.
+    pub max_image_size: u64,
+}
+
+impl Default for SingleFile {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            max_image_size: 1024 * 1024,
+        }
+    }
+}
+
+/// Configuration of the check of the links of the rendered book, which reads
+/// every HTML file of the output directory after the book is rendered.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LinkCheck {
+    /// Whether the links are checked, failing the build when one of them
+    /// doesn't point to a file and heading of the book. Default: `false`.
+    pub enable: bool,
+    /// Whether the `http` and `https` links are checked too, which only warns
+    /// about the broken ones. Default: `false`, or `true` with
+    /// `mdbook build --check-external`.
+    pub check_external: bool,
+    /// How long to wait for the servers of the external links, in seconds.
+    /// Default: `10`.
+    pub timeout: u64,
+    /// How many external links are checked at the same time. Default: `8`.
+    pub concurrency: usize,
+    /// The external links starting with one of these aren't checked.
+    pub ignore: Vec<String>,
+}
+
+impl Default for LinkCheck {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            check_external: false,
+            timeout: 10,
+            concurrency: 8,
+            ignore: Vec::new(),
+        }
+    }
+}
+
+impl LinkCheck {
+    /// `output.html.link-check`, either as a table or as the `enable` value.
+    fn from_bool_or_table<'de, D: Deserializer<'de>>(
+        de: D,
+    ) -> std::result::Result<LinkCheck, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum BoolOrTable {
+            Bool(bool),
+            Table(LinkCheck),
+        }
+
+        Ok(match BoolOrTable::deserialize(de)? {
+            BoolOrTable::Bool(enable) => LinkCheck {
+                enable,
+                ..LinkCheck::default()
+            },
+            BoolOrTable::Table(link_check) => link_check,
+        })
+    }
+}
+
+/// A post-processor of the chapters' pages, in `[[output.html.post-process]]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PostProcess {
+    /// The name of a built-in post-processor, or of the command, for the
+    /// errors.
+    pub name: String,
+    /// The command the HTML of the pages is piped through, which isn't set
+    /// for the built-in post-processors.
+    pub command: Option<String>,
+}
+
+/// The formats of the archive of the rendered book.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ArchiveFormat {
+    /// A `.zip` file.
+    #[serde(rename = "zip")]
+    Zip,
+    /// A gzipped tarball, `.tar.gz`.
+    #[serde(rename = "tar.gz")]
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// The extension of the archives of this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    }
+
+    /// Where the archive of the `destination` directory goes: beside it, and
+    /// named after it, like `book.zip` for `book/`.
+    pub fn archive_path(self, destination: &Path) -> PathBuf {
+        let name = destination
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_else(|| "book".into());
+        destination.with_file_name(format!("{}.{}", name, self.extension()))
+    }
+}
+
+/// Configuration for how to fold chapters of sidebar.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Fold {
+    /// When off, all folds are open. Default: `false`.
+    pub enable: bool,
+    /// The higher the more folded regions are open. When level is 0, all folds
+    /// are closed.
     /// Default: `0`.
     pub level: u8,
 }
@@ -575,6 +1876,12 @@ pub struct Playpen {
     pub copy_js: bool,
     /// Display line numbers on playpen snippets. Default: `false`.
     pub line_numbers: bool,
+    /// The edition the playpen snippets are run with, instead of
+    /// `rust.edition`. Default: `None`.
+    pub edition: Option<RustEdition>,
+    /// The external crates snippets may use. When it's empty, any crate of
+    /// the playground is allowed. Default: empty.
+    pub crates: Vec<String>,
 }
 
 impl Default for Playpen {
@@ -584,10 +1891,189 @@ impl Default for Playpen {
             copyable: true,
             copy_js: true,
             line_numbers: false,
+            edition: None,
+            crates: Vec::new(),
         }
     }
 }
 
+impl Playpen {
+    /// The edition the snippets are run and tested with: the playpen's, or
+    /// else the book's `rust.edition`.
+    pub fn effective_edition(&self, rust: &RustConfig) -> Option<RustEdition> {
+        self.edition.or(rust.edition)
+    }
+}
+
+/// Where the code blocks of the HTML renderer are highlighted.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Highlight {
+    /// In the browser, with highlight.js.
+    Javascript,
+    /// When the book is built, with syntect, so the pages don't need
+    /// JavaScript for it.
+    Syntect,
+}
+
+impl Default for Highlight {
+    fn default() -> Highlight {
+        Highlight::Javascript
+    }
+}
+
+/// What's written at the `index.html` of the book when its landing page, the
+/// first chapter, is somewhere else.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexPage {
+    /// A copy of the first chapter.
+    Copy,
+    /// A page redirecting to the first chapter, so it has a single URL.
+    Redirect,
+}
+
+/// What renders the math of the chapters in the browser.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MathRenderer {
+    /// MathJax, for the math between `\\(` and `\\)` or `\\[` and `\\]`.
+    Mathjax,
+    /// KaTeX, which also renders the math between `$` and `$` or `$$` and
+    /// `$$`.
+    Katex,
+    /// Nothing, the math is left as it's written.
+    None,
+}
+
+/// Configuration for highlighting code blocks with syntect.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Syntect {
+    /// The name of the syntect theme used with the light color themes of
+    /// the book. Default: `InspiredGitHub`.
+    pub theme: String,
+    /// The name of the syntect theme used with the dark color themes of the
+    /// book (`coal`, `navy` and `ayu`). Default: `base16-ocean.dark`.
+    pub dark_theme: String,
+    /// Extra `.sublime-syntax` files (relative to the book root) for
+    /// languages syntect doesn't know.
+    pub syntaxes: Vec<PathBuf>,
+    /// Code block languages which are highlighted with another syntax, by
+    /// the name of that syntax (like `Rust`) or of the file it was loaded
+    /// from (like `MyLang.sublime-syntax`).
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl Default for Syntect {
+    fn default() -> Syntect {
+        Syntect {
+            theme: String::from("InspiredGitHub"),
+            dark_theme: String::from("base16-ocean.dark"),
+            syntaxes: Vec::new(),
+            aliases: BTreeMap::new(),
+        }
+    }
+}
+
+/// Configuration of the admonitions, the blockquotes starting with a marker
+/// like `[!NOTE]` which are rendered as callouts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Admonitions {
+    /// Render the admonitions as callouts, instead of as blockquotes with the
+    /// marker. Default: `true`.
+    pub enable: bool,
+    /// The title of each kind of admonition (`note`, `tip`, `important`,
+    /// `warning` and `danger`), instead of the English one.
+    pub titles: BTreeMap<String, String>,
+}
+
+impl Default for Admonitions {
+    fn default() -> Admonitions {
+        Admonitions {
+            enable: true,
+            titles: BTreeMap::new(),
+        }
+    }
+}
+
+/// A button of the menu bar, a link with an icon.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct NavButton {
+    /// The FontAwesome icon class, like `fa-github`. Default: `fa-link`.
+    pub icon: String,
+    /// Where the button links to, relative to the book's root unless it's an
+    /// absolute URL. It's required.
+    pub url: String,
+    /// The tooltip and label of the button. Default: none.
+    pub title: String,
+}
+
+impl Default for NavButton {
+    fn default() -> NavButton {
+        NavButton {
+            icon: String::from("fa-link"),
+            url: String::new(),
+            title: String::new(),
+        }
+    }
+}
+
+/// Configuration of the in-page table of contents, the headings of a chapter
+/// which themes get as its `page_headings`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct PageToc {
+    /// The level of the highest headings listed, after the `heading-offset`.
+    /// Default: `2`.
+    pub min_level: u32,
+    /// The level of the lowest headings listed. Default: `3`.
+    pub max_level: u32,
+}
+
+impl Default for PageToc {
+    fn default() -> PageToc {
+        PageToc {
+            min_level: 2,
+            max_level: 3,
+        }
+    }
+}
+
+/// Configuration of the word counts and reading times of the chapters.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ReadingTime {
+    /// How many words are read in a minute. Default: `200`.
+    pub words_per_minute: u32,
+    /// How the words are counted. Default: `auto`.
+    pub counting: WordCounting,
+}
+
+impl Default for ReadingTime {
+    fn default() -> ReadingTime {
+        ReadingTime {
+            words_per_minute: 200,
+            counting: WordCounting::Auto,
+        }
+    }
+}
+
+/// How the words of a chapter are counted.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WordCounting {
+    /// Every Chinese or Japanese character is a word, like in `characters`,
+    /// and the rest of the text is split by whitespace, like in `words`.
+    Auto,
+    /// Everything between whitespace is a word.
+    Words,
+    /// Every letter or digit is a word, for scripts without spaces.
+    Characters,
+}
+
 /// Configuration of the search functionality of the HTML renderer.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default, rename_all = "kebab-case")]
@@ -616,6 +2102,14 @@ pub struct Search {
     /// Documents are split into smaller parts, seperated by headings. This defines, until which
     /// level of heading documents should be split. Default: `3`. (`### This is a level 3 heading`)
     pub heading_split_level: u8,
+    /// Sections with more words than this are split into several documents,
+    /// so their teasers stay close to the result. `0` doesn't split them.
+    /// Default: `0`.
+    pub max_section_words: u32,
+    /// Patterns of the chapters (like `internal/**`), relative to the source
+    /// directory, which are left out of the index. They're still rendered.
+    /// Default: none.
+    pub exclude: Vec<String>,
     /// Copy JavaScript files for the search functionality to the output directory?
     /// Default: `true`.
     pub copy_js: bool,
@@ -634,6 +2128,8 @@ impl Default for Search {
             boost_paragraph: 1,
             expand: true,
             heading_split_level: 3,
+            max_section_words: 0,
+            exclude: Vec::new(),
             copy_js: true,
         }
     }
@@ -649,7 +2145,7 @@ trait Updateable<'de>: Serialize + Deserialize<'de> {
         let mut raw = Value::try_from(&self).expect("unreachable");
 
         if let Ok(value) = Value::try_from(value) {
-            let _ = raw.insert(key, value);
+            raw.insert(key, value);
         } else {
             return;
         }
@@ -666,6 +2162,188 @@ impl<'de, T> Updateable<'de> for T where T: Serialize + Deserialize<'de> {}
 mod tests {
     use super::*;
 
+    #[test]
+    fn missing_html_config_table_is_not_an_error() {
+        let cfg = Config::from_str("[book]\ntitle = \"Some Book\"").unwrap();
+
+        assert!(cfg.html_config_checked().unwrap().is_none());
+    }
+
+    #[test]
+    fn valid_html_config_table_is_deserialized() {
+        let src = r#"
+        [output.html]
+        curly-quotes = true
+        mathjax-support = true
+        "#;
+        let cfg = Config::from_str(src).unwrap();
+
+        let got = cfg.html_config_checked().unwrap().unwrap();
+        assert!(got.curly_quotes);
+        assert!(got.mathjax_support);
+        assert!(got
+            .mathjax_source()
+            .starts_with("https://cdnjs.cloudflare.com/"));
+    }
+
+    #[test]
+    fn math_renderers_are_exclusive_with_mathjax_support() {
+        let cfg = |src: &str| Config::from_str(&format!("[output.html]\n{}", src)).unwrap();
+
+        let got = cfg("mathjax-support = true").html_config_checked();
+        assert_eq!(got.unwrap().unwrap().math_renderer(), MathRenderer::Mathjax);
+        let got = cfg("math-renderer = \"katex\"").html_config_checked();
+        assert_eq!(got.unwrap().unwrap().math_renderer(), MathRenderer::Katex);
+        let got = cfg("").html_config_checked();
+        assert_eq!(got.unwrap().unwrap().math_renderer(), MathRenderer::None);
+        let got = cfg("mathjax-support = true\nmath-renderer = \"mathjax\"").html_config_checked();
+        assert_eq!(got.unwrap().unwrap().math_renderer(), MathRenderer::Mathjax);
+
+        assert!(cfg("mathjax-support = true\nmath-renderer = \"katex\"")
+            .html_config_checked()
+            .is_err());
+        assert!(cfg("mathjax-support = true\nmath-renderer = \"none\"")
+            .html_config_checked()
+            .is_err());
+    }
+
+    #[test]
+    fn nav_buttons_need_a_url() {
+        let src = r#"
+        [[output.html.nav-buttons]]
+        icon = "fa-github"
+        url = "https://github.com/rust-lang/mdBook"
+        title = "Repository"
+
+        [[output.html.nav-buttons]]
+        url = "about.html"
+        "#;
+        let cfg = Config::from_str(src).unwrap();
+
+        let got = cfg.html_config_checked().unwrap().unwrap();
+        assert_eq!(
+            got.nav_buttons,
+            [
+                NavButton {
+                    icon: String::from("fa-github"),
+                    url: String::from("https://github.com/rust-lang/mdBook"),
+                    title: String::from("Repository"),
+                },
+                NavButton {
+                    url: String::from("about.html"),
+                    ..Default::default()
+                },
+            ]
+        );
+
+        let src = r#"
+        [[output.html.nav-buttons]]
+        url = "about.html"
+
+        [[output.html.nav-buttons]]
+        icon = "fa-github"
+        "#;
+        let cfg = Config::from_str(src).unwrap();
+
+        let got = cfg.html_config_checked().unwrap_err();
+        assert_eq!(
+            got.to_string(),
+            "`output.html.nav-buttons[1]` needs a `url`"
+        );
+    }
+
+    #[test]
+    fn mathjax_can_be_self_hosted() {
+        let src = r#"
+        [output.html]
+        mathjax-support = true
+        mathjax-source = "/mathjax/tex-chtml.js"
+        "#;
+        let cfg = Config::from_str(src).unwrap();
+
+        let got = cfg.html_config_checked().unwrap().unwrap();
+        assert_eq!(got.mathjax_source(), "/mathjax/tex-chtml.js");
+    }
+
+    #[test]
+    fn the_link_check_is_a_bool_or_a_table() {
+        let cfg = Config::from_str("[output.html]\nlink-check = true\n").unwrap();
+        let link_check = cfg.html_config_checked().unwrap().unwrap().link_check;
+        assert_eq!(
+            link_check,
+            LinkCheck {
+                enable: true,
+                ..LinkCheck::default()
+            }
+        );
+
+        let src = "[output.html.link-check]\nenable = true\ntimeout = 3\n";
+        let cfg = Config::from_str(src).unwrap();
+        let link_check = cfg.html_config_checked().unwrap().unwrap().link_check;
+        assert!(link_check.enable);
+        assert_eq!(link_check.timeout, 3);
+        assert_eq!(link_check.concurrency, 8);
+
+        let cfg = Config::from_str("[output.html]\nlink-check = \"yes\"\n").unwrap();
+        assert!(cfg.html_config_checked().is_err());
+    }
+
+    #[test]
+    fn the_syntect_table_of_book_toml_is_deserialized() {
+        let src = r#"
+        [output.html]
+        highlight = "syntect"
+
+        [output.html.syntect]
+        theme = "Solarized (light)"
+        dark-theme = "Solarized (dark)"
+        syntaxes = ["syntaxes/MyLang.sublime-syntax"]
+
+        [output.html.syntect.aliases]
+        ml = "MyLang.sublime-syntax"
+        "#;
+
+        let html_config = Config::from_str(src)
+            .unwrap()
+            .html_config_checked()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(html_config.highlight, Highlight::Syntect);
+        let mut aliases = BTreeMap::new();
+        aliases.insert(String::from("ml"), String::from("MyLang.sublime-syntax"));
+        assert_eq!(
+            html_config.syntect,
+            Syntect {
+                theme: String::from("Solarized (light)"),
+                dark_theme: String::from("Solarized (dark)"),
+                syntaxes: vec![PathBuf::from("syntaxes/MyLang.sublime-syntax")],
+                aliases,
+            }
+        );
+
+        let html_config = Config::from_str("[output.html]\nhighlight = \"syntect\"\n")
+            .unwrap()
+            .html_config_checked()
+            .unwrap()
+            .unwrap();
+        assert_eq!(html_config.syntect, Syntect::default());
+    }
+
+    #[test]
+    fn html_config_with_type_error_is_reported() {
+        let src = r#"
+        [output.html]
+        curly-quotes = "yes"
+        "#;
+        let cfg = Config::from_str(src).unwrap();
+
+        let err = cfg.html_config_checked().unwrap_err();
+        let message = format!("{:?}", err);
+        assert!(message.contains("[output.html]"), "{}", message);
+        assert!(message.contains("curly-quotes"), "{}", message);
+    }
+
     const COMPLEX_CONFIG: &str = r#"
         [book]
         title = "Some Book"
@@ -709,11 +2387,17 @@ mod tests {
             multilingual: true,
             src: PathBuf::from("source"),
             language: Some(String::from("ja")),
+            text_direction: TextDirection::Auto,
         };
         let build_should_be = BuildConfig {
             build_dir: PathBuf::from("outputs"),
             create_missing: false,
             use_default_preprocessors: true,
+            restart_numbering_per_part: false,
+            strict: false,
+            ignore_unlisted: Vec::new(),
+            deny_warnings: false,
+            timings: false,
         };
         let rust_should_be = RustConfig { edition: None };
         let playpen_should_be = Playpen {
@@ -721,6 +2405,8 @@ mod tests {
             copyable: true,
             copy_js: true,
             line_numbers: false,
+            edition: None,
+            crates: Vec::new(),
         };
         let html_should_be = HtmlConfig {
             curly_quotes: true,
@@ -794,6 +2480,57 @@ mod tests {
         assert_eq!(got.rust, rust_should_be);
     }
 
+    #[test]
+    fn playpen_edition_and_crates() {
+        let src = r#"
+        [rust]
+        edition = "2018"
+        [output.html.playpen]
+        edition = "2021"
+        crates = ["rand"]
+        "#;
+
+        let got = Config::from_str(src).unwrap();
+        let playpen = got.html_config().unwrap().playpen;
+        assert_eq!(got.rust.edition, Some(RustEdition::E2018));
+        assert_eq!(playpen.edition, Some(RustEdition::E2021));
+        assert_eq!(playpen.crates, vec![String::from("rand")]);
+        assert_eq!(
+            playpen.effective_edition(&got.rust),
+            Some(RustEdition::E2021)
+        );
+        let playpen = Playpen::default();
+        assert_eq!(
+            playpen.effective_edition(&got.rust),
+            Some(RustEdition::E2018)
+        );
+    }
+
+    #[test]
+    fn unknown_editions_are_an_error() {
+        let inputs = [
+            ("[rust]\nedition = \"2020\"", "`rust.edition` \"2020\""),
+            ("[rust]\nedition = 2018", "`rust.edition` 2018"),
+            (
+                "[output.html.playpen]\nedition = \"next\"",
+                "`output.html.playpen.edition` \"next\"",
+            ),
+        ];
+
+        for &(src, key) in &inputs {
+            let err = Config::from_str(src).unwrap_err();
+            let message = format!("{:?}", err);
+            assert!(
+                message.contains(&format!(
+                    "Invalid {}, expected one of: \"2015\", \"2018\", \"2021\"",
+                    key
+                )),
+                "{}",
+                message
+            );
+        }
+    }
+
     #[test]
     fn load_arbitrary_output_type() {
         #[derive(Debug, Deserialize, PartialEq)]
@@ -843,6 +2580,151 @@ mod tests {
         assert_eq!(config.get(key).unwrap(), &Value::Boolean(false));
     }
 
+    #[test]
+    fn dotted_keys_reach_into_every_table() {
+        let config = Config::from_str(COMPLEX_CONFIG).unwrap();
+
+        let editable: Option<bool> = config
+            .get_deserialized_opt("output.html.playpen.editable")
+            .unwrap();
+        assert_eq!(editable, Some(true));
+        let title: Option<String> = config.get_deserialized_opt("book.title").unwrap();
+        assert_eq!(title, config.book.title);
+        let build_dir: Option<PathBuf> = config.get_deserialized_opt("build.build-dir").unwrap();
+        assert_eq!(build_dir, Some(PathBuf::from("outputs")));
+        let book: Option<BookConfig> = config.get_deserialized_opt("book").unwrap();
+        assert_eq!(book, Some(config.book.clone()));
+        let missing: Option<String> = config.get_deserialized_opt("output.html.nope").unwrap();
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn set_creates_the_tables_on_the_way() {
+        let mut config = Config::default();
+        config
+            .set("output.linkcheck.follow-web-links", true)
+            .unwrap();
+        config.set("rust.edition", "2018").unwrap();
+
+        assert_eq!(
+            config.get("output.linkcheck"),
+            Some(&Value::Table(
+                vec![("follow-web-links".to_string(), Value::Boolean(true))]
+                    .into_iter()
+                    .collect()
+            ))
+        );
+        assert_eq!(config.rust.edition, Some(RustEdition::E2018));
+    }
+
+    #[test]
+    fn snake_case_keys_are_the_kebab_case_ones() {
+        let src = "[output.html]\nsite-url = \"/docs/\"\n\n[preprocessor.my_preprocessor]\n";
+        let mut config = Config::from_str(src).unwrap();
+
+        assert_eq!(
+            config.get("output.html.site_url"),
+            Some(&Value::String("/docs/".into()))
+        );
+        config
+            .set("output.html.git_repository_url", "https://example.com")
+            .unwrap();
+        assert!(config.get("output.html.git-repository-url").is_some());
+        config.set("build.create_missing", false).unwrap();
+        assert!(!config.build.create_missing);
+
+        // The tables of the preprocessors and renderers are theirs
+        assert!(config.get("preprocessor.my_preprocessor").is_some());
+        config
+            .set("preprocessor.my_preprocessor.some_key", 1)
+            .unwrap();
+        assert!(config
+            .get("preprocessor.my_preprocessor.some_key")
+            .is_some());
+        assert!(config.get("preprocessor.my-preprocessor").is_none());
+    }
+
+    #[test]
+    fn snake_case_keys_of_the_users_are_left_alone() {
+        let src = "[output.html]\nmy-key = 1\n\n[preprocessor.custom]\nsome-key = 2\n";
+        let mut config = Config::from_str(src).unwrap();
+
+        // The kebab-case key exists, but isn't one of mdBook's
+        assert_eq!(config.get("preprocessor.custom.some_key"), None);
+        assert_eq!(config.get("output.html.my_key"), None);
+        config.set("output.html.my_key", 3).unwrap();
+        assert_eq!(config.get("output.html.my-key"), Some(&Value::Integer(1)));
+        assert_eq!(config.get("output.html.my_key"), Some(&Value::Integer(3)));
+
+        // mdBook's own keys, nested ones too
+        assert!(is_known_key("output.html.playpen.copy-js"));
+        assert!(is_known_key("book.multilingual"));
+        assert!(!is_known_key("output.html.playpen.my-option"));
+        assert!(!is_known_key("output.markdown.some-key"));
+    }
+
+    #[test]
+    fn book_toml_round_trips_in_its_order() {
+        let src = r#"
+        [preprocessor.custom]
+        command = "./custom"
+
+        [book]
+        title = "The Book"
+        subtitle = "Not a key of mdBook"
+
+        [output.html]
+        unknown-key = 1
+        default-theme = "ayu"
+
+        [output.linkcheck]
+        follow-web-links = true
+        "#;
+        let mut config = Config::from_str(src).unwrap();
+        config.set("output.html.site-url", "/docs/").unwrap();
+        config.book.title = Some(String::from("A Better Title"));
+
+        let written = toml::to_string(&config).unwrap();
+        let position = |needle: &str| written.find(needle).expect(needle);
+        assert!(position("[preprocessor.custom]") < position("[book]"));
+        assert!(position("[book]") < position("[output.html]"));
+        assert!(position("[output.html]") < position("[output.linkcheck]"));
+        assert!(written.contains("subtitle = \"Not a key of mdBook\""));
+        assert!(written.contains("title = \"A Better Title\""));
+        assert!(written.contains("unknown-key = 1"));
+        assert!(position("unknown-key = 1") < position("default-theme = \"ayu\""));
+        assert!(position("default-theme = \"ayu\"") < position("site-url = \"/docs/\""));
+        assert!(written.contains("[build]"));
+
+        let read_back = Config::from_str(&written).unwrap();
+        assert_eq!(read_back, config);
+        assert_eq!(toml::to_string(&read_back).unwrap(), written);
+    }
+
+    #[test]
+    fn typos_of_the_top_level_tables_are_suggested() {
+        assert_eq!(edit_distance("outptu", "output"), 1);
+        assert_eq!(edit_distance("otuptu", "output"), 2);
+        assert_eq!(edit_distance("", "book"), 4);
+        assert_eq!(closest_key("boook", TOP_LEVEL_TABLES), Some("book"));
+        assert_eq!(closest_key("bild", TOP_LEVEL_TABLES), Some("build"));
+        // Too short, or too far from any of them
+        assert_eq!(closest_key("foo", TOP_LEVEL_TABLES), None);
+        assert_eq!(closest_key("bok", TOP_LEVEL_TABLES), None);
+        assert_eq!(closest_key("bolt", TOP_LEVEL_TABLES), None);
+        assert_eq!(
+            closest_key("preprocesor", TOP_LEVEL_TABLES),
+            Some("preprocessor")
+        );
+        assert_eq!(closest_key("custom-tool", TOP_LEVEL_TABLES), None);
+
+        let cfg = Config::from_str("[buidl]\ncreate-missing = false\nversion = 2\n").unwrap();
+        assert_eq!(
+            cfg.unknown_top_level_keys(),
+            vec!["Unknown table `[buidl]` in book.toml, ignoring it (did you mean `[build]`?)"]
+        );
+    }
+
     /// The config file format has slightly changed (metadata stuff is now under
     /// the `book` table instead of being at the top level) so we're adding a
     /// **temporary** compatibility check. You should be able to still load the
@@ -878,6 +2760,11 @@ mod tests {
             build_dir: PathBuf::from("my-book"),
             create_missing: true,
             use_default_preprocessors: true,
+            restart_numbering_per_part: false,
+            strict: false,
+            ignore_unlisted: Vec::new(),
+            deny_warnings: false,
+            timings: false,
         };
 
         let html_should_be = HtmlConfig {
@@ -986,4 +2873,296 @@ mod tests {
 
         assert_eq!(cfg.book.title, Some(should_be));
     }
+
+    fn update_from(cfg: &mut Config, vars: &[(&str, &str)]) -> Result<()> {
+        let vars = vars.iter().map(|(k, v)| (k.to_string(), v.to_string()));
+        cfg.update_from_vars(vars)
+    }
+
+    #[test]
+    fn env_values_are_parsed_as_toml() {
+        let src = r#"
+        [book]
+        title = "The Book"
+        authors = ["Someone"]
+
+        [output.html]
+        site-url = "https://example.com/"
+        "#;
+        let mut cfg = Config::from_str(src).unwrap();
+        update_from(
+            &mut cfg,
+            &[
+                (
+                    "MDBOOK_OUTPUT__HTML__SITE_URL",
+                    "https://preview.example.com/",
+                ),
+                ("MDBOOK_OUTPUT__HTML__LIVERELOAD_URL", "ws://localhost:3001"),
+                ("MDBOOK_BUILD__STRICT", "true"),
+                ("MDBOOK_BOOK__AUTHORS", r#"["Someone", "Someone else"]"#),
+                ("MDBOOK_OUTPUT__HTML__PLAYPEN", "{ editable = true }"),
+                ("MDBOOK_PREPROCESSOR__CUSTOM__COMMAND", "./custom --verbose"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            cfg.get("output.html.site-url"),
+            Some(&Value::String("https://preview.example.com/".into()))
+        );
+        assert_eq!(
+            cfg.get("output.html.livereload-url"),
+            Some(&Value::String("ws://localhost:3001".into()))
+        );
+        assert!(cfg.build.strict);
+        assert_eq!(cfg.book.title, Some(String::from("The Book")));
+        assert_eq!(cfg.book.authors, vec!["Someone", "Someone else"]);
+        assert_eq!(
+            cfg.get("output.html.playpen.editable"),
+            Some(&Value::Boolean(true))
+        );
+        assert_eq!(
+            cfg.get("preprocessor.custom.command"),
+            Some(&Value::String("./custom --verbose".into()))
+        );
+    }
+
+    #[test]
+    fn env_tables_are_merged_with_the_config_file() {
+        let src = r#"
+        [book]
+        title = "The Book"
+
+        [output.html]
+        default-theme = "rust"
+        "#;
+        let mut cfg = Config::from_str(src).unwrap();
+        update_from(
+            &mut cfg,
+            &[
+                ("MDBOOK_OUTPUT__HTML__SITE_URL", "/docs/"),
+                (
+                    "MDBOOK_OUTPUT__HTML",
+                    r#"{ git-repository-url = "https://example.com" }"#,
+                ),
+                ("MDBOOK_BOOK", r#"{ authors = ["Someone"] }"#),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(cfg.book.title, Some(String::from("The Book")));
+        assert_eq!(cfg.book.authors, vec!["Someone"]);
+        let html = cfg.html_config().unwrap();
+        assert_eq!(html.default_theme, Some(String::from("rust")));
+        assert_eq!(html.site_url, Some(String::from("/docs/")));
+        assert_eq!(
+            html.git_repository_url,
+            Some(String::from("https://example.com"))
+        );
+    }
+
+    #[test]
+    fn env_values_which_conflict_with_the_config_file_are_errors() {
+        let mut cfg = Config::from_str(
+            "[output.html.playpen]
+editable = true
+",
+        )
+        .unwrap();
+        let err = update_from(&mut cfg, &[("MDBOOK_OUTPUT__HTML__PLAYPEN", "true")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The environment variable MDBOOK_OUTPUT__HTML__PLAYPEN sets \
+             `output.html.playpen` to a boolean, but it's a table in book.toml"
+        );
+
+        let err = update_from(
+            &mut cfg,
+            &[("MDBOOK_OUTPUT__HTML__PLAYPEN__EDITABLE__NOW", "false")],
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The environment variable MDBOOK_OUTPUT__HTML__PLAYPEN__EDITABLE__NOW sets \
+             `output.html.playpen.editable.now`, but `output.html.playpen.editable` is a \
+             boolean in book.toml, not a table"
+        );
+
+        let err = update_from(&mut cfg, &[("MDBOOK_BUILD__CREATE_MISSING", "[1, 2]")]).unwrap_err();
+        assert!(
+            err.to_string().contains("MDBOOK_BUILD__CREATE_MISSING"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn env_values_which_cant_be_applied_are_skipped_unless_trying() {
+        let src = "[skipped-env]\ntable = { kept = true }\n";
+        let vars = [
+            (
+                "MDBOOK_SKIPPED_ENV__TABLE",
+                "{ kept = false, added = true }",
+            ),
+            ("MDBOOK_SKIPPED_ENV__TABLE__KEPT__NOW", "false"),
+            ("MDBOOK_SKIPPED_ENV__OTHER", "1"),
+        ];
+        for &(var, value) in &vars {
+            env::set_var(var, value);
+        }
+
+        let mut cfg = Config::from_str(src).unwrap();
+        let err = cfg.try_update_from_env().unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("MDBOOK_SKIPPED_ENV__TABLE__KEPT__NOW"),
+            "{}",
+            err
+        );
+
+        // The variable which conflicts with an earlier one is left out, and
+        // none of it is half applied
+        let mut cfg = Config::from_str(src).unwrap();
+        cfg.update_from_env();
+        for &(var, _) in &vars {
+            env::remove_var(var);
+        }
+        assert_eq!(
+            cfg.get("skipped-env").unwrap(),
+            &Value::from_str("table = { kept = false, added = true }\nother = 1").unwrap()
+        );
+    }
+
+    #[test]
+    fn the_text_direction_follows_the_language() {
+        let inputs = [
+            (Some("ar"), TextDirection::Rtl),
+            (Some("he-IL"), TextDirection::Rtl),
+            (Some("FA"), TextDirection::Rtl),
+            (Some("ur"), TextDirection::Rtl),
+            (Some("en"), TextDirection::Ltr),
+            (Some("ja"), TextDirection::Ltr),
+            (None, TextDirection::Ltr),
+        ];
+        for &(language, direction) in &inputs {
+            let book = BookConfig {
+                language: language.map(String::from),
+                ..Default::default()
+            };
+            assert_eq!(book.realized_text_direction(), direction, "{:?}", language);
+        }
+    }
+
+    #[test]
+    fn the_text_direction_can_be_set() {
+        let src = r#"
+        [book]
+        language = "ar"
+        text-direction = "ltr"
+        "#;
+        let cfg = Config::from_str(src).unwrap();
+        assert_eq!(cfg.book.text_direction, TextDirection::Ltr);
+        assert_eq!(cfg.book.realized_text_direction(), TextDirection::Ltr);
+
+        let book = BookConfig {
+            language: Some(String::from("en")),
+            text_direction: TextDirection::Rtl,
+            ..Default::default()
+        };
+        assert_eq!(book.realized_text_direction(), TextDirection::Rtl);
+    }
+
+    #[test]
+    fn languages_of_translations() {
+        let src = r#"
+        [book]
+        language = "fr"
+
+        [language.en]
+        name = "English"
+
+        [language.fr]
+        name = "Français"
+        title = "Un livre"
+        authors = ["Ferris"]
+        "#;
+        let cfg = Config::from_str(src).unwrap();
+
+        assert_eq!(cfg.language.len(), 2);
+        assert_eq!(cfg.language["fr"].title, Some(String::from("Un livre")));
+        assert_eq!(cfg.language["en"].authors, None);
+        assert_eq!(cfg.default_language(), Some("fr"));
+        assert!(cfg.get("language").is_none());
+
+        let mut cfg = cfg;
+        cfg.book.language = Some(String::from("de"));
+        assert_eq!(cfg.default_language(), Some("en"));
+        cfg.language.get_mut("fr").unwrap().default = true;
+        assert_eq!(cfg.default_language(), Some("fr"));
+
+        assert_eq!(Config::default().default_language(), None);
+        assert!(Config::from_str("[language.en]\nname = 1\n").is_err());
+    }
+
+    #[test]
+    fn language_tags() {
+        for tag in &["en", "de", "pt-BR", "zh-Hant-TW", "ast"] {
+            assert!(is_language_tag(tag), "{}", tag);
+        }
+        for tag in &["", "e", "english", "en_US", "en-", "de DE"] {
+            assert!(!is_language_tag(tag), "{}", tag);
+        }
+    }
+
+    #[test]
+    fn google_analytics_ids() {
+        let inputs = vec![
+            ("G-ABC123DEF4", Some(GoogleAnalyticsId::Measurement)),
+            ("UA-12345-6", Some(GoogleAnalyticsId::Tracking)),
+            ("UA-12345", None),
+            ("g-abc123", None),
+            ("G-", None),
+            ("123456", None),
+        ];
+
+        for (id, should_be) in inputs {
+            assert_eq!(google_analytics_id_kind(id), should_be, "{}", id);
+
+            let html_config = HtmlConfig {
+                google_analytics: Some(id.to_string()),
+                ..Default::default()
+            };
+            let gtag = should_be == Some(GoogleAnalyticsId::Measurement);
+            assert_eq!(html_config.google_analytics_gtag(), gtag, "{}", id);
+        }
+
+        assert!(!HtmlConfig::default().google_analytics_gtag());
+    }
+
+    #[test]
+    fn head_extra_can_be_inline_or_a_file() {
+        let src = r#"
+        [output.html]
+        head-extra = "<meta name=\"verify\" content=\"1234\">"
+        "#;
+        let got = Config::from_str(src).unwrap().html_config().unwrap();
+        assert_eq!(
+            got.head_extra,
+            Some(HeadExtra::Html(String::from(
+                r#"<meta name="verify" content="1234">"#
+            )))
+        );
+
+        let src = r#"
+        [output.html]
+        head-extra = { file = "theme/head-extra.html" }
+        "#;
+        let got = Config::from_str(src).unwrap().html_config().unwrap();
+        assert_eq!(
+            got.head_extra,
+            Some(HeadExtra::File {
+                file: PathBuf::from("theme/head-extra.html")
+            })
+        );
+    }
 }
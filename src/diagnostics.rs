@@ -0,0 +1,238 @@
+//! The warnings of a build, collected so they can be summarised at its end
+//! and turned into a failure with `--deny-warnings`.
+//!
+//! The renderers get the [`Diagnostics`] of the build in their
+//! [`RenderContext`](../renderer/struct.RenderContext.html), and everything
+//! reported to it is logged as it happens too.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// What a warning is about.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiagnosticKind {
+    /// A link or image of a chapter which doesn't go anywhere.
+    BrokenLink,
+    /// A file of the source directory which isn't part of the book.
+    UnlistedFile,
+    /// A mistake in `book.toml`, like a key which doesn't exist.
+    Config,
+    /// Anything else.
+    Other,
+}
+
+impl DiagnosticKind {
+    /// Its name, like `broken-link`.
+    fn name(self) -> &'static str {
+        match self {
+            DiagnosticKind::BrokenLink => "broken-link",
+            DiagnosticKind::UnlistedFile => "unlisted-file",
+            DiagnosticKind::Config => "config",
+            DiagnosticKind::Other => "other",
+        }
+    }
+
+    fn heading(self) -> &'static str {
+        match self {
+            DiagnosticKind::BrokenLink => "broken links",
+            DiagnosticKind::UnlistedFile => "unlisted files",
+            DiagnosticKind::Config => "configuration",
+            DiagnosticKind::Other => "other",
+        }
+    }
+}
+
+/// A warning of the build.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// What it's about.
+    pub kind: DiagnosticKind,
+    /// What's wrong.
+    pub message: String,
+    /// The source file of the chapter it's in, relative to the source
+    /// directory, if it's in one.
+    pub chapter: Option<PathBuf>,
+    /// The line of the chapter it's on, if that's known.
+    pub line: Option<usize>,
+}
+
+impl Diagnostic {
+    /// Create a warning which isn't about a particular chapter.
+    pub fn new<S: Into<String>>(kind: DiagnosticKind, message: S) -> Diagnostic {
+        Diagnostic {
+            kind,
+            message: message.into(),
+            chapter: None,
+            line: None,
+        }
+    }
+
+    /// Say which chapter the warning is about.
+    pub fn with_chapter<P: Into<PathBuf>>(mut self, chapter: P) -> Diagnostic {
+        self.chapter = Some(chapter.into());
+        self
+    }
+
+    /// Say which line of the chapter the warning is about.
+    pub fn with_line(mut self, line: usize) -> Diagnostic {
+        self.line = Some(line);
+        self
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match (&self.chapter, self.line) {
+            (Some(chapter), Some(line)) => {
+                write!(f, "{}:{}: {}", chapter.display(), line, self.message)
+            }
+            (Some(chapter), None) => write!(f, "{}: {}", chapter.display(), self.message),
+            (None, _) => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// The warnings of a build. Clones share the warnings, so the renderers (and
+/// the threads they render with) all report to the same collection.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    warnings: Arc<Mutex<Vec<Diagnostic>>>,
+    /// The backend reporting to this clone, for the logs.
+    backend: Option<String>,
+}
+
+impl Diagnostics {
+    /// Create an empty collection of warnings.
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    /// A clone for the backend `name` to report to, so the logs of its
+    /// warnings say which backend they're from.
+    pub fn for_backend(&self, name: &str) -> Diagnostics {
+        Diagnostics {
+            warnings: Arc::clone(&self.warnings),
+            backend: Some(name.to_string()),
+        }
+    }
+
+    /// Log a warning and add it to the collection. The message of the log
+    /// ends with its kind and backend, like `[broken-link, html]`.
+    pub fn warn(&self, diagnostic: Diagnostic) {
+        match self.backend {
+            Some(ref backend) => warn!("{} [{}, {}]", diagnostic, diagnostic.kind.name(), backend),
+            None => warn!("{} [{}]", diagnostic, diagnostic.kind.name()),
+        }
+        self.warnings.lock().unwrap().push(diagnostic);
+    }
+
+    /// The warnings so far, in the order they were reported.
+    pub fn warnings(&self) -> Vec<Diagnostic> {
+        self.warnings.lock().unwrap().clone()
+    }
+
+    /// The number of warnings so far.
+    pub fn len(&self) -> usize {
+        self.warnings.lock().unwrap().len()
+    }
+
+    /// Whether there weren't any warnings.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget the warnings, before building the book again.
+    pub fn clear(&self) {
+        self.warnings.lock().unwrap().clear();
+    }
+
+    /// The warnings grouped by their kind, for the end of the build.
+    pub fn summary(&self) -> String {
+        let warnings = self.warnings();
+        let mut groups: BTreeMap<DiagnosticKind, Vec<&Diagnostic>> = BTreeMap::new();
+        for warning in &warnings {
+            groups.entry(warning.kind).or_default().push(warning);
+        }
+
+        let mut summary = format!(
+            "The build had {} warning{}",
+            warnings.len(),
+            if warnings.len() == 1 { "" } else { "s" }
+        );
+        for (kind, warnings) in groups {
+            summary.push_str(&format!("\n  {} ({}):", kind.heading(), warnings.len()));
+            for warning in warnings {
+                summary.push_str(&format!("\n    {}", warning));
+            }
+        }
+        summary
+    }
+}
+
+impl PartialEq for Diagnostics {
+    fn eq(&self, other: &Diagnostics) -> bool {
+        Arc::ptr_eq(&self.warnings, &other.warnings) || self.warnings() == other.warnings()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_the_warnings() {
+        let diagnostics = Diagnostics::new();
+        let clone = diagnostics.clone();
+        clone.warn(Diagnostic::new(DiagnosticKind::Config, "Unknown key"));
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics.warnings()[0].message, "Unknown key");
+
+        diagnostics.clear();
+        assert!(clone.is_empty());
+    }
+
+    #[test]
+    fn the_summary_groups_the_warnings_by_kind() {
+        let diagnostics = Diagnostics::new();
+        diagnostics.warn(
+            Diagnostic::new(DiagnosticKind::BrokenLink, "\"missing.md\" (no such file)")
+                .with_chapter("intro.md"),
+        );
+        diagnostics.warn(Diagnostic::new(DiagnosticKind::Config, "Unknown key"));
+        diagnostics.warn(
+            Diagnostic::new(DiagnosticKind::BrokenLink, "\"#nope\" (no such heading)")
+                .with_chapter("guide.md")
+                .with_line(3),
+        );
+
+        assert_eq!(
+            diagnostics.summary(),
+            "The build had 3 warnings\n  \
+             broken links (2):\n    \
+             intro.md: \"missing.md\" (no such file)\n    \
+             guide.md:3: \"#nope\" (no such heading)\n  \
+             configuration (1):\n    \
+             Unknown key"
+        );
+    }
+
+    #[test]
+    fn warnings_are_serialized_with_their_details() {
+        let warning = Diagnostic::new(DiagnosticKind::UnlistedFile, "Not in SUMMARY.md")
+            .with_chapter("draft.md");
+
+        assert_eq!(
+            serde_json::to_value(&warning).unwrap(),
+            json!({
+                "kind": "unlisted-file",
+                "message": "Not in SUMMARY.md",
+                "chapter": "draft.md",
+                "line": null,
+            })
+        );
+    }
+}
@@ -57,6 +57,24 @@
 //! md.build().expect("Building failed");
 //! ```
 //!
+//! Or put a book together in memory, with your own renderers and
+//! preprocessors, and build it like `mdbook build` does.
+//!
+//! ```rust,no_run
+//! use mdbook::book::{Book, Chapter};
+//! use mdbook::config::Config;
+//! use mdbook::MDBook;
+//!
+//! let mut book = Book::new();
+//! let content = String::from("# Introduction\n\nHello, world!\n");
+//! book.push_item(Chapter::new("Introduction", content, "intro.md", Vec::new()));
+//!
+//! let md = MDBook::load_with_config_and_book("/path/to/book/root", Config::default(), book)
+//!     .expect("Invalid configuration");
+//! let report = md.build_with_report().expect("Building failed");
+//! println!("Rendered with {:?}", report.backends);
+//! ```
+//!
 //! ## Implementing a new Backend
 //!
 //! `mdbook` has a fairly flexible mechanism for creating additional backends
@@ -99,9 +117,11 @@ extern crate pretty_assertions;
 
 pub mod book;
 pub mod config;
+pub mod diagnostics;
 pub mod preprocess;
 pub mod renderer;
 pub mod theme;
+pub mod timings;
 pub mod utils;
 
 /// The current version of `mdbook`.
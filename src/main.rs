@@ -4,13 +4,15 @@ extern crate clap;
 extern crate log;
 
 use chrono::Local;
-use clap::{App, AppSettings, ArgMatches};
+use clap::{App, AppSettings, Arg, ArgMatches};
 use env_logger::Builder;
-use log::LevelFilter;
+use log::{LevelFilter, Record};
+use mdbook::errors::Error;
 use mdbook::utils;
+use serde_json::json;
 use std::env;
 use std::ffi::OsStr;
-use std::io::Write;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 
 mod cmd;
@@ -18,8 +20,6 @@ mod cmd;
 const VERSION: &str = concat!("v", crate_version!());
 
 fn main() {
-    init_logger();
-
     // Create a list of valid arguments and sub-commands
     let app = App::new(crate_name!())
         .about(crate_description!())
@@ -32,21 +32,39 @@ fn main() {
             "For more information about a specific command, try `mdbook <command> --help`\n\
              The source code for mdBook is available at: https://github.com/rust-lang/mdBook",
         )
+        .arg(
+            Arg::with_name("message-format")
+                .long("message-format")
+                .global(true)
+                .takes_value(true)
+                .possible_values(&["human", "json"])
+                .default_value("human")
+                .help(
+                    "The format of the messages on stderr{n}\
+                     With `json`, every message is a JSON object on a line of its own.",
+                ),
+        )
         .subcommand(cmd::init::make_subcommand())
         .subcommand(cmd::build::make_subcommand())
         .subcommand(cmd::test::make_subcommand())
-        .subcommand(cmd::clean::make_subcommand());
+        .subcommand(cmd::clean::make_subcommand())
+        .subcommand(cmd::config::make_subcommand());
 
     #[cfg(feature = "watch")]
     let app = app.subcommand(cmd::watch::make_subcommand());
     #[cfg(feature = "serve")]
     let app = app.subcommand(cmd::serve::make_subcommand());
 
+    let matches = app.get_matches();
+    let json = matches.value_of("message-format") == Some("json");
+    init_logger(json);
+
     // Check which subcomamnd the user ran...
-    let res = match app.get_matches().subcommand() {
+    let res = match matches.subcommand() {
         ("init", Some(sub_matches)) => cmd::init::execute(sub_matches),
         ("build", Some(sub_matches)) => cmd::build::execute(sub_matches),
         ("clean", Some(sub_matches)) => cmd::clean::execute(sub_matches),
+        ("config", Some(sub_matches)) => cmd::config::execute(sub_matches),
         #[cfg(feature = "watch")]
         ("watch", Some(sub_matches)) => cmd::watch::execute(sub_matches),
         #[cfg(feature = "serve")]
@@ -56,25 +74,33 @@ fn main() {
     };
 
     if let Err(e) = res {
-        utils::log_backtrace(&e);
+        if json {
+            let _ = write_json_error(&mut io::stderr(), &e);
+        } else {
+            utils::log_backtrace(&e);
+        }
 
         std::process::exit(101);
     }
 }
 
-fn init_logger() {
+fn init_logger(json: bool) {
     let mut builder = Builder::new();
 
-    builder.format(|formatter, record| {
-        writeln!(
-            formatter,
-            "{} [{}] ({}): {}",
-            Local::now().format("%Y-%m-%d %H:%M:%S"),
-            record.level(),
-            record.target(),
-            record.args()
-        )
-    });
+    if json {
+        builder.format(|formatter, record| write_json_record(formatter, record));
+    } else {
+        builder.format(|formatter, record| {
+            writeln!(
+                formatter,
+                "{} [{}] ({}): {}",
+                Local::now().format("%Y-%m-%d %H:%M:%S"),
+                record.level(),
+                record.target(),
+                record.args()
+            )
+        });
+    }
 
     if let Ok(var) = env::var("RUST_LOG") {
         builder.parse_filters(&var);
@@ -88,6 +114,32 @@ fn init_logger() {
     builder.init();
 }
 
+/// Write a log record as a JSON object on a line of its own, like
+/// `{"level": "warn", "target": "mdbook::book", "message": "..."}`.
+fn write_json_record(out: &mut dyn Write, record: &Record<'_>) -> io::Result<()> {
+    let object = json!({
+        "level": record.level().to_string().to_lowercase(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    });
+
+    writeln!(out, "{}", object)
+}
+
+/// Write an error as a JSON object, with the messages of its causes (the
+/// context added to it) in a `causes` array.
+fn write_json_error(out: &mut dyn Write, error: &Error) -> io::Result<()> {
+    let causes: Vec<_> = error.chain().skip(1).map(ToString::to_string).collect();
+    let object = json!({
+        "level": "error",
+        "target": "mdbook",
+        "message": error.to_string(),
+        "causes": causes,
+    });
+
+    writeln!(out, "{}", object)
+}
+
 fn get_book_dir(args: &ArgMatches) -> PathBuf {
     if let Some(dir) = args.value_of("dir") {
         // Check if path is relative from current dir, or absolute...
@@ -107,3 +159,57 @@ fn open<P: AsRef<OsStr>>(path: P) {
         error!("Error opening web browser: {}", e);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use log::Level;
+
+    #[test]
+    fn records_are_json_objects() {
+        let record = Record::builder()
+            .args(format_args!(
+                "intro.md:3: Broken link \"setup.html\" (no such file) [broken-link, html]"
+            ))
+            .level(Level::Warn)
+            .target("mdbook::diagnostics")
+            .build();
+
+        let mut out = Vec::new();
+        write_json_record(&mut out, &record).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.ends_with('\n') && !out.trim_end().contains('\n'));
+        let got: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(
+            got,
+            json!({
+                "level": "warn",
+                "target": "mdbook::diagnostics",
+                "message": "intro.md:3: Broken link \"setup.html\" (no such file) [broken-link, html]",
+            })
+        );
+    }
+
+    #[test]
+    fn errors_have_their_causes() {
+        let error = Err::<(), _>(anyhow::anyhow!("No such file"))
+            .context("Unable to read SUMMARY.md")
+            .unwrap_err();
+
+        let mut out = Vec::new();
+        write_json_error(&mut out, &error).unwrap();
+
+        let got: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            got,
+            json!({
+                "level": "error",
+                "target": "mdbook",
+                "message": "Unable to read SUMMARY.md",
+                "causes": ["No such file"],
+            })
+        );
+    }
+}
@@ -1,13 +1,16 @@
 use regex::Regex;
-use std::path::Path;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
 
 use crate::errors::*;
+use crate::utils;
 
 use super::{Preprocessor, PreprocessorContext};
 use crate::book::{Book, BookItem};
 
 /// A preprocessor for converting file name `README.md` to `index.md` since
 /// `README.md` is the de facto index file in markdown-based documentation.
+/// The links of the chapters to the ones it renames are changed to match.
 #[derive(Default)]
 pub struct IndexPreprocessor;
 
@@ -25,45 +28,73 @@ impl Preprocessor for IndexPreprocessor {
         Self::NAME
     }
 
-    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
-        let source_dir = ctx.root.join(&ctx.config.book.src);
-        book.for_each_mut(|section: &mut BookItem| {
+    fn run(&self, _ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let chapters: BTreeSet<PathBuf> = book
+            .iter()
+            .filter_map(|item| match *item {
+                BookItem::Chapter(ref ch) => ch.path.clone(),
+                _ => None,
+            })
+            .collect();
+        let mut renamed = BTreeSet::new();
+
+        book.try_for_each_mut(|section: &mut BookItem| {
             if let BookItem::Chapter(ref mut ch) = *section {
                 if let Some(ref mut path) = ch.path {
                     if is_readme_file(&path) {
-                        let mut index_md = source_dir.join(path.with_file_name("index.md"));
-                        if index_md.exists() {
-                            warn_readme_name_conflict(&path, &&mut index_md);
+                        let index_md = path.with_file_name("index.md");
+                        if chapters.contains(&index_md) {
+                            bail!(
+                                "Both {} and {} would be rendered to {}, rename one of them or \
+                                 disable the \"index\" preprocessor",
+                                path.display(),
+                                index_md.display(),
+                                index_md.with_extension("html").display()
+                            );
                         }
 
+                        renamed.insert(path.clone());
                         path.set_file_name("index.md");
                     }
                 }
             }
-        });
+            Ok(())
+        })?;
+
+        if !renamed.is_empty() {
+            book.for_each_mut(|section: &mut BookItem| {
+                if let BookItem::Chapter(ref mut ch) = *section {
+                    if let Some(dir) = ch.path.as_ref().and_then(|path| path.parent()) {
+                        ch.content = link_to_index(&ch.content, dir, &renamed);
+                    }
+                }
+            });
+        }
 
         Ok(book)
     }
 }
 
-fn warn_readme_name_conflict<P: AsRef<Path>>(readme_path: P, index_path: P) {
-    let file_name = readme_path.as_ref().file_name().unwrap_or_default();
-    let parent_dir = index_path
-        .as_ref()
-        .parent()
-        .unwrap_or_else(|| index_path.as_ref());
-    warn!(
-        "It seems that there are both {:?} and index.md under \"{}\".",
-        file_name,
-        parent_dir.display()
-    );
-    warn!(
-        "mdbook converts {:?} into index.html by default. It may cause",
-        file_name
-    );
-    warn!("unexpected behavior if putting both files under the same directory.");
-    warn!("To solve the warning, try to rearrange the book structure or disable");
-    warn!("\"index\" preprocessor to stop the conversion.");
+/// Point the links of the chapter in `dir` to the `renamed` chapters at their
+/// `index.md`.
+fn link_to_index(content: &str, dir: &Path, renamed: &BTreeSet<PathBuf>) -> String {
+    utils::rewrite_links(content, |dest| {
+        if !utils::is_relative_link(dest) {
+            return None;
+        }
+        let (file, anchor) = match dest.find('#') {
+            Some(index) => dest.split_at(index),
+            None => (dest, ""),
+        };
+        if !renamed.contains(Path::new(&utils::resolve_link(dir, file))) {
+            return None;
+        }
+        let index_dir = match file.rfind('/') {
+            Some(index) => &file[..=index],
+            None => "",
+        };
+        Some(format!("{}index.md{}", index_dir, anchor))
+    })
 }
 
 fn is_readme_file<P: AsRef<Path>>(path: P) -> bool {
@@ -81,6 +112,96 @@ fn is_readme_file<P: AsRef<Path>>(path: P) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::book::Chapter;
+    use crate::config::Config;
+    use tempfile::Builder as TempFileBuilder;
+
+    fn run(book: Book, root: &Path) -> Result<Book> {
+        let ctx = PreprocessorContext::new(root.to_path_buf(), Config::default(), "html".into());
+        IndexPreprocessor::new().run(&ctx, book)
+    }
+
+    fn chapter(path: &str) -> Chapter {
+        Chapter::new(path, String::new(), path, Vec::new())
+    }
+
+    #[test]
+    fn readme_chapters_become_index_chapters() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let mut guide = chapter("guide/README.md");
+        guide
+            .sub_items
+            .push(BookItem::Chapter(chapter("guide/api/readme.md")));
+        let mut book = Book::new();
+        book.push_item(chapter("README.md"));
+        book.push_item(guide);
+        book.push_item(chapter("guide/usage.md"));
+
+        let book = run(book, temp.path()).unwrap();
+
+        let paths: Vec<_> = book
+            .iter()
+            .filter_map(|item| match *item {
+                BookItem::Chapter(ref ch) => ch.path.clone(),
+                _ => None,
+            })
+            .collect();
+        let should_be: Vec<PathBuf> = vec![
+            "index.md".into(),
+            "guide/index.md".into(),
+            "guide/api/index.md".into(),
+            "guide/usage.md".into(),
+        ];
+        assert_eq!(paths, should_be);
+    }
+
+    #[test]
+    fn links_to_the_renamed_chapters_point_at_their_index() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let mut guide = chapter("guide/usage.md");
+        guide.content = String::from(
+            "[Guide](README.md#setup) [Intro](../README.md) [Repo](../../README.md)\n\
+             [Not renamed](../api/README.md) [Web](https://example.com/README.md)\n",
+        );
+        let mut book = Book::new();
+        book.push_item(chapter("README.md"));
+        book.push_item(chapter("guide/README.md"));
+        book.push_item(guide);
+
+        let book = run(book, temp.path()).unwrap();
+
+        let content = book
+            .iter()
+            .find_map(|item| match *item {
+                BookItem::Chapter(ref ch) if ch.name == "guide/usage.md" => Some(&ch.content),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            content,
+            "[Guide](index.md#setup) [Intro](../index.md) [Repo](../../README.md)\n\
+             [Not renamed](../api/README.md) [Web](https://example.com/README.md)\n"
+        );
+    }
+
+    #[test]
+    fn readme_and_index_in_the_same_directory_is_an_error() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let mut book = Book::new();
+        book.push_item(chapter("guide/README.md"));
+        book.push_item(chapter("guide/index.md"));
+
+        let err = run(book, temp.path()).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Both guide/README.md and guide/index.md"));
+
+        // An index.md which isn't in the summary isn't rendered
+        crate::utils::fs::write_file(&temp.path().join("src"), "index.md", b"").unwrap();
+        let mut book = Book::new();
+        book.push_item(chapter("README.md"));
+        assert!(run(book, temp.path()).is_ok());
+    }
 
     #[test]
     fn file_stem_exactly_matches_readme_case_insensitively() {
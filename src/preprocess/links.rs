@@ -1,8 +1,9 @@
 use crate::errors::*;
 use crate::utils::{
-    take_anchored_lines, take_lines, take_rustdoc_include_anchored_lines,
+    has_anchor, take_anchored_lines, take_lines, take_rustdoc_include_anchored_lines,
     take_rustdoc_include_lines,
 };
+use pulldown_cmark::{Event, Parser, Tag};
 use regex::{CaptureMatches, Captures, Regex};
 use std::fs;
 use std::ops::{Bound, Range, RangeBounds, RangeFrom, RangeFull, RangeTo};
@@ -17,12 +18,16 @@ const MAX_LINK_NESTED_DEPTH: usize = 10;
 /// A preprocessor for expanding helpers in a chapter. Supported helpers are:
 ///
 /// - `{{# include}}` - Insert an external file of any type. Include the whole file, only particular
-///.  lines, or only between the specified anchors.
+///   lines, or only between the specified anchors.
 /// - `{{# rustdoc_include}}` - Insert an external Rust file, showing the particular lines
-///.  specified or the lines between specified anchors, and include the rest of the file behind `#`.
+///   specified or the lines between specified anchors, and include the rest of the file behind `#`.
 ///   This hides the lines from initial display but shows them when the reader expands the code
 ///   block and provides them to Rustdoc for testing.
 /// - `{{# playpen}}` - Insert runnable Rust files
+/// - `{{# title}}` - Replace the title of the chapter's page, like the `title`
+///   of its frontmatter does
+/// - `{{# only}}` - Keep the content up to the next `{{#endonly}}` only for the
+///   renderers it names, like `{{#only html epub}}`
 #[derive(Default)]
 pub struct LinkPreprocessor;
 
@@ -43,7 +48,7 @@ impl Preprocessor for LinkPreprocessor {
     fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
         let src_dir = ctx.root.join(&ctx.config.book.src);
 
-        book.for_each_mut(|section: &mut BookItem| {
+        book.try_for_each_mut(|section: &mut BookItem| {
             if let BookItem::Chapter(ref mut ch) = *section {
                 if let Some(ref chapter_path) = ch.path {
                     let base = chapter_path
@@ -51,17 +56,45 @@ impl Preprocessor for LinkPreprocessor {
                         .map(|dir| src_dir.join(dir))
                         .expect("All book items have a parent");
 
-                    let content = replace_all(&ch.content, base, chapter_path, 0);
-                    ch.content = content;
+                    let source = ch.source_path.as_ref().unwrap_or(chapter_path);
+                    ch.content = keep_renderer_blocks(&ch.content, &ctx.renderer, source)?;
+
+                    let titles = find_titles(&ch.content);
+                    if titles.len() > 1 {
+                        bail!(
+                            "The chapter \"{}\" has {} `{{{{#title}}}}`s, it can only have one",
+                            ch.name,
+                            titles.len()
+                        );
+                    }
+                    if let Some((range, title)) = titles.into_iter().next() {
+                        ch.metadata.insert(String::from("page-title"), json!(title));
+                        ch.content.replace_range(range, "");
+                    }
+
+                    ch.content = ctx.timings.time_chapter(source, "preprocess", || {
+                        replace_all(&ch.content, base, chapter_path, &ctx.renderer, &[])
+                    })?;
                 }
             }
-        });
+            Ok(())
+        })?;
 
         Ok(book)
     }
 }
 
-fn replace_all<P1, P2>(s: &str, path: P1, source: P2, depth: usize) -> String
+/// Expand the links of `s`, and of the files they include. The `{{#only ...}}`
+/// blocks of the included files which aren't for the `renderer` are removed
+/// before their own links are expanded, like the chapter's. `chain` is the
+/// files included on the way from the chapter to `s`.
+fn replace_all<P1, P2>(
+    s: &str,
+    path: P1,
+    source: P2,
+    renderer: &str,
+    chain: &[PathBuf],
+) -> Result<String>
 where
     P1: AsRef<Path>,
     P2: AsRef<Path>,
@@ -77,44 +110,210 @@ where
     for link in find_links(s) {
         replaced.push_str(&s[previous_end_index..link.start_index]);
 
-        match link.render_with_path(&path) {
-            Ok(new_content) => {
-                if depth < MAX_LINK_NESTED_DEPTH {
-                    if let Some(rel_path) = link.link_type.relative_path(path) {
-                        replaced.push_str(&replace_all(&new_content, rel_path, source, depth + 1));
-                    } else {
-                        replaced.push_str(&new_content);
-                    }
-                } else {
-                    error!(
-                        "Stack depth exceeded in {}. Check for cyclic includes",
-                        source.display()
+        let new_content = link.render_with_path(path).with_context(|| {
+            format!(
+                "Unable to expand \"{}\" in {}",
+                link.link_text,
+                source.display()
+            )
+        })?;
+
+        let included = match link.link_type {
+            LinkType::Include(ref p, _)
+            | LinkType::RustdocInclude(ref p, _)
+            | LinkType::Playpen(ref p, ..) => Some(path.join(p)),
+            LinkType::Escaped => None,
+        };
+        if let (Some(rel_path), Some(file)) = (link.link_type.relative_path(path), included) {
+            let mut chain = chain.to_vec();
+            chain.push(file);
+            if chain.len() > MAX_LINK_NESTED_DEPTH {
+                let files: Vec<String> = chain
+                    .iter()
+                    .map(|file| file.display().to_string())
+                    .collect();
+                bail!(
+                    "More than {} nested includes in {}, check for cyclic includes: {}",
+                    MAX_LINK_NESTED_DEPTH,
+                    source.display(),
+                    files.join(" -> ")
+                );
+            }
+            let file = &chain[chain.len() - 1];
+            let new_content = keep_renderer_blocks(&new_content, renderer, file)?;
+            replaced.push_str(&replace_all(
+                &new_content,
+                rel_path,
+                source,
+                renderer,
+                &chain,
+            )?);
+        } else {
+            replaced.push_str(&new_content);
+        }
+        previous_end_index = link.end_index;
+    }
+
+    replaced.push_str(&s[previous_end_index..]);
+    Ok(replaced)
+}
+
+/// The ranges of `content` which are code, where the helpers are left alone.
+fn code_ranges(content: &str) -> Vec<Range<usize>> {
+    Parser::new(content)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Code(_) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Remove the `{{#only ...}}` blocks of the chapters of `book` which aren't for
+/// the `renderer`, for the books built without the links preprocessor.
+pub(crate) fn keep_renderer_blocks_of(book: &mut Book, renderer: &str) -> Result<()> {
+    book.try_for_each_mut(|section: &mut BookItem| {
+        if let BookItem::Chapter(ref mut ch) = *section {
+            if let Some(source) = ch.source_path.as_ref().or(ch.path.as_ref()) {
+                ch.content = keep_renderer_blocks(&ch.content, renderer, source)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Remove the `{{#only ...}}` blocks of `content` which aren't for the
+/// `renderer`, and the markers of the ones which are. `source` is the chapter's
+/// source file, for the errors.
+fn keep_renderer_blocks(content: &str, renderer: &str, source: &Path) -> Result<String> {
+    lazy_static! {
+        static ref MARKER: Regex =
+            Regex::new(r"\\?\{\{\s*#(only|endonly)\b\s*([^}]*?)\s*\}\}\n?").unwrap();
+    }
+
+    if !content.contains("#only") && !content.contains("#endonly") {
+        return Ok(content.to_string());
+    }
+    let code = code_ranges(content);
+    let line_of = |index: usize| content[..index].matches('\n').count() + 1;
+
+    let mut kept = String::new();
+    let mut previous_end_index = 0;
+    // the line of the open block, and whether it's kept
+    let mut open: Option<(usize, bool)> = None;
+
+    for cap in MARKER.captures_iter(content) {
+        let mat = cap.get(0).unwrap();
+        let in_code = code
+            .iter()
+            .any(|range| range.start <= mat.start() && mat.end() <= range.end);
+        if mat.as_str().starts_with(ESCAPE_CHAR) || in_code {
+            continue;
+        }
+        let line = line_of(mat.start());
+
+        match (&cap[1], open) {
+            ("only", Some((open_line, _))) => bail!(
+                "{}:{}: `{{{{#only}}}}` blocks can't be nested, the one of line {} isn't closed",
+                source.display(),
+                line,
+                open_line
+            ),
+            ("only", None) => {
+                let renderers: Vec<&str> = cap[2].split_whitespace().collect();
+                if renderers.is_empty() {
+                    bail!(
+                        "{}:{}: `{{{{#only}}}}` needs the names of the renderers, like `{{{{#only html}}}}`",
+                        source.display(),
+                        line
                     );
                 }
-                previous_end_index = link.end_index;
+                kept.push_str(&content[previous_end_index..mat.start()]);
+                previous_end_index = mat.end();
+                open = Some((line, renderers.contains(&renderer)));
             }
-            Err(e) => {
-                error!("Error updating \"{}\", {}", link.link_text, e);
-                for cause in e.chain().skip(1) {
-                    warn!("Caused By: {}", cause);
+            (_, Some((_, keep))) => {
+                if keep {
+                    kept.push_str(&content[previous_end_index..mat.start()]);
                 }
-
-                // This should make sure we include the raw `{{# ... }}` snippet
-                // in the page content if there are any errors.
-                previous_end_index = link.start_index;
+                previous_end_index = mat.end();
+                open = None;
             }
+            (_, None) => bail!(
+                "{}:{}: `{{{{#endonly}}}}` without an `{{{{#only ...}}}}` before it",
+                source.display(),
+                line
+            ),
         }
     }
 
-    replaced.push_str(&s[previous_end_index..]);
-    replaced
+    if let Some((line, _)) = open {
+        bail!(
+            "{}:{}: the `{{{{#only}}}}` block isn't closed by an `{{{{#endonly}}}}`",
+            source.display(),
+            line
+        );
+    }
+    kept.push_str(&content[previous_end_index..]);
+    Ok(kept)
+}
+
+/// The `{{#title ...}}`s of a chapter and where they are. The ones in code and
+/// the escaped ones (`\{{#title ...}}`) are left alone.
+fn find_titles(content: &str) -> Vec<(Range<usize>, String)> {
+    lazy_static! {
+        static ref TITLE: Regex = Regex::new(r"\\?\{\{\s*#title\s+([^}]+?)\s*\}\}").unwrap();
+    }
+
+    let code = code_ranges(content);
+
+    TITLE
+        .captures_iter(content)
+        .filter_map(|cap| {
+            let mat = cap.get(0).unwrap();
+            let in_code = code
+                .iter()
+                .any(|range| range.start <= mat.start() && mat.end() <= range.end);
+            if mat.as_str().starts_with(ESCAPE_CHAR) || in_code {
+                None
+            } else {
+                Some((mat.range(), cap[1].to_string()))
+            }
+        })
+        .collect()
+}
+
+/// Every file the links in `content` include, including the files included by
+/// those files. `base` is the directory the links are relative to.
+pub(crate) fn included_files(content: &str, base: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    collect_included_files(content, base, 0, &mut files);
+    files
+}
+
+fn collect_included_files(content: &str, base: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    for link in find_links(content) {
+        let file = match link.link_type {
+            LinkType::Escaped => continue,
+            LinkType::Include(p, _) | LinkType::RustdocInclude(p, _) | LinkType::Playpen(p, ..) => {
+                base.join(p)
+            }
+        };
+
+        if depth < MAX_LINK_NESTED_DEPTH {
+            if let (Ok(nested), Some(dir)) = (fs::read_to_string(&file), file.parent()) {
+                collect_included_files(&nested, dir, depth + 1, files);
+            }
+        }
+        files.push(file);
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
 enum LinkType<'a> {
     Escaped,
     Include(PathBuf, RangeOrAnchor),
-    Playpen(PathBuf, Vec<&'a str>),
+    Playpen(PathBuf, RangeOrAnchor, Vec<&'a str>),
     RustdocInclude(PathBuf, RangeOrAnchor),
 }
 
@@ -124,6 +323,42 @@ enum RangeOrAnchor {
     Anchor(String),
 }
 
+impl RangeOrAnchor {
+    /// Make sure the lines to include are actually in the `file` which was
+    /// read into `contents`.
+    fn check(&self, contents: &str, file: &Path) -> Result<()> {
+        match self {
+            RangeOrAnchor::Range(range) => {
+                let line_count = contents.lines().count();
+                let past_the_end = match range.start_bound() {
+                    Bound::Included(&start) if start >= line_count => Some(start + 1),
+                    _ => None,
+                }
+                .or_else(|| match range.end_bound() {
+                    Bound::Excluded(&end) if end > line_count => Some(end),
+                    _ => None,
+                });
+
+                if let Some(line) = past_the_end {
+                    bail!(
+                        "Line {} is past the end of {}, which has {} lines",
+                        line,
+                        file.display(),
+                        line_count
+                    );
+                }
+            }
+            RangeOrAnchor::Anchor(anchor) => {
+                if !has_anchor(contents, anchor) {
+                    bail!("Anchor `{}` not found in {}", anchor, file.display());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
 // A range of lines specified with some include directive.
 #[derive(PartialEq, Debug, Clone)]
 enum LineRange {
@@ -183,7 +418,7 @@ impl<'a> LinkType<'a> {
         match self {
             LinkType::Escaped => None,
             LinkType::Include(p, _) => Some(return_relative_path(base, &p)),
-            LinkType::Playpen(p, _) => Some(return_relative_path(base, &p)),
+            LinkType::Playpen(p, _, _) => Some(return_relative_path(base, &p)),
             LinkType::RustdocInclude(p, _) => Some(return_relative_path(base, &p)),
         }
     }
@@ -244,6 +479,15 @@ fn parse_rustdoc_include_path(path: &str) -> LinkType<'static> {
     LinkType::RustdocInclude(path, range_or_anchor)
 }
 
+fn parse_playpen_path<'a>(path: &str, props: Vec<&'a str>) -> LinkType<'a> {
+    let mut parts = path.splitn(2, ':');
+
+    let path = parts.next().unwrap().into();
+    let range_or_anchor = parse_range_or_anchor(parts.next());
+
+    LinkType::Playpen(path, range_or_anchor, props)
+}
+
 #[derive(PartialEq, Debug, Clone)]
 struct Link<'a> {
     start_index: usize,
@@ -262,7 +506,7 @@ impl<'a> Link<'a> {
 
                 match (typ.as_str(), file_arg) {
                     ("include", Some(pth)) => Some(parse_include_path(pth)),
-                    ("playpen", Some(pth)) => Some(LinkType::Playpen(pth.into(), props)),
+                    ("playpen", Some(pth)) => Some(parse_playpen_path(pth, props)),
                     ("rustdoc_include", Some(pth)) => Some(parse_rustdoc_include_path(pth)),
                     _ => None,
                 }
@@ -287,53 +531,59 @@ impl<'a> Link<'a> {
         let base = base.as_ref();
         match self.link_type {
             // omit the escape char
-            LinkType::Escaped => Ok((&self.link_text[1..]).to_owned()),
+            LinkType::Escaped => Ok(self.link_text[1..].to_owned()),
             LinkType::Include(ref pat, ref range_or_anchor) => {
                 let target = base.join(pat);
 
-                fs::read_to_string(&target)
-                    .map(|s| match range_or_anchor {
-                        RangeOrAnchor::Range(range) => take_lines(&s, range.clone()),
-                        RangeOrAnchor::Anchor(anchor) => take_anchored_lines(&s, anchor),
-                    })
-                    .with_context(|| {
-                        format!(
-                            "Could not read file for link {} ({})",
-                            self.link_text,
-                            target.display(),
-                        )
-                    })
+                let s = fs::read_to_string(&target).with_context(|| {
+                    format!(
+                        "Could not read file for link {} ({})",
+                        self.link_text,
+                        target.display(),
+                    )
+                })?;
+                range_or_anchor.check(&s, &target)?;
+
+                Ok(match range_or_anchor {
+                    RangeOrAnchor::Range(range) => take_lines(&s, range.clone()),
+                    RangeOrAnchor::Anchor(anchor) => take_anchored_lines(&s, anchor),
+                })
             }
             LinkType::RustdocInclude(ref pat, ref range_or_anchor) => {
                 let target = base.join(pat);
 
-                fs::read_to_string(&target)
-                    .map(|s| match range_or_anchor {
-                        RangeOrAnchor::Range(range) => {
-                            take_rustdoc_include_lines(&s, range.clone())
-                        }
-                        RangeOrAnchor::Anchor(anchor) => {
-                            take_rustdoc_include_anchored_lines(&s, anchor)
-                        }
-                    })
-                    .with_context(|| {
-                        format!(
-                            "Could not read file for link {} ({})",
-                            self.link_text,
-                            target.display(),
-                        )
-                    })
+                let s = fs::read_to_string(&target).with_context(|| {
+                    format!(
+                        "Could not read file for link {} ({})",
+                        self.link_text,
+                        target.display(),
+                    )
+                })?;
+                range_or_anchor.check(&s, &target)?;
+
+                Ok(match range_or_anchor {
+                    RangeOrAnchor::Range(range) => take_rustdoc_include_lines(&s, range.clone()),
+                    RangeOrAnchor::Anchor(anchor) => {
+                        take_rustdoc_include_anchored_lines(&s, anchor)
+                    }
+                })
             }
-            LinkType::Playpen(ref pat, ref attrs) => {
+            LinkType::Playpen(ref pat, ref range_or_anchor, ref attrs) => {
                 let target = base.join(pat);
 
-                let contents = fs::read_to_string(&target).with_context(|| {
+                let s = fs::read_to_string(&target).with_context(|| {
                     format!(
                         "Could not read file for link {} ({})",
                         self.link_text,
                         target.display()
                     )
                 })?;
+                range_or_anchor.check(&s, &target)?;
+
+                let contents = match range_or_anchor {
+                    RangeOrAnchor::Range(range) => take_lines(&s, range.clone()),
+                    RangeOrAnchor::Anchor(anchor) => take_anchored_lines(&s, anchor),
+                };
                 let ftype = if !attrs.is_empty() { "rust," } else { "rust" };
                 Ok(format!(
                     "```{}{}\n{}\n```\n",
@@ -383,6 +633,68 @@ fn find_links(contents: &str) -> LinkIter<'_> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn only_the_blocks_of_the_renderer_are_kept() {
+        let content = "# Chapter\n\
+                       {{#only html}}\n\
+                       An interactive figure.\n\
+                       {{#endonly}}\n\
+                       {{#only epub markdown}}\n\
+                       A static figure.\n\
+                       {{#endonly}}\n\
+                       ```\n\
+                       {{#only html}}\n\
+                       ```\n\
+                       `{{#endonly}}` and \\{{#only html}} are left alone.\n";
+        let source = Path::new("chapter.md");
+
+        assert_eq!(
+            keep_renderer_blocks(content, "html", source).unwrap(),
+            "# Chapter\n\
+             An interactive figure.\n\
+             ```\n\
+             {{#only html}}\n\
+             ```\n\
+             `{{#endonly}}` and \\{{#only html}} are left alone.\n"
+        );
+        assert_eq!(
+            keep_renderer_blocks(content, "markdown", source).unwrap(),
+            "# Chapter\n\
+             A static figure.\n\
+             ```\n\
+             {{#only html}}\n\
+             ```\n\
+             `{{#endonly}}` and \\{{#only html}} are left alone.\n"
+        );
+    }
+
+    #[test]
+    fn misplaced_only_markers_are_errors() {
+        let source = Path::new("chapter.md");
+        let error = |content: &str| {
+            keep_renderer_blocks(content, "html", source)
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(
+            error("{{#only html}}\n\n{{#only epub}}\n{{#endonly}}\n{{#endonly}}\n"),
+            "chapter.md:3: `{{#only}}` blocks can't be nested, the one of line 1 isn't closed"
+        );
+        assert_eq!(
+            error("Text\n{{#only html}}\nMore text\n"),
+            "chapter.md:2: the `{{#only}}` block isn't closed by an `{{#endonly}}`"
+        );
+        assert_eq!(
+            error("{{#endonly}}\n"),
+            "chapter.md:1: `{{#endonly}}` without an `{{#only ...}}` before it"
+        );
+        assert_eq!(
+            error("{{#only }}\n{{#endonly}}\n"),
+            "chapter.md:1: `{{#only}}` needs the names of the renderers, like `{{#only html}}`"
+        );
+    }
+
     #[test]
     fn test_replace_all_escaped() {
         let start = r"
@@ -395,7 +707,7 @@ mod tests {
         ```hbs
         {{#include file.rs}} << an escaped link!
         ```";
-        assert_eq!(replace_all(start, "", "", 0), end);
+        assert_eq!(replace_all(start, "", "", "html", &[]).unwrap(), end);
     }
 
     #[test]
@@ -439,13 +751,21 @@ mod tests {
                 Link {
                     start_index: 22,
                     end_index: 42,
-                    link_type: LinkType::Playpen(PathBuf::from("file.rs"), vec![]),
+                    link_type: LinkType::Playpen(
+                        PathBuf::from("file.rs"),
+                        RangeOrAnchor::Range(LineRange::from(..)),
+                        vec![]
+                    ),
                     link_text: "{{#playpen file.rs}}",
                 },
                 Link {
                     start_index: 47,
                     end_index: 68,
-                    link_type: LinkType::Playpen(PathBuf::from("test.rs"), vec![]),
+                    link_type: LinkType::Playpen(
+                        PathBuf::from("test.rs"),
+                        RangeOrAnchor::Range(LineRange::from(..)),
+                        vec![]
+                    ),
                     link_text: "{{#playpen test.rs }}",
                 },
             ]
@@ -464,7 +784,11 @@ mod tests {
             vec![Link {
                 start_index: 22,
                 end_index: 54,
-                link_type: LinkType::Playpen(PathBuf::from("foo-bar\\baz/_c++.rs"), vec![]),
+                link_type: LinkType::Playpen(
+                    PathBuf::from("foo-bar\\baz/_c++.rs"),
+                    RangeOrAnchor::Range(LineRange::from(..)),
+                    vec![]
+                ),
                 link_text: "{{#playpen foo-bar\\baz/_c++.rs}}",
             },]
         );
@@ -634,7 +958,11 @@ mod tests {
                 Link {
                     start_index: 38,
                     end_index: 68,
-                    link_type: LinkType::Playpen(PathBuf::from("file.rs"), vec!["editable"]),
+                    link_type: LinkType::Playpen(
+                        PathBuf::from("file.rs"),
+                        RangeOrAnchor::Range(LineRange::from(..)),
+                        vec!["editable"]
+                    ),
                     link_text: "{{#playpen file.rs editable }}",
                 },
                 Link {
@@ -642,6 +970,7 @@ mod tests {
                     end_index: 136,
                     link_type: LinkType::Playpen(
                         PathBuf::from("my.rs"),
+                        RangeOrAnchor::Range(LineRange::from(..)),
                         vec!["editable", "no_run", "should_panic"],
                     ),
                     link_text: "{{#playpen my.rs editable no_run should_panic}}",
@@ -687,6 +1016,7 @@ mod tests {
                 end_index: 177,
                 link_type: LinkType::Playpen(
                     PathBuf::from("my.rs"),
+                    RangeOrAnchor::Range(LineRange::from(..)),
                     vec!["editable", "no_run", "should_panic"]
                 ),
                 link_text: "{{#playpen my.rs editable no_run should_panic}}",
@@ -873,4 +1203,198 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn include_a_rust_file_in_a_code_block() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(temp.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+        let chapter = "Example:\n\n```rust\n{{#include main.rs}}\n```\n";
+        let got = replace_all(chapter, temp.path(), "chapter.md", "html", &[]).unwrap();
+
+        assert_eq!(got, "Example:\n\n```rust\nfn main() {}\n```\n");
+    }
+
+    #[test]
+    fn included_markdown_can_include_other_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let snippets = temp.path().join("snippets");
+        fs::create_dir(&snippets).unwrap();
+        fs::write(
+            snippets.join("warning.md"),
+            "> **Warning:** {{#include message.txt}}",
+        )
+        .unwrap();
+        fs::write(snippets.join("message.txt"), "here be dragons").unwrap();
+
+        let chapter = "# Chapter\n\n{{#include snippets/warning.md}}\n";
+        let got = replace_all(chapter, temp.path(), "chapter.md", "html", &[]).unwrap();
+
+        assert_eq!(got, "# Chapter\n\n> **Warning:** here be dragons\n");
+    }
+
+    #[test]
+    fn the_only_blocks_of_included_files_are_kept_for_their_renderers() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(
+            temp.path().join("demo.md"),
+            "{{#only html}}\nThe demo.\n{{#endonly}}\n{{#only epub}}\n{{#include missing.png}}\n{{#endonly}}\n",
+        )
+        .unwrap();
+
+        let chapter = "# Chapter\n\n{{#include demo.md}}\n";
+        let got = replace_all(chapter, temp.path(), "chapter.md", "html", &[]).unwrap();
+
+        assert_eq!(got, "# Chapter\n\nThe demo.\n\n");
+    }
+
+    #[test]
+    fn missing_include_is_an_error() {
+        let temp = tempfile::tempdir().unwrap();
+
+        let chapter = "Some text\n{{#include missing.rs}}\n";
+        let err = replace_all(chapter, temp.path(), "guide/chapter.md", "html", &[]).unwrap_err();
+
+        let message = err.to_string();
+        assert!(message.contains("{{#include missing.rs}}"), "{}", message);
+        assert!(message.contains("guide/chapter.md"), "{}", message);
+    }
+
+    #[test]
+    fn cyclic_includes_are_an_error_naming_the_includes() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(temp.path().join("a.md"), "A\n{{#include b.md}}\n").unwrap();
+        fs::write(temp.path().join("b.md"), "B\n{{#include a.md}}\n").unwrap();
+
+        let chapter = "{{#include a.md}}\n";
+        let err = replace_all(chapter, temp.path(), "chapter.md", "html", &[]).unwrap_err();
+
+        let message = err.to_string();
+        let (a, b) = (temp.path().join("a.md"), temp.path().join("b.md"));
+        let chain = format!("{} -> {} -> {}", a.display(), b.display(), a.display());
+        assert!(
+            message.contains("nested includes in chapter.md"),
+            "{}",
+            message
+        );
+        assert!(message.contains(&chain), "{}", message);
+    }
+
+    const NUMBERED_LINES: &str = "line 1\nline 2\nline 3\nline 4\nline 5\n";
+
+    const NESTED_ANCHORS: &str = "\
+// ANCHOR: all
+use std::fmt;
+// ANCHOR: body
+fn body() {}
+// ANCHOR_END: body
+// ANCHOR_END: all
+";
+
+    fn include(directive: &str) -> Result<String> {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(temp.path().join("lines.txt"), NUMBERED_LINES).unwrap();
+        fs::write(temp.path().join("anchors.rs"), NESTED_ANCHORS).unwrap();
+
+        replace_all(directive, temp.path(), "chapter.md", "html", &[])
+    }
+
+    #[test]
+    fn include_line_range_counts_like_an_editor() {
+        assert_eq!(
+            include("{{#include lines.txt:2:4}}").unwrap(),
+            "line 2\nline 3\nline 4"
+        );
+        assert_eq!(include("{{#include lines.txt:3}}").unwrap(), "line 3");
+        assert_eq!(
+            include("{{#include lines.txt::2}}").unwrap(),
+            "line 1\nline 2"
+        );
+        assert_eq!(
+            include("{{#include lines.txt:4:}}").unwrap(),
+            "line 4\nline 5"
+        );
+        assert_eq!(include("{{#include lines.txt:5:5}}").unwrap(), "line 5");
+    }
+
+    #[test]
+    fn include_nested_anchors() {
+        assert_eq!(
+            include("{{#include anchors.rs:all}}").unwrap(),
+            "use std::fmt;\nfn body() {}"
+        );
+        assert_eq!(
+            include("{{#include anchors.rs:body}}").unwrap(),
+            "fn body() {}"
+        );
+    }
+
+    #[test]
+    fn include_past_the_end_of_the_file_is_an_error() {
+        for directive in &[
+            "{{#include lines.txt:6}}",
+            "{{#include lines.txt:6:}}",
+            "{{#include lines.txt::6}}",
+            "{{#include lines.txt:2:10}}",
+        ] {
+            let err = include(directive).unwrap_err();
+            let message = format!("{:?}", err);
+            assert!(message.contains("chapter.md"), "{}", message);
+            assert!(message.contains("is past the end of"), "{}", message);
+            assert!(message.contains("lines.txt"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn include_unknown_anchor_is_an_error() {
+        let err = include("{{#rustdoc_include anchors.rs:missing}}").unwrap_err();
+
+        let message = format!("{:?}", err);
+        assert!(message.contains("chapter.md"), "{}", message);
+        assert!(
+            message.contains("Anchor `missing` not found in"),
+            "{}",
+            message
+        );
+        assert!(message.contains("anchors.rs"), "{}", message);
+    }
+
+    #[test]
+    fn playpen_with_anchor_and_properties() {
+        let got = include("{{#playpen anchors.rs:body editable}}").unwrap();
+        assert_eq!(got, "```rust,editable\nfn body() {}\n```\n");
+
+        let got = include("{{#playpen lines.txt:2:3}}").unwrap();
+        assert_eq!(got, "```rust\nline 2\nline 3\n```\n");
+    }
+
+    #[test]
+    fn find_the_title_of_a_chapter() {
+        let content = "{{#title Frequently Asked Questions}}\n# FAQ\n";
+        let titles = find_titles(content);
+        assert_eq!(
+            titles,
+            vec![(0..37, String::from("Frequently Asked Questions"))]
+        );
+
+        assert_eq!(
+            find_titles("# FAQ\n\n{{ #title  Spaced Out }}"),
+            vec![(7..31, String::from("Spaced Out"))]
+        );
+        assert_eq!(find_titles("# FAQ\n\n{{#title}}\n").len(), 0);
+    }
+
+    #[test]
+    fn titles_in_code_and_escaped_titles_are_ignored() {
+        let content = "# FAQ\n\n\
+                       ```hbs\n{{#title In A Block}}\n```\n\n    {{#title Indented}}\n\n\
+                       Inline `{{#title Inline}}` and \\{{#title Escaped}}\n\n\
+                       {{#title Real}}\n";
+
+        let titles = find_titles(content);
+
+        assert_eq!(titles.len(), 1);
+        assert_eq!(titles[0].1, "Real");
+        assert_eq!(&content[titles[0].0.clone()], "{{#title Real}}");
+    }
 }
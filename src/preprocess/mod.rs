@@ -4,6 +4,8 @@ pub use self::cmd::CmdPreprocessor;
 pub use self::index::IndexPreprocessor;
 pub use self::links::LinkPreprocessor;
 
+pub(crate) use self::links::{included_files, keep_renderer_blocks_of};
+
 mod cmd;
 mod index;
 mod links;
@@ -11,6 +13,7 @@ mod links;
 use crate::book::Book;
 use crate::config::Config;
 use crate::errors::*;
+use crate::timings::Timings;
 
 use std::path::PathBuf;
 
@@ -26,6 +29,10 @@ pub struct PreprocessorContext {
     pub renderer: String,
     /// The calling `mdbook` version.
     pub mdbook_version: String,
+    /// Where the preprocessor measures its work on each chapter, with
+    /// `build.timings`. It's disabled otherwise.
+    #[serde(skip)]
+    pub timings: Timings,
     #[serde(skip)]
     __non_exhaustive: (),
 }
@@ -38,6 +45,7 @@ impl PreprocessorContext {
             config,
             renderer,
             mdbook_version: crate::MDBOOK_VERSION.to_string(),
+            timings: Timings::disabled(),
             __non_exhaustive: (),
         }
     }
@@ -0,0 +1,582 @@
+//! The `book.zip` or `book.tar.gz` beside the rendered book. The same book gives
+//! the same archive: the files are in the order of their paths, and they all
+//! have the same permissions and modification time.
+//!
+//! The archive is written as the files are read, one at a time, so a big book
+//! doesn't have to fit in memory.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Datelike, NaiveDateTime, Timelike, Utc};
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, GzBuilder};
+
+use super::build_info;
+use super::cache::CACHE_FILE;
+use crate::config::ArchiveFormat;
+use crate::errors::*;
+
+/// The modification time of the files when there's no `SOURCE_DATE_EPOCH`:
+/// 1980-01-01, the earliest a zip archive can store.
+const DEFAULT_TIME: i64 = 315_532_800;
+
+/// The files of the destination directory which aren't archived, the ones
+/// changing from a build to the next.
+const EXCLUDED_FILES: &[&str] = &[CACHE_FILE, "timings.json"];
+
+/// A file or directory of the archive.
+#[derive(Debug, PartialEq)]
+struct Entry {
+    /// The path in the archive, with slashes, and ending with one for the
+    /// directories.
+    name: String,
+    /// The file to archive, `None` for the directories.
+    file: Option<PathBuf>,
+}
+
+/// Archive the `destination` directory beside it, and return the path of the
+/// archive.
+pub(super) fn write_archive(destination: &Path, format: ArchiveFormat) -> Result<PathBuf> {
+    let archive_path = format.archive_path(destination);
+    let mut entries = Vec::new();
+    let mut visited = HashSet::new();
+    collect_entries(destination, "", &mut visited, &mut entries)?;
+
+    let file = File::create(&archive_path)
+        .with_context(|| format!("Unable to create {}", archive_path.display()))?;
+    let mut writer = BufWriter::new(file);
+    let time = archive_time();
+    match format {
+        ArchiveFormat::Zip => zip(&entries, time, &mut writer),
+        ArchiveFormat::TarGz => tar_gz(&entries, time, &mut writer),
+    }
+    .and_then(|_| Ok(writer.flush()?))
+    .with_context(|| format!("Unable to write {}", archive_path.display()))?;
+    Ok(archive_path)
+}
+
+/// The `SOURCE_DATE_EPOCH` when it's set, so the archive is the one of the
+/// other reproducible builds, else a fixed time.
+fn archive_time() -> i64 {
+    env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| build_info::source_date_epoch(&epoch))
+        .map(|time| time.timestamp())
+        .unwrap_or(DEFAULT_TIME)
+}
+
+/// Add the files of `dir` to `entries`, sorted by their paths. The symlinks
+/// are archived as the files they point to, and the directories already
+/// `visited` (through a symlink to one of their parents) are left out.
+fn collect_entries(
+    dir: &Path,
+    prefix: &str,
+    visited: &mut HashSet<PathBuf>,
+    entries: &mut Vec<Entry>,
+) -> Result<()> {
+    let canonical = dir
+        .canonicalize()
+        .with_context(|| format!("Unable to read {}", dir.display()))?;
+    if !visited.insert(canonical) {
+        return Ok(());
+    }
+    let mut paths = fs::read_dir(dir)
+        .with_context(|| format!("Unable to read {}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.sort();
+
+    for path in paths {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        if prefix.is_empty() && EXCLUDED_FILES.contains(&&*file_name) {
+            continue;
+        }
+        let name = format!("{}{}", prefix, file_name);
+        let metadata =
+            fs::metadata(&path).with_context(|| format!("Unable to archive {}", path.display()))?;
+
+        if metadata.is_dir() {
+            let canonical = path
+                .canonicalize()
+                .with_context(|| format!("Unable to read {}", path.display()))?;
+            if visited.contains(&canonical) {
+                continue;
+            }
+            let name = format!("{}/", name);
+            entries.push(Entry {
+                name: name.clone(),
+                file: None,
+            });
+            collect_entries(&path, &name, visited, entries)?;
+        } else {
+            entries.push(Entry {
+                name,
+                file: Some(path),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The size of a file, which has to be the one it had in the header of the
+/// archive once it's written.
+fn file_size(path: &Path) -> Result<u64> {
+    Ok(fs::metadata(path)
+        .with_context(|| format!("Unable to read {}", path.display()))?
+        .len())
+}
+
+fn tar_gz<W: Write>(entries: &[Entry], time: i64, writer: W) -> Result<()> {
+    let mut encoder = GzBuilder::new()
+        .mtime(time.max(0) as u32)
+        .write(writer, Compression::best());
+    for entry in entries {
+        let size = match entry.file {
+            Some(ref path) => file_size(path)?,
+            None => 0,
+        };
+        encoder.write_all(&tar_header(entry, size, time)?)?;
+        if let Some(ref path) = entry.file {
+            let mut file =
+                File::open(path).with_context(|| format!("Unable to read {}", path.display()))?;
+            let copied = io::copy(&mut file, &mut encoder)?;
+            ensure!(
+                copied == size,
+                "{} changed while it was archived",
+                path.display()
+            );
+            let padding = (512 - size % 512) % 512;
+            encoder.write_all(&vec![0; padding as usize])?;
+        }
+    }
+    // The end of the archive
+    encoder.write_all(&[0; 1024])?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// The ustar header of an entry of `size` bytes.
+fn tar_header(entry: &Entry, size: u64, time: i64) -> Result<[u8; 512]> {
+    let mut header = [0; 512];
+    let (prefix, name) = split_tar_name(&entry.name)?;
+    let (mode, kind) = match entry.file {
+        Some(_) => (0o644, b'0'),
+        None => (0o755, b'5'),
+    };
+    ensure!(
+        size < 1 << 33,
+        "{} is too big for a tar archive",
+        entry.name
+    );
+
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    write_octal(&mut header[100..108], mode);
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_octal(&mut header[124..136], size);
+    write_octal(&mut header[136..148], time.max(0) as u64);
+    header[156] = kind;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+    // The checksum is computed with its own field filled with spaces
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    Ok(header)
+}
+
+/// Split a path into the prefix and name fields of a ustar header, which
+/// hold 155 and 100 bytes.
+fn split_tar_name(path: &str) -> Result<(&str, &str)> {
+    if path.len() <= 100 {
+        return Ok(("", path));
+    }
+    let trimmed = path.trim_end_matches('/');
+    let split = trimmed
+        .char_indices()
+        .filter(|&(i, c)| c == '/' && i <= 155 && path.len() - i - 1 <= 100)
+        .map(|(i, _)| i)
+        .next();
+    match split {
+        Some(i) => Ok((&path[..i], &path[i + 1..])),
+        None => bail!("The path {} is too long for a tar archive", path),
+    }
+}
+
+/// Write `value` in octal, padded with zeros and ended by a NUL.
+fn write_octal(field: &mut [u8], value: u64) {
+    let octal = format!("{:0width$o}\0", value, width = field.len() - 1);
+    field.copy_from_slice(octal.as_bytes());
+}
+
+/// A writer counting the bytes written, for the offsets of a zip archive.
+struct Counting<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A reader computing the CRC-32 and the size of what's read.
+struct Checksummed<R> {
+    inner: R,
+    hasher: crc32fast::Hasher,
+    size: u64,
+}
+
+impl<R: io::Read> io::Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.size += read as u64;
+        Ok(read)
+    }
+}
+
+/// The zip archive of the entries. The sizes and CRC-32 of the files follow
+/// their data, so the files are compressed as they're read.
+fn zip<W: Write>(entries: &[Entry], time: i64, writer: W) -> Result<()> {
+    let (dos_time, dos_date) = dos_date_time(time);
+    let mut zip = Counting {
+        inner: writer,
+        count: 0,
+    };
+    let mut central_directory = Vec::new();
+    let too_big = "The book is too big for a zip archive, try output.html.archive = \"tar.gz\"";
+
+    for entry in entries {
+        let offset = zip.count;
+        ensure!(offset <= u64::from(std::u32::MAX), too_big);
+        let (method, flags, attributes) = match entry.file {
+            // The sizes and CRC-32 of the files are after their data, and the
+            // names are UTF-8
+            Some(_) => (8u16, 0x0808u16, 0o100_644 << 16),
+            // The MS-DOS directory attribute, with the Unix permissions
+            None => (0, 0x0800, (0o040_755 << 16) | 0x10),
+        };
+
+        // The fields shared by the local header and the central directory
+        // before the CRC-32
+        let mut fields = Vec::new();
+        fields.extend_from_slice(&20u16.to_le_bytes()); // version needed to extract
+        fields.extend_from_slice(&flags.to_le_bytes());
+        fields.extend_from_slice(&method.to_le_bytes());
+        fields.extend_from_slice(&dos_time.to_le_bytes());
+        fields.extend_from_slice(&dos_date.to_le_bytes());
+        let mut name_fields = Vec::new();
+        name_fields.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+        name_fields.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+
+        zip.write_all(&0x0403_4b50u32.to_le_bytes())?;
+        zip.write_all(&fields)?;
+        // The CRC-32 and the sizes, which are in the data descriptor
+        zip.write_all(&[0; 12])?;
+        zip.write_all(&name_fields)?;
+        zip.write_all(entry.name.as_bytes())?;
+
+        let (crc, compressed, size) = match entry.file {
+            Some(ref path) => {
+                let file = File::open(path)
+                    .with_context(|| format!("Unable to read {}", path.display()))?;
+                let mut file = Checksummed {
+                    inner: file,
+                    hasher: crc32fast::Hasher::new(),
+                    size: 0,
+                };
+                let start = zip.count;
+                let mut encoder = DeflateEncoder::new(&mut zip, Compression::best());
+                io::copy(&mut file, &mut encoder)?;
+                encoder.finish()?;
+                let compressed = zip.count - start;
+                let crc = file.hasher.finalize();
+                ensure!(
+                    file.size <= u64::from(std::u32::MAX) && compressed <= u64::from(std::u32::MAX),
+                    too_big
+                );
+
+                zip.write_all(&0x0807_4b50u32.to_le_bytes())?;
+                zip.write_all(&crc.to_le_bytes())?;
+                zip.write_all(&(compressed as u32).to_le_bytes())?;
+                zip.write_all(&(file.size as u32).to_le_bytes())?;
+                (crc, compressed as u32, file.size as u32)
+            }
+            None => (0, 0, 0),
+        };
+
+        central_directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        // Made by Unix, so the attributes are permissions
+        central_directory.extend_from_slice(&((3u16 << 8) | 20).to_le_bytes());
+        central_directory.extend_from_slice(&fields);
+        central_directory.extend_from_slice(&crc.to_le_bytes());
+        central_directory.extend_from_slice(&compressed.to_le_bytes());
+        central_directory.extend_from_slice(&size.to_le_bytes());
+        central_directory.extend_from_slice(&name_fields);
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        central_directory.extend_from_slice(&(attributes as u32).to_le_bytes());
+        central_directory.extend_from_slice(&(offset as u32).to_le_bytes());
+        central_directory.extend_from_slice(entry.name.as_bytes());
+    }
+
+    let offset = zip.count;
+    ensure!(
+        entries.len() <= 0xFFFF && offset <= u64::from(std::u32::MAX),
+        too_big
+    );
+    zip.write_all(&central_directory)?;
+    zip.write_all(&0x0605_4b50u32.to_le_bytes())?;
+    zip.write_all(&0u16.to_le_bytes())?; // this disk
+    zip.write_all(&0u16.to_le_bytes())?; // the disk of the central directory
+    zip.write_all(&(entries.len() as u16).to_le_bytes())?;
+    zip.write_all(&(entries.len() as u16).to_le_bytes())?;
+    zip.write_all(&(central_directory.len() as u32).to_le_bytes())?;
+    zip.write_all(&(offset as u32).to_le_bytes())?;
+    zip.write_all(&0u16.to_le_bytes())?; // comment length
+    Ok(())
+}
+
+/// The MS-DOS time and date of a timestamp, which can't be before 1980 or
+/// after 2107.
+fn dos_date_time(time: i64) -> (u16, u16) {
+    let time = if time < DEFAULT_TIME {
+        DEFAULT_TIME
+    } else {
+        time.min(4_354_819_199)
+    };
+    let time = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(time, 0), Utc);
+    let dos_time = (time.hour() << 11) | (time.minute() << 5) | (time.second() / 2);
+    let dos_date = ((time.year() as u32 - 1980) << 9) | (time.month() << 5) | time.day();
+    (dos_time as u16, dos_date as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::{DeflateDecoder, GzDecoder};
+    use std::io::Read;
+    use tempfile::TempDir;
+
+    /// A rendered book, with the files which aren't archived.
+    fn destination() -> TempDir {
+        let temp = TempDir::new().unwrap();
+        let book = temp.path().join("book");
+        fs::create_dir_all(book.join("css")).unwrap();
+        fs::create_dir_all(book.join("empty")).unwrap();
+        fs::write(book.join("index.html"), "<h1>Index</h1>").unwrap();
+        fs::write(book.join("css").join("general.css"), "body {}").unwrap();
+        fs::write(book.join(CACHE_FILE), "{}").unwrap();
+        fs::write(book.join("timings.json"), "{}").unwrap();
+        // Not excluded when it isn't the one of the build
+        fs::write(book.join("css").join("timings.json"), "[]").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(book.join("index.html"), book.join("link.html")).unwrap();
+        temp
+    }
+
+    /// The files and directories of `dir`, as they should be archived.
+    fn expected_entries(dir: &Path) -> Vec<(String, Option<Vec<u8>>)> {
+        let mut entries = vec![
+            (String::from("css/"), None),
+            (String::from("css/general.css"), Some(b"body {}".to_vec())),
+            (String::from("css/timings.json"), Some(b"[]".to_vec())),
+            (String::from("empty/"), None),
+            (String::from("index.html"), Some(b"<h1>Index</h1>".to_vec())),
+        ];
+        if cfg!(unix) {
+            entries.push((String::from("link.html"), Some(b"<h1>Index</h1>".to_vec())));
+        }
+        for (name, contents) in &entries {
+            let path = dir.join(name);
+            match contents {
+                Some(contents) => assert_eq!(&fs::read(path).unwrap(), contents),
+                None => assert!(path.is_dir()),
+            }
+        }
+        entries
+    }
+
+    fn octal(field: &[u8]) -> u64 {
+        let digits = String::from_utf8_lossy(field);
+        u64::from_str_radix(digits.trim_matches(|c| c == '\0' || c == ' '), 8).unwrap()
+    }
+
+    fn field(bytes: &[u8], start: usize, len: usize) -> String {
+        let field = &bytes[start..start + len];
+        let end = field.iter().position(|&b| b == 0).unwrap_or(len);
+        String::from_utf8(field[..end].to_vec()).unwrap()
+    }
+
+    /// The entries of a `.tar.gz`, with the modification time of each.
+    fn untar(archive: &[u8]) -> Vec<(String, Option<Vec<u8>>, u64)> {
+        let mut tar = Vec::new();
+        GzDecoder::new(archive).read_to_end(&mut tar).unwrap();
+
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while tar[offset..offset + 512].iter().any(|&b| b != 0) {
+            let header = &tar[offset..offset + 512];
+            let mut blank = header.to_vec();
+            blank[148..156].copy_from_slice(b"        ");
+            let checksum: u64 = blank.iter().map(|&b| u64::from(b)).sum();
+            assert_eq!(octal(&header[148..156]), checksum);
+            assert_eq!(&header[257..263], b"ustar\0");
+
+            let prefix = field(header, 345, 155);
+            let name = field(header, 0, 100);
+            let name = if prefix.is_empty() {
+                name
+            } else {
+                format!("{}/{}", prefix, name)
+            };
+            let size = octal(&header[124..136]) as usize;
+            let mtime = octal(&header[136..148]);
+            offset += 512;
+            let contents = match header[156] {
+                b'0' => Some(tar[offset..offset + size].to_vec()),
+                b'5' => None,
+                kind => panic!("Unexpected entry type {}", kind),
+            };
+            offset += size + (512 - size % 512) % 512;
+            entries.push((name, contents, mtime));
+        }
+        assert_eq!(tar.len(), offset + 1024);
+        entries
+    }
+
+    fn u16_at(bytes: &[u8], offset: usize) -> usize {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]]) as usize
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> usize {
+        let mut le = [0; 4];
+        le.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(le) as usize
+    }
+
+    /// The entries of a `.zip`, read through its central directory.
+    fn unzip(archive: &[u8]) -> Vec<(String, Option<Vec<u8>>)> {
+        let end = archive.len() - 22;
+        assert_eq!(u32_at(archive, end), 0x0605_4b50);
+        let count = u16_at(archive, end + 10);
+        let mut offset = u32_at(archive, end + 16);
+
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            assert_eq!(u32_at(archive, offset), 0x0201_4b50);
+            let crc = u32_at(archive, offset + 16) as u32;
+            let compressed_size = u32_at(archive, offset + 20);
+            let size = u32_at(archive, offset + 24);
+            let name_len = u16_at(archive, offset + 28);
+            let local = u32_at(archive, offset + 42);
+            let name = &archive[offset + 46..offset + 46 + name_len];
+            let name = String::from_utf8(name.to_vec()).unwrap();
+            offset += 46 + name_len;
+
+            assert_eq!(u32_at(archive, local), 0x0403_4b50);
+            let data_start = local + 30 + u16_at(archive, local + 26);
+            let data = &archive[data_start..data_start + compressed_size];
+            let contents = if name.ends_with('/') {
+                assert!(data.is_empty());
+                None
+            } else {
+                let mut contents = Vec::new();
+                DeflateDecoder::new(data)
+                    .read_to_end(&mut contents)
+                    .unwrap();
+                assert_eq!(contents.len(), size);
+                assert_eq!(crc32fast::hash(&contents), crc);
+                Some(contents)
+            };
+            entries.push((name, contents));
+        }
+        entries
+    }
+
+    #[test]
+    fn the_tarball_has_the_files_of_the_destination() {
+        let temp = destination();
+        let book = temp.path().join("book");
+        let path = write_archive(&book, ArchiveFormat::TarGz).unwrap();
+        assert_eq!(path, temp.path().join("book.tar.gz"));
+
+        let archive = fs::read(&path).unwrap();
+        let entries = untar(&archive);
+        assert!(entries.iter().all(|&(_, _, mtime)| mtime == 315_532_800));
+        let entries: Vec<_> = entries
+            .into_iter()
+            .map(|(name, contents, _)| (name, contents))
+            .collect();
+        assert_eq!(entries, expected_entries(&book));
+
+        // Archiving it again gives the same archive
+        fs::write(book.join(CACHE_FILE), "{\"changed\": true}").unwrap();
+        write_archive(&book, ArchiveFormat::TarGz).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), archive);
+    }
+
+    #[test]
+    fn the_zip_has_the_files_of_the_destination() {
+        let temp = destination();
+        let book = temp.path().join("book");
+        let path = write_archive(&book, ArchiveFormat::Zip).unwrap();
+        assert_eq!(path, temp.path().join("book.zip"));
+
+        let archive = fs::read(&path).unwrap();
+        assert_eq!(unzip(&archive), expected_entries(&book));
+        write_archive(&book, ArchiveFormat::Zip).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), archive);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn directories_linked_to_their_parents_are_archived_once() {
+        let temp = destination();
+        let book = temp.path().join("book");
+        std::os::unix::fs::symlink(&book, book.join("css").join("loop")).unwrap();
+        let path = write_archive(&book, ArchiveFormat::Zip).unwrap();
+
+        let archive = fs::read(&path).unwrap();
+        assert_eq!(unzip(&archive), expected_entries(&book));
+    }
+
+    #[test]
+    fn long_paths_are_split_between_the_name_and_the_prefix() {
+        let dir = "a".repeat(120);
+        let path = format!("{}/{}", dir, "b".repeat(90));
+        assert_eq!(split_tar_name(&path).unwrap(), (&*dir, &*"b".repeat(90)));
+        assert_eq!(
+            split_tar_name("short/path.html").unwrap(),
+            ("", "short/path.html")
+        );
+        assert!(split_tar_name(&"c".repeat(101)).is_err());
+    }
+
+    #[test]
+    fn the_zip_dates_are_ms_dos_dates() {
+        // 2020-05-01T12:30:10Z
+        let (time, date) = dos_date_time(1_588_336_210);
+        assert_eq!(
+            (time >> 11, (time >> 5) & 0x3F, (time & 0x1F) * 2),
+            (12, 30, 10)
+        );
+        assert_eq!((date >> 9, (date >> 5) & 0xF, date & 0x1F), (40, 5, 1));
+        assert_eq!(dos_date_time(0), dos_date_time(DEFAULT_TIME));
+    }
+}
@@ -0,0 +1,162 @@
+//! When and from what a book is built, and when its chapters were last
+//! changed, for the themes to show.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+
+use crate::book::{Book, BookItem};
+
+/// The time of the build in RFC 3339, like `2020-06-21T14:05:00Z`. It's the
+/// `SOURCE_DATE_EPOCH` when that's set, so the builds are reproducible.
+pub(super) fn build_timestamp() -> String {
+    let time = match env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => match source_date_epoch(&epoch) {
+            Some(time) => time,
+            None => {
+                warn!("Ignoring the invalid SOURCE_DATE_EPOCH \"{}\"", epoch);
+                Utc::now()
+            }
+        },
+        Err(_) => Utc::now(),
+    };
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+pub(super) fn source_date_epoch(epoch: &str) -> Option<DateTime<Utc>> {
+    let seconds = epoch.trim().parse::<i64>().ok()?;
+    let time = NaiveDateTime::from_timestamp_opt(seconds, 0)?;
+    Some(DateTime::from_utc(time, Utc))
+}
+
+/// The commit of the git repository the book is in.
+#[derive(Debug, PartialEq)]
+pub(super) struct GitInfo {
+    /// The full hash of `HEAD`.
+    pub(super) commit: String,
+    /// The short hash of `HEAD`, like `abc1234`.
+    pub(super) short_commit: String,
+    /// Whether the working tree has changes which aren't committed.
+    pub(super) dirty: bool,
+}
+
+/// The commit `root` is at, or `None` when git isn't installed or `root`
+/// isn't in a repository.
+pub(super) fn git_info(root: &Path) -> Option<GitInfo> {
+    let commit = git(root, &["rev-parse", "HEAD"])?;
+    let short_commit = git(root, &["rev-parse", "--short", "HEAD"])?;
+    let dirty = match git(root, &["status", "--porcelain"]) {
+        Some(status) => !status.is_empty(),
+        None => false,
+    };
+    Some(GitInfo {
+        commit,
+        short_commit,
+        dirty,
+    })
+}
+
+/// When the source files of the chapters of `book` were last modified, like
+/// `2020-06-21T14:05:00+02:00`, by the source path. It's the date of the last
+/// commit changing the file, or its modification time when git doesn't know
+/// it, and chapters with neither are left out.
+///
+/// The whole history of `src_dir` is read at once, with a single `git log`.
+pub(super) fn last_modified_dates(src_dir: &Path, book: &Book) -> HashMap<PathBuf, String> {
+    let committed = commit_dates(src_dir).unwrap_or_default();
+
+    let mut dates = HashMap::new();
+    for item in book.iter() {
+        let ch = match *item {
+            BookItem::Chapter(ref ch) if !ch.is_draft_chapter() => ch,
+            _ => continue,
+        };
+        let source = ch.source_path.as_ref().or(ch.path.as_ref()).unwrap();
+        let date = committed
+            .get(source)
+            .cloned()
+            .or_else(|| modification_time(&src_dir.join(source)));
+        if let Some(date) = date {
+            dates.insert(source.clone(), date);
+        }
+    }
+    dates
+}
+
+/// The date of the last commit changing each file in `src_dir`, by its path
+/// relative to `src_dir`.
+fn commit_dates(src_dir: &Path) -> Option<HashMap<PathBuf, String>> {
+    let log = git(
+        src_dir,
+        &[
+            "-c",
+            "core.quotePath=false",
+            "log",
+            "--format=%x00%cI",
+            "--name-only",
+            "--relative",
+            "--",
+            ".",
+        ],
+    )?;
+
+    // The newest commits come first, so the first date of a file is the last
+    let mut dates = HashMap::new();
+    let mut date = None;
+    for line in log.lines().filter(|line| !line.is_empty()) {
+        if line.starts_with('\0') {
+            date = Some(line.trim_start_matches('\0'));
+        } else if let Some(date) = date {
+            dates
+                .entry(PathBuf::from(line))
+                .or_insert_with(|| date.to_string());
+        }
+    }
+    Some(dates)
+}
+
+fn modification_time(path: &Path) -> Option<String> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    Some(DateTime::<Utc>::from(modified).to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+fn git(root: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(root)
+        .output()
+        .ok()?;
+
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    } else {
+        debug!("`git {}` failed in {}", args.join(" "), root.display());
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder as TempFileBuilder;
+
+    #[test]
+    fn the_source_date_epoch_is_the_build_time() {
+        let time = source_date_epoch("1588291200").unwrap();
+        assert_eq!(
+            time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            "2020-05-01T00:00:00Z"
+        );
+        assert_eq!(source_date_epoch("yesterday"), None);
+    }
+
+    #[test]
+    fn directories_outside_of_repositories_have_no_commit() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        assert_eq!(git_info(temp.path()), None);
+    }
+}
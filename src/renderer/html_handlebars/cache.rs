@@ -0,0 +1,163 @@
+//! Bookkeeping for incremental builds, so pages whose inputs haven't changed
+//! since the previous build don't have to be rendered again.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::config::HtmlConfig;
+use crate::errors::*;
+use crate::theme::{playpen_editor, Theme};
+use crate::utils;
+
+/// The file in the destination directory the cache is kept in.
+pub(super) const CACHE_FILE: &str = ".mdbook-cache.json";
+
+/// What the previous build rendered.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct RenderCache {
+    /// The hash of everything shared by all pages: the book's data (including
+    /// the table of contents), the configuration and the theme.
+    pub(super) global: String,
+    /// The hash of the inputs of each rendered page, by the page's path.
+    pub(super) pages: BTreeMap<String, String>,
+}
+
+impl RenderCache {
+    /// Read the cache of the previous build. A missing or unreadable cache
+    /// just means everything has to be rendered.
+    pub(super) fn load(destination: &Path) -> RenderCache {
+        let contents = match fs::read_to_string(destination.join(CACHE_FILE)) {
+            Ok(contents) => contents,
+            Err(_) => return RenderCache::default(),
+        };
+
+        serde_json::from_str(&contents).unwrap_or_else(|e| {
+            debug!("Ignoring the invalid {}: {}", CACHE_FILE, e);
+            RenderCache::default()
+        })
+    }
+
+    pub(super) fn save(&self, destination: &Path) -> Result<()> {
+        let contents = serde_json::to_string(self)?;
+        utils::fs::write_file(destination, CACHE_FILE, contents.as_bytes())
+    }
+}
+
+/// Hash the inputs shared by all the pages of the book. `root` is the
+/// directory the `additional-css` and `additional-js` files are in.
+pub(super) fn global_hash(
+    data: &serde_json::Map<String, serde_json::Value>,
+    html_config: &HtmlConfig,
+    theme: &Theme,
+    root: &Path,
+) -> Result<String> {
+    let mut hasher = StableHasher::new();
+
+    hasher.add(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.add(hashed_data(data)?.as_bytes());
+    hasher.add(serde_json::to_string(html_config)?.as_bytes());
+    for file in &[
+        &theme.index,
+        &theme.head,
+        &theme.header,
+        &theme.chrome_css,
+        &theme.general_css,
+        &theme.print_css,
+        &theme.variables_css,
+        &theme.favicon,
+        &theme.js,
+        &theme.highlight_css,
+        &theme.tomorrow_night_css,
+        &theme.ayu_highlight_css,
+        &theme.highlight_js,
+        &theme.clipboard_js,
+    ] {
+        hasher.add(file);
+    }
+    for (name, template) in &theme.templates {
+        hasher.add(name.as_bytes());
+        hasher.add(template);
+    }
+    for file in &[
+        playpen_editor::JS,
+        playpen_editor::ACE_JS,
+        playpen_editor::MODE_RUST_JS,
+        playpen_editor::THEME_DAWN_JS,
+        playpen_editor::THEME_TOMORROW_NIGHT_JS,
+    ] {
+        hasher.add(file);
+    }
+    // A missing file is reported when it's copied
+    for file in html_config
+        .additional_css
+        .iter()
+        .chain(&html_config.additional_js)
+    {
+        hasher.add(file.to_string_lossy().as_bytes());
+        hasher.add(&fs::read(root.join(file)).unwrap_or_default());
+    }
+
+    Ok(hasher.finish())
+}
+
+/// The JSON of the data of the templates, as it's hashed. It's all of it but
+/// the timestamp of the build, which changes every time: the pages which
+/// didn't have to be rendered again keep the timestamp of the build which
+/// wrote them.
+pub(super) fn hashed_data<T: Serialize>(data: &T) -> Result<String> {
+    let mut data = serde_json::to_value(data)?;
+    if let Some(data) = data.as_object_mut() {
+        data.remove("build_timestamp");
+    }
+    Ok(serde_json::to_string(&data)?)
+}
+
+/// Hash the inputs of a single page.
+pub(super) fn page_hash(global: &str, inputs: &[&[u8]]) -> String {
+    let mut hasher = StableHasher::new();
+    hasher.add(global.as_bytes());
+    for input in inputs {
+        hasher.add(input);
+    }
+    hasher.finish()
+}
+
+/// The hashes are kept on disk from one build to the next, so they're CRC32s
+/// (unlike `DefaultHasher`, they're the same with every version of Rust).
+struct StableHasher(crc32fast::Hasher);
+
+impl StableHasher {
+    fn new() -> StableHasher {
+        StableHasher(crc32fast::Hasher::new())
+    }
+
+    /// Add some bytes, after their length, so moving bytes from one input to
+    /// the next changes the hash.
+    fn add(&mut self, bytes: &[u8]) {
+        self.0.update(&(bytes.len() as u64).to_le_bytes());
+        self.0.update(bytes);
+    }
+
+    fn finish(self) -> String {
+        format!("{:08x}", self.0.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_hashes_are_stable() {
+        // The hashes of a previous build are compared with the ones of the
+        // next, which may be built by another version of mdBook or Rust
+        assert_eq!(page_hash("global", &[b"page", b"data"]), "cfba9804");
+        assert_ne!(
+            page_hash("global", &[b"page", b"data"]),
+            page_hash("global", &[b"pag", b"edata"])
+        );
+    }
+}
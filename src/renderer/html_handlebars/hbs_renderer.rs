@@ -1,19 +1,39 @@
-use crate::book::{Book, BookItem};
-use crate::config::{Config, HtmlConfig, Playpen, RustEdition};
+use crate::book::{self, Book, BookItem, Chapter, Summary, SummaryItem};
+use crate::config::{
+    Config, HeadExtra, Highlight, HtmlConfig, IndexPage, MathRenderer, PageToc, Playpen,
+    RustEdition, TextDirection,
+};
+use crate::diagnostics::{Diagnostic, DiagnosticKind, Diagnostics};
 use crate::errors::*;
+use crate::renderer::html_handlebars::archive;
+use crate::renderer::html_handlebars::build_info;
+use crate::renderer::html_handlebars::cache::{self, RenderCache};
 use crate::renderer::html_handlebars::helpers;
-use crate::renderer::{RenderContext, Renderer};
+use crate::renderer::html_handlebars::highlight::Highlighter;
+use crate::renderer::html_handlebars::link_check;
+use crate::renderer::html_handlebars::locales;
+use crate::renderer::html_handlebars::redirect;
+use crate::renderer::html_handlebars::single_file;
+use crate::renderer::html_handlebars::sitemap;
+use crate::renderer::{PostProcessors, RenderContext, Renderer};
 use crate::theme::{self, playpen_editor, Theme};
+use crate::timings::Timings;
 use crate::utils;
+use crate::utils::fs::CopyOptions;
+use crate::utils::toml_ext;
+use crate::utils::{CodeBlockAttributes, LinkStyle, MarkdownOptions};
 
 use std::borrow::Cow;
 use std::collections::BTreeMap;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
-use handlebars::Handlebars;
+use handlebars::{Context as TemplateContext, Handlebars, RenderError, Renderable};
+use rayon::prelude::*;
 use regex::{Captures, Regex};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use url::Url;
 
 #[derive(Default)]
 pub struct HtmlHandlebars;
@@ -23,11 +43,91 @@ impl HtmlHandlebars {
         HtmlHandlebars
     }
 
+    /// Render a chapter to the HTML of its page, the one the build would
+    /// write, without writing anything, for the tools previewing a chapter as
+    /// it's being written.
+    ///
+    /// `ctx` has the book the chapter is in, for the table of contents and
+    /// the links to the chapters around it, and its configuration. Its
+    /// `destination` isn't used. The chapter doesn't have to be in the book,
+    /// but it has to have a path, which its links are relative to. It's
+    /// rendered as it is, so its includes need to be expanded by the
+    /// preprocessors first.
+    ///
+    /// ```rust
+    /// use mdbook::book::{Book, Chapter};
+    /// use mdbook::config::{Config, HtmlConfig};
+    /// use mdbook::renderer::{HtmlHandlebars, RenderContext};
+    /// use mdbook::theme::Theme;
+    ///
+    /// let content = String::from("# Usage\n\nSee [the introduction](intro.md).\n");
+    /// let chapter = Chapter::new("Usage", content, "usage.md", Vec::new());
+    /// let mut book = Book::new();
+    /// book.push_item(chapter.clone());
+    ///
+    /// let ctx = RenderContext::new("/path/to/book", book, Config::default(), "/path/to/book/book");
+    /// let html = HtmlHandlebars::new()
+    ///     .render_chapter_to_string(&ctx, &chapter, &HtmlConfig::default(), &Theme::default())
+    ///     .unwrap();
+    /// assert!(html.contains(r#"<a href="intro.html">the introduction</a>"#));
+    /// ```
+    pub fn render_chapter_to_string(
+        &self,
+        ctx: &RenderContext,
+        chapter: &Chapter,
+        html_config: &HtmlConfig,
+        theme: &Theme,
+    ) -> Result<String> {
+        let book = book_to_render(&ctx.book, html_config);
+        let handlebars = self.handlebars(theme, html_config)?;
+        let highlighter = highlighter(html_config, &ctx.root)?;
+        let post_processors = ctx
+            .post_processors
+            .with_configured(html_config, ctx.root.clone())?;
+        let uses_editor = uses_editor(&book, &html_config.playpen);
+        let (data, last_modified) = book_data(ctx, &book, html_config, theme, uses_editor)?;
+
+        let position = book
+            .iter()
+            .zip(positions(ctx, &book, html_config)?)
+            .find(|&(item, _)| match *item {
+                BookItem::Chapter(ref ch) => ch.path.is_some() && ch.path == chapter.path,
+                _ => false,
+            })
+            .map(|(_, position)| position)
+            .unwrap_or_default();
+        let mut chapter = chapter.clone();
+        if html_config.strip_first_h1 {
+            if let Some(name) = utils::leading_h1(&chapter.content) {
+                chapter.name = name;
+            }
+        }
+
+        let is_index = position.is_index;
+        let item = BookItem::Chapter(chapter.clone());
+        let mut data = ChapterData::new(&data);
+        insert_item_data(&mut data, &item, position, ctx, &last_modified)?;
+        let mut item_ctx = RenderItemContext::new(
+            ctx,
+            &handlebars,
+            data,
+            is_index,
+            html_config,
+            highlighter.as_ref(),
+            &post_processors,
+        );
+        let mut context =
+            TemplateContext::wraps(item_ctx.data.book).map_err(template_render_error)?;
+        self.render_page(&chapter, &mut item_ctx, &mut context)
+    }
+
+    /// Render the page of `item` (if it has one). `context` has the data of
+    /// the book, see [`ChapterData::render()`].
     fn render_item(
         &self,
         item: &BookItem,
         mut ctx: RenderItemContext<'_>,
-        print_content: &mut String,
+        context: &mut TemplateContext,
     ) -> Result<()> {
         // FIXME: This should be made DRY-er and rely less on mutable state
 
@@ -36,87 +136,298 @@ impl HtmlHandlebars {
             _ => return Ok(()),
         };
 
-        let content = ch.content.clone();
-        let content = utils::render_markdown(&content, ctx.html_config.curly_quotes);
-
-        let fixed_content = utils::render_markdown_with_path(
-            &ch.content,
-            ctx.html_config.curly_quotes,
-            Some(&path),
-        );
-        print_content.push_str(&fixed_content);
+        // "print.html" is used for the print page.
+        if path == Path::new("print.md") {
+            bail!("{} is reserved for internal use", path.display());
+        };
 
-        // Update the context with data for this file
+        let rendered = self.render_page(ch, &mut ctx, context)?;
+        let template = chapter_template(ch, ctx.handlebars)?;
+        let pretty_urls = ctx.html_config.pretty_urls;
         let ctx_path = path
             .to_str()
             .with_context(|| "Could not convert path to str")?;
-        let filepath = Path::new(&ctx_path).with_extension("html");
+        let filepath = utils::fs::chapter_page(path, pretty_urls);
 
-        // "print.html" is used for the print page.
-        if path == Path::new("print.md") {
-            bail!("{} is reserved for internal use", path.display());
+        // Write to file
+        debug!("Creating {}", filepath.display());
+        utils::fs::write_file(&ctx.destination, &filepath, rendered.as_bytes())?;
+
+        let write_index = ctx.is_index && filepath != Path::new("index.html");
+        if write_index && ctx.html_config.index_page == IndexPage::Redirect {
+            let page = utils::fs::normalize_path(
+                filepath
+                    .to_str()
+                    .with_context(|| "Could not convert path to str")?,
+            );
+            debug!("Redirecting index.html to {}", page);
+            let redirect = redirect::redirect_page(&page);
+            utils::fs::write_file(&ctx.destination, "index.html", redirect.as_bytes())?;
+        } else if write_index {
+            ctx.data.insert("path".to_owned(), json!("index.md"));
+            ctx.data.insert("path_to_root".to_owned(), json!(""));
+            if pretty_urls {
+                // The chapter's links are relative to its source, not to its
+                // own directory
+                let footnotes = footnote_prefix(path);
+                let content = utils::render_markdown_with_links(
+                    &ch.content,
+                    None,
+                    LinkStyle::Pretty { nested: false },
+                    &markdown_options(&ctx.html_config, &footnotes),
+                );
+                ctx.data.insert("content".to_owned(), json!(content));
+            }
+            if let Some(ref site_url) = ctx.html_config.site_url {
+                let title = ctx
+                    .data
+                    .get("title")
+                    .and_then(serde_json::Value::as_str)
+                    .unwrap_or_default()
+                    .to_string();
+                if let Some(url) = page_url(site_url, "index.html") {
+                    insert_open_graph(&mut ctx.data, &url, &title);
+                }
+            }
+            let rendered_index = ctx.data.render(ctx.handlebars, template, context)?;
+            let rendered_index = self.post_process(
+                rendered_index,
+                &ctx.html_config,
+                ctx.edition,
+                ctx.highlighter,
+            );
+            let rendered_index = ctx.post_processors.process(ch, rendered_index)?;
+            debug!("Creating index.html from {}", ctx_path);
+            utils::fs::write_file(&ctx.destination, "index.html", rendered_index.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Render the page of a chapter: its Markdown, in the data of the page,
+    /// through its template and the post-processing. The data is left with
+    /// the chapter's keys, for its `index.html`.
+    fn render_page(
+        &self,
+        ch: &Chapter,
+        ctx: &mut RenderItemContext<'_>,
+        context: &mut TemplateContext,
+    ) -> Result<String> {
+        let path = match ch.path {
+            Some(ref path) => path,
+            None => bail!("The draft chapter \"{}\" has no page", ch.name),
         };
+        let template = chapter_template(ch, ctx.handlebars)?;
+        let pretty_urls = ctx.html_config.pretty_urls;
+        let footnotes = footnote_prefix(path);
+        let options = markdown_options(&ctx.html_config, &footnotes);
+        let source = ch.source_path.as_ref().unwrap_or(path);
+        let content = ctx.timings.time_chapter(source, "markdown", || {
+            utils::render_markdown_with_links(
+                &ch.content,
+                None,
+                chapter_link_style(path, pretty_urls),
+                &options,
+            )
+        });
+        for label in utils::undefined_footnotes(&ch.content) {
+            ctx.diagnostics.warn(
+                Diagnostic::new(
+                    DiagnosticKind::Other,
+                    format!(
+                        "The footnote [^{}] of \"{}\" is referenced but never defined",
+                        label, ch.name
+                    ),
+                )
+                .with_chapter(ch.source_path.as_ref().unwrap_or(path)),
+            );
+        }
 
+        // Update the context with data for this file
+        let filepath = utils::fs::chapter_page(path, pretty_urls);
         let book_title = ctx
             .data
             .get("book_title")
             .and_then(serde_json::Value::as_str)
             .unwrap_or("");
 
-        let title = match book_title {
-            "" => ch.name.clone(),
-            _ => ch.name.clone() + " - " + book_title,
+        // A `{{#title}}` is the whole title, the frontmatter's replaces the
+        // chapter's name
+        let name = ch
+            .metadata
+            .get("title")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or(&ch.name);
+        let title = match ch
+            .metadata
+            .get("page-title")
+            .and_then(serde_json::Value::as_str)
+        {
+            Some(title) => title.to_string(),
+            None if book_title.is_empty() => name.to_string(),
+            None => format!("{} - {}", name, book_title),
         };
 
         ctx.data.insert("path".to_owned(), json!(path));
         ctx.data.insert("content".to_owned(), json!(content));
+        ctx.data.insert(
+            "page_headings".to_owned(),
+            json!(page_headings(&content, &ctx.html_config.pagetoc)),
+        );
         ctx.data.insert("chapter_title".to_owned(), json!(ch.name));
+        let reading_time = &ctx.html_config.reading_time;
+        let words = utils::word_count(&ch.content, reading_time.counting);
+        ctx.data.insert("word_count".to_owned(), json!(words));
+        ctx.data.insert(
+            "reading_time".to_owned(),
+            json!(utils::reading_time(words, reading_time)),
+        );
         ctx.data.insert("title".to_owned(), json!(title));
         ctx.data.insert(
             "path_to_root".to_owned(),
-            json!(utils::fs::path_to_root(&path)),
+            json!(utils::fs::path_to_root(&filepath)),
         );
         if let Some(ref section) = ch.number {
             ctx.data
                 .insert("section".to_owned(), json!(section.to_string()));
         }
 
+        if let Some(ref site_url) = ctx.html_config.site_url {
+            let page = utils::fs::normalize_path(
+                filepath
+                    .to_str()
+                    .with_context(|| "Could not convert path to str")?,
+            );
+            if let Some(url) = page_url(site_url, &page) {
+                insert_open_graph(&mut ctx.data, &url, &title);
+            }
+        }
+
+        if let Some(ref edit_url_template) = ctx.html_config.edit_url_template {
+            let source_path = ch.source_path.as_ref().unwrap_or(path);
+            let edit_url = edit_url(edit_url_template, &ctx.src_dir.join(source_path));
+            ctx.data
+                .insert("git_repository_edit_url".to_owned(), json!(edit_url));
+        }
+
+        if ctx.is_index {
+            ctx.data.insert("is_index".to_owned(), json!(true));
+        }
+
         // Render the handlebars template with the data
         debug!("Render template");
-        let rendered = ctx.handlebars.render("index", &ctx.data)?;
+        let rendered = ctx.timings.time_chapter(source, "template", || {
+            ctx.data.render(ctx.handlebars, template, context)
+        })?;
+
+        let rendered = ctx.timings.time_chapter(source, "post-process", || {
+            let rendered =
+                self.post_process(rendered, &ctx.html_config, ctx.edition, ctx.highlighter);
+            ctx.post_processors.process(ch, rendered)
+        })?;
+        Ok(rendered)
+    }
 
-        let rendered = self.post_process(rendered, &ctx.html_config.playpen, ctx.edition);
+    /// Render the page static hosts serve for missing URLs, returning its name
+    /// in the output directory and its HTML. It can be at any depth, so its
+    /// links start at the absolute path of the book.
+    fn render_404(
+        &self,
+        ctx: &RenderContext,
+        html_config: &HtmlConfig,
+        data: &serde_json::Map<String, serde_json::Value>,
+        handlebars: &Handlebars<'_>,
+        highlighter: Option<&Highlighter>,
+        post_processors: &PostProcessors,
+    ) -> Result<(String, String)> {
+        let content = match html_config.input_404 {
+            Some(ref input) => {
+                let path = ctx.source_dir().join(input);
+                fs::read_to_string(&path)
+                    .with_context(|| format!("Unable to open the 404 page {}", path.display()))?
+            }
+            None => DEFAULT_404.to_string(),
+        };
+        let content = utils::render_markdown(&content, html_config.curly_quotes);
+        let title = match ctx.config.book.title {
+            Some(ref title) => format!("Page not found - {}", title),
+            None => String::from("Page not found"),
+        };
+        let path_to_root = site_url_path(html_config.site_url.as_ref().map(|url| &url[..]));
+
+        let mut data = ChapterData::new(data);
+        data.insert("path".to_owned(), json!("404.md"));
+        data.insert("content".to_owned(), json!(content));
+        data.insert("title".to_owned(), json!(title));
+        data.insert("path_to_root".to_owned(), json!(path_to_root));
+
+        let rendered = handlebars
+            .render("index", &data)
+            .map_err(template_render_error)?;
+        let rendered = self.post_process(
+            rendered,
+            html_config,
+            html_config.playpen.effective_edition(&ctx.config.rust),
+            highlighter,
+        );
+        let rendered = post_processors.process_page(&title, "404.md", rendered)?;
 
-        // Write to file
-        debug!("Creating {}", filepath.display());
-        utils::fs::write_file(&ctx.destination, &filepath, rendered.as_bytes())?;
+        let output = html_config
+            .output_404
+            .clone()
+            .unwrap_or_else(|| String::from("404.html"));
+        Ok((output, rendered))
+    }
 
-        if ctx.is_index {
-            ctx.data.insert("path".to_owned(), json!("index.md"));
-            ctx.data.insert("path_to_root".to_owned(), json!(""));
-            ctx.data.insert("is_index".to_owned(), json!("true"));
-            let rendered_index = ctx.handlebars.render("index", &ctx.data)?;
-            let rendered_index =
-                self.post_process(rendered_index, &ctx.html_config.playpen, ctx.edition);
-            debug!("Creating index.html from {}", ctx_path);
-            utils::fs::write_file(&ctx.destination, "index.html", rendered_index.as_bytes())?;
+    /// What an item of the book adds to the print page (if anything).
+    fn print_item(&self, item: &BookItem, ctx: &RenderItemContext<'_>) -> Option<PrintContent> {
+        if !ctx.html_config.print.enable {
+            return None;
         }
 
-        Ok(())
+        match item {
+            BookItem::Chapter(ch) if !ch.is_draft_chapter() => {
+                let path = ch.path.as_ref().unwrap();
+                // The IDs and the links to the chapters are pointed at the
+                // chapters' parts once the page has them all, and the
+                // footnotes get the chapter's ID with the other IDs
+                let html = utils::render_markdown_with_links(
+                    &ch.content,
+                    Some(path),
+                    LinkStyle::Html,
+                    // The print page has no other titles for the chapters
+                    &MarkdownOptions {
+                        strip_first_h1: false,
+                        ..markdown_options(&ctx.html_config, "")
+                    },
+                );
+                let html = self.post_process(html, &ctx.html_config, ctx.edition, ctx.highlighter);
+                Some(PrintContent::Chapter(path.clone(), html))
+            }
+            BookItem::Separator => Some(PrintContent::Separator),
+            _ => None,
+        }
     }
 
-    #[cfg_attr(feature = "cargo-clippy", allow(clippy::let_and_return))]
+    #[allow(clippy::let_and_return)]
     fn post_process(
         &self,
         rendered: String,
-        playpen_config: &Playpen,
+        html_config: &HtmlConfig,
         edition: Option<RustEdition>,
+        highlighter: Option<&Highlighter>,
     ) -> String {
         let rendered = build_header_links(&rendered);
         let rendered = fix_code_blocks(&rendered);
-        let rendered = add_playpen_pre(&rendered, playpen_config, edition);
+        let rendered = passthrough_code_blocks(&rendered, &html_config.passthrough_code_languages);
+        let rendered = add_playpen_pre(&rendered, edition, &html_config.playpen.crates);
+        let rendered = match highlighter {
+            Some(highlighter) => highlighter.highlight_code_blocks(&rendered),
+            None => rendered,
+        };
 
-        rendered
+        let copyable = html_config.copyable_code && html_config.playpen.copyable;
+        add_code_block_options(&rendered, copyable, html_config.line_numbers)
     }
 
     fn copy_static_files(
@@ -124,14 +435,32 @@ impl HtmlHandlebars {
         destination: &Path,
         theme: &Theme,
         html_config: &HtmlConfig,
+        highlighter: Option<&Highlighter>,
+        uses_editor: bool,
+        shared: Option<&Path>,
     ) -> Result<()> {
-        use crate::utils::fs::write_file;
+        // A translation links to the files of the one in `shared` which are
+        // the same, instead of writing them again
+        let write_file = |destination: &Path, name: &str, contents: &[u8]| -> Result<()> {
+            if let Some(shared) = shared {
+                let file = shared.join(name);
+                if fs::read(&file)
+                    .ok()
+                    .map_or(false, |shared| shared == contents)
+                {
+                    return utils::fs::link_or_copy(&file, &destination.join(name));
+                }
+            }
+            utils::fs::write_file(destination, name, contents)
+        };
 
-        write_file(
-            destination,
-            ".nojekyll",
-            b"This file makes sure that Github Pages doesn't process mdBook's output.",
-        )?;
+        if html_config.nojekyll {
+            write_file(
+                destination,
+                ".nojekyll",
+                b"This file makes sure that Github Pages doesn't process mdBook's output.",
+            )?;
+        }
 
         write_file(destination, "book.js", &theme.js)?;
         write_file(destination, "css/general.css", &theme.general_css)?;
@@ -139,10 +468,21 @@ impl HtmlHandlebars {
         write_file(destination, "css/print.css", &theme.print_css)?;
         write_file(destination, "css/variables.css", &theme.variables_css)?;
         write_file(destination, "favicon.png", &theme.favicon)?;
-        write_file(destination, "highlight.css", &theme.highlight_css)?;
-        write_file(destination, "tomorrow-night.css", &theme.tomorrow_night_css)?;
-        write_file(destination, "ayu-highlight.css", &theme.ayu_highlight_css)?;
-        write_file(destination, "highlight.js", &theme.highlight_js)?;
+        match highlighter {
+            // The stylesheets of syntect's themes take the place of the
+            // highlight.js ones, so book.js still switches between them
+            Some(highlighter) => {
+                for (name, css) in highlighter.stylesheets() {
+                    write_file(destination, name, css.as_bytes())?;
+                }
+            }
+            None => {
+                write_file(destination, "highlight.css", &theme.highlight_css)?;
+                write_file(destination, "tomorrow-night.css", &theme.tomorrow_night_css)?;
+                write_file(destination, "ayu-highlight.css", &theme.ayu_highlight_css)?;
+                write_file(destination, "highlight.js", &theme.highlight_js)?;
+            }
+        }
         write_file(destination, "clipboard.min.js", &theme.clipboard_js)?;
         write_file(
             destination,
@@ -194,10 +534,8 @@ impl HtmlHandlebars {
             )?;
         }
 
-        let playpen_config = &html_config.playpen;
-
         // Ace is a very large dependency, so only load it when requested
-        if playpen_config.editable && playpen_config.copy_js {
+        if uses_editor {
             // Load the editor
             write_file(destination, "editor.js", playpen_editor::JS)?;
             write_file(destination, "ace.js", playpen_editor::ACE_JS)?;
@@ -231,16 +569,96 @@ impl HtmlHandlebars {
         );
     }
 
+    /// Render `book.html`, every chapter of the `book` in a single page, before
+    /// its files are embedded in it. `data` is the data of the book's pages.
+    fn render_single_file(
+        &self,
+        ctx: &RenderContext,
+        book: &Book,
+        mut data: serde_json::Map<String, serde_json::Value>,
+        handlebars: &Handlebars<'_>,
+        html_config: &HtmlConfig,
+        highlighter: Option<&Highlighter>,
+    ) -> Result<String> {
+        let edition = html_config.playpen.effective_edition(&ctx.config.rust);
+        let mut content = String::new();
+        let mut parts = single_file::ChapterParts::new(book);
+
+        for item in book.iter() {
+            match *item {
+                BookItem::Chapter(ref ch) if !ch.is_draft_chapter() => {
+                    let path = ch.path.as_ref().unwrap();
+                    // The footnotes get the chapter's ID with the other IDs
+                    let html = utils::render_markdown_with_links(
+                        &ch.content,
+                        Some(path),
+                        LinkStyle::Html,
+                        &MarkdownOptions {
+                            strip_first_h1: false,
+                            ..markdown_options(html_config, "")
+                        },
+                    );
+                    let html = self.post_process(html, html_config, edition, highlighter);
+                    content.push_str(&parts.add(path, &html));
+                }
+                BookItem::Separator => content.push_str("<hr />\n"),
+                _ => {}
+            }
+        }
+
+        data.insert("path".to_owned(), json!("book.md"));
+        data.insert("content".to_owned(), json!(content));
+        data.insert("path_to_root".to_owned(), json!(""));
+        // The search index is a file of its own
+        data.insert("search_enabled".to_owned(), json!(false));
+        let rendered = handlebars
+            .render("index", &data)
+            .map_err(template_render_error)?;
+
+        Ok(parts.link_chapters(&rendered))
+    }
+
+    /// The templates of the theme, with mdBook's helpers. They're compiled
+    /// once here, so an invalid one is a single error rather than one for
+    /// every page.
+    fn handlebars(&self, theme: &Theme, html_config: &HtmlConfig) -> Result<Handlebars<'static>> {
+        let mut handlebars = Handlebars::new();
+        handlebars.set_strict_mode(html_config.strict_templates);
+
+        debug!("Register the index handlebars template");
+        register_template(&mut handlebars, "index", &theme.index)?;
+
+        debug!("Register the head handlebars template");
+        register_template(&mut handlebars, "head", &theme.head)?;
+
+        debug!("Register the header handlebars template");
+        register_template(&mut handlebars, "header", &theme.header)?;
+
+        for (name, template) in &theme.templates {
+            debug!("Register the {} handlebars template", name);
+            register_template(&mut handlebars, name, template)?;
+        }
+
+        debug!("Register handlebars helpers");
+        self.register_hbs_helpers(&mut handlebars, html_config);
+        Ok(handlebars)
+    }
+
     fn register_hbs_helpers(&self, handlebars: &mut Handlebars<'_>, html_config: &HtmlConfig) {
         handlebars.register_helper(
             "toc",
             Box::new(helpers::toc::RenderToc {
                 no_section_label: html_config.no_section_label,
+                fold: html_config.fold,
+                pretty_urls: html_config.pretty_urls,
             }),
         );
         handlebars.register_helper("previous", Box::new(helpers::navigation::previous));
         handlebars.register_helper("next", Box::new(helpers::navigation::next));
         handlebars.register_helper("theme_option", Box::new(helpers::theme::theme_option));
+        handlebars.register_helper("pagetoc", Box::new(helpers::pagetoc::pagetoc));
+        handlebars.register_helper("resolve", Box::new(helpers::resolve::resolve));
+        handlebars.register_helper("fa", Box::new(helpers::fa::fa));
     }
 
     /// Copy across any additional CSS and JavaScript files which the book
@@ -248,16 +666,23 @@ impl HtmlHandlebars {
     fn copy_additional_css_and_js(
         &self,
         html: &HtmlConfig,
+        chapter_files: &BTreeSet<String>,
         root: &Path,
         destination: &Path,
     ) -> Result<()> {
-        let custom_files = html.additional_css.iter().chain(html.additional_js.iter());
+        let custom_files = html
+            .additional_css
+            .iter()
+            .chain(html.additional_js.iter())
+            .map(PathBuf::as_path)
+            .chain(chapter_files.iter().map(Path::new));
 
         debug!("Copying additional CSS and JS");
 
         for custom_file in custom_files {
-            let input_location = root.join(custom_file);
-            let output_location = destination.join(custom_file);
+            let custom_file = additional_file_location(root, custom_file)?;
+            let input_location = root.join(&custom_file);
+            let output_location = destination.join(&custom_file);
             if let Some(parent) = output_location.parent() {
                 fs::create_dir_all(parent)
                     .with_context(|| format!("Unable to create {}", parent.display()))?;
@@ -306,24 +731,57 @@ impl Renderer for HtmlHandlebars {
     }
 
     fn render(&self, ctx: &RenderContext) -> Result<()> {
-        let html_config = ctx.config.html_config().unwrap_or_default();
-        let src_dir = ctx.root.join(&ctx.config.book.src);
+        let mut html_config = ctx
+            .config
+            .html_config_checked_with(&ctx.diagnostics)?
+            .unwrap_or_default();
+        let src_dir = ctx.source_dir();
+        // Every translation of a book is at a directory of its own
+        let translation = translation(&ctx.config);
+        if let Some(ref code) = translation {
+            if let Some(ref mut site_url) = html_config.site_url {
+                *site_url = format!("{}/{}/", site_url.trim_end_matches('/'), code);
+            }
+        }
         let destination = &ctx.destination;
-        let book = &ctx.book;
-        let build_dir = ctx.root.join(&ctx.config.build.build_dir);
+        let book = &book_to_render(&ctx.book, &html_config);
+        let build_dir = ctx.build_dir();
+
+        // The post-processors registered with the library aren't part of the
+        // hashes of the pages, so they're all rendered again
+        let incremental = html_config.incremental && ctx.post_processors.is_empty();
+        let previous_build = if incremental {
+            RenderCache::load(destination)
+        } else {
+            if destination.exists() {
+                utils::fs::remove_dir_content(destination)
+                    .with_context(|| "Unable to remove stale HTML output")?;
+            }
+            RenderCache::default()
+        };
 
-        if destination.exists() {
-            utils::fs::remove_dir_content(destination)
-                .with_context(|| "Unable to remove stale HTML output")?;
+        trace!("render");
+        if let Some(ref edit_url_template) = html_config.edit_url_template {
+            ensure!(
+                edit_url_template.contains("{path}"),
+                "`output.html.edit-url-template` must contain a `{{path}}` placeholder, got \"{}\"",
+                edit_url_template
+            );
         }
 
-        trace!("render");
-        let mut handlebars = Handlebars::new();
+        if let Some(ref cname) = html_config.cname {
+            ensure!(
+                is_bare_domain(cname),
+                "`output.html.cname` should be a domain like `docs.example.com`, without a \
+                 scheme or path, got \"{}\"",
+                cname
+            );
+        }
 
-        let theme_dir = match html_config.theme {
-            Some(ref theme) => theme.to_path_buf(),
-            None => ctx.root.join("theme"),
-        };
+        let theme_dir = html_config.theme_dir(&ctx.root);
+        if html_config.theme.is_some() && !theme_dir.is_dir() {
+            bail!("The theme directory doesn't exist: {}", theme_dir.display());
+        }
 
         if html_config.theme.is_none()
             && maybe_wrong_theme_dir(&src_dir.join("theme")).unwrap_or(false)
@@ -335,127 +793,732 @@ impl Renderer for HtmlHandlebars {
             warn!("Please move your theme files to `./theme` for them to continue being used");
         }
 
-        let theme = theme::Theme::new(theme_dir);
-
-        debug!("Register the index handlebars template");
-        handlebars.register_template_string("index", String::from_utf8(theme.index.clone())?)?;
+        let timings = &ctx.timings;
+        let theme = timings.time("loading the theme", || {
+            theme::Theme::try_new_with(theme_dir, &ctx.diagnostics)
+        })?;
+        let highlighter = highlighter(&html_config, &ctx.root)?;
+        let post_processors = ctx
+            .post_processors
+            .with_configured(&html_config, ctx.root.clone())?;
+        let handlebars = self.handlebars(&theme, &html_config)?;
+        // The translations share the theme's files of the default language,
+        // which is rendered first
+        let shared = match (&translation, destination.parent()) {
+            (Some(code), Some(site_root)) => match ctx.config.default_language() {
+                Some(default) if default != code => Some(site_root.join(default)),
+                _ => None,
+            },
+            _ => None,
+        };
+        // Whether the editor is loaded, by the pages and into the output
+        let uses_editor = uses_editor(book, &html_config.playpen);
+        let (mut data, last_modified) = book_data(ctx, book, &html_config, &theme, uses_editor)?;
 
-        debug!("Register the head handlebars template");
-        handlebars.register_partial("head", String::from_utf8(theme.head.clone())?)?;
+        fs::create_dir_all(destination)
+            .with_context(|| "Unexpected error when constructing destination path")?;
 
-        debug!("Register the header handlebars template");
-        handlebars.register_partial("header", String::from_utf8(theme.header.clone())?)?;
+        let mut cache = RenderCache {
+            global: cache::global_hash(&data, &html_config, &theme, &ctx.root)?,
+            pages: BTreeMap::new(),
+        };
+        let global_changed = cache.global != previous_build.global;
+
+        let positions = positions(ctx, book, &html_config)?;
+        let mut items = Vec::new();
+        // The chapters of the pages, to catch two chapters sharing one, like
+        // `foo.md` and `foo/index.md` with pretty URLs
+        let mut page_chapters: HashMap<String, &Path> = HashMap::new();
+        let mut chapter_css = BTreeSet::new();
+        let mut chapter_js = BTreeSet::new();
+
+        for (item, position) in book.iter().zip(positions) {
+            let mut item_data = ChapterData::new(&data);
+            let is_index = position.is_index;
+            let (extra_css, extra_js) =
+                insert_item_data(&mut item_data, item, position, ctx, &last_modified)?;
+            chapter_css.extend(extra_css);
+            chapter_js.extend(extra_js);
+
+            let mut needs_render = false;
+            if let BookItem::Chapter(ref ch) = *item {
+                if let Some(ref path) = ch.path {
+                    let page = utils::fs::normalize_path(
+                        utils::fs::chapter_page(path, html_config.pretty_urls)
+                            .to_str()
+                            .with_context(|| "Could not convert path to str")?,
+                    );
+                    let source = ch.source_path.as_ref().unwrap_or(path);
+                    if let Some(other) = page_chapters.insert(page.clone(), source) {
+                        bail!(
+                            "Both {} and {} would be rendered to {}, rename one of them",
+                            other.display(),
+                            source.display(),
+                            page
+                        );
+                    }
+                    let data = cache::hashed_data(&item_data)?;
+                    let inputs: [&[u8]; 4] = [
+                        ch.name.as_bytes(),
+                        ch.content.as_bytes(),
+                        data.as_bytes(),
+                        &[is_index as u8],
+                    ];
+                    let hash = cache::page_hash(&cache.global, &inputs);
+
+                    needs_render = global_changed
+                        || previous_build.pages.get(&page) != Some(&hash)
+                        || !destination.join(&page).exists()
+                        || (is_index && !destination.join("index.html").exists());
+                    cache.pages.insert(page, hash);
+                }
+            }
 
-        debug!("Register handlebars helpers");
-        self.register_hbs_helpers(&mut handlebars, &html_config);
+            let ctx = RenderItemContext::new(
+                ctx,
+                &handlebars,
+                item_data,
+                is_index,
+                &html_config,
+                highlighter.as_ref(),
+                &post_processors,
+            );
+            items.push((item, ctx, needs_render));
+        }
 
-        let mut data = make_data(&ctx.root, &book, &ctx.config, &html_config)?;
+        let (page_404, rendered_404) = timings
+            .time("404 page", || {
+                self.render_404(
+                    ctx,
+                    &html_config,
+                    &data,
+                    &handlebars,
+                    highlighter.as_ref(),
+                    &post_processors,
+                )
+            })
+            .with_context(|| "Unable to render the 404 page")?;
+        let hash = cache::page_hash(&cache.global, &[rendered_404.as_bytes()]);
+        if previous_build.pages.get(&page_404) != Some(&hash)
+            || !destination.join(&page_404).exists()
+        {
+            debug!("Creating {}", page_404);
+            utils::fs::write_file(destination, &page_404, rendered_404.as_bytes())?;
+        }
+        cache.pages.insert(page_404, hash);
+
+        let changed = global_changed
+            || cache.pages != previous_build.pages
+            || items.iter().any(|&(_, _, needs_render)| needs_render);
+
+        // Every item can be rendered on its own, so use all the cores for big
+        // books, but report the first error in reading order. The book's data
+        // is only copied for each of the rayon jobs, not for every chapter
+        let book_context = TemplateContext::wraps(&data).map_err(template_render_error)?;
+        let rendered: Vec<_> = items
+            .into_par_iter()
+            .map_init(
+                || book_context.clone(),
+                |context, (item, ctx, needs_render)| {
+                    let print_content = self.print_item(item, &ctx);
+                    if needs_render {
+                        self.render_item(item, ctx, context)
+                            .with_context(|| match *item {
+                                BookItem::Chapter(ref ch) => {
+                                    format!("Unable to render \"{}\"", ch.name)
+                                }
+                                _ => String::from("Unable to render the book"),
+                            })?;
+                    }
+                    Ok(print_content)
+                },
+            )
+            .collect();
+
+        // Pages of chapters which were removed from the book
+        for page in previous_build.pages.keys() {
+            if !cache.pages.contains_key(page) {
+                let page = destination.join(page);
+                if page.exists() {
+                    debug!("Removing {}", page.display());
+                    fs::remove_file(&page)
+                        .with_context(|| format!("Unable to remove {}", page.display()))?;
+                }
+            }
+        }
 
         // Print version
         let mut print_content = String::new();
-
-        fs::create_dir_all(&destination)
-            .with_context(|| "Unexpected error when constructing destination path")?;
-
-        let mut is_index = true;
-        for item in book.iter() {
-            let ctx = RenderItemContext {
-                handlebars: &handlebars,
-                destination: destination.to_path_buf(),
-                data: data.clone(),
-                is_index,
-                html_config: html_config.clone(),
-                edition: ctx.config.rust.edition,
-            };
-            self.render_item(item, ctx, &mut print_content)?;
-            is_index = false;
+        let mut print_parts = single_file::ChapterParts::new(book);
+        for content in rendered {
+            let content: Result<_> = content;
+            match content? {
+                Some(PrintContent::Separator) => print_content.push_str("<hr />\n"),
+                Some(PrintContent::Chapter(path, html)) => {
+                    if !print_content.is_empty() {
+                        // Each chapter starts on a new page when printed
+                        print_content.push_str(
+                            r#"<div style="break-before: page; page-break-before: always;"></div>"#,
+                        );
+                        print_content.push('\n');
+                    }
+                    print_content.push_str(&print_parts.add(&path, &html));
+                }
+                None => {}
+            }
         }
+        let print_content = print_parts.link_chapters(&print_content);
+
+        // The data of the print page without anything of the print page, for
+        // book.html
+        let single_file_data = if html_config.single_file.enable {
+            Some(data.clone())
+        } else {
+            None
+        };
 
         // Print version
-        self.configure_print_version(&mut data, &print_content);
-        if let Some(ref title) = ctx.config.book.title {
-            data.insert("title".to_owned(), json!(title));
+        if html_config.print.enable && changed {
+            timings.time("print page", || -> Result<()> {
+                self.configure_print_version(&mut data, &print_content);
+                // The print page has every chapter, so it needs all their files
+                data.insert("extra_css".to_owned(), json!(chapter_css));
+                data.insert("extra_js".to_owned(), json!(chapter_js));
+                let title = ctx.config.book.title.clone().unwrap_or_default();
+                data.insert("title".to_owned(), json!(title));
+
+                // Render the handlebars template with the data
+                debug!("Render template");
+                let rendered = handlebars
+                    .render("index", &data)
+                    .map_err(template_render_error)?;
+
+                // mdBook's own post-processing is done on the chapters' parts
+                let rendered = post_processors.process_page(&title, "print.md", rendered)?;
+                utils::fs::write_file(destination, "print.html", rendered.as_bytes())?;
+                debug!("Creating print.html ✓");
+                Ok(())
+            })?;
         }
 
-        // Render the handlebars template with the data
-        debug!("Render template");
-        let rendered = handlebars.render("index", &data)?;
-
-        let rendered = self.post_process(rendered, &html_config.playpen, ctx.config.rust.edition);
-
-        utils::fs::write_file(&destination, "print.html", rendered.as_bytes())?;
-        debug!("Creating print.html ✓");
-
-        debug!("Copy static files");
-        self.copy_static_files(&destination, &theme, &html_config)
-            .with_context(|| "Unable to copy across static files")?;
-        self.copy_additional_css_and_js(&html_config, &ctx.root, &destination)
-            .with_context(|| "Unable to copy across additional CSS and JS")?;
+        timings.time("copying the theme", || -> Result<()> {
+            if global_changed {
+                debug!("Copy static files");
+                self.copy_static_files(
+                    destination,
+                    &theme,
+                    &html_config,
+                    highlighter.as_ref(),
+                    uses_editor,
+                    shared.as_ref().map(PathBuf::as_path),
+                )
+                .with_context(|| "Unable to copy across static files")?;
+            }
+            let chapter_files = chapter_css.union(&chapter_js).cloned().collect();
+            self.copy_additional_css_and_js(&html_config, &chapter_files, &ctx.root, destination)
+                .with_context(|| "Unable to copy across additional CSS and JS")
+        })?;
 
         // Render search index
         #[cfg(feature = "search")]
         {
-            let search = html_config.search.unwrap_or_default();
-            if search.enable {
-                super::search::create_files(&search, &destination, &book)?;
+            let search = html_config.search.clone().unwrap_or_default();
+            if search.enable && changed {
+                timings.time("search index", || {
+                    super::search::create_files(&search, destination, book, &html_config)
+                })?;
+            }
+        }
+
+        if let Some(ref site_url) = html_config.site_url {
+            // A site URL which is only a path (already warned about) has no
+            // absolute URLs for the sitemap
+            if html_config.sitemap && Url::parse(site_url).is_ok() {
+                timings.time("sitemap", || {
+                    let sitemap = sitemap::create_sitemap(
+                        book,
+                        site_url,
+                        &src_dir,
+                        &last_modified,
+                        html_config.pretty_urls,
+                    )?;
+                    utils::fs::write_file(destination, "sitemap.xml", sitemap.as_bytes())
+                })?;
             }
         }
 
         // Copy all remaining files, avoid a recursive copy from/to the book build dir
-        utils::fs::copy_files_except_ext(&src_dir, &destination, true, Some(&build_dir), &["md"])?;
+        timings.time("copying the static files", || -> Result<()> {
+            // The book may have been put together in memory, without one
+            if src_dir.is_dir() {
+                copy_static_dir(&src_dir, destination, &build_dir, &ctx.root, &["md"])?;
+            }
+            for dir in &html_config.extra_static_dirs {
+                let location = match relative_to_root(&ctx.root, dir) {
+                    Some(ref location) if ctx.root.join(location).is_dir() => location.clone(),
+                    Some(_) => bail!("The extra static directory {} doesn't exist", dir.display()),
+                    None => bail!(
+                        "The extra static directory {} isn't inside the book root",
+                        dir.display()
+                    ),
+                };
+                let output = destination.join(&location);
+                copy_static_dir(
+                    &ctx.root.join(&location),
+                    &output,
+                    &build_dir,
+                    &ctx.root,
+                    &[],
+                )
+                .with_context(|| format!("Unable to copy across {}", dir.display()))?;
+            }
+            Ok(())
+        })?;
+
+        // After the static files are copied, so book.html can embed them
+        if let Some(mut data) = single_file_data {
+            if changed || !destination.join("book.html").exists() {
+                data.insert("extra_css".to_owned(), json!(chapter_css));
+                data.insert("extra_js".to_owned(), json!(chapter_js));
+                let title = ctx.config.book.title.clone().unwrap_or_default();
+                data.insert("title".to_owned(), json!(title));
+                timings.time("single file", || -> Result<()> {
+                    let rendered = self
+                        .render_single_file(
+                            ctx,
+                            book,
+                            data,
+                            &handlebars,
+                            &html_config,
+                            highlighter.as_ref(),
+                        )
+                        .and_then(|rendered| {
+                            post_processors.process_page(&title, "book.md", rendered)
+                        })
+                        .with_context(|| "Unable to render book.html")?;
+                    let rendered = single_file::inline_assets(
+                        &rendered,
+                        destination,
+                        html_config.single_file.max_image_size,
+                        &ctx.diagnostics,
+                    );
+                    utils::fs::write_file(destination, "book.html", rendered.as_bytes())?;
+                    debug!("Creating book.html ✓");
+                    Ok(())
+                })?;
+            }
+        }
 
-        Ok(())
-    }
-}
+        let mut pages: BTreeSet<_> = cache.pages.keys().cloned().collect();
+        pages.insert(String::from("index.html"));
+        if html_config.print.enable {
+            pages.insert(String::from("print.html"));
+        }
+        if html_config.single_file.enable {
+            pages.insert(String::from("book.html"));
+        }
+        redirect::write_redirects(destination, &html_config.redirect, &pages)?;
 
-fn make_data(
-    root: &Path,
-    book: &Book,
-    config: &Config,
-    html_config: &HtmlConfig,
-) -> Result<serde_json::Map<String, serde_json::Value>> {
-    trace!("make_data");
+        // Lets GitHub Pages serve the output directory from a custom domain
+        if let Some(ref cname) = html_config.cname {
+            utils::fs::write_file(destination, "CNAME", format!("{}\n", cname).as_bytes())?;
+        }
 
-    let mut data = serde_json::Map::new();
-    data.insert(
-        "language".to_owned(),
-        json!(config.book.language.clone().unwrap_or_default()),
-    );
-    data.insert(
-        "book_title".to_owned(),
-        json!(config.book.title.clone().unwrap_or_default()),
-    );
-    data.insert(
-        "description".to_owned(),
-        json!(config.book.description.clone().unwrap_or_default()),
-    );
-    data.insert("favicon".to_owned(), json!("favicon.png"));
-    if let Some(ref livereload) = html_config.livereload_url {
-        data.insert("livereload".to_owned(), json!(livereload));
-    }
+        // The directory of the translations opens the default one
+        match (translation, destination.parent()) {
+            (Some(ref code), Some(site_root)) if ctx.config.default_language() == Some(code) => {
+                let index = redirect::redirect_page(&format!("{}/index.html", code));
+                utils::fs::write_file(site_root, "index.html", index.as_bytes())?;
+                if html_config.nojekyll {
+                    fs::copy(destination.join(".nojekyll"), site_root.join(".nojekyll"))?;
+                }
+                if let Some(ref cname) = html_config.cname {
+                    utils::fs::write_file(site_root, "CNAME", format!("{}\n", cname).as_bytes())?;
+                }
+            }
+            _ => {}
+        }
 
-    let default_theme = match html_config.default_theme {
-        Some(ref theme) => theme.to_lowercase(),
-        None => "light".to_string(),
-    };
-    data.insert("default_theme".to_owned(), json!(default_theme));
+        if incremental {
+            cache.save(destination)?;
+        }
 
-    let preferred_dark_theme = match html_config.preferred_dark_theme {
-        Some(ref theme) => theme.to_lowercase(),
-        None => default_theme,
-    };
-    data.insert(
-        "preferred_dark_theme".to_owned(),
-        json!(preferred_dark_theme),
-    );
+        let broken_links = timings.time("checking the links", || {
+            link_check::check_links(
+                book,
+                destination,
+                html_config.curly_quotes,
+                html_config.pretty_urls,
+                &html_config.redirect,
+            )
+        });
+        // The broken links of the chapters are reported by the chapter
+        // they're in, and only the other ones with the pages they're in
+        let mut targets = link_check::Targets::new(book, html_config.pretty_urls);
+        for link in &broken_links {
+            let page = utils::fs::chapter_page(&link.source, html_config.pretty_urls);
+            targets.is_new(&link.link, &page, &page);
+        }
+        let links = if html_config.link_check.enable {
+            timings.time("checking the output links", || {
+                link_check::check_output(
+                    destination,
+                    html_config.site_url.as_ref().map(|url| &url[..]),
+                    &html_config.redirect,
+                    &mut targets,
+                )
+            })?
+        } else {
+            link_check::OutputLinks::default()
+        };
+        let fails = ctx.config.build.strict || html_config.link_check.enable;
+        if fails && !(broken_links.is_empty() && links.broken.is_empty()) {
+            let mut report: String = broken_links
+                .iter()
+                .map(|link| format!("    {}\n", link))
+                .collect();
+            report.push_str(&link_check::report(&links.broken));
+            bail!(
+                "Found {} broken links:\n{}",
+                broken_links.len() + links.broken.len(),
+                report.trim_end()
+            );
+        }
+        for link in broken_links {
+            ctx.diagnostics.warn(
+                Diagnostic::new(
+                    DiagnosticKind::BrokenLink,
+                    format!("Broken link \"{}\" ({})", link.link, link.reason),
+                )
+                .with_chapter(link.source),
+            );
+        }
 
-    // Add google analytics tag
-    if let Some(ref ga) = html_config.google_analytics {
-        data.insert("google_analytics".to_owned(), json!(ga));
+        if html_config.link_check.check_external {
+            let broken = timings.time("checking the external links", || {
+                link_check::check_external(&links.external, &html_config.link_check)
+            })?;
+            for link in broken {
+                ctx.diagnostics.warn(Diagnostic::new(
+                    DiagnosticKind::BrokenLink,
+                    format!(
+                        "Broken link \"{}\" in {} ({})",
+                        link.link,
+                        link.file.display(),
+                        link.reason
+                    ),
+                ));
+            }
+        }
+
+        if let Some(format) = html_config.archive {
+            let path = timings.time("archive", || archive::write_archive(destination, format))?;
+            debug!("Archived the book to {}", path.display());
+            ctx.archives.add(path);
+        }
+
+        if timings.is_enabled() {
+            info!("Timings (the slowest chapters first):\n{}", timings.table());
+            let report = serde_json::to_string_pretty(&timings.to_json())?;
+            utils::fs::write_file(destination, "timings.json", report.as_bytes())?;
+        }
+
+        Ok(())
     }
+}
 
-    if html_config.mathjax_support {
-        data.insert("mathjax_support".to_owned(), json!(true));
+/// The language of the translation being rendered, for a book with
+/// translations.
+fn translation(config: &Config) -> Option<String> {
+    match config.book.language {
+        Some(ref code) if config.language.contains_key(code) => Some(code.clone()),
+        _ => None,
+    }
+}
+
+/// The book as its pages are rendered: without the chapters which are drafts,
+/// and named after their first headings with `strip-first-h1`.
+fn book_to_render(book: &Book, html_config: &HtmlConfig) -> Book {
+    let book = book.without_drafts();
+    if html_config.strip_first_h1 {
+        with_h1_names(book)
+    } else {
+        book
+    }
+}
+
+/// Highlights the code blocks when they aren't left to highlight.js.
+fn highlighter(html_config: &HtmlConfig, root: &Path) -> Result<Option<Highlighter>> {
+    match html_config.highlight {
+        Highlight::Javascript => Ok(None),
+        Highlight::Syntect => Ok(Some(Highlighter::new(&html_config.syntect, root)?)),
+    }
+}
+
+/// The data shared by every page of the book, and when its chapters were last
+/// changed, by their source paths.
+fn book_data(
+    ctx: &RenderContext,
+    book: &Book,
+    html_config: &HtmlConfig,
+    theme: &Theme,
+    uses_editor: bool,
+) -> Result<(
+    serde_json::Map<String, serde_json::Value>,
+    HashMap<PathBuf, String>,
+)> {
+    let mut data = make_data(&ctx.root, book, &ctx.config, html_config)?;
+    if uses_editor {
+        data.insert("playpen_js".to_owned(), json!(true));
+        if html_config.playpen.line_numbers {
+            data.insert("playpen_line_numbers".to_owned(), json!(true));
+        }
+    }
+    // git is only asked once per build, and only for the templates showing it
+    let git_keys = ["git_commit", "git_short_commit", "git_dirty"];
+    if html_config.git_metadata && git_keys.iter().any(|key| templates_use(theme, key)) {
+        if let Some(git) = build_info::git_info(&ctx.root) {
+            data.insert("git_commit".to_owned(), json!(git.commit));
+            data.insert("git_short_commit".to_owned(), json!(git.short_commit));
+            data.insert("git_dirty".to_owned(), json!(git.dirty));
+        }
+    }
+    let last_modified = if html_config.last_modified {
+        build_info::last_modified_dates(&ctx.source_dir(), book)
+    } else {
+        HashMap::new()
+    };
+    insert_last_modified(&mut data, book, &last_modified);
+    Ok((data, last_modified))
+}
+
+/// Whether one of the templates of the `theme` mentions `name`.
+fn templates_use(theme: &Theme, name: &str) -> bool {
+    [&theme.index, &theme.head, &theme.header]
+        .iter()
+        .copied()
+        .chain(theme.templates.values())
+        .any(|template| {
+            template
+                .windows(name.len())
+                .any(|window| window == name.as_bytes())
+        })
+}
+
+/// Where an item is in the book, for the links of its page.
+#[derive(Debug, Default)]
+struct Position {
+    breadcrumbs: Vec<serde_json::Value>,
+    previous: Option<serde_json::Value>,
+    next: Option<serde_json::Value>,
+    /// Whether it's the landing page of the book.
+    is_index: bool,
+    /// The languages of a book with translations, with links to the
+    /// chapter's page in each of them.
+    languages: Option<Vec<serde_json::Value>>,
+}
+
+/// The position of every item in the book, in the same order as `book.iter()`.
+fn positions(ctx: &RenderContext, book: &Book, html_config: &HtmlConfig) -> Result<Vec<Position>> {
+    let navigation = chapter_navigation(book, html_config.pretty_urls)?;
+    let root_crumb = match ctx.config.book.title {
+        Some(ref title) if html_config.breadcrumbs_include_title => {
+            Some(json!({ "name": title, "path": "index.html" }))
+        }
+        _ => None,
+    };
+    let breadcrumbs = chapter_breadcrumbs(book, root_crumb, html_config.pretty_urls)?;
+    let landing = landing_chapter(book, html_config.pretty_urls);
+    let translated = match translation(&ctx.config) {
+        Some(_) => Some(translated_chapters(&ctx.config, &ctx.source_dir())?),
+        None => None,
+    };
+
+    let mut chapter_index = 0;
+    let mut positions = Vec::new();
+    for (item, breadcrumbs) in book.iter().zip(breadcrumbs) {
+        let mut position = Position {
+            breadcrumbs,
+            ..Position::default()
+        };
+        if let BookItem::Chapter(ref ch) = *item {
+            if !ch.is_draft_chapter() {
+                position.is_index = ch.path.as_ref() == landing;
+                if chapter_index > 0 {
+                    position.previous = Some(navigation[chapter_index - 1].clone());
+                }
+                position.next = navigation.get(chapter_index + 1).cloned();
+                chapter_index += 1;
+            }
+            if let Some(ref translated) = translated {
+                let chapter = ch.path.as_ref().map(|path| {
+                    let source = ch.source_path.as_ref().unwrap_or(path);
+                    (path.as_path(), source.as_path())
+                });
+                position.languages = Some(translations(
+                    &ctx.config,
+                    chapter,
+                    translated,
+                    html_config.pretty_urls,
+                ));
+            }
+        }
+        positions.push(position);
+    }
+    Ok(positions)
+}
+
+/// Add the keys of the page of an item to the data of the book: where it is
+/// in the book, and what the frontmatter of its chapter sets. Returns the
+/// extra CSS and JavaScript files of the chapter.
+fn insert_item_data(
+    data: &mut ChapterData<'_>,
+    item: &BookItem,
+    position: Position,
+    ctx: &RenderContext,
+    last_modified: &HashMap<PathBuf, String>,
+) -> Result<(Vec<String>, Vec<String>)> {
+    data.insert("breadcrumbs".to_owned(), json!(position.breadcrumbs));
+    let ch = match *item {
+        BookItem::Chapter(ref ch) => ch,
+        _ => return Ok((Vec::new(), Vec::new())),
+    };
+
+    if let Some(languages) = position.languages {
+        data.insert("languages".to_owned(), json!(languages));
+    }
+    data.insert("frontmatter".to_owned(), json!(ch.metadata));
+    if let Some(date) = ch
+        .source_path
+        .as_ref()
+        .or(ch.path.as_ref())
+        .and_then(|source| last_modified.get(source))
+    {
+        data.insert("last_modified".to_owned(), json!(date));
+    }
+    let extra_css = chapter_assets(ch, "extra-css", &ctx.root)?;
+    let extra_js = chapter_assets(ch, "extra-js", &ctx.root)?;
+    data.insert("extra_css".to_owned(), json!(extra_css));
+    data.insert("extra_js".to_owned(), json!(extra_js));
+    if let Some(description) = ch.metadata.get("description") {
+        data.insert("description".to_owned(), description.clone());
+    }
+    if let Some(previous) = position.previous {
+        data.insert("previous".to_owned(), previous);
+    }
+    if let Some(next) = position.next {
+        data.insert("next".to_owned(), next);
+    }
+    Ok((extra_css, extra_js))
+}
+
+pub(super) fn make_data(
+    root: &Path,
+    book: &Book,
+    config: &Config,
+    html_config: &HtmlConfig,
+) -> Result<serde_json::Map<String, serde_json::Value>> {
+    trace!("make_data");
+
+    let mut data = serde_json::Map::new();
+    let language = config.book.language.clone().unwrap_or_default();
+    data.insert("strings".to_owned(), json!(locales::strings(&language)));
+    data.insert("language".to_owned(), json!(language));
+    if let Some(current) = config.language.get(&language) {
+        let translations = translations(config, None, &HashMap::new(), html_config.pretty_urls);
+        data.insert("languages".to_owned(), json!(translations));
+        data.insert(
+            "current_language".to_owned(),
+            json!({ "code": language, "name": current.name }),
+        );
+    }
+    let text_direction = config.book.realized_text_direction();
+    data.insert("text_direction".to_owned(), json!(text_direction.as_str()));
+    data.insert(
+        "is_rtl".to_owned(),
+        json!(text_direction == TextDirection::Rtl),
+    );
+    data.insert(
+        "book_title".to_owned(),
+        json!(config.book.title.clone().unwrap_or_default()),
+    );
+    data.insert(
+        "description".to_owned(),
+        json!(config.book.description.clone().unwrap_or_default()),
+    );
+    data.insert("favicon".to_owned(), json!("favicon.png"));
+    data.insert(
+        "context".to_owned(),
+        toml_ext::to_json(&toml::Value::Table(html_config.context.clone())),
+    );
+    if let Some(ref livereload) = html_config.livereload_url {
+        data.insert("livereload".to_owned(), json!(livereload));
+    }
+
+    data.insert(
+        "mdbook_version".to_owned(),
+        json!(env!("CARGO_PKG_VERSION")),
+    );
+    data.insert(
+        "build_timestamp".to_owned(),
+        json!(build_info::build_timestamp()),
+    );
+
+    let default_theme = match html_config.default_theme {
+        Some(ref theme) => {
+            color_theme_id("default-theme", theme).unwrap_or_else(|| "light".to_string())
+        }
+        None => "light".to_string(),
+    };
+    data.insert("default_theme".to_owned(), json!(default_theme));
+
+    let preferred_dark_theme = match html_config.preferred_dark_theme {
+        Some(ref theme) => {
+            color_theme_id("preferred-dark-theme", theme).unwrap_or_else(|| default_theme.clone())
+        }
+        None => default_theme,
+    };
+    data.insert(
+        "preferred_dark_theme".to_owned(),
+        json!(preferred_dark_theme),
+    );
+
+    let color_themes: Vec<_> = COLOR_THEMES
+        .iter()
+        .map(|&(id, name)| json!({ "id": id, "name": name }))
+        .collect();
+    data.insert("color_themes".to_owned(), json!(color_themes));
+
+    if let Some(ref head_extra) = html_config.head_extra {
+        data.insert(
+            "head_extra".to_owned(),
+            json!(read_head_extra(root, head_extra)?),
+        );
+    }
+
+    // Add google analytics tag
+    if let Some(ref ga) = html_config.google_analytics {
+        data.insert("google_analytics".to_owned(), json!(ga));
+        if html_config.google_analytics_gtag() {
+            data.insert("google_analytics_gtag".to_owned(), json!(true));
+        }
+    }
+    if html_config.highlight == Highlight::Syntect {
+        data.insert("syntect_highlighting".to_owned(), json!(true));
+    }
+
+    match html_config.math_renderer() {
+        MathRenderer::Mathjax => {
+            data.insert("mathjax_support".to_owned(), json!(true));
+            data.insert(
+                "mathjax_source".to_owned(),
+                json!(html_config.mathjax_source()),
+            );
+        }
+        MathRenderer::Katex => {
+            data.insert("katex_support".to_owned(), json!(true));
+        }
+        MathRenderer::None => {}
     }
 
     if html_config.copy_fonts {
@@ -466,10 +1529,7 @@ fn make_data(
     if !html_config.additional_css.is_empty() {
         let mut css = Vec::new();
         for style in &html_config.additional_css {
-            match style.strip_prefix(root) {
-                Ok(p) => css.push(p.to_str().expect("Could not convert to str")),
-                Err(_) => css.push(style.to_str().expect("Could not convert to str")),
-            }
+            css.push(additional_file_link(root, style)?);
         }
         data.insert("additional_css".to_owned(), json!(css));
     }
@@ -478,24 +1538,25 @@ fn make_data(
     if !html_config.additional_js.is_empty() {
         let mut js = Vec::new();
         for script in &html_config.additional_js {
-            match script.strip_prefix(root) {
-                Ok(p) => js.push(p.to_str().expect("Could not convert to str")),
-                Err(_) => js.push(script.to_str().expect("Could not convert to str")),
-            }
+            js.push(additional_file_link(root, script)?);
         }
         data.insert("additional_js".to_owned(), json!(js));
     }
 
-    if html_config.playpen.editable && html_config.playpen.copy_js {
-        data.insert("playpen_js".to_owned(), json!(true));
-        if html_config.playpen.line_numbers {
-            data.insert("playpen_line_numbers".to_owned(), json!(true));
-        }
-    }
     if html_config.playpen.copyable {
         data.insert("playpen_copyable".to_owned(), json!(true));
     }
+    if let Some(edition) = html_config.playpen.effective_edition(&config.rust) {
+        data.insert("playpen_edition".to_owned(), json!(edition.as_str()));
+    }
+    if !html_config.playpen.crates.is_empty() {
+        data.insert(
+            "playpen_crates".to_owned(),
+            json!(html_config.playpen.crates),
+        );
+    }
 
+    data.insert("print_enable".to_owned(), json!(html_config.print.enable));
     data.insert("fold_enable".to_owned(), json!((html_config.fold.enable)));
     data.insert("fold_level".to_owned(), json!((html_config.fold.level)));
 
@@ -517,15 +1578,19 @@ fn make_data(
 
     if let Some(ref git_repository_url) = html_config.git_repository_url {
         data.insert("git_repository_url".to_owned(), json!(git_repository_url));
-    }
 
-    let git_repository_icon = match html_config.git_repository_icon {
-        Some(ref git_repository_icon) => git_repository_icon,
-        None => "fa-github",
-    };
-    data.insert("git_repository_icon".to_owned(), json!(git_repository_icon));
+        let git_repository_icon = match html_config.git_repository_icon {
+            Some(ref git_repository_icon) => git_repository_icon,
+            None => default_git_repository_icon(git_repository_url),
+        };
+        data.insert("git_repository_icon".to_owned(), json!(git_repository_icon));
+    }
+    let nav_buttons = nav_buttons(html_config, &data);
+    data.insert("nav_buttons".to_owned(), json!(nav_buttons));
 
     let mut chapters = vec![];
+    let reading_time = &html_config.reading_time;
+    let mut book_word_count = 0;
 
     for item in book.iter() {
         // Create the data to inject in the template
@@ -546,11 +1611,25 @@ fn make_data(
                 );
 
                 chapter.insert("name".to_owned(), json!(ch.name));
-                if let Some(ref path) = ch.path {
-                    let p = path
-                        .to_str()
-                        .with_context(|| "Could not convert path to str")?;
-                    chapter.insert("path".to_owned(), json!(p));
+                match ch.path {
+                    Some(ref path) => {
+                        let p = path
+                            .to_str()
+                            .with_context(|| "Could not convert path to str")?;
+                        chapter.insert("path".to_owned(), json!(p));
+
+                        let words = utils::word_count(&ch.content, reading_time.counting);
+                        book_word_count += words;
+                        chapter.insert("word_count".to_owned(), json!(words));
+                        chapter.insert(
+                            "reading_time".to_owned(),
+                            json!(utils::reading_time(words, reading_time)),
+                        );
+                    }
+                    // Draft chapters are listed without a page to link to
+                    None => {
+                        chapter.insert("path".to_owned(), serde_json::Value::Null);
+                    }
                 }
             }
             BookItem::Separator => {
@@ -562,156 +1641,933 @@ fn make_data(
     }
 
     data.insert("chapters".to_owned(), json!(chapters));
+    data.insert("book_word_count".to_owned(), json!(book_word_count));
+    data.insert(
+        "book_reading_time".to_owned(),
+        json!(utils::reading_time(book_word_count, reading_time)),
+    );
+    // Only the chapters' own pages have headings of their own
+    data.insert("page_headings".to_owned(), json!([]));
 
     debug!("[*]: JSON constructed");
     Ok(data)
 }
 
-/// Goes through the rendered HTML, making sure all header tags have
-/// an anchor respectively so people can link to sections directly.
-fn build_header_links(html: &str) -> String {
-    let regex = Regex::new(r"<h(\d)>(.*?)</h\d>").unwrap();
-    let mut id_counter = HashMap::new();
+/// The path of the chapter which is the landing page of the book, marked with
+/// `is_index`: the one at `index.html`, or else the first one, which is also
+/// written there.
+pub(super) fn landing_chapter(book: &Book, pretty_urls: bool) -> Option<&PathBuf> {
+    let paths: Vec<&PathBuf> = book
+        .iter()
+        .filter_map(|item| match *item {
+            BookItem::Chapter(ref ch) => ch.path.as_ref(),
+            _ => None,
+        })
+        .collect();
 
-    regex
-        .replace_all(html, |caps: &Captures<'_>| {
-            let level = caps[1]
-                .parse()
-                .expect("Regex should ensure we only ever get numbers here");
+    paths
+        .iter()
+        .find(|path| utils::fs::chapter_page(path, pretty_urls) == Path::new("index.html"))
+        .or(paths.first())
+        .cloned()
+}
 
-            insert_link_into_header(level, &caps[2], &mut id_counter)
-        })
-        .into_owned()
+/// Add the `last_modified` dates, by source path, to the `chapters` of the
+/// data, which has one for every item of `book`.
+fn insert_last_modified(
+    data: &mut serde_json::Map<String, serde_json::Value>,
+    book: &Book,
+    dates: &HashMap<PathBuf, String>,
+) {
+    if let Some(serde_json::Value::Array(chapters)) = data.get_mut("chapters") {
+        for (item, chapter) in book.iter().zip(chapters) {
+            let source = match *item {
+                BookItem::Chapter(ref ch) => ch.source_path.as_ref().or(ch.path.as_ref()),
+                _ => None,
+            };
+            if let (Some(date), Some(chapter)) = (
+                source.and_then(|source| dates.get(source)),
+                chapter.as_object_mut(),
+            ) {
+                chapter.insert("last_modified".to_owned(), json!(date));
+            }
+        }
+    }
 }
 
-/// Insert a sinle link into a header, making sure each link gets its own
-/// unique ID by appending an auto-incremented number (if necessary).
-fn insert_link_into_header(
-    level: usize,
-    content: &str,
-    id_counter: &mut HashMap<String, usize>,
-) -> String {
-    let raw_id = utils::id_from_content(content);
+/// Create the `{"title": ..., "link": ...}` objects used for the previous and
+/// next links of each rendered chapter, in reading order.
+fn chapter_navigation(book: &Book, pretty_urls: bool) -> Result<Vec<serde_json::Value>> {
+    let mut navigation = Vec::new();
+
+    for item in book.iter() {
+        if let BookItem::Chapter(ref ch) = *item {
+            if let Some(ref path) = ch.path {
+                let link = page_link(path, pretty_urls)?;
+                navigation.push(json!({ "title": ch.name, "link": link }));
+            }
+        }
+    }
 
-    let id_count = id_counter.entry(raw_id.clone()).or_insert(0);
+    Ok(navigation)
+}
 
-    let id = match *id_count {
-        0 => raw_id,
-        other => format!("{}-{}", raw_id, other),
+/// The HTML to inject into the `<head>` of every page.
+fn read_head_extra(root: &Path, head_extra: &HeadExtra) -> Result<String> {
+    let html = match *head_extra {
+        HeadExtra::Html(ref html) => html.clone(),
+        HeadExtra::File { ref file } => {
+            let path = root.join(file);
+            fs::read_to_string(&path).with_context(|| {
+                format!(
+                    "Unable to read `output.html.head-extra` from {}",
+                    path.display()
+                )
+            })?
+        }
     };
 
-    *id_count += 1;
+    ensure!(
+        !html.to_lowercase().contains("</head>"),
+        "`output.html.head-extra` can't contain `</head>`, it's already inside the page's <head>"
+    );
+    Ok(html)
+}
 
-    format!(
-        r##"<h{level}><a class="header" href="#{id}" id="{id}">{text}</a></h{level}>"##,
-        level = level,
-        id = id,
-        text = content
-    )
+/// Copy the files of a directory (except those with an extension in
+/// `ext_blacklist`) to `destination`, skipping the build directory and `.git`
+/// directories.
+fn copy_static_dir(
+    dir: &Path,
+    destination: &Path,
+    build_dir: &Path,
+    root: &Path,
+    ext_blacklist: &[&str],
+) -> Result<()> {
+    let mut ignore = vec![String::from("**/.git")];
+    if let Ok(build_dir) = build_dir.strip_prefix(dir) {
+        ignore.push(build_dir.to_string_lossy().into_owned());
+    }
+
+    let options = CopyOptions {
+        ext_blacklist,
+        ignore: &ignore,
+        symlink_root: Some(root),
+        ..Default::default()
+    };
+    utils::fs::copy_dir(dir, destination, &options)
 }
 
-// The rust book uses annotations for rustdoc to test code snippets,
-// like the following:
-// ```rust,should_panic
-// fn main() {
-//     // Code here
-// }
-// ```
-// This function replaces all commas by spaces in the code block classes
-fn fix_code_blocks(html: &str) -> String {
-    let regex = Regex::new(r##"<code([^>]+)class="([^"]+)"([^>]*)>"##).unwrap();
-    regex
-        .replace_all(html, |caps: &Captures<'_>| {
-            let before = &caps[1];
-            let classes = &caps[2].replace(",", " ");
-            let after = &caps[3];
+/// Where an `additional-css` or `additional-js` file is, relative to the book
+/// root. The file is copied to the same place in the destination, so it has
+/// to be inside the book.
+fn additional_file_location(root: &Path, file: &Path) -> Result<PathBuf> {
+    let relative = relative_to_root(root, file).with_context(|| {
+        format!(
+            "The additional file {} isn't inside the book root",
+            file.display()
+        )
+    })?;
+    ensure!(
+        root.join(&relative).is_file(),
+        "Unable to find the additional file {}",
+        file.display()
+    );
 
-            format!(
-                r#"<code{before}class="{classes}"{after}>"#,
-                before = before,
-                classes = classes,
-                after = after
-            )
-        })
-        .into_owned()
+    Ok(relative)
 }
 
-fn add_playpen_pre(html: &str, playpen_config: &Playpen, edition: Option<RustEdition>) -> String {
-    let regex = Regex::new(r##"((?s)<code[^>]?class="([^"]+)".*?>(.*?)</code>)"##).unwrap();
-    regex
-        .replace_all(html, |caps: &Captures<'_>| {
-            let text = &caps[1];
-            let classes = &caps[2];
-            let code = &caps[3];
+/// A path relative to the book root (or an absolute path inside of it), as a
+/// path relative to the book root. `None` if it points outside of the book.
+fn relative_to_root(root: &Path, path: &Path) -> Option<PathBuf> {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let inside_root = relative.is_relative()
+        && !relative.has_root()
+        && !relative.components().any(|c| c == Component::ParentDir);
 
-            if classes.contains("language-rust") {
-                if (!classes.contains("ignore") && !classes.contains("noplaypen"))
-                    || classes.contains("mdbook-runnable")
-                {
-                    let contains_e2015 = classes.contains("edition2015");
-                    let contains_e2018 = classes.contains("edition2018");
-                    let edition_class = if contains_e2015 || contains_e2018 {
-                        // the user forced edition, we should not overwrite it
-                        ""
-                    } else {
-                        match edition {
-                            Some(RustEdition::E2015) => " edition2015",
-                            Some(RustEdition::E2018) => " edition2018",
-                            None => "",
-                        }
-                    };
+    if inside_root {
+        Some(relative.to_path_buf())
+    } else {
+        None
+    }
+}
 
-                    // wrap the contents in an external pre block
-                    format!(
-                        "<pre class=\"playpen\"><code class=\"{}{}\">{}</code></pre>",
-                        classes,
-                        edition_class,
-                        {
-                            let content: Cow<'_, str> = if playpen_config.editable
-                                && classes.contains("editable")
-                                || text.contains("fn main")
-                                || text.contains("quick_main!")
-                            {
-                                code.into()
-                            } else {
-                                // we need to inject our own main
-                                let (attrs, code) = partition_source(code);
+/// The link to an `additional-css` or `additional-js` file, relative to the
+/// root of the rendered book.
+fn additional_file_link(root: &Path, file: &Path) -> Result<String> {
+    let location = additional_file_location(root, file)?;
+    Ok(location
+        .to_str()
+        .with_context(|| "Could not convert path to str")?
+        .replace("\\", "/"))
+}
 
-                                format!(
-                                    "\n# #![allow(unused)]\n{}#fn main() {{\n{}#}}",
-                                    attrs, code
-                                )
-                                .into()
-                            };
-                            hide_lines(&content)
-                        }
-                    )
-                } else {
-                    format!("<code class=\"{}\">{}</code>", classes, hide_lines(code))
-                }
-            } else {
-                // not language-rust, so no-op
-                text.to_owned()
-            }
+/// Compile one of the theme's templates, like `header` from `header.hbs`,
+/// and register it under its name.
+fn register_template(handlebars: &mut Handlebars<'_>, name: &str, template: &[u8]) -> Result<()> {
+    let template = std::str::from_utf8(template)
+        .with_context(|| format!("The template {}.hbs isn't valid UTF-8", name))?;
+    handlebars
+        .register_template_string(name, template)
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid template {}.hbs{}: {}",
+                name,
+                template_location(e.line_no, e.column_no),
+                e.reason
+            )
         })
-        .into_owned()
 }
 
-lazy_static! {
-    static ref BORING_LINES_REGEX: Regex = Regex::new(r"^(\s*)#(.?)(.*)$").unwrap();
+/// The template a chapter is rendered with: the `template` of its
+/// frontmatter, or `index`.
+fn chapter_template<'a>(ch: &'a Chapter, handlebars: &Handlebars<'_>) -> Result<&'a str> {
+    let template = match ch.metadata.get("template") {
+        Some(template) => template.as_str().with_context(|| {
+            format!(
+                "`template` in the frontmatter of \"{}\" should be the name of a template",
+                ch.name
+            )
+        })?,
+        None => return Ok("index"),
+    };
+    ensure!(
+        handlebars.get_template(template).is_some(),
+        "The template \"{}\" of \"{}\" isn't in the theme, there's no {}.hbs",
+        template,
+        ch.name,
+        template
+    );
+    Ok(template)
 }
 
-fn hide_lines(content: &str) -> String {
-    let mut result = String::with_capacity(content.len());
-    for line in content.lines() {
-        if let Some(caps) = BORING_LINES_REGEX.captures(line) {
-            if &caps[2] == "#" {
-                result += &caps[1];
-                result += &caps[2];
+/// The error of rendering a page, with the template file (like
+/// `header.hbs`) and the line and column it happened at.
+fn template_render_error(e: handlebars::RenderError) -> Error {
+    match e.template_name {
+        Some(ref name) => anyhow::anyhow!(
+            "Error in the template {}.hbs{}: {}",
+            name,
+            template_location(e.line_no, e.column_no),
+            e.desc
+        ),
+        None => e.into(),
+    }
+}
+
+fn template_location(line: Option<usize>, column: Option<usize>) -> String {
+    match (line, column) {
+        (Some(line), Some(column)) => format!(", line {}, column {}", line, column),
+        (Some(line), None) => format!(", line {}", line),
+        _ => String::new(),
+    }
+}
+
+/// The files listed under `key` (`extra-css` or `extra-js`) in a chapter's
+/// frontmatter, relative to the book root.
+fn chapter_assets(ch: &Chapter, key: &str, root: &Path) -> Result<Vec<String>> {
+    let value = match ch.metadata.get(key) {
+        Some(value) => value,
+        None => return Ok(Vec::new()),
+    };
+    let files = value
+        .as_array()
+        .and_then(|files| {
+            files
+                .iter()
+                .map(serde_json::Value::as_str)
+                .collect::<Option<Vec<_>>>()
+        })
+        .with_context(|| {
+            format!(
+                "`{}` in the frontmatter of \"{}\" should be a list of paths",
+                key, ch.name
+            )
+        })?;
+
+    files
+        .into_iter()
+        .map(|file| {
+            let path = Path::new(file);
+            let inside_root = path.is_relative()
+                && !path.has_root()
+                && !path.components().any(|c| c == Component::ParentDir);
+            ensure!(
+                inside_root,
+                "The {} file \"{}\" of \"{}\" isn't inside the book",
+                key,
+                file,
+                ch.name
+            );
+            ensure!(
+                root.join(file).is_file(),
+                "Unable to find the {} file \"{}\" of \"{}\"",
+                key,
+                file,
+                ch.name
+            );
+            Ok(file.replace("\\", "/"))
+        })
+        .collect()
+}
+
+/// The book with the chapters which start with an H1 named after it, for
+/// `output.html.strip-first-h1`.
+fn with_h1_names(mut book: Book) -> Book {
+    /// `names` are the (new) names of the chapters containing `items`.
+    fn rename(items: &mut [BookItem], names: &mut Vec<String>) {
+        for item in items {
+            if let BookItem::Chapter(ref mut ch) = *item {
+                let depth = ch.parent_names.len();
+                if names.len() <= depth {
+                    ch.parent_names[depth - names.len()..].clone_from_slice(names);
+                }
+                if let Some(name) = utils::leading_h1(&ch.content) {
+                    ch.name = name;
+                }
+
+                names.push(ch.name.clone());
+                rename(&mut ch.sub_items, names);
+                names.pop();
+            }
+        }
+    }
+
+    rename(book.sections_mut(), &mut Vec::new());
+    book
+}
+
+/// The breadcrumbs of every item in the book, in the same order as
+/// `book.iter()`. Each item gets the `{name, path}` of the part and chapters
+/// containing it, outermost first, where parts and draft chapters don't have
+/// a path. The prefix and suffix chapters aren't in any part.
+fn chapter_breadcrumbs(
+    book: &Book,
+    root: Option<serde_json::Value>,
+    pretty_urls: bool,
+) -> Result<Vec<Vec<serde_json::Value>>> {
+    let mut breadcrumbs = Vec::new();
+    let mut parents: Vec<_> = root.into_iter().collect();
+    let outside_parts = parents.len();
+
+    for item in &book.sections {
+        if let BookItem::PartTitle(ref title) = *item {
+            parents.truncate(outside_parts);
+            breadcrumbs.push(parents.clone());
+            parents.push(json!({ "name": title, "path": null }));
+        } else {
+            if let BookItem::Chapter(Chapter { number: None, .. }) = *item {
+                // The suffix chapters, after the last part, aren't in it
+                parents.truncate(outside_parts);
+            }
+            item_breadcrumbs(item, &mut parents, &mut breadcrumbs, pretty_urls)?;
+        }
+    }
+
+    Ok(breadcrumbs)
+}
+
+fn item_breadcrumbs(
+    item: &BookItem,
+    parents: &mut Vec<serde_json::Value>,
+    breadcrumbs: &mut Vec<Vec<serde_json::Value>>,
+    pretty_urls: bool,
+) -> Result<()> {
+    breadcrumbs.push(parents.clone());
+
+    if let BookItem::Chapter(ref ch) = *item {
+        let path = match ch.path {
+            Some(ref path) => json!(page_link(path, pretty_urls)?),
+            None => serde_json::Value::Null,
+        };
+        parents.push(json!({ "name": ch.name, "path": path }));
+        for sub_item in &ch.sub_items {
+            item_breadcrumbs(sub_item, parents, breadcrumbs, pretty_urls)?;
+        }
+        parents.pop();
+    }
+
+    Ok(())
+}
+
+/// The source files of the chapters of every translation, by its language,
+/// as they're listed in its `SUMMARY.md`. `src_dir` is the source directory
+/// of the translation being rendered.
+fn translated_chapters(
+    config: &Config,
+    src_dir: &Path,
+) -> Result<HashMap<String, HashSet<PathBuf>>> {
+    // The source of the translation is in the directory of its language
+    let translations_dir = src_dir.parent().unwrap_or(src_dir);
+    let mut translated = HashMap::new();
+    for code in config.language.keys() {
+        let summary = translations_dir.join(code).join("SUMMARY.md");
+        let chapters = match fs::read_to_string(&summary) {
+            Ok(summary) => summary_chapters(&book::parse_summary(&summary)?),
+            // A book put together in memory
+            Err(_) => HashSet::new(),
+        };
+        translated.insert(code.clone(), chapters);
+    }
+    Ok(translated)
+}
+
+/// The source files of the chapters listed in a `SUMMARY.md`.
+fn summary_chapters(summary: &Summary) -> HashSet<PathBuf> {
+    fn add(items: &[SummaryItem], chapters: &mut HashSet<PathBuf>) {
+        for item in items {
+            if let SummaryItem::Link(ref link) = *item {
+                chapters.extend(link.location.clone());
+                add(&link.nested_items, chapters);
+            }
+        }
+    }
+
+    let mut chapters = HashSet::new();
+    add(&summary.prefix_chapters, &mut chapters);
+    add(&summary.numbered_chapters, &mut chapters);
+    add(&summary.suffix_chapters, &mut chapters);
+    chapters
+}
+
+/// The languages of a book with translations, with links (relative to the
+/// root of the translation) to the chapter in each of them. `chapter` is the
+/// path of the chapter and the source file it's from, and `translated` the
+/// source files of the chapters of every translation. Translations without
+/// that chapter, or pages which aren't chapters, link to the translation's
+/// index.
+fn translations(
+    config: &Config,
+    chapter: Option<(&Path, &Path)>,
+    translated: &HashMap<String, HashSet<PathBuf>>,
+    pretty_urls: bool,
+) -> Vec<serde_json::Value> {
+    let current = config.book.language.as_ref().map_or("", |code| &code[..]);
+
+    config
+        .language
+        .iter()
+        .map(|(code, language)| {
+            let in_translation = |source: &Path| {
+                translated
+                    .get(code)
+                    .map_or(false, |chapters| chapters.contains(source))
+            };
+            let page = match chapter {
+                Some((path, source)) if code == current || in_translation(source) => {
+                    utils::fs::chapter_link(path, pretty_urls)
+                }
+                _ => String::from("index.html"),
+            };
+            json!({
+                "code": code,
+                "name": language.name,
+                "is_current": code == current,
+                "link": format!("../{}/{}", code, page),
+            })
+        })
+        .collect()
+}
+
+/// The link to a chapter's page, relative to the root of the book. With
+/// pretty URLs that's the directory of the page.
+fn page_link(path: &Path, pretty_urls: bool) -> Result<String> {
+    ensure!(path.to_str().is_some(), "Could not convert path to str");
+    Ok(utils::fs::chapter_link(path, pretty_urls))
+}
+
+/// How the links of the chapter at `path` to the other chapters are written.
+pub(super) fn chapter_link_style(path: &Path, pretty_urls: bool) -> LinkStyle {
+    if pretty_urls {
+        LinkStyle::Pretty {
+            nested: utils::fs::chapter_page(path, true) != path.with_extension("html"),
+        }
+    } else {
+        LinkStyle::Html
+    }
+}
+
+/// How the chapters are rendered to HTML, with `footnotes` as the start of the
+/// IDs of their footnotes.
+fn markdown_options<'a>(html_config: &'a HtmlConfig, footnotes: &'a str) -> MarkdownOptions<'a> {
+    MarkdownOptions {
+        curly_quotes: html_config.curly_quotes,
+        tables: html_config.tables,
+        strikethrough: html_config.strikethrough,
+        task_lists: html_config.task_lists,
+        admonitions: Some(&html_config.admonitions),
+        footnotes: Some(footnotes),
+        strip_first_h1: html_config.strip_first_h1,
+        heading_offset: html_config.heading_offset,
+        math: html_config.math_renderer() != MathRenderer::None,
+        dollar_math: html_config.math_renderer() == MathRenderer::Katex,
+    }
+}
+
+/// The start of the IDs of a chapter's footnotes, like `first-nested` for
+/// `first/nested.md`, so they're unique on the print page too.
+fn footnote_prefix(path: &Path) -> String {
+    let path = path.with_extension("");
+    let path = path.to_string_lossy().replace(&['/', '\\'][..], "-");
+    utils::normalize_id(&path)
+}
+
+/// Whether `domain` is just a domain name, like `docs.example.com`.
+fn is_bare_domain(domain: &str) -> bool {
+    lazy_static! {
+        static ref DOMAIN: Regex = Regex::new(
+            r"^[a-zA-Z0-9]([a-zA-Z0-9-]*[a-zA-Z0-9])?(\.[a-zA-Z0-9]([a-zA-Z0-9-]*[a-zA-Z0-9])?)*$"
+        )
+        .unwrap();
+    }
+
+    domain.len() <= 253 && DOMAIN.is_match(domain)
+}
+
+/// The absolute path the book is served from, like `/book/`, going by its
+/// `site-url`. Books without one are expected to be at the root of the site.
+pub(super) fn site_url_path(site_url: Option<&str>) -> String {
+    let path = match site_url.map(Url::parse) {
+        Some(Ok(url)) => url.path().to_string(),
+        Some(Err(_)) => site_url.unwrap_or_default().to_string(),
+        None => String::new(),
+    };
+
+    let mut path = path.trim_end_matches('/').to_string();
+    if !path.starts_with('/') {
+        path.insert(0, '/');
+    }
+    if path != "/" {
+        path.push('/');
+    }
+    path
+}
+
+/// The absolute URL of a page of the book, where `page` is relative to the
+/// root of the book, or `None` when `site_url` isn't an absolute URL. Pages
+/// called `index.html` are referred to by their directory.
+pub(super) fn page_url(site_url: &str, page: &str) -> Option<String> {
+    let page = if page == "index.html" {
+        ""
+    } else if page.ends_with("/index.html") {
+        &page[..page.len() - "index.html".len()]
+    } else {
+        page
+    };
+
+    // The pages are under the site URL, whether it ends with a slash or not
+    let mut base = Url::parse(site_url).ok()?;
+    let path = format!("{}/", base.path().trim_end_matches('/'));
+    base.set_path(&path);
+    base.join(page).ok().map(|url| url.to_string())
+}
+
+/// Add the canonical URL and the Open Graph values of a page to its context.
+fn insert_open_graph(data: &mut ChapterData<'_>, url: &str, title: &str) {
+    let description = data
+        .get("description")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or("")
+        .to_string();
+
+    let mut open_graph = json!({ "title": title, "url": url });
+    if !description.is_empty() {
+        open_graph["description"] = json!(description);
+    }
+    data.insert("canonical_url".to_owned(), json!(url));
+    data.insert("open_graph".to_owned(), open_graph);
+}
+
+/// The buttons of the menu bar, `{id, icon, url, title}`: the print and git
+/// repository ones, then the `nav-buttons`. The URLs are relative to the
+/// book's root, for the `resolve` helper.
+fn nav_buttons(
+    html_config: &HtmlConfig,
+    data: &serde_json::Map<String, serde_json::Value>,
+) -> Vec<serde_json::Value> {
+    let strings = &data["strings"];
+    let mut buttons = Vec::new();
+    if html_config.print.enable {
+        buttons.push(json!({
+            "id": "print-button",
+            "icon": "fa-print",
+            "url": "print.html",
+            "title": strings["print"],
+        }));
+    }
+    if let Some(ref git_repository_url) = html_config.git_repository_url {
+        buttons.push(json!({
+            "id": "git-repository-button",
+            "icon": data["git_repository_icon"],
+            "url": git_repository_url,
+            "title": strings["git_repository"],
+        }));
+    }
+    for button in &html_config.nav_buttons {
+        buttons.push(json!({
+            "id": "",
+            "icon": button.icon,
+            "url": button.url,
+            "title": button.title,
+        }));
+    }
+    buttons
+}
+
+/// Pick the FontAwesome icon for a repository link based on where it's hosted.
+/// The other hosts get `fa-git` rather than `fa-git-alt`, which is only in
+/// FontAwesome 5, and the theme has FontAwesome 4.7.
+fn default_git_repository_icon(git_repository_url: &str) -> &'static str {
+    let url = match Url::parse(git_repository_url) {
+        Ok(url) => url,
+        Err(e) => {
+            warn!(
+                "`output.html.git-repository-url` should be an absolute URL, got \"{}\" ({})",
+                git_repository_url, e
+            );
+            return "fa-git";
+        }
+    };
+
+    match url.host_str() {
+        Some("github.com") | Some("www.github.com") => "fa-github",
+        Some("gitlab.com") | Some("www.gitlab.com") => "fa-gitlab",
+        Some("bitbucket.org") | Some("www.bitbucket.org") => "fa-bitbucket",
+        _ => "fa-git",
+    }
+}
+
+/// Fill in the `{path}` placeholder of an `edit-url-template`, always using
+/// forward slashes so the URL also works when building on Windows.
+fn edit_url(template: &str, source_path: &Path) -> String {
+    let path: Vec<_> = source_path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+
+    template.replace("{path}", &path.join("/"))
+}
+
+/// The content of the 404 page when `output.html.input-404` isn't set.
+const DEFAULT_404: &str = "# Document not found (404)\n\n\
+                           This URL is invalid, sorry. Please use the navigation bar or search \
+                           to continue.\n";
+
+/// The color themes shipped with the default theme, as `(id, display name)`.
+const COLOR_THEMES: &[(&str, &str)] = &[
+    ("light", "Light"),
+    ("rust", "Rust"),
+    ("coal", "Coal"),
+    ("navy", "Navy"),
+    ("ayu", "Ayu"),
+];
+
+/// Look up the ID of the color theme called `name` (case-insensitive), warning
+/// if there's no such theme.
+fn color_theme_id(option: &str, name: &str) -> Option<String> {
+    let id = name.to_lowercase();
+    if COLOR_THEMES.iter().any(|&(known, _)| known == id) {
+        Some(id)
+    } else {
+        let known: Vec<_> = COLOR_THEMES.iter().map(|&(known, _)| known).collect();
+        warn!(
+            "Unknown color theme \"{}\" for `output.html.{}`, expected one of: {}",
+            name,
+            option,
+            known.join(", ")
+        );
+        None
+    }
+}
+
+lazy_static! {
+    static ref HEADER: Regex = Regex::new(r"<h(\d)>(.*?)</h\d>").unwrap();
+}
+
+/// Goes through the rendered HTML, making sure all header tags have
+/// an anchor respectively so people can link to sections directly.
+pub(super) fn build_header_links(html: &str) -> String {
+    let mut id_counter = HashMap::new();
+
+    HEADER
+        .replace_all(html, |caps: &Captures<'_>| {
+            let level = caps[1]
+                .parse()
+                .expect("Regex should ensure we only ever get numbers here");
+
+            insert_link_into_header(level, &caps[2], &mut id_counter)
+        })
+        .into_owned()
+}
+
+/// The headings of a chapter's rendered HTML within the levels of the
+/// `pagetoc`, with the IDs `build_header_links` gives them.
+fn page_headings(html: &str, pagetoc: &PageToc) -> Vec<serde_json::Value> {
+    let mut id_counter = HashMap::new();
+
+    HEADER
+        .captures_iter(html)
+        .filter_map(|caps| {
+            let level: u32 = caps[1]
+                .parse()
+                .expect("Regex should ensure we only ever get numbers here");
+            // Every heading uses up its ID, even when it isn't listed
+            let id = utils::unique_id_from_content(&caps[2], &mut id_counter);
+            if level < pagetoc.min_level || level > pagetoc.max_level {
+                return None;
+            }
+            Some(json!({ "level": level, "id": id, "text": &caps[2] }))
+        })
+        .collect()
+}
+
+/// Insert a sinle link into a header, making sure each link gets its own
+/// unique ID by appending an auto-incremented number (if necessary).
+fn insert_link_into_header(
+    level: usize,
+    content: &str,
+    id_counter: &mut HashMap<String, usize>,
+) -> String {
+    let id = utils::unique_id_from_content(content, id_counter);
+
+    format!(
+        r##"<h{level}><a class="header" href="#{id}" id="{id}">{text}</a></h{level}>"##,
+        level = level,
+        id = id,
+        text = content
+    )
+}
+
+// The rust book uses annotations for rustdoc to test code snippets,
+// like the following:
+// ```rust,should_panic
+// fn main() {
+//     // Code here
+// }
+// ```
+// This function replaces all commas by spaces in the code block classes
+fn fix_code_blocks(html: &str) -> String {
+    let regex = Regex::new(r##"<code([^>]+)class="([^"]+)"([^>]*)>"##).unwrap();
+    regex
+        .replace_all(html, |caps: &Captures<'_>| {
+            let before = &caps[1];
+            let classes = &caps[2].replace(",", " ");
+            let after = &caps[3];
+
+            format!(
+                r#"<code{before}class="{classes}"{after}>"#,
+                before = before,
+                classes = classes,
+                after = after
+            )
+        })
+        .into_owned()
+}
+
+/// Turn the code blocks in one of the `languages` into a
+/// `<pre class="language">` of their (escaped) code, so a JavaScript library
+/// like mermaid can find and render them.
+fn passthrough_code_blocks(html: &str, languages: &[String]) -> String {
+    lazy_static! {
+        static ref CODE_BLOCK: Regex =
+            Regex::new(r#"(?s)<pre><code class="([^"]+)">(.*?)</code></pre>"#).unwrap();
+    }
+
+    if languages.is_empty() {
+        return html.to_string();
+    }
+
+    CODE_BLOCK
+        .replace_all(html, |caps: &Captures<'_>| {
+            let attributes = CodeBlockAttributes::from_classes(&caps[1]);
+            match attributes.language {
+                Some(ref language) if languages.contains(language) => {
+                    format!("<pre class=\"{}\">{}</pre>", language, &caps[2])
+                }
+                _ => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// Whether the pages need the editor, because the playpens are editable or a
+/// code block is marked `editable`.
+fn uses_editor(book: &Book, playpen_config: &Playpen) -> bool {
+    playpen_config.copy_js
+        && (playpen_config.editable
+            || book.iter().any(|item| match *item {
+                BookItem::Chapter(ref ch) => utils::code_blocks(&ch.content)
+                    .iter()
+                    .any(|block| block.editable && block.has_playpen()),
+                _ => false,
+            }))
+}
+
+fn add_playpen_pre(html: &str, edition: Option<RustEdition>, crates: &[String]) -> String {
+    let regex = Regex::new(r##"((?s)<code[^>]?class="([^"]+)".*?>(.*?)</code>)"##).unwrap();
+    regex
+        .replace_all(html, |caps: &Captures<'_>| {
+            let text = &caps[1];
+            let code = &caps[3];
+
+            // `rust,no_run` and the like were split into classes by `fix_code_blocks`
+            let attributes = CodeBlockAttributes::from_classes(&caps[2]);
+            if attributes.is_rust() {
+                let classes = attributes.class_list();
+                if attributes.has_playpen() {
+                    let edition_class = if attributes.edition.is_some() {
+                        // the user forced edition, we should not overwrite it
+                        String::new()
+                    } else {
+                        edition
+                            .map(|edition| format!(" edition{}", edition.as_str()))
+                            .unwrap_or_default()
+                    };
+
+                    // The playpen runs the code with these
+                    let mut data = String::new();
+                    if let Some(edition) = attributes.edition.or(edition) {
+                        data.push_str(&format!(" data-edition=\"{}\"", edition.as_str()));
+                    }
+                    if !crates.is_empty() {
+                        let crates = utils::escape_html(&crates.join(" "));
+                        data.push_str(&format!(" data-crates=\"{}\"", crates));
+                    }
+
+                    // wrap the contents in an external pre block
+                    format!(
+                        "<pre class=\"playpen\"{}><code class=\"{}{}\">{}</code></pre>",
+                        data,
+                        classes,
+                        edition_class,
+                        {
+                            let content: Cow<'_, str> = if attributes.editable
+                                || text.contains("fn main")
+                                || text.contains("quick_main!")
+                            {
+                                code.into()
+                            } else {
+                                // we need to inject our own main
+                                let (attrs, code) = partition_source(code);
+
+                                format!(
+                                    "\n# #![allow(unused)]\n{}# fn main() {{\n{}# }}",
+                                    attrs, code
+                                )
+                                .into()
+                            };
+                            hide_lines(&content)
+                        }
+                    )
+                } else {
+                    format!("<code class=\"{}\">{}</code>", classes, hide_lines(code))
+                }
+            } else {
+                // not language-rust, so no-op
+                text.to_owned()
+            }
+        })
+        .into_owned()
+}
+
+/// Mark the code blocks which get a copy button (`data-copyable`) and a gutter
+/// of line numbers (`data-line-numbers`), unless a block's attributes
+/// (`copyable`, `nocopy`, `line-numbers` and `no-line-numbers`) say
+/// otherwise. Every numbered line starts with an empty
+/// `<span class="line-number">` for the theme to number with a CSS counter, so
+/// the numbers aren't part of the copied code.
+fn add_code_block_options(html: &str, copyable: bool, line_numbers: bool) -> String {
+    lazy_static! {
+        static ref CODE_BLOCK: Regex =
+            Regex::new(r#"(?s)<pre([^>]*)><code([^>]*)>(.*?)</code></pre>"#).unwrap();
+        static ref CLASS: Regex = Regex::new(r#"class="([^"]*)""#).unwrap();
+    }
+
+    CODE_BLOCK
+        .replace_all(html, |caps: &Captures<'_>| {
+            let attributes = CLASS
+                .captures(&caps[2])
+                .map(|class| CodeBlockAttributes::from_classes(&class[1]))
+                .unwrap_or_default();
+            let copyable = attributes.copyable.unwrap_or(copyable);
+            let line_numbers = attributes.line_numbers.unwrap_or(line_numbers);
+
+            if !copyable && !line_numbers {
+                return caps[0].to_string();
+            }
+
+            let mut pre = caps[1].to_string();
+            if copyable {
+                pre.push_str(" data-copyable");
+            }
+            if line_numbers {
+                pre.push_str(" data-line-numbers");
+            }
+            let code = if line_numbers {
+                number_lines(&caps[3])
+            } else {
+                caps[3].to_string()
+            };
+
+            format!("<pre{}><code{}>{}</code></pre>", pre, &caps[2], code)
+        })
+        .into_owned()
+}
+
+/// Start every line of a code block with a line number. It goes after the
+/// `<span class="boring">` of a hidden line, so it's hidden along with it.
+fn number_lines(code: &str) -> String {
+    let mut numbered = String::with_capacity(code.len() * 2);
+
+    let mut rest = code;
+    while !rest.is_empty() {
+        let end = rest.find('\n').map_or(rest.len(), |ix| ix + 1);
+        let mut line = &rest[..end];
+        rest = &rest[end..];
+
+        loop {
+            let tag = ["</span>", "<span class=\"boring\">"]
+                .iter()
+                .find(|tag| line.starts_with(**tag));
+            match tag {
+                Some(tag) => {
+                    numbered.push_str(tag);
+                    line = &line[tag.len()..];
+                }
+                None => break,
+            }
+        }
+
+        if !line.is_empty() {
+            numbered.push_str("<span class=\"line-number\"></span>");
+            numbered.push_str(line);
+        }
+    }
+
+    numbered
+}
+
+lazy_static! {
+    static ref BORING_LINES_REGEX: Regex = Regex::new(r"^(\s*)#(#|\s|$)(.*)$").unwrap();
+}
+
+/// Wrap the lines rustdoc hides (`# ` followed by code, or a lone `#`) in
+/// `<span class="boring">`, which the theme hides while keeping them in the
+/// code the playpen runs. `##` at the start of a line is a literal `#`.
+fn hide_lines(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    for line in content.lines() {
+        if let Some(caps) = BORING_LINES_REGEX.captures(line) {
+            if &caps[2] == "#" {
+                result += &caps[1];
+                result += &caps[2];
                 result += &caps[3];
                 result += "\n";
                 continue;
-            } else if &caps[2] != "!" && &caps[2] != "[" {
+            } else {
                 result += "<span class=\"boring\">";
                 result += &caps[1];
                 if &caps[2] != " " {
@@ -729,39 +2585,977 @@ fn hide_lines(content: &str) -> String {
     result
 }
 
-fn partition_source(s: &str) -> (String, String) {
-    let mut after_header = false;
-    let mut before = String::new();
-    let mut after = String::new();
-
-    for line in s.lines() {
-        let trimline = line.trim();
-        let header = trimline.chars().all(char::is_whitespace) || trimline.starts_with("#![");
-        if !header || after_header {
-            after_header = true;
-            after.push_str(line);
-            after.push_str("\n");
-        } else {
-            before.push_str(line);
-            before.push_str("\n");
+fn partition_source(s: &str) -> (String, String) {
+    let mut after_header = false;
+    let mut before = String::new();
+    let mut after = String::new();
+
+    for line in s.lines() {
+        let trimline = line.trim();
+        let header = trimline.chars().all(char::is_whitespace) || trimline.starts_with("#![");
+        if !header || after_header {
+            after_header = true;
+            after.push_str(line);
+            after.push('\n');
+        } else {
+            before.push_str(line);
+            before.push('\n');
+        }
+    }
+
+    (before, after)
+}
+
+/// The data a chapter is rendered with: the keys of the chapter itself,
+/// layered on top of the data shared by the whole book, without copying it.
+/// Its `Serialize` gives the merged data, for the hashes of the pages.
+struct ChapterData<'a> {
+    book: &'a serde_json::Map<String, serde_json::Value>,
+    chapter: serde_json::Map<String, serde_json::Value>,
+}
+
+impl<'a> ChapterData<'a> {
+    fn new(book: &'a serde_json::Map<String, serde_json::Value>) -> ChapterData<'a> {
+        ChapterData {
+            book,
+            chapter: serde_json::Map::new(),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&serde_json::Value> {
+        self.chapter.get(key).or_else(|| self.book.get(key))
+    }
+
+    /// Set a key for this chapter, hiding the book's value of the same key.
+    fn insert(&mut self, key: String, value: serde_json::Value) {
+        self.chapter.insert(key, value);
+    }
+
+    /// Render the template `name` with this data. `context` has the book's
+    /// data: the chapter's keys are set in it while the template renders, and
+    /// the book's values are put back after, so its data (like the table of
+    /// contents) isn't serialized again for every chapter.
+    fn render(
+        &self,
+        handlebars: &Handlebars<'_>,
+        name: &str,
+        context: &mut TemplateContext,
+    ) -> Result<String> {
+        let template = handlebars
+            .get_template(name)
+            .ok_or_else(|| RenderError::new(format!("Template not found: {}", name)))
+            .map_err(template_render_error)?;
+
+        let book = context
+            .data_mut()
+            .as_object_mut()
+            .expect("The book's data is an object");
+        let hidden: Vec<_> = self
+            .chapter
+            .iter()
+            .map(|(key, value)| (key.clone(), book.insert(key.clone(), value.clone())))
+            .collect();
+
+        let rendered = template.renders(
+            handlebars,
+            context,
+            &mut handlebars::RenderContext::new(template.name.as_ref()),
+        );
+
+        let book = context
+            .data_mut()
+            .as_object_mut()
+            .expect("The book's data is an object");
+        for (key, value) in hidden {
+            match value {
+                Some(value) => book.insert(key, value),
+                None => book.remove(&key),
+            };
+        }
+        rendered.map_err(template_render_error)
+    }
+}
+
+impl<'a> Serialize for ChapterData<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let hidden = self
+            .book
+            .keys()
+            .filter(|key| self.chapter.contains_key(*key))
+            .count();
+        let len = self.book.len() + self.chapter.len() - hidden;
+
+        let mut map = serializer.serialize_map(Some(len))?;
+        for (key, value) in self.book {
+            if !self.chapter.contains_key(key) {
+                map.serialize_entry(key, value)?;
+            }
         }
+        for (key, value) in &self.chapter {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
     }
+}
 
-    (before, after)
+/// What a rendered item adds to the print page.
+enum PrintContent {
+    Separator,
+    Chapter(PathBuf, String),
 }
 
 struct RenderItemContext<'a> {
     handlebars: &'a Handlebars<'a>,
     destination: PathBuf,
-    data: serde_json::Map<String, serde_json::Value>,
+    data: ChapterData<'a>,
     is_index: bool,
     html_config: HtmlConfig,
+    /// Highlights the code blocks, unless that's left to highlight.js.
+    highlighter: Option<&'a Highlighter>,
     edition: Option<RustEdition>,
+    /// The book's source directory, relative to the book root.
+    src_dir: PathBuf,
+    diagnostics: Diagnostics,
+    timings: Timings,
+    /// Transform the pages after the post-processing.
+    post_processors: &'a PostProcessors,
+}
+
+impl<'a> RenderItemContext<'a> {
+    fn new(
+        ctx: &RenderContext,
+        handlebars: &'a Handlebars<'a>,
+        data: ChapterData<'a>,
+        is_index: bool,
+        html_config: &HtmlConfig,
+        highlighter: Option<&'a Highlighter>,
+        post_processors: &'a PostProcessors,
+    ) -> RenderItemContext<'a> {
+        RenderItemContext {
+            handlebars,
+            destination: ctx.destination.clone(),
+            data,
+            is_index,
+            html_config: html_config.clone(),
+            highlighter,
+            edition: html_config.playpen.effective_edition(&ctx.config.rust),
+            src_dir: ctx.config.book.src.clone(),
+            diagnostics: ctx.diagnostics.clone(),
+            timings: ctx.timings.clone(),
+            post_processors,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::book::{Chapter, SectionNumber};
+    use crate::renderer::html_handlebars::cache::CACHE_FILE;
+    use tempfile::Builder as TempFileBuilder;
+
+    #[test]
+    fn render_a_small_book() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let destination = temp.path().join("book");
+        fs::create_dir(temp.path().join("src")).unwrap();
+
+        let mut first = Chapter::new("First", "# First\n".to_string(), "first.md", Vec::new());
+        first.sub_items.push(BookItem::Chapter(Chapter::new(
+            "Nested",
+            "# Nested Chapter\n\nSome *text*.".to_string(),
+            "first/nested.md",
+            vec![String::from("First")],
+        )));
+        let mut book = Book::new();
+        book.push_item(first);
+
+        let ctx = RenderContext::new(temp.path(), book, Config::default(), &destination);
+        HtmlHandlebars::new().render(&ctx).unwrap();
+
+        assert!(!destination.join("first/nested.md").exists());
+        let nested = fs::read_to_string(destination.join("first/nested.html")).unwrap();
+        assert!(nested.contains(
+            r##"<h1><a class="header" href="#nested-chapter" id="nested-chapter">Nested Chapter</a></h1>"##
+        ));
+        assert!(nested.contains("<em>text</em>"));
+        assert!(nested.contains(r#"href="../css/general.css""#));
+
+        let first = fs::read_to_string(destination.join("first.html")).unwrap();
+        assert!(first.contains(r##"id="first">First</a></h1>"##));
+        assert!(first.contains(r#"<link rel="next" href="first/nested.html">"#));
+        assert!(!first.contains(r#"rel="prev""#));
+        assert!(nested.contains(r#"<link rel="prev" href="../first.html">"#));
+        assert!(!nested.contains(r#"rel="next""#));
+        assert!(destination.join("index.html").exists());
+    }
+
+    #[test]
+    fn a_chapter_is_previewed_like_it_is_rendered() {
+        use crate::preprocess::{LinkPreprocessor, Preprocessor, PreprocessorContext};
+
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let destination = temp.path().join("book");
+        fs::create_dir(temp.path().join("src")).unwrap();
+        fs::write(temp.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+
+        let content = "# Usage\n\n```rust\n{{#include main.rs}}\n```\n\n\
+                       Back to [the introduction](intro.md#install).\n";
+        let mut book = Book::new();
+        book.push_item(Chapter::new(
+            "Intro",
+            "# Intro\n".to_string(),
+            "intro.md",
+            vec![],
+        ))
+        .push_item(Chapter::new(
+            "Usage",
+            content.to_string(),
+            "usage.md",
+            vec![],
+        ));
+        let config = Config::default();
+        let preprocess_ctx =
+            PreprocessorContext::new(temp.path().into(), config.clone(), String::from("html"));
+        let book = LinkPreprocessor::new().run(&preprocess_ctx, book).unwrap();
+        let chapter = match book.sections[1] {
+            BookItem::Chapter(ref ch) => ch.clone(),
+            _ => unreachable!(),
+        };
+
+        let ctx = RenderContext::new(temp.path(), book, config, &destination);
+        let preview = HtmlHandlebars::new()
+            .render_chapter_to_string(&ctx, &chapter, &HtmlConfig::default(), &Theme::default())
+            .unwrap();
+        assert!(preview.contains(r#"<code class="language-rust">fn main() {}"#));
+        assert!(preview.contains(r#"<a href="intro.html#install">the introduction</a>"#));
+        assert!(preview.contains(r#"<link rel="prev" href="intro.html">"#));
+        assert!(preview.contains("<title>Usage</title>"));
+        assert!(!destination.exists());
+
+        HtmlHandlebars::new().render(&ctx).unwrap();
+        let rendered = fs::read_to_string(destination.join("usage.html")).unwrap();
+        assert_eq!(preview, rendered);
+    }
+
+    #[test]
+    fn part_titles_are_rendered_as_sidebar_headings() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let destination = temp.path().join("book");
+        fs::create_dir(temp.path().join("src")).unwrap();
+
+        let mut book = Book::new();
+        book.push_item(BookItem::PartTitle(String::from("User Guide")))
+            .push_item(Chapter::new(
+                "Install",
+                String::new(),
+                "install.md",
+                Vec::new(),
+            ))
+            .push_item(BookItem::PartTitle(String::from("Reference")))
+            .push_item(Chapter::new(
+                "Config",
+                String::new(),
+                "config.md",
+                Vec::new(),
+            ));
+
+        let ctx = RenderContext::new(temp.path(), book, Config::default(), &destination);
+        HtmlHandlebars::new().render(&ctx).unwrap();
+
+        let install = fs::read_to_string(destination.join("install.html")).unwrap();
+        assert!(install.contains(r#"<li class="part-title active">User Guide</li>"#));
+        assert!(install.contains(r#"<li class="part-title active">Reference</li>"#));
+        assert!(!install.contains(">User Guide</a>"));
+    }
+
+    #[test]
+    fn chapter_names_are_only_escaped_in_html() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let destination = temp.path().join("book");
+        fs::create_dir(temp.path().join("src")).unwrap();
+
+        let name = "Generics & <T>";
+        let mut book = Book::new();
+        book.push_item(Chapter::new(name, String::new(), "generics.md", Vec::new()));
+        let mut cfg = Config::default();
+        cfg.book.title = Some(String::from("\"Rust\""));
+
+        let ctx = RenderContext::new(temp.path(), book, cfg, &destination);
+        let data = make_data(&ctx.root, &ctx.book, &ctx.config, &HtmlConfig::default()).unwrap();
+        assert_eq!(data["chapters"][0]["name"], json!(name));
+
+        HtmlHandlebars::new().render(&ctx).unwrap();
+
+        let page = fs::read_to_string(destination.join("generics.html")).unwrap();
+        assert!(page.contains("<title>Generics &amp; &lt;T&gt; - &quot;Rust&quot;</title>"));
+        assert!(page.contains(">Generics &amp; &lt;T&gt;</a>"));
+        assert!(!page.contains("<T>"));
+    }
+
+    #[test]
+    fn draft_chapters_are_listed_but_not_rendered() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let destination = temp.path().join("book");
+        fs::create_dir(temp.path().join("src")).unwrap();
+
+        let mut book = Book::new();
+        book.push_item(Chapter::new_draft("Introduction", Vec::new()))
+            .push_item(Chapter::new("First", String::new(), "first.md", Vec::new()))
+            .push_item(Chapter::new(
+                "Second",
+                String::new(),
+                "second.md",
+                Vec::new(),
+            ))
+            .push_item(Chapter::new_draft("Future Work", Vec::new()));
+
+        let ctx = RenderContext::new(temp.path(), book, Config::default(), &destination);
+        let data = make_data(&ctx.root, &ctx.book, &ctx.config, &HtmlConfig::default()).unwrap();
+        assert_eq!(data["chapters"][0]["name"], json!("Introduction"));
+        assert_eq!(data["chapters"][0]["path"], serde_json::Value::Null);
+        assert_eq!(data["chapters"][1]["path"], json!("first.md"));
+
+        HtmlHandlebars::new().render(&ctx).unwrap();
+
+        let first = fs::read_to_string(destination.join("first.html")).unwrap();
+        assert!(first.contains(r#"<li class="chapter-item expanded affix ">Introduction</li>"#));
+        assert!(first.contains(r#"<li class="chapter-item expanded affix ">Future Work</li>"#));
+        assert!(!first.contains(r#"rel="prev""#));
+        assert!(first.contains(r#"<link rel="next" href="second.html">"#));
+
+        let second = fs::read_to_string(destination.join("second.html")).unwrap();
+        assert!(second.contains(r#"<link rel="prev" href="first.html">"#));
+        assert!(!second.contains(r#"rel="next""#));
+
+        let index = fs::read_to_string(destination.join("index.html")).unwrap();
+        assert!(index.contains(r#"<link rel="next" href="second.html">"#));
+
+        let mut pages: Vec<_> = fs::read_dir(&destination)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.ends_with(".html"))
+            .collect();
+        pages.sort();
+        assert_eq!(
+            pages,
+            [
+                "404.html",
+                "first.html",
+                "index.html",
+                "print.html",
+                "second.html"
+            ]
+        );
+    }
+
+    #[test]
+    fn parallel_rendering_matches_serial_rendering() {
+        fn render_in_pool(book: &Book, threads: usize) -> BTreeMap<PathBuf, String> {
+            let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+            let destination = temp.path().join("book");
+            fs::create_dir(temp.path().join("src")).unwrap();
+
+            let ctx =
+                RenderContext::new(temp.path(), book.clone(), Config::default(), &destination);
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| HtmlHandlebars::new().render(&ctx)).unwrap();
+
+            walkdir::WalkDir::new(&destination)
+                .into_iter()
+                .map(|entry| entry.unwrap())
+                .filter(|entry| entry.path().extension() == Some("html".as_ref()))
+                .map(|entry| {
+                    let relative = entry.path().strip_prefix(&destination).unwrap();
+                    let content = fs::read_to_string(entry.path()).unwrap();
+                    (relative.to_path_buf(), content)
+                })
+                .collect()
+        }
+
+        let mut book = Book::new();
+        for i in 0..200 {
+            let mut chapter = Chapter::new(
+                &format!("Chapter {}", i),
+                format!("# Chapter {}\n\nSome *text* for chapter {}.\n", i, i),
+                format!("chapter_{}.md", i),
+                Vec::new(),
+            );
+            chapter.number = Some(SectionNumber(vec![i + 1]));
+            book.push_item(chapter);
+            if i % 50 == 49 {
+                book.push_item(BookItem::Separator);
+            }
+        }
+
+        let serial = render_in_pool(&book, 1);
+        let parallel = render_in_pool(&book, 4);
+
+        // every chapter, the index, the print page and the 404 page
+        assert_eq!(serial.len(), 203);
+        assert!(
+            serial == parallel,
+            "rendering in parallel changed the output"
+        );
+    }
+
+    #[test]
+    fn chapter_data_matches_merging_into_a_copy_of_the_book_data() {
+        let mut book = Book::new();
+        book.push_item(Chapter::new("First", String::new(), "first.md", Vec::new()));
+        let ctx = RenderContext::new("/", book, Config::default(), "/book");
+        let book_data =
+            make_data(&ctx.root, &ctx.book, &ctx.config, &HtmlConfig::default()).unwrap();
+        assert!(book_data.contains_key("favicon"));
+
+        let chapter_keys = vec![
+            ("path", json!("first.md")),
+            ("title", json!("First")),
+            ("path_to_root", json!("")),
+            // overrides a key of the book
+            ("favicon", json!("first.png")),
+            ("next", json!({ "title": "Second", "link": "second.html" })),
+            // set twice, the last value wins
+            ("title", json!("First - Book")),
+        ];
+
+        let mut merged = book_data.clone();
+        let mut layered = ChapterData::new(&book_data);
+        for (key, value) in chapter_keys {
+            merged.insert(key.to_owned(), value.clone());
+            layered.insert(key.to_owned(), value);
+        }
+
+        assert_eq!(layered.get("title"), Some(&json!("First - Book")));
+        assert_eq!(layered.get("book_title"), book_data.get("book_title"));
+        assert_eq!(
+            serde_json::to_value(&layered).unwrap(),
+            serde_json::Value::Object(merged)
+        );
+    }
+
+    #[test]
+    fn chapters_render_in_the_books_context_without_copying_it() {
+        let mut handlebars = Handlebars::new();
+        handlebars
+            .register_template_string(
+                "index",
+                "{{title}} of {{book_title}}:{{#each chapters}} {{name}}{{/each}}",
+            )
+            .unwrap();
+        let mut book_data = serde_json::Map::new();
+        book_data.insert("book_title".to_owned(), json!("Book"));
+        book_data.insert("title".to_owned(), json!("Book"));
+        book_data.insert(
+            "chapters".to_owned(),
+            json!([{ "name": "First" }, { "name": "Second" }]),
+        );
+        let mut context = TemplateContext::wraps(&book_data).unwrap();
+        let toc = context.data()["chapters"].as_array().unwrap().as_ptr();
+
+        for name in &["First", "Second"] {
+            let mut data = ChapterData::new(&book_data);
+            data.insert("title".to_owned(), json!(name));
+            data.insert("path".to_owned(), json!(format!("{}.md", name)));
+            assert_eq!(
+                data.render(&handlebars, "index", &mut context).unwrap(),
+                format!("{} of Book: First Second", name)
+            );
+        }
+
+        // The same table of contents every time, and only the book's data after
+        assert_eq!(context.data()["chapters"].as_array().unwrap().as_ptr(), toc);
+        assert_eq!(context.data(), &serde_json::Value::Object(book_data));
+        let err = ChapterData::new(&serde_json::Map::new())
+            .render(&handlebars, "missing", &mut context)
+            .unwrap_err();
+        assert!(err.to_string().contains("Template not found: missing"));
+    }
+
+    fn render_incrementally(root: &Path, book: &Book) {
+        let mut config = Config::default();
+        config.set("output.html.incremental", true).unwrap();
+        let ctx = RenderContext::new(root, book.clone(), config, root.join("book"));
+        HtmlHandlebars::new().render(&ctx).unwrap();
+    }
+
+    /// Replace every page in the output with a sentinel so we can tell which
+    /// ones the next render rewrites.
+    fn stamp_pages(root: &Path) -> Vec<PathBuf> {
+        let destination = root.join("book");
+        let mut pages = Vec::new();
+        for entry in walkdir::WalkDir::new(&destination) {
+            let entry = entry.unwrap();
+            let path = entry.path();
+            if path.extension() == Some("html".as_ref()) || path.ends_with("searchindex.js") {
+                fs::write(path, "sentinel").unwrap();
+                pages.push(path.strip_prefix(&destination).unwrap().to_path_buf());
+            }
+        }
+        pages.sort();
+        pages
+    }
+
+    fn rewritten_pages(root: &Path, pages: &[PathBuf]) -> Vec<PathBuf> {
+        pages
+            .iter()
+            .filter(
+                |page| match fs::read_to_string(root.join("book").join(page)) {
+                    Ok(content) => content != "sentinel",
+                    Err(_) => false,
+                },
+            )
+            .cloned()
+            .collect()
+    }
+
+    fn three_chapters() -> Book {
+        let mut book = Book::new();
+        for name in &["first", "second", "third"] {
+            book.push_item(Chapter::new(
+                name,
+                format!("# {}\n", name),
+                format!("{}.md", name),
+                Vec::new(),
+            ));
+        }
+        book
+    }
+
+    #[test]
+    fn unchanged_books_are_not_rewritten() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        fs::create_dir(temp.path().join("src")).unwrap();
+        let book = three_chapters();
+
+        render_incrementally(temp.path(), &book);
+        let pages = stamp_pages(temp.path());
+        assert!(pages.contains(&PathBuf::from("second.html")));
+        assert!(pages.contains(&PathBuf::from("print.html")));
+        assert!(temp.path().join("book").join(CACHE_FILE).exists());
+
+        render_incrementally(temp.path(), &book);
+        assert_eq!(rewritten_pages(temp.path(), &pages), Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn only_changed_chapters_are_rewritten() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        fs::create_dir(temp.path().join("src")).unwrap();
+        let mut book = three_chapters();
+
+        render_incrementally(temp.path(), &book);
+        let pages = stamp_pages(temp.path());
+        book.for_each_mut(|item| {
+            if let BookItem::Chapter(ref mut ch) = *item {
+                if ch.name == "second" {
+                    ch.content.push_str("\nSome new text.\n");
+                }
+            }
+        });
+        render_incrementally(temp.path(), &book);
+
+        // The word counts of every chapter are in the data of every page
+        let rewritten = rewritten_pages(temp.path(), &pages);
+        assert!(rewritten.contains(&PathBuf::from("first.html")));
+
+        let pages = stamp_pages(temp.path());
+        book.for_each_mut(|item| {
+            if let BookItem::Chapter(ref mut ch) = *item {
+                if ch.name == "second" {
+                    ch.content = ch.content.replace("new", "old");
+                }
+            }
+        });
+        render_incrementally(temp.path(), &book);
+
+        let rewritten = rewritten_pages(temp.path(), &pages);
+        let chapters: Vec<_> = rewritten
+            .iter()
+            .filter(|page| !page.ends_with("print.html") && !page.ends_with("searchindex.js"))
+            .collect();
+        assert_eq!(chapters, vec![Path::new("second.html")]);
+    }
+
+    #[test]
+    fn changed_additional_files_rewrite_every_page() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        fs::create_dir(temp.path().join("src")).unwrap();
+        fs::write(temp.path().join("custom.css"), "p { color: red; }").unwrap();
+        let book = three_chapters();
+        let render = || {
+            let mut config = Config::default();
+            config.set("output.html.incremental", true).unwrap();
+            config
+                .set("output.html.additional-css", vec!["custom.css"])
+                .unwrap();
+            let ctx =
+                RenderContext::new(temp.path(), book.clone(), config, temp.path().join("book"));
+            HtmlHandlebars::new().render(&ctx).unwrap();
+        };
+
+        render();
+        let pages = stamp_pages(temp.path());
+        render();
+        assert!(rewritten_pages(temp.path(), &pages).is_empty());
+
+        fs::write(temp.path().join("custom.css"), "p { color: blue; }").unwrap();
+        render();
+        assert!(rewritten_pages(temp.path(), &pages).contains(&PathBuf::from("second.html")));
+    }
+
+    #[test]
+    fn removed_chapters_are_deleted_from_the_output() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        fs::create_dir(temp.path().join("src")).unwrap();
+        render_incrementally(temp.path(), &three_chapters());
+        let destination = temp.path().join("book");
+        assert!(destination.join("third.html").exists());
+
+        let mut book = three_chapters();
+        book.sections.pop();
+        render_incrementally(temp.path(), &book);
+
+        assert!(!destination.join("third.html").exists());
+        assert!(destination.join("second.html").exists());
+    }
+
+    #[test]
+    fn full_rebuilds_ignore_the_cache() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        fs::create_dir(temp.path().join("src")).unwrap();
+        let book = three_chapters();
+        render_incrementally(temp.path(), &book);
+        let pages = stamp_pages(temp.path());
+
+        let destination = temp.path().join("book");
+        let ctx = RenderContext::new(temp.path(), book, Config::default(), &destination);
+        HtmlHandlebars::new().render(&ctx).unwrap();
+
+        assert_eq!(rewritten_pages(temp.path(), &pages), pages);
+        assert!(!destination.join(CACHE_FILE).exists());
+    }
+
+    #[test]
+    fn navigation_follows_reading_order() {
+        let mut first = Chapter::new("First", String::new(), "first.md", Vec::new());
+        first.sub_items.push(BookItem::Chapter(Chapter::new(
+            "Nested",
+            String::new(),
+            "first/nested.md",
+            vec![String::from("First")],
+        )));
+        let mut book = Book::new();
+        book.push_item(first)
+            .push_item(BookItem::Separator)
+            .push_item(Chapter::new_draft("Draft", Vec::new()))
+            .push_item(Chapter::new(
+                "Second",
+                String::new(),
+                "second.md",
+                Vec::new(),
+            ));
+
+        let got = chapter_navigation(&book, false).unwrap();
+
+        let should_be = vec![
+            json!({ "title": "First", "link": "first.html" }),
+            json!({ "title": "Nested", "link": "first/nested.html" }),
+            json!({ "title": "Second", "link": "second.html" }),
+        ];
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn breadcrumbs_list_the_containing_parts_and_chapters() {
+        let mut draft = Chapter::new_draft("Draft", vec![String::from("Guide")]);
+        draft.sub_items.push(BookItem::Chapter(Chapter::new(
+            "Deep",
+            String::new(),
+            "guide/deep.md",
+            vec![String::from("Guide"), String::from("Draft")],
+        )));
+        let mut guide = Chapter::new("Guide", String::new(), "guide/index.md", Vec::new());
+        guide.number = Some(SectionNumber(vec![1]));
+        guide.sub_items.push(BookItem::Chapter(draft));
+        let mut book = Book::new();
+        book.push_item(Chapter::new("Intro", String::new(), "intro.md", Vec::new()))
+            .push_item(BookItem::PartTitle(String::from("User Guide")))
+            .push_item(guide)
+            .push_item(Chapter::new("End", String::new(), "end.md", Vec::new()));
+
+        let got = chapter_breadcrumbs(&book, None, false).unwrap();
+
+        let part = json!({ "name": "User Guide", "path": null });
+        let guide = json!({ "name": "Guide", "path": "guide/index.html" });
+        let draft = json!({ "name": "Draft", "path": null });
+        let should_be = vec![
+            vec![],
+            vec![],
+            vec![part.clone()],
+            vec![part.clone(), guide.clone()],
+            vec![part, guide, draft],
+            vec![],
+        ];
+        assert_eq!(got, should_be);
+
+        let root = json!({ "name": "Book", "path": "index.html" });
+        let got = chapter_breadcrumbs(&book, Some(root.clone()), false).unwrap();
+        assert_eq!(got.len(), book.iter().count());
+        assert!(got.iter().all(|crumbs| crumbs[0] == root));
+        assert_eq!(got[0], vec![root.clone()]);
+        assert_eq!(got[3][1]["name"], "User Guide");
+    }
+
+    #[test]
+    fn chapter_assets_must_be_existing_files_inside_the_book() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        utils::fs::write_file(temp.path(), "js/diagram.js", b"").unwrap();
+        let chapter = |extra_js: serde_json::Value| {
+            let mut ch = Chapter::new("Diagram", String::new(), "diagram.md", Vec::new());
+            ch.metadata.insert("extra-js".to_string(), extra_js);
+            ch
+        };
+
+        let got = chapter_assets(&chapter(json!(["js/diagram.js"])), "extra-js", temp.path());
+        assert_eq!(got.unwrap(), vec!["js/diagram.js"]);
+        let got = chapter_assets(&chapter(json!([])), "extra-css", temp.path());
+        assert!(got.unwrap().is_empty());
+
+        let errors = vec![
+            (
+                json!("js/diagram.js"),
+                "`extra-js` in the frontmatter of \"Diagram\" should be a list of paths",
+            ),
+            (
+                json!([1]),
+                "`extra-js` in the frontmatter of \"Diagram\" should be a list of paths",
+            ),
+            (
+                json!(["../diagram.js"]),
+                "The extra-js file \"../diagram.js\" of \"Diagram\" isn't inside the book",
+            ),
+            (
+                json!(["js/missing.js"]),
+                "Unable to find the extra-js file \"js/missing.js\" of \"Diagram\"",
+            ),
+        ];
+        for (extra_js, should_be) in errors {
+            let got = chapter_assets(&chapter(extra_js), "extra-js", temp.path());
+            assert_eq!(got.unwrap_err().to_string(), should_be);
+        }
+    }
+
+    #[test]
+    fn edit_url_uses_forward_slashes() {
+        let template = "https://github.com/rust-lang/mdBook/edit/master/{path}";
+        let source_path = Path::new("src").join("first").join("nested.md");
+
+        assert_eq!(
+            edit_url(template, &source_path),
+            "https://github.com/rust-lang/mdBook/edit/master/src/first/nested.md"
+        );
+    }
+
+    #[test]
+    fn git_repository_keys_are_only_set_when_configured() {
+        let config = Config::default();
+        let data = make_data(Path::new(""), &Book::new(), &config, &HtmlConfig::default()).unwrap();
+
+        assert!(!data.contains_key("git_repository_url"));
+        assert!(!data.contains_key("git_repository_icon"));
+
+        let html_config = HtmlConfig {
+            git_repository_url: Some(String::from("https://github.com/rust-lang/mdBook")),
+            ..Default::default()
+        };
+        let data = make_data(Path::new(""), &Book::new(), &config, &html_config).unwrap();
+
+        assert_eq!(
+            data["git_repository_url"],
+            json!("https://github.com/rust-lang/mdBook")
+        );
+        assert_eq!(data["git_repository_icon"], json!("fa-github"));
+    }
+
+    #[test]
+    fn the_strings_are_in_the_book_language() {
+        let mut config = Config::default();
+        config.book.language = Some(String::from("de"));
+        let data = make_data(Path::new(""), &Book::new(), &config, &HtmlConfig::default()).unwrap();
+
+        assert_eq!(data["language"], json!("de"));
+        assert_eq!(data["strings"]["print"], json!("Dieses Buch drucken"));
+        assert_eq!(data["strings"]["previous"], json!("Vorheriges Kapitel"));
+
+        config.book.language = Some(String::from("eo"));
+        let data = make_data(Path::new(""), &Book::new(), &config, &HtmlConfig::default()).unwrap();
+        assert_eq!(data["language"], json!("eo"));
+        assert_eq!(data["strings"]["print"], json!("Print this book"));
+    }
+
+    #[test]
+    fn google_analytics_keys_depend_on_the_id() {
+        let config = Config::default();
+        let data = make_data(Path::new(""), &Book::new(), &config, &HtmlConfig::default()).unwrap();
+        assert!(!data.contains_key("google_analytics"));
+        assert!(!data.contains_key("google_analytics_gtag"));
+
+        let inputs = [("UA-12345-6", false), ("G-ABC123DEF4", true)];
+        for &(id, gtag) in &inputs {
+            let html_config = HtmlConfig {
+                google_analytics: Some(id.to_string()),
+                ..Default::default()
+            };
+            let data = make_data(Path::new(""), &Book::new(), &config, &html_config).unwrap();
+
+            assert_eq!(data["google_analytics"], json!(id));
+            assert_eq!(data.contains_key("google_analytics_gtag"), gtag, "{}", id);
+        }
+    }
+
+    #[test]
+    fn cname_must_be_a_bare_domain() {
+        let valid = [
+            "docs.example.com",
+            "example.com",
+            "my-book.github.io",
+            "localhost",
+        ];
+        for domain in &valid {
+            assert!(is_bare_domain(domain), "{}", domain);
+        }
+
+        let invalid = [
+            "",
+            "https://docs.example.com",
+            "docs.example.com/book",
+            "docs.example.com.",
+            "-docs.example.com",
+            "docs..example.com",
+            "docs.example.com:8080",
+            "docs example.com",
+        ];
+        for domain in &invalid {
+            assert!(!is_bare_domain(domain), "{}", domain);
+        }
+    }
+
+    #[test]
+    fn base_path_of_the_site_url() {
+        let inputs = [
+            (None, "/"),
+            (Some("https://example.com"), "/"),
+            (Some("https://example.com/"), "/"),
+            (Some("https://example.com/docs"), "/docs/"),
+            (Some("https://example.com/docs/v1/"), "/docs/v1/"),
+            (Some("/docs/"), "/docs/"),
+            (Some("docs"), "/docs/"),
+        ];
+
+        for &(site_url, should_be) in &inputs {
+            assert_eq!(site_url_path(site_url), should_be, "{:?}", site_url);
+        }
+    }
+
+    #[test]
+    fn page_urls_are_absolute() {
+        let inputs = [
+            (
+                "https://example.com/book",
+                "intro.html",
+                "https://example.com/book/intro.html",
+            ),
+            (
+                "https://example.com/book/",
+                "intro.html",
+                "https://example.com/book/intro.html",
+            ),
+            (
+                "https://example.com/book//",
+                "a/b.html",
+                "https://example.com/book/a/b.html",
+            ),
+            (
+                "https://example.com/book",
+                "index.html",
+                "https://example.com/book/",
+            ),
+            (
+                "https://example.com",
+                "guide/index.html",
+                "https://example.com/guide/",
+            ),
+            ("http://example.com/", "index.html", "http://example.com/"),
+            (
+                "https://example.com/book",
+                "my chapter/café.html",
+                "https://example.com/book/my%20chapter/caf%C3%A9.html",
+            ),
+            (
+                "https://example.com/book",
+                "q&a/index.html",
+                "https://example.com/book/q&a/",
+            ),
+        ];
+
+        for &(site_url, page, should_be) in &inputs {
+            assert_eq!(
+                page_url(site_url, page).as_ref().map(String::as_str),
+                Some(should_be),
+                "{} {}",
+                site_url,
+                page
+            );
+        }
+        assert_eq!(page_url("/docs/", "intro.html"), None);
+    }
+
+    #[test]
+    fn git_repository_icon_depends_on_the_host() {
+        let inputs = [
+            ("https://github.com/rust-lang/mdBook", "fa-github"),
+            ("https://gitlab.com/foo/bar", "fa-gitlab"),
+            ("https://bitbucket.org/foo/bar", "fa-bitbucket"),
+            ("https://git.example.com/foo/bar.git", "fa-git"),
+            ("github.com/rust-lang/mdBook", "fa-git"),
+        ];
+
+        for (url, should_be) in &inputs {
+            assert_eq!(default_git_repository_icon(url), *should_be, "{}", url);
+        }
+    }
+
+    #[test]
+    fn color_theme_names_are_case_insensitive() {
+        assert_eq!(color_theme_id("default-theme", "Navy"), Some("navy".into()));
+        assert_eq!(color_theme_id("default-theme", "AYU"), Some("ayu".into()));
+        assert_eq!(color_theme_id("default-theme", "solarized"), None);
+    }
+
+    #[test]
+    fn unknown_color_themes_fall_back() {
+        let mut config = Config::default();
+        config
+            .set("output.html.default-theme", "Solarized")
+            .unwrap();
+        config
+            .set("output.html.preferred-dark-theme", "COAL")
+            .unwrap();
+        let html_config = config.html_config_checked().unwrap().unwrap();
+
+        let data = make_data(Path::new(""), &Book::new(), &config, &html_config).unwrap();
+
+        assert_eq!(data["default_theme"], json!("light"));
+        assert_eq!(data["preferred_dark_theme"], json!("coal"));
+        assert_eq!(
+            data["color_themes"][3],
+            json!({ "id": "navy", "name": "Navy" })
+        );
+    }
 
     #[test]
     fn original_build_header_links() {
@@ -793,7 +3587,7 @@ mod tests {
         ];
 
         for (src, should_be) in inputs {
-            let got = build_header_links(&src);
+            let got = build_header_links(src);
             assert_eq!(got, should_be);
         }
     }
@@ -817,14 +3611,107 @@ mod tests {
            "<pre class=\"playpen\"><code class=\"language-rust editable\">#![no_std]\nlet s = \"foo\";\n #[some_attr]\n</code></pre>"),
         ];
         for (src, should_be) in &inputs {
-            let got = add_playpen_pre(
-                src,
-                &Playpen {
-                    editable: true,
-                    ..Playpen::default()
-                },
-                None,
-            );
+            let got = add_playpen_pre(src, None, &[]);
+            assert_eq!(&*got, *should_be);
+        }
+    }
+    #[test]
+    fn hidden_rust_lines() {
+        let inputs = [
+            // Only hidden lines
+            (
+                "<code class=\"language-rust\"># fn main() {\n#     run();\n# }</code>",
+                "<pre class=\"playpen\"><code class=\"language-rust\"><span class=\"boring\">fn main() {\n</span><span class=\"boring\">    run();\n</span><span class=\"boring\">}\n</span></code></pre>",
+            ),
+            // `##` is a literal `#`, and `#` followed by code isn't hidden
+            (
+                "<code class=\"language-rust no_run\">fn main() {\n    ## not hidden\n    #name\n}</code>",
+                "<pre class=\"playpen\"><code class=\"language-rust no_run\">fn main() {\n    # not hidden\n    #name\n}\n</code></pre>",
+            ),
+            // Other languages are left alone
+            (
+                "<code class=\"language-rusty\"># fn main() {}</code>",
+                "<code class=\"language-rusty\"># fn main() {}</code>",
+            ),
+            (
+                "<code class=\"language-python\"># comment\nx = 1</code>",
+                "<code class=\"language-python\"># comment\nx = 1</code>",
+            ),
+        ];
+        for (src, should_be) in &inputs {
+            let got = add_playpen_pre(src, None, &[]);
+            assert_eq!(&*got, *should_be);
+        }
+    }
+    #[test]
+    fn add_playpen_edition2021_and_crates() {
+        let crates = [String::from("rand"), String::from("regex")];
+        let inputs = [
+          ("<code class=\"language-rust\">fn main() {}</code>",
+           "<pre class=\"playpen\" data-edition=\"2021\" data-crates=\"rand regex\"><code class=\"language-rust edition2021\">fn main() {}\n</code></pre>"),
+          ("<code class=\"language-rust edition2018\">fn main() {}</code>",
+           "<pre class=\"playpen\" data-edition=\"2018\" data-crates=\"rand regex\"><code class=\"language-rust edition2018\">fn main() {}\n</code></pre>"),
+        ];
+        for (src, should_be) in &inputs {
+            let got = add_playpen_pre(src, Some(RustEdition::E2021), &crates);
+            assert_eq!(&*got, *should_be);
+        }
+    }
+    #[test]
+    fn add_playpen_escapes_the_crates() {
+        let crates = [String::from("a\"b<c")];
+        let got = add_playpen_pre(
+            "<code class=\"language-rust\">fn main() {}</code>",
+            None,
+            &crates,
+        );
+        assert!(got.contains(" data-crates=\"a&quot;b&lt;c\""), "{}", got);
+    }
+    #[test]
+    fn passthrough_languages_arent_code() {
+        let languages = [String::from("mermaid"), String::from("graphviz")];
+        let inputs = [
+            (
+                "<pre><code class=\"language-mermaid\">graph TD;\n  A--&gt;B;\n</code></pre>",
+                "<pre class=\"mermaid\">graph TD;\n  A--&gt;B;\n</pre>",
+            ),
+            (
+                "<pre><code class=\"language-graphviz nocopy\">digraph {}</code></pre>",
+                "<pre class=\"graphviz\">digraph {}</pre>",
+            ),
+            (
+                "<pre><code class=\"language-rust\">fn main() {}</code></pre>",
+                "<pre><code class=\"language-rust\">fn main() {}</code></pre>",
+            ),
+            (
+                "<pre><code class=\"language-mermaidish\">x</code></pre>",
+                "<pre><code class=\"language-mermaidish\">x</code></pre>",
+            ),
+        ];
+        for (src, should_be) in &inputs {
+            let got = passthrough_code_blocks(src, &languages);
+            assert_eq!(&*got, *should_be);
+        }
+    }
+
+    #[test]
+    fn add_code_block_options_to_blocks() {
+        let plain = "<pre><code class=\"language-text\">a\nb\n</code></pre>";
+        let inputs = [
+          (plain, false, false, plain),
+          (plain, true, false,
+           "<pre data-copyable><code class=\"language-text\">a\nb\n</code></pre>"),
+          (plain, false, true,
+           "<pre data-line-numbers><code class=\"language-text\"><span class=\"line-number\"></span>a\n<span class=\"line-number\"></span>b\n</code></pre>"),
+          (plain, true, true,
+           "<pre data-copyable data-line-numbers><code class=\"language-text\"><span class=\"line-number\"></span>a\n<span class=\"line-number\"></span>b\n</code></pre>"),
+          ("<pre><code class=\"language-text nocopy line-numbers\">a</code></pre>", true, false,
+           "<pre data-line-numbers><code class=\"language-text nocopy line-numbers\"><span class=\"line-number\"></span>a</code></pre>"),
+          ("<pre class=\"playpen\"><code class=\"language-rust\"><span class=\"boring\">fn main() {\n</span>x()\n<span class=\"boring\">}\n</span></code></pre>", false, true,
+           "<pre class=\"playpen\" data-line-numbers><code class=\"language-rust\"><span class=\"boring\"><span class=\"line-number\"></span>fn main() {\n</span><span class=\"line-number\"></span>x()\n<span class=\"boring\"><span class=\"line-number\"></span>}\n</span></code></pre>"),
+        ];
+        for (src, copyable, line_numbers, should_be) in &inputs {
+            let got = add_code_block_options(src, *copyable, *line_numbers);
             assert_eq!(&*got, *should_be);
         }
     }
@@ -832,23 +3719,16 @@ mod tests {
     fn add_playpen_edition2015() {
         let inputs = [
           ("<code class=\"language-rust\">x()</code>",
-           "<pre class=\"playpen\"><code class=\"language-rust edition2015\">\n<span class=\"boring\">#![allow(unused)]\n</span><span class=\"boring\">fn main() {\n</span>x()\n<span class=\"boring\">}\n</span></code></pre>"),
+           "<pre class=\"playpen\" data-edition=\"2015\"><code class=\"language-rust edition2015\">\n<span class=\"boring\">#![allow(unused)]\n</span><span class=\"boring\">fn main() {\n</span>x()\n<span class=\"boring\">}\n</span></code></pre>"),
           ("<code class=\"language-rust\">fn main() {}</code>",
-           "<pre class=\"playpen\"><code class=\"language-rust edition2015\">fn main() {}\n</code></pre>"),
+           "<pre class=\"playpen\" data-edition=\"2015\"><code class=\"language-rust edition2015\">fn main() {}\n</code></pre>"),
           ("<code class=\"language-rust edition2015\">fn main() {}</code>",
-           "<pre class=\"playpen\"><code class=\"language-rust edition2015\">fn main() {}\n</code></pre>"),
+           "<pre class=\"playpen\" data-edition=\"2015\"><code class=\"language-rust edition2015\">fn main() {}\n</code></pre>"),
           ("<code class=\"language-rust edition2018\">fn main() {}</code>",
-           "<pre class=\"playpen\"><code class=\"language-rust edition2018\">fn main() {}\n</code></pre>"),
+           "<pre class=\"playpen\" data-edition=\"2018\"><code class=\"language-rust edition2018\">fn main() {}\n</code></pre>"),
         ];
         for (src, should_be) in &inputs {
-            let got = add_playpen_pre(
-                src,
-                &Playpen {
-                    editable: true,
-                    ..Playpen::default()
-                },
-                Some(RustEdition::E2015),
-            );
+            let got = add_playpen_pre(src, Some(RustEdition::E2015), &[]);
             assert_eq!(&*got, *should_be);
         }
     }
@@ -856,23 +3736,16 @@ mod tests {
     fn add_playpen_edition2018() {
         let inputs = [
           ("<code class=\"language-rust\">x()</code>",
-           "<pre class=\"playpen\"><code class=\"language-rust edition2018\">\n<span class=\"boring\">#![allow(unused)]\n</span><span class=\"boring\">fn main() {\n</span>x()\n<span class=\"boring\">}\n</span></code></pre>"),
+           "<pre class=\"playpen\" data-edition=\"2018\"><code class=\"language-rust edition2018\">\n<span class=\"boring\">#![allow(unused)]\n</span><span class=\"boring\">fn main() {\n</span>x()\n<span class=\"boring\">}\n</span></code></pre>"),
           ("<code class=\"language-rust\">fn main() {}</code>",
-           "<pre class=\"playpen\"><code class=\"language-rust edition2018\">fn main() {}\n</code></pre>"),
+           "<pre class=\"playpen\" data-edition=\"2018\"><code class=\"language-rust edition2018\">fn main() {}\n</code></pre>"),
           ("<code class=\"language-rust edition2015\">fn main() {}</code>",
-           "<pre class=\"playpen\"><code class=\"language-rust edition2015\">fn main() {}\n</code></pre>"),
+           "<pre class=\"playpen\" data-edition=\"2015\"><code class=\"language-rust edition2015\">fn main() {}\n</code></pre>"),
           ("<code class=\"language-rust edition2018\">fn main() {}</code>",
-           "<pre class=\"playpen\"><code class=\"language-rust edition2018\">fn main() {}\n</code></pre>"),
+           "<pre class=\"playpen\" data-edition=\"2018\"><code class=\"language-rust edition2018\">fn main() {}\n</code></pre>"),
         ];
         for (src, should_be) in &inputs {
-            let got = add_playpen_pre(
-                src,
-                &Playpen {
-                    editable: true,
-                    ..Playpen::default()
-                },
-                Some(RustEdition::E2018),
-            );
+            let got = add_playpen_pre(src, Some(RustEdition::E2018), &[]);
             assert_eq!(&*got, *should_be);
         }
     }
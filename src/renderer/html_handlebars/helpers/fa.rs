@@ -0,0 +1,80 @@
+use crate::utils;
+use handlebars::{Context, Handlebars, Helper, Output, RenderContext, RenderError};
+
+/// Write the `<i>` of a FontAwesome icon, like `{{fa "fa-print"}}`. A second
+/// parameter is its title, and the `id` hash parameter its ID.
+pub fn fa(
+    h: &Helper<'_, '_>,
+    _r: &Handlebars<'_>,
+    _ctx: &Context,
+    _rc: &mut RenderContext<'_, '_>,
+    out: &mut dyn Output,
+) -> Result<(), RenderError> {
+    trace!("fa (handlebars helper)");
+
+    let icon = h
+        .param(0)
+        .and_then(|v| v.value().as_str())
+        .ok_or_else(|| RenderError::new("Param 0 with String type is required for fa helper."))?;
+    let title = h.param(1).and_then(|v| v.value().as_str()).unwrap_or("");
+    let id = h
+        .hash_get("id")
+        .and_then(|v| v.value().as_str())
+        .unwrap_or("");
+
+    out.write("<i")?;
+    if !id.is_empty() {
+        out.write(&format!(" id=\"{}\"", utils::escape_html(id)))?;
+    }
+    out.write(&format!(" class=\"fa {}\"", utils::escape_html(icon)))?;
+    if !title.is_empty() {
+        out.write(&format!(" title=\"{}\"", utils::escape_html(title)))?;
+    }
+    out.write("></i>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, data: &serde_json::Value) -> String {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_helper("fa", Box::new(fa));
+        handlebars
+            .register_template_string("icon", template)
+            .unwrap();
+
+        handlebars.render("icon", data).unwrap()
+    }
+
+    #[test]
+    fn icons_are_written_with_their_title_and_id() {
+        assert_eq!(
+            render("{{fa \"fa-print\"}}", &json!({})),
+            "<i class=\"fa fa-print\"></i>"
+        );
+        assert_eq!(
+            render("{{fa \"fa-print\" \"Print this book\"}}", &json!({})),
+            "<i class=\"fa fa-print\" title=\"Print this book\"></i>"
+        );
+        assert_eq!(
+            render(
+                "{{fa icon title id=\"print-button\"}}",
+                &json!({ "icon": "fa-print", "title": "Print" })
+            ),
+            "<i id=\"print-button\" class=\"fa fa-print\" title=\"Print\"></i>"
+        );
+    }
+
+    #[test]
+    fn the_attributes_are_escaped() {
+        let data = json!({ "title": "\"Quotes\" & <tags>", "icon": "fa-x\" onclick=\"alert(1)" });
+
+        assert_eq!(
+            render("{{fa icon title}}", &data),
+            "<i class=\"fa fa-x&quot; onclick=&quot;alert(1)\" \
+             title=\"&quot;Quotes&quot; &amp; &lt;tags&gt;\"></i>"
+        );
+    }
+}
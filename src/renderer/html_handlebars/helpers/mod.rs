@@ -1,3 +1,37 @@
+use std::collections::BTreeMap;
+
+use handlebars::{Context, RenderContext, RenderError};
+use serde_json::Value;
+
+pub mod fa;
 pub mod navigation;
+pub mod pagetoc;
+pub mod resolve;
 pub mod theme;
 pub mod toc;
+
+/// Read the `chapters` list from the root of the context, with the values as
+/// strings (like the `word_count` numbers). A `null` value (e.g. the path of a
+/// draft chapter) is treated the same as a missing one.
+fn chapters_from_context(
+    ctx: &Context,
+    rc: &mut RenderContext<'_, '_>,
+) -> Result<Vec<BTreeMap<String, String>>, RenderError> {
+    let chapters = rc.evaluate(ctx, "@root/chapters").and_then(|c| {
+        serde_json::value::from_value::<Vec<BTreeMap<String, Value>>>(c.as_json().clone())
+            .map_err(|_| RenderError::new("Could not decode the JSON data"))
+    })?;
+
+    Ok(chapters
+        .into_iter()
+        .map(|item| {
+            item.into_iter()
+                .filter_map(|(key, value)| match value {
+                    Value::Null => None,
+                    Value::String(value) => Some((key, value)),
+                    value => Some((key, value.to_string())),
+                })
+                .collect()
+        })
+        .collect())
+}
@@ -51,10 +51,7 @@ fn find_chapter(
 ) -> Result<Option<StringMap>, RenderError> {
     debug!("Get data from context");
 
-    let chapters = rc.evaluate(ctx, "@root/chapters").and_then(|c| {
-        serde_json::value::from_value::<Vec<StringMap>>(c.as_json().clone())
-            .map_err(|_| RenderError::new("Could not decode the JSON data"))
-    })?;
+    let chapters = super::chapters_from_context(ctx, rc)?;
 
     let base_path = rc
         .evaluate(ctx, "@root/path")?
@@ -63,8 +60,14 @@ fn find_chapter(
         .ok_or_else(|| RenderError::new("Type error for `path`, string expected"))?
         .replace("\"", "");
 
-    if !rc.evaluate(ctx, "@root/is_index")?.is_missing() {
-        // Special case for index.md which may be a synthetic page.
+    let is_index = rc.evaluate(ctx, "@root/is_index")?.as_json().clone();
+    let is_index = is_index == json!(true) || is_index == json!("true");
+    if is_index
+        && !chapters
+            .iter()
+            .any(|chapter| chapter.get("path") == Some(&base_path))
+    {
+        // Special case for the copy of the first chapter at index.html.
         // Target::find won't match because there is no page with the path
         // "index.md" (unless there really is an index.md in SUMMARY.md).
         match target {
@@ -91,7 +94,7 @@ fn find_chapter(
         match item.get("path") {
             Some(path) if !path.is_empty() => {
                 if let Some(previous) = previous {
-                    if let Some(item) = target.find(&base_path, &path, &item, &previous)? {
+                    if let Some(item) = target.find(&base_path, path, &item, &previous)? {
                         return Ok(Some(item));
                     }
                 }
@@ -105,13 +108,87 @@ fn find_chapter(
     Ok(None)
 }
 
+/// A previous/next chapter, as something which can be linked to.
+struct ChapterLink {
+    title: String,
+    link: String,
+}
+
+impl ChapterLink {
+    /// Read the chapter from a `{"title": ..., "link": ...}` object added to
+    /// the context by the renderer.
+    fn from_context_value(value: &serde_json::Value) -> Result<ChapterLink, RenderError> {
+        let field = |name: &str| {
+            value
+                .get(name)
+                .and_then(serde_json::Value::as_str)
+                .map(ToString::to_string)
+                .ok_or_else(|| {
+                    RenderError::new(format!("Navigation link without a `{}` string", name))
+                })
+        };
+
+        Ok(ChapterLink {
+            title: field("title")?,
+            link: field("link")?,
+        })
+    }
+
+    /// Create the link from an entry in `@root/chapters`.
+    fn from_chapter(chapter: &StringMap) -> Result<ChapterLink, RenderError> {
+        let title = chapter
+            .get("name")
+            .ok_or_else(|| RenderError::new("No title found for chapter in JSON data"))?;
+
+        let link = chapter
+            .get("path")
+            .ok_or_else(|| RenderError::new("No path found for chapter in JSON data"))
+            .and_then(|p| {
+                Path::new(p)
+                    .with_extension("html")
+                    .to_str()
+                    .ok_or_else(|| RenderError::new("Link could not be converted to str"))
+                    .map(|p| p.replace("\\", "/"))
+            })?;
+
+        Ok(ChapterLink {
+            title: title.clone(),
+            link,
+        })
+    }
+}
+
+/// Find the chapter to link to, preferring the value the renderer put in the
+/// context (`@root/previous` or `@root/next`) and otherwise searching the
+/// chapter list.
+fn find_link(
+    ctx: &Context,
+    rc: &mut RenderContext<'_, '_>,
+    target: Target,
+) -> Result<Option<ChapterLink>, RenderError> {
+    let key = match target {
+        Target::Previous => "@root/previous",
+        Target::Next => "@root/next",
+    };
+
+    let from_context = rc.evaluate(ctx, key)?;
+    if !from_context.is_missing() {
+        return ChapterLink::from_context_value(from_context.as_json()).map(Some);
+    }
+
+    match find_chapter(ctx, rc, target)? {
+        Some(chapter) => ChapterLink::from_chapter(&chapter).map(Some),
+        None => Ok(None),
+    }
+}
+
 fn render(
     _h: &Helper<'_, '_>,
     r: &Handlebars<'_>,
     ctx: &Context,
     rc: &mut RenderContext<'_, '_>,
     out: &mut dyn Output,
-    chapter: &StringMap,
+    chapter: &ChapterLink,
 ) -> Result<(), RenderError> {
     trace!("Creating BTreeMap to inject in context");
 
@@ -122,27 +199,24 @@ fn render(
         .as_str()
         .ok_or_else(|| RenderError::new("Type error for `path`, string expected"))?
         .replace("\"", "");
-
-    context.insert(
-        "path_to_root".to_owned(),
-        json!(utils::fs::path_to_root(&base_path)),
-    );
-
-    chapter
-        .get("name")
-        .ok_or_else(|| RenderError::new("No title found for chapter in JSON data"))
-        .map(|name| context.insert("title".to_owned(), json!(name)))?;
-
-    chapter
-        .get("path")
-        .ok_or_else(|| RenderError::new("No path found for chapter in JSON data"))
-        .and_then(|p| {
-            Path::new(p)
-                .with_extension("html")
-                .to_str()
-                .ok_or_else(|| RenderError::new("Link could not be converted to str"))
-                .map(|p| context.insert("link".to_owned(), json!(p.replace("\\", "/"))))
-        })?;
+    // Pages in a directory of their own (with pretty URLs) set their own path
+    // to the root
+    let path_to_root = rc
+        .evaluate(ctx, "@root/path_to_root")?
+        .as_json()
+        .as_str()
+        .map(str::to_owned)
+        .unwrap_or_else(|| utils::fs::path_to_root(&base_path));
+
+    context.insert("path_to_root".to_owned(), json!(path_to_root));
+    context.insert("title".to_owned(), json!(chapter.title));
+    context.insert("link".to_owned(), json!(chapter.link));
+    // The block has its own context, but the labels and the direction of the
+    // arrows are still needed
+    let strings = rc.evaluate(ctx, "@root/strings")?.as_json().clone();
+    context.insert("strings".to_owned(), strings);
+    let is_rtl = rc.evaluate(ctx, "@root/is_rtl")?.as_json().as_bool();
+    context.insert("is_rtl".to_owned(), json!(is_rtl.unwrap_or(false)));
 
     trace!("Render template");
 
@@ -166,7 +240,7 @@ pub fn previous(
 ) -> Result<(), RenderError> {
     trace!("previous (handlebars helper)");
 
-    if let Some(previous) = find_chapter(ctx, rc, Target::Previous)? {
+    if let Some(previous) = find_link(ctx, rc, Target::Previous)? {
         render(_h, r, ctx, rc, out, &previous)?;
     }
 
@@ -182,7 +256,7 @@ pub fn next(
 ) -> Result<(), RenderError> {
     trace!("next (handlebars helper)");
 
-    if let Some(next) = find_chapter(ctx, rc, Target::Next)? {
+    if let Some(next) = find_link(ctx, rc, Target::Next)? {
         render(_h, r, ctx, rc, out, &next)?;
     }
 
@@ -192,6 +266,9 @@ pub fn next(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::book::{Book, BookItem, Chapter};
+    use crate::config::{Config, HtmlConfig};
+    use crate::renderer::html_handlebars::hbs_renderer::make_data;
 
     static TEMPLATE: &str =
         "{{#previous}}{{title}}: {{link}}{{/previous}}|{{#next}}{{title}}: {{link}}{{/next}}";
@@ -257,6 +334,35 @@ mod tests {
             "|two: two.html"
         );
     }
+    #[test]
+    fn context_values_take_precedence() {
+        let data = json!({
+           "name": "two",
+           "path": "two.path",
+           "previous": { "title": "One", "link": "first/one.html" },
+           "next": { "title": "Three", "link": "three.html" },
+           "chapters": [
+              {
+                 "name": "one",
+                 "path": "one.path"
+              },
+              {
+                 "name": "two",
+                 "path": "two.path",
+              }
+           ]
+        });
+
+        let mut h = Handlebars::new();
+        h.register_helper("previous", Box::new(previous));
+        h.register_helper("next", Box::new(next));
+
+        assert_eq!(
+            h.render_template(TEMPLATE, &data).unwrap(),
+            "One: first/one.html|Three: three.html"
+        );
+    }
+
     #[test]
     fn test_last() {
         let data = json!({
@@ -287,4 +393,81 @@ mod tests {
             "two: two.html|"
         );
     }
+
+    /// A book with nested chapters, a separator and drafts at both ends.
+    fn nested_book() -> Book {
+        let mut first = Chapter::new("First", String::new(), "first.md", Vec::new());
+        let mut nested = Chapter::new(
+            "Nested",
+            String::new(),
+            "first/nested.md",
+            vec![String::from("First")],
+        );
+        nested.sub_items.push(BookItem::Chapter(Chapter::new(
+            "Deeply Nested",
+            String::new(),
+            "first/nested/deep.md",
+            vec![String::from("First"), String::from("Nested")],
+        )));
+        first.sub_items.push(BookItem::Chapter(nested));
+        first.sub_items.push(BookItem::Chapter(Chapter::new_draft(
+            "Nested Draft",
+            vec![String::from("First")],
+        )));
+
+        let mut book = Book::new();
+        book.push_item(Chapter::new_draft("Draft Intro", Vec::new()))
+            .push_item(first)
+            .push_item(BookItem::Separator)
+            .push_item(Chapter::new(
+                "Second",
+                String::new(),
+                "second.md",
+                Vec::new(),
+            ))
+            .push_item(Chapter::new_draft("Future Work", Vec::new()));
+        book
+    }
+
+    #[test]
+    fn navigation_walks_nested_chapters_depth_first() {
+        let book = nested_book();
+        let config = Config::default();
+        let mut data = make_data(Path::new(""), &book, &config, &HtmlConfig::default()).unwrap();
+
+        let mut h = Handlebars::new();
+        h.register_helper("previous", Box::new(previous));
+        h.register_helper("next", Box::new(next));
+
+        let expected = [
+            ("first.md", "|Nested: first/nested.html"),
+            (
+                "first/nested.md",
+                "First: first.html|Deeply Nested: first/nested/deep.html",
+            ),
+            (
+                "first/nested/deep.md",
+                "Nested: first/nested.html|Second: second.html",
+            ),
+            ("second.md", "Deeply Nested: first/nested/deep.html|"),
+        ];
+
+        for (path, should_be) in &expected {
+            data.insert("path".to_owned(), json!(path));
+            assert_eq!(
+                h.render_template(TEMPLATE, &data).unwrap(),
+                *should_be,
+                "{}",
+                path
+            );
+        }
+
+        // The copy of the first chapter written to index.html
+        data.insert("path".to_owned(), json!("index.md"));
+        data.insert("is_index".to_owned(), json!(true));
+        assert_eq!(
+            h.render_template(TEMPLATE, &data).unwrap(),
+            "|Nested: first/nested.html"
+        );
+    }
 }
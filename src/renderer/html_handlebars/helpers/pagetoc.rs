@@ -0,0 +1,123 @@
+use handlebars::{Context, Handlebars, Helper, Output, RenderContext, RenderError};
+
+/// A heading of the current chapter, from its `page_headings`.
+#[derive(Deserialize)]
+struct Heading {
+    level: u32,
+    id: String,
+    /// The HTML inside the heading.
+    text: String,
+}
+
+/// Render the `page_headings` of the current chapter as nested lists of
+/// links, `<ol class="pagetoc">`, or nothing when it has none.
+pub fn pagetoc(
+    _h: &Helper<'_, '_>,
+    _r: &Handlebars<'_>,
+    ctx: &Context,
+    rc: &mut RenderContext<'_, '_>,
+    out: &mut dyn Output,
+) -> Result<(), RenderError> {
+    trace!("pagetoc (handlebars helper)");
+
+    let headings = rc.evaluate(ctx, "@root/page_headings")?;
+    let headings: Vec<Heading> = if headings.is_missing() {
+        Vec::new()
+    } else {
+        serde_json::value::from_value(headings.as_json().clone())
+            .map_err(|_| RenderError::new("Type error for `page_headings`, list expected"))?
+    };
+
+    out.write(&render_headings(&headings))?;
+    Ok(())
+}
+
+fn render_headings(headings: &[Heading]) -> String {
+    let mut html = String::new();
+    // The levels of the open lists. A heading of a lower level than the
+    // previous one is nested in its item
+    let mut levels: Vec<u32> = Vec::new();
+
+    for heading in headings {
+        match levels.last() {
+            None => {
+                html.push_str("<ol class=\"pagetoc\">\n");
+                levels.push(heading.level);
+            }
+            Some(&level) if heading.level > level => {
+                html.push_str("\n<ol>\n");
+                levels.push(heading.level);
+            }
+            Some(_) => {
+                html.push_str("</li>\n");
+                while levels.len() > 1 && heading.level < levels[levels.len() - 1] {
+                    levels.pop();
+                    html.push_str("</ol>\n</li>\n");
+                }
+                // The outermost list has the highest level so far
+                if heading.level < levels[0] {
+                    levels[0] = heading.level;
+                }
+            }
+        }
+        html.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a>",
+            heading.id, heading.text
+        ));
+    }
+
+    if !levels.is_empty() {
+        html.push_str("</li>\n");
+        for _ in 1..levels.len() {
+            html.push_str("</ol>\n</li>\n");
+        }
+        html.push_str("</ol>\n");
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(data: &serde_json::Value) -> String {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_helper("pagetoc", Box::new(pagetoc));
+        handlebars
+            .register_template_string("pagetoc", "{{pagetoc}}")
+            .unwrap();
+
+        handlebars.render("pagetoc", data).unwrap()
+    }
+
+    #[test]
+    fn headings_are_nested_by_level() {
+        let heading = |level: u32, id: &str| json!({ "level": level, "id": id, "text": id });
+        let data = json!({
+            "page_headings": [
+                heading(3, "before"),
+                heading(2, "first"),
+                heading(3, "nested"),
+                heading(4, "deeper"),
+                heading(2, "second"),
+            ],
+        });
+
+        assert_eq!(
+            render(&data),
+            "<ol class=\"pagetoc\">\n\
+             <li><a href=\"#before\">before</a></li>\n\
+             <li><a href=\"#first\">first</a>\n<ol>\n\
+             <li><a href=\"#nested\">nested</a>\n<ol>\n\
+             <li><a href=\"#deeper\">deeper</a></li>\n</ol>\n</li>\n</ol>\n</li>\n\
+             <li><a href=\"#second\">second</a></li>\n\
+             </ol>\n"
+        );
+    }
+
+    #[test]
+    fn no_headings_render_nothing() {
+        assert_eq!(render(&json!({ "page_headings": [] })), "");
+        assert_eq!(render(&json!({})), "");
+    }
+}
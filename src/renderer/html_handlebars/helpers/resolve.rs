@@ -0,0 +1,155 @@
+use handlebars::{Context, Handlebars, Helper, Output, RenderContext, RenderError};
+use regex::Regex;
+
+/// Write the URL of a file of the book, like `css/chrome.css`, relative to
+/// the current page: its `path_to_root` followed by the path. Absolute URLs
+/// (`https://…`, `/…`) and fragments (`#…`) are written as they are. The URL
+/// is escaped like the other values of the templates, since it goes in an
+/// attribute.
+pub fn resolve(
+    h: &Helper<'_, '_>,
+    r: &Handlebars<'_>,
+    ctx: &Context,
+    rc: &mut RenderContext<'_, '_>,
+    out: &mut dyn Output,
+) -> Result<(), RenderError> {
+    trace!("resolve (handlebars helper)");
+
+    let path = h.param(0).and_then(|v| v.value().as_str()).ok_or_else(|| {
+        RenderError::new("Param 0 with String type is required for resolve helper.")
+    })?;
+
+    let path_to_root = rc.evaluate(ctx, "@root/path_to_root")?;
+    let path_to_root = if path_to_root.is_missing() {
+        ""
+    } else {
+        path_to_root
+            .as_json()
+            .as_str()
+            .ok_or_else(|| RenderError::new("Type error for `path_to_root`, string expected"))?
+    };
+
+    out.write(&r.get_escape_fn()(&resolve_path(path_to_root, path)))?;
+    Ok(())
+}
+
+fn resolve_path(path_to_root: &str, path: &str) -> String {
+    lazy_static! {
+        static ref SCHEME: Regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*:").unwrap();
+        static ref SLASHES: Regex = Regex::new(r"/{2,}").unwrap();
+    }
+
+    if SCHEME.is_match(path) || path.starts_with('/') || path.starts_with('#') {
+        return path.to_string();
+    }
+
+    let path = path.replace('\\', "/");
+    let mut path = path.as_str();
+    while path.starts_with("./") {
+        path = &path[2..];
+    }
+    let prefix = path_to_root.replace('\\', "/");
+
+    let resolved = if prefix.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}/{}", prefix.trim_end_matches('/'), path)
+    };
+    SLASHES.replace_all(&resolved, "/").into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, data: &serde_json::Value) -> String {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_helper("resolve", Box::new(resolve));
+        handlebars
+            .register_partial("link", "{{resolve \"book.js\"}}")
+            .unwrap();
+        handlebars
+            .register_template_string("page", template)
+            .unwrap();
+
+        handlebars.render("page", data).unwrap()
+    }
+
+    #[test]
+    fn paths_on_the_root_page_are_unchanged() {
+        let data = json!({ "path_to_root": "" });
+
+        assert_eq!(
+            render("{{resolve \"css/chrome.css\"}}", &data),
+            "css/chrome.css"
+        );
+        assert_eq!(
+            render("{{resolve \"./favicon.png\"}}", &data),
+            "favicon.png"
+        );
+        assert_eq!(
+            render("{{resolve \"css/chrome.css\"}}", &json!({})),
+            "css/chrome.css"
+        );
+    }
+
+    #[test]
+    fn paths_on_nested_pages_are_relative_to_the_root() {
+        let data = json!({ "path_to_root": "../../" });
+
+        assert_eq!(
+            render("{{resolve \"css/chrome.css\"}}", &data),
+            "../../css/chrome.css"
+        );
+        assert_eq!(
+            resolve_path("..\\..\\", "css\\print.css"),
+            "../../css/print.css"
+        );
+        assert_eq!(
+            render("{{resolve \"fonts//fonts.css\"}}", &data),
+            "../../fonts/fonts.css"
+        );
+        assert_eq!(render("{{> link}}", &data), "../../book.js");
+        assert_eq!(
+            render(
+                "{{#each files}}{{resolve this}} {{/each}}",
+                &json!({
+                    "path_to_root": "../",
+                    "files": ["a.css", "b.js"],
+                })
+            ),
+            "../a.css ../b.js "
+        );
+    }
+
+    #[test]
+    fn absolute_urls_are_unchanged() {
+        let data = json!({ "path_to_root": "../../" });
+
+        for &url in &[
+            "https://example.com/style.css",
+            "//cdn.example.com/script.js",
+            "/absolute/path.css",
+            "mailto:someone@example.com",
+            "#top",
+        ] {
+            let template = format!("{{{{resolve \"{}\"}}}}", url);
+            assert_eq!(render(&template, &data), url);
+        }
+    }
+
+    #[test]
+    fn urls_are_escaped() {
+        let data = json!({
+            "path_to_root": "../",
+            "url": "https://github.com/a/b\" onmouseover=\"alert(1)",
+            "path": "a<b>.html",
+        });
+
+        assert_eq!(
+            render("<a href=\"{{resolve url}}\">", &data),
+            "<a href=\"https://github.com/a/b&quot; onmouseover=&quot;alert(1)\">"
+        );
+        assert_eq!(render("{{resolve path}}", &data), "../a&lt;b&gt;.html");
+    }
+}
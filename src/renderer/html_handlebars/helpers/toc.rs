@@ -1,6 +1,6 @@
-use std::collections::BTreeMap;
 use std::path::Path;
 
+use crate::config::Fold;
 use crate::utils;
 
 use handlebars::{Context, Handlebars, Helper, HelperDef, Output, RenderContext, RenderError};
@@ -10,6 +10,8 @@ use pulldown_cmark::{html, Event, Parser};
 #[derive(Clone, Copy)]
 pub struct RenderToc {
     pub no_section_label: bool,
+    pub fold: Fold,
+    pub pretty_urls: bool,
 }
 
 impl HelperDef for RenderToc {
@@ -24,16 +26,21 @@ impl HelperDef for RenderToc {
         // get value from context data
         // rc.get_path() is current json parent path, you should always use it like this
         // param is the key of value you want to display
-        let chapters = rc.evaluate(ctx, "@root/chapters").and_then(|c| {
-            serde_json::value::from_value::<Vec<BTreeMap<String, String>>>(c.as_json().clone())
-                .map_err(|_| RenderError::new("Could not decode the JSON data"))
-        })?;
+        let chapters = super::chapters_from_context(ctx, rc)?;
         let current_path = rc
             .evaluate(ctx, "@root/path")?
             .as_json()
             .as_str()
             .ok_or_else(|| RenderError::new("Type error for `path`, string expected"))?
             .replace("\"", "");
+        // Pages outside of the book's structure (like the 404 page) set their
+        // own path to the root
+        let path_to_root = rc
+            .evaluate(ctx, "@root/path_to_root")?
+            .as_json()
+            .as_str()
+            .map(str::to_owned)
+            .unwrap_or_else(|| utils::fs::path_to_root(&current_path));
 
         let current_section = rc
             .evaluate(ctx, "@root/section")?
@@ -42,17 +49,14 @@ impl HelperDef for RenderToc {
             .map(str::to_owned)
             .unwrap_or_default();
 
-        let fold_enable = rc
-            .evaluate(ctx, "@root/fold_enable")?
-            .as_json()
-            .as_bool()
-            .ok_or_else(|| RenderError::new("Type error for `fold_enable`, bool expected"))?;
+        // The toggles point to where the text goes
+        let toggle = match rc.evaluate(ctx, "@root/is_rtl")?.as_json().as_bool() {
+            Some(true) => "<a class=\"toggle\"><div>❰</div></a>",
+            _ => "<a class=\"toggle\"><div>❱</div></a>",
+        };
 
-        let fold_level = rc
-            .evaluate(ctx, "@root/fold_level")?
-            .as_json()
-            .as_u64()
-            .ok_or_else(|| RenderError::new("Type error for `fold_level`, u64 expected"))?;
+        let fold_enable = self.fold.enable;
+        let fold_level = usize::from(self.fold.level);
 
         out.write("<ol class=\"chapter\">")?;
 
@@ -60,7 +64,7 @@ impl HelperDef for RenderToc {
 
         for item in chapters {
             // Spacer
-            if item.get("spacer").is_some() {
+            if item.contains_key("spacer") {
                 out.write("<li class=\"spacer\"></li>")?;
                 continue;
             }
@@ -78,7 +82,7 @@ impl HelperDef for RenderToc {
                     true
                 } else {
                     // Levels that are larger than this would be folded.
-                    level - 1 < fold_level as usize
+                    level - 1 < fold_level
                 };
 
             if level > current_level {
@@ -96,7 +100,7 @@ impl HelperDef for RenderToc {
                 }
                 write_li_open_tag(out, is_expanded, false)?;
             } else {
-                write_li_open_tag(out, is_expanded, item.get("section").is_none())?;
+                write_li_open_tag(out, is_expanded, !item.contains_key("section"))?;
             }
 
             // Part title
@@ -112,15 +116,10 @@ impl HelperDef for RenderToc {
                 if !path.is_empty() {
                     out.write("<a href=\"")?;
 
-                    let tmp = Path::new(item.get("path").expect("Error: path should be Some(_)"))
-                        .with_extension("html")
-                        .to_str()
-                        .unwrap()
-                        // Hack for windows who tends to use `\` as separator instead of `/`
-                        .replace("\\", "/");
+                    let tmp = utils::fs::chapter_link(Path::new(path), self.pretty_urls);
 
                     // Add link
-                    out.write(&utils::fs::path_to_root(&current_path))?;
+                    out.write(&path_to_root)?;
                     out.write(&tmp)?;
                     out.write("\"")?;
 
@@ -141,7 +140,7 @@ impl HelperDef for RenderToc {
                 // Section does not necessarily exist
                 if let Some(section) = item.get("section") {
                     out.write("<strong aria-hidden=\"true\">")?;
-                    out.write(&section)?;
+                    out.write(section)?;
                     out.write("</strong> ")?;
                 }
             }
@@ -149,10 +148,13 @@ impl HelperDef for RenderToc {
             if let Some(name) = item.get("name") {
                 // Render only inline code blocks
 
-                // filter all events that are not inline code blocks
-                let parser = Parser::new(name).filter(|event| match *event {
-                    Event::Code(_) | Event::Html(_) | Event::Text(_) => true,
-                    _ => false,
+                // filter all events that are not inline code blocks, and show
+                // inline HTML as text so a name like `Generics & <T>` can't
+                // break the markup of the sidebar
+                let parser = Parser::new(name).filter_map(|event| match event {
+                    Event::Code(_) | Event::Text(_) => Some(event),
+                    Event::Html(html) => Some(Event::Text(html)),
+                    _ => None,
                 });
 
                 // render markdown to html
@@ -171,7 +173,7 @@ impl HelperDef for RenderToc {
             if let Some(flag) = item.get("has_sub_items") {
                 let has_sub_items = flag.parse::<bool>().unwrap_or_default();
                 if fold_enable && has_sub_items {
-                    out.write("<a class=\"toggle\"><div>❱</div></a>")?;
+                    out.write(toggle)?;
                 }
             }
             out.write("</li>")?;
@@ -202,3 +204,162 @@ fn write_li_open_tag(
     li.push_str("\">");
     out.write(&li)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(fold: Fold, data: &serde_json::Value) -> String {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_helper(
+            "toc",
+            Box::new(RenderToc {
+                no_section_label: false,
+                fold,
+                pretty_urls: false,
+            }),
+        );
+        handlebars
+            .register_template_string("toc", "{{toc}}")
+            .unwrap();
+
+        handlebars.render("toc", data).unwrap()
+    }
+
+    /// Render the table of contents of a small book while `current` is open.
+    fn render_toc(fold: Fold, current: &str, section: &str) -> String {
+        let chapter = |section: &str, name: &str, has_sub_items: bool| {
+            json!({
+                "section": section,
+                "name": name,
+                "path": format!("{}.md", name),
+                "has_sub_items": has_sub_items.to_string(),
+            })
+        };
+        let data = json!({
+            "path": current,
+            "section": section,
+            "chapters": [
+                chapter("1.", "first", true),
+                chapter("1.1.", "nested", true),
+                chapter("1.1.1.", "deeply-nested", false),
+                chapter("2.", "second", true),
+                chapter("2.1.", "other-nested", false),
+            ],
+        });
+
+        render(fold, &data)
+    }
+
+    /// Is the item linking to `name` rendered as expanded?
+    fn is_expanded(toc: &str, name: &str) -> bool {
+        let link = format!("\"><a href=\"{}.html\"", name);
+        if toc.contains(&format!("<li class=\"chapter-item expanded {}", link)) {
+            true
+        } else if toc.contains(&format!("<li class=\"chapter-item {}", link)) {
+            false
+        } else {
+            panic!("{} isn't in the table of contents: {}", name, toc);
+        }
+    }
+
+    fn expanded_items(toc: &str) -> Vec<&'static str> {
+        let names = ["first", "nested", "deeply-nested", "second", "other-nested"];
+        names
+            .iter()
+            .cloned()
+            .filter(|name| is_expanded(toc, name))
+            .collect()
+    }
+
+    #[test]
+    fn everything_is_expanded_without_folding() {
+        let fold = Fold {
+            enable: false,
+            level: 0,
+        };
+        let toc = render_toc(fold, "second.md", "2.");
+
+        assert_eq!(
+            expanded_items(&toc),
+            ["first", "nested", "deeply-nested", "second", "other-nested"]
+        );
+        assert!(!toc.contains("class=\"toggle\""));
+    }
+
+    #[test]
+    fn fold_level_zero_only_expands_the_active_chapter() {
+        let fold = Fold {
+            enable: true,
+            level: 0,
+        };
+        let toc = render_toc(fold, "second.md", "2.");
+
+        assert_eq!(expanded_items(&toc), ["second"]);
+        assert!(toc.contains("<a href=\"second.html\" class=\"active\">"));
+        assert!(toc.contains("<a class=\"toggle\"><div>❱</div></a>"));
+    }
+
+    #[test]
+    fn the_toggles_point_left_in_right_to_left_books() {
+        let fold = Fold {
+            enable: true,
+            level: 0,
+        };
+        let data = json!({
+            "path": "first.md",
+            "is_rtl": true,
+            "chapters": [
+                { "section": "1.", "name": "first", "path": "first.md", "has_sub_items": "true" },
+                { "section": "1.1.", "name": "nested", "path": "nested.md", "has_sub_items": "false" },
+            ],
+        });
+
+        let toc = render(fold, &data);
+        assert!(toc.contains("<a class=\"toggle\"><div>❰</div></a>"));
+        assert!(!toc.contains("❱"));
+    }
+
+    #[test]
+    fn fold_level_one_expands_the_top_level_chapters() {
+        let fold = Fold {
+            enable: true,
+            level: 1,
+        };
+        let toc = render_toc(fold, "other-nested.md", "2.1.");
+
+        assert_eq!(expanded_items(&toc), ["first", "second", "other-nested"]);
+    }
+
+    #[test]
+    fn the_path_to_a_deeply_nested_chapter_is_always_expanded() {
+        let fold = Fold {
+            enable: true,
+            level: 0,
+        };
+        let toc = render_toc(fold, "deeply-nested.md", "1.1.1.");
+
+        assert_eq!(expanded_items(&toc), ["first", "nested", "deeply-nested"]);
+        assert!(toc.contains("<a href=\"deeply-nested.html\" class=\"active\">"));
+    }
+
+    #[test]
+    fn chapter_names_are_escaped() {
+        let data = json!({
+            "path": "generics.md",
+            "chapters": [
+                {
+                    "section": "1.",
+                    "name": "Generics & <T> \"quoted\"",
+                    "path": "generics.md",
+                },
+                { "section": "2.", "name": "`Vec<T>`", "path": "vec.md" },
+            ],
+        });
+        let toc = render(Fold::default(), &data);
+
+        assert!(toc.contains("</strong> Generics &amp; &lt;T&gt; &quot;quoted&quot;</a>"));
+        assert!(toc.contains("</strong> <code>Vec&lt;T&gt;</code></a>"));
+        assert!(!toc.contains("<T>"));
+    }
+}
@@ -0,0 +1,398 @@
+//! Highlighting the code blocks of the rendered pages when the book is built,
+//! with syntect (`output.html.highlight = "syntect"`). The highlighted code
+//! has classes, and the stylesheets of syntect's themes which color them
+//! replace the ones of highlight.js.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use regex::{Captures, Regex};
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{css_for_theme_with_class_style, line_tokens_to_classed_spans, ClassStyle};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxDefinition, SyntaxReference, SyntaxSet};
+
+use crate::config::Syntect;
+use crate::errors::*;
+
+/// The classes of the highlighted code are prefixed, so they don't clash with
+/// the theme's.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "syn-" };
+
+/// The syntaxes and the theme used to highlight the code blocks of a book.
+pub(super) struct Highlighter {
+    syntaxes: SyntaxSet,
+    /// The themes of the light and dark color themes of the book.
+    theme: Theme,
+    dark_theme: Theme,
+    /// The name of the syntax of every aliased language.
+    aliases: BTreeMap<String, String>,
+    /// The unknown languages which were already logged.
+    unknown: Mutex<HashSet<String>>,
+}
+
+impl Highlighter {
+    /// Load syntect's syntaxes (and the book's own, if it has some) and the
+    /// configured themes.
+    pub(super) fn new(config: &Syntect, root: &Path) -> Result<Highlighter> {
+        let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+        // The syntax loaded from each file, by the file's path and its name
+        let mut loaded = BTreeMap::new();
+        for path in &config.syntaxes {
+            let file = root.join(path);
+            let text = fs::read_to_string(&file)
+                .with_context(|| format!("Unable to read the syntax {}", file.display()))?;
+            let fallback_name = file.file_stem().and_then(|stem| stem.to_str());
+            let syntax = SyntaxDefinition::load_from_str(&text, true, fallback_name)
+                .with_context(|| format!("Unable to load the syntax {}", file.display()))?;
+            debug!(
+                "Loaded the syntax \"{}\" from {}",
+                syntax.name,
+                file.display()
+            );
+
+            loaded.insert(path.to_string_lossy().into_owned(), syntax.name.clone());
+            if let Some(file_name) = path.file_name() {
+                loaded.insert(
+                    file_name.to_string_lossy().into_owned(),
+                    syntax.name.clone(),
+                );
+            }
+            builder.add(syntax);
+        }
+        let syntaxes = builder.build();
+
+        let mut aliases = BTreeMap::new();
+        for (language, target) in &config.aliases {
+            let name = match loaded.get(target) {
+                Some(name) => name.clone(),
+                None if syntaxes.find_syntax_by_name(target).is_some() => target.clone(),
+                None => bail!(
+                    "The `output.html.syntect.aliases` of \"{}\" is \"{}\", which isn't \
+                     the name of a syntax or of a file in `output.html.syntect.syntaxes`",
+                    language,
+                    target
+                ),
+            };
+            aliases.insert(language.clone(), name);
+        }
+
+        let themes = ThemeSet::load_defaults().themes;
+        let find_theme = |option: &str, name: &str| match themes.get(name) {
+            Some(theme) => Ok(theme.clone()),
+            None => Err(anyhow::anyhow!(
+                "Unknown `output.html.syntect.{}` \"{}\", expected one of: {}",
+                option,
+                name,
+                themes.keys().cloned().collect::<Vec<_>>().join(", ")
+            )),
+        };
+        let theme = find_theme("theme", &config.theme)?;
+        let dark_theme = find_theme("dark-theme", &config.dark_theme)?;
+
+        Ok(Highlighter {
+            syntaxes,
+            theme,
+            dark_theme,
+            aliases,
+            unknown: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// The stylesheets coloring the highlighted code, by the names of the
+    /// highlight.js ones they replace: `highlight.css` for the light color
+    /// themes, and the others for the dark ones.
+    pub(super) fn stylesheets(&self) -> Vec<(&'static str, String)> {
+        let css = css_for_theme_with_class_style(&self.theme, CLASS_STYLE);
+        let dark_css = css_for_theme_with_class_style(&self.dark_theme, CLASS_STYLE);
+        vec![
+            ("highlight.css", css),
+            ("tomorrow-night.css", dark_css.clone()),
+            ("ayu-highlight.css", dark_css),
+        ]
+    }
+
+    /// Highlight the code blocks of a rendered page. Blocks in languages
+    /// syntect doesn't know are left alone, and so are the classes of the
+    /// blocks and the hidden lines of Rust code, which the playpen needs.
+    pub(super) fn highlight_code_blocks(&self, html: &str) -> String {
+        lazy_static! {
+            static ref CODE_BLOCK: Regex =
+                Regex::new(r#"(?s)<code class="([^"]+)">(.*?)</code>"#).unwrap();
+        }
+
+        CODE_BLOCK
+            .replace_all(html, |caps: &Captures<'_>| {
+                let language = caps[1]
+                    .split_whitespace()
+                    .find(|class| class.starts_with("language-"))
+                    .map(|class| &class["language-".len()..]);
+
+                match language.and_then(|lang| self.highlight(lang, &caps[2])) {
+                    Some(highlighted) => format!(
+                        "<code class=\"{} syn-code\">{}</code>",
+                        &caps[1], highlighted
+                    ),
+                    None => caps[0].to_string(),
+                }
+            })
+            .into_owned()
+    }
+
+    /// Highlight the (escaped) content of a code block, keeping the
+    /// `<span class="boring">` around hidden lines. The spans of every line
+    /// are closed at its end (and opened again on the next one), so they
+    /// don't cross the ones of the hidden lines.
+    fn highlight(&self, language: &str, code: &str) -> Option<String> {
+        let syntax = self.find_syntax(language)?;
+        let mut state = ParseState::new(syntax);
+        let mut scopes = ScopeStack::new();
+        let mut highlighted = String::with_capacity(code.len() * 2);
+
+        let mut rest = code;
+        while !rest.is_empty() {
+            let end = rest.find('\n').map_or(rest.len(), |ix| ix + 1);
+            let mut line = &rest[..end];
+            rest = &rest[end..];
+
+            // The hidden lines are wrapped in spans which start and end at
+            // the start of a line
+            loop {
+                let tag = ["</span>", "<span class=\"boring\">"]
+                    .iter()
+                    .find(|tag| line.starts_with(**tag));
+                match tag {
+                    Some(tag) => {
+                        highlighted.push_str(tag);
+                        line = &line[tag.len()..];
+                    }
+                    None => break,
+                }
+            }
+
+            let line = unescape_html(line);
+            let reopened = scopes.as_slice().len() as isize;
+            for scope in scopes.as_slice() {
+                let classes: Vec<_> = scope
+                    .build_string()
+                    .split('.')
+                    .map(|atom| format!("syn-{}", atom))
+                    .collect();
+                highlighted.push_str(&format!("<span class=\"{}\">", classes.join(" ")));
+            }
+            let ops = state.parse_line(&line, &self.syntaxes);
+            let (html, delta) = line_tokens_to_classed_spans(&line, &ops, CLASS_STYLE, &mut scopes);
+            highlighted.push_str(&html);
+            for _ in 0..reopened + delta {
+                highlighted.push_str("</span>");
+            }
+        }
+
+        Some(highlighted)
+    }
+
+    /// The syntax of a code block's language, logging the languages which
+    /// have none (once each) so typos are easy to spot.
+    fn find_syntax(&self, language: &str) -> Option<&SyntaxReference> {
+        let syntax = match self.aliases.get(language) {
+            Some(name) => self.syntaxes.find_syntax_by_name(name),
+            None => self.syntaxes.find_syntax_by_token(language),
+        };
+
+        if syntax.is_none() && self.unknown.lock().unwrap().insert(language.to_string()) {
+            info!(
+                "The \"{}\" code blocks aren't highlighted, there's no syntax for that language",
+                language
+            );
+        }
+        syntax
+    }
+}
+
+fn unescape_html(html: &str) -> String {
+    html.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils;
+    use std::path::PathBuf;
+    use tempfile::Builder as TempFileBuilder;
+
+    fn highlighter() -> Highlighter {
+        Highlighter::new(&Syntect::default(), Path::new("")).unwrap()
+    }
+
+    #[test]
+    fn known_languages_are_highlighted() {
+        let html =
+            "<pre><code class=\"language-rust\">let title = &quot;Example&quot;;\n</code></pre>";
+
+        let highlighted = highlighter().highlight_code_blocks(html);
+
+        assert!(highlighted.starts_with(
+            "<pre><code class=\"language-rust syn-code\"><span class=\"syn-source syn-rust\">"
+        ));
+        assert!(highlighted.contains("<span class=\"syn-storage syn-type syn-rust\">let</span>"));
+        assert!(!highlighted.contains("style="));
+        assert!(highlighted.contains("&quot;</span>Example<span"));
+        assert!(highlighted.ends_with("</code></pre>"));
+    }
+
+    #[test]
+    fn unknown_languages_and_inline_code_are_left_alone() {
+        let html = "<pre><code class=\"language-klingon\">Qapla&amp;</code></pre>\
+                    <p><code>inline</code></p>";
+
+        assert_eq!(highlighter().highlight_code_blocks(html), html);
+    }
+
+    #[test]
+    fn hidden_rust_lines_are_kept() {
+        let html = "<pre class=\"playpen\"><code class=\"language-rust edition2018\">\
+                    <span class=\"boring\">fn main() {\n</span>let x = 1;\n\
+                    <span class=\"boring\">}\n</span></code></pre>";
+
+        let highlighted = highlighter().highlight_code_blocks(html);
+
+        assert!(highlighted.starts_with(
+            "<pre class=\"playpen\"><code class=\"language-rust edition2018 syn-code\">\
+             <span class=\"boring\"><span class=\"syn-source syn-rust\">"
+        ));
+        assert_eq!(highlighted.matches("<span class=\"boring\">").count(), 2);
+        // The spans of the first line are closed before its hidden line ends
+        assert!(highlighted.contains("{</span>\n</span></span></span></span><span class=\"syn-"));
+        assert_eq!(
+            highlighted.matches("<span").count(),
+            highlighted.matches("</span>").count()
+        );
+    }
+
+    const MY_LANG: &str = "%YAML 1.2
+---
+name: MyLang
+file_extensions: [mylang]
+scope: source.mylang
+contexts:
+  main:
+    - match: '\\bshout\\b'
+      scope: keyword.control.mylang
+";
+
+    #[test]
+    fn book_syntaxes_and_aliases() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        utils::fs::write_file(
+            temp.path(),
+            "syntaxes/MyLang.sublime-syntax",
+            MY_LANG.as_bytes(),
+        )
+        .unwrap();
+        let mut config = Syntect {
+            syntaxes: vec![PathBuf::from("syntaxes/MyLang.sublime-syntax")],
+            ..Default::default()
+        };
+        config
+            .aliases
+            .insert(String::from("ml"), String::from("MyLang.sublime-syntax"));
+        config
+            .aliases
+            .insert(String::from("rs2018"), String::from("Rust"));
+
+        let highlighter = Highlighter::new(&config, temp.path()).unwrap();
+
+        for &(language, class) in &[
+            ("mylang", "syn-source syn-mylang"),
+            ("ml", "syn-source syn-mylang"),
+            ("rs2018", "syn-source syn-rust"),
+        ] {
+            let html = format!(
+                "<pre><code class=\"language-{}\">shout</code></pre>",
+                language
+            );
+            assert!(
+                highlighter
+                    .highlight_code_blocks(&html)
+                    .contains(&format!("<span class=\"{}\">", class)),
+                "{}",
+                language
+            );
+        }
+
+        let html = "<pre><code class=\"language-ruts\">fn</code></pre>";
+        assert_eq!(highlighter.highlight_code_blocks(html), html);
+        assert_eq!(highlighter.highlight_code_blocks(html), html);
+        assert_eq!(highlighter.unknown.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn invalid_syntaxes_and_aliases_are_errors() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let broken = MY_LANG.replace("  main:", "  main: [");
+        utils::fs::write_file(temp.path(), "Broken.sublime-syntax", broken.as_bytes()).unwrap();
+        let config = Syntect {
+            syntaxes: vec![PathBuf::from("Broken.sublime-syntax")],
+            ..Default::default()
+        };
+
+        let err = Highlighter::new(&config, temp.path()).err().unwrap();
+        let message = format!("{:?}", err);
+        assert!(message.contains("Broken.sublime-syntax"), "{}", message);
+        assert!(message.contains("line 8"), "{}", message);
+
+        let mut config = Syntect::default();
+        config
+            .aliases
+            .insert(String::from("ml"), String::from("MyLang.sublime-syntax"));
+        let err = Highlighter::new(&config, temp.path()).err().unwrap();
+        assert!(err.to_string().starts_with(
+            "The `output.html.syntect.aliases` of \"ml\" is \"MyLang.sublime-syntax\""
+        ));
+    }
+
+    #[test]
+    fn unknown_themes_are_an_error() {
+        let config = Syntect {
+            theme: String::from("Neon"),
+            ..Default::default()
+        };
+
+        let err = Highlighter::new(&config, Path::new(""))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.starts_with("Unknown `output.html.syntect.theme` \"Neon\""));
+        assert!(err.contains("InspiredGitHub"));
+
+        let config = Syntect {
+            dark_theme: String::from("Neon"),
+            ..Default::default()
+        };
+        let err = Highlighter::new(&config, Path::new(""))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.starts_with("Unknown `output.html.syntect.dark-theme` \"Neon\""));
+    }
+
+    #[test]
+    fn the_stylesheets_color_the_classes() {
+        let stylesheets = highlighter().stylesheets();
+
+        let names: Vec<_> = stylesheets.iter().map(|&(name, _)| name).collect();
+        assert_eq!(
+            names,
+            ["highlight.css", "tomorrow-night.css", "ayu-highlight.css"]
+        );
+        let (_, ref light) = stylesheets[0];
+        let (_, ref dark) = stylesheets[1];
+        assert!(light.contains(".syn-code {"), "{}", light);
+        assert!(light.contains(".syn-keyword"), "{}", light);
+        assert_ne!(light, dark);
+    }
+}
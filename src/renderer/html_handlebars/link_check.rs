@@ -0,0 +1,928 @@
+//! Checking that the links of the rendered book point to pages and headings
+//! which actually exist: the links between its chapters, and, with
+//! `output.html.link-check`, the links of every HTML file it's made of.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ffi::OsStr;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+use rayon::prelude::*;
+use regex::Regex;
+
+use super::hbs_renderer::{build_header_links, chapter_link_style, landing_chapter, site_url_path};
+use super::redirect;
+use super::single_file::ChapterParts;
+use crate::book::{Book, BookItem};
+use crate::config::LinkCheck;
+use crate::errors::*;
+use crate::utils;
+use crate::MDBOOK_VERSION;
+
+/// A link or image in a chapter which doesn't point to anything in the
+/// rendered book.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct BrokenLink {
+    /// The name of the chapter containing the link.
+    pub(super) chapter: String,
+    /// The chapter's source file.
+    pub(super) source: PathBuf,
+    /// The link, as it appears in the rendered chapter.
+    pub(super) link: String,
+    /// What's wrong with it.
+    pub(super) reason: &'static str,
+}
+
+impl Display for BrokenLink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\"{}\" ({}): {} ({})",
+            self.chapter,
+            self.source.display(),
+            self.link,
+            self.reason
+        )
+    }
+}
+
+/// Check the relative links and images of every chapter against the files in
+/// `destination` and the headings of the chapters. Links with a scheme (like
+/// `https://`) aren't checked, and neither are links to the old paths of
+/// `redirects`.
+pub(super) fn check_links(
+    book: &Book,
+    destination: &Path,
+    curly_quotes: bool,
+    pretty_urls: bool,
+    redirects: &BTreeMap<String, String>,
+) -> Vec<BrokenLink> {
+    let pages: Vec<_> = book
+        .iter()
+        .filter_map(|item| match *item {
+            BookItem::Chapter(ref ch) => ch.path.as_ref().map(|path| {
+                let links = chapter_link_style(path, pretty_urls);
+                let options = utils::MarkdownOptions {
+                    curly_quotes,
+                    ..Default::default()
+                };
+                let html = utils::render_markdown_with_links(&ch.content, None, links, &options);
+                let page = utils::fs::chapter_page(path, pretty_urls);
+                (ch, page, build_header_links(&html))
+            }),
+            _ => None,
+        })
+        .collect();
+
+    let redirected: BTreeSet<PathBuf> = redirects
+        .keys()
+        .filter_map(|from| redirect::source_page(from).ok())
+        .map(PathBuf::from)
+        .collect();
+
+    let ids: HashMap<&Path, BTreeSet<&str>> = pages
+        .iter()
+        .map(|(_, page, html)| (page.as_path(), ids_in(html)))
+        .collect();
+
+    let mut broken = Vec::new();
+    for &(ch, ref page, ref html) in &pages {
+        for (_, link) in links_in(html) {
+            if let Some(reason) = check_link(&link, page, destination, &ids, &redirected) {
+                broken.push(BrokenLink {
+                    chapter: ch.name.clone(),
+                    source: ch.path.clone().unwrap_or_default(),
+                    link,
+                    reason,
+                });
+            }
+        }
+    }
+
+    broken
+}
+
+/// A link of an HTML file of the rendered book which doesn't point to
+/// anything.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct BrokenReference {
+    /// The HTML file containing the link, relative to the output directory.
+    pub(super) file: PathBuf,
+    /// The link, as it appears in the file.
+    pub(super) link: String,
+    /// What's wrong with it.
+    pub(super) reason: String,
+}
+
+/// The links of the HTML files of the rendered book.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(super) struct OutputLinks {
+    /// The links to the files of the book which are broken.
+    pub(super) broken: Vec<BrokenReference>,
+    /// The `http` and `https` links, which aren't checked yet, and the files
+    /// they're in.
+    pub(super) external: BTreeMap<String, BTreeSet<PathBuf>>,
+}
+
+/// Check the links of every HTML file in `destination` against the files next
+/// to them and the IDs of their elements, as they were written. The links to
+/// the `site_url` of the book, and to the absolute path the book is served
+/// from, are links to the files of the book, and so are the links to the old
+/// paths of `redirects`. The broken links to the `targets` already reported
+/// are left out.
+pub(super) fn check_output(
+    destination: &Path,
+    site_url: Option<&str>,
+    redirects: &BTreeMap<String, String>,
+    targets: &mut Targets<'_>,
+) -> Result<OutputLinks> {
+    let mut files = Vec::new();
+    html_files(destination, Path::new(""), &mut files)?;
+    // The links of the chapters are reported with their own pages
+    files.sort_by_key(|file| (targets.repeats_chapters(file), file.clone()));
+    let pages = files
+        .into_iter()
+        .map(|file| {
+            let html = fs::read(destination.join(&file))
+                .with_context(|| format!("Unable to read {}", file.display()))?;
+            Ok((file, String::from_utf8_lossy(&html).into_owned()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let redirected: BTreeSet<PathBuf> = redirects
+        .keys()
+        .filter_map(|from| redirect::source_page(from).ok())
+        .map(PathBuf::from)
+        .collect();
+    let ids: HashMap<&Path, BTreeSet<&str>> = pages
+        .iter()
+        .map(|(file, html)| (file.as_path(), ids_in(html)))
+        .collect();
+    let site_path = site_url_path(site_url);
+    let site_url = site_url.map(|url| format!("{}/", url.trim_end_matches('/')));
+
+    let mut links = OutputLinks::default();
+    for (file, html) in &pages {
+        let base = base_in(html);
+        let chapters = targets.chapters_in(file, html);
+        for (offset, link) in links_in(html) {
+            let internal = match site_url {
+                Some(ref site_url) if link.starts_with(site_url.as_str()) => {
+                    format!("{}{}", site_path, &link[site_url.len()..])
+                }
+                _ if is_external(&link) => {
+                    links.external.entry(link).or_default().insert(file.clone());
+                    continue;
+                }
+                _ => match base {
+                    Some(base) if !link.starts_with('/') && !link.starts_with('#') => {
+                        format!("{}{}", base, link)
+                    }
+                    _ => link.clone(),
+                },
+            };
+
+            let (internal, reason) = if !internal.starts_with('/') {
+                let reason = check_link(&internal, file, destination, &ids, &redirected);
+                (internal, reason)
+            } else if internal.starts_with(&site_path) {
+                let from_root = format!("/{}", &internal[site_path.len()..]);
+                let reason = check_link(&from_root, file, destination, &ids, &redirected);
+                (from_root, reason)
+            } else {
+                (internal, Some("points outside of the book"))
+            };
+            let reason = match reason {
+                Some(reason) => reason,
+                None => continue,
+            };
+            // The chapter of the page the link is from
+            let source = chapters
+                .iter()
+                .rev()
+                .find(|&&(start, _)| start <= offset)
+                .map_or(file.as_path(), |(_, page)| page.as_path());
+            if targets.is_new(&internal, file, source) {
+                links.broken.push(BrokenReference {
+                    file: file.clone(),
+                    link,
+                    reason: reason.to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(links)
+}
+
+/// What the broken links point to, so the links of the pages which repeat the
+/// chapters (the print page, `book.html` and `index.html`) are only reported
+/// once, with the chapter they're from.
+pub(super) struct Targets<'a> {
+    parts: ChapterParts,
+    pretty_urls: bool,
+    landing: Option<&'a Path>,
+    /// The chapters' pages the broken links are from, and the pages and IDs
+    /// they point to.
+    reported: BTreeSet<(PathBuf, PathBuf, String)>,
+}
+
+impl<'a> Targets<'a> {
+    pub(super) fn new(book: &'a Book, pretty_urls: bool) -> Targets<'a> {
+        Targets {
+            parts: ChapterParts::new(book),
+            pretty_urls,
+            landing: landing_chapter(book, pretty_urls).map(PathBuf::as_path),
+            reported: BTreeSet::new(),
+        }
+    }
+
+    /// Whether `file` is one of the pages repeating the chapters.
+    fn repeats_chapters(&self, file: &Path) -> bool {
+        file == Path::new("print.html")
+            || file == Path::new("book.html")
+            || (file == Path::new("index.html") && self.landing_page() != Some(file.to_path_buf()))
+    }
+
+    fn landing_page(&self) -> Option<PathBuf> {
+        self.landing
+            .map(|landing| utils::fs::chapter_page(landing, self.pretty_urls))
+    }
+
+    /// Whether the broken `link` on `page`, from the chapter at the page
+    /// `source`, wasn't reported yet, remembering it.
+    pub(super) fn is_new(&mut self, link: &str, page: &Path, source: &Path) -> bool {
+        let (target, id) = self.target(link, page);
+        self.reported.insert((source.to_path_buf(), target, id))
+    }
+
+    /// The pages of the chapters `html` repeats, by the offsets where its
+    /// parts start, when `file` is a page repeating the chapters.
+    fn chapters_in(&self, file: &Path, html: &str) -> Vec<(usize, PathBuf)> {
+        lazy_static! {
+            static ref PART: Regex = Regex::new(r#"<div id="([^"]+)">"#).unwrap();
+        }
+
+        if !self.repeats_chapters(file) {
+            return Vec::new();
+        }
+        if file == Path::new("index.html") {
+            return self
+                .landing_page()
+                .map(|page| (0, page))
+                .into_iter()
+                .collect();
+        }
+        PART.captures_iter(html)
+            .filter_map(|caps| match self.parts.source_of(&caps[1]) {
+                Some((chapter, "")) => Some((caps.get(0).unwrap().start(), self.page_of(chapter))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The page of a chapter, from its page on the print page.
+    fn page_of(&self, chapter: &str) -> PathBuf {
+        let source = Path::new(chapter).with_extension("md");
+        utils::fs::chapter_page(&source, self.pretty_urls)
+    }
+
+    /// The page and ID `link` on `page` points to, with the pages which
+    /// repeat the chapters replaced by the chapters' pages.
+    fn target(&self, link: &str, page: &Path) -> (PathBuf, String) {
+        let (path, fragment) = match link.find('#') {
+            Some(ix) => (&link[..ix], percent_decode(&link[ix + 1..])),
+            None => (link, String::new()),
+        };
+        let path = path.split('?').next().unwrap_or_default();
+        let base = if path.starts_with('/') {
+            Path::new("")
+        } else {
+            page.parent().unwrap_or_else(|| Path::new(""))
+        };
+        let mut target = match path {
+            "" => page.to_path_buf(),
+            // Outside of the book, the link is all there is
+            _ => match resolve(base, &percent_decode(path.trim_start_matches('/'))) {
+                Some(target) => target,
+                None => return (PathBuf::new(), link.to_string()),
+            },
+        };
+        if path.ends_with('/') {
+            target.push("index.html");
+        }
+
+        if !self.repeats_chapters(&target) {
+            return (target, fragment);
+        }
+        if target == Path::new("index.html") {
+            return (self.landing_page().unwrap_or(target), fragment);
+        }
+        match self.parts.source_of(&fragment) {
+            Some((chapter, id)) => (self.page_of(chapter), id.to_string()),
+            None => (target, fragment),
+        }
+    }
+}
+
+/// The HTML files in the `dir` of `destination`, relative to `destination`.
+fn html_files(destination: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(destination.join(dir))
+        .with_context(|| format!("Unable to read the directory {}", dir.display()))?
+        .map(|entry| entry.map(|entry| dir.join(entry.file_name())))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for path in entries {
+        if destination.join(&path).is_dir() {
+            html_files(destination, &path, files)?;
+        } else if path.extension() == Some(OsStr::new("html")) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_external(link: &str) -> bool {
+    link.starts_with("http://") || link.starts_with("https://") || link.starts_with("//")
+}
+
+/// List the broken links by the file they're in, for the error of the build.
+pub(super) fn report(broken: &[BrokenReference]) -> String {
+    let mut by_file: BTreeMap<&Path, Vec<&BrokenReference>> = BTreeMap::new();
+    for link in broken {
+        by_file.entry(&link.file).or_default().push(link);
+    }
+
+    let mut report = String::new();
+    for (file, links) in by_file {
+        report.push_str(&format!("    {}:\n", file.display()));
+        for link in links {
+            report.push_str(&format!("        {} ({})\n", link.link, link.reason));
+        }
+    }
+    report
+}
+
+/// Check the external `links` with `curl`, `concurrency` of them at a time,
+/// except the ignored ones. They have to answer with a status which isn't an
+/// error, once their redirections are followed.
+pub(super) fn check_external(
+    links: &BTreeMap<String, BTreeSet<PathBuf>>,
+    config: &LinkCheck,
+) -> Result<Vec<BrokenReference>> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.concurrency.max(1))
+        .build()
+        .with_context(|| "Unable to start the threads checking the external links")?;
+    let timeout = config.timeout.max(1);
+
+    let urls: Vec<&String> = links
+        .keys()
+        .filter(|url| !config.ignore.iter().any(|prefix| url.starts_with(prefix)))
+        .collect();
+    let checked = pool.install(|| {
+        urls.par_iter()
+            .map(|&url| Ok((url, check_url(url, timeout)?)))
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    Ok(checked
+        .into_iter()
+        .filter_map(|(url, checked)| checked.err().map(|reason| (url, reason)))
+        .flat_map(|(url, reason)| {
+            links[url].iter().map(move |file| BrokenReference {
+                file: file.clone(),
+                link: url.clone(),
+                reason: reason.clone(),
+            })
+        })
+        .collect())
+}
+
+/// The arguments of `curl` for every link: no progress bar but the errors,
+/// following the redirections, and writing out the status.
+const CURL_ARGS: &[&str] = &[
+    "--silent",
+    "--show-error",
+    "--location",
+    "--max-redirs",
+    "10",
+    "--write-out",
+    "%{http_code}",
+];
+
+/// What's wrong with the external `link`, if anything. Servers which don't
+/// answer `HEAD` requests are asked for the page itself.
+fn check_url(link: &str, timeout: u64) -> Result<std::result::Result<(), String>> {
+    let url = if link.starts_with("//") {
+        format!("https:{}", link)
+    } else {
+        link.to_string()
+    };
+
+    let mut status = None;
+    for &head in &[true, false] {
+        let mut cmd = Command::new("curl");
+        for arg in CURL_ARGS {
+            cmd.arg(arg);
+        }
+        cmd.arg("--max-time")
+            .arg(timeout.to_string())
+            .arg("--user-agent")
+            .arg(format!("mdbook/{}", MDBOOK_VERSION))
+            .arg("--output")
+            .arg(if cfg!(windows) { "NUL" } else { "/dev/null" });
+        if head {
+            cmd.arg("--head");
+        }
+        let output = cmd.arg(&url).output().with_context(|| {
+            "Unable to start `curl`, which checks the external links. Is it installed?"
+        })?;
+
+        if !output.status.success() {
+            // Like `curl: (6) Could not resolve host: example.invalid`
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr.trim().trim_start_matches("curl: ");
+            return Ok(Err(reason.to_string()));
+        }
+        let code = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        match code.parse::<u16>() {
+            Ok(status) if status < 400 => return Ok(Ok(())),
+            _ => {}
+        }
+        status = Some(code);
+    }
+
+    Ok(Err(format!("HTTP {}", status.unwrap_or_default())))
+}
+
+/// Why the `link` on `page` is broken, if it is.
+fn check_link(
+    link: &str,
+    page: &Path,
+    destination: &Path,
+    ids: &HashMap<&Path, BTreeSet<&str>>,
+    redirected: &BTreeSet<PathBuf>,
+) -> Option<&'static str> {
+    lazy_static! {
+        static ref SCHEME_LINK: Regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*:").unwrap();
+    }
+
+    if SCHEME_LINK.is_match(link) || link.starts_with("//") {
+        return None;
+    }
+
+    let (path, fragment) = match link.find('#') {
+        Some(ix) => (&link[..ix], Some(&link[ix + 1..])),
+        None => (link, None),
+    };
+    let path = path.split('?').next().unwrap_or_default();
+
+    let mut target = if path.is_empty() {
+        page.to_path_buf()
+    } else {
+        let base = if path.starts_with('/') {
+            Path::new("")
+        } else {
+            page.parent().unwrap_or_else(|| Path::new(""))
+        };
+        match resolve(base, &percent_decode(path.trim_start_matches('/'))) {
+            Some(target) => target,
+            None => return Some("points outside of the book"),
+        }
+    };
+    if path.ends_with('/') || destination.join(&target).is_dir() {
+        target.push("index.html");
+    }
+    if redirected.contains(&target) {
+        return None;
+    }
+
+    if !destination.join(&target).is_file() {
+        return Some("no such file");
+    }
+
+    match (fragment, ids.get(target.as_path())) {
+        (Some(fragment), Some(ids)) if !fragment.is_empty() => {
+            if ids.contains(percent_decode(fragment).as_str()) {
+                None
+            } else {
+                Some("no such heading")
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Join `link` onto `base`, without leaving the root of the book.
+pub(super) fn resolve(base: &Path, link: &str) -> Option<PathBuf> {
+    let mut resolved = PathBuf::new();
+
+    for component in base.join(link).components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(resolved)
+}
+
+/// The links of `html`, with their offsets.
+fn links_in(html: &str) -> Vec<(usize, String)> {
+    lazy_static! {
+        static ref LINK: Regex = Regex::new(r#"\b(?:href|src)="([^"]+)""#).unwrap();
+    }
+
+    LINK.captures_iter(html)
+        .map(|caps| (caps.get(0).unwrap().start(), caps[1].replace("&amp;", "&")))
+        .collect()
+}
+
+/// The `href` of the `<base>` of a page, when it's an absolute path, like the
+/// one of the 404 page.
+fn base_in(html: &str) -> Option<&str> {
+    lazy_static! {
+        static ref BASE: Regex = Regex::new(r#"<base\b[^>]*\bhref="(/[^"]*)""#).unwrap();
+    }
+
+    BASE.captures(html)
+        .and_then(|caps| caps.get(1))
+        .map(|base| base.as_str())
+}
+
+fn ids_in(html: &str) -> BTreeSet<&str> {
+    lazy_static! {
+        static ref ID: Regex = Regex::new(r#"\bid="([^"]+)""#).unwrap();
+    }
+
+    ID.captures_iter(html)
+        .filter_map(|caps| caps.get(1))
+        .map(|id| id.as_str())
+        .collect()
+}
+
+/// Decode the `%xx` escapes of a link, leaving invalid ones alone.
+fn percent_decode(link: &str) -> String {
+    let mut decoded = Vec::with_capacity(link.len());
+    let mut bytes = link.bytes().enumerate();
+
+    while let Some((ix, byte)) = bytes.next() {
+        let escaped = link
+            .get(ix + 1..ix + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(escaped) if byte == b'%' => {
+                decoded.push(escaped);
+                bytes.next();
+                bytes.next();
+            }
+            _ => decoded.push(byte),
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::Chapter;
+    use tempfile::Builder as TempFileBuilder;
+
+    fn check(chapters: &[(&str, &str)], other_files: &[&str]) -> Vec<String> {
+        check_with_redirects(chapters, other_files, &BTreeMap::new())
+    }
+
+    fn check_with_redirects(
+        chapters: &[(&str, &str)],
+        other_files: &[&str],
+        redirects: &BTreeMap<String, String>,
+    ) -> Vec<String> {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let mut book = Book::new();
+        for &(path, content) in chapters {
+            let page = Path::new(path).with_extension("html");
+            utils::fs::write_file(temp.path(), &page, b"").unwrap();
+            book.push_item(Chapter::new(path, content.to_string(), path, Vec::new()));
+        }
+        for file in other_files {
+            utils::fs::write_file(temp.path(), file, b"").unwrap();
+        }
+
+        check_links(&book, temp.path(), false, false, redirects)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn links_to_existing_pages_and_headings_are_fine() {
+        let chapters = [
+            (
+                "intro.md",
+                "# Intro\n\n## Getting Started\n\n[start](#getting-started) \
+                 [nested](guide/nested.md#some-heading) [dir](guide/) \
+                 [query](guide/nested.html?q=1) ![logo](images/logo%20big.png) \
+                 <a href=\"intro.html#intro\">html</a>",
+            ),
+            (
+                "guide/nested.md",
+                "# Some Heading\n\n[back](../intro.md) [root](/intro.html#getting-started) \
+                 [external](https://example.com/missing.html) [mail](mailto:a@b.c)",
+            ),
+            ("guide/index.md", "# Guide"),
+        ];
+
+        let broken = check(&chapters, &["images/logo big.png"]);
+
+        assert_eq!(broken, Vec::<String>::new());
+    }
+
+    #[test]
+    fn missing_headings_are_reported() {
+        let chapters = [
+            ("intro.md", "# Intro\n\n[here](#nowhere)"),
+            (
+                "other.md",
+                "# Other\n\n[there](intro.md#missing) [fine](intro.md#intro)",
+            ),
+        ];
+
+        let broken = check(&chapters, &[]);
+
+        assert_eq!(
+            broken,
+            vec![
+                "\"intro.md\" (intro.md): #nowhere (no such heading)",
+                "\"other.md\" (other.md): intro.html#missing (no such heading)",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_files_are_reported() {
+        let chapters = [(
+            "guide/chapter.md",
+            "# Chapter\n\n[gone](../missing.md) ![image](image.png) [up](../../../outside.html)",
+        )];
+
+        let broken = check(&chapters, &[]);
+
+        assert_eq!(
+            broken,
+            vec![
+                "\"guide/chapter.md\" (guide/chapter.md): ../missing.html (no such file)",
+                "\"guide/chapter.md\" (guide/chapter.md): image.png (no such file)",
+                "\"guide/chapter.md\" (guide/chapter.md): ../../../outside.html (points outside of the book)",
+            ]
+        );
+    }
+
+    #[test]
+    fn links_to_redirected_pages_are_fine() {
+        let chapters = [(
+            "intro.md",
+            "# Intro\n\n[old](old/page.html#heading) [moved](moved/) [dir](dir/) [gone](gone.html)",
+        )];
+        let redirects = vec![
+            ("/old/page.html", "../intro.html"),
+            ("moved/index.html", "https://example.com/"),
+            ("/dir/", "intro.html"),
+        ]
+        .into_iter()
+        .map(|(from, to)| (from.to_string(), to.to_string()))
+        .collect();
+
+        let broken = check_with_redirects(&chapters, &[], &redirects);
+
+        assert_eq!(
+            broken,
+            vec!["\"intro.md\" (intro.md): gone.html (no such file)"]
+        );
+    }
+
+    fn check_files(files: &[(&str, &str)], site_url: Option<&str>) -> Vec<String> {
+        check_files_of(&Book::new(), files, site_url)
+    }
+
+    fn check_files_of(book: &Book, files: &[(&str, &str)], site_url: Option<&str>) -> Vec<String> {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        for &(path, content) in files {
+            utils::fs::write_file(temp.path(), path, content.as_bytes()).unwrap();
+        }
+        let redirects = vec![(String::from("/old.html"), String::from("intro.html"))]
+            .into_iter()
+            .collect();
+
+        let mut targets = Targets::new(book, false);
+        let links = check_output(temp.path(), site_url, &redirects, &mut targets).unwrap();
+        let mut checked: Vec<_> = links
+            .broken
+            .iter()
+            .map(|link| format!("{}: {} ({})", link.file.display(), link.link, link.reason))
+            .collect();
+        for (url, files) in links.external {
+            checked.push(format!("{} in {:?}", url, files));
+        }
+        checked
+    }
+
+    #[test]
+    fn every_html_file_of_the_output_is_checked() {
+        let files = [
+            (
+                "intro.html",
+                r##"<h1 id="intro">Intro</h1> <a href="#intro">self</a> <a href="#gone">gone</a>
+<img src="img/logo.png"> <a href="guide/">guide</a> <a href="old.html#x">redirected</a>
+<a href="https://example.com/a">external</a> <a href="mailto:a@b.c">mail</a>"##,
+            ),
+            (
+                "guide/index.html",
+                r##"<a href="../intro.html#intro">back</a> <a href="../print.html#missing">print</a>
+<a href="//example.com/a">protocol-relative</a> <a href="../../up.html">up</a>"##,
+            ),
+            ("print.html", r#"<div id="guide"></div>"#),
+            ("img/logo.png", ""),
+            ("static/raw.html", r#"<a href="../nope.html">nope</a>"#),
+        ];
+
+        assert_eq!(
+            check_files(&files, None),
+            vec![
+                "guide/index.html: ../print.html#missing (no such heading)",
+                "guide/index.html: ../../up.html (points outside of the book)",
+                "intro.html: #gone (no such heading)",
+                "static/raw.html: ../nope.html (no such file)",
+                "//example.com/a in {\"guide/index.html\"}",
+                "https://example.com/a in {\"intro.html\"}",
+            ]
+        );
+    }
+
+    #[test]
+    fn links_to_the_site_of_the_book_are_internal() {
+        let files = [
+            (
+                "intro.html",
+                r#"<link rel="canonical" href="https://example.com/book/intro.html">
+<a href="/book/guide.html#guide">absolute</a> <a href="/elsewhere.html">elsewhere</a>
+<a href="https://example.com/book/missing.html">missing</a>"#,
+            ),
+            ("guide.html", r#"<h1 id="guide">Guide</h1>"#),
+            ("index.html", ""),
+            (
+                "404.html",
+                r#"<base href="/book/"><a href="guide.html">guide</a> <a href="gone.html">gone</a>"#,
+            ),
+        ];
+
+        assert_eq!(
+            check_files(&files, Some("https://example.com/book")),
+            vec![
+                "404.html: gone.html (no such file)",
+                "intro.html: /elsewhere.html (points outside of the book)",
+                "intro.html: https://example.com/book/missing.html (no such file)",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_links_the_chapters_repeat_are_reported_once() {
+        let mut book = Book::new();
+        for path in &["intro.md", "guide.md"] {
+            book.push_item(Chapter::new(path, String::new(), path, Vec::new()));
+        }
+        let files = [
+            (
+                "intro.html",
+                r##"<a href="gone.html">gone</a> <a href="#nope">nope</a>"##,
+            ),
+            (
+                "index.html",
+                r##"<a href="gone.html">gone</a> <a href="#nope">nope</a>"##,
+            ),
+            ("guide.html", r##"<a href="intro.html#nope">nope</a>"##),
+            (
+                "print.html",
+                r##"<div id="intro-md"><a href="gone.html">gone</a> <a href="#intro-md-nope">nope</a></div>
+<div id="guide-md"><a href="#intro-md-nope">nope</a> <a href="#guide-md-missing">missing</a></div>"##,
+            ),
+        ];
+
+        assert_eq!(
+            check_files_of(&book, &files, None),
+            vec![
+                "guide.html: intro.html#nope (no such heading)",
+                "intro.html: gone.html (no such file)",
+                "intro.html: #nope (no such heading)",
+                "print.html: #guide-md-missing (no such heading)",
+            ]
+        );
+    }
+
+    #[test]
+    fn broken_links_are_grouped_by_file() {
+        let broken: Vec<_> = vec![("b.html", "x.html"), ("a.html", "#y"), ("b.html", "#z")]
+            .into_iter()
+            .map(|(file, link)| BrokenReference {
+                file: PathBuf::from(file),
+                link: link.to_string(),
+                reason: String::from("no such file"),
+            })
+            .collect();
+
+        assert_eq!(
+            report(&broken),
+            "    a.html:\n        #y (no such file)\n    \
+             b.html:\n        x.html (no such file)\n        #z (no such file)\n"
+        );
+    }
+
+    /// A server with a page at `/`, which `/moved` redirects to, and a page
+    /// at `/get-only` which doesn't answer `HEAD` requests.
+    fn serve() -> String {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+                let status = match request.split(' ').take(2).collect::<Vec<_>>()[..] {
+                    [_, "/"] | ["GET", "/get-only"] => "200 OK",
+                    [_, "/moved"] => "301 Moved Permanently\r\nLocation: /",
+                    ["HEAD", "/get-only"] => "405 Method Not Allowed",
+                    _ => "404 Not Found",
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    #[test]
+    fn external_links_are_checked_except_the_ignored_ones() {
+        // Fails without curl, like the builds checking the external links
+        let server = serve();
+        let paths = vec!["/", "/moved", "/get-only", "/missing", "/ignored/missing"];
+        let mut links: BTreeMap<_, _> = paths
+            .into_iter()
+            .map(|path| {
+                let files = vec![PathBuf::from("intro.html")].into_iter().collect();
+                (format!("{}{}", server, path), files)
+            })
+            .collect();
+        let files = vec![PathBuf::from("other.html")].into_iter().collect();
+        links.insert(String::from("http://127.0.0.1:9/closed"), files);
+        let config = LinkCheck {
+            ignore: vec![format!("{}/ignored/", server)],
+            concurrency: 2,
+            ..LinkCheck::default()
+        };
+
+        let mut broken = check_external(&links, &config).unwrap();
+        broken.sort_by(|a, b| a.file.cmp(&b.file));
+
+        assert_eq!(broken.len(), 2, "{:?}", broken);
+        assert_eq!(
+            broken[0],
+            BrokenReference {
+                file: PathBuf::from("intro.html"),
+                link: format!("{}/missing", server),
+                reason: String::from("HTTP 404"),
+            }
+        );
+        assert_eq!(broken[1].link, "http://127.0.0.1:9/closed");
+        assert!(broken[1].reason.starts_with("(7) "), "{}", broken[1].reason);
+    }
+
+    #[test]
+    fn decode_percent_escapes() {
+        assert_eq!(percent_decode("a%20b%2Fc"), "a b/c");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+    }
+}
@@ -0,0 +1,171 @@
+//! The strings of the default theme's user interface, in the languages mdBook
+//! has translations for.
+
+/// The English strings, which are used for the ones a language doesn't have.
+const ENGLISH: &[(&str, &str)] = &[
+    ("table_of_contents", "Table of contents"),
+    ("toggle_sidebar", "Toggle Table of Contents"),
+    ("change_theme", "Change theme"),
+    ("themes", "Themes"),
+    ("toggle_search", "Toggle Searchbar"),
+    ("search_title", "Search. (Shortkey: s)"),
+    ("search_placeholder", "Search this book ..."),
+    ("print", "Print this book"),
+    ("git_repository", "Git repository"),
+    ("suggest_edit", "Suggest an edit"),
+    ("breadcrumbs", "Breadcrumbs"),
+    ("page_navigation", "Page navigation"),
+    ("previous", "Previous chapter"),
+    ("next", "Next chapter"),
+];
+
+/// The translations, by their language code.
+const TRANSLATIONS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "de",
+        &[
+            ("table_of_contents", "Inhaltsverzeichnis"),
+            ("toggle_sidebar", "Inhaltsverzeichnis ein-/ausblenden"),
+            ("change_theme", "Farbschema ändern"),
+            ("themes", "Farbschemata"),
+            ("toggle_search", "Suchleiste ein-/ausblenden"),
+            ("search_title", "Suchen. (Tastenkürzel: s)"),
+            ("search_placeholder", "Dieses Buch durchsuchen ..."),
+            ("print", "Dieses Buch drucken"),
+            ("git_repository", "Git-Repository"),
+            ("suggest_edit", "Änderung vorschlagen"),
+            ("breadcrumbs", "Brotkrümelnavigation"),
+            ("page_navigation", "Seitennavigation"),
+            ("previous", "Vorheriges Kapitel"),
+            ("next", "Nächstes Kapitel"),
+        ],
+    ),
+    (
+        "es",
+        &[
+            ("table_of_contents", "Tabla de contenidos"),
+            ("toggle_sidebar", "Mostrar/ocultar la tabla de contenidos"),
+            ("change_theme", "Cambiar tema"),
+            ("themes", "Temas"),
+            ("toggle_search", "Mostrar/ocultar la búsqueda"),
+            ("search_title", "Buscar. (Atajo: s)"),
+            ("search_placeholder", "Buscar en este libro ..."),
+            ("print", "Imprimir este libro"),
+            ("git_repository", "Repositorio Git"),
+            ("suggest_edit", "Sugerir un cambio"),
+            ("breadcrumbs", "Ruta de navegación"),
+            ("page_navigation", "Navegación de la página"),
+            ("previous", "Capítulo anterior"),
+            ("next", "Capítulo siguiente"),
+        ],
+    ),
+    (
+        "fr",
+        &[
+            ("table_of_contents", "Table des matières"),
+            ("toggle_sidebar", "Afficher/masquer la table des matières"),
+            ("change_theme", "Changer de thème"),
+            ("themes", "Thèmes"),
+            ("toggle_search", "Afficher/masquer la recherche"),
+            ("search_title", "Rechercher. (Raccourci : s)"),
+            ("search_placeholder", "Rechercher dans ce livre ..."),
+            ("print", "Imprimer ce livre"),
+            ("git_repository", "Dépôt Git"),
+            ("suggest_edit", "Suggérer une modification"),
+            ("breadcrumbs", "Fil d'Ariane"),
+            ("page_navigation", "Navigation de la page"),
+            ("previous", "Chapitre précédent"),
+            ("next", "Chapitre suivant"),
+        ],
+    ),
+    (
+        "ja",
+        &[
+            ("table_of_contents", "目次"),
+            ("toggle_sidebar", "目次の表示を切り替え"),
+            ("change_theme", "テーマを変更"),
+            ("themes", "テーマ"),
+            ("toggle_search", "検索バーの表示を切り替え"),
+            ("search_title", "検索 (ショートカット: s)"),
+            ("search_placeholder", "この本を検索 ..."),
+            ("print", "この本を印刷"),
+            ("git_repository", "Git リポジトリ"),
+            ("suggest_edit", "編集を提案"),
+            ("breadcrumbs", "パンくずリスト"),
+            ("page_navigation", "ページナビゲーション"),
+            ("previous", "前の章"),
+            ("next", "次の章"),
+        ],
+    ),
+    (
+        "zh",
+        &[
+            ("table_of_contents", "目录"),
+            ("toggle_sidebar", "切换目录"),
+            ("change_theme", "更换主题"),
+            ("themes", "主题"),
+            ("toggle_search", "切换搜索栏"),
+            ("search_title", "搜索（快捷键：s）"),
+            ("search_placeholder", "搜索本书 ..."),
+            ("print", "打印本书"),
+            ("git_repository", "Git 仓库"),
+            ("suggest_edit", "建议修改"),
+            ("breadcrumbs", "面包屑导航"),
+            ("page_navigation", "页面导航"),
+            ("previous", "上一章"),
+            ("next", "下一章"),
+        ],
+    ),
+];
+
+/// The strings of the user interface in `language`, like `de` or `pt-BR`.
+/// Tags with a region or script use the translation of their language, and
+/// the strings without a translation are in English.
+pub(super) fn strings(language: &str) -> serde_json::Map<String, serde_json::Value> {
+    let language = language.to_lowercase();
+    let primary = language.split('-').next().unwrap_or_default();
+    let translation = TRANSLATIONS
+        .iter()
+        .find(|&&(code, _)| code == language)
+        .or_else(|| TRANSLATIONS.iter().find(|&&(code, _)| code == primary))
+        .map_or(&[][..], |&(_, strings)| strings);
+
+    ENGLISH
+        .iter()
+        .map(|&(key, english)| {
+            let string = translation
+                .iter()
+                .find(|&&(name, _)| name == key)
+                .map_or(english, |&(_, string)| string);
+            (key.to_string(), json!(string))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_translation_has_known_keys() {
+        for &(code, strings) in TRANSLATIONS {
+            for &(key, _) in strings {
+                assert!(
+                    ENGLISH.iter().any(|&(name, _)| name == key),
+                    "{}: {}",
+                    code,
+                    key
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn languages_fall_back_to_their_primary_tag_and_english() {
+        assert_eq!(strings("de")["next"], json!("Nächstes Kapitel"));
+        assert_eq!(strings("de-AT")["next"], json!("Nächstes Kapitel"));
+        assert_eq!(strings("zh-Hans")["print"], json!("打印本书"));
+        assert_eq!(strings("tlh")["next"], json!("Next chapter"));
+        assert_eq!(strings("")["next"], json!("Next chapter"));
+    }
+}
@@ -1,9 +1,55 @@
 #![allow(missing_docs)] // FIXME: Document this
 
 pub use self::hbs_renderer::HtmlHandlebars;
+pub use self::post_processor::{
+    ChapterPostProcessor, CmdPostProcessor, LazyImages, PostProcessors,
+};
 
+mod archive;
+mod build_info;
+mod cache;
 mod hbs_renderer;
 mod helpers;
+mod link_check;
+mod locales;
+mod post_processor;
+mod redirect;
+mod single_file;
+mod sitemap;
 
 #[cfg(feature = "search")]
 mod search;
+
+#[cfg(feature = "syntect")]
+mod highlight;
+
+#[cfg(not(feature = "syntect"))]
+mod highlight {
+    //! Without the `syntect` feature there's nothing to highlight the code
+    //! blocks with, and asking for it is an error.
+
+    use std::path::Path;
+
+    use crate::config::Syntect;
+    use crate::errors::*;
+
+    pub(super) enum Highlighter {}
+
+    impl Highlighter {
+        pub(super) fn new(_config: &Syntect, _root: &Path) -> Result<Highlighter> {
+            bail!(
+                "`output.html.highlight = \"syntect\"` needs mdBook to be compiled with the \
+                 `syntect` feature, please reinstall with \
+                 `cargo install mdbook --force --features syntect`"
+            )
+        }
+
+        pub(super) fn stylesheets(&self) -> Vec<(&'static str, String)> {
+            match *self {}
+        }
+
+        pub(super) fn highlight_code_blocks(&self, _html: &str) -> String {
+            match *self {}
+        }
+    }
+}
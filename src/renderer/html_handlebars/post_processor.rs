@@ -0,0 +1,337 @@
+//! The transformations of the HTML of the chapters' pages, after their
+//! templates and mdBook's own post-processing, before they're written.
+
+use std::fmt::{self, Debug, Formatter};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+
+use regex::{Captures, Regex};
+use shlex::Shlex;
+
+use crate::book::Chapter;
+use crate::config::HtmlConfig;
+use crate::errors::*;
+
+/// A transformation of the HTML of every page, like adding attributes to its
+/// images or wrapping its tables, without forking the HTML renderer. They're
+/// registered with
+/// [`MDBook::with_post_processor()`](../book/struct.MDBook.html#method.with_post_processor),
+/// or `[[output.html.post-process]]` in `book.toml`.
+///
+/// The pages which aren't of a single chapter, the print page, `book.html`
+/// and the 404 page, get a chapter without content named after the page,
+/// whose path is `print.md`, `book.md` or `404.md`.
+///
+/// The pages are rendered in parallel, so post-processors have to be `Send`
+/// and `Sync`.
+pub trait ChapterPostProcessor: Send + Sync {
+    /// The name of the post-processor, for the errors.
+    fn name(&self) -> &str;
+
+    /// Transform the HTML of the page of `chapter`.
+    fn process(&self, chapter: &Chapter, html: String) -> Result<String>;
+}
+
+/// The post-processors of the pages, in the order they run.
+#[derive(Clone, Default)]
+pub struct PostProcessors(Vec<Arc<dyn ChapterPostProcessor>>);
+
+impl PostProcessors {
+    /// Add a post-processor, which runs after the ones already there.
+    pub fn push<P: ChapterPostProcessor + 'static>(&mut self, post_processor: P) {
+        self.0.push(Arc::new(post_processor));
+    }
+
+    /// Whether there are no post-processors.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The post-processors, in the order they run.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn ChapterPostProcessor> {
+        self.0.iter().map(|post_processor| &**post_processor)
+    }
+
+    /// The post-processors of `[[output.html.post-process]]`, in the order of
+    /// `book.toml`, followed by these.
+    pub(super) fn with_configured(&self, html_config: &HtmlConfig, root: PathBuf) -> Result<Self> {
+        let mut post_processors = Vec::new();
+        for post_process in &html_config.post_process {
+            let post_processor: Arc<dyn ChapterPostProcessor> =
+                match (post_process.name.as_str(), &post_process.command) {
+                    (name, Some(command)) => Arc::new(CmdPostProcessor::new(
+                        name.to_string(),
+                        command.clone(),
+                        root.clone(),
+                    )),
+                    ("lazy-images", None) => Arc::new(LazyImages),
+                    (name, None) => bail!(
+                        "output.html.post-process has no `command` for \"{}\", \
+                         which isn't a built-in post-processor",
+                        name
+                    ),
+                };
+            post_processors.push(post_processor);
+        }
+        post_processors.extend(self.0.iter().cloned());
+        Ok(PostProcessors(post_processors))
+    }
+
+    /// Run the post-processors on the page of `chapter`, one after the other.
+    pub(super) fn process(&self, chapter: &Chapter, html: String) -> Result<String> {
+        let mut html = html;
+        for post_processor in self.iter() {
+            html = post_processor.process(chapter, html).with_context(|| {
+                let path = chapter.source_path.as_ref().or(chapter.path.as_ref());
+                format!(
+                    "The \"{}\" post-processor failed on {}",
+                    post_processor.name(),
+                    path.map(|path| path.display().to_string())
+                        .unwrap_or_else(|| format!("\"{}\"", chapter.name))
+                )
+            })?;
+        }
+        Ok(html)
+    }
+
+    /// Run the post-processors on a page which isn't of a single chapter,
+    /// like the print page. `path` is the path its chapter is given.
+    pub(super) fn process_page(&self, name: &str, path: &str, html: String) -> Result<String> {
+        let page = Chapter::new(name, String::new(), path, Vec::new());
+        self.process(&page, html)
+    }
+}
+
+impl Debug for PostProcessors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.iter().map(|post_processor| post_processor.name()))
+            .finish()
+    }
+}
+
+impl PartialEq for PostProcessors {
+    fn eq(&self, other: &PostProcessors) -> bool {
+        self.0.len() == other.0.len() && self.0.iter().zip(&other.0).all(|(a, b)| Arc::ptr_eq(a, b))
+    }
+}
+
+/// The built-in `lazy-images` post-processor: the images of the pages get
+/// `loading="lazy"`, so the browsers only load them when they're about to be
+/// seen. The images which already have a `loading` attribute are left alone.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct LazyImages;
+
+impl ChapterPostProcessor for LazyImages {
+    fn name(&self) -> &str {
+        "lazy-images"
+    }
+
+    fn process(&self, _chapter: &Chapter, html: String) -> Result<String> {
+        lazy_static! {
+            static ref IMAGE: Regex = Regex::new(r"<img\b[^>]*>").unwrap();
+            static ref LOADING: Regex = Regex::new(r#"\sloading\s*="#).unwrap();
+        }
+
+        let html = IMAGE.replace_all(&html, |caps: &Captures<'_>| {
+            let tag = &caps[0];
+            if LOADING.is_match(tag) {
+                tag.to_string()
+            } else {
+                format!(r#"<img loading="lazy"{}"#, &tag[4..])
+            }
+        });
+        Ok(html.into_owned())
+    }
+}
+
+/// A post-processor piping the HTML of the pages through a command, run in
+/// the book's root directory. The command reads the HTML from its stdin and
+/// writes the new HTML to its stdout, and knows which chapter the page is of
+/// with the `MDBOOK_CHAPTER_NAME`, `MDBOOK_CHAPTER_PATH` (its source file)
+/// and `MDBOOK_CHAPTER_FRONTMATTER` (as JSON) environment variables. Exiting
+/// with a non-zero exit code is an error.
+///
+/// The command is run once for every page, so a slow one to start slows down
+/// the builds of big books.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CmdPostProcessor {
+    name: String,
+    cmd: String,
+    root: PathBuf,
+}
+
+impl CmdPostProcessor {
+    /// Create a new `CmdPostProcessor`.
+    pub fn new(name: String, cmd: String, root: PathBuf) -> CmdPostProcessor {
+        CmdPostProcessor { name, cmd, root }
+    }
+
+    fn command(&self) -> Result<Command> {
+        let mut words = Shlex::new(&self.cmd);
+        let executable = match words.next() {
+            Some(e) => e,
+            None => bail!("Command string was empty"),
+        };
+
+        let mut cmd = Command::new(executable);
+        for arg in words {
+            cmd.arg(arg);
+        }
+        cmd.current_dir(&self.root);
+        Ok(cmd)
+    }
+}
+
+impl ChapterPostProcessor for CmdPostProcessor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn process(&self, chapter: &Chapter, html: String) -> Result<String> {
+        let source = chapter.source_path.as_ref().or(chapter.path.as_ref());
+        let mut child = self
+            .command()?
+            .env("MDBOOK_CHAPTER_NAME", &chapter.name)
+            .env(
+                "MDBOOK_CHAPTER_PATH",
+                source.map(|path| path.as_os_str()).unwrap_or_default(),
+            )
+            .env(
+                "MDBOOK_CHAPTER_FRONTMATTER",
+                serde_json::to_string(&chapter.metadata)?,
+            )
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .with_context(|| format!("Unable to start `{}`. Is it installed?", self.cmd))?;
+
+        // Written from another thread, so a command writing before it has
+        // read everything doesn't block on a full pipe
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let writer = thread::spawn(move || stdin.write_all(html.as_bytes()));
+        let output = child
+            .wait_with_output()
+            .with_context(|| format!("Error waiting for `{}` to complete", self.cmd))?;
+        // The command doesn't have to read all its input
+        let _ = writer.join();
+
+        ensure!(
+            output.status.success(),
+            "`{}` exited unsuccessfully ({})",
+            self.cmd,
+            output.status
+        );
+        String::from_utf8(output.stdout)
+            .with_context(|| format!("The output of `{}` isn't UTF-8", self.cmd))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PostProcess;
+
+    fn chapter() -> Chapter {
+        Chapter::new("Images", String::new(), "images.md", Vec::new())
+    }
+
+    #[test]
+    fn images_are_loaded_lazily() {
+        let html = r#"<p><img src="a.png" alt="A" /> <img loading="eager" src="b.png"></p>
+<pre><code>&lt;img src="c.png"&gt;</code></pre><imgs>"#;
+
+        assert_eq!(
+            LazyImages.process(&chapter(), html.to_string()).unwrap(),
+            r#"<p><img loading="lazy" src="a.png" alt="A" /> <img loading="eager" src="b.png"></p>
+<pre><code>&lt;img src="c.png"&gt;</code></pre><imgs>"#
+        );
+    }
+
+    struct Suffix(&'static str);
+
+    impl ChapterPostProcessor for Suffix {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn process(&self, chapter: &Chapter, html: String) -> Result<String> {
+            ensure!(self.0 != "broken", "Nothing works");
+            Ok(format!("{} {}:{}", html, self.0, chapter.name))
+        }
+    }
+
+    #[test]
+    fn the_configured_post_processors_run_first() {
+        let mut registered = PostProcessors::default();
+        registered.push(Suffix("second"));
+        let mut html_config = HtmlConfig {
+            post_process: vec![PostProcess {
+                name: String::from("lazy-images"),
+                command: None,
+            }],
+            ..HtmlConfig::default()
+        };
+
+        let post_processors = registered
+            .with_configured(&html_config, PathBuf::from("."))
+            .unwrap();
+        assert_eq!(
+            format!("{:?}", post_processors),
+            r#"["lazy-images", "second"]"#
+        );
+        assert_eq!(
+            post_processors
+                .process(&chapter(), String::from("<img>"))
+                .unwrap(),
+            r#"<img loading="lazy"> second:Images"#
+        );
+
+        html_config.post_process[0].name = String::from("missing");
+        let err = registered
+            .with_configured(&html_config, PathBuf::from("."))
+            .unwrap_err();
+        assert!(err.to_string().contains("no `command` for \"missing\""));
+    }
+
+    #[test]
+    fn failures_name_the_post_processor_and_the_chapter() {
+        let mut post_processors = PostProcessors::default();
+        post_processors.push(Suffix("broken"));
+
+        let err = post_processors
+            .process(&chapter(), String::new())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The \"broken\" post-processor failed on images.md"
+        );
+        assert_eq!(err.root_cause().to_string(), "Nothing works");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn commands_get_the_html_on_stdin() {
+        let cmd = CmdPostProcessor::new(
+            String::from("upper"),
+            String::from(r#"sh -c 'tr a-z A-Z; echo "$MDBOOK_CHAPTER_PATH"'"#),
+            PathBuf::from("."),
+        );
+
+        assert_eq!(
+            cmd.process(&chapter(), String::from("<p>hi</p>\n"))
+                .unwrap(),
+            "<P>HI</P>\nimages.md\n"
+        );
+        let failing = CmdPostProcessor::new(
+            String::from("false"),
+            String::from("false"),
+            PathBuf::from("."),
+        );
+        assert!(failing.process(&chapter(), String::new()).is_err());
+    }
+}
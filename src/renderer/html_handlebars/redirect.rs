@@ -0,0 +1,299 @@
+//! Writing the pages which redirect from the old location of a moved page
+//! (`[output.html.redirect]`) to its new one.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use regex::Regex;
+
+use super::link_check::resolve;
+use crate::errors::*;
+use crate::utils;
+
+/// Where a redirect goes.
+#[derive(Debug, Clone, PartialEq)]
+enum Target {
+    /// A URL outside of the book, used as is.
+    External(String),
+    /// A page of the book, relative to its root.
+    Page {
+        path: String,
+        fragment: Option<String>,
+    },
+}
+
+/// Write a redirect page at the old path of every entry of `redirects`.
+/// Redirects to another redirect go straight to the final page. `pages` are
+/// the pages of the book, which redirects mustn't replace.
+pub(super) fn write_redirects(
+    destination: &Path,
+    redirects: &BTreeMap<String, String>,
+    pages: &BTreeSet<String>,
+) -> Result<()> {
+    let mut sources = BTreeMap::new();
+    for (from, to) in redirects {
+        sources.insert(source_page(from)?, (from.as_str(), to.as_str()));
+    }
+
+    for (source, &(from, _)) in &sources {
+        if pages.contains(source) {
+            bail!(
+                "The redirect of \"{}\" would overwrite a page of the book",
+                from
+            );
+        }
+
+        let link = match final_target(&sources, source)? {
+            Target::External(url) => url,
+            Target::Page { path, fragment } => {
+                let mut link = utils::fs::path_to_root(Path::new(source)) + &path;
+                if let Some(fragment) = fragment {
+                    link.push('#');
+                    link.push_str(&fragment);
+                }
+                link
+            }
+        };
+        debug!("Redirecting {} to {}", source, link);
+        utils::fs::write_file(destination, source, redirect_page(&link).as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Follow the redirects starting at `source` until they leave the redirected
+/// pages.
+fn final_target(sources: &BTreeMap<String, (&str, &str)>, source: &str) -> Result<Target> {
+    let mut current = source.to_string();
+    let mut visited = BTreeSet::new();
+    let mut first_fragment = None;
+
+    loop {
+        let (from, to) = sources[&current];
+        visited.insert(current.clone());
+
+        match parse_target(&current, to)
+            .with_context(|| format!("Invalid redirect of \"{}\"", from))?
+        {
+            Target::External(url) => return Ok(Target::External(url)),
+            Target::Page { path, fragment } => {
+                // The first redirect with a fragment decides where the page ends up
+                if first_fragment.is_none() {
+                    first_fragment = fragment;
+                }
+                let page = directory_index(path.clone());
+                if !sources.contains_key(&page) {
+                    return Ok(Target::Page {
+                        path,
+                        fragment: first_fragment,
+                    });
+                }
+                if visited.contains(&page) {
+                    bail!(
+                        "The redirect of \"{}\" leads back to itself",
+                        sources[source].0
+                    );
+                }
+                current = page;
+            }
+        }
+    }
+}
+
+/// The redirect to `to` from the page `from`, relative to the root.
+fn parse_target(from: &str, to: &str) -> Result<Target> {
+    lazy_static! {
+        static ref SCHEME_LINK: Regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*:").unwrap();
+    }
+
+    if SCHEME_LINK.is_match(to) || to.starts_with("//") {
+        return Ok(Target::External(to.to_string()));
+    }
+
+    let (path, fragment) = match to.find('#') {
+        Some(ix) => (&to[..ix], Some(to[ix + 1..].to_string())),
+        None => (to, None),
+    };
+    let path = if path.is_empty() {
+        from.to_string()
+    } else {
+        let base = if path.starts_with('/') {
+            Path::new("")
+        } else {
+            Path::new(from).parent().unwrap_or_else(|| Path::new(""))
+        };
+        let resolved = match resolve(base, path.trim_start_matches('/')) {
+            Some(resolved) => utils::fs::forward_slashes(&resolved),
+            None => bail!("\"{}\" is outside of the book", to),
+        };
+        if path.ends_with('/') && !resolved.is_empty() {
+            resolved + "/"
+        } else {
+            resolved
+        }
+    };
+
+    Ok(Target::Page { path, fragment })
+}
+
+/// The old path of a page, relative to the root of the book. The page of a
+/// directory (`/old/`) is its `index.html`.
+pub(super) fn source_page(from: &str) -> Result<String> {
+    match resolve(Path::new(""), from.trim_start_matches('/')) {
+        Some(ref path) if !path.as_os_str().is_empty() => {
+            let path = utils::fs::forward_slashes(path);
+            if from.ends_with('/') {
+                Ok(directory_index(path + "/"))
+            } else {
+                Ok(path)
+            }
+        }
+        _ => bail!(
+            "The redirect path \"{}\" isn't a page inside the book",
+            from
+        ),
+    }
+}
+
+/// The page served for `path`: its `index.html` if it's a directory.
+fn directory_index(path: String) -> String {
+    if path.is_empty() || path.ends_with('/') {
+        path + "index.html"
+    } else {
+        path
+    }
+}
+
+/// A page which redirects to `link` right away.
+pub(super) fn redirect_page(link: &str) -> String {
+    let link = utils::escape_html(link);
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta charset="UTF-8">
+        <title>Redirecting...</title>
+        <meta http-equiv="refresh" content="0; URL={link}">
+        <link rel="canonical" href="{link}">
+    </head>
+    <body>
+        <p>This page has moved to <a href="{link}">{link}</a>.</p>
+    </body>
+</html>
+"#,
+        link = link
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder as TempFileBuilder;
+
+    fn redirects(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|&(from, to)| (from.to_string(), to.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_redirect_targets() {
+        let inputs = [
+            ("old.html", "new.html", "new.html", None),
+            (
+                "a/old.html",
+                "../b/new.html#usage",
+                "b/new.html",
+                Some("usage"),
+            ),
+            ("a/old.html", "/c/new.html", "c/new.html", None),
+            ("a/old.html", "guide/", "a/guide/", None),
+            ("a/old.html", "#heading", "a/old.html", Some("heading")),
+        ];
+
+        for &(from, to, path, fragment) in &inputs {
+            let should_be = Target::Page {
+                path: path.to_string(),
+                fragment: fragment.map(String::from),
+            };
+            assert_eq!(parse_target(from, to).unwrap(), should_be, "{}", to);
+        }
+
+        assert_eq!(
+            parse_target("old.html", "https://example.com/").unwrap(),
+            Target::External(String::from("https://example.com/"))
+        );
+        assert!(parse_target("old.html", "../../outside.html").is_err());
+    }
+
+    #[test]
+    fn chains_of_redirects_are_flattened() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let map = redirects(&[
+            ("/first.html", "second.html#part"),
+            ("/second.html", "guide/third.html"),
+            ("/old/index.html", "../first.html"),
+        ]);
+
+        write_redirects(temp.path(), &map, &BTreeSet::new()).unwrap();
+
+        let first = std::fs::read_to_string(temp.path().join("first.html")).unwrap();
+        assert!(
+            first.contains(r#"<meta http-equiv="refresh" content="0; URL=guide/third.html#part">"#)
+        );
+        assert!(first.contains(r#"<link rel="canonical" href="guide/third.html#part">"#));
+        let old = std::fs::read_to_string(temp.path().join("old/index.html")).unwrap();
+        assert!(old.contains(r#"URL=../guide/third.html#part""#));
+    }
+
+    #[test]
+    fn directories_redirect_from_their_index() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let map = redirects(&[
+            ("/old/", "../new/"),
+            ("/older/", "/old/"),
+            ("/new/", "/guide/index.html"),
+        ]);
+
+        write_redirects(temp.path(), &map, &BTreeSet::new()).unwrap();
+
+        assert!(!temp.path().join("old").is_file());
+        let old = std::fs::read_to_string(temp.path().join("old/index.html")).unwrap();
+        assert!(old.contains(r#"URL=../guide/index.html""#));
+        let older = std::fs::read_to_string(temp.path().join("older/index.html")).unwrap();
+        assert!(older.contains(r#"URL=../guide/index.html""#));
+
+        let pages: BTreeSet<_> = vec![String::from("guide/index.html")].into_iter().collect();
+        let err =
+            write_redirects(temp.path(), &redirects(&[("/guide/", "x.html")]), &pages).unwrap_err();
+        assert!(err.to_string().contains("would overwrite a page"));
+    }
+
+    #[test]
+    fn invalid_redirects_are_errors() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let pages: BTreeSet<_> = vec![String::from("intro.html")].into_iter().collect();
+
+        let err = write_redirects(
+            temp.path(),
+            &redirects(&[("/intro.html", "x.html")]),
+            &pages,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The redirect of \"/intro.html\" would overwrite a page of the book"
+        );
+
+        let looping = redirects(&[("/a.html", "b.html"), ("/b.html", "/a.html")]);
+        let err = write_redirects(temp.path(), &looping, &pages).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The redirect of \"/a.html\" leads back to itself"
+        );
+
+        let outside = redirects(&[("/../a.html", "b.html")]);
+        assert!(write_redirects(temp.path(), &outside, &pages).is_err());
+    }
+}
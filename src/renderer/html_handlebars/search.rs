@@ -4,23 +4,44 @@ use std::path::Path;
 
 use elasticlunr::Index;
 use pulldown_cmark::*;
+use regex::Regex;
 
-use crate::book::{Book, BookItem};
-use crate::config::Search;
+use crate::book::{Book, BookItem, Chapter};
+use crate::config::{HtmlConfig, Search};
 use crate::errors::*;
 use crate::theme::searcher;
-use crate::utils;
+use crate::utils::{self, CodeBlockAttributes};
 
-/// Creates all files required for search.
-pub fn create_files(search_config: &Search, destination: &Path, book: &Book) -> Result<()> {
+/// Creates all files required for search. The code blocks in the
+/// `passthrough-code-languages` of `html_config` aren't indexed, and neither
+/// are the ones marked `noindex`, the chapters the `exclude` patterns match
+/// and the ones with `search = false` in their frontmatter.
+pub fn create_files(
+    search_config: &Search,
+    destination: &Path,
+    book: &Book,
+    html_config: &HtmlConfig,
+) -> Result<()> {
     let mut index = Index::new(&["title", "body", "breadcrumbs"]);
     let mut doc_urls = Vec::with_capacity(book.sections.len());
+    let excluded = search_config
+        .exclude
+        .iter()
+        .map(|pattern| utils::fs::glob_to_regex(pattern))
+        .collect::<Result<Vec<_>>>()
+        .with_context(|| "Invalid pattern in output.html.search.exclude")?;
 
     for item in book.iter() {
-        render_item(&mut index, &search_config, &mut doc_urls, item)?;
+        if let BookItem::Chapter(ref ch) = *item {
+            if is_excluded(ch, &excluded) {
+                debug!("Leaving \"{}\" out of the search index", ch.name);
+                continue;
+            }
+        }
+        render_item(&mut index, search_config, &mut doc_urls, item, html_config)?;
     }
 
-    let index = write_to_json(index, &search_config, doc_urls)?;
+    let index = write_to_json(index, search_config, doc_urls)?;
     debug!("Writing search index ✓");
     if index.len() > 10_000_000 {
         warn!("searchindex.json is very large ({} bytes)", index.len());
@@ -69,7 +90,9 @@ fn render_item(
     search_config: &Search,
     doc_urls: &mut Vec<String>,
     item: &BookItem,
+    html_config: &HtmlConfig,
 ) -> Result<()> {
+    let passthrough = &html_config.passthrough_code_languages;
     let chapter = match *item {
         BookItem::Chapter(ref ch) if !ch.is_draft_chapter() => ch,
         _ => return Ok(()),
@@ -79,26 +102,43 @@ fn render_item(
         .path
         .as_ref()
         .expect("Checked that path exists above");
-    let filepath = Path::new(&chapter_path).with_extension("html");
-    let filepath = filepath
+    chapter_path
         .to_str()
         .with_context(|| "Could not convert HTML path to str")?;
-    let anchor_base = utils::fs::normalize_path(filepath);
+    let anchor_base = utils::fs::chapter_link(chapter_path, html_config.pretty_urls);
 
     let mut p = utils::new_cmark_parser(&chapter.content).peekable();
 
     let mut in_heading = false;
+    let mut in_skipped_heading = false;
+    let mut skipped_heading = String::new();
+    let mut id_counter = HashMap::new();
     let max_section_depth = u32::from(search_config.heading_split_level);
     let mut section_id = None;
     let mut heading = String::new();
     let mut body = String::new();
-    let mut breadcrumbs = chapter.parent_names.clone();
+    // The headings the current section is under, and its own one
+    let mut headings: Vec<(u32, String)> = Vec::new();
+    let max_section_words = search_config.max_section_words as usize;
+    // The words of the body, up to the end of its last block
+    let mut body_words = 0;
+    let mut counted = 0;
+    // Whether the section was split, so there's nothing left to add without
+    // a body
+    let mut split = false;
     let mut footnote_numbers = HashMap::new();
+    // The H1 the chapter starts with isn't on the page, so it has no ID
+    let mut in_stripped_h1 = false;
+    let mut first_event = true;
 
     while let Some(event) = p.next() {
+        if first_event {
+            first_event = false;
+            in_stripped_h1 = html_config.strip_first_h1 && event == Event::Start(Tag::Heading(1));
+        }
         match event {
             Event::Start(Tag::Heading(i)) if i <= max_section_depth => {
-                if !heading.is_empty() {
+                if !(heading.is_empty() || (split && body.trim().is_empty())) {
                     // Section finished, the next heading is following now
                     // Write the data to the index, and clear it for the next section
                     add_doc(
@@ -106,25 +146,68 @@ fn render_item(
                         doc_urls,
                         &anchor_base,
                         &section_id,
-                        &[&heading, &body, &breadcrumbs.join(" » ")],
+                        &[
+                            &heading,
+                            &body,
+                            &hierarchy(&chapter.parent_names, &headings),
+                        ],
                     );
+                }
+                if !heading.is_empty() {
                     section_id = None;
                     heading.clear();
                     body.clear();
-                    breadcrumbs.pop();
+                    body_words = 0;
+                    counted = 0;
+                }
+                split = false;
+                while let Some(&(level, _)) = headings.last() {
+                    if level < i {
+                        break;
+                    }
+                    headings.pop();
                 }
 
                 in_heading = true;
             }
             Event::End(Tag::Heading(i)) if i <= max_section_depth => {
                 in_heading = false;
-                section_id = Some(utils::id_from_content(&heading));
-                breadcrumbs.push(heading.clone());
+                section_id = if in_stripped_h1 {
+                    in_stripped_h1 = false;
+                    None
+                } else {
+                    Some(utils::unique_id_from_content(&heading, &mut id_counter))
+                };
+                headings.push((i, heading.clone()));
+            }
+            Event::Start(Tag::Heading(_)) => {
+                // Headings below the split level aren't sections of their own,
+                // but they still use up IDs in the rendered page
+                in_skipped_heading = true;
+                skipped_heading.clear();
+                body.push(' ');
+            }
+            Event::End(Tag::Heading(_)) => {
+                in_skipped_heading = false;
+                utils::unique_id_from_content(&skipped_heading, &mut id_counter);
+                body.push(' ');
             }
             Event::Start(Tag::FootnoteDefinition(name)) => {
                 let number = footnote_numbers.len() + 1;
                 footnote_numbers.entry(name).or_insert(number);
             }
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref info)))
+                if is_passthrough(info, passthrough)
+                    || CodeBlockAttributes::parse(info).noindex =>
+            {
+                // Skip the block, it's a diagram (or the like) rather than text
+                for event in p.by_ref() {
+                    if let Event::End(Tag::CodeBlock(_)) = event {
+                        break;
+                    }
+                }
+                body.push(' ');
+            }
             Event::Html(html) => {
                 let mut html_block = html.into_string();
 
@@ -132,12 +215,36 @@ fn render_item(
                 // in an HtmlBlock tag. We must collect consecutive Html events
                 // into a block ourselves.
                 while let Some(Event::Html(html)) = p.peek() {
-                    html_block.push_str(&html);
+                    html_block.push_str(html);
                     p.next();
                 }
 
                 body.push_str(&clean_html(&html_block));
             }
+            Event::End(ref tag) if !in_heading && max_section_words > 0 && is_block(tag) => {
+                body.push(' ');
+                body_words += added_words(&body[..counted], &body[counted..]);
+                counted = body.len();
+                if body_words >= max_section_words {
+                    // Split the long section after the block it's long enough
+                    // with
+                    add_doc(
+                        index,
+                        doc_urls,
+                        &anchor_base,
+                        &section_id,
+                        &[
+                            &heading,
+                            &body,
+                            &hierarchy(&chapter.parent_names, &headings),
+                        ],
+                    );
+                    body.clear();
+                    body_words = 0;
+                    counted = 0;
+                    split = true;
+                }
+            }
             Event::Start(_) | Event::End(_) | Event::Rule | Event::SoftBreak | Event::HardBreak => {
                 // Insert spaces where HTML output would usually seperate text
                 // to ensure words don't get merged together
@@ -151,6 +258,9 @@ fn render_item(
                 if in_heading {
                     heading.push_str(&text);
                 } else {
+                    if in_skipped_heading {
+                        skipped_heading.push_str(&text);
+                    }
                     body.push_str(&text);
                 }
             }
@@ -163,20 +273,56 @@ fn render_item(
         }
     }
 
-    if !heading.is_empty() {
+    if !(heading.is_empty() || (split && body.trim().is_empty())) {
         // Make sure the last section is added to the index
         add_doc(
             index,
             doc_urls,
             &anchor_base,
             &section_id,
-            &[&heading, &body, &breadcrumbs.join(" » ")],
+            &[
+                &heading,
+                &body,
+                &hierarchy(&chapter.parent_names, &headings),
+            ],
         );
     }
 
     Ok(())
 }
 
+/// Whether `tag` is a block a long section can be split after.
+fn is_block(tag: &Tag<'_>) -> bool {
+    match *tag {
+        Tag::Paragraph | Tag::List(_) | Tag::BlockQuote | Tag::CodeBlock(_) | Tag::Table(_) => true,
+        _ => false,
+    }
+}
+
+/// The number of words `text` adds to the `before` it's appended to, which
+/// may end in the middle of the first one.
+fn added_words(before: &str, text: &str) -> usize {
+    let words = text.split_whitespace().count();
+    let continues_word = before.ends_with(|c: char| !c.is_whitespace())
+        && text.starts_with(|c: char| !c.is_whitespace());
+    if continues_word {
+        words - 1
+    } else {
+        words
+    }
+}
+
+/// The breadcrumbs of a section, like `Installation » Linux » Arch`: the
+/// names of the chapter's parents, then the headings it's under.
+fn hierarchy(parent_names: &[String], headings: &[(u32, String)]) -> String {
+    parent_names
+        .iter()
+        .map(String::as_str)
+        .chain(headings.iter().map(|(_, heading)| heading.as_str()))
+        .collect::<Vec<_>>()
+        .join(" » ")
+}
+
 fn write_to_json(index: Index, search_config: &Search, doc_urls: Vec<String>) -> Result<String> {
     use elasticlunr::config::{SearchBool, SearchOptions, SearchOptionsField};
     use std::collections::BTreeMap;
@@ -200,13 +346,13 @@ fn write_to_json(index: Index, search_config: &Search, doc_urls: Vec<String>) ->
     }
 
     let mut fields = BTreeMap::new();
-    let mut opt = SearchOptionsField::default();
-    opt.boost = Some(search_config.boost_title);
-    fields.insert("title".into(), opt);
-    opt.boost = Some(search_config.boost_paragraph);
-    fields.insert("body".into(), opt);
-    opt.boost = Some(search_config.boost_hierarchy);
-    fields.insert("breadcrumbs".into(), opt);
+    let field = |boost| SearchOptionsField {
+        boost: Some(boost),
+        ..SearchOptionsField::default()
+    };
+    fields.insert("title".into(), field(search_config.boost_title));
+    fields.insert("body".into(), field(search_config.boost_paragraph));
+    fields.insert("breadcrumbs".into(), field(search_config.boost_hierarchy));
 
     let search_options = SearchOptions {
         bool: if search_config.use_boolean_and {
@@ -257,3 +403,261 @@ fn clean_html(html: &str) -> String {
     }
     AMMONIA.clean(html).to_string()
 }
+
+/// Whether a chapter is left out of the index, by one of the `exclude`
+/// patterns or `search = false` in its frontmatter.
+fn is_excluded(ch: &Chapter, excluded: &[Regex]) -> bool {
+    if ch.metadata.get("search") == Some(&serde_json::Value::Bool(false)) {
+        return true;
+    }
+    match ch.source_path.as_ref().or(ch.path.as_ref()) {
+        Some(path) => {
+            let path = path.to_string_lossy().replace('\\', "/");
+            excluded.iter().any(|pattern| pattern.is_match(&path))
+        }
+        None => false,
+    }
+}
+
+fn is_passthrough(info: &str, passthrough: &[String]) -> bool {
+    match CodeBlockAttributes::parse(info).language {
+        Some(ref language) => passthrough.contains(language),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::Chapter;
+    use std::fs;
+    use tempfile::Builder as TempFileBuilder;
+
+    fn index_book(book: &Book, search_config: &Search) -> serde_json::Value {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let html_config = HtmlConfig {
+            passthrough_code_languages: vec![String::from("mermaid")],
+            ..Default::default()
+        };
+        create_files(search_config, temp.path(), book, &html_config).unwrap();
+
+        let index = fs::read_to_string(temp.path().join("searchindex.json")).unwrap();
+        serde_json::from_str(&index).unwrap()
+    }
+
+    #[test]
+    fn sections_link_to_the_rendered_heading_ids() {
+        let content = "# Usage\n\nText\n\n## Usage\n\n#### Usage\n\n## Usage\n";
+        let mut book = Book::new();
+        book.push_item(Chapter::new_draft("Draft", Vec::new()))
+            .push_item(Chapter::new(
+                "Usage",
+                content.to_string(),
+                "guide/usage.md",
+                Vec::new(),
+            ));
+
+        let index = index_book(&book, &Search::default());
+
+        let should_be = json!([
+            "guide/usage.html#usage",
+            "guide/usage.html#usage-1",
+            "guide/usage.html#usage-3",
+        ]);
+        assert_eq!(index["doc_urls"], should_be);
+    }
+
+    #[test]
+    fn sections_have_the_headings_they_are_under_as_breadcrumbs() {
+        let content = "# Installation\n\nText\n\n## Linux\n\n### Arch\n\npacman\n\n\
+                       ### Debian\n\napt\n\n## Windows\n\nchoco\n\n#### Deep\n\nnot split\n";
+        let mut book = Book::new();
+        book.push_item(Chapter::new(
+            "Installation",
+            content.to_string(),
+            "guide/installation.md",
+            vec![String::from("Guide")],
+        ));
+
+        let index = index_book(&book, &Search::default());
+
+        let should_be = json!([
+            "guide/installation.html#installation",
+            "guide/installation.html#linux",
+            "guide/installation.html#arch",
+            "guide/installation.html#debian",
+            "guide/installation.html#windows",
+        ]);
+        assert_eq!(index["doc_urls"], should_be);
+        let docs = &index["index"]["documentStore"]["docs"];
+        let breadcrumbs: Vec<_> = (0..5)
+            .map(|i| docs[i.to_string()]["breadcrumbs"].clone())
+            .collect();
+        assert_eq!(
+            breadcrumbs,
+            [
+                "Guide » Installation",
+                "Guide » Installation » Linux",
+                "Guide » Installation » Linux » Arch",
+                "Guide » Installation » Linux » Debian",
+                "Guide » Installation » Windows",
+            ]
+        );
+        assert_eq!(docs["2"]["title"], "Arch");
+        assert_eq!(docs["2"]["body"], "pacman");
+        assert_eq!(docs["4"]["body"], "choco Deep not split");
+    }
+
+    #[test]
+    fn long_sections_are_split() {
+        let content = "# Long\n\nOne two three.\n\nFour five.\n\nSix.\n\n## Short\n\nSeven.\n";
+        let mut book = Book::new();
+        book.push_item(Chapter::new(
+            "Long",
+            content.to_string(),
+            "long.md",
+            Vec::new(),
+        ));
+        let search_config = Search {
+            max_section_words: 4,
+            ..Default::default()
+        };
+
+        let index = index_book(&book, &search_config);
+
+        let should_be = json!(["long.html#long", "long.html#long", "long.html#short"]);
+        assert_eq!(index["doc_urls"], should_be);
+        let docs = &index["index"]["documentStore"]["docs"];
+        assert_eq!(docs["0"]["body"], "One two three. Four five.");
+        assert_eq!(docs["1"]["body"], "Six.");
+        assert_eq!(docs["1"]["title"], "Long");
+        assert_eq!(docs["2"]["body"], "Seven.");
+    }
+
+    #[test]
+    fn sections_are_split_after_blocks_even_before_the_first_heading() {
+        let content = "Before one *two* three.\n\nAfter.\n\n# Long\n\n\
+                       One *two* three four.\n\n- Five\n- Six\n";
+        let mut book = Book::new();
+        book.push_item(Chapter::new(
+            "Long",
+            content.to_string(),
+            "long.md",
+            Vec::new(),
+        ));
+        let search_config = Search {
+            max_section_words: 2,
+            ..Default::default()
+        };
+
+        let index = index_book(&book, &search_config);
+
+        let should_be = json!(["long.html", "long.html#long", "long.html#long"]);
+        assert_eq!(index["doc_urls"], should_be);
+        let docs = &index["index"]["documentStore"]["docs"];
+        assert_eq!(docs["0"]["body"], "Before one two three.");
+        assert_eq!(docs["1"]["body"], "After. One two three four.");
+        assert_eq!(docs["2"]["body"], "Five Six");
+    }
+
+    #[test]
+    fn excluded_chapters_and_code_blocks_arent_indexed() {
+        let mut hidden = Chapter::new(
+            "Hidden",
+            "# Hidden\n\nSecret frobnication.\n".to_string(),
+            "hidden.md",
+            Vec::new(),
+        );
+        hidden.metadata.insert("search".to_string(), json!(false));
+        let mut book = Book::new();
+        book.push_item(Chapter::new(
+            "Public",
+            "# Public\n\n```text,noindex\nfrobnication\n```\n\nVisible text.\n".to_string(),
+            "public.md",
+            Vec::new(),
+        ))
+        .push_item(hidden)
+        .push_item(Chapter::new(
+            "Boilerplate",
+            "# Boilerplate\n\nMore frobnication.\n".to_string(),
+            "internal/boilerplate.md",
+            Vec::new(),
+        ));
+        let search_config = Search {
+            exclude: vec![String::from("internal/**")],
+            ..Default::default()
+        };
+
+        let index = index_book(&book, &search_config);
+
+        assert_eq!(index["doc_urls"], json!(["public.html#public"]));
+        let docs = index["index"]["documentStore"]["docs"].to_string();
+        assert!(docs.contains("Visible"), "{}", docs);
+        assert!(!docs.contains("frobnication"), "{}", docs);
+        let body = &index["index"]["index"]["body"]["root"];
+        assert!(body["f"]["r"]["o"]["b"].is_null(), "{}", body);
+    }
+
+    #[test]
+    fn a_book_without_indexed_chapters_has_an_empty_index() {
+        let mut book = Book::new();
+        book.push_item(Chapter::new(
+            "Internal",
+            "# Internal\n\nText.\n".to_string(),
+            "internal.md",
+            Vec::new(),
+        ));
+        let search_config = Search {
+            exclude: vec![String::from("*.md")],
+            ..Default::default()
+        };
+
+        let index = index_book(&book, &search_config);
+
+        assert_eq!(index["doc_urls"], json!([]));
+        assert_eq!(index["index"]["documentStore"]["length"], 0);
+        assert!(index["index"]["index"]["body"].is_object());
+    }
+
+    #[test]
+    fn search_options_are_written_to_the_index() {
+        let search_config = Search {
+            limit_results: 5,
+            teaser_word_count: 12,
+            boost_title: 4,
+            boost_hierarchy: 3,
+            boost_paragraph: 2,
+            ..Default::default()
+        };
+        let index = index_book(&Book::new(), &search_config);
+
+        assert_eq!(
+            index["results_options"],
+            json!({ "limit_results": 5, "teaser_word_count": 12 })
+        );
+        let fields = &index["search_options"]["fields"];
+        assert_eq!(fields["title"]["boost"], 4);
+        assert_eq!(fields["breadcrumbs"]["boost"], 3);
+        assert_eq!(fields["body"]["boost"], 2);
+        assert_eq!(index["doc_urls"], json!([]));
+    }
+
+    #[test]
+    fn passthrough_code_blocks_arent_indexed() {
+        let content = "# Diagrams\n\n```mermaid\ngraph TD;\n  Kitchen-->Sink;\n```\n\n\
+                       ```rust\nlet plumbing = 1;\n```\n";
+        let mut book = Book::new();
+        book.push_item(Chapter::new(
+            "Diagrams",
+            content.to_string(),
+            "diagrams.md",
+            Vec::new(),
+        ));
+
+        let index = index_book(&book, &Search::default());
+
+        let docs = index["index"]["documentStore"]["docs"].to_string();
+        assert!(docs.contains("plumbing"), "{}", docs);
+        assert!(!docs.contains("Kitchen"), "{}", docs);
+    }
+}
@@ -0,0 +1,515 @@
+//! `book.html`, the whole book in one page which doesn't need any other file:
+//! the chapters are sections of the page, the links between them go to those
+//! sections, and the stylesheets, scripts, fonts and images are embedded.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use regex::{Captures, Regex};
+
+use crate::book::{Book, BookItem};
+use crate::diagnostics::{Diagnostic, DiagnosticKind, Diagnostics};
+use crate::utils;
+
+lazy_static! {
+    static ref SCHEME_LINK: Regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*:").unwrap();
+}
+
+/// The parts of a page with every chapter, the print page or `book.html`.
+/// Each chapter is in an element of its own, with the chapter's path as its
+/// ID (like `guide-windows-md`), and the IDs of the chapter's HTML start with
+/// that ID (like `guide-windows-md-installation`). Every ID is unique on the
+/// page, even when the paths of two chapters give the same one.
+#[derive(Default)]
+pub(super) struct ChapterParts {
+    /// The IDs of the page.
+    ids: HashSet<String>,
+    /// The ID of the part of each chapter, by the path of the chapter's page
+    /// (with forward slashes).
+    parts: HashMap<String, String>,
+    /// The IDs on the page of the IDs of the chapters' HTML, by the path of
+    /// the chapter's page and the ID on that page.
+    elements: HashMap<(String, String), String>,
+}
+
+impl ChapterParts {
+    /// The parts of the chapters of the `book`, which get their IDs in the
+    /// order of the table of contents, before the IDs of their HTML.
+    pub(super) fn new(book: &Book) -> ChapterParts {
+        let mut parts = ChapterParts::default();
+        for item in book.iter() {
+            if let BookItem::Chapter(ref ch) = *item {
+                if let Some(ref path) = ch.path {
+                    parts.part(path);
+                }
+            }
+        }
+        parts
+    }
+
+    /// The part of the page of the chapter at `path`, with the chapter's
+    /// `html` and its IDs made unique. The links to the other chapters are
+    /// pointed at their parts with `link_chapters` once the page has them all.
+    pub(super) fn add(&mut self, path: &Path, html: &str) -> String {
+        lazy_static! {
+            static ref ID: Regex = Regex::new(r#"\bid="([^"]*)""#).unwrap();
+            static ref LINK: Regex = Regex::new(r##"\bhref="#([^"]*)""##).unwrap();
+        }
+
+        let (page, part) = self.part(path);
+        let page = page.as_str();
+
+        let html = ID.replace_all(html, |caps: &Captures<'_>| {
+            let id = self.unique(prefixed_id(&part, &caps[1]));
+            // Like in a browser, the links go to the first element with the ID
+            self.elements
+                .entry((page.to_string(), caps[1].to_string()))
+                .or_insert_with(|| id.clone());
+            format!(r#"id="{}""#, id)
+        });
+        let html = LINK.replace_all(&html, |caps: &Captures<'_>| {
+            format!(r##"href="#{}""##, self.element(&part, page, &caps[1]))
+        });
+        format!("<div id=\"{}\">\n{}</div>\n", part, html)
+    }
+
+    /// Point the links to the pages of the chapters at their parts of the
+    /// page.
+    pub(super) fn link_chapters(&self, html: &str) -> String {
+        lazy_static! {
+            static ref HREF: Regex = Regex::new(r##"\bhref="([^"#]+)(?:#([^"]*))?""##).unwrap();
+        }
+
+        HREF.replace_all(html, |caps: &Captures<'_>| {
+            let link = &caps[1];
+            if SCHEME_LINK.is_match(link) || link.starts_with('/') {
+                return caps[0].to_string();
+            }
+            let page = normalize(link);
+            match self.parts.get(&page) {
+                Some(part) => match caps.get(2) {
+                    Some(fragment) if !fragment.as_str().is_empty() => format!(
+                        r##"href="#{}""##,
+                        self.element(part, &page, fragment.as_str())
+                    ),
+                    _ => format!(r##"href="#{}""##, part),
+                },
+                None => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+    }
+
+    /// The ID on the page of the ID `id` of the chapter's page `page`, whose
+    /// part is `part`. The IDs the chapter doesn't have get the part's ID
+    /// too, so the links to them are still broken.
+    fn element(&self, part: &str, page: &str, id: &str) -> String {
+        match self.elements.get(&(page.to_string(), id.to_string())) {
+            Some(element) => element.clone(),
+            None => prefixed_id(part, id),
+        }
+    }
+
+    /// The path of the page of the chapter at `path` (with forward slashes),
+    /// and the ID of the chapter's part.
+    fn part(&mut self, path: &Path) -> (String, String) {
+        let page = utils::fs::normalize_path(&path.with_extension("html").to_string_lossy());
+        if let Some(part) = self.parts.get(&page) {
+            return (page, part.clone());
+        }
+        let id = path.to_string_lossy().replace(&['/', '\\', '.'][..], "-");
+        let part = self.unique(utils::normalize_id(&id));
+        self.parts.insert(page.clone(), part.clone());
+        (page, part)
+    }
+
+    /// The page of the chapter an ID of the page is from, with the chapter's
+    /// own ID (empty for the chapter's part itself), like `first/nested.html`
+    /// and `some-section` for `first-nested-md-some-section`.
+    pub(super) fn source_of<'a>(&'a self, id: &'a str) -> Option<(&'a str, &'a str)> {
+        self.parts
+            .iter()
+            .filter_map(|(page, part)| {
+                if id == part {
+                    Some((page.as_str(), part.len(), ""))
+                } else if id.starts_with(part.as_str()) && id[part.len()..].starts_with('-') {
+                    Some((page.as_str(), part.len(), &id[part.len() + 1..]))
+                } else {
+                    None
+                }
+            })
+            // `a-md-md-b` is rather from `a-md.md` than from `a.md`
+            .max_by_key(|&(_, len, _)| len)
+            .map(|(page, _, id)| (page, id))
+    }
+
+    /// `id`, or `id` with the first number which makes it unique on the page.
+    fn unique(&mut self, id: String) -> String {
+        let mut unique = id.clone();
+        let mut count = 0;
+        while self.ids.contains(&unique) {
+            count += 1;
+            unique = format!("{}-{}", id, count);
+        }
+        self.ids.insert(unique.clone());
+        unique
+    }
+}
+
+/// The ID `id` of a chapter's HTML with the ID of the chapter's part, `part`.
+fn prefixed_id(part: &str, id: &str) -> String {
+    // The footnotes are rendered with an empty prefix, so their IDs already
+    // start with the `-` which separates the prefix
+    if id.starts_with('-') {
+        format!("{}{}", part, id)
+    } else {
+        format!("{}-{}", part, id)
+    }
+}
+
+/// Embed the stylesheets, scripts, icons and images `html` links to in the
+/// `destination` directory. The files bigger than `max_size` are linked to
+/// instead, with a warning.
+pub(super) fn inline_assets(
+    html: &str,
+    destination: &Path,
+    max_size: u64,
+    diagnostics: &Diagnostics,
+) -> String {
+    lazy_static! {
+        static ref LINK: Regex = Regex::new(r#"<link\b[^>]*>"#).unwrap();
+        static ref REL: Regex = Regex::new(r#"\brel="([^"]*)""#).unwrap();
+        static ref HREF: Regex = Regex::new(r#"\bhref="([^"]*)""#).unwrap();
+        static ref MEDIA: Regex = Regex::new(r#"\bmedia="([^"]*)""#).unwrap();
+        static ref SCRIPT: Regex =
+            Regex::new(r#"<script\b([^>]*?)\s*\bsrc="([^"]*)"([^>]*)>\s*</script>"#).unwrap();
+        static ref IMG: Regex = Regex::new(r#"(<img\b[^>]*?\bsrc=")([^"]*)(")"#).unwrap();
+    }
+    let assets = Assets {
+        destination,
+        max_size,
+        diagnostics,
+    };
+
+    let html = LINK.replace_all(html, |caps: &Captures<'_>| {
+        let tag = &caps[0];
+        let rel = REL
+            .captures(tag)
+            .map(|rel| rel[1].to_string())
+            .unwrap_or_default();
+        let href = match HREF.captures(tag) {
+            Some(href) => href[1].to_string(),
+            None => return tag.to_string(),
+        };
+        match rel.as_str() {
+            "stylesheet" => match assets.text(Path::new(""), &href) {
+                Some((path, css)) => {
+                    let css_dir = path.parent().unwrap_or_else(|| Path::new(""));
+                    let css = assets.inline_css_urls(&css, css_dir);
+                    match MEDIA.captures(tag) {
+                        Some(media) => {
+                            format!("<style media=\"{}\">\n{}\n</style>", &media[1], css)
+                        }
+                        None => format!("<style>\n{}\n</style>", css),
+                    }
+                }
+                None => tag.to_string(),
+            },
+            "icon" | "shortcut icon" => match assets.data_uri(Path::new(""), &href) {
+                Some(uri) => {
+                    tag.replace(&format!("href=\"{}\"", href), &format!("href=\"{}\"", uri))
+                }
+                None => tag.to_string(),
+            },
+            _ => tag.to_string(),
+        }
+    });
+
+    let html = SCRIPT.replace_all(&html, |caps: &Captures<'_>| {
+        match assets.text(Path::new(""), &caps[2]) {
+            // A `</script>` in the script would end it early
+            Some((_, js)) => format!(
+                "<script{}{}>\n{}\n</script>",
+                &caps[1],
+                &caps[3],
+                js.replace("</script", "<\\/script")
+            ),
+            None => caps[0].to_string(),
+        }
+    });
+
+    IMG.replace_all(&html, |caps: &Captures<'_>| {
+        match assets.data_uri(Path::new(""), &caps[2]) {
+            Some(uri) => format!("{}{}{}", &caps[1], uri, &caps[3]),
+            None => caps[0].to_string(),
+        }
+    })
+    .into_owned()
+}
+
+/// The files of the build directory which get embedded.
+struct Assets<'a> {
+    destination: &'a Path,
+    max_size: u64,
+    diagnostics: &'a Diagnostics,
+}
+
+impl Assets<'_> {
+    /// The file `link` is to, relative to the build directory, if it's a file
+    /// of the build directory. `dir` is the directory `link` is relative to.
+    fn file(&self, dir: &Path, link: &str) -> Option<PathBuf> {
+        if SCHEME_LINK.is_match(link) || link.starts_with('/') {
+            return None;
+        }
+        // The fonts of Font Awesome have queries and fragments for old browsers
+        let link = link.split(&['?', '#'][..]).next().unwrap_or("");
+        let path = normalize(&format!("{}/{}", dir.display(), link));
+        if path.is_empty() || path.starts_with("..") || !self.destination.join(&path).is_file() {
+            return None;
+        }
+        Some(PathBuf::from(path))
+    }
+
+    /// The path and content of a text file, like a stylesheet.
+    fn text(&self, dir: &Path, link: &str) -> Option<(PathBuf, String)> {
+        let path = self.file(dir, link)?;
+        let text = fs::read_to_string(self.destination.join(&path)).ok()?;
+        Some((path, text))
+    }
+
+    /// The file as a `data:` URI, unless it's bigger than the maximum size.
+    fn data_uri(&self, dir: &Path, link: &str) -> Option<String> {
+        let path = self.file(dir, link)?;
+        let full_path = self.destination.join(&path);
+        let size = fs::metadata(&full_path).ok()?.len();
+        if size > self.max_size {
+            self.diagnostics.warn(Diagnostic::new(
+                DiagnosticKind::Other,
+                format!(
+                    "{} is {} bytes, more than output.html.single-file.max-image-size, \
+                     so book.html links to it instead of embedding it",
+                    path.display(),
+                    size
+                ),
+            ));
+            return None;
+        }
+        let content = fs::read(&full_path).ok()?;
+        Some(format!(
+            "data:{};base64,{}",
+            mime_type(&path),
+            base64::encode(&content)
+        ))
+    }
+
+    /// Embed the files the `url()`s of a stylesheet in `dir` are to.
+    fn inline_css_urls(&self, css: &str, dir: &Path) -> String {
+        lazy_static! {
+            static ref URL: Regex = Regex::new(r#"url\(\s*(['"]?)([^'")]+)(['"]?)\s*\)"#).unwrap();
+        }
+
+        URL.replace_all(css, |caps: &Captures<'_>| {
+            match self.data_uri(dir, caps[2].trim()) {
+                Some(uri) => format!("url(\"{}\")", uri),
+                None => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+    }
+}
+
+/// `path` with forward slashes and without its `.` and `..` components, as
+/// far as they can be resolved.
+fn normalize(path: &str) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    for component in Path::new(&path.replace('\\', "/")).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::ParentDir => match parts.last().map(String::as_str) {
+                Some(last) if last != ".." => {
+                    parts.pop();
+                }
+                _ => parts.push(String::from("..")),
+            },
+            _ => {}
+        }
+    }
+    parts.join("/")
+}
+
+fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "eot" => "application/vnd.ms-fontobject",
+        "css" => "text/css",
+        "js" => "text/javascript",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn the_ids_of_a_chapter_are_prefixed() {
+        let html = r##"<h1><a class="header" href="#intro" id="intro">Intro</a></h1>
+<p>See <a href="first/nested.html#intro">the other intro</a>.<sup class="footnote-reference" id="-fnref-1"><a href="#-fn-1">1</a></sup></p>"##;
+        let mut parts = ChapterParts::default();
+
+        assert_eq!(
+            parts.add(Path::new("first/index.md"), html),
+            r##"<div id="first-index-md">
+<h1><a class="header" href="#first-index-md-intro" id="first-index-md-intro">Intro</a></h1>
+<p>See <a href="first/nested.html#intro">the other intro</a>.<sup class="footnote-reference" id="first-index-md-fnref-1"><a href="#first-index-md-fn-1">1</a></sup></p></div>
+"##
+        );
+    }
+
+    #[test]
+    fn the_ids_of_the_page_are_unique() {
+        let mut parts = ChapterParts::default();
+        let a = parts.add(
+            Path::new("a.md"),
+            r##"<h2 id="b">B</h2><a href="#b">B</a>"##,
+        );
+        let a_b = parts.add(Path::new("a-b.md"), r##"<h2 id="c">C</h2>"##);
+        let a_slash_b = parts.add(
+            Path::new("a/b.md"),
+            r##"<h2 id="c">C</h2><a href="#c">C</a>"##,
+        );
+
+        assert_eq!(
+            a,
+            "<div id=\"a-md\">\n<h2 id=\"a-md-b\">B</h2><a href=\"#a-md-b\">B</a></div>\n"
+        );
+        assert_eq!(
+            a_b,
+            "<div id=\"a-b-md\">\n<h2 id=\"a-b-md-c\">C</h2></div>\n"
+        );
+        assert_eq!(
+            a_slash_b,
+            "<div id=\"a-b-md-1\">\n<h2 id=\"a-b-md-1-c\">C</h2><a href=\"#a-b-md-1-c\">C</a></div>\n"
+        );
+        assert_eq!(
+            parts.link_chapters(r##"<a href="a-b.html#c">AB</a><a href="a/b.html#c">A/B</a>"##),
+            r##"<a href="#a-b-md-c">AB</a><a href="#a-b-md-1-c">A/B</a>"##
+        );
+    }
+
+    #[test]
+    fn links_to_chapters_go_to_their_part_of_the_page() {
+        let mut parts = ChapterParts::default();
+        parts.add(Path::new("index.md"), "");
+        parts.add(
+            Path::new("first/nested.md"),
+            r#"<h2 id="some-section">Some Section</h2>"#,
+        );
+        let html = r##"<a href="first/nested.html">Nested</a>
+<a href="second/../first/nested.html#some-section">Section</a>
+<a href="first/nested.html#nope">Nope</a>
+<a href="index.html#">Home</a>
+<a href="https://example.com/index.html">Elsewhere</a>
+<a href="missing.html#nope">Missing</a>
+<a href="#already-there">Here</a>"##;
+
+        assert_eq!(
+            parts.link_chapters(html),
+            r##"<a href="#first-nested-md">Nested</a>
+<a href="#first-nested-md-some-section">Section</a>
+<a href="#first-nested-md-nope">Nope</a>
+<a href="#index-md">Home</a>
+<a href="https://example.com/index.html">Elsewhere</a>
+<a href="missing.html#nope">Missing</a>
+<a href="#already-there">Here</a>"##
+        );
+    }
+
+    fn build_dir() -> TempDir {
+        let temp = tempfile::Builder::new().prefix("mdbook").tempdir().unwrap();
+        let files: &[(&str, &[u8])] = &[
+            ("css/general.css", b"body { color: black; }"),
+            (
+                "fonts/fonts.css",
+                b"@font-face { src: url('open-sans.woff2'), url(../FontAwesome/fonts/icons.eot?v=4#iefix); }",
+            ),
+            ("fonts/open-sans.woff2", b"font"),
+            ("FontAwesome/fonts/icons.eot", b"icons"),
+            ("book.js", b"if (a </script> b) {}"),
+            ("images/small.png", b"png"),
+            ("images/big.png", b"a much bigger image"),
+        ];
+        for (path, content) in files {
+            crate::utils::fs::write_file(temp.path(), path, content).unwrap();
+        }
+        temp
+    }
+
+    #[test]
+    fn the_assets_are_embedded() {
+        let temp = build_dir();
+        let html = r#"<link rel="stylesheet" href="css/general.css">
+<link rel="stylesheet" href="fonts/fonts.css" media="print">
+<link rel="stylesheet" href="https://cdn.example.com/katex.css">
+<script src="book.js" type="text/javascript" charset="utf-8"></script>
+<script async type="text/javascript" src="https://example.com/mathjax.js"></script>
+<img src="images/small.png" alt="Small"><img src="missing.png">"#;
+        let diagnostics = Diagnostics::new();
+
+        assert_eq!(
+            inline_assets(html, temp.path(), 10, &diagnostics),
+            r#"<style>
+body { color: black; }
+</style>
+<style media="print">
+@font-face { src: url("data:font/woff2;base64,Zm9udA=="), url("data:application/vnd.ms-fontobject;base64,aWNvbnM="); }
+</style>
+<link rel="stylesheet" href="https://cdn.example.com/katex.css">
+<script type="text/javascript" charset="utf-8">
+if (a <\/script> b) {}
+</script>
+<script async type="text/javascript" src="https://example.com/mathjax.js"></script>
+<img src="data:image/png;base64,cG5n" alt="Small"><img src="missing.png">"#
+        );
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn big_images_are_linked_with_a_warning() {
+        let temp = build_dir();
+        let diagnostics = Diagnostics::new();
+
+        let html = r#"<img src="images/big.png">"#;
+        assert_eq!(inline_assets(html, temp.path(), 10, &diagnostics), html);
+        assert_eq!(
+            diagnostics.warnings()[0].message,
+            "images/big.png is 19 bytes, more than output.html.single-file.max-image-size, \
+             so book.html links to it instead of embedding it"
+        );
+    }
+
+    #[test]
+    fn paths_are_normalized() {
+        assert_eq!(
+            normalize("./first/../second/./page.html"),
+            "second/page.html"
+        );
+        assert_eq!(normalize("../outside.png"), "../outside.png");
+        assert_eq!(normalize("/css/general.css"), "css/general.css");
+    }
+}
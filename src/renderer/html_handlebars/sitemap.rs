@@ -0,0 +1,144 @@
+//! Generating the `sitemap.xml` which lists every page of the book for search
+//! engines.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+
+use super::hbs_renderer::page_url;
+use crate::book::{Book, BookItem};
+use crate::errors::*;
+use crate::utils;
+
+/// The sitemap of the chapters of `book`, published at `site_url`. The
+/// `<lastmod>` of a page is the date of its `last_modified` entry or else the
+/// modification time of its source file in `src_dir`, when that's known.
+pub(super) fn create_sitemap(
+    book: &Book,
+    site_url: &str,
+    src_dir: &Path,
+    last_modified: &HashMap<PathBuf, String>,
+    pretty_urls: bool,
+) -> Result<String> {
+    let mut sitemap = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+
+    for item in book.iter() {
+        let ch = match *item {
+            BookItem::Chapter(ref ch) if !ch.is_draft_chapter() => ch,
+            _ => continue,
+        };
+        let path = ch.path.as_ref().unwrap();
+        let page = utils::fs::normalize_path(
+            utils::fs::chapter_page(path, pretty_urls)
+                .to_str()
+                .with_context(|| "Could not convert path to str")?,
+        );
+
+        let url = page_url(site_url, &page).with_context(|| {
+            format!(
+                "The sitemap needs an absolute `output.html.site-url`, got \"{}\"",
+                site_url
+            )
+        })?;
+        sitemap.push_str("  <url>\n");
+        sitemap.push_str(&format!("    <loc>{}</loc>\n", utils::escape_html(&url)));
+        let source = ch.source_path.as_ref().unwrap_or(path);
+        let modified = match last_modified.get(source) {
+            // The date of `2020-06-21T14:05:00+02:00`
+            Some(date) => date.get(..10).map(str::to_string),
+            None => modification_date(&src_dir.join(source)),
+        };
+        if let Some(modified) = modified {
+            sitemap.push_str(&format!("    <lastmod>{}</lastmod>\n", modified));
+        }
+        sitemap.push_str("  </url>\n");
+    }
+
+    sitemap.push_str("</urlset>\n");
+    Ok(sitemap)
+}
+
+/// The date a file was last modified, like `2020-06-21`.
+fn modification_date(path: &Path) -> Option<String> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    Some(
+        DateTime::<Utc>::from(modified)
+            .format("%Y-%m-%d")
+            .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::Chapter;
+
+    #[test]
+    fn sitemap_lists_the_chapters() {
+        let mut book = Book::new();
+        book.push_item(Chapter::new("Intro", String::new(), "index.md", Vec::new()));
+        book.push_item(BookItem::Separator);
+        book.push_item(Chapter::new_draft("Draft", Vec::new()));
+        book.push_item(Chapter::new(
+            "Q&A",
+            String::new(),
+            "guide/q&a.md",
+            Vec::new(),
+        ));
+
+        let sitemap = create_sitemap(
+            &book,
+            "https://example.com/book/",
+            Path::new(""),
+            &HashMap::new(),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            sitemap,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n  \
+             <url>\n    <loc>https://example.com/book/</loc>\n  </url>\n  \
+             <url>\n    <loc>https://example.com/book/guide/q&amp;a.html</loc>\n  </url>\n\
+             </urlset>\n"
+        );
+    }
+
+    #[test]
+    fn the_urls_are_percent_encoded() {
+        let mut book = Book::new();
+        book.push_item(Chapter::new(
+            "My Chapter",
+            String::new(),
+            "my chapter.md",
+            Vec::new(),
+        ));
+        book.push_item(Chapter::new("Café", String::new(), "café.md", Vec::new()));
+
+        let sitemap = create_sitemap(
+            &book,
+            "https://example.com/book",
+            Path::new(""),
+            &HashMap::new(),
+            true,
+        )
+        .unwrap();
+
+        assert!(sitemap.contains("<loc>https://example.com/book/my%20chapter/</loc>"));
+        assert!(sitemap.contains("<loc>https://example.com/book/caf%C3%A9/</loc>"));
+
+        let err = create_sitemap(&book, "/book/", Path::new(""), &HashMap::new(), true)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "The sitemap needs an absolute `output.html.site-url`, got \"/book/\""
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use crate::book::{BookItem, Chapter};
+use crate::config::WordCounting;
+use crate::errors::*;
+use crate::renderer::{RenderContext, Renderer};
+use crate::utils;
+
+use pulldown_cmark::{Event, Tag};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The version of the schema of `book.json`, increased when a key changes
+/// meaning or goes away.
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Default)]
+/// A renderer writing the structure of the book to a `book.json`: its
+/// chapters, as a tree, with where they come from and where the HTML renderer
+/// puts them, their word counts and headings, and the configuration of the
+/// book. For the tools which would otherwise have to scrape the HTML.
+///
+/// The keys of the JSON objects are sorted, so the file only changes where
+/// the book does.
+pub struct JsonRenderer;
+
+impl JsonRenderer {
+    /// Create a new `JsonRenderer` instance.
+    pub fn new() -> Self {
+        JsonRenderer
+    }
+}
+
+impl Renderer for JsonRenderer {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn render(&self, ctx: &RenderContext) -> Result<()> {
+        trace!("json render");
+        let manifest = manifest(ctx)?;
+        let mut json = serde_json::to_string_pretty(&manifest)?;
+        json.push('\n');
+        utils::fs::write_file(&ctx.destination, "book.json", json.as_bytes())
+    }
+}
+
+/// What the renderer writes to `book.json`.
+fn manifest(ctx: &RenderContext) -> Result<Value> {
+    let html_config = ctx.config.html_config().unwrap_or_default();
+    let options = Options {
+        pretty_urls: html_config.pretty_urls,
+        counting: html_config.reading_time.counting,
+    };
+    let config = serde_json::to_value(&ctx.config)
+        .with_context(|| "Unable to serialize the configuration of the book")?;
+
+    Ok(json!({
+        "version": MANIFEST_VERSION,
+        "config": config,
+        "items": items(&ctx.book.without_drafts().sections, &options),
+    }))
+}
+
+/// How the HTML renderer names the pages and counts the words.
+struct Options {
+    pretty_urls: bool,
+    counting: WordCounting,
+}
+
+/// The `items` of the table of contents.
+fn items(items: &[BookItem], options: &Options) -> Vec<Value> {
+    items
+        .iter()
+        .map(|item| match *item {
+            BookItem::Chapter(ref ch) => chapter(ch, options),
+            BookItem::Separator => json!({ "type": "separator" }),
+            BookItem::PartTitle(ref title) => json!({ "type": "part-title", "title": title }),
+        })
+        .collect()
+}
+
+fn chapter(ch: &Chapter, options: &Options) -> Value {
+    let output_path = ch
+        .path
+        .as_ref()
+        .map(|path| slashes(&utils::fs::chapter_page(path, options.pretty_urls)));
+
+    json!({
+        "type": "chapter",
+        "name": ch.name,
+        "number": ch.number.as_ref().map(ToString::to_string),
+        "path": ch.path.as_ref().map(|path| slashes(path)),
+        "source-path": ch.source_path.as_ref().map(|path| slashes(path)),
+        "output-path": output_path,
+        "draft": ch.is_draft_chapter(),
+        "word-count": utils::word_count(&ch.content, options.counting),
+        "headings": headings(&ch.content),
+        "sub-items": items(&ch.sub_items, options),
+    })
+}
+
+/// The headings of a chapter, with the IDs the HTML renderer gives them. Like
+/// it, the IDs come from the headings' rendered HTML.
+fn headings(content: &str) -> Vec<Value> {
+    let mut headings = Vec::new();
+    let mut id_counter = HashMap::new();
+    // the level, title and events of the open heading
+    let mut heading: Option<(u32, String, Vec<Event<'_>>)> = None;
+
+    for event in utils::new_cmark_parser(content) {
+        match event {
+            Event::Start(Tag::Heading(level)) => {
+                heading = Some((level, String::new(), Vec::new()));
+            }
+            Event::End(Tag::Heading(_)) => {
+                if let Some((level, title, events)) = heading.take() {
+                    let mut html = String::new();
+                    pulldown_cmark::html::push_html(&mut html, events.into_iter());
+                    let id = utils::unique_id_from_content(&html, &mut id_counter);
+                    headings.push(json!({ "level": level, "title": title, "id": id }));
+                }
+            }
+            event => {
+                if let Some((_, ref mut title, ref mut events)) = heading {
+                    if let Event::Text(ref text) | Event::Code(ref text) = event {
+                        title.push_str(text);
+                    }
+                    events.push(event);
+                }
+            }
+        }
+    }
+    headings
+}
+
+fn slashes(path: &Path) -> String {
+    utils::fs::normalize_path(&path.to_string_lossy())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::Book;
+    use crate::config::Config;
+
+    #[test]
+    fn headings_have_the_ids_of_the_html_renderer() {
+        let content =
+            "# The `mdbook` CLI\n\nText.\n\n## Usage\n\n```\n# not a heading\n```\n\n## Usage\n";
+
+        assert_eq!(
+            Value::from(headings(content)),
+            json!([
+                { "level": 1, "title": "The mdbook CLI", "id": "the-mdbook-cli" },
+                { "level": 2, "title": "Usage", "id": "usage" },
+                { "level": 2, "title": "Usage", "id": "usage-1" },
+            ])
+        );
+    }
+
+    #[test]
+    fn the_ids_of_headings_with_html_and_escapes_are_the_html_renderers() {
+        let content = "# Q&A <b>x</b> \\*star\\*\n";
+
+        assert_eq!(
+            Value::from(headings(content)),
+            json!([{ "level": 1, "title": "Q&A x *star*", "id": "qa-bxb-star" }])
+        );
+    }
+
+    #[test]
+    fn drafts_and_their_sub_chapters_are_left_out() {
+        let mut draft = Chapter::new("Draft", String::new(), "draft.md", Vec::new());
+        draft.metadata.insert(String::from("draft"), json!(true));
+        draft.sub_items.push(BookItem::Chapter(Chapter::new(
+            "Nested",
+            String::new(),
+            "draft/nested.md",
+            vec![String::from("Draft")],
+        )));
+        let mut book = Book::new();
+        book.push_item(Chapter::new("Intro", String::new(), "intro.md", Vec::new()));
+        book.push_item(draft);
+        book.push_item(Chapter::new_draft("Later", Vec::new()));
+        let ctx = RenderContext::new("/book", book, Config::default(), "/book/book");
+
+        let items = &manifest(&ctx).unwrap()["items"];
+        let names: Vec<_> = items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| (item["name"].clone(), item["draft"].clone()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (json!("Intro"), json!(false)),
+                (json!("Later"), json!(true)),
+            ]
+        );
+        assert_eq!(items[1]["output-path"], Value::Null);
+    }
+}
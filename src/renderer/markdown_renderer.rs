@@ -3,11 +3,16 @@ use crate::errors::*;
 use crate::renderer::{RenderContext, Renderer};
 use crate::utils;
 
+use pulldown_cmark::{Event, Parser, Tag};
 use std::fs;
+use std::path::Path;
 
 #[derive(Default)]
 /// A renderer to output the Markdown after the preprocessors have run. Mostly useful
-/// when debugging preprocessors.
+/// when debugging preprocessors, or to give a book to tools which read Markdown.
+///
+/// Each chapter is written to the same place as its source file, and the
+/// whole book to `combined.md`, in the order of the table of contents.
 pub struct MarkdownRenderer;
 
 impl MarkdownRenderer {
@@ -24,7 +29,7 @@ impl Renderer for MarkdownRenderer {
 
     fn render(&self, ctx: &RenderContext) -> Result<()> {
         let destination = &ctx.destination;
-        let book = &ctx.book;
+        let book = &ctx.book.without_drafts();
 
         if destination.exists() {
             utils::fs::remove_dir_content(destination)
@@ -32,21 +37,184 @@ impl Renderer for MarkdownRenderer {
         }
 
         trace!("markdown render");
-        for item in book.iter() {
-            if let BookItem::Chapter(ref ch) = *item {
-                if !ch.is_draft_chapter() {
-                    utils::fs::write_file(
-                        &ctx.destination,
-                        &ch.path.as_ref().expect("Checked path exists before"),
-                        ch.content.as_bytes(),
-                    )?;
+        write_chapters(&book.sections, destination)?;
+
+        let has_parts = book
+            .sections
+            .iter()
+            .find_map(|item| match *item {
+                BookItem::PartTitle(ref title) => Some(title),
+                _ => None,
+            })
+            .is_some();
+        let mut parts = Vec::new();
+        combine(&book.sections, if has_parts { 2 } else { 1 }, &mut parts);
+        utils::fs::write_file(
+            &ctx.destination,
+            "combined.md",
+            (parts.join("\n\n") + "\n").as_bytes(),
+        )?;
+
+        fs::create_dir_all(destination)
+            .with_context(|| "Unexpected error when constructing destination path")?;
+
+        Ok(())
+    }
+}
+
+/// Write the chapters of `items` which have a file, and their sub-chapters, to
+/// the same place as their source files.
+fn write_chapters(items: &[BookItem], destination: &Path) -> Result<()> {
+    for item in items {
+        if let BookItem::Chapter(ref ch) = *item {
+            if ch.is_draft_chapter() {
+                continue;
+            }
+            utils::fs::write_file(
+                destination,
+                ch.path.as_ref().expect("Checked path exists before"),
+                ch.content.as_bytes(),
+            )?;
+            write_chapters(&ch.sub_items, destination)?;
+        }
+    }
+    Ok(())
+}
+
+/// Add the Markdown of `items` to `parts`, with the chapters' headings
+/// starting at `level`.
+fn combine(items: &[BookItem], level: u32, parts: &mut Vec<String>) {
+    for item in items {
+        match *item {
+            BookItem::Chapter(ref ch) => {
+                if ch.is_draft_chapter() {
+                    continue;
                 }
+                let path = ch.path.as_ref().expect("Checked path exists before");
+                let content = rebase_links(&ch.content, path);
+                parts.push(normalize_headings(&content, &ch.name, level));
+                combine(&ch.sub_items, level + 1, parts);
             }
+            BookItem::Separator => parts.push(String::from("---")),
+            BookItem::PartTitle(ref title) => parts.push(format!("# {}", title)),
         }
+    }
+}
 
-        fs::create_dir_all(&destination)
-            .with_context(|| "Unexpected error when constructing destination path")?;
+/// Point the relative links and images of the chapter at `path` at the same
+/// files from the root of the book, where `combined.md` is.
+fn rebase_links(content: &str, path: &Path) -> String {
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => return content.to_string(),
+    };
+    utils::rewrite_links(content, |dest| {
+        if utils::is_relative_link(dest) {
+            Some(utils::resolve_link(dir, dest))
+        } else {
+            None
+        }
+    })
+}
 
-        Ok(())
+/// Shift the headings of a chapter so its first-level headings are at
+/// `level`, as ATX headings. A chapter without headings gets one with its
+/// `name`.
+fn normalize_headings(content: &str, name: &str, level: u32) -> String {
+    let content = content.trim();
+    let headings: Vec<_> = Parser::new(content)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::Heading(heading_level)) => Some((heading_level, range)),
+            _ => None,
+        })
+        .collect();
+    let top_level = match headings
+        .iter()
+        .map(|(heading_level, _)| *heading_level)
+        .min()
+    {
+        Some(top_level) => top_level,
+        None => return format!("{} {}\n\n{}", "#".repeat(level as usize), name, content),
+    };
+
+    let mut normalized = String::new();
+    let mut previous_end_index = 0;
+    for (heading_level, range) in headings {
+        let (start, end) = (range.start, range.end);
+        let new_level = (heading_level + level).saturating_sub(top_level).max(1);
+        let hashes = "#".repeat(new_level.min(6) as usize);
+        let heading = &content[start..end];
+        normalized.push_str(&content[previous_end_index..start]);
+
+        let text = heading.trim_start_matches(' ');
+        if text.starts_with('#') {
+            let old_hashes = text.len() - text.trim_start_matches('#').len();
+            normalized.push_str(&hashes);
+            normalized.push_str(&text[old_hashes..]);
+        } else {
+            // A setext heading: everything but the line underlining it
+            let lines: Vec<&str> = heading.trim_end().lines().collect();
+            let title: Vec<&str> = lines[..lines.len() - 1].iter().map(|l| l.trim()).collect();
+            normalized.push_str(&format!("{} {}", hashes, title.join(" ")));
+            if heading.ends_with('\n') {
+                normalized.push('\n');
+            }
+        }
+        previous_end_index = end;
+    }
+    normalized.push_str(&content[previous_end_index..]);
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headings_are_shifted_to_the_level_of_the_chapter() {
+        let content = "# Chapter\n\nText.\n\n## Section\n\n```\n# Not a heading\n```\n";
+
+        assert_eq!(normalize_headings(content, "Chapter", 1), content.trim());
+        assert_eq!(
+            normalize_headings(content, "Chapter", 2),
+            "## Chapter\n\nText.\n\n### Section\n\n```\n# Not a heading\n```"
+        );
+        assert_eq!(
+            normalize_headings("### Deep\n\n###### Deeper\n", "Deep", 2),
+            "## Deep\n\n##### Deeper"
+        );
+    }
+
+    #[test]
+    fn setext_headings_become_atx_headings() {
+        assert_eq!(
+            normalize_headings("Chapter\n=======\n\nSection\n-------\n\nText.\n", "X", 2),
+            "## Chapter\n\n### Section\n\nText."
+        );
+    }
+
+    #[test]
+    fn relative_links_are_rebased_to_the_root_of_the_book() {
+        let content = "[Up](../intro.md) [Here](./other.md#part) ![Image](img/a.png \"a.png\")\n\n\
+                       [![Badge](badge.svg)](https://example.com) [Out](../../out.md)\n\n\
+                       [Anchor](#part) [Root](/index.md) [Ref][r]\n\n[r]: ref.md\n";
+
+        assert_eq!(
+            rebase_links(content, Path::new("part/sub/chapter.md")),
+            "[Up](part/intro.md) [Here](part/sub/other.md#part) \
+             ![Image](part/sub/img/a.png \"a.png\")\n\n\
+             [![Badge](part/sub/badge.svg)](https://example.com) [Out](out.md)\n\n\
+             [Anchor](#part) [Root](/index.md) [Ref][r]\n\n[r]: part/sub/ref.md\n"
+        );
+        assert_eq!(rebase_links(content, Path::new("chapter.md")), content);
+    }
+
+    #[test]
+    fn chapters_without_headings_get_one() {
+        assert_eq!(
+            normalize_headings("Just text.\n", "A Chapter", 3),
+            "### A Chapter\n\nJust text."
+        );
     }
 }
@@ -11,21 +11,30 @@
 //! [For Developers]: https://rust-lang.github.io/mdBook/for_developers/index.html
 //! [RenderContext]: struct.RenderContext.html
 
-pub use self::html_handlebars::HtmlHandlebars;
+pub use self::html_handlebars::{
+    ChapterPostProcessor, CmdPostProcessor, HtmlHandlebars, LazyImages, PostProcessors,
+};
+pub use self::json_renderer::JsonRenderer;
 pub use self::markdown_renderer::MarkdownRenderer;
 
 mod html_handlebars;
+mod json_renderer;
 mod markdown_renderer;
 
 use shlex::Shlex;
+use std::error::Error as StdError;
+use std::fmt::{self, Display, Formatter};
 use std::fs;
 use std::io::{self, ErrorKind, Read};
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
 
 use crate::book::Book;
 use crate::config::Config;
+use crate::diagnostics::Diagnostics;
 use crate::errors::*;
+use crate::timings::Timings;
 use toml::Value;
 
 /// An arbitrary `mdbook` backend.
@@ -66,6 +75,22 @@ pub struct RenderContext {
     /// renderers to cache intermediate results, this directory is not
     /// guaranteed to be empty or even exist.
     pub destination: PathBuf,
+    /// Where the renderer reports its warnings, for the summary at the end of
+    /// the build. It isn't passed to the backends which are programs.
+    #[serde(skip)]
+    pub diagnostics: Diagnostics,
+    /// Where the renderer measures its work, with `build.timings`. It's
+    /// disabled otherwise.
+    #[serde(skip)]
+    pub timings: Timings,
+    /// What the HTML renderer runs on the pages of the chapters after the
+    /// ones of `[[output.html.post-process]]`.
+    #[serde(skip)]
+    pub post_processors: PostProcessors,
+    /// Where the renderer reports the archives of the rendered book it
+    /// writes, for the report of the build.
+    #[serde(skip)]
+    pub archives: Archives,
     #[serde(skip)]
     __non_exhaustive: (),
 }
@@ -83,6 +108,10 @@ impl RenderContext {
             version: crate::MDBOOK_VERSION.to_string(),
             root: root.into(),
             destination: destination.into(),
+            diagnostics: Diagnostics::new(),
+            timings: Timings::disabled(),
+            post_processors: PostProcessors::default(),
+            archives: Archives::default(),
             __non_exhaustive: (),
         }
     }
@@ -92,12 +121,47 @@ impl RenderContext {
         self.root.join(&self.config.book.src)
     }
 
+    /// Get the build directory's (absolute) path on disk. The `destination`
+    /// is in it, or is it when this is the only backend.
+    pub fn build_dir(&self) -> PathBuf {
+        self.root.join(&self.config.build.build_dir)
+    }
+
     /// Load a `RenderContext` from its JSON representation.
     pub fn from_json<R: Read>(reader: R) -> Result<RenderContext> {
         serde_json::from_reader(reader).with_context(|| "Unable to deserialize the `RenderContext`")
     }
 }
 
+/// The archives of the rendered book written during a build, like the ones of
+/// `output.html.archive`. Clones share the archives, like the ones of
+/// [`Diagnostics`](../diagnostics/struct.Diagnostics.html).
+#[derive(Debug, Clone, Default)]
+pub struct Archives(Arc<Mutex<Vec<PathBuf>>>);
+
+impl Archives {
+    /// Add the archive at `path`.
+    pub fn add(&self, path: PathBuf) {
+        self.0.lock().unwrap().push(path);
+    }
+
+    /// The archives, in the order they were written.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Forget the archives, for the next build.
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+impl PartialEq for Archives {
+    fn eq(&self, other: &Archives) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || self.paths() == other.paths()
+    }
+}
+
 /// A generic renderer which will shell out to an arbitrary executable.
 ///
 /// # Rendering Protocol
@@ -121,6 +185,12 @@ impl RenderContext {
 ///
 /// If the subprocess wishes to indicate that rendering failed, it should exit
 /// with a non-zero return code.
+///
+/// When the backend is marked as `optional` and its command can't be found,
+/// `render()` fails with a [`SkippedBackend`] error, which `MDBook::build()`
+/// reports as a skipped backend instead of a failed build.
+///
+/// [`SkippedBackend`]: struct.SkippedBackend.html
 #[derive(Debug, Clone, PartialEq)]
 pub struct CmdRenderer {
     name: String,
@@ -170,7 +240,10 @@ impl CmdRenderer {
                     but was marked as optional.",
                     self.cmd, self.name
                 );
-                return Ok(());
+                return Err(SkippedBackend {
+                    name: self.name.clone(),
+                }
+                .into());
             } else {
                 error!(
                     "The command `{}` wasn't found, is the `{}` backend installed?",
@@ -182,6 +255,24 @@ impl CmdRenderer {
     }
 }
 
+/// The error returned by a [`CmdRenderer`] for an optional backend which
+/// isn't installed.
+///
+/// [`CmdRenderer`]: struct.CmdRenderer.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkippedBackend {
+    /// The name of the backend which was skipped.
+    pub name: String,
+}
+
+impl Display for SkippedBackend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "The optional \"{}\" backend isn't installed", self.name)
+    }
+}
+
+impl StdError for SkippedBackend {}
+
 impl Renderer for CmdRenderer {
     fn name(&self) -> &str {
         &self.name
@@ -222,7 +313,7 @@ impl Renderer for CmdRenderer {
 
         if !status.success() {
             error!("Renderer exited with non-zero return code.");
-            bail!("The \"{}\" renderer failed", self.name);
+            bail!("The \"{}\" renderer failed ({})", self.name, status);
         } else {
             Ok(())
         }
@@ -15,6 +15,21 @@ function playpen_text(playpen) {
     }
 }
 
+// The code of a block as it's shown, without the lines which are hidden
+function copyable_text(pre_block) {
+    let code_block = pre_block.querySelector("code");
+
+    if (window.ace && code_block.classList.contains("editable")) {
+        return playpen_text(pre_block);
+    }
+
+    let shown = code_block.cloneNode(true);
+    if (shown.classList.contains("hide-boring")) {
+        Array.from(shown.querySelectorAll(".boring")).forEach(function (line) { line.remove(); });
+    }
+    return shown.textContent;
+}
+
 (function codeSnippets() {
     function fetch_with_timeout(url, options, timeout = 6000) {
         return Promise.race([
@@ -85,9 +100,12 @@ function playpen_text(playpen) {
             snippet_crates.push(item[1]);
         }
 
-        // check if all used crates are available on play.rust-lang.org
+        // check if all used crates are available on play.rust-lang.org, and
+        // allowed by `output.html.playpen.crates` if the book has a list
+        var allowed_crates = pre_block.dataset.crates ? pre_block.dataset.crates.split(" ") : null;
         var all_available = snippet_crates.every(function (elem) {
-            return playground_crates.indexOf(elem) > -1;
+            return playground_crates.indexOf(elem) > -1 &&
+                (!allowed_crates || allowed_crates.indexOf(elem) > -1);
         });
 
         if (all_available) {
@@ -107,9 +125,7 @@ function playpen_text(playpen) {
         }
 
         let text = playpen_text(code_block);
-        let classes = code_block.querySelector('code').classList;
-        let has_2018 = classes.contains("edition2018");
-        let edition = has_2018 ? "2018" : "2015";
+        let edition = code_block.dataset.edition || "2015";
 
         var params = {
             version: "stable",
@@ -137,11 +153,16 @@ function playpen_text(playpen) {
         .catch(error => result_block.innerText = "Playground Communication: " + error.message);
     }
 
-    // Syntax highlighting Configuration
-    hljs.configure({
-        tabReplace: '    ', // 4 spaces
-        languages: [],      // Languages used for auto-detection
-    });
+    // Without highlight.js, the code was highlighted when the book was built
+    var highlightBlock = function (block) {};
+    if (typeof hljs !== 'undefined') {
+        // Syntax highlighting Configuration
+        hljs.configure({
+            tabReplace: '    ', // 4 spaces
+            languages: [],      // Languages used for auto-detection
+        });
+        highlightBlock = function (block) { hljs.highlightBlock(block); };
+    }
 
     let code_nodes = Array
         .from(document.querySelectorAll('code'))
@@ -157,9 +178,9 @@ function playpen_text(playpen) {
 
         Array
             .from(document.querySelectorAll('code:not(.editable)'))
-            .forEach(function (block) { hljs.highlightBlock(block); });
+            .forEach(highlightBlock);
     } else {
-        code_nodes.forEach(function (block) { hljs.highlightBlock(block); });
+        code_nodes.forEach(highlightBlock);
     }
 
     // Adding the hljs class gives code blocks the color css
@@ -200,27 +221,25 @@ function playpen_text(playpen) {
         });
     });
 
-    if (window.playpen_copyable) {
-        Array.from(document.querySelectorAll('pre code')).forEach(function (block) {
-            var pre_block = block.parentNode;
-            if (!pre_block.classList.contains('playpen')) {
-                var buttons = pre_block.querySelector(".buttons");
-                if (!buttons) {
-                    buttons = document.createElement('div');
-                    buttons.className = 'buttons';
-                    pre_block.insertBefore(buttons, pre_block.firstChild);
-                }
+    Array.from(document.querySelectorAll('pre[data-copyable] > code')).forEach(function (block) {
+        var pre_block = block.parentNode;
+        if (!pre_block.classList.contains('playpen')) {
+            var buttons = pre_block.querySelector(".buttons");
+            if (!buttons) {
+                buttons = document.createElement('div');
+                buttons.className = 'buttons';
+                pre_block.insertBefore(buttons, pre_block.firstChild);
+            }
 
-                var clipButton = document.createElement('button');
-                clipButton.className = 'fa fa-copy clip-button';
-                clipButton.title = 'Copy to clipboard';
-                clipButton.setAttribute('aria-label', clipButton.title);
-                clipButton.innerHTML = '<i class=\"tooltiptext\"></i>';
+            var clipButton = document.createElement('button');
+            clipButton.className = 'fa fa-copy clip-button';
+            clipButton.title = 'Copy to clipboard';
+            clipButton.setAttribute('aria-label', clipButton.title);
+            clipButton.innerHTML = '<i class=\"tooltiptext\"></i>';
 
-                buttons.insertBefore(clipButton, buttons.firstChild);
-            }
-        });
-    }
+            buttons.insertBefore(clipButton, buttons.firstChild);
+        }
+    });
 
     // Process playpen code blocks
     Array.from(document.querySelectorAll(".playpen")).forEach(function (pre_block) {
@@ -243,7 +262,7 @@ function playpen_text(playpen) {
             run_rust_code(pre_block);
         });
 
-        if (window.playpen_copyable) {
+        if (pre_block.hasAttribute('data-copyable')) {
             var copyCodeClipboardButton = document.createElement('button');
             copyCodeClipboardButton.className = 'fa fa-copy clip-button';
             copyCodeClipboardButton.innerHTML = '<i class="tooltiptext"></i>';
@@ -537,15 +556,21 @@ function playpen_text(playpen) {
         if (e.altKey || e.ctrlKey || e.metaKey || e.shiftKey) { return; }
         if (window.search && window.search.hasFocus()) { return; }
 
+        // Right-to-left books read from right to left, so the next chapter
+        // is on the left
+        var rtl = document.documentElement.dir === 'rtl';
+        var nextKey = rtl ? 'ArrowLeft' : 'ArrowRight';
+        var previousKey = rtl ? 'ArrowRight' : 'ArrowLeft';
+
         switch (e.key) {
-            case 'ArrowRight':
+            case nextKey:
                 e.preventDefault();
                 var nextButton = document.querySelector('.nav-chapters.next');
                 if (nextButton) {
                     window.location.href = nextButton.href;
                 }
                 break;
-            case 'ArrowLeft':
+            case previousKey:
                 e.preventDefault();
                 var previousButton = document.querySelector('.nav-chapters.previous');
                 if (previousButton) {
@@ -573,7 +598,7 @@ function playpen_text(playpen) {
         text: function (trigger) {
             hideTooltip(trigger);
             let playpen = trigger.closest("pre");
-            return playpen_text(playpen);
+            return copyable_text(playpen);
         }
     });
 
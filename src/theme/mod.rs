@@ -7,10 +7,12 @@ pub mod fonts;
 #[cfg(feature = "search")]
 pub mod searcher;
 
-use std::fs::File;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
 use std::io::Read;
 use std::path::Path;
 
+use crate::diagnostics::{Diagnostic, DiagnosticKind, Diagnostics};
 use crate::errors::*;
 
 pub static INDEX: &[u8] = include_bytes!("index.hbs");
@@ -58,26 +60,59 @@ pub struct Theme {
     pub ayu_highlight_css: Vec<u8>,
     pub highlight_js: Vec<u8>,
     pub clipboard_js: Vec<u8>,
+    /// The other `*.hbs` files of the theme directory and of its `templates`
+    /// directory, by their name (like `footer` for `footer.hbs`). They're
+    /// registered as partials, and chapters can be rendered with them instead
+    /// of `index`.
+    pub templates: BTreeMap<String, Vec<u8>>,
 }
 
 impl Theme {
     /// Creates a `Theme` from the given `theme_dir`.
     /// If a file is found in the theme dir, it will override the default version.
+    ///
+    /// When a template is both in the theme directory and in its `templates`
+    /// directory, the one in `templates` is used. [`Theme::try_new`] makes it
+    /// an error instead.
     pub fn new<P: AsRef<Path>>(theme_dir: P) -> Self {
         let theme_dir = theme_dir.as_ref();
+        Theme::load(theme_dir, false, &Diagnostics::new()).unwrap_or_else(|e| {
+            warn!("Couldn't load the theme in {}: {}", theme_dir.display(), e);
+            Theme::default()
+        })
+    }
+
+    /// Like [`Theme::new`], but failing when a template is both in the theme
+    /// directory and in its `templates` directory.
+    pub fn try_new<P: AsRef<Path>>(theme_dir: P) -> Result<Self> {
+        Theme::try_new_with(theme_dir, &Diagnostics::new())
+    }
+
+    /// Like [`Theme::try_new`], reporting the files it couldn't read to the
+    /// `diagnostics` of the build.
+    pub fn try_new_with<P: AsRef<Path>>(theme_dir: P, diagnostics: &Diagnostics) -> Result<Self> {
+        Theme::load(theme_dir.as_ref(), true, diagnostics)
+    }
+
+    fn load(theme_dir: &Path, strict: bool, diagnostics: &Diagnostics) -> Result<Self> {
+        let unreadable = |filename: &Path, e: Error| {
+            diagnostics.warn(Diagnostic::new(
+                DiagnosticKind::Other,
+                format!("Couldn't load custom file, {}: {}", filename.display(), e),
+            ));
+        };
+
         let mut theme = Theme::default();
 
         // If the theme directory doesn't exist there's no point continuing...
         if !theme_dir.exists() || !theme_dir.is_dir() {
-            return theme;
+            return Ok(theme);
         }
 
-        // Check for individual files, if they exist copy them across
+        // Check for individual files, if they exist copy them across. The
+        // templates are below
         {
             let files = vec![
-                (theme_dir.join("index.hbs"), &mut theme.index),
-                (theme_dir.join("head.hbs"), &mut theme.head),
-                (theme_dir.join("header.hbs"), &mut theme.header),
                 (theme_dir.join("book.js"), &mut theme.js),
                 (theme_dir.join("css/chrome.css"), &mut theme.chrome_css),
                 (theme_dir.join("css/general.css"), &mut theme.general_css),
@@ -105,14 +140,69 @@ impl Theme {
                     continue;
                 }
 
-                if let Err(e) = load_file_contents(&filename, dest) {
-                    warn!("Couldn't load custom file, {}: {}", filename.display(), e);
+                match load_file_contents(&filename, dest) {
+                    Ok(_) => debug!("Using {} from the theme directory", filename.display()),
+                    Err(e) => unreadable(&filename, e),
+                }
+            }
+        }
+
+        let mut found = BTreeMap::new();
+        for dir in &[theme_dir.to_path_buf(), theme_dir.join("templates")] {
+            for (name, filename) in template_files(dir)? {
+                if let Some(other) = found.insert(name.clone(), filename.clone()) {
+                    let message = format!(
+                        "The template \"{}\" is both {} and {}",
+                        name,
+                        other.display(),
+                        filename.display()
+                    );
+                    if strict {
+                        bail!(message);
+                    }
+                    diagnostics.warn(Diagnostic::new(
+                        DiagnosticKind::Other,
+                        format!("{}, using the second one", message),
+                    ));
                 }
             }
         }
 
-        theme
+        for (name, filename) in found {
+            let dest = match name.as_str() {
+                "index" => &mut theme.index,
+                "head" => &mut theme.head,
+                "header" => &mut theme.header,
+                _ => theme.templates.entry(name).or_insert_with(Vec::new),
+            };
+            match load_file_contents(&filename, dest) {
+                Ok(_) => debug!("Using {} from the theme directory", filename.display()),
+                Err(e) => unreadable(&filename, e),
+            }
+        }
+
+        Ok(theme)
+    }
+}
+
+/// The `*.hbs` files directly in `dir`, by their name.
+fn template_files(dir: &Path) -> Result<Vec<(String, std::path::PathBuf)>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() || path.extension() != Some("hbs".as_ref()) {
+            continue;
+        }
+        if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+            files.push((name.to_string(), path.clone()));
+        }
     }
+    files.sort();
+    Ok(files)
 }
 
 impl Default for Theme {
@@ -132,6 +222,7 @@ impl Default for Theme {
             ayu_highlight_css: AYU_HIGHLIGHT_CSS.to_owned(),
             highlight_js: HIGHLIGHT_JS.to_owned(),
             clipboard_js: CLIPBOARD_JS.to_owned(),
+            templates: BTreeMap::new(),
         }
     }
 }
@@ -195,7 +286,7 @@ mod tests {
 
         // "touch" all of the special files so we have empty copies
         for file in &files {
-            File::create(&temp.path().join(file)).unwrap();
+            File::create(temp.path().join(file)).unwrap();
         }
 
         let got = Theme::new(temp.path());
@@ -215,8 +306,53 @@ mod tests {
             ayu_highlight_css: Vec::new(),
             highlight_js: Vec::new(),
             clipboard_js: Vec::new(),
+            templates: BTreeMap::new(),
         };
 
         assert_eq!(got, empty);
     }
+
+    #[test]
+    fn partial_theme_dir_only_overrides_its_own_files() {
+        let temp = TempFileBuilder::new().prefix("mdbook-").tempdir().unwrap();
+        fs::create_dir(temp.path().join("css")).unwrap();
+        fs::write(temp.path().join("css/general.css"), "body { color: red; }").unwrap();
+
+        let got = Theme::new(temp.path());
+
+        let should_be = Theme {
+            general_css: b"body { color: red; }".to_vec(),
+            ..Theme::default()
+        };
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn other_templates_are_loaded_by_name() {
+        let temp = TempFileBuilder::new().prefix("mdbook-").tempdir().unwrap();
+        fs::create_dir(temp.path().join("templates")).unwrap();
+        fs::write(temp.path().join("footer.hbs"), "footer").unwrap();
+        fs::write(temp.path().join("templates/landing.hbs"), "landing").unwrap();
+        fs::write(temp.path().join("templates/header.hbs"), "header").unwrap();
+        fs::write(temp.path().join("notes.txt"), "not a template").unwrap();
+
+        let got = Theme::try_new(temp.path()).unwrap();
+
+        assert_eq!(got.header, b"header");
+        assert_eq!(got.index, INDEX);
+        let names: Vec<_> = got.templates.keys().collect();
+        assert_eq!(names, ["footer", "landing"]);
+        assert_eq!(got.templates["landing"], b"landing");
+    }
+
+    #[test]
+    fn templates_in_both_directories_are_an_error() {
+        let temp = TempFileBuilder::new().prefix("mdbook-").tempdir().unwrap();
+        fs::create_dir(temp.path().join("templates")).unwrap();
+        fs::write(temp.path().join("footer.hbs"), "one").unwrap();
+        fs::write(temp.path().join("templates/footer.hbs"), "two").unwrap();
+
+        assert!(Theme::try_new(temp.path()).is_err());
+        assert_eq!(Theme::new(temp.path()).templates["footer"], b"two");
+    }
 }
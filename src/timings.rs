@@ -0,0 +1,264 @@
+//! How long the steps of a build take, for `mdbook build --timings`.
+//!
+//! The preprocessors and renderers get the [`Timings`] of the build in their
+//! contexts, and measure their work with [`Timings::time_chapter()`] and
+//! [`Timings::time()`]. Timings which aren't enabled don't measure anything,
+//! so the calls cost nothing in normal builds.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+/// The time spent on each chapter, by phase, and on the steps of the build
+/// which aren't about one chapter, like copying the theme. Clones share the
+/// times, so the threads rendering the chapters all add to the same report.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    inner: Option<Arc<Mutex<Inner>>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// The phases of the chapters, in the order they were first measured.
+    phases: Vec<String>,
+    chapters: HashMap<PathBuf, HashMap<String, Duration>>,
+    /// The steps of the build, in the order they were first measured.
+    steps: Vec<(String, Duration)>,
+}
+
+impl Timings {
+    /// Timings which measure nothing.
+    pub fn disabled() -> Timings {
+        Timings::default()
+    }
+
+    /// Timings which measure the calls to [`time()`](#method.time) and
+    /// [`time_chapter()`](#method.time_chapter).
+    pub fn enabled() -> Timings {
+        Timings {
+            inner: Some(Arc::new(Mutex::new(Inner::default()))),
+        }
+    }
+
+    /// Whether anything is measured.
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Run `f`, adding the time it takes to the `phase` of the chapter whose
+    /// source file is `chapter`.
+    pub fn time_chapter<T, F: FnOnce() -> T>(&self, chapter: &Path, phase: &str, f: F) -> T {
+        if self.inner.is_none() {
+            return f();
+        }
+        let start = Instant::now();
+        let result = f();
+        self.record_chapter(chapter, phase, start.elapsed());
+        result
+    }
+
+    /// Run `f`, adding the time it takes to the `step` of the build.
+    pub fn time<T, F: FnOnce() -> T>(&self, step: &str, f: F) -> T {
+        if self.inner.is_none() {
+            return f();
+        }
+        let start = Instant::now();
+        let result = f();
+        self.record(step, start.elapsed());
+        result
+    }
+
+    /// Add `duration` to the `phase` of a chapter.
+    pub fn record_chapter(&self, chapter: &Path, phase: &str, duration: Duration) {
+        let inner = match self.inner {
+            Some(ref inner) => inner,
+            None => return,
+        };
+        let mut inner = inner.lock().unwrap();
+        if !inner.phases.iter().any(|p| p == phase) {
+            inner.phases.push(phase.to_string());
+        }
+        *inner
+            .chapters
+            .entry(chapter.to_path_buf())
+            .or_default()
+            .entry(phase.to_string())
+            .or_default() += duration;
+    }
+
+    /// Add `duration` to a step of the build.
+    pub fn record(&self, step: &str, duration: Duration) {
+        let inner = match self.inner {
+            Some(ref inner) => inner,
+            None => return,
+        };
+        let mut inner = inner.lock().unwrap();
+        match inner.steps.iter_mut().find(|(name, _)| name == step) {
+            Some((_, total)) => *total += duration,
+            None => inner.steps.push((step.to_string(), duration)),
+        }
+    }
+
+    /// The chapters with their total time, the slowest first, and the phases
+    /// measured for chapters.
+    fn sorted_chapters(inner: &Inner) -> Vec<(&PathBuf, &HashMap<String, Duration>, Duration)> {
+        let mut chapters: Vec<_> = inner
+            .chapters
+            .iter()
+            .map(|(chapter, phases)| (chapter, phases, phases.values().sum::<Duration>()))
+            .collect();
+        chapters.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(b.0)));
+        chapters
+    }
+
+    /// A table of the time of each chapter, the slowest first, followed by
+    /// the steps of the build. It's empty when nothing was measured.
+    pub fn table(&self) -> String {
+        let inner = match self.inner {
+            Some(ref inner) => inner.lock().unwrap(),
+            None => return String::new(),
+        };
+        let chapters = Timings::sorted_chapters(&inner);
+
+        let names: Vec<String> = chapters
+            .iter()
+            .map(|(chapter, _, _)| chapter.display().to_string())
+            .chain(inner.steps.iter().map(|(step, _)| step.clone()))
+            .collect();
+        let width = names
+            .iter()
+            .map(|name| name.chars().count())
+            .chain(Some("chapter".len()))
+            .max()
+            .unwrap_or(0);
+        let mut lines = Vec::new();
+
+        if !chapters.is_empty() {
+            let mut header = format!("{:<width$}", "chapter", width = width);
+            for phase in inner.phases.iter().map(String::as_str).chain(Some("total")) {
+                header.push_str(&format!("  {:>12}", phase));
+            }
+            lines.push(header);
+
+            for (chapter, phases, total) in &chapters {
+                let mut line = format!("{:<width$}", chapter.display(), width = width);
+                for phase in &inner.phases {
+                    let duration = phases.get(phase).cloned().unwrap_or_default();
+                    line.push_str(&format!("  {:>12}", millis(duration)));
+                }
+                line.push_str(&format!("  {:>12}", millis(*total)));
+                lines.push(line);
+            }
+        }
+
+        for (step, duration) in &inner.steps {
+            lines.push(format!(
+                "{:<width$}  {:>12}",
+                step,
+                millis(*duration),
+                width = width
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// The times as JSON, in seconds, with the chapters in the order of the
+    /// [`table()`](#method.table).
+    pub fn to_json(&self) -> Value {
+        let inner = match self.inner {
+            Some(ref inner) => inner.lock().unwrap(),
+            None => return json!({ "chapters": [], "steps": [] }),
+        };
+
+        let chapters: Vec<Value> = Timings::sorted_chapters(&inner)
+            .into_iter()
+            .map(|(chapter, phases, total)| {
+                let phases: serde_json::Map<String, Value> = inner
+                    .phases
+                    .iter()
+                    .filter_map(|phase| {
+                        let duration = phases.get(phase)?;
+                        Some((phase.clone(), json!(duration.as_secs_f64())))
+                    })
+                    .collect();
+                json!({
+                    "chapter": chapter,
+                    "phases": phases,
+                    "total": total.as_secs_f64(),
+                })
+            })
+            .collect();
+        let steps: Vec<Value> = inner
+            .steps
+            .iter()
+            .map(|(step, duration)| json!({ "step": step, "duration": duration.as_secs_f64() }))
+            .collect();
+
+        json!({ "chapters": chapters, "steps": steps })
+    }
+}
+
+impl PartialEq for Timings {
+    fn eq(&self, other: &Timings) -> bool {
+        match (&self.inner, &other.inner) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_timings_measure_nothing() {
+        let timings = Timings::disabled();
+        assert_eq!(
+            timings.time_chapter(Path::new("intro.md"), "markdown", || 1),
+            1
+        );
+        timings.record("search index", Duration::from_millis(5));
+
+        assert!(!timings.is_enabled());
+        assert_eq!(timings.table(), "");
+        assert_eq!(timings.to_json(), json!({ "chapters": [], "steps": [] }));
+    }
+
+    #[test]
+    fn the_slowest_chapters_come_first() {
+        let timings = Timings::enabled();
+        let clone = timings.clone();
+        timings.record_chapter(Path::new("intro.md"), "markdown", Duration::from_millis(2));
+        clone.record_chapter(Path::new("guide.md"), "markdown", Duration::from_millis(3));
+        clone.record_chapter(Path::new("intro.md"), "template", Duration::from_millis(4));
+        timings.record_chapter(Path::new("intro.md"), "markdown", Duration::from_millis(1));
+        timings.record("search index", Duration::from_millis(10));
+
+        assert_eq!(
+            timings.table(),
+            "chapter           markdown      template         total\n\
+             intro.md             3.0ms         4.0ms         7.0ms\n\
+             guide.md             3.0ms         0.0ms         3.0ms\n\
+             search index        10.0ms"
+        );
+        assert_eq!(
+            timings.to_json(),
+            json!({
+                "chapters": [
+                    { "chapter": "intro.md", "phases": { "markdown": 0.003, "template": 0.004 }, "total": 0.007 },
+                    { "chapter": "guide.md", "phases": { "markdown": 0.003 }, "total": 0.003 },
+                ],
+                "steps": [{ "step": "search index", "duration": 0.01 }],
+            })
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Rendering GitHub-style alerts, blockquotes whose first line is a marker
+//! like `[!NOTE]`, as callouts.
+
+use pulldown_cmark::{CowStr, Event, Tag};
+
+use super::escape_html;
+use crate::config::Admonitions;
+
+/// The markers of the admonitions and their default titles.
+const KINDS: &[(&str, &str)] = &[
+    ("note", "Note"),
+    ("tip", "Tip"),
+    ("important", "Important"),
+    ("warning", "Warning"),
+    ("danger", "Danger"),
+];
+
+/// Replace the blockquotes starting with a known marker by a
+/// `<div class="admonition note">` with a title, leaving out the marker.
+/// Other blockquotes, including the ones nested in admonitions, are left
+/// alone.
+pub(super) fn render_admonitions<'a>(
+    events: Vec<Event<'a>>,
+    config: &Admonitions,
+) -> Vec<Event<'a>> {
+    let mut rendered = Vec::with_capacity(events.len());
+    // Whether each of the open blockquotes is an admonition
+    let mut quotes = Vec::new();
+
+    let mut ix = 0;
+    while ix < events.len() {
+        match events[ix] {
+            Event::Start(Tag::BlockQuote) => match marker(&events[ix + 1..]) {
+                Some((kind, len, rest_of_paragraph)) => {
+                    rendered.push(Event::Html(CowStr::from(format!(
+                        "<div class=\"admonition {}\">\n<p class=\"admonition-title\">{}</p>\n",
+                        kind,
+                        escape_html(title(config, kind))
+                    ))));
+                    if rest_of_paragraph {
+                        rendered.push(Event::Start(Tag::Paragraph));
+                    }
+                    quotes.push(true);
+                    ix += 1 + len;
+                    continue;
+                }
+                None => quotes.push(false),
+            },
+            Event::End(Tag::BlockQuote) => {
+                let admonition = quotes.pop() == Some(true);
+                if admonition {
+                    rendered.push(Event::Html(CowStr::from("</div>\n")));
+                    ix += 1;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        rendered.push(events[ix].clone());
+        ix += 1;
+    }
+
+    rendered
+}
+
+/// The kind of the admonition when the events of a blockquote start with a
+/// paragraph whose first line is a known marker, with the number of events of
+/// the marker and whether the paragraph goes on after it.
+fn marker(events: &[Event<'_>]) -> Option<(&'static str, usize, bool)> {
+    match events.first() {
+        Some(Event::Start(Tag::Paragraph)) => {}
+        _ => return None,
+    }
+
+    // The marker can be split into several text events, like `[`, `!NOTE`
+    // and `]`
+    let mut text = String::new();
+    let mut len = 1;
+    while let Some(Event::Text(part)) = events.get(len) {
+        text.push_str(part);
+        len += 1;
+    }
+
+    let rest_of_paragraph = match events.get(len) {
+        Some(Event::SoftBreak) | Some(Event::HardBreak) => true,
+        Some(Event::End(Tag::Paragraph)) => false,
+        _ => return None,
+    };
+
+    let text = text.trim();
+    if !text.starts_with("[!") || !text.ends_with(']') {
+        return None;
+    }
+    let name = text[2..text.len() - 1].to_lowercase();
+    KINDS
+        .iter()
+        .find(|&&(kind, _)| kind == name)
+        .map(|&(kind, _)| (kind, len + 1, rest_of_paragraph))
+}
+
+fn title<'a>(config: &'a Admonitions, kind: &str) -> &'a str {
+    match config.titles.get(kind) {
+        Some(title) => title,
+        None => KINDS
+            .iter()
+            .find(|&&(name, _)| name == kind)
+            .map_or("", |&(_, title)| title),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{render_markdown_with_links, LinkStyle, MarkdownOptions};
+    use crate::config::Admonitions;
+
+    fn render(markdown: &str, admonitions: Option<&Admonitions>) -> String {
+        let options = MarkdownOptions {
+            admonitions,
+            ..Default::default()
+        };
+        render_markdown_with_links(markdown, None, LinkStyle::Html, &options)
+    }
+
+    #[test]
+    fn admonitions_are_callouts() {
+        let config = Admonitions::default();
+
+        assert_eq!(
+            render("> [!NOTE]\n> Read this.\n", Some(&config)),
+            "<div class=\"admonition note\">\n<p class=\"admonition-title\">Note</p>\n\
+             <p>Read this.</p>\n</div>\n"
+        );
+        assert_eq!(
+            render("> [!warning]\n>\n> Careful.\n", Some(&config)),
+            "<div class=\"admonition warning\">\n<p class=\"admonition-title\">Warning</p>\n\
+             <p>Careful.</p>\n</div>\n"
+        );
+    }
+
+    #[test]
+    fn nested_blockquotes_and_code_blocks() {
+        let markdown = "> [!TIP]\n> Run:\n>\n> ```console\n> $ mdbook build\n> ```\n>\n\
+                        > > Just a quote.\n";
+
+        assert_eq!(
+            render(markdown, Some(&Admonitions::default())),
+            "<div class=\"admonition tip\">\n<p class=\"admonition-title\">Tip</p>\n\
+             <p>Run:</p>\n<pre><code class=\"language-console\">$ mdbook build\n</code></pre>\n\
+             <blockquote>\n<p>Just a quote.</p>\n</blockquote>\n</div>\n"
+        );
+
+        let markdown = "> > [!DANGER]\n> > Inner.\n";
+        assert_eq!(
+            render(markdown, Some(&Admonitions::default())),
+            "<blockquote>\n<div class=\"admonition danger\">\n\
+             <p class=\"admonition-title\">Danger</p>\n<p>Inner.</p>\n</div>\n</blockquote>\n"
+        );
+    }
+
+    #[test]
+    fn unknown_markers_and_disabled_admonitions_are_blockquotes() {
+        let config = Admonitions::default();
+        for &markdown in &[
+            "> [!FOO]\n> Text.\n",
+            "> [!NOTE] Text.\n",
+            "> Text [!NOTE]\n",
+        ] {
+            assert!(
+                render(markdown, Some(&config)).starts_with("<blockquote>\n<p>"),
+                "{:?}",
+                markdown
+            );
+        }
+
+        assert_eq!(
+            render("> [!NOTE]\n> Text.\n", None),
+            "<blockquote>\n<p>[!NOTE]\nText.</p>\n</blockquote>\n"
+        );
+    }
+
+    #[test]
+    fn titles_can_be_replaced() {
+        let mut config = Admonitions::default();
+        config
+            .titles
+            .insert(String::from("important"), String::from("Wichtig & <Neu>"));
+
+        assert!(render("> [!IMPORTANT]\n> Text.\n", Some(&config))
+            .contains("<p class=\"admonition-title\">Wichtig &amp; &lt;Neu&gt;</p>"));
+    }
+}
@@ -0,0 +1,259 @@
+//! The attributes of a code block, from the info string of its fence (like
+//! ```` ```rust,editable,no_run ````). The HTML renderer and `mdbook test`
+//! both use them, so they agree about which blocks are Rust and which can run.
+
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag};
+
+use crate::config::RustEdition;
+
+/// The attributes rustdoc knows. A block with nothing else is Rust.
+const RUSTDOC_ATTRIBUTES: &[&str] = &[
+    "no_run",
+    "ignore",
+    "edition2015",
+    "edition2018",
+    "edition2021",
+    "should_panic",
+    "compile_fail",
+    "allow_fail",
+    "test_harness",
+];
+
+/// The attributes only mdBook knows.
+const MDBOOK_ATTRIBUTES: &[&str] = &[
+    "editable",
+    "noplayground",
+    "noplaypen",
+    "mdbook-runnable",
+    "copyable",
+    "nocopy",
+    "line-numbers",
+    "no-line-numbers",
+    "noindex",
+];
+
+/// The parsed info string of a code block.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct CodeBlockAttributes {
+    /// The language, or `None` when the info string has none. An info string
+    /// with only rustdoc's attributes (like `no_run`) is Rust, like for
+    /// rustdoc.
+    pub(crate) language: Option<String>,
+    /// Editable in the browser, whatever `output.html.playpen.editable` is.
+    pub(crate) editable: bool,
+    /// Compiled by the tests, but the playpen has no run button.
+    pub(crate) no_run: bool,
+    /// Neither tested nor given a playpen, unless it's also `mdbook-runnable`.
+    pub(crate) ignore: bool,
+    /// No playpen at all (`noplayground`, or the older `noplaypen`).
+    pub(crate) noplayground: bool,
+    /// Runnable in the playpen even though it's `ignore`d by the tests.
+    pub(crate) runnable: bool,
+    /// The edition the block is forced to.
+    pub(crate) edition: Option<RustEdition>,
+    /// Whether the block gets a copy button (`copyable` or `nocopy`), instead
+    /// of `output.html.copyable-code`.
+    pub(crate) copyable: Option<bool>,
+    /// Whether the lines are numbered (`line-numbers` or `no-line-numbers`),
+    /// instead of `output.html.line-numbers`.
+    pub(crate) line_numbers: Option<bool>,
+    /// Left out of the search index (`noindex`).
+    pub(crate) noindex: bool,
+    /// Every other token, which ends up as a CSS class of the block.
+    pub(crate) classes: Vec<String>,
+}
+
+impl CodeBlockAttributes {
+    /// Parse an info string. The tokens may be separated by commas and
+    /// whitespace, in any order.
+    pub(crate) fn parse(info: &str) -> CodeBlockAttributes {
+        let mut attributes = CodeBlockAttributes::default();
+        let mut rustdoc_only = true;
+        let mut tokens = 0;
+
+        for token in info
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|token| !token.is_empty())
+        {
+            tokens += 1;
+            rustdoc_only &= RUSTDOC_ATTRIBUTES.contains(&token);
+            match token {
+                "editable" => attributes.editable = true,
+                "no_run" => attributes.no_run = true,
+                "ignore" => attributes.ignore = true,
+                "noplayground" | "noplaypen" => attributes.noplayground = true,
+                "mdbook-runnable" => attributes.runnable = true,
+                "edition2015" => attributes.edition = Some(RustEdition::E2015),
+                "edition2018" => attributes.edition = Some(RustEdition::E2018),
+                "edition2021" => attributes.edition = Some(RustEdition::E2021),
+                "copyable" => attributes.copyable = Some(true),
+                "nocopy" => attributes.copyable = Some(false),
+                "line-numbers" => attributes.line_numbers = Some(true),
+                "no-line-numbers" => attributes.line_numbers = Some(false),
+                "noindex" => attributes.noindex = true,
+                _ if attributes.language.is_none()
+                    && !RUSTDOC_ATTRIBUTES.contains(&token)
+                    && !MDBOOK_ATTRIBUTES.contains(&token) =>
+                {
+                    attributes.language = Some(token.to_string());
+                    continue;
+                }
+                _ => {}
+            }
+            attributes.classes.push(token.to_string());
+        }
+
+        if attributes.language.is_none() && tokens > 0 && rustdoc_only {
+            attributes.language = Some(String::from("rust"));
+        }
+        attributes
+    }
+
+    /// Parse the `class` of a rendered `<code>` block, like
+    /// `language-rust editable`.
+    pub(crate) fn from_classes(classes: &str) -> CodeBlockAttributes {
+        CodeBlockAttributes::parse(classes.trim_start().trim_start_matches("language-"))
+    }
+
+    /// Whether this is a block of Rust code.
+    pub(crate) fn is_rust(&self) -> bool {
+        match self.language {
+            Some(ref language) => language == "rust",
+            None => false,
+        }
+    }
+
+    /// Whether the block gets a playpen, with the buttons to copy and run it.
+    pub(crate) fn has_playpen(&self) -> bool {
+        self.is_rust() && (!(self.ignore || self.noplayground) || self.runnable)
+    }
+
+    /// Whether rustdoc compiles the block when the book is tested. Like
+    /// rustdoc, blocks without an info string are Rust too.
+    pub(crate) fn is_tested(&self) -> bool {
+        let no_info = self.language.is_none() && self.classes.is_empty();
+        (no_info || self.is_rust()) && !self.ignore
+    }
+
+    /// The classes of the rendered block: the language, then the other
+    /// tokens in their original order.
+    pub(crate) fn class_list(&self) -> String {
+        let mut classes = Vec::with_capacity(self.classes.len() + 1);
+        if let Some(ref language) = self.language {
+            classes.push(format!("language-{}", language));
+        }
+        classes.extend(self.classes.iter().cloned());
+        classes.join(" ")
+    }
+}
+
+/// The attributes of every code block of a chapter. Indented code blocks have
+/// no info string, so they're the same as a fence without one.
+pub(crate) fn code_blocks(markdown: &str) -> Vec<CodeBlockAttributes> {
+    Parser::new(markdown)
+        .filter_map(|event| match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                Some(CodeBlockAttributes::parse(&info))
+            }
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Indented)) => {
+                Some(CodeBlockAttributes::default())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_info_strings() {
+        let attributes = CodeBlockAttributes::parse("rust,editable,no_run,ignore");
+        assert_eq!(attributes.language.as_ref().unwrap(), "rust");
+        assert!(attributes.editable);
+        assert!(attributes.no_run);
+        assert!(attributes.ignore);
+        assert!(!attributes.noplayground);
+        assert_eq!(attributes.classes, vec!["editable", "no_run", "ignore"]);
+
+        let attributes = CodeBlockAttributes::parse("rust,edition2018,should_panic,my-class");
+        assert_eq!(attributes.edition, Some(RustEdition::E2018));
+        assert_eq!(
+            attributes.class_list(),
+            "language-rust edition2018 should_panic my-class"
+        );
+    }
+
+    #[test]
+    fn whitespace_and_order_dont_matter() {
+        let expected = CodeBlockAttributes::parse("rust,no_run,editable");
+        for info in &[
+            "rust, no_run ,editable",
+            "rust no_run editable",
+            "no_run,rust,editable",
+            " ,rust,,no_run,editable, ",
+        ] {
+            let mut attributes = CodeBlockAttributes::parse(info);
+            attributes.classes.sort();
+            let mut expected = expected.clone();
+            expected.classes.sort();
+            assert_eq!(attributes, expected, "{:?}", info);
+        }
+    }
+
+    #[test]
+    fn languages() {
+        assert_eq!(CodeBlockAttributes::parse("").language, None);
+        assert!(CodeBlockAttributes::parse("no_run").is_rust());
+        assert!(!CodeBlockAttributes::parse("nocopy").is_rust());
+        assert!(!CodeBlockAttributes::parse("nocopy").is_tested());
+        assert!(CodeBlockAttributes::parse("rust,noindex").is_rust());
+        assert!(CodeBlockAttributes::parse("rust,noindex").noindex);
+        assert!(!CodeBlockAttributes::parse("toml").is_rust());
+        assert!(!CodeBlockAttributes::parse("rusty").is_rust());
+        assert_eq!(
+            CodeBlockAttributes::parse("console,rust").class_list(),
+            "language-console rust"
+        );
+        assert_eq!(
+            CodeBlockAttributes::from_classes("language-rust edition2015").edition,
+            Some(RustEdition::E2015)
+        );
+    }
+
+    #[test]
+    fn playpens_and_tests_agree() {
+        let inputs = [
+            // info, playpen, tested
+            ("rust", true, true),
+            ("rust,no_run", true, true),
+            ("rust,ignore", false, false),
+            ("rust,ignore,mdbook-runnable", true, false),
+            ("rust,noplayground", false, true),
+            ("rust,noplaypen", false, true),
+            ("rust,editable", true, true),
+            ("text", false, false),
+            ("", false, true),
+        ];
+
+        for &(info, playpen, tested) in &inputs {
+            let attributes = CodeBlockAttributes::parse(info);
+            assert_eq!(attributes.has_playpen(), playpen, "{:?}", info);
+            assert_eq!(attributes.is_tested(), tested, "{:?}", info);
+        }
+    }
+
+    #[test]
+    fn code_blocks_of_a_chapter() {
+        let markdown =
+            "# Chapter\n\n```rust,editable\nfn main() {}\n```\n\n    indented\n\n```toml\n```\n";
+
+        let blocks = code_blocks(markdown);
+
+        assert_eq!(blocks.len(), 3);
+        assert!(blocks[0].editable);
+        assert_eq!(blocks[1], CodeBlockAttributes::default());
+        assert_eq!(blocks[2].language.as_ref().unwrap(), "toml");
+    }
+}
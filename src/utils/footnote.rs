@@ -0,0 +1,209 @@
+//! Rendering the footnotes of a chapter as a list at its end, with links back
+//! to where they're referenced.
+
+use std::collections::{HashMap, HashSet};
+
+use pulldown_cmark::{CowStr, Event, Tag};
+
+use super::{new_cmark_parser, normalize_id};
+
+/// Move the footnote definitions to a `<section class="footnotes">` at the
+/// end, numbered in the order they're first referenced, and give each of them
+/// a link back to its first reference. The IDs start with `prefix`, so the
+/// footnotes of different chapters don't collide on the print page.
+/// Definitions which aren't referenced are left out, and references to
+/// footnotes which aren't defined are left as text.
+pub(super) fn render_footnotes<'a>(events: Vec<Event<'a>>, prefix: &str) -> Vec<Event<'a>> {
+    let mut definitions = HashMap::new();
+    let mut body = Vec::with_capacity(events.len());
+    let mut definition: Option<(String, Vec<Event<'a>>)> = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                definition = Some((label.to_string(), Vec::new()));
+            }
+            Event::End(Tag::FootnoteDefinition(_)) => {
+                if let Some((label, events)) = definition.take() {
+                    definitions.entry(label).or_insert(events);
+                }
+            }
+            event => match definition {
+                Some((_, ref mut events)) => events.push(event),
+                None => body.push(event),
+            },
+        }
+    }
+
+    let mut footnotes = Footnotes {
+        prefix,
+        defined: definitions.keys().cloned().collect(),
+        order: Vec::new(),
+        references: HashMap::new(),
+    };
+    let mut rendered = footnotes.render_references(body);
+    if footnotes.order.is_empty() {
+        return rendered;
+    }
+
+    rendered.push(html("<section class=\"footnotes\">\n<ol>\n".to_string()));
+    // Definitions can reference footnotes too, which adds them to the end
+    let mut ix = 0;
+    while ix < footnotes.order.len() {
+        let label = footnotes.order[ix].clone();
+        let events = definitions.remove(&label).unwrap_or_default();
+        let mut events = footnotes.render_references(events);
+        let backref = html(format!(
+            " <a href=\"#{}\" class=\"footnote-backref\">↩</a>",
+            footnotes.reference_id(&label, 1)
+        ));
+        match events.last() {
+            Some(Event::End(Tag::Paragraph)) => {
+                let end = events.pop().unwrap();
+                events.push(backref);
+                events.push(end);
+            }
+            _ => events.push(backref),
+        }
+
+        rendered.push(html(format!(
+            "<li id=\"{}\">\n",
+            footnotes.definition_id(&label)
+        )));
+        rendered.extend(events);
+        rendered.push(html("</li>\n".to_string()));
+        ix += 1;
+    }
+    rendered.push(html("</ol>\n</section>\n".to_string()));
+
+    rendered
+}
+
+/// The labels of the footnotes which are referenced but never defined.
+pub fn undefined_footnotes(text: &str) -> Vec<String> {
+    let mut defined = HashSet::new();
+    let mut referenced = Vec::new();
+    for event in new_cmark_parser(text) {
+        match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                defined.insert(label.to_string());
+            }
+            Event::FootnoteReference(ref label)
+                if !referenced.iter().any(|known: &String| known == &**label) =>
+            {
+                referenced.push(label.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    referenced.retain(|label| !defined.contains(label));
+    referenced
+}
+
+struct Footnotes<'p> {
+    prefix: &'p str,
+    defined: HashSet<String>,
+    /// The labels of the referenced footnotes, by number.
+    order: Vec<String>,
+    /// How many times each footnote was referenced so far.
+    references: HashMap<String, usize>,
+}
+
+impl Footnotes<'_> {
+    fn render_references<'a>(&mut self, events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+        events
+            .into_iter()
+            .map(|event| match event {
+                Event::FootnoteReference(label) => {
+                    if !self.defined.contains(&*label) {
+                        return Event::Text(CowStr::from(format!("[^{}]", label)));
+                    }
+
+                    let label = label.to_string();
+                    if !self.references.contains_key(&label) {
+                        self.order.push(label.clone());
+                    }
+                    let count = {
+                        let count = self.references.entry(label.clone()).or_insert(0);
+                        *count += 1;
+                        *count
+                    };
+                    let number = self.order.iter().position(|l| *l == label).unwrap() + 1;
+
+                    html(format!(
+                        "<sup class=\"footnote-reference\" id=\"{}\"><a href=\"#{}\">{}</a></sup>",
+                        self.reference_id(&label, count),
+                        self.definition_id(&label),
+                        number
+                    ))
+                }
+                event => event,
+            })
+            .collect()
+    }
+
+    fn definition_id(&self, label: &str) -> String {
+        format!("{}-fn-{}", self.prefix, normalize_id(label))
+    }
+
+    /// The ID of a footnote's `count`th reference.
+    fn reference_id(&self, label: &str, count: usize) -> String {
+        if count == 1 {
+            format!("{}-fnref-{}", self.prefix, normalize_id(label))
+        } else {
+            format!("{}-fnref-{}-{}", self.prefix, normalize_id(label), count)
+        }
+    }
+}
+
+fn html<'a>(html: String) -> Event<'a> {
+    Event::Html(CowStr::from(html))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{render_markdown_with_links, LinkStyle, MarkdownOptions};
+    use super::*;
+
+    fn render(markdown: &str) -> String {
+        let options = MarkdownOptions {
+            footnotes: Some("intro"),
+            ..Default::default()
+        };
+        render_markdown_with_links(markdown, None, LinkStyle::Html, &options)
+    }
+
+    #[test]
+    fn footnotes_are_listed_at_the_end() {
+        let markdown = "Text[^note] and more[^other].\n\n\
+                        [^other]: The other one.\n\n\
+                        Last paragraph[^note].\n\n\
+                        [^note]: A note.\n";
+
+        assert_eq!(
+            render(markdown),
+            "<p>Text<sup class=\"footnote-reference\" id=\"intro-fnref-note\">\
+             <a href=\"#intro-fn-note\">1</a></sup> and more\
+             <sup class=\"footnote-reference\" id=\"intro-fnref-other\">\
+             <a href=\"#intro-fn-other\">2</a></sup>.</p>\n\
+             <p>Last paragraph<sup class=\"footnote-reference\" id=\"intro-fnref-note-2\">\
+             <a href=\"#intro-fn-note\">1</a></sup>.</p>\n\
+             <section class=\"footnotes\">\n<ol>\n\
+             <li id=\"intro-fn-note\">\n<p>A note. \
+             <a href=\"#intro-fnref-note\" class=\"footnote-backref\">↩</a></p>\n</li>\n\
+             <li id=\"intro-fn-other\">\n<p>The other one. \
+             <a href=\"#intro-fnref-other\" class=\"footnote-backref\">↩</a></p>\n</li>\n\
+             </ol>\n</section>\n"
+        );
+    }
+
+    #[test]
+    fn unreferenced_and_undefined_footnotes() {
+        let markdown = "Text[^missing].\n\n[^unused]: Never referenced.\n";
+
+        assert_eq!(render(markdown), "<p>Text[^missing].</p>\n");
+        assert_eq!(undefined_footnotes(markdown), vec!["missing"]);
+        assert!(undefined_footnotes("Text[^a].\n\n[^a]: A.\n").is_empty());
+    }
+}
@@ -1,8 +1,11 @@
 use crate::errors::*;
+use regex::Regex;
 use std::convert::Into;
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 /// Naively replaces any path seperator with a forward-slash '/'
 pub fn normalize_path(path: &str) -> String {
@@ -12,11 +15,105 @@ pub fn normalize_path(path: &str) -> String {
         .collect::<String>()
 }
 
-/// Write the given data to a file, creating it first if necessary
+/// Write the given data to a file, creating it first if necessary.
+///
+/// The data is written to a temporary file next to the destination, which is
+/// then renamed over it, so nobody reading the file (like the `serve` web
+/// server) ever sees a half written file.
 pub fn write_file<P: AsRef<Path>>(build_dir: &Path, filename: P, content: &[u8]) -> Result<()> {
+    static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
     let path = build_dir.join(filename);
+    let file_name = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => bail!("{} is not a file", path.display()),
+    };
+    // unique between processes and the threads rendering the book
+    let temp = path.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        file_name,
+        process::id(),
+        TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
 
-    create_file(&path)?.write_all(content).map_err(Into::into)
+    let written = create_file(&temp)
+        .and_then(|mut f| f.write_all(content).map_err(Into::into))
+        // `rename` replaces an existing file on every platform (on Windows it
+        // uses `MoveFileEx` with `MOVEFILE_REPLACE_EXISTING`)
+        .and_then(|_| fs::rename(&temp, &path).map_err(Into::into));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+
+    written.with_context(|| format!("Unable to write {}", path.display()))
+}
+
+/// Whether `path` is one of the temporary files of [`write_file()`], like
+/// `.index.html.1234-5.tmp`, which are left behind when mdBook is killed
+/// while it writes them.
+pub fn is_temp_file(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if name.starts_with('.') && name.ends_with(".tmp") => name,
+        _ => return false,
+    };
+    let stem = &name[..name.len() - ".tmp".len()];
+    match stem.rfind('.') {
+        Some(dot) if dot > 0 => {
+            let ids: Vec<_> = stem[dot + 1..].split('-').collect();
+            ids.len() == 2
+                && ids
+                    .iter()
+                    .all(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()))
+        }
+        _ => false,
+    }
+}
+
+/// Make `to` the same file as `from` with a hard link, so nothing is copied,
+/// or make it a copy where the file system has no hard links.
+pub(crate) fn link_or_copy(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if to.symlink_metadata().is_ok() {
+        fs::remove_file(to).with_context(|| format!("Unable to remove {}", to.display()))?;
+    }
+    if fs::hard_link(from, to).is_err() {
+        fs::copy(from, to)
+            .with_context(|| format!("Unable to copy {} to {}", from.display(), to.display()))?;
+    }
+    Ok(())
+}
+
+/// Turn a glob pattern for a path relative to some directory into a regex
+/// matching the path (with `/` as the separator). `*` and `?` don't match
+/// across directories, `**` does, and a pattern matching a directory matches
+/// everything inside it.
+pub(crate) fn glob_to_regex(pattern: &str) -> Result<Regex> {
+    let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    // any number of directories, including none
+                    chars.next();
+                    regex.push_str("(.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push_str("(/.*)?$");
+
+    Regex::new(&regex).with_context(|| format!("Invalid pattern \"{}\"", pattern))
 }
 
 /// Takes a path and returns a path containing just enough `../` to point to
@@ -55,6 +152,44 @@ pub fn path_to_root<P: Into<PathBuf>>(path: P) -> String {
         })
 }
 
+/// The page a chapter is rendered to, relative to the root of the book.
+///
+/// With pretty URLs every chapter gets a directory of its own, so
+/// `guide/usage.md` becomes `guide/usage/index.html` and can be linked to as
+/// `guide/usage/`. Chapters which are already called `index.md` stay where
+/// they are.
+///
+/// ```rust
+/// # use std::path::{Path, PathBuf};
+/// # use mdbook::utils::fs::chapter_page;
+/// let usage = Path::new("guide/usage.md");
+/// assert_eq!(chapter_page(usage, false), PathBuf::from("guide/usage.html"));
+/// assert_eq!(chapter_page(usage, true), PathBuf::from("guide/usage/index.html"));
+/// assert_eq!(chapter_page(Path::new("index.md"), true), PathBuf::from("index.html"));
+/// ```
+pub fn chapter_page(source: &Path, pretty_urls: bool) -> PathBuf {
+    let is_index = source.file_stem().map_or(false, |stem| stem == "index");
+    if pretty_urls && !is_index {
+        source.with_extension("").join("index.html")
+    } else {
+        source.with_extension("html")
+    }
+}
+
+/// The link to a chapter's page relative to the root of the book, with
+/// forward slashes. With pretty URLs, that's the page's directory.
+pub fn chapter_link(source: &Path, pretty_urls: bool) -> String {
+    let link = normalize_path(&chapter_page(source, pretty_urls).to_string_lossy());
+
+    if !pretty_urls {
+        link
+    } else if link == "index.html" {
+        String::from("./")
+    } else {
+        link.trim_end_matches("index.html").to_string()
+    }
+}
+
 /// This function creates a file and returns it. But before creating the file
 /// it checks every directory in the path to see if it exists,
 /// and if it does not it will be created.
@@ -73,14 +208,12 @@ pub fn create_file(path: &Path) -> Result<File> {
 
 /// Removes all the content of a directory but not the directory itself
 pub fn remove_dir_content(dir: &Path) -> Result<()> {
-    for item in fs::read_dir(dir)? {
-        if let Ok(item) = item {
-            let item = item.path();
-            if item.is_dir() {
-                fs::remove_dir_all(item)?;
-            } else {
-                fs::remove_file(item)?;
-            }
+    for item in fs::read_dir(dir)?.flatten() {
+        let item = item.path();
+        if item.is_dir() {
+            fs::remove_dir_all(item)?;
+        } else {
+            fs::remove_file(item)?;
         }
     }
     Ok(())
@@ -103,84 +236,274 @@ pub fn copy_files_except_ext(
         avoid_dir
     );
 
-    // Check that from and to are different
-    if from == to {
-        return Ok(());
-    }
+    let copy = DirCopy {
+        ext_blacklist,
+        ignore: Vec::new(),
+        avoid_dir: avoid_dir.map(PathBuf::as_path),
+        recursive,
+        keep_newer: false,
+        symlink_root: None,
+    };
+    copy.copy(from, to, Path::new(""))
+}
 
-    for entry in fs::read_dir(from)? {
-        let entry = entry?;
-        let metadata = entry.metadata()?;
+/// How [`copy_dir()`] copies a directory.
+///
+/// [`copy_dir()`]: fn.copy_dir.html
+#[derive(Debug, Clone, Default)]
+pub struct CopyOptions<'a> {
+    /// Skip the files with these extensions, like `"md"`.
+    pub ext_blacklist: &'a [&'a str],
+    /// Skip the files and directories matching these glob patterns, which are
+    /// relative to the copied directory and can use either `/` or `\` as the
+    /// separator. `*` and `?` don't match across directories, `**` does.
+    pub ignore: &'a [String],
+    /// Don't overwrite files at the destination which were changed after
+    /// their source file.
+    pub keep_newer: bool,
+    /// Copy symlinked files, as long as they point to something inside this
+    /// directory. Symlinks pointing anywhere else are an error, symlinked
+    /// directories are skipped, and without a `symlink_root` all symlinks are
+    /// skipped.
+    pub symlink_root: Option<&'a Path>,
+}
+
+/// Recursively copy the directory `from` to `to`, creating `to` and its
+/// sub-directories if necessary. Files at the destination which have the same
+/// size as their source and aren't older than it are assumed to be up to date,
+/// and left alone.
+pub fn copy_dir(from: &Path, to: &Path, options: &CopyOptions<'_>) -> Result<()> {
+    debug!(
+        "Copying all files from {} to {} ({:?})",
+        from.display(),
+        to.display(),
+        options
+    );
+
+    let ignore = options
+        .ignore
+        .iter()
+        .map(|pattern| glob_to_regex(&pattern.replace('\\', "/")))
+        .collect::<Result<_>>()?;
+    let symlink_root = match options.symlink_root {
+        Some(root) => Some(
+            root.canonicalize()
+                .with_context(|| format!("Unable to find {}", root.display()))?,
+        ),
+        None => None,
+    };
+    fs::create_dir_all(to).with_context(|| format!("Unable to create {}", to.display()))?;
+
+    let copy = DirCopy {
+        ext_blacklist: options.ext_blacklist,
+        ignore,
+        avoid_dir: None,
+        recursive: true,
+        keep_newer: options.keep_newer,
+        symlink_root,
+    };
+    copy.copy(from, to, Path::new(""))
+}
+
+struct DirCopy<'a> {
+    ext_blacklist: &'a [&'a str],
+    ignore: Vec<Regex>,
+    avoid_dir: Option<&'a Path>,
+    recursive: bool,
+    keep_newer: bool,
+    symlink_root: Option<PathBuf>,
+}
 
-        // If the entry is a dir and the recursive option is enabled, call itself
-        if metadata.is_dir() && recursive {
-            if entry.path() == to.to_path_buf() {
+impl DirCopy<'_> {
+    /// Copy the contents of `from`, which is `relative` to the directory being
+    /// copied, to `to`.
+    fn copy(&self, from: &Path, to: &Path, relative: &Path) -> Result<()> {
+        // Check that from and to are different
+        if from == to {
+            return Ok(());
+        }
+
+        let entries =
+            fs::read_dir(from).with_context(|| format!("Unable to read {}", from.display()))?;
+        for entry in entries {
+            let entry = entry.with_context(|| format!("Unable to read {}", from.display()))?;
+            let path = entry.path();
+            let relative = relative.join(entry.file_name());
+            if self.is_ignored(&relative) {
+                debug!("Ignoring {}", path.display());
                 continue;
             }
 
-            if let Some(avoid) = avoid_dir {
-                if entry.path() == *avoid {
+            let mut metadata = entry
+                .metadata()
+                .with_context(|| format!("Unable to read {}", path.display()))?;
+            if metadata.file_type().is_symlink() {
+                metadata = match self.follow_symlink(&path)? {
+                    Some(metadata) => metadata,
+                    None => continue,
+                };
+            }
+
+            let output = to.join(entry.file_name());
+
+            // If the entry is a dir and the recursive option is enabled, call itself
+            if metadata.is_dir() && self.recursive {
+                if path == to || self.avoid_dir == Some(path.as_path()) {
                     continue;
                 }
-            }
 
-            // check if output dir already exists
-            if !to.join(entry.file_name()).exists() {
-                fs::create_dir(&to.join(entry.file_name()))?;
-            }
+                // check if output dir already exists
+                if !output.exists() {
+                    fs::create_dir(&output)
+                        .with_context(|| format!("Unable to create {}", output.display()))?;
+                }
 
-            copy_files_except_ext(
-                &from.join(entry.file_name()),
-                &to.join(entry.file_name()),
-                true,
-                avoid_dir,
-                ext_blacklist,
-            )?;
-        } else if metadata.is_file() {
-            // Check if it is in the blacklist
-            if let Some(ext) = entry.path().extension() {
-                if ext_blacklist.contains(&ext.to_str().unwrap()) {
+                self.copy(&path, &output, &relative)?;
+            } else if metadata.is_file() {
+                // Check if it is in the blacklist
+                if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+                    if self.ext_blacklist.contains(&ext) {
+                        continue;
+                    }
+                }
+
+                if self.is_up_to_date(&metadata, &output) {
+                    debug!("{} is up to date", output.display());
                     continue;
                 }
+
+                debug!("Copying {:?} to {:?}", path, output);
+                fs::copy(&path, &output).with_context(|| {
+                    format!("Unable to copy {} to {}", path.display(), output.display())
+                })?;
             }
-            debug!(
-                "creating path for file: {:?}",
-                &to.join(
-                    entry
-                        .path()
-                        .file_name()
-                        .expect("a file should have a file name...")
-                )
-            );
-
-            debug!(
-                "Copying {:?} to {:?}",
-                entry.path(),
-                &to.join(
-                    entry
-                        .path()
-                        .file_name()
-                        .expect("a file should have a file name...")
-                )
-            );
-            fs::copy(
-                entry.path(),
-                &to.join(
-                    entry
-                        .path()
-                        .file_name()
-                        .expect("a file should have a file name..."),
-                ),
-            )?;
         }
+        Ok(())
     }
-    Ok(())
+
+    fn is_ignored(&self, relative: &Path) -> bool {
+        if self.ignore.is_empty() {
+            return false;
+        }
+
+        let relative = forward_slashes(relative);
+        self.ignore
+            .iter()
+            .any(|pattern| pattern.is_match(&relative))
+    }
+
+    /// The metadata of the file a symlink points to, if it should be copied.
+    fn follow_symlink(&self, path: &Path) -> Result<Option<fs::Metadata>> {
+        let root = match self.symlink_root {
+            Some(ref root) => root,
+            None => return Ok(None),
+        };
+        let target = match path.canonicalize() {
+            Ok(target) => target,
+            Err(_) => {
+                warn!("Ignoring the broken symlink {}", path.display());
+                return Ok(None);
+            }
+        };
+        ensure!(
+            target.starts_with(root),
+            "The symlink {} points outside of the book ({})",
+            path.display(),
+            target.display()
+        );
+
+        let metadata = fs::metadata(&target)
+            .with_context(|| format!("Unable to read {}", target.display()))?;
+        if metadata.is_dir() {
+            debug!("Not following the symlinked directory {}", path.display());
+            return Ok(None);
+        }
+        Ok(Some(metadata))
+    }
+
+    /// Whether a previous copy of a file at `output` can be kept, because it
+    /// has the same size as its source and isn't older than it (or is newer,
+    /// when keeping newer files).
+    fn is_up_to_date(&self, source: &fs::Metadata, output: &Path) -> bool {
+        let copy = match fs::metadata(output) {
+            Ok(ref copy) if copy.is_file() => copy.clone(),
+            _ => return false,
+        };
+
+        match (source.modified(), copy.modified()) {
+            (Ok(source_modified), Ok(copy_modified)) if self.keep_newer => {
+                copy_modified > source_modified
+                    || (copy_modified == source_modified && copy.len() == source.len())
+            }
+            (Ok(source_modified), Ok(copy_modified)) => {
+                copy.len() == source.len() && copy_modified >= source_modified
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A relative path with `/` as the separator, whatever the platform's is.
+pub(crate) fn forward_slashes(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
 }
 
 #[cfg(test)]
 mod tests {
-    use super::copy_files_except_ext;
+    use super::{
+        copy_dir, copy_files_except_ext, forward_slashes, glob_to_regex, is_temp_file, write_file,
+        CopyOptions,
+    };
     use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn glob_patterns() {
+        let matches = |pattern: &str, path: &str| glob_to_regex(pattern).unwrap().is_match(path);
+
+        assert!(matches("notes.md", "notes.md"));
+        assert!(!matches("notes.md", "other/notes.md"));
+        assert!(matches("drafts", "drafts/idea.md"));
+        assert!(matches("./drafts/", "drafts/deeper/idea.md"));
+        assert!(matches("*.draft.md", "intro.draft.md"));
+        assert!(!matches("*.draft.md", "guide/intro.draft.md"));
+        assert!(matches("**/*.draft.md", "guide/intro.draft.md"));
+        assert!(matches("**/*.draft.md", "intro.draft.md"));
+        assert!(matches("chapter_?.md", "chapter_1.md"));
+        assert!(!matches("chapter_?.md", "chapter_10.md"));
+        assert!(!matches("a+b.md", "aab.md"));
+    }
+
+    #[test]
+    fn write_file_replaces_existing_files_without_leaving_temp_files() {
+        let tmp = tempfile::TempDir::new().unwrap();
+
+        write_file(tmp.path(), "nested/dir/file.html", b"first").unwrap();
+        write_file(tmp.path(), "nested/dir/file.html", b"second").unwrap();
+
+        let dir = tmp.path().join("nested").join("dir");
+        let content = fs::read_to_string(dir.join("file.html")).unwrap();
+        assert_eq!(content, "second");
+        let entries: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, vec!["file.html"]);
+    }
+
+    #[test]
+    fn the_temp_files_of_write_file_are_recognized() {
+        assert!(is_temp_file(Path::new("book/.index.html.1234-0.tmp")));
+        assert!(is_temp_file(Path::new(".file.tar.gz.99-12.tmp")));
+        assert!(!is_temp_file(Path::new("book/index.html")));
+        assert!(!is_temp_file(Path::new("notes.1234-0.tmp")));
+        assert!(!is_temp_file(Path::new(".1234-0.tmp")));
+        assert!(!is_temp_file(Path::new(".backup.old.tmp")));
+        assert!(!is_temp_file(Path::new(".index.html.12-3-4.tmp")));
+    }
 
     #[test]
     fn copy_files_except_ext_test() {
@@ -190,57 +513,207 @@ mod tests {
         };
 
         // Create a couple of files
-        if let Err(err) = fs::File::create(&tmp.path().join("file.txt")) {
+        if let Err(err) = fs::File::create(tmp.path().join("file.txt")) {
             panic!("Could not create file.txt: {}", err);
         }
-        if let Err(err) = fs::File::create(&tmp.path().join("file.md")) {
+        if let Err(err) = fs::File::create(tmp.path().join("file.md")) {
             panic!("Could not create file.md: {}", err);
         }
-        if let Err(err) = fs::File::create(&tmp.path().join("file.png")) {
+        if let Err(err) = fs::File::create(tmp.path().join("file.png")) {
             panic!("Could not create file.png: {}", err);
         }
-        if let Err(err) = fs::create_dir(&tmp.path().join("sub_dir")) {
+        if let Err(err) = fs::create_dir(tmp.path().join("sub_dir")) {
             panic!("Could not create sub_dir: {}", err);
         }
-        if let Err(err) = fs::File::create(&tmp.path().join("sub_dir/file.png")) {
+        if let Err(err) = fs::File::create(tmp.path().join("sub_dir/file.png")) {
             panic!("Could not create sub_dir/file.png: {}", err);
         }
-        if let Err(err) = fs::create_dir(&tmp.path().join("sub_dir_exists")) {
+        if let Err(err) = fs::create_dir(tmp.path().join("sub_dir_exists")) {
             panic!("Could not create sub_dir_exists: {}", err);
         }
-        if let Err(err) = fs::File::create(&tmp.path().join("sub_dir_exists/file.txt")) {
+        if let Err(err) = fs::File::create(tmp.path().join("sub_dir_exists/file.txt")) {
             panic!("Could not create sub_dir_exists/file.txt: {}", err);
         }
 
         // Create output dir
-        if let Err(err) = fs::create_dir(&tmp.path().join("output")) {
+        if let Err(err) = fs::create_dir(tmp.path().join("output")) {
             panic!("Could not create output: {}", err);
         }
-        if let Err(err) = fs::create_dir(&tmp.path().join("output/sub_dir_exists")) {
+        if let Err(err) = fs::create_dir(tmp.path().join("output/sub_dir_exists")) {
             panic!("Could not create output/sub_dir_exists: {}", err);
         }
 
         if let Err(e) =
-            copy_files_except_ext(&tmp.path(), &tmp.path().join("output"), true, None, &["md"])
+            copy_files_except_ext(tmp.path(), &tmp.path().join("output"), true, None, &["md"])
         {
             panic!("Error while executing the function:\n{:?}", e);
         }
 
         // Check if the correct files where created
-        if !(&tmp.path().join("output/file.txt")).exists() {
+        if !tmp.path().join("output/file.txt").exists() {
             panic!("output/file.txt should exist")
         }
-        if (&tmp.path().join("output/file.md")).exists() {
+        if tmp.path().join("output/file.md").exists() {
             panic!("output/file.md should not exist")
         }
-        if !(&tmp.path().join("output/file.png")).exists() {
+        if !tmp.path().join("output/file.png").exists() {
             panic!("output/file.png should exist")
         }
-        if !(&tmp.path().join("output/sub_dir/file.png")).exists() {
+        if !tmp.path().join("output/sub_dir/file.png").exists() {
             panic!("output/sub_dir/file.png should exist")
         }
-        if !(&tmp.path().join("output/sub_dir_exists/file.txt")).exists() {
+        if !tmp.path().join("output/sub_dir_exists/file.txt").exists() {
             panic!("output/sub_dir/file.png should exist")
         }
     }
+
+    #[test]
+    fn up_to_date_files_are_not_copied_again() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (src, out) = (tmp.path().join("src"), tmp.path().join("out"));
+        write_file(&src, "image.png", b"image").unwrap();
+        write_file(&src, "notes.md", b"notes").unwrap();
+        let options = CopyOptions {
+            ext_blacklist: &["md"],
+            ..Default::default()
+        };
+
+        copy_dir(&src, &out, &options).unwrap();
+        assert_eq!(fs::read(out.join("image.png")).unwrap(), b"image");
+        assert!(!out.join("notes.md").exists());
+
+        // Same size and newer than the source, so it's left alone
+        fs::write(out.join("image.png"), "IMAGE").unwrap();
+        copy_dir(&src, &out, &options).unwrap();
+        assert_eq!(fs::read(out.join("image.png")).unwrap(), b"IMAGE");
+
+        fs::write(src.join("image.png"), "a bigger image").unwrap();
+        copy_dir(&src, &out, &options).unwrap();
+        assert_eq!(fs::read(out.join("image.png")).unwrap(), b"a bigger image");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_files_are_copied_unless_they_leave_the_root() {
+        use std::os::unix::fs::symlink;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let root = tmp.path().join("root");
+        let (src, out) = (root.join("src"), root.join("out"));
+        write_file(&root, "assets/logo.png", b"logo").unwrap();
+        fs::create_dir_all(&src).unwrap();
+        let options = CopyOptions {
+            symlink_root: Some(&root),
+            ..Default::default()
+        };
+        symlink(root.join("assets/logo.png"), src.join("logo.png")).unwrap();
+        symlink(root.join("assets"), src.join("assets")).unwrap();
+
+        copy_dir(&src, &out, &options).unwrap();
+        let logo = fs::symlink_metadata(out.join("logo.png")).unwrap();
+        assert!(logo.is_file());
+        assert_eq!(fs::read(out.join("logo.png")).unwrap(), b"logo");
+        assert!(!out.join("assets").exists());
+
+        write_file(tmp.path(), "secret.txt", b"secret").unwrap();
+        symlink(tmp.path().join("secret.txt"), src.join("secret.txt")).unwrap();
+        let err = copy_dir(&src, &out, &options).unwrap_err();
+        assert!(err.to_string().contains("points outside of the book"));
+        assert!(!out.join("secret.txt").exists());
+    }
+
+    #[test]
+    fn copied_directories_skip_ignored_paths() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (src, out) = (tmp.path().join("src"), tmp.path().join("out/nested"));
+        for file in &[
+            "index.html",
+            "book/index.html",
+            ".git/config",
+            "images/.git/config",
+            "images/logo.png",
+            "images/logo.svg",
+            "images/raw/logo.png",
+            ".nojekyll",
+        ] {
+            write_file(&src, file, b"").unwrap();
+        }
+        let ignore = vec![
+            String::from("book"),
+            String::from("**/.git"),
+            String::from("images\\*.svg"),
+            String::from("images/raw/"),
+        ];
+        let options = CopyOptions {
+            ignore: &ignore,
+            ..Default::default()
+        };
+
+        copy_dir(&src, &out, &options).unwrap();
+
+        let mut copied: Vec<_> = walkdir::WalkDir::new(&out)
+            .into_iter()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| forward_slashes(entry.path().strip_prefix(&out).unwrap()))
+            .collect();
+        copied.sort();
+        assert_eq!(copied, vec![".nojekyll", "images/logo.png", "index.html"]);
+    }
+
+    #[test]
+    fn newer_files_can_be_kept() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (src, out) = (tmp.path().join("src"), tmp.path().join("out"));
+        write_file(&src, "style.css", b"old").unwrap();
+        write_file(&out, "style.css", b"edited by hand").unwrap();
+        let an_hour_ago =
+            filetime::FileTime::from_unix_time(filetime::FileTime::now().unix_seconds() - 3600, 0);
+        filetime::set_file_mtime(src.join("style.css"), an_hour_ago).unwrap();
+
+        let options = CopyOptions {
+            keep_newer: true,
+            ..Default::default()
+        };
+        copy_dir(&src, &out, &options).unwrap();
+        assert_eq!(fs::read(out.join("style.css")).unwrap(), b"edited by hand");
+
+        copy_dir(&src, &out, &CopyOptions::default()).unwrap();
+        assert_eq!(fs::read(out.join("style.css")).unwrap(), b"old");
+    }
+
+    #[test]
+    fn copy_errors_name_the_path() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let missing = tmp.path().join("missing");
+
+        let err = copy_dir(&missing, &tmp.path().join("out"), &CopyOptions::default()).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            format!("Unable to read {}", missing.display())
+        );
+    }
+
+    #[test]
+    fn paths_are_matched_with_forward_slashes() {
+        assert_eq!(
+            forward_slashes(&Path::new("a").join("b").join("c.png")),
+            "a/b/c.png"
+        );
+        assert_eq!(forward_slashes(Path::new("a//b/")), "a/b");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_paths_are_matched_with_forward_slashes() {
+        assert_eq!(
+            forward_slashes(Path::new(r"images\raw\logo.png")),
+            "images/raw/logo.png"
+        );
+        assert_eq!(
+            forward_slashes(Path::new(r"images/raw\logo.png")),
+            "images/raw/logo.png"
+        );
+    }
 }
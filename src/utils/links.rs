@@ -0,0 +1,146 @@
+//! Changing where the links of a chapter's markdown go, in its text, for what
+//! passes the book on as markdown instead of rendering it.
+
+use std::path::{Component, Path};
+
+use pulldown_cmark::{Event, LinkType, Parser, Tag};
+use regex::Regex;
+use url::Url;
+
+/// Whether `dest` is relative to the chapter, so it's a file of the book: not
+/// an anchor of the chapter's page, or a link from the root of the site or
+/// with a scheme like `https`.
+pub(crate) fn is_relative_link(dest: &str) -> bool {
+    !dest.is_empty()
+        && !dest.starts_with('#')
+        && !dest.starts_with('/')
+        && Url::parse(dest).is_err()
+}
+
+/// Replace the destinations of the inline links and images of the markdown,
+/// and of its link reference definitions, by what `rewrite` returns for them.
+/// The ones it returns `None` for, and the ones written with escapes, are left
+/// as they are.
+pub(crate) fn rewrite_links<F>(content: &str, mut rewrite: F) -> String
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    lazy_static! {
+        static ref REFERENCE_DEFINITION: Regex =
+            Regex::new(r"(?m)^ {0,3}\[(?:[^\]\\]|\\.)+\]:[ \t]*<?([^\s<>]+)").unwrap();
+    }
+
+    let mut links = Vec::new();
+    let mut code = Vec::new();
+    for (event, range) in Parser::new(content).into_offset_iter() {
+        let dest = match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Code(_) => {
+                code.push(range);
+                continue;
+            }
+            Event::Start(Tag::Link(LinkType::Inline, dest, _))
+            | Event::Start(Tag::Image(LinkType::Inline, dest, _)) => dest,
+            _ => continue,
+        };
+        // The destination follows the last `](` of the link, before its title
+        let text = &content[range.clone()];
+        let start = text.rmatch_indices("](").find_map(|(index, _)| {
+            let after = &text[index + 2..];
+            let dest_start = after.len() - after.trim_start().trim_start_matches('<').len();
+            if after[dest_start..].starts_with(&*dest) {
+                Some(range.start + index + 2 + dest_start)
+            } else {
+                None
+            }
+        });
+        if let Some(start) = start {
+            if let Some(link) = rewrite(&dest) {
+                links.push((start, start + dest.len(), link));
+            }
+        }
+    }
+    for cap in REFERENCE_DEFINITION.captures_iter(content) {
+        let dest = cap.get(1).unwrap();
+        let in_code = code
+            .iter()
+            .any(|range| range.start <= dest.start() && dest.end() <= range.end);
+        if !in_code {
+            if let Some(link) = rewrite(dest.as_str()) {
+                links.push((dest.start(), dest.end(), link));
+            }
+        }
+    }
+    // The links around images come before them
+    links.sort_by_key(|&(start, _, _)| start);
+
+    let mut rewritten = String::new();
+    let mut previous_end_index = 0;
+    for (start, end, link) in links {
+        rewritten.push_str(&content[previous_end_index..start]);
+        rewritten.push_str(&link);
+        previous_end_index = end;
+    }
+    rewritten.push_str(&content[previous_end_index..]);
+    rewritten
+}
+
+/// The relative link `dest` of a chapter in `dir`, as a link from the root of
+/// the book, without `.` or `..` where it can do without them.
+pub(crate) fn resolve_link(dir: &Path, dest: &str) -> String {
+    let mut parts: Vec<&str> = dir
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect();
+    for part in dest.split('/') {
+        match part {
+            "." => {}
+            ".." if parts.last().map_or(false, |&last| last != "..") => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_links_and_images_are_rewritten_outside_code() {
+        let content = "[A](a.md \"a.md\") ![B](b.png) [![C](c.svg)](d.md)\n\n\
+                       `[E](e.md)` [F][f] [Escaped](e\\_f.md)\n\n\
+                       ```\n[f]: f.md\n```\n\n[f]: f.md\n";
+        let rewritten = rewrite_links(content, |dest| Some(format!("x/{}", dest)));
+
+        assert_eq!(
+            rewritten,
+            "[A](x/a.md \"a.md\") ![B](x/b.png) [![C](x/c.svg)](x/d.md)\n\n\
+             `[E](e.md)` [F][f] [Escaped](e\\_f.md)\n\n\
+             ```\n[f]: f.md\n```\n\n[f]: x/f.md\n"
+        );
+    }
+
+    #[test]
+    fn links_are_resolved_from_the_root() {
+        let dir = Path::new("part/sub");
+        assert_eq!(resolve_link(dir, "../intro.md"), "part/intro.md");
+        assert_eq!(resolve_link(dir, "./img/a.png"), "part/sub/img/a.png");
+        assert_eq!(resolve_link(dir, "../../../out.md"), "../out.md");
+        assert_eq!(resolve_link(Path::new(""), "a.md#b"), "a.md#b");
+    }
+
+    #[test]
+    fn only_links_to_files_of_the_book_are_relative() {
+        assert!(is_relative_link("../chapter.md"));
+        assert!(is_relative_link("img.png"));
+        assert!(!is_relative_link("#anchor"));
+        assert!(!is_relative_link("/index.html"));
+        assert!(!is_relative_link("https://example.com/README.md"));
+        assert!(!is_relative_link("mailto:someone@example.com"));
+    }
+}
@@ -0,0 +1,189 @@
+//! Keeping the `\\( ... \\)` and `\\[ ... \\]` math of a chapter, and the
+//! `$ ... $` and `$$ ... $$` one for KaTeX, away from the markdown parser, so
+//! the math renderer gets it as it was written.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use pulldown_cmark::{Event, Tag};
+use regex::{Captures, Regex};
+
+use super::{escape_html, new_cmark_parser};
+
+/// The characters around the placeholders of the math. The ones of Unicode's
+/// private use area, which don't mean anything to the markdown parser.
+const START: char = '\u{E000}';
+const END: char = '\u{E001}';
+
+/// Replace the math outside of the code with placeholders, which the parser
+/// leaves as they are, so underscores and asterisks in it aren't emphasis.
+/// Returns the text and the HTML of the math the placeholders stand for.
+///
+/// With `dollars`, the math between `$` and `$` or `$$` and `$$` is written
+/// with `\(` and `\)` or `\[` and `\]` too, so the bare dollar signs are
+/// never math. Inline math is on one line and doesn't start or end with a
+/// space, so amounts like `$5 and $10` aren't math, and `\$` is a dollar sign.
+pub(super) fn protect_math(text: &str, dollars: bool) -> (Cow<'_, str>, Vec<String>) {
+    lazy_static! {
+        static ref MATH: Regex = Regex::new(
+            r"(?s)\\\\\((?P<inline>.+?)\\\\\)|\\\\\[(?P<display>.+?)\\\\\]"
+        )
+        .unwrap();
+        static ref DOLLAR_MATH: Regex = Regex::new(
+            r"(?s)\\\\\((?P<inline>.+?)\\\\\)|\\\\\[(?P<display>.+?)\\\\\]|\\\$|\$\$(?P<dollar_display>.+?)\$\$|\$(?P<dollar_inline>[^\s$](?:[^$\n]*[^\s$\\])?)\$"
+        )
+        .unwrap();
+    }
+    let regex: &Regex = if dollars { &DOLLAR_MATH } else { &MATH };
+
+    let code: Vec<Range<usize>> = new_cmark_parser(text)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Code(_) => Some(range),
+            _ => None,
+        })
+        .collect();
+
+    let mut math = Vec::new();
+    let protected = regex.replace_all(text, |cap: &Captures<'_>| {
+        let mat = cap.get(0).unwrap();
+        let in_code = code
+            .iter()
+            .any(|range| range.start < mat.end() && mat.start() < range.end);
+        // An amount like `$5` right after the end isn't math either
+        let before_digit = cap.name("dollar_inline").is_some()
+            && text[mat.end()..].starts_with(|c: char| c.is_ascii_digit());
+        if in_code || before_digit || mat.as_str() == "\\$" {
+            return mat.as_str().to_string();
+        }
+
+        let html = if let Some(inline) = cap.name("inline") {
+            format!("\\({}\\)", escape_html(&unescape(inline.as_str())))
+        } else if let Some(display) = cap.name("display") {
+            format!("\\[{}\\]", escape_html(&unescape(display.as_str())))
+        } else if let Some(inline) = cap.name("dollar_inline") {
+            format!("\\({}\\)", escape_html(inline.as_str()))
+        } else {
+            format!("\\[{}\\]", escape_html(&cap["dollar_display"]))
+        };
+        math.push(html);
+        format!("{}{}{}", START, math.len() - 1, END)
+    });
+
+    (protected, math)
+}
+
+/// Put the math back in place of the placeholders of the rendered HTML.
+pub(super) fn restore_math(html: &str, math: &[String]) -> String {
+    lazy_static! {
+        static ref PLACEHOLDER: Regex = Regex::new("\u{E000}([0-9]+)\u{E001}").unwrap();
+    }
+
+    PLACEHOLDER
+        .replace_all(html, |cap: &Captures<'_>| {
+            cap[1]
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| math.get(index))
+                .cloned()
+                .unwrap_or_default()
+        })
+        .into_owned()
+}
+
+/// Remove the backslashes which escape punctuation, like markdown does, so
+/// the math of the books which escape its `\\` or `\_` stays the same.
+fn unescape(math: &str) -> String {
+    let mut unescaped = String::with_capacity(math.len());
+    let mut chars = math.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(&next) if c == '\\' && next.is_ascii_punctuation() => {
+                unescaped.push(next);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{render_markdown_with_links, LinkStyle, MarkdownOptions};
+
+    fn render(text: &str) -> String {
+        let options = MarkdownOptions {
+            math: true,
+            ..Default::default()
+        };
+        render_markdown_with_links(text, None, LinkStyle::Html, &options)
+    }
+
+    fn render_with_dollars(text: &str) -> String {
+        let options = MarkdownOptions {
+            math: true,
+            dollar_math: true,
+            ..Default::default()
+        };
+        render_markdown_with_links(text, None, LinkStyle::Html, &options)
+    }
+
+    #[test]
+    fn math_is_not_emphasized() {
+        let text = r"Where \\( x_i * y_i \\) and \\( a_j * b_j \\) are *products*.";
+
+        assert_eq!(
+            render(text),
+            "<p>Where \\( x_i * y_i \\) and \\( a_j * b_j \\) are <em>products</em>.</p>\n"
+        );
+    }
+
+    #[test]
+    fn block_math_keeps_its_escapes() {
+        let text = "\\\\[\n\\begin{cases} a_1 \\\\\\\\ b_{*} < c \\end{cases}\n\\\\]\n";
+
+        assert_eq!(
+            render(text),
+            "<p>\\[\n\\begin{cases} a_1 \\\\ b_{*} &lt; c \\end{cases}\n\\]</p>\n"
+        );
+    }
+
+    #[test]
+    fn math_in_code_is_left_alone() {
+        let text = "`\\\\( x_i \\\\)`\n\n```\n\\\\[ a_b \\\\]\n```\n";
+
+        assert_eq!(
+            render(text),
+            "<p><code>\\\\( x_i \\\\)</code></p>\n<pre><code>\\\\[ a_b \\\\]\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn dollar_math_is_written_with_backslashes() {
+        let text = "Where $x_i * y_i$ and\n\n$$\n\\sum_i a_i * b_i\n$$\n";
+
+        assert_eq!(
+            render_with_dollars(text),
+            "<p>Where \\(x_i * y_i\\) and</p>\n<p>\\[\n\\sum_i a_i * b_i\n\\]</p>\n"
+        );
+    }
+
+    #[test]
+    fn dollar_signs_of_amounts_are_not_math() {
+        let cases = [
+            ("It costs $5 and $10.", "<p>It costs $5 and $10.</p>\n"),
+            ("From $5 to$10.", "<p>From $5 to$10.</p>\n"),
+            ("A $ dollar $ sign.", "<p>A $ dollar $ sign.</p>\n"),
+            (r"Escaped \$x$ signs.", "<p>Escaped $x$ signs.</p>\n"),
+            ("Not $on\none line$.", "<p>Not $on\none line$.</p>\n"),
+            ("But $x$ is.", "<p>But \\(x\\) is.</p>\n"),
+            ("`$x$` is code.", "<p><code>$x$</code> is code.</p>\n"),
+        ];
+
+        for &(text, html) in cases.iter() {
+            assert_eq!(render_with_dollars(text), html, "{}", text);
+        }
+    }
+}
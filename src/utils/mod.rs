@@ -1,21 +1,44 @@
 #![allow(missing_docs)] // FIXME: Document this
 
+mod admonition;
+mod code_block;
+mod footnote;
 pub mod fs;
+mod links;
+mod math;
 mod string;
 pub(crate) mod toml_ext;
+mod word_count;
+use crate::config::Admonitions;
 use crate::errors::Error;
 use regex::Regex;
 
 use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Options, Parser, Tag};
 
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt::Write;
 use std::path::Path;
 
+pub(crate) use self::code_block::{code_blocks, CodeBlockAttributes};
+pub use self::footnote::undefined_footnotes;
+pub(crate) use self::links::{is_relative_link, resolve_link, rewrite_links};
+pub(crate) use self::string::has_anchor;
 pub use self::string::{
     take_anchored_lines, take_lines, take_rustdoc_include_anchored_lines,
     take_rustdoc_include_lines,
 };
+pub use self::word_count::{reading_time, word_count};
+
+/// Escape the characters of `text` which are special in HTML (and XML), for
+/// the text of an element or the value of a quoted attribute.
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
 
 /// Replaces multiple consecutive whitespace characters with a single space character.
 pub fn collapse_whitespace(text: &str) -> Cow<'_, str> {
@@ -42,6 +65,15 @@ pub fn normalize_id(content: &str) -> String {
         .collect::<String>()
 }
 
+/// Like `normalize_id()`, but only drops whitespace. Used for headings without
+/// any alphanumeric characters (e.g. emoji), so they still get a usable ID.
+fn normalize_id_keeping_symbols(content: &str) -> String {
+    content
+        .chars()
+        .map(|ch| if ch.is_whitespace() { '-' } else { ch })
+        .collect::<String>()
+}
+
 /// Generate an ID for use with anchors which is derived from a "normalised"
 /// string.
 pub fn id_from_content(content: &str) -> String {
@@ -68,7 +100,72 @@ pub fn id_from_content(content: &str) -> String {
     // Remove spaces and hashes indicating a header
     let trimmed = content.trim().trim_start_matches('#').trim();
 
-    normalize_id(trimmed)
+    match normalize_id(trimmed) {
+        ref id if !id.chars().any(char::is_alphanumeric) => normalize_id_keeping_symbols(trimmed),
+        id => id,
+    }
+}
+
+/// Generate an ID for a heading which is unique within a page.
+///
+/// `id_counter` keeps track of the IDs already handed out, so repeated
+/// headings get a numeric suffix (`usage`, `usage-1`, ...). The suffix is
+/// skipped over if another heading already produced that ID.
+pub fn unique_id_from_content(content: &str, id_counter: &mut HashMap<String, usize>) -> String {
+    let raw_id = id_from_content(content);
+
+    let mut id = raw_id.clone();
+    if let Some(&count) = id_counter.get(&raw_id) {
+        let mut count = count;
+        loop {
+            id = format!("{}-{}", raw_id, count);
+            count += 1;
+            if !id_counter.contains_key(&id) {
+                break;
+            }
+        }
+        id_counter.insert(raw_id, count);
+    }
+
+    id_counter.insert(id.clone(), 1);
+    id
+}
+
+/// How the links to other chapters are written.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LinkStyle {
+    /// Links to `chapter.md` go to `chapter.html`.
+    Html,
+    /// Links to `chapter.md` go to its directory, `chapter/`, for books with
+    /// pretty URLs (see [`fs::chapter_page`]). `nested` is whether the page
+    /// being rendered is in a directory of its own, one level below where its
+    /// source is, so its relative links need to go up one more level.
+    ///
+    /// [`fs::chapter_page`]: fs/fn.chapter_page.html
+    Pretty { nested: bool },
+}
+
+impl LinkStyle {
+    /// The link to a chapter, given the link to its source without the `.md`.
+    fn chapter_link(self, link: &str) -> String {
+        match self {
+            LinkStyle::Html => format!("{}.html", link),
+            LinkStyle::Pretty { .. } => {
+                let dir = if link == "index" {
+                    ""
+                } else if link.ends_with("/index") {
+                    &link[..link.len() - "index".len()]
+                } else {
+                    return format!("{}/", link);
+                };
+                if dir.is_empty() {
+                    String::from("./")
+                } else {
+                    dir.to_string()
+                }
+            }
+        }
+    }
 }
 
 /// Fix links to the correct location.
@@ -77,22 +174,22 @@ pub fn id_from_content(content: &str) -> String {
 ///
 /// `path` is the path to the page being rendered relative to the root of the
 /// book. This is used for the `print.html` page so that links on the print
-/// page go to the original location. Normal page rendering sets `path` to
-/// None. Ideally, print page links would link to anchors on the print page,
-/// but that is very difficult.
-fn adjust_links<'a>(event: Event<'a>, path: Option<&Path>) -> Event<'a> {
+/// page go to the original location, before the ones to the chapters are
+/// pointed at their parts of the print page. Normal page rendering sets
+/// `path` to None.
+fn adjust_links<'a>(event: Event<'a>, path: Option<&Path>, links: LinkStyle) -> Event<'a> {
     lazy_static! {
         static ref SCHEME_LINK: Regex = Regex::new(r"^[a-z][a-z0-9+.-]*:").unwrap();
-        static ref MD_LINK: Regex = Regex::new(r"(?P<link>.*)\.md(?P<anchor>#.*)?").unwrap();
+        static ref MD_LINK: Regex = Regex::new(r"^(?P<link>.*)\.md(?P<anchor>#.*)?$").unwrap();
     }
 
-    fn fix<'a>(dest: CowStr<'a>, path: Option<&Path>) -> CowStr<'a> {
+    fn fix<'a>(dest: CowStr<'a>, path: Option<&Path>, links: LinkStyle) -> CowStr<'a> {
         if dest.starts_with('#') {
             // Fragment-only link.
             if let Some(path) = path {
                 let mut base = path.display().to_string();
                 if base.ends_with(".md") {
-                    base.replace_range(base.len() - 3.., ".html");
+                    base = links.chapter_link(&base[..base.len() - 3]);
                 }
                 return format!("{}{}", base, dest).into();
             } else {
@@ -103,6 +200,9 @@ fn adjust_links<'a>(event: Event<'a>, path: Option<&Path>) -> Event<'a> {
         if !SCHEME_LINK.is_match(&dest) {
             // This is a relative link, adjust it as necessary.
             let mut fixed_link = String::new();
+            if links == (LinkStyle::Pretty { nested: true }) && !dest.starts_with('/') {
+                fixed_link.push_str("../");
+            }
             if let Some(path) = path {
                 let base = path
                     .parent()
@@ -115,8 +215,7 @@ fn adjust_links<'a>(event: Event<'a>, path: Option<&Path>) -> Event<'a> {
             }
 
             if let Some(caps) = MD_LINK.captures(&dest) {
-                fixed_link.push_str(&caps["link"]);
-                fixed_link.push_str(".html");
+                fixed_link.push_str(&links.chapter_link(&caps["link"]));
                 if let Some(anchor) = caps.name("anchor") {
                     fixed_link.push_str(anchor.as_str());
                 }
@@ -128,7 +227,7 @@ fn adjust_links<'a>(event: Event<'a>, path: Option<&Path>) -> Event<'a> {
         dest
     }
 
-    fn fix_html<'a>(html: CowStr<'a>, path: Option<&Path>) -> CowStr<'a> {
+    fn fix_html<'a>(html: CowStr<'a>, path: Option<&Path>, links: LinkStyle) -> CowStr<'a> {
         // This is a terrible hack, but should be reasonably reliable. Nobody
         // should ever parse a tag with a regex. However, there isn't anything
         // in Rust that I know of that is suitable for handling partial html
@@ -144,7 +243,7 @@ fn adjust_links<'a>(event: Event<'a>, path: Option<&Path>) -> Event<'a> {
 
         HTML_LINK
             .replace_all(&html, |caps: &regex::Captures<'_>| {
-                let fixed = fix(caps[2].into(), path);
+                let fixed = fix(caps[2].into(), path, links);
                 format!("{}{}\"", &caps[1], fixed)
             })
             .into_owned()
@@ -153,12 +252,12 @@ fn adjust_links<'a>(event: Event<'a>, path: Option<&Path>) -> Event<'a> {
 
     match event {
         Event::Start(Tag::Link(link_type, dest, title)) => {
-            Event::Start(Tag::Link(link_type, fix(dest, path), title))
+            Event::Start(Tag::Link(link_type, fix(dest, path, links), title))
         }
         Event::Start(Tag::Image(link_type, dest, title)) => {
-            Event::Start(Tag::Image(link_type, fix(dest, path), title))
+            Event::Start(Tag::Image(link_type, fix(dest, path, links), title))
         }
-        Event::Html(html) => Event::Html(fix_html(html, path)),
+        Event::Html(html) => Event::Html(fix_html(html, path, links)),
         _ => event,
     }
 }
@@ -169,30 +268,175 @@ pub fn render_markdown(text: &str, curly_quotes: bool) -> String {
 }
 
 pub fn new_cmark_parser(text: &str) -> Parser<'_> {
+    new_cmark_parser_with(text, &MarkdownOptions::default())
+}
+
+fn new_cmark_parser_with<'a>(text: &'a str, options: &MarkdownOptions<'_>) -> Parser<'a> {
     let mut opts = Options::empty();
-    opts.insert(Options::ENABLE_TABLES);
+    opts.set(Options::ENABLE_TABLES, options.tables);
     opts.insert(Options::ENABLE_FOOTNOTES);
-    opts.insert(Options::ENABLE_STRIKETHROUGH);
-    opts.insert(Options::ENABLE_TASKLISTS);
+    opts.set(Options::ENABLE_STRIKETHROUGH, options.strikethrough);
+    opts.set(Options::ENABLE_TASKLISTS, options.task_lists);
     Parser::new_ext(text, opts)
 }
 
 pub fn render_markdown_with_path(text: &str, curly_quotes: bool, path: Option<&Path>) -> String {
+    let options = MarkdownOptions {
+        curly_quotes,
+        ..Default::default()
+    };
+    render_markdown_with_links(text, path, LinkStyle::Html, &options)
+}
+
+/// How [`render_markdown_with_links`] renders markdown.
+///
+/// [`render_markdown_with_links`]: fn.render_markdown_with_links.html
+#[derive(Debug, Clone, Copy)]
+pub struct MarkdownOptions<'a> {
+    /// Convert straight quotes to curly ones. Default: `false`.
+    pub curly_quotes: bool,
+    /// Render GitHub-style tables. Default: `true`.
+    pub tables: bool,
+    /// Render `~~text~~` as struck through text. Default: `true`.
+    pub strikethrough: bool,
+    /// Render list items starting with `[ ]` or `[x]` as (disabled)
+    /// checkboxes. Default: `true`.
+    pub task_lists: bool,
+    /// Render the blockquotes starting with a marker like `[!NOTE]` as
+    /// callouts. Default: `None`.
+    pub admonitions: Option<&'a Admonitions>,
+    /// The start of the footnotes' IDs. With one, the footnotes are listed at
+    /// the end, with links back to their references. Default: `None`.
+    pub footnotes: Option<&'a str>,
+    /// Leave out the H1 the text starts with, if it starts with one.
+    /// Default: `false`.
+    pub strip_first_h1: bool,
+    /// How many levels the headings are shifted down, up to `<h6>`.
+    /// Default: `0`.
+    pub heading_offset: u32,
+    /// Pass the math between `\\(` and `\\)` or `\\[` and `\\]` through as it
+    /// is, for MathJax. Default: `false`.
+    pub math: bool,
+    /// With `math`, pass the math between `$` and `$` or `$$` and `$$` through
+    /// too, for KaTeX. Default: `false`.
+    pub dollar_math: bool,
+}
+
+impl Default for MarkdownOptions<'_> {
+    fn default() -> Self {
+        MarkdownOptions {
+            curly_quotes: false,
+            tables: true,
+            strikethrough: true,
+            task_lists: true,
+            admonitions: None,
+            footnotes: None,
+            strip_first_h1: false,
+            heading_offset: 0,
+            math: false,
+            dollar_math: false,
+        }
+    }
+}
+
+/// Render markdown like [`render_markdown_with_path`], writing the links to
+/// other chapters in the given style.
+///
+/// [`render_markdown_with_path`]: fn.render_markdown_with_path.html
+pub fn render_markdown_with_links(
+    text: &str,
+    path: Option<&Path>,
+    links: LinkStyle,
+    options: &MarkdownOptions<'_>,
+) -> String {
+    let (text, math) = if options.math {
+        math::protect_math(text, options.dollar_math)
+    } else {
+        (Cow::Borrowed(text), Vec::new())
+    };
     let mut s = String::with_capacity(text.len() * 3 / 2);
-    let p = new_cmark_parser(text);
-    let mut converter = EventQuoteConverter::new(curly_quotes);
-    let events = p
+    let mut events: Vec<_> = new_cmark_parser_with(&text, options).collect();
+    if options.strip_first_h1 {
+        if let Some(Event::Start(Tag::Heading(1))) = events.first() {
+            let end = events
+                .iter()
+                .position(|event| *event == Event::End(Tag::Heading(1)))
+                .unwrap_or(0);
+            events.drain(..=end);
+        }
+    }
+    if let Some(config) = options.admonitions {
+        if config.enable {
+            events = admonition::render_admonitions(events, config);
+        }
+    }
+    let mut converter = EventQuoteConverter::new(options.curly_quotes);
+    let events = events
+        .into_iter()
         .map(clean_codeblock_headers)
-        .map(|event| adjust_links(event, path))
+        .map(|event| adjust_links(event, path, links))
+        .map(|event| shift_heading(event, options.heading_offset))
         .map(|event| converter.convert(event));
 
-    html::push_html(&mut s, events);
-    s
+    match options.footnotes {
+        // After the links are adjusted, so the links to the footnotes stay
+        // on the same page
+        Some(prefix) => {
+            let events = footnote::render_footnotes(events.collect(), prefix);
+            html::push_html(&mut s, events.into_iter());
+        }
+        None => html::push_html(&mut s, events),
+    }
+    if math.is_empty() {
+        s
+    } else {
+        math::restore_math(&s, &math)
+    }
+}
+
+/// The text of the H1 the markdown starts with, if it starts with one.
+pub fn leading_h1(text: &str) -> Option<String> {
+    let mut events = new_cmark_parser(text);
+    match events.next() {
+        Some(Event::Start(Tag::Heading(1))) => {}
+        _ => return None,
+    }
+
+    let mut title = String::new();
+    for event in events {
+        match event {
+            Event::Text(text) | Event::Code(text) => title.push_str(&text),
+            Event::End(Tag::Heading(1)) => break,
+            _ => {}
+        }
+    }
+    let title = title.trim();
+    if title.is_empty() {
+        None
+    } else {
+        Some(title.to_string())
+    }
+}
+
+fn shift_heading(event: Event<'_>, offset: u32) -> Event<'_> {
+    match event {
+        Event::Start(Tag::Heading(level)) if offset > 0 => {
+            Event::Start(Tag::Heading((level + offset).min(6)))
+        }
+        Event::End(Tag::Heading(level)) if offset > 0 => {
+            Event::End(Tag::Heading((level + offset).min(6)))
+        }
+        _ => event,
+    }
 }
 
 struct EventQuoteConverter {
     enabled: bool,
     convert_text: bool,
+    /// Whether the next quote starts a new quotation. This is carried across
+    /// text events so `*emphasis*'s` or `` `code`'s `` still get an
+    /// apostrophe.
+    at_word_start: bool,
 }
 
 impl EventQuoteConverter {
@@ -200,6 +444,7 @@ impl EventQuoteConverter {
         EventQuoteConverter {
             enabled,
             convert_text: true,
+            at_word_start: true,
         }
     }
 
@@ -215,20 +460,48 @@ impl EventQuoteConverter {
             }
             Event::End(Tag::CodeBlock(_)) => {
                 self.convert_text = true;
+                self.at_word_start = true;
+                event
+            }
+            Event::Text(ref text) if self.convert_text => Event::Text(CowStr::from(
+                convert_quotes_with_context(text, &mut self.at_word_start),
+            )),
+            Event::Code(_) | Event::FootnoteReference(_) => {
+                self.at_word_start = false;
                 event
             }
-            Event::Text(ref text) if self.convert_text => {
-                Event::Text(CowStr::from(convert_quotes_to_curly(text)))
+            Event::Start(ref tag) | Event::End(ref tag) if !is_inline_tag(tag) => {
+                self.at_word_start = true;
+                event
+            }
+            Event::SoftBreak | Event::HardBreak | Event::Rule => {
+                self.at_word_start = true;
+                event
             }
             _ => event,
         }
     }
 }
 
+fn is_inline_tag(tag: &Tag<'_>) -> bool {
+    match tag {
+        Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..) | Tag::Image(..) => true,
+        _ => false,
+    }
+}
+
 fn clean_codeblock_headers(event: Event<'_>) -> Event<'_> {
     match event {
         Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref info))) => {
-            let info: String = info.chars().filter(|ch| !ch.is_whitespace()).collect();
+            // Whitespace between two attributes separates them like a comma
+            let mut cleaned = String::with_capacity(info.len());
+            for part in info.split_whitespace() {
+                if !cleaned.is_empty() && !cleaned.ends_with(',') && !part.starts_with(',') {
+                    cleaned.push(',');
+                }
+                cleaned.push_str(part);
+            }
+            let info = cleaned;
 
             Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(CowStr::from(info))))
         }
@@ -236,23 +509,29 @@ fn clean_codeblock_headers(event: Event<'_>) -> Event<'_> {
     }
 }
 
+#[cfg(test)]
 fn convert_quotes_to_curly(original_text: &str) -> String {
     // We'll consider the start to be "whitespace".
-    let mut preceded_by_whitespace = true;
+    convert_quotes_with_context(original_text, &mut true)
+}
 
+/// Convert quotes, where `at_word_start` says whether the text is preceded by
+/// whitespace (or opening punctuation). It is updated to reflect the end of
+/// `original_text`.
+fn convert_quotes_with_context(original_text: &str, at_word_start: &mut bool) -> String {
     original_text
         .chars()
         .map(|original_char| {
             let converted_char = match original_char {
                 '\'' => {
-                    if preceded_by_whitespace {
+                    if *at_word_start {
                         '‘'
                     } else {
                         '’'
                     }
                 }
                 '"' => {
-                    if preceded_by_whitespace {
+                    if *at_word_start {
                         '“'
                     } else {
                         '”'
@@ -261,7 +540,14 @@ fn convert_quotes_to_curly(original_text: &str) -> String {
                 _ => original_char,
             };
 
-            preceded_by_whitespace = original_char.is_whitespace();
+            *at_word_start = original_char.is_whitespace()
+                || match original_char {
+                    '(' | '[' | '{' | '—' | '–' | '/' => true,
+                    // A quote directly after an opening quote opens a nested
+                    // quotation, e.g. `"'Hi,' she said"`.
+                    '\'' | '"' => *at_word_start,
+                    _ => false,
+                };
 
             converted_char
         })
@@ -308,6 +594,72 @@ mod tests {
             );
         }
 
+        #[test]
+        fn it_can_adjust_relative_markdown_links() {
+            assert_eq!(
+                render_markdown("[nested](./sub/chapter.md#anchor)", false),
+                "<p><a href=\"./sub/chapter.html#anchor\">nested</a></p>\n"
+            );
+            assert_eq!(
+                render_markdown("[parent](../other/chapter.md)", false),
+                "<p><a href=\"../other/chapter.html\">parent</a></p>\n"
+            );
+        }
+
+        #[test]
+        fn links_outside_the_book_are_left_alone() {
+            assert_eq!(
+                render_markdown(
+                    "[repo](https://github.com/rust-lang/mdBook/README.md)",
+                    false
+                ),
+                "<p><a href=\"https://github.com/rust-lang/mdBook/README.md\">repo</a></p>\n"
+            );
+            assert_eq!(
+                render_markdown("[file](file:///home/user/notes.md)", false),
+                "<p><a href=\"file:///home/user/notes.md\">file</a></p>\n"
+            );
+            assert_eq!(
+                render_markdown("[mdx](component.mdx)", false),
+                "<p><a href=\"component.mdx\">mdx</a></p>\n"
+            );
+            assert_eq!(
+                render_markdown("[archive](notes.md.tar.gz)", false),
+                "<p><a href=\"notes.md.tar.gz\">archive</a></p>\n"
+            );
+        }
+
+        #[test]
+        fn pretty_links_go_to_the_directory_of_chapters() {
+            use super::super::{render_markdown_with_links, LinkStyle};
+            use std::path::Path;
+
+            let text = "[a](a.md#x) [up](../b.md) [index](sub/index.md) [root](index.md) \
+                        ![img](img.png) [frag](#y) [abs](/c.html)";
+            let render =
+                |path, links| render_markdown_with_links(text, path, links, &Default::default());
+
+            assert_eq!(
+                render(None, LinkStyle::Pretty { nested: false }),
+                "<p><a href=\"a/#x\">a</a> <a href=\"../b/\">up</a> <a href=\"sub/\">index</a> \
+                 <a href=\"./\">root</a> <img src=\"img.png\" alt=\"img\" /> \
+                 <a href=\"#y\">frag</a> <a href=\"/c.html\">abs</a></p>\n"
+            );
+            assert_eq!(
+                render(None, LinkStyle::Pretty { nested: true }),
+                "<p><a href=\"../a/#x\">a</a> <a href=\"../../b/\">up</a> \
+                 <a href=\"../sub/\">index</a> <a href=\".././\">root</a> \
+                 <img src=\"../img.png\" alt=\"img\" /> <a href=\"#y\">frag</a> \
+                 <a href=\"/c.html\">abs</a></p>\n"
+            );
+            // The print page
+            assert!(render(
+                Some(Path::new("guide/page.md")),
+                LinkStyle::Pretty { nested: false }
+            )
+            .contains("<a href=\"guide/page/#y\">frag</a>"));
+        }
+
         #[test]
         fn it_can_keep_quotes_straight() {
             assert_eq!(render_markdown("'one'", false), "<p>'one'</p>\n");
@@ -329,6 +681,28 @@ mod tests {
             assert_eq!(render_markdown(input, true), expected);
         }
 
+        #[test]
+        fn curly_quotes_are_only_applied_when_enabled() {
+            let input = r#"Don't "quote" `"foo"` or <a title="x">links</a>"#;
+
+            assert_eq!(
+                render_markdown(input, true),
+                "<p>Don’t “quote” <code>&quot;foo&quot;</code> or <a title=\"x\">links</a></p>\n"
+            );
+            assert_eq!(
+                render_markdown(input, false),
+                "<p>Don't &quot;quote&quot; <code>&quot;foo&quot;</code> or <a title=\"x\">links</a></p>\n"
+            );
+        }
+
+        #[test]
+        fn curly_quotes_follow_inline_markup() {
+            assert_eq!(
+                render_markdown("`Vec`'s and *mdBook*'s", true),
+                "<p><code>Vec</code>’s and <em>mdBook</em>’s</p>\n"
+            );
+        }
+
         #[test]
         fn whitespace_outside_of_codeblock_header_is_preserved() {
             let input = r#"
@@ -378,6 +752,18 @@ more text with spaces
             assert_eq!(render_markdown(input, true), expected);
         }
 
+        #[test]
+        fn rust_code_block_properties_separated_by_whitespace() {
+            let input = r#"
+```rust editable  no_run
+```
+"#;
+
+            let expected = r#"<pre><code class="language-rust,editable,no_run"></code></pre>
+"#;
+            assert_eq!(render_markdown(input, false), expected);
+        }
+
         #[test]
         fn rust_code_block_without_properties_has_proper_html_class() {
             let input = r#"
@@ -397,10 +783,130 @@ more text with spaces
             assert_eq!(render_markdown(input, false), expected);
             assert_eq!(render_markdown(input, true), expected);
         }
+
+        #[test]
+        fn tables_keep_their_alignment_and_inline_markup() {
+            let input = "| Left | Center | Right | None |\n\
+                         |:-----|:------:|------:|------|\n\
+                         | *em* | `code` | **strong** ~~struck~~ | [link](other.md) |\n";
+
+            assert_eq!(
+                render_markdown(input, false),
+                "<table><thead><tr><th align=\"left\">Left</th><th align=\"center\">Center</th>\
+                 <th align=\"right\">Right</th><th>None</th></tr></thead><tbody>\n\
+                 <tr><td align=\"left\"><em>em</em></td><td align=\"center\"><code>code</code></td>\
+                 <td align=\"right\"><strong>strong</strong> <del>struck</del></td>\
+                 <td><a href=\"other.html\">link</a></td></tr>\n\
+                 </tbody></table>\n"
+            );
+        }
+
+        #[test]
+        fn task_lists_in_nested_lists_are_disabled_checkboxes() {
+            let input = "- Groceries\n  - [x] Apples\n  - [ ] Carrots\n";
+
+            assert_eq!(
+                render_markdown(input, false),
+                "<ul>\n<li>Groceries\n<ul>\n\
+                 <li><input disabled=\"\" type=\"checkbox\" checked=\"\"/>\nApples</li>\n\
+                 <li><input disabled=\"\" type=\"checkbox\"/>\nCarrots</li>\n\
+                 </ul>\n</li>\n</ul>\n"
+            );
+        }
+
+        #[test]
+        fn the_first_h1_can_be_stripped_and_headings_shifted() {
+            use super::super::{render_markdown_with_links, LinkStyle, MarkdownOptions};
+
+            let render = |text, strip_first_h1, heading_offset| {
+                let options = MarkdownOptions {
+                    strip_first_h1,
+                    heading_offset,
+                    ..Default::default()
+                };
+                render_markdown_with_links(text, None, LinkStyle::Html, &options)
+            };
+            let text = "# Title\n\nIntro\n\n## Section\n\n##### Deep\n";
+
+            assert_eq!(
+                render(text, true, 0),
+                "<p>Intro</p>\n<h2>Section</h2>\n<h5>Deep</h5>\n"
+            );
+            assert_eq!(
+                render(text, false, 1),
+                "<h2>Title</h2>\n<p>Intro</p>\n<h3>Section</h3>\n<h6>Deep</h6>\n"
+            );
+            assert_eq!(
+                render(text, true, 3),
+                "<p>Intro</p>\n<h5>Section</h5>\n<h6>Deep</h6>\n"
+            );
+
+            // Only an H1 the chapter starts with is stripped
+            let text = "Intro\n\n# Title\n";
+            assert_eq!(render(text, true, 0), "<p>Intro</p>\n<h1>Title</h1>\n");
+            let text = "## Section\n";
+            assert_eq!(render(text, true, 0), "<h2>Section</h2>\n");
+        }
+
+        #[test]
+        fn leading_h1_text() {
+            use super::super::leading_h1;
+
+            assert_eq!(
+                leading_h1("# The `mdbook` *CLI*\n\nText\n"),
+                Some(String::from("The mdbook CLI"))
+            );
+            assert_eq!(leading_h1("Setext\n======\n"), Some(String::from("Setext")));
+            assert_eq!(leading_h1("Text\n\n# Title\n"), None);
+            assert_eq!(leading_h1("## Section\n"), None);
+            assert_eq!(leading_h1(""), None);
+        }
+
+        #[test]
+        fn extensions_can_be_turned_off() {
+            use super::super::{render_markdown_with_links, LinkStyle, MarkdownOptions};
+
+            let options = MarkdownOptions {
+                tables: false,
+                strikethrough: false,
+                task_lists: false,
+                ..Default::default()
+            };
+            let input = "| a |\n|---|\n| b |\n\n~~c~~\n\n- [x] d\n";
+
+            assert_eq!(
+                render_markdown_with_links(input, None, LinkStyle::Html, &options),
+                "<p>| a |\n|---|\n| b |</p>\n<p>~~c~~</p>\n<ul>\n<li>[x] d</li>\n</ul>\n"
+            );
+        }
     }
 
     mod html_munging {
-        use super::super::{id_from_content, normalize_id};
+        use super::super::{escape_html, id_from_content, normalize_id, unique_id_from_content};
+        use std::collections::HashMap;
+
+        #[test]
+        fn escape_special_characters() {
+            assert_eq!(escape_html("a&b<c>\"d'"), "a&amp;b&lt;c&gt;&quot;d&#39;");
+        }
+
+        #[test]
+        fn it_generates_anchors_for_symbol_only_headings() {
+            assert_eq!(id_from_content("## 🔊"), "🔊");
+            assert_eq!(id_from_content("## 🔊 😍"), "🔊-😍");
+            assert_eq!(id_from_content("## ?! ?!"), "?!-?!");
+        }
+
+        #[test]
+        fn it_deduplicates_repeated_headings() {
+            let mut ids = HashMap::new();
+            let got: Vec<_> = ["Usage", "Usage", "Usage 1", "Usage"]
+                .iter()
+                .map(|heading| unique_id_from_content(heading, &mut ids))
+                .collect();
+
+            assert_eq!(got, ["usage", "usage-1", "usage-1-1", "usage-2"]);
+        }
 
         #[test]
         fn it_generates_anchors() {
@@ -461,5 +967,23 @@ more text with spaces
         fn it_treats_tab_as_whitespace() {
             assert_eq!(convert_quotes_to_curly("\t'one'"), "\t‘one’");
         }
+
+        #[test]
+        fn it_converts_apostrophes_in_contractions() {
+            assert_eq!(convert_quotes_to_curly("don't, can't"), "don’t, can’t");
+            assert_eq!(convert_quotes_to_curly("'90s"), "‘90s");
+        }
+
+        #[test]
+        fn it_handles_quotes_next_to_punctuation() {
+            assert_eq!(
+                convert_quotes_to_curly(r#"("one") ["two"], "three"."#),
+                "(“one”) [“two”], “three”."
+            );
+            assert_eq!(
+                convert_quotes_to_curly(r#""'nested,' she said""#),
+                "“‘nested,’ she said”"
+            );
+        }
     }
 }
@@ -28,6 +28,14 @@ lazy_static! {
     static ref ANCHOR_END: Regex = Regex::new(r"ANCHOR_END:\s*(?P<anchor_name>[\w_-]+)").unwrap();
 }
 
+/// Does the string contain the start of the anchor?
+pub(crate) fn has_anchor(s: &str, anchor: &str) -> bool {
+    s.lines().any(|l| match ANCHOR_START.captures(l) {
+        Some(cap) => &cap["anchor_name"] == anchor,
+        None => false,
+    })
+}
+
 /// Take anchored lines from a string.
 /// Lines containing anchor are ignored.
 pub fn take_anchored_lines(s: &str, anchor: &str) -> String {
@@ -70,7 +78,7 @@ pub fn take_rustdoc_include_lines<R: RangeBounds<usize>>(s: &str, range: R) -> S
             output.push_str("# ");
         }
         output.push_str(line);
-        output.push_str("\n");
+        output.push('\n');
     }
     output.pop();
     output
@@ -95,7 +103,7 @@ pub fn take_rustdoc_include_anchored_lines(s: &str, anchor: &str) -> String {
                 None => {
                     if !ANCHOR_START.is_match(l) {
                         output.push_str(l);
-                        output.push_str("\n");
+                        output.push('\n');
                     }
                 }
             }
@@ -106,7 +114,7 @@ pub fn take_rustdoc_include_anchored_lines(s: &str, anchor: &str) -> String {
         } else if !ANCHOR_END.is_match(l) {
             output.push_str("# ");
             output.push_str(l);
-            output.push_str("\n");
+            output.push('\n');
         }
     }
 
@@ -122,6 +130,7 @@ mod tests {
     };
 
     #[test]
+    #[allow(clippy::reversed_empty_ranges)]
     fn take_lines_test() {
         let s = "Lorem\nipsum\ndolor\nsit\namet";
         assert_eq!(take_lines(s, 1..3), "ipsum\ndolor");
@@ -163,6 +172,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::reversed_empty_ranges)]
     fn take_rustdoc_include_lines_test() {
         let s = "Lorem\nipsum\ndolor\nsit\namet";
         assert_eq!(
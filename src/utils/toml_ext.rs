@@ -62,11 +62,61 @@ fn split(key: &str) -> Option<(&str, &str)> {
     Some((head, tail))
 }
 
+/// Convert a TOML value to the equivalent JSON value. Dates are converted to
+/// their TOML representation, floats JSON can't represent become `null`.
+pub(crate) fn to_json(value: &Value) -> serde_json::Value {
+    match *value {
+        Value::String(ref s) => serde_json::Value::String(s.clone()),
+        Value::Integer(i) => serde_json::Value::from(i),
+        Value::Float(f) => serde_json::Number::from_f64(f)
+            .map(serde_json::Value::Number)
+            .unwrap_or(serde_json::Value::Null),
+        Value::Boolean(b) => serde_json::Value::Bool(b),
+        Value::Datetime(ref d) => serde_json::Value::String(d.to_string()),
+        Value::Array(ref items) => serde_json::Value::Array(items.iter().map(to_json).collect()),
+        Value::Table(ref table) => serde_json::Value::Object(
+            table
+                .iter()
+                .map(|(key, value)| (key.clone(), to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::str::FromStr;
 
+    #[test]
+    fn convert_to_json() {
+        let src = r#"
+            string = "text"
+            integer = 42
+            float = 1.5
+            boolean = true
+            date = 1979-05-27T07:32:00Z
+            array = [1, 2, 3]
+            tables = [{ name = "first" }, { name = "second" }]
+
+            [nested.table]
+            key = "value"
+        "#;
+        let value = Value::from_str(src).unwrap();
+
+        let should_be = json!({
+            "string": "text",
+            "integer": 42,
+            "float": 1.5,
+            "boolean": true,
+            "date": "1979-05-27T07:32:00Z",
+            "array": [1, 2, 3],
+            "tables": [{ "name": "first" }, { "name": "second" }],
+            "nested": { "table": { "key": "value" } },
+        });
+        assert_eq!(to_json(&value), should_be);
+    }
+
     #[test]
     fn read_simple_table() {
         let src = "[table]";
@@ -0,0 +1,120 @@
+//! Counting the words of a chapter's prose, for its reading time.
+
+use pulldown_cmark::{Event, Tag};
+
+use super::new_cmark_parser;
+use crate::config::{ReadingTime, WordCounting};
+
+/// The number of words of the prose of a chapter, leaving out its code
+/// blocks (including the included files) and raw HTML.
+pub fn word_count(markdown: &str, counting: WordCounting) -> usize {
+    let mut text = String::new();
+    let mut in_code_block = false;
+
+    for event in new_cmark_parser(markdown) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            Event::Text(ref part) | Event::Code(ref part) if !in_code_block => {
+                text.push_str(part);
+            }
+            // The text of different blocks isn't part of the same word
+            Event::End(Tag::Paragraph)
+            | Event::End(Tag::Heading(_))
+            | Event::End(Tag::Item)
+            | Event::End(Tag::TableCell)
+            | Event::SoftBreak
+            | Event::HardBreak => text.push(' '),
+            _ => {}
+        }
+    }
+
+    count(&text, counting)
+}
+
+/// The minutes it takes to read `words` words, rounded up.
+pub fn reading_time(words: usize, config: &ReadingTime) -> usize {
+    let words_per_minute = config.words_per_minute.max(1) as usize;
+    let minutes = words / words_per_minute;
+    if minutes * words_per_minute == words {
+        minutes
+    } else {
+        minutes + 1
+    }
+}
+
+fn count(text: &str, counting: WordCounting) -> usize {
+    let mut words = 0;
+    let mut in_word = false;
+
+    for c in text.chars() {
+        let counted_alone = match counting {
+            WordCounting::Auto => is_cjk(c),
+            WordCounting::Words => false,
+            WordCounting::Characters => c.is_alphanumeric(),
+        };
+        if counted_alone {
+            words += 1;
+            in_word = false;
+        } else if c.is_whitespace() || counting == WordCounting::Characters {
+            in_word = false;
+        } else if !in_word {
+            words += 1;
+            in_word = true;
+        }
+    }
+
+    words
+}
+
+/// Whether a character is Chinese or Japanese, which are written without
+/// spaces between the words. Korean has them.
+fn is_cjk(c: char) -> bool {
+    match c as u32 {
+        0x3040..=0x30FF // Hiragana and Katakana
+        | 0x3400..=0x4DBF // CJK Unified Ideographs Extension A
+        | 0x4E00..=0x9FFF // CJK Unified Ideographs
+        | 0xF900..=0xFAFF // CJK Compatibility Ideographs
+        | 0x20000..=0x2FA1F => true, // The supplementary ideographs
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_blocks_and_html_are_left_out() {
+        let markdown = "# A Title\n\nSome *emph*asized `code`.\n\n\
+                        ```rust\nfn main() { println!(\"lots of words\"); }\n```\n\n\
+                        <div class=\"x\">\n\n- One\n- Two\n";
+
+        assert_eq!(word_count(markdown, WordCounting::Auto), 7);
+        assert_eq!(word_count(markdown, WordCounting::Words), 7);
+    }
+
+    #[test]
+    fn cjk_characters_can_be_counted_alone() {
+        let markdown = "日本語の本 and some English.\n";
+
+        assert_eq!(word_count(markdown, WordCounting::Auto), 8);
+        assert_eq!(word_count(markdown, WordCounting::Words), 4);
+        assert_eq!(word_count(markdown, WordCounting::Characters), 19);
+    }
+
+    #[test]
+    fn reading_times_are_rounded_up() {
+        let config = ReadingTime::default();
+        assert_eq!(reading_time(0, &config), 0);
+        assert_eq!(reading_time(1, &config), 1);
+        assert_eq!(reading_time(200, &config), 1);
+        assert_eq!(reading_time(201, &config), 2);
+
+        let config = ReadingTime {
+            words_per_minute: 0,
+            ..Default::default()
+        };
+        assert_eq!(reading_time(3, &config), 3);
+    }
+}
@@ -17,7 +17,31 @@ fn passing_alternate_backend() {
 fn failing_alternate_backend() {
     let (md, _temp) = dummy_book_with_backend("failing", fail_cmd(), false);
 
-    md.build().unwrap_err();
+    let err = md.build().unwrap_err();
+    let message = format!("{:?}", err);
+    assert!(
+        message.contains("\"failing\" renderer failed"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn each_backend_gets_its_own_build_dir() {
+    let temp = TempFileBuilder::new().prefix("mdbook").tempdir().unwrap();
+    let mut config = Config::default();
+    config.set("output.html.print.enable", false).unwrap();
+    config.set("output.passing.command", success_cmd()).unwrap();
+
+    let md = MDBook::init(temp.path())
+        .with_config(config)
+        .build()
+        .unwrap();
+    md.build().unwrap();
+
+    let build_dir = temp.path().join("book");
+    assert!(build_dir.join("html").join("index.html").is_file());
+    assert!(build_dir.join("passing").is_dir());
 }
 
 #[test]
@@ -32,6 +56,31 @@ fn missing_optional_backends_are_not_fatal() {
     assert!(md.build().is_ok());
 }
 
+#[test]
+fn failing_optional_backends_are_fatal() {
+    let (md, _temp) = dummy_book_with_backend("failing", fail_cmd(), true);
+    assert!(md.build().is_err());
+}
+
+#[test]
+fn missing_optional_backends_dont_stop_the_other_backends() {
+    let temp = TempFileBuilder::new().prefix("mdbook").tempdir().unwrap();
+    let mut config = Config::default();
+    config
+        .set("output.missing.command", "trduyvbhijnorgevfuhn")
+        .unwrap();
+    config.set("output.missing.optional", true).unwrap();
+    config.set("output.html.print.enable", false).unwrap();
+
+    let md = MDBook::init(temp.path())
+        .with_config(config)
+        .build()
+        .unwrap();
+    md.build().unwrap();
+
+    assert!(temp.path().join("book/html/index.html").is_file());
+}
+
 #[test]
 fn alternate_backend_with_arguments() {
     let (md, _temp) = dummy_book_with_backend("arguments", "echo Hello World!", false);
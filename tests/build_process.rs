@@ -1,13 +1,15 @@
 mod dummy_book;
 
 use crate::dummy_book::DummyBook;
-use mdbook::book::Book;
+use mdbook::book::{Book, BookItem, BuildReport, Chapter, SectionNumber};
 use mdbook::config::Config;
 use mdbook::errors::*;
 use mdbook::preprocess::{Preprocessor, PreprocessorContext};
 use mdbook::renderer::{RenderContext, Renderer};
 use mdbook::MDBook;
+use std::fs;
 use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
 
 struct Spy(Arc<Mutex<Inner>>);
 
@@ -76,3 +78,79 @@ fn mdbook_runs_renderers() {
     let inner = spy.lock().unwrap();
     assert_eq!(inner.run_count, 1);
 }
+
+#[test]
+fn a_book_can_be_built_from_chapters_in_memory() {
+    let spy: Arc<Mutex<Inner>> = Default::default();
+    let temp = TempDir::new().unwrap();
+
+    let mut intro = Chapter::new(
+        "Introduction",
+        "# Introduction\n".to_string(),
+        "intro.md",
+        vec![],
+    );
+    intro.number = Some(SectionNumber(vec![1]));
+    let mut usage = Chapter::new(
+        "Usage",
+        "# Usage\n\nSee [the introduction](intro.md).\n".to_string(),
+        "usage.md",
+        vec![],
+    );
+    usage.number = Some(SectionNumber(vec![2]));
+    let mut book = Book::new();
+    book.push_item(intro)
+        .push_item(BookItem::Separator)
+        .push_item(usage);
+
+    let mut cfg = Config::default();
+    cfg.book.title = Some(String::from("In Memory"));
+    let mut md = MDBook::load_with_config_and_book(temp.path(), cfg, book).unwrap();
+    md.with_renderer(Spy(Arc::clone(&spy)));
+    let report = md.build_with_report().unwrap();
+
+    assert_eq!(
+        report,
+        BuildReport {
+            backends: vec![String::from("html"), String::from("dummy")],
+            skipped: vec![],
+            warnings: vec![],
+            archives: vec![],
+        }
+    );
+    assert_eq!(spy.lock().unwrap().run_count, 1);
+    let usage = fs::read_to_string(temp.path().join("book/html/usage.html")).unwrap();
+    assert!(usage.contains(r#"<a href="intro.html">the introduction</a>"#));
+    assert!(usage.contains("<title>Usage - In Memory</title>"));
+    assert!(!temp.path().join("src").exists());
+}
+
+#[test]
+fn an_in_memory_book_is_the_default_translation() {
+    let temp = TempDir::new().unwrap();
+    let src = temp.path().join("src/fr");
+    fs::create_dir_all(&src).unwrap();
+    fs::write(src.join("SUMMARY.md"), "- [Introduction](intro.md)\n").unwrap();
+    fs::write(src.join("intro.md"), "# Introduction\n\nBonjour.\n").unwrap();
+
+    let mut book = Book::new();
+    book.push_item(Chapter::new(
+        "Introduction",
+        "# Introduction\n\nHello.\n".to_string(),
+        "intro.md",
+        vec![],
+    ));
+    let cfg: Config = "[book]\nlanguage = \"en\"\n\n\
+                       [language.en]\nname = \"English\"\n\n\
+                       [language.fr]\nname = \"Français\"\n"
+        .parse()
+        .unwrap();
+    let md = MDBook::load_with_config_and_book(temp.path(), cfg, book).unwrap();
+    md.build().unwrap();
+
+    let book = temp.path().join("book");
+    let en = fs::read_to_string(book.join("en/intro.html")).unwrap();
+    assert!(en.contains("Hello."));
+    let fr = fs::read_to_string(book.join("fr/intro.html")).unwrap();
+    assert!(fr.contains("Bonjour."));
+}
@@ -0,0 +1,92 @@
+//! Integration tests for loading `book.toml`, and the `book.json` of the
+//! first versions of mdBook.
+
+use mdbook::diagnostics::{Diagnostic, DiagnosticKind};
+use mdbook::MDBook;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::{Builder as TempFileBuilder, TempDir};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("config_files")
+        .join(name)
+}
+
+/// A book whose configuration file `file_name` is the fixture `name`.
+fn book_with_config(name: &str, file_name: &str) -> TempDir {
+    let temp = TempFileBuilder::new().prefix("mdbook").tempdir().unwrap();
+    MDBook::init(temp.path()).build().unwrap();
+    fs::remove_file(temp.path().join("book.toml")).unwrap();
+    fs::copy(fixture(name), temp.path().join(file_name)).unwrap();
+    temp
+}
+
+#[test]
+fn syntax_errors_say_where_they_are() {
+    let temp = book_with_config("malformed.toml", "book.toml");
+
+    let err = match MDBook::load(temp.path()) {
+        Ok(_) => panic!("The malformed book.toml was loaded"),
+        Err(err) => err,
+    };
+    let cause = err.root_cause().to_string();
+    let location = format!("{}:3:9: ", temp.path().join("book.toml").display());
+    assert!(cause.starts_with(&location), "{}", cause);
+    assert!(cause.contains("expected an equals"), "{}", cause);
+}
+
+#[test]
+fn unknown_tables_are_warnings_with_suggestions() {
+    let temp = book_with_config("unknown_tables.toml", "book.toml");
+
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let warnings = md.warnings();
+    for message in &[
+        "Unknown table `[boook]` in book.toml, ignoring it (did you mean `[book]`?)",
+        "Unknown table `[outptu]` in book.toml, ignoring it (did you mean `[output]`?)",
+        "Unknown table `[custom-tool]` in book.toml, ignoring it",
+    ] {
+        let warning = Diagnostic::new(DiagnosticKind::Config, *message);
+        assert!(warnings.contains(&warning), "{:?}", warnings);
+    }
+}
+
+#[test]
+fn a_legacy_book_json_is_read_and_can_be_migrated() {
+    let temp = book_with_config("book.json", "book.json");
+    fs::rename(temp.path().join("src"), temp.path().join("source")).unwrap();
+
+    let md = MDBook::load(temp.path()).unwrap();
+    assert_eq!(md.config.book.title, Some(String::from("An Old Book")));
+    assert_eq!(md.config.book.authors, vec![String::from("Someone")]);
+    assert_eq!(md.config.book.src, PathBuf::from("source"));
+    assert_eq!(md.config.build.build_dir, PathBuf::from("public"));
+    let html = md.config.html_config().unwrap();
+    assert_eq!(html.theme, Some(PathBuf::from("my-theme")));
+    assert_eq!(html.google_analytics, Some(String::from("UA-123456-7")));
+
+    assert!(MDBook::migrate_legacy_config(temp.path()).unwrap());
+    let book_toml = fs::read_to_string(temp.path().join("book.toml")).unwrap();
+    assert_eq!(
+        book_toml,
+        "[book]\n\
+         title = \"An Old Book\"\n\
+         authors = [\"Someone\"]\n\
+         description = \"A book from the first versions of mdBook\"\n\
+         src = \"source\"\n\
+         \n\
+         [build]\n\
+         build-dir = \"public\"\n\
+         [output.html]\n\
+         theme = \"my-theme\"\n\
+         google-analytics = \"UA-123456-7\"\n"
+    );
+    assert_eq!(MDBook::load(temp.path()).unwrap().config, md.config);
+
+    // The book.toml is never overwritten
+    assert!(!MDBook::migrate_legacy_config(temp.path()).unwrap());
+}
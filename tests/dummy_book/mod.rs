@@ -9,7 +9,7 @@ use mdbook::errors::*;
 use mdbook::MDBook;
 use std::fs::{self, File};
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tempfile::{Builder as TempFileBuilder, TempDir};
 use walkdir::WalkDir;
 
@@ -112,12 +112,12 @@ fn recursive_copy<A: AsRef<Path>, B: AsRef<Path>>(from: A, to: B) -> Result<()>
     let from = from.as_ref();
     let to = to.as_ref();
 
-    for entry in WalkDir::new(&from) {
+    for entry in WalkDir::new(from) {
         let entry = entry.with_context(|| "Unable to inspect directory entry")?;
 
         let original_location = entry.path();
         let relative = original_location
-            .strip_prefix(&from)
+            .strip_prefix(from)
             .expect("`original_location` is inside the `from` directory");
         let new_location = to.join(relative);
 
@@ -126,7 +126,7 @@ fn recursive_copy<A: AsRef<Path>, B: AsRef<Path>>(from: A, to: B) -> Result<()>
                 fs::create_dir_all(parent).with_context(|| "Couldn't create directory")?;
             }
 
-            fs::copy(&original_location, &new_location)
+            fs::copy(original_location, &new_location)
                 .with_context(|| "Unable to copy file contents")?;
         }
     }
@@ -134,12 +134,17 @@ fn recursive_copy<A: AsRef<Path>, B: AsRef<Path>>(from: A, to: B) -> Result<()>
     Ok(())
 }
 
-pub fn new_copy_of_example_book() -> Result<TempDir> {
+/// Copy the example book into a temporary directory, returning the directory
+/// and the root of the book inside it. The `examples/` directory is copied
+/// next to the book because some chapters include files from it.
+pub fn new_copy_of_example_book() -> Result<(TempDir, PathBuf)> {
     let temp = TempFileBuilder::new().prefix("book-example").tempdir()?;
 
-    let book_example = Path::new(env!("CARGO_MANIFEST_DIR")).join("book-example");
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let book_dir = temp.path().join("book-example");
 
-    recursive_copy(book_example, temp.path())?;
+    recursive_copy(root.join("book-example"), &book_dir)?;
+    recursive_copy(root.join("examples"), temp.path().join("examples"))?;
 
-    Ok(temp)
+    Ok((temp, book_dir))
 }
@@ -96,3 +96,39 @@ fn book_toml_isnt_required() {
 
     md.build().unwrap();
 }
+
+#[test]
+fn initialized_books_with_a_theme_build_cleanly() {
+    let temp = TempFileBuilder::new().prefix("mdbook").tempdir().unwrap();
+    let mut cfg = Config::default();
+    cfg.book.title = Some(String::from("A Book"));
+
+    MDBook::init(temp.path())
+        .with_config(cfg)
+        .copy_theme(true)
+        .create_gitignore(true)
+        .build()
+        .unwrap();
+
+    assert!(temp.path().join("theme").join("index.hbs").exists());
+    assert!(temp
+        .path()
+        .join("theme")
+        .join("css")
+        .join("chrome.css")
+        .exists());
+    let gitignore = fs::read_to_string(temp.path().join(".gitignore")).unwrap();
+    assert_eq!(gitignore.trim(), "book");
+
+    // load the book again, like `mdbook build` does
+    let md = MDBook::load(temp.path()).unwrap();
+    assert_eq!(md.config.book.title, Some(String::from("A Book")));
+    md.build().unwrap();
+
+    let index = fs::read_to_string(temp.path().join("book").join("index.html")).unwrap();
+    assert!(index.contains(
+        "<h1><a class=\"header\" href=\"#chapter-1\" id=\"chapter-1\">Chapter 1</a></h1>"
+    ));
+    // the theme isn't part of the book's sources
+    assert!(!temp.path().join("book").join("theme").exists());
+}
@@ -6,7 +6,9 @@ mod dummy_book;
 use crate::dummy_book::{assert_contains_strings, assert_doesnt_contain_strings, DummyBook};
 
 use anyhow::Context;
+use mdbook::book::BookItem;
 use mdbook::config::Config;
+use mdbook::diagnostics::{Diagnostic, DiagnosticKind};
 use mdbook::errors::*;
 use mdbook::utils::fs::write_file;
 use mdbook::MDBook;
@@ -123,16 +125,61 @@ fn check_correct_relative_links_in_print_page() {
     assert_contains_strings(
         first.join("print.html"),
         &[
-            r##"<a href="second/../first/nested.html">the first section</a>,"##,
+            r##"<a href="#first-nested-md">the first section</a>,"##,
             r##"<a href="second/../../std/foo/bar.html">outside</a>"##,
             r##"<img src="second/../images/picture.png" alt="Some image" />"##,
-            r##"<a href="second/nested.html#some-section">fragment link</a>"##,
-            r##"<a href="second/../first/markdown.html">HTML Link</a>"##,
+            r##"<a href="#second-nested-md-some-section">fragment link</a>"##,
+            r##"<a href="#first-markdown-md">HTML Link</a>"##,
             r##"<img src="second/../images/picture.png" alt="raw html">"##,
         ],
     );
 }
 
+#[test]
+fn the_headings_of_the_print_page_are_unique() {
+    let temp = DummyBook::new().build().unwrap();
+    let src = temp.path().join("src");
+    let summary = "# Summary\n\n- [Linux](linux.md)\n- [Windows](guide/windows.md)\n";
+    write_file(&src, "SUMMARY.md", summary.as_bytes()).unwrap();
+    let linux = "# Linux\n\n## Installation\n\nSee [Windows](guide/windows.md#installation) \
+                 and [above](#installation).\n";
+    write_file(&src, "linux.md", linux.as_bytes()).unwrap();
+    let windows = "# Windows\n\n## Installation\n\n![Setup](setup.png) \
+                   [Back](../linux.md#installation)\n";
+    write_file(&src, "guide/windows.md", windows.as_bytes()).unwrap();
+    write_file(&src, "guide/setup.png", b"").unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    // Every link of the print page has to go somewhere
+    md.config
+        .set("output.html.link-check.enable", true)
+        .unwrap();
+    md.build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_contains_strings(
+        book.join("print.html"),
+        &[
+            "<div id=\"linux-md\">",
+            r##"<h2><a class="header" href="#linux-md-installation" id="linux-md-installation">Installation</a></h2>"##,
+            r##"<a href="#guide-windows-md-installation">Windows</a>"##,
+            r##"<a href="#linux-md-installation">above</a>"##,
+            "<div id=\"guide-windows-md\">",
+            r##"<h2><a class="header" href="#guide-windows-md-installation" id="guide-windows-md-installation">Installation</a></h2>"##,
+            r##"<img src="guide/setup.png" alt="Setup" />"##,
+            r##"<a href="#linux-md-installation">Back</a>"##,
+        ],
+    );
+    // The pages of the chapters are left alone
+    assert_contains_strings(
+        book.join("linux.html"),
+        &[
+            r##"id="installation""##,
+            r##"<a href="guide/windows.html#installation">Windows</a>"##,
+            r##"<a href="#installation">above</a>"##,
+        ],
+    );
+}
+
 #[test]
 fn rendered_code_has_playpen_stuff() {
     let temp = DummyBook::new().build().unwrap();
@@ -148,6 +195,61 @@ fn rendered_code_has_playpen_stuff() {
     assert_contains_strings(book_js, &[".playpen"]);
 }
 
+#[test]
+fn code_block_attributes_control_the_playpen() {
+    let temp = DummyBook::new().build().unwrap();
+    let chapter = "# Nested Chapter\n\n\
+                   ```rust editable, no_run\nlet x = 1;\n```\n\n\
+                   ```rust,noplayground,my-class\nlet y = 2;\n```\n";
+    write_file(
+        &temp.path().join("src"),
+        "first/nested.md",
+        chapter.as_bytes(),
+    )
+    .unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let nested = temp.path().join("book/first/nested.html");
+    assert_contains_strings(
+        &nested,
+        &[
+            "<pre class=\"playpen\" data-copyable><code class=\"language-rust editable no_run\">let x = 1;\n</code></pre>",
+            "<code class=\"language-rust noplayground my-class\">let y = 2;\n</code>",
+            "editor.js",
+        ],
+    );
+    // The editor is loaded for the editable block, even though the playpens
+    // aren't editable
+    assert!(temp.path().join("book/ace.js").exists());
+}
+
+#[test]
+fn code_blocks_can_be_numbered_and_not_copyable() {
+    let temp = DummyBook::new().build().unwrap();
+    let chapter = "# Nested Chapter\n\n\
+                   ```console\n$ mdbook build\n```\n\n\
+                   ```text,no-line-numbers,copyable\nplain\n```\n";
+    write_file(
+        &temp.path().join("src"),
+        "first/nested.md",
+        chapter.as_bytes(),
+    )
+    .unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.set("output.html.copyable-code", false).unwrap();
+    md.config.set("output.html.line-numbers", true).unwrap();
+    md.build().unwrap();
+
+    let nested = temp.path().join("book/first/nested.html");
+    assert_contains_strings(
+        &nested,
+        &[
+            "<pre data-line-numbers><code class=\"language-console\"><span class=\"line-number\"></span>$ mdbook build\n</code></pre>",
+            "<pre data-copyable><code class=\"language-text no-line-numbers copyable\">plain\n</code></pre>",
+        ],
+    );
+}
+
 #[test]
 fn anchors_include_text_between_but_not_anchor_comments() {
     let temp = DummyBook::new().build().unwrap();
@@ -362,6 +464,37 @@ fn able_to_include_playpen_files_in_chapters() {
     assert_doesnt_contain_strings(&second, &["{{#playpen example.rs}}"]);
 }
 
+#[test]
+fn playpen_includes_can_select_lines_and_pass_flags() {
+    let temp = DummyBook::new().build().unwrap();
+    let src = temp.path().join("src");
+    fs::write(
+        src.join("anchored.rs"),
+        "// ANCHOR: main\nfn main() {\n    println!(\"anchored\");\n}\n// ANCHOR_END: main\n\
+         fn unused() {}\n",
+    )
+    .unwrap();
+    fs::write(
+        src.join("second.md"),
+        "# Second Chapter\n\n{{#playpen anchored.rs:main editable}}\n",
+    )
+    .unwrap();
+
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.set("output.html.playpen.editable", true).unwrap();
+    md.build().unwrap();
+
+    let second = temp.path().join("book/second.html");
+    assert_contains_strings(
+        &second,
+        &[
+            r#"<pre class="playpen" data-copyable><code class="language-rust editable">"#,
+            r#"println!(&quot;anchored&quot;);"#,
+        ],
+    );
+    assert_doesnt_contain_strings(&second, &["ANCHOR", "unused", "{{#playpen"]);
+}
+
 /// This makes sure you can include a Rust file with `{{#include ../SUMMARY.md}}`.
 #[test]
 fn able_to_include_files_in_chapters() {
@@ -380,9 +513,8 @@ fn able_to_include_files_in_chapters() {
     assert_doesnt_contain_strings(&includes, &["{{#include ../SUMMARY.md::}}"]);
 }
 
-/// Ensure cyclic includes are capped so that no exceptions occur
 #[test]
-fn recursive_includes_are_capped() {
+fn nested_includes_are_expanded() {
     let temp = DummyBook::new().build().unwrap();
     let md = MDBook::load(temp.path()).unwrap();
     md.build().unwrap();
@@ -394,13 +526,115 @@ Around the world, around the world"];
     assert_contains_strings(&recursive, content);
 }
 
+#[test]
+fn cyclic_includes_fail_the_build() {
+    let temp = DummyBook::new().build().unwrap();
+    let recursive = "Around the world, around the world\n{{#include recursive.md}}\n";
+    write_file(
+        &temp.path().join("src"),
+        "first/recursive.md",
+        recursive.as_bytes(),
+    )
+    .unwrap();
+
+    let err = MDBook::load(temp.path()).unwrap().build().unwrap_err();
+    let message = format!("{:?}", err);
+    assert!(
+        message.contains("nested includes in first/recursive.md"),
+        "{}",
+        message
+    );
+}
+
 #[test]
 fn example_book_can_build() {
-    let example_book_dir = dummy_book::new_copy_of_example_book().unwrap();
+    let (_temp, example_book_dir) = dummy_book::new_copy_of_example_book().unwrap();
+
+    let mut md = MDBook::load(&example_book_dir).unwrap();
+    // the example book shouldn't have any broken links either
+    md.config.build.strict = true;
+
+    md.build().unwrap();
+}
+
+#[test]
+fn broken_links_only_fail_strict_builds() {
+    let temp = DummyBook::new().build().unwrap();
+    let broken = "\"Includes\" (first/includes.md): second.html (no such file)";
+
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    md.config.build.strict = true;
+    let err = format!("{:?}", md.build().unwrap_err());
+    assert!(err.contains("broken links"), "{}", err);
+    assert!(err.contains(broken), "{}", err);
+    let outside = "(second/nested.md): ../../std/foo/bar.html (points outside of the book)";
+    assert!(err.contains(outside), "{}", err);
+    // links which work aren't listed
+    assert!(!err.contains("../first/nested.html"), "{}", err);
+    assert!(!err.contains("#some-section"), "{}", err);
+}
+
+#[test]
+fn the_warnings_of_a_build_are_collected() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[output.html]\nmathjax-suport = true\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let warnings = md.warnings();
+    assert!(warnings.contains(&Diagnostic::new(
+        DiagnosticKind::Config,
+        "Unknown key `output.html.mathjax-suport` in book.toml, ignoring it"
+    )));
+    assert!(warnings.contains(
+        &Diagnostic::new(
+            DiagnosticKind::BrokenLink,
+            "Broken link \"second.html\" (no such file)"
+        )
+        .with_chapter("first/includes.md")
+    ));
+    // Every build starts again
+    md.build().unwrap();
+    assert_eq!(md.warnings(), warnings);
+
+    md.config.build.deny_warnings = true;
+    let err = format!("{:?}", md.build().unwrap_err());
+    assert!(err.contains("`build.deny-warnings` is set"), "{}", err);
+}
 
-    let md = MDBook::load(example_book_dir.path()).unwrap();
+#[test]
+fn the_build_can_be_timed() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+    assert!(!temp.path().join("book/timings.json").exists());
 
+    md.config.build.timings = true;
     md.build().unwrap();
+
+    let timings = fs::read_to_string(temp.path().join("book/timings.json")).unwrap();
+    let timings: serde_json::Value = serde_json::from_str(&timings).unwrap();
+    let chapters = timings["chapters"].as_array().unwrap();
+    let nested = chapters
+        .iter()
+        .find(|ch| ch["chapter"] == "first/nested.md")
+        .unwrap();
+    for phase in &["preprocess", "markdown", "template", "post-process"] {
+        assert!(nested["phases"][phase].is_f64(), "{}", phase);
+    }
+    let steps: Vec<_> = timings["steps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|step| step["step"].as_str().unwrap())
+        .collect();
+    assert!(steps.contains(&"the links preprocessor"), "{:?}", steps);
+    assert!(steps.contains(&"print page"), "{:?}", steps);
+    assert!(steps.contains(&"copying the theme"), "{:?}", steps);
 }
 
 #[test]
@@ -440,14 +674,105 @@ fn by_default_mdbook_use_index_preprocessor_to_convert_readme_to_index() {
     assert_doesnt_contain_strings(&first_index, &["README.html"]);
 
     let second_index = temp.path().join("book").join("second").join("index.html");
-    let unexpected_strings = vec!["Second README"];
-    assert_doesnt_contain_strings(&second_index, &unexpected_strings);
+    assert_contains_strings(
+        &second_index,
+        &["Second README", r#"href="../second/nested/index.html""#],
+    );
+
+    let nested_index = temp.path().join("book/second/nested/index.html");
+    assert_contains_strings(&nested_index, &["Second nested README"]);
+    assert!(!temp.path().join("book/second/nested/README.html").exists());
+}
+
+#[test]
+fn readme_and_index_chapters_in_the_same_directory_dont_build() {
+    let temp = DummyBook::new().build().unwrap();
+    let src = temp.path().join("src2");
+    write_file(&src, "second/index.md", b"# Second index").unwrap();
+    let mut summary = fs::read_to_string(src.join("SUMMARY.md")).unwrap();
+    summary.push_str("- [2nd index](second/index.md)\n");
+    write_file(&src, "SUMMARY.md", summary.as_bytes()).unwrap();
+    let mut cfg = Config::default();
+    cfg.set("book.src", "src2").unwrap();
+
+    let err = MDBook::load_with_config(temp.path(), cfg)
+        .unwrap()
+        .build()
+        .unwrap_err();
+
+    let err = format!("{:?}", err);
+    assert!(err.contains("would be rendered to"), "{}", err);
+    assert!(err.contains("README.md"), "{}", err);
+}
+
+#[test]
+fn an_index_md_outside_the_summary_doesnt_collide_with_a_readme() {
+    let temp = DummyBook::new().build().unwrap();
+    let src = temp.path().join("src2");
+    write_file(&src, "second/index.md", b"# Second index").unwrap();
+    let mut cfg = Config::default();
+    cfg.set("book.src", "src2").unwrap();
+
+    MDBook::load_with_config(temp.path(), cfg)
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let second_index = temp.path().join("book/second/index.html");
+    assert_contains_strings(&second_index, &["Second README"]);
+    assert_doesnt_contain_strings(&second_index, &["Second index"]);
+}
+
+#[test]
+fn the_landing_page_is_marked_and_at_the_index() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut index = mdbook::theme::INDEX.to_vec();
+    index.extend_from_slice(b"\n{{#if is_index}}<!-- The landing page -->{{/if}}");
+    write_file(&temp.path().join("theme"), "index.hbs", &index).unwrap();
+    let src = temp.path().join("src");
+    let summary = "# Summary\n\n[Intro](intro.md)\n\n- [Second](second/nested.md)\n";
+    write_file(&src, "SUMMARY.md", summary.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_contains_strings(book.join("intro.html"), &["<!-- The landing page -->"]);
+    assert_contains_strings(
+        book.join("index.html"),
+        &[
+            "<!-- The landing page -->",
+            "some interesting text...",
+            r#"<link rel="stylesheet" href="css/chrome.css">"#,
+        ],
+    );
+    assert_doesnt_contain_strings(
+        book.join("second/nested.html"),
+        &["<!-- The landing page -->"],
+    );
+
+    let book_toml = "[output.html]\nindex-page = \"redirect\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+    assert_contains_strings(
+        book.join("index.html"),
+        &[r#"<meta http-equiv="refresh" content="0; URL=intro.html">"#],
+    );
+    assert_contains_strings(book.join("intro.html"), &["<!-- The landing page -->"]);
+
+    // A chapter at index.html is the landing page, wherever it's listed
+    let summary = "# Summary\n\n[Intro](intro.md)\n\n- [Home](index.md)\n";
+    write_file(&src, "SUMMARY.md", summary.as_bytes()).unwrap();
+    write_file(&src, "index.md", b"# Home\n\nWelcome home.\n").unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+    assert_contains_strings(
+        book.join("index.html"),
+        &["<!-- The landing page -->", "Welcome home."],
+    );
+    assert_doesnt_contain_strings(book.join("intro.html"), &["<!-- The landing page -->"]);
 }
 
 #[test]
 fn theme_dir_overrides_work_correctly() {
-    let book_dir = dummy_book::new_copy_of_example_book().unwrap();
-    let book_dir = book_dir.path();
+    let (_temp, book_dir) = dummy_book::new_copy_of_example_book().unwrap();
     let theme_dir = book_dir.join("theme");
 
     let mut index = mdbook::theme::INDEX.to_vec();
@@ -455,7 +780,7 @@ fn theme_dir_overrides_work_correctly() {
 
     write_file(&theme_dir, "index.hbs", &index).unwrap();
 
-    let md = MDBook::load(book_dir).unwrap();
+    let md = MDBook::load(&book_dir).unwrap();
     md.build().unwrap();
 
     let built_index = book_dir.join("book").join("index.html");
@@ -463,103 +788,2108 @@ fn theme_dir_overrides_work_correctly() {
 }
 
 #[test]
-fn no_index_for_print_html() {
+fn custom_context_values_are_available_to_the_theme() {
     let temp = DummyBook::new().build().unwrap();
+    let book_toml = r#"
+        [book]
+        title = "Dummy Book"
+
+        [output.html.context]
+        banner = "Pre-release <b>docs</b>"
+        versions = ["0.3", "0.4"]
+
+        [output.html.context.features]
+        dark-mode = true
+    "#;
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+
+    let mut index = mdbook::theme::INDEX.to_vec();
+    index.extend_from_slice(
+        b"\n<!-- banner: {{context.banner}} -->\
+          \n<!-- versions:{{#each context.versions}} {{this}}{{/each}} -->\
+          \n<!-- dark mode: {{#if context.features.dark-mode}}on{{/if}} -->",
+    );
+    write_file(&temp.path().join("theme"), "index.hbs", &index).unwrap();
+
     let md = MDBook::load(temp.path()).unwrap();
     md.build().unwrap();
 
-    let print_html = temp.path().join("book/print.html");
-    assert_contains_strings(print_html, &[r##"noindex"##]);
+    let built_index = temp.path().join("book").join("index.html");
+    assert_contains_strings(
+        built_index,
+        &[
+            "<!-- banner: Pre-release &lt;b&gt;docs&lt;/b&gt; -->",
+            "<!-- versions: 0.3 0.4 -->",
+            "<!-- dark mode: on -->",
+        ],
+    );
+}
 
-    let index_html = temp.path().join("book/index.html");
-    assert_doesnt_contain_strings(index_html, &[r##"noindex"##]);
+#[test]
+fn nested_chapters_have_breadcrumbs() {
+    let temp = DummyBook::new().build().unwrap();
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let nested = temp.path().join("book/second/nested.html");
+    assert_contains_strings(
+        &nested,
+        &[
+            r#"<nav class="breadcrumbs" aria-label="Breadcrumbs">"#,
+            r#"<a href="../second.html">Second Chapter</a> ›"#,
+            r#"<span aria-current="page">Nested Chapter</span>"#,
+        ],
+    );
+    let intro = temp.path().join("book/intro.html");
+    assert_doesnt_contain_strings(&intro, &[r#"class="breadcrumbs""#]);
+
+    let book_toml = r#"
+        [book]
+        title = "Dummy Book"
+
+        [output.html]
+        breadcrumbs-include-title = true
+    "#;
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(
+        &nested,
+        &[
+            r#"<a href="../index.html">Dummy Book</a> ›"#,
+            r#"<a href="../second.html">Second Chapter</a> ›"#,
+        ],
+    );
+    assert_contains_strings(&intro, &[r#"<a href="index.html">Dummy Book</a> ›"#]);
 }
 
 #[test]
-fn markdown_options() {
+fn the_chapters_of_a_part_have_it_as_their_first_breadcrumb() {
     let temp = DummyBook::new().build().unwrap();
+    let summary =
+        "# Summary\n\n[Intro](intro.md)\n\n# Guide\n\n- [First Chapter](first/index.md)\n\n\
+                   [Conclusion](conclusion.md)\n";
+    write_file(temp.path(), "src/SUMMARY.md", summary.as_bytes()).unwrap();
     let md = MDBook::load(temp.path()).unwrap();
     md.build().unwrap();
 
-    let path = temp.path().join("book/first/markdown.html");
     assert_contains_strings(
-        &path,
+        temp.path().join("book/first/index.html"),
         &[
-            "<th>foo</th>",
-            "<th>bar</th>",
-            "<td>baz</td>",
-            "<td>bim</td>",
+            r#"<span>Guide</span> ›"#,
+            r#"<span aria-current="page">First Chapter</span>"#,
         ],
     );
+    // The prefix and suffix chapters aren't in a part
+    for page in &["book/intro.html", "book/conclusion.html"] {
+        assert_doesnt_contain_strings(temp.path().join(page), &[r#"class="breadcrumbs""#]);
+    }
+}
+
+#[test]
+fn chapter_frontmatter_is_used_by_the_renderer() {
+    let temp = DummyBook::new().build().unwrap();
+    write_file(temp.path(), "book.toml", b"[book]\ntitle = \"Dummy\"\n").unwrap();
+    let second = fs::read_to_string(temp.path().join("src/second.md")).unwrap();
+    let frontmatter =
+        "+++\ntitle = \"A Custom Title\"\ndescription = \"All about the second chapter\"\n\
+                       author = \"Ferris\"\n+++\n";
+    write_file(
+        &temp.path().join("src"),
+        "second.md",
+        (frontmatter.to_string() + &second).as_bytes(),
+    )
+    .unwrap();
+    let unicode = "+++\ndraft = true\n+++\n# Unicode\n";
+    write_file(
+        &temp.path().join("src"),
+        "first/unicode.md",
+        unicode.as_bytes(),
+    )
+    .unwrap();
+
+    let mut index = mdbook::theme::INDEX.to_vec();
+    index.extend_from_slice(b"\n<!-- author: {{frontmatter.author}} -->");
+    write_file(&temp.path().join("theme"), "index.hbs", &index).unwrap();
+
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let book = temp.path().join("book");
     assert_contains_strings(
-        &path,
+        book.join("second.html"),
         &[
-            r##"<sup class="footnote-reference"><a href="#1">1</a></sup>"##,
-            r##"<sup class="footnote-reference"><a href="#word">2</a></sup>"##,
-            r##"<div class="footnote-definition" id="1"><sup class="footnote-definition-label">1</sup>"##,
-            r##"<div class="footnote-definition" id="word"><sup class="footnote-definition-label">2</sup>"##,
+            "<title>A Custom Title - Dummy</title>",
+            r#"<meta name="description" content="All about the second chapter">"#,
+            "<!-- author: Ferris -->",
         ],
     );
-    assert_contains_strings(&path, &["<del>strikethrough example</del>"]);
+    assert_doesnt_contain_strings(book.join("second.html"), &["+++", "Unicode"]);
+    assert_contains_strings(book.join("intro.html"), &["<!-- author:  -->"]);
+    assert!(!book.join("first/unicode.html").exists());
+    assert_doesnt_contain_strings(book.join("print.html"), &[r#"id="unicode""#]);
+    assert_doesnt_contain_strings(book.join("second.html"), &[r#"href="first/unicode.html""#]);
+}
+
+#[test]
+fn title_directive_replaces_the_page_title() {
+    let temp = DummyBook::new().build().unwrap();
+    write_file(temp.path(), "book.toml", b"[book]\ntitle = \"Dummy\"\n").unwrap();
+    let second = "+++\ntitle = \"From The Frontmatter\"\n+++\n\
+                  {{#title Frequently Asked Questions}}\n# Second Chapter\n\n\
+                  ```hbs\n{{#title Not This One}}\n```\n";
+    write_file(&temp.path().join("src"), "second.md", second.as_bytes()).unwrap();
+    let nested = "{{#title Nested Title}}\n# Nested Chapter\n";
+    write_file(
+        &temp.path().join("src"),
+        "first/nested.md",
+        nested.as_bytes(),
+    )
+    .unwrap();
+
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
     assert_contains_strings(
-        &path,
+        book.join("second.html"),
         &[
-            "<li><input disabled=\"\" type=\"checkbox\" checked=\"\"/>\nApples",
-            "<li><input disabled=\"\" type=\"checkbox\" checked=\"\"/>\nBroccoli",
-            "<li><input disabled=\"\" type=\"checkbox\"/>\nCarrots",
+            "<title>Frequently Asked Questions</title>",
+            "{{#title Not This One}}",
+            r#"<a href="second.html" class="active"><strong aria-hidden="true">2.</strong> Second Chapter</a>"#,
         ],
     );
+    assert_doesnt_contain_strings(
+        book.join("second.html"),
+        &["From The Frontmatter", "{{#title Frequently"],
+    );
+    assert_contains_strings(
+        book.join("first/nested.html"),
+        &["<title>Nested Title</title>"],
+    );
 }
 
-#[cfg(feature = "search")]
-mod search {
-    use crate::dummy_book::DummyBook;
-    use mdbook::MDBook;
-    use std::fs::{self, File};
-    use std::path::Path;
+#[test]
+fn a_chapter_can_only_have_one_title_directive() {
+    let temp = DummyBook::new().build().unwrap();
+    let second = "{{#title One}}\n# Second Chapter\n\n{{#title Two}}\n";
+    write_file(&temp.path().join("src"), "second.md", second.as_bytes()).unwrap();
 
-    fn read_book_index(root: &Path) -> serde_json::Value {
-        let index = root.join("book/searchindex.js");
-        let index = fs::read_to_string(index).unwrap();
-        let index = index.trim_start_matches("Object.assign(window.search, ");
-        let index = index.trim_end_matches(");");
-        serde_json::from_str(&index).unwrap()
-    }
+    let got = MDBook::load(temp.path()).unwrap().build();
 
-    #[test]
-    #[allow(clippy::float_cmp)]
-    fn book_creates_reasonable_search_index() {
-        let temp = DummyBook::new().build().unwrap();
+    let err = format!("{:?}", got.err().unwrap());
+    assert!(
+        err.contains("The chapter \"Second Chapter\" has 2 `{{#title}}`s"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn additional_css_and_js_are_copied_to_the_destination() {
+    let temp = DummyBook::new().build().unwrap();
+    write_file(temp.path(), "css/custom.css", b"/* custom */").unwrap();
+    write_file(temp.path(), "js/custom.js", b"// custom").unwrap();
+    let book_toml = r#"
+        [output.html]
+        additional-css = ["css/custom.css"]
+        additional-js = ["js/custom.js"]
+    "#;
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_eq!(
+        fs::read_to_string(book.join("css/custom.css")).unwrap(),
+        "/* custom */"
+    );
+    assert_eq!(
+        fs::read_to_string(book.join("js/custom.js")).unwrap(),
+        "// custom"
+    );
+    assert_contains_strings(
+        book.join("first/nested.html"),
+        &[
+            r#"<link rel="stylesheet" href="../css/custom.css">"#,
+            r#"<script type="text/javascript" src="../js/custom.js"></script>"#,
+        ],
+    );
+    assert_contains_strings(
+        book.join("index.html"),
+        &[r#"<link rel="stylesheet" href="css/custom.css">"#],
+    );
+}
+
+#[test]
+fn additional_files_must_exist_inside_the_book() {
+    let temp = DummyBook::new().build().unwrap();
+    let build_with = |additional_css: &str| {
+        let book_toml = format!("[output.html]\nadditional-css = [{:?}]\n", additional_css);
+        write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
         let md = MDBook::load(temp.path()).unwrap();
-        md.build().unwrap();
+        format!("{:?}", md.build().unwrap_err())
+    };
 
-        let index = read_book_index(temp.path());
+    let err = build_with("missing.css");
+    assert!(
+        err.contains("Unable to find the additional file missing.css"),
+        "{}",
+        err
+    );
 
-        let doc_urls = index["doc_urls"].as_array().unwrap();
-        let get_doc_ref =
-            |url: &str| -> String { doc_urls.iter().position(|s| s == url).unwrap().to_string() };
+    let err = build_with("src/../../outside.css");
+    assert!(
+        err.contains("The additional file src/../../outside.css isn't inside the book root"),
+        "{}",
+        err
+    );
+}
 
-        let first_chapter = get_doc_ref("first/index.html#first-chapter");
-        let introduction = get_doc_ref("intro.html#introduction");
-        let some_section = get_doc_ref("first/index.html#some-section");
-        let summary = get_doc_ref("first/includes.html#summary");
-        let conclusion = get_doc_ref("conclusion.html#conclusion");
+#[test]
+fn static_files_and_extra_static_dirs_are_copied() {
+    let temp = DummyBook::new().build().unwrap();
+    let src = temp.path().join("src");
+    write_file(&src, "images/diagram.png", b"png").unwrap();
+    write_file(temp.path(), "assets/downloads/book.pdf", b"pdf").unwrap();
+    write_file(temp.path(), "assets/README.md", b"readme").unwrap();
+    let nested = fs::read_to_string(src.join("first/nested.md")).unwrap();
+    let nested = nested + "\n![Diagram](../images/diagram.png)\n";
+    write_file(&src, "first/nested.md", nested.as_bytes()).unwrap();
+    let book_toml = "[output.html]\nextra-static-dirs = [\"assets\"]\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
 
-        let bodyidx = &index["index"]["index"]["body"]["root"];
-        let textidx = &bodyidx["t"]["e"]["x"]["t"];
-        assert_eq!(textidx["df"], 2);
-        assert_eq!(textidx["docs"][&first_chapter]["tf"], 1.0);
-        assert_eq!(textidx["docs"][&introduction]["tf"], 1.0);
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
 
-        let docs = &index["index"]["documentStore"]["docs"];
-        assert_eq!(docs[&first_chapter]["body"], "more text.");
-        assert_eq!(docs[&some_section]["body"], "");
-        assert_eq!(
-            docs[&summary]["body"],
-            "Dummy Book Introduction First Chapter Nested Chapter Includes Recursive Markdown Unicode Second Chapter Nested Chapter Conclusion"
-        );
-        assert_eq!(docs[&summary]["breadcrumbs"], "First Chapter » Summary");
-        assert_eq!(docs[&conclusion]["body"], "I put &lt;HTML&gt; in here!");
+    let book = temp.path().join("book");
+    assert_contains_strings(
+        book.join("first/nested.html"),
+        &[r#"<img src="../images/diagram.png" alt="Diagram" />"#],
+    );
+    assert_eq!(fs::read(book.join("images/diagram.png")).unwrap(), b"png");
+    assert_eq!(
+        fs::read(book.join("assets/downloads/book.pdf")).unwrap(),
+        b"pdf"
+    );
+    assert_eq!(fs::read(book.join("assets/README.md")).unwrap(), b"readme");
+    assert!(!book.join("first/nested.md").exists());
+
+    let book_toml = "[output.html]\nextra-static-dirs = [\"missing\"]\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    let md = MDBook::load(temp.path()).unwrap();
+    let err = format!("{:?}", md.build().unwrap_err());
+    assert!(
+        err.contains("The extra static directory missing doesn't exist"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn chapters_can_add_their_own_css_and_js() {
+    let temp = DummyBook::new().build().unwrap();
+    let nested = fs::read_to_string(temp.path().join("src/first/nested.md")).unwrap();
+    let frontmatter =
+        "+++\nextra-js = [\"scripts/diagram.js\"]\nextra-css = [\"diagram.css\"]\n+++\n";
+    write_file(
+        &temp.path().join("src"),
+        "first/nested.md",
+        (frontmatter.to_string() + &nested).as_bytes(),
+    )
+    .unwrap();
+    write_file(temp.path(), "scripts/diagram.js", b"// diagram").unwrap();
+    write_file(temp.path(), "diagram.css", b"/* diagram */").unwrap();
+    write_file(temp.path(), "custom.css", b"/* everywhere */").unwrap();
+    let book_toml = "[output.html]\nadditional-css = [\"custom.css\"]\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_contains_strings(
+        book.join("first/nested.html"),
+        &[
+            r#"<script type="text/javascript" src="../scripts/diagram.js"></script>"#,
+            r#"<link rel="stylesheet" href="../diagram.css">"#,
+            r#"<link rel="stylesheet" href="../custom.css">"#,
+        ],
+    );
+    assert_contains_strings(
+        book.join("print.html"),
+        &[r#"<script type="text/javascript" src="scripts/diagram.js"></script>"#],
+    );
+    assert_contains_strings(
+        book.join("second.html"),
+        &[r#"<link rel="stylesheet" href="custom.css">"#],
+    );
+    assert_doesnt_contain_strings(book.join("second.html"), &["diagram.js", "diagram.css"]);
+    assert!(book.join("scripts/diagram.js").is_file());
+    assert!(book.join("diagram.css").is_file());
+
+    fs::remove_file(temp.path().join("scripts/diagram.js")).unwrap();
+    let err = format!("{:?}", md.build().unwrap_err());
+    assert!(
+        err.contains(
+            r#"Unable to find the extra-js file "scripts/diagram.js" of "Nested Chapter""#
+        ),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn malformed_frontmatter_names_the_chapter() {
+    let temp = DummyBook::new().build().unwrap();
+    let broken = "+++\ntitle = \n+++\n# Second Chapter\n";
+    write_file(&temp.path().join("src"), "second.md", broken.as_bytes()).unwrap();
+
+    let got = MDBook::load(temp.path());
+
+    let err = format!("{:?}", got.err().unwrap());
+    assert!(
+        err.contains("Unable to parse the frontmatter of \"Second Chapter\""),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn google_analytics_snippet_depends_on_the_id() {
+    let temp = DummyBook::new().build().unwrap();
+    let index = temp.path().join("book/index.html");
+    let build_with = |google_analytics: Option<&str>| {
+        let book_toml = match google_analytics {
+            Some(id) => format!("[output.html]\ngoogle-analytics = {:?}\n", id),
+            None => String::new(),
+        };
+        write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+        MDBook::load(temp.path()).unwrap().build().unwrap();
+    };
+
+    build_with(None);
+    assert_doesnt_contain_strings(&index, &["Google Analytics Tag", "gtag.js"]);
+
+    build_with(Some("UA-12345-6"));
+    assert_contains_strings(&index, &["ga('create', 'UA-12345-6', 'auto');"]);
+    assert_doesnt_contain_strings(&index, &["gtag.js"]);
+
+    build_with(Some("G-ABC123DEF4"));
+    assert_contains_strings(
+        &index,
+        &[
+            "https://www.googletagmanager.com/gtag/js?id=G-ABC123DEF4",
+            "gtag('config', 'G-ABC123DEF4');",
+        ],
+    );
+    assert_doesnt_contain_strings(&index, &["analytics.js"]);
+}
+
+#[test]
+fn site_url_adds_canonical_and_open_graph_tags() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[book]\ntitle = \"Dummy\"\ndescription = \"A dummy book\"\n\n\
+                     [output.html]\nsite-url = \"https://example.com/docs\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_contains_strings(
+        book.join("first/nested.html"),
+        &[
+            r#"<link rel="canonical" href="https://example.com/docs/first/nested.html">"#,
+            r#"<meta property="og:title" content="Nested Chapter - Dummy">"#,
+            r#"<meta property="og:description" content="A dummy book">"#,
+            r#"<meta property="og:url" content="https://example.com/docs/first/nested.html">"#,
+        ],
+    );
+    assert_contains_strings(
+        book.join("index.html"),
+        &[r#"<link rel="canonical" href="https://example.com/docs/">"#],
+    );
+    assert_contains_strings(
+        book.join("intro.html"),
+        &[r#"<link rel="canonical" href="https://example.com/docs/intro.html">"#],
+    );
+}
+
+#[test]
+fn sitemap_lists_every_chapter() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[output.html]\nsite-url = \"https://example.com/docs/\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let chapters = md
+        .iter()
+        .filter(|item| match item {
+            BookItem::Chapter(ch) => !ch.is_draft_chapter(),
+            _ => false,
+        })
+        .count();
+    let sitemap = fs::read_to_string(temp.path().join("book/sitemap.xml")).unwrap();
+    let doc = Document::from(sitemap.as_str());
+    let locs: Vec<_> = doc.find(Name("url").descendant(Name("loc"))).collect();
+    assert_eq!(locs.len(), chapters);
+    assert_eq!(doc.find(Name("lastmod")).count(), chapters);
+    assert_eq!(locs[0].text(), "https://example.com/docs/");
+    assert!(locs
+        .iter()
+        .any(|loc| loc.text() == "https://example.com/docs/first/nested.html"));
+
+    let book_toml = "[output.html]\nsite-url = \"https://example.com/docs/\"\nsitemap = false\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+    assert!(!temp.path().join("book/sitemap.xml").exists());
+
+    // A site URL which is only a path has no absolute URLs to list
+    let book_toml = "[output.html]\nsite-url = \"/docs/\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+    assert!(!temp.path().join("book/sitemap.xml").exists());
+}
+
+#[test]
+fn a_404_page_is_generated() {
+    let temp = DummyBook::new().build().unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let page = temp.path().join("book/404.html");
+    assert_contains_strings(
+        &page,
+        &[
+            "Document not found (404)",
+            r#"<link rel="stylesheet" href="/css/general.css">"#,
+            r#"<a href="/first/nested.html">"#,
+        ],
+    );
+    // It isn't one of the chapters
+    assert_doesnt_contain_strings(
+        temp.path().join("book/intro.html"),
+        &["404.html", "Document not found"],
+    );
+}
+
+#[test]
+fn custom_404_page_with_a_site_url_subpath() {
+    let temp = DummyBook::new().build().unwrap();
+    write_file(
+        &temp.path().join("src"),
+        "missing.md",
+        b"# Nothing here\n\nGo [home](index.html).",
+    )
+    .unwrap();
+    let book_toml = "[output.html]\n\
+                     site-url = \"https://example.com/docs/v1\"\n\
+                     input-404 = \"missing.md\"\n\
+                     output-404 = \"not-found.html\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
+    assert!(!book.join("404.html").exists());
+    assert!(!book.join("missing.html").exists());
+    assert_contains_strings(
+        book.join("not-found.html"),
+        &[
+            "Nothing here",
+            r#"<link rel="stylesheet" href="/docs/v1/css/general.css">"#,
+            r#"<a href="/docs/v1/first/nested.html">"#,
+        ],
+    );
+    assert_doesnt_contain_strings(book.join("not-found.html"), &["Document not found"]);
+}
+
+#[test]
+fn redirects_are_written_for_moved_pages() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[output.html.redirect]\n\
+                     \"/old/nested.html\" = \"../first/nested.html#nested-chapter\"\n\
+                     \"/older.html\" = \"/old/nested.html\"\n\
+                     \"/rust.html\" = \"https://www.rust-lang.org/\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_contains_strings(
+        book.join("old/nested.html"),
+        &[r#"<meta http-equiv="refresh" content="0; URL=../first/nested.html#nested-chapter">"#],
+    );
+    assert_contains_strings(
+        book.join("older.html"),
+        &[r#"<link rel="canonical" href="first/nested.html#nested-chapter">"#],
+    );
+    assert_contains_strings(
+        book.join("rust.html"),
+        &[r#"URL=https://www.rust-lang.org/""#],
+    );
+}
+
+#[test]
+fn redirects_cant_replace_chapters() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[output.html.redirect]\n\"/intro.html\" = \"first/index.html\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+
+    let err = MDBook::load(temp.path()).unwrap().build().unwrap_err();
+
+    assert!(
+        format!("{:?}", err).contains("The redirect of \"/intro.html\" would overwrite a page"),
+        "{:?}",
+        err
+    );
+}
+
+#[test]
+fn github_pages_files() {
+    let temp = DummyBook::new().build().unwrap();
+    let book = temp.path().join("book");
+
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+    assert!(book.join(".nojekyll").exists());
+    assert!(!book.join("CNAME").exists());
+
+    let book_toml = "[output.html]\nnojekyll = false\ncname = \"docs.example.com\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+    assert!(!book.join(".nojekyll").exists());
+    assert_eq!(
+        fs::read_to_string(book.join("CNAME")).unwrap(),
+        "docs.example.com\n"
+    );
+
+    let book_toml = "[output.html]\ncname = \"https://docs.example.com/\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    let err = MDBook::load(temp.path()).unwrap().build().unwrap_err();
+    assert!(
+        format!("{:?}", err).contains("`output.html.cname` should be a domain"),
+        "{:?}",
+        err
+    );
+}
+
+#[test]
+fn pretty_urls_give_every_chapter_a_directory() {
+    let plain = DummyBook::new().build().unwrap();
+    MDBook::load(plain.path()).unwrap().build().unwrap();
+    let pretty = DummyBook::new().build().unwrap();
+    let book_toml = "[output.html]\npretty-urls = true\n";
+    write_file(pretty.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    MDBook::load(pretty.path()).unwrap().build().unwrap();
+
+    let plain_book = plain.path().join("book");
+    let pretty_book = pretty.path().join("book");
+    let pages = [
+        ("index.html", "index.html"),
+        ("intro.html", "intro/index.html"),
+        ("first/index.html", "first/index.html"),
+        ("first/nested.html", "first/nested/index.html"),
+        ("second/nested.html", "second/nested/index.html"),
+    ];
+    for &(plain_page, pretty_page) in &pages {
+        assert!(plain_book.join(plain_page).is_file(), "{}", plain_page);
+        assert!(pretty_book.join(pretty_page).is_file(), "{}", pretty_page);
+    }
+    assert!(!pretty_book.join("first/nested.html").exists());
+
+    assert_contains_strings(
+        plain_book.join("second/nested.html"),
+        &[
+            r#"href="../css/general.css""#,
+            r#"<a href="../first/nested.html">the first section</a>"#,
+            r#"<img src="../images/picture.png""#,
+            r#"<a href="../first/markdown.html">HTML Link</a>"#,
+            r#"<a href="../first/index.html""#,
+        ],
+    );
+    assert_contains_strings(
+        pretty_book.join("second/nested/index.html"),
+        &[
+            r#"href="../../css/general.css""#,
+            r#"<a href="../../first/nested/">the first section</a>"#,
+            r#"<img src="../../images/picture.png""#,
+            r#"<a href="../../first/markdown/">HTML Link</a>"#,
+            r#"<a href="../../first/""#,
+            r##"<a href="#some-section">fragment link</a>"##,
+        ],
+    );
+    assert_contains_strings(
+        pretty_book.join("index.html"),
+        &[r#"href="css/general.css""#, r#"<a href="intro/""#],
+    );
+    if cfg!(feature = "search") {
+        assert_contains_strings(
+            pretty_book.join("searchindex.json"),
+            &[r#""first/nested/#nested-chapter""#],
+        );
+    }
+    assert_contains_strings(
+        pretty_book.join("print.html"),
+        &[r##"<a href="#first-nested-md">the first section</a>"##],
+    );
+}
+
+#[test]
+fn pretty_urls_of_two_chapters_at_one_page_dont_build() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[output.html]\npretty-urls = true\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    let src = temp.path().join("src");
+    write_file(&src, "intro/index.md", b"# Another intro").unwrap();
+    let summary = "# Summary\n\n- [Intro](intro.md)\n- [Another](intro/index.md)\n";
+    write_file(&src, "SUMMARY.md", summary.as_bytes()).unwrap();
+
+    let err = MDBook::load(temp.path()).unwrap().build().unwrap_err();
+
+    assert_eq!(
+        format!("{:#}", err),
+        "Rendering failed: Both intro.md and intro/index.md would be rendered to \
+         intro/index.html, rename one of them"
+    );
+}
+
+#[test]
+fn no_canonical_url_without_site_url() {
+    let temp = DummyBook::new().build().unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    assert_doesnt_contain_strings(
+        temp.path().join("book/intro.html"),
+        &["rel=\"canonical\"", "og:url"],
+    );
+}
+
+#[test]
+fn head_extra_is_injected_into_every_page() {
+    let temp = DummyBook::new().build().unwrap();
+    let snippet = r#"<script defer data-domain="example.com" src="https://plausible.io/js/script.js"></script>"#;
+    let book_toml = format!("[output.html]\nhead-extra = {:?}\n", snippet);
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
+    for page in &["index.html", "first/nested.html", "print.html"] {
+        let html = fs::read_to_string(book.join(page)).unwrap();
+        let injected = html
+            .find(snippet)
+            .expect("The snippet should be on every page");
+        assert!(injected < html.find("</head>").unwrap());
+    }
+
+    write_file(
+        temp.path(),
+        "head.html",
+        b"<meta name=\"verify\" content=\"1\">",
+    )
+    .unwrap();
+    let book_toml = "[output.html]\nhead-extra = { file = \"head.html\" }\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+    assert_contains_strings(
+        book.join("index.html"),
+        &[r#"<meta name="verify" content="1">"#],
+    );
+
+    // The file is read again on every build
+    write_file(
+        temp.path(),
+        "head.html",
+        b"<meta name=\"verify\" content=\"2\">",
+    )
+    .unwrap();
+    md.build().unwrap();
+    assert_contains_strings(
+        book.join("index.html"),
+        &[r#"<meta name="verify" content="2">"#],
+    );
+
+    write_file(temp.path(), "head.html", b"<meta name=\"x\"></HEAD><body>").unwrap();
+    let err = format!("{:?}", md.build().unwrap_err());
+    assert!(
+        err.contains("`output.html.head-extra` can't contain `</head>`"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn theme_static_files_are_copied_to_the_destination() {
+    let temp = DummyBook::new().build().unwrap();
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let book = temp.path().join("book");
+    let expected = [
+        "book.js",
+        "css/general.css",
+        "css/chrome.css",
+        "css/print.css",
+        "css/variables.css",
+        "favicon.png",
+        "highlight.css",
+        "tomorrow-night.css",
+        "ayu-highlight.css",
+        "highlight.js",
+        "clipboard.min.js",
+        "FontAwesome/css/font-awesome.css",
+        "FontAwesome/fonts/fontawesome-webfont.woff2",
+        "fonts/fonts.css",
+    ];
+    for file in &expected {
+        assert!(book.join(file).is_file(), "{} wasn't copied", file);
+    }
+
+    // The playpen editor is only copied across when it's needed
+    assert!(!book.join("editor.js").exists());
+    assert!(!book.join("ace.js").exists());
+}
+
+#[test]
+fn playpen_editor_files_are_copied_when_editable() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.set("output.html.playpen.editable", true).unwrap();
+    md.build().unwrap();
+
+    let book = temp.path().join("book");
+    for file in &[
+        "editor.js",
+        "ace.js",
+        "mode-rust.js",
+        "theme-dawn.js",
+        "theme-tomorrow_night.js",
+    ] {
+        assert!(book.join(file).is_file(), "{} wasn't copied", file);
+    }
+}
+
+#[test]
+fn print_page_puts_each_chapter_on_its_own_page() {
+    let temp = DummyBook::new().build().unwrap();
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let print_html = temp.path().join("book/print.html");
+    assert_contains_strings(
+        &print_html,
+        &[r#"<div style="break-before: page; page-break-before: always;"></div>"#],
+    );
+
+    let index_html = temp.path().join("book/index.html");
+    assert_contains_strings(index_html, &[r#"id="print-button""#]);
+}
+
+#[test]
+fn print_page_can_be_disabled() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.set("output.html.print.enable", false).unwrap();
+    md.build().unwrap();
+
+    assert!(!temp.path().join("book/print.html").exists());
+
+    let index_html = temp.path().join("book/index.html");
+    assert_doesnt_contain_strings(index_html, &[r#"id="print-button""#]);
+}
+
+#[test]
+fn the_menu_bar_has_the_configured_buttons() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = r#"
+[output.html]
+git-repository-url = "https://github.com/rust-lang/mdBook"
+
+[[output.html.nav-buttons]]
+icon = "fa-comments"
+url = "https://users.rust-lang.org/"
+title = "Forum & \"chat\""
+
+[[output.html.nav-buttons]]
+url = "first/index.html"
+"#;
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    assert_contains_strings(
+        temp.path().join("book/first/nested.html"),
+        &[
+            r#"<a href="../print.html" title="Print this book" aria-label="Print this book">"#,
+            r#"<i id="print-button" class="fa fa-print"></i>"#,
+            r#"<a href="https://github.com/rust-lang/mdBook" title="Git repository""#,
+            r#"<i id="git-repository-button" class="fa fa-github"></i>"#,
+            r#"<a href="https://users.rust-lang.org/" title="Forum &amp; &quot;chat&quot;""#,
+            r#"<i class="fa fa-comments"></i>"#,
+            r#"<a href="../first/index.html" title="" aria-label="">"#,
+            r#"<i class="fa fa-link"></i>"#,
+        ],
+    );
+
+    let book_toml = "[[output.html.nav-buttons]]\nicon = \"fa-github\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    let err = MDBook::load(temp.path()).unwrap().build().unwrap_err();
+    assert!(format!("{:#}", err).contains("`output.html.nav-buttons[0]` needs a `url`"));
+}
+
+#[test]
+fn templates_can_show_the_build_metadata() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut index = mdbook::theme::INDEX.to_vec();
+    index.extend_from_slice(
+        b"\n<!-- mdBook {{ mdbook_version }}, built at {{ build_timestamp }} \
+          {{#if git_commit}}from {{ git_short_commit }}{{else}}outside of git{{/if}} -->",
+    );
+    write_file(&temp.path().join("theme"), "index.hbs", &index).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let index_html = temp.path().join("book/index.html");
+    assert_contains_strings(
+        &index_html,
+        &[
+            &format!("<!-- mdBook {}, built at 2", env!("CARGO_PKG_VERSION")),
+            "outside of git -->",
+        ],
+    );
+
+    // Commit the book, if git is installed
+    if !commit(temp.path(), &["."], "2020-06-01T12:00:00+00:00") {
+        return;
+    }
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+    assert_doesnt_contain_strings(&index_html, &["outside of git"]);
+    assert_contains_strings(&index_html, &[" from "]);
+
+    let book_toml = "[output.html]\ngit-metadata = false\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+    assert_contains_strings(&index_html, &["outside of git -->"]);
+}
+
+/// Commit the `files` of the repository at `root` (creating it if needed) at
+/// `date`, returning `false` when git isn't installed.
+fn commit(root: &Path, files: &[&str], date: &str) -> bool {
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args(args)
+            .env("GIT_AUTHOR_NAME", "mdBook")
+            .env("GIT_AUTHOR_EMAIL", "mdbook@example.com")
+            .env("GIT_AUTHOR_DATE", date)
+            .env("GIT_COMMITTER_NAME", "mdBook")
+            .env("GIT_COMMITTER_EMAIL", "mdbook@example.com")
+            .env("GIT_COMMITTER_DATE", date)
+            .current_dir(root)
+            .output()
+            .map(|output| output.status.success())
+            .unwrap_or(false)
+    };
+    (root.join(".git").exists() || git(&["init", "-q"]))
+        && git(&[&["add", "--"][..], files].concat())
+        && git(&["commit", "-q", "-m", "Book"])
+}
+
+#[test]
+fn chapters_know_when_they_were_last_modified() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut index = mdbook::theme::INDEX.to_vec();
+    index.extend_from_slice(
+        b"\n<!-- Modified {{ last_modified }} -->\
+          {{#each chapters}}{{#if path}}\n<!-- {{ path }}: {{ last_modified }} -->{{/if}}{{/each}}",
+    );
+    write_file(&temp.path().join("theme"), "index.hbs", &index).unwrap();
+    let book_toml = "[output.html]\nlast-modified = true\nsite-url = \"https://example.com/\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+
+    let src = temp.path().join("src");
+    if !commit(
+        temp.path(),
+        &["book.toml", "theme", "src/intro.md", "src/second.md"],
+        "2020-01-01T12:00:00+00:00",
+    ) {
+        return;
+    }
+    write_file(&src, "second.md", b"# Second Chapter\n\nRewritten.\n").unwrap();
+    assert!(commit(
+        temp.path(),
+        &["src/second.md"],
+        "2020-06-01T12:00:00+00:00"
+    ));
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_contains_strings(
+        book.join("intro.html"),
+        &[
+            "<!-- Modified 2020-01-01T12:00:00+00:00 -->",
+            "<!-- intro.md: 2020-01-01T12:00:00+00:00 -->",
+            "<!-- second.md: 2020-06-01T12:00:00+00:00 -->",
+        ],
+    );
+    assert_contains_strings(
+        book.join("second.html"),
+        &["<!-- Modified 2020-06-01T12:00:00+00:00 -->"],
+    );
+    // The files git doesn't know have their modification time
+    let conclusion = book.join("conclusion.html");
+    assert_contains_strings(&conclusion, &["<!-- Modified 2"]);
+    assert_doesnt_contain_strings(&conclusion, &["<!-- Modified 2020-0"]);
+    assert_contains_strings(
+        book.join("sitemap.xml"),
+        &[
+            "<loc>https://example.com/intro.html</loc>\n    <lastmod>2020-01-01</lastmod>",
+            "<loc>https://example.com/second.html</loc>\n    <lastmod>2020-06-01</lastmod>",
+        ],
+    );
+
+    let book_toml = "[output.html]\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+    assert_contains_strings(book.join("intro.html"), &["<!-- Modified  -->"]);
+}
+
+#[test]
+fn missing_theme_dir_is_an_error() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config
+        .set("output.html.theme", "non-existent-theme")
+        .unwrap();
+
+    let got = md.build();
+    assert!(got.is_err());
+}
+
+#[test]
+fn edit_url_template_links_to_the_source_file() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config
+        .set(
+            "output.html.edit-url-template",
+            "https://github.com/rust-lang/mdBook/edit/master/{path}",
+        )
+        .unwrap();
+    md.build().unwrap();
+
+    let nested = temp.path().join("book/first/nested.html");
+    assert_contains_strings(
+        nested,
+        &[r#"href="https://github.com/rust-lang/mdBook/edit/master/src/first/nested.md""#],
+    );
+
+    // The index preprocessor renames README.md, but edits should go to the
+    // original file
+    let index = temp.path().join("book/index.html");
+    assert_contains_strings(
+        index,
+        &[r#"href="https://github.com/rust-lang/mdBook/edit/master/src/README.md""#],
+    );
+}
+
+#[test]
+fn edit_url_template_without_a_path_is_an_error() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config
+        .set("output.html.edit-url-template", "https://example.com/edit")
+        .unwrap();
+
+    let err = md.build().unwrap_err();
+    assert!(format!("{:?}", err).contains("{path}"));
+}
+
+#[test]
+fn no_index_for_print_html() {
+    let temp = DummyBook::new().build().unwrap();
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let print_html = temp.path().join("book/print.html");
+    assert_contains_strings(print_html, &[r##"noindex"##]);
+
+    let index_html = temp.path().join("book/index.html");
+    assert_doesnt_contain_strings(index_html, &[r##"noindex"##]);
+}
+
+#[test]
+fn livereload_is_only_embedded_when_serving() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let index_html = temp.path().join("book/index.html");
+    assert_doesnt_contain_strings(&index_html, &["WebSocket"]);
+
+    md.config
+        .set(
+            "output.html.livereload-url",
+            "ws://localhost:3000/__livereload",
+        )
+        .unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(
+        &index_html,
+        &[r#"new WebSocket("ws://localhost:3000/__livereload")"#],
+    );
+}
+
+#[test]
+fn rendering_leaves_no_temporary_files_behind() {
+    let temp = DummyBook::new().build().unwrap();
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+    // and again, so every file is replaced
+    md.build().unwrap();
+
+    let leftovers: Vec<_> = WalkDir::new(temp.path().join("book"))
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| entry_ends_with(entry, ".tmp"))
+        .map(|entry| entry.path().display().to_string())
+        .collect();
+    assert!(leftovers.is_empty(), "leftover files: {:?}", leftovers);
+    assert!(temp.path().join("book/first/nested.html").exists());
+}
+
+#[test]
+fn markdown_options() {
+    let temp = DummyBook::new().build().unwrap();
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let path = temp.path().join("book/first/markdown.html");
+    assert_contains_strings(
+        &path,
+        &[
+            "<th>foo</th>",
+            "<th>bar</th>",
+            "<td>baz</td>",
+            "<td>bim</td>",
+        ],
+    );
+    assert_contains_strings(
+        &path,
+        &[
+            r##"<sup class="footnote-reference" id="first-markdown-fnref-1"><a href="#first-markdown-fn-1">1</a></sup>"##,
+            r##"<sup class="footnote-reference" id="first-markdown-fnref-word"><a href="#first-markdown-fn-word">2</a></sup>"##,
+            r##"<li id="first-markdown-fn-1">"##,
+            r##"<li id="first-markdown-fn-word">"##,
+        ],
+    );
+    assert_contains_strings(&path, &["<del>strikethrough example</del>"]);
+    assert_contains_strings(
+        &path,
+        &[
+            "<li><input disabled=\"\" type=\"checkbox\" checked=\"\"/>\nApples",
+            "<li><input disabled=\"\" type=\"checkbox\" checked=\"\"/>\nBroccoli",
+            "<li><input disabled=\"\" type=\"checkbox\"/>\nCarrots",
+        ],
+    );
+}
+
+#[test]
+#[cfg(feature = "syntect")]
+fn code_can_be_highlighted_when_the_book_is_built() {
+    let temp = DummyBook::new().build().unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let nested = temp.path().join("book/first/nested.html");
+    assert_contains_strings(&nested, &["highlight.js"]);
+    assert_doesnt_contain_strings(&nested, &["<span style="]);
+    assert!(temp.path().join("book/highlight.js").exists());
+
+    let book_toml = "[output.html]\nhighlight = \"syntect\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    fs::remove_dir_all(temp.path().join("book")).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    assert_contains_strings(
+        &nested,
+        &[r#"<code class="language-rust syn-code"><span class="syn-source syn-rust">"#],
+    );
+    assert_doesnt_contain_strings(&nested, &["highlight.js", "<span style="]);
+    assert!(!temp.path().join("book/highlight.js").exists());
+    // The stylesheets of syntect's themes replace the ones of highlight.js
+    let highlight_css = temp.path().join("book/highlight.css");
+    assert_contains_strings(&highlight_css, &[".syn-code {", ".syn-keyword"]);
+    assert_doesnt_contain_strings(&highlight_css, &[".hljs"]);
+    let dark_css = fs::read_to_string(temp.path().join("book/ayu-highlight.css")).unwrap();
+    assert!(dark_css.contains(".syn-code {"));
+    assert_ne!(dark_css, fs::read_to_string(&highlight_css).unwrap());
+
+    let book_toml =
+        "[output.html]\nhighlight = \"syntect\"\n[output.html.syntect]\ntheme = \"Neon\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    let err = MDBook::load(temp.path()).unwrap().build().unwrap_err();
+    assert!(
+        format!("{:?}", err).contains("Unknown `output.html.syntect.theme` \"Neon\""),
+        "{:?}",
+        err
+    );
+}
+
+#[test]
+fn markdown_extensions_can_be_turned_off() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[output.html]\ntables = false\nstrikethrough = false\ntask-lists = false\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let path = temp.path().join("book/first/markdown.html");
+    assert_contains_strings(
+        &path,
+        &["| foo | bar |", "~~strikethrough example~~", "[X] Apples"],
+    );
+    assert_doesnt_contain_strings(&path, &["<table>", "<del>", "type=\"checkbox\""]);
+}
+
+#[test]
+fn the_first_h1_can_be_the_title_and_headings_shifted() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[output.html]\nstrip-first-h1 = true\nheading-offset = 1\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    let second = "# Renamed Chapter\n\nText.\n\n## Section\n\n##### Deep\n\n###### Deepest\n";
+    write_file(&temp.path().join("src"), "second.md", second.as_bytes()).unwrap();
+    let unicode = "Starts with text.\n\n# Not A Title\n";
+    write_file(
+        &temp.path().join("src"),
+        "first/unicode.md",
+        unicode.as_bytes(),
+    )
+    .unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
+    let second = book.join("second.html");
+    assert_contains_strings(
+        &second,
+        &[
+            "<title>Renamed Chapter</title>",
+            r##"<strong aria-hidden="true">2.</strong> Renamed Chapter</a>"##,
+            r##"<h3><a class="header" href="#section" id="section">"##,
+            r##"<h6><a class="header" href="#deep" id="deep">"##,
+            r##"<h6><a class="header" href="#deepest" id="deepest">"##,
+        ],
+    );
+    assert_doesnt_contain_strings(&second, &["id=\"renamed-chapter\"", "Second Chapter"]);
+
+    // Chapters which don't start with an H1 keep it, and their name
+    assert_contains_strings(
+        book.join("first/unicode.html"),
+        &[
+            "<title>Unicode</title>",
+            r##"<h2><a class="header" href="#not-a-title" id="not-a-title">"##,
+        ],
+    );
+
+    // The print page keeps the H1s, shifted like the other headings
+    assert_contains_strings(
+        book.join("print.html"),
+        &[r##"id="second-md-renamed-chapter">Renamed Chapter</a></h2>"##],
+    );
+}
+
+#[test]
+fn chapters_list_their_headings_for_an_in_page_toc() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[output.html]\nstrip-first-h1 = true\nheading-offset = 1\n\
+                     [output.html.pagetoc]\nmax-level = 4\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    let second = "# Second Chapter\n\n## Usage\n\n### Details\n\n## Usage\n\n# Other\n";
+    write_file(&temp.path().join("src"), "second.md", second.as_bytes()).unwrap();
+
+    let mut index = mdbook::theme::INDEX.to_vec();
+    index.extend_from_slice(
+        b"\n<!-- {{#each page_headings}}[{{level}} {{id}}]{{/each}} -->\n{{pagetoc}}",
+    );
+    write_file(&temp.path().join("theme"), "index.hbs", &index).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_contains_strings(
+        book.join("second.html"),
+        &[
+            "<!-- [3 usage][4 details][3 usage-1][2 other] -->",
+            "<ol class=\"pagetoc\">\n<li><a href=\"#usage\">Usage</a>\n<ol>\n\
+             <li><a href=\"#details\">Details</a></li>\n</ol>\n</li>\n\
+             <li><a href=\"#usage-1\">Usage</a></li>\n\
+             <li><a href=\"#other\">Other</a></li>\n</ol>\n",
+            r##"<h3><a class="header" href="#usage-1" id="usage-1">Usage</a></h3>"##,
+        ],
+    );
+    assert_contains_strings(book.join("intro.html"), &["<!--  -->"]);
+    assert_contains_strings(book.join("print.html"), &["<!--  -->"]);
+}
+
+#[test]
+fn chapters_have_word_counts_and_reading_times() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[output.html.reading-time]\nwords-per-minute = 2\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    let second = "# Second Chapter\n\nFive words, not the code.\n\n```\nlet x = 1;\n```\n";
+    write_file(&temp.path().join("src"), "second.md", second.as_bytes()).unwrap();
+
+    let mut index = mdbook::theme::INDEX.to_vec();
+    index.extend_from_slice(
+        b"\n<!-- {{word_count}} words, {{reading_time}} min -->\n\
+          <!-- {{#each chapters}}{{#if word_count}}[{{name}}: {{reading_time}}]{{/if}}{{/each}} -->\n\
+          <!-- book: {{book_word_count}} words -->",
+    );
+    write_file(&temp.path().join("theme"), "index.hbs", &index).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_contains_strings(
+        book.join("second.html"),
+        &["<!-- 7 words, 4 min -->", "[Second Chapter: 4]"],
+    );
+    assert_contains_strings(book.join("print.html"), &["<!-- book: "]);
+}
+
+#[test]
+fn the_theme_is_in_the_book_language() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[book]\nlanguage = \"de\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    assert_contains_strings(
+        temp.path().join("book/first/index.html"),
+        &[
+            r#"<html lang="de""#,
+            r#"title="Dieses Buch drucken""#,
+            r#"title="Nächstes Kapitel""#,
+        ],
+    );
+    assert_doesnt_contain_strings(
+        temp.path().join("book/first/index.html"),
+        &["Print this book"],
+    );
+}
+
+#[test]
+fn right_to_left_languages_are_rendered_right_to_left() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[book]\nlanguage = \"ar\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_contains_strings(
+        book.join("first/nested.html"),
+        &[
+            r#"<html lang="ar" dir="rtl""#,
+            r#"class="nav-chapters previous" title="Previous chapter" aria-label="Previous chapter" aria-keyshortcuts="Left">
+                        <i class="fa fa-angle-right"></i>"#,
+        ],
+    );
+    assert_contains_strings(book.join("print.html"), &[r#"<html lang="ar" dir="rtl""#]);
+
+    let book_toml = "[book]\nlanguage = \"ar\"\ntext-direction = \"ltr\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+    assert_contains_strings(
+        book.join("first/nested.html"),
+        &[r#"<html lang="ar" dir="ltr""#, "fa-angle-left"],
+    );
+}
+
+#[test]
+fn translations_are_rendered_to_a_directory_each() {
+    let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+    let root = temp.path();
+    let book_toml = "[book]\ntitle = \"A Book\"\nlanguage = \"en\"\n\n\
+                     [language.en]\nname = \"English\"\n\n\
+                     [language.fr]\nname = \"Français\"\ntitle = \"Un livre\"\n\n\
+                     [output.html]\nsite-url = \"https://example.com/book/\"\n";
+    write_file(root, "book.toml", book_toml.as_bytes()).unwrap();
+    let src = root.join("src");
+    write_file(
+        &src,
+        "en/SUMMARY.md",
+        b"- [Intro](intro.md)\n- [Only English](english.md)\n",
+    )
+    .unwrap();
+    write_file(&src, "en/intro.md", b"# Intro\n\nHello.\n").unwrap();
+    write_file(&src, "en/english.md", b"# Only English\n").unwrap();
+    write_file(&src, "fr/SUMMARY.md", b"- [Intro](intro.md)\n").unwrap();
+    write_file(&src, "fr/intro.md", b"# Intro\n\nBonjour.\n").unwrap();
+    // Not in the SUMMARY.md of the translation
+    write_file(&src, "fr/english.md", b"# Seulement en anglais\n").unwrap();
+
+    let mut index = mdbook::theme::INDEX.to_vec();
+    index.extend_from_slice(
+        b"\n<!-- {{current_language.name}}: \
+          {{#each languages}}[{{code}} {{name}} {{link}}]{{/each}} -->",
+    );
+    write_file(&root.join("theme"), "index.hbs", &index).unwrap();
+    MDBook::load(root).unwrap().build().unwrap();
+
+    let book = root.join("book");
+    assert_contains_strings(
+        book.join("en/english.html"),
+        &[
+            "<title>Only English - A Book</title>",
+            r#"<html lang="en""#,
+            "<!-- English: [en English ../en/english.html][fr Français ../fr/index.html] -->",
+        ],
+    );
+    assert_contains_strings(
+        book.join("fr/intro.html"),
+        &[
+            "<title>Intro - Un livre</title>",
+            r#"<html lang="fr""#,
+            "Bonjour.",
+            "<!-- Français: [en English ../en/intro.html][fr Français ../fr/intro.html] -->",
+        ],
+    );
+    assert!(!book.join("fr/english.html").exists());
+    assert!(!book.join("intro.html").exists());
+    assert_contains_strings(book.join("index.html"), &["URL=en/index.html"]);
+    assert_contains_strings(
+        book.join("fr/sitemap.xml"),
+        &["<loc>https://example.com/book/fr/intro.html</loc>"],
+    );
+    if cfg!(feature = "search") {
+        assert!(book.join("en/searchindex.js").exists());
+        assert!(book.join("fr/searchindex.js").exists());
+    }
+
+    // The theme's files are shared with the default language
+    let css = fs::read(book.join("en/css/general.css")).unwrap();
+    assert_eq!(fs::read(book.join("fr/css/general.css")).unwrap(), css);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let en = fs::metadata(book.join("en/css/general.css")).unwrap();
+        let fr = fs::metadata(book.join("fr/css/general.css")).unwrap();
+        assert_eq!((en.dev(), en.ino()), (fr.dev(), fr.ino()));
+    }
+}
+
+#[test]
+fn mathjax_math_is_not_mangled_by_markdown() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[output.html]\nmathjax-support = true\n\
+                     mathjax-source = \"/mathjax/MathJax.js\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    let second = r"# Second Chapter
+
+The sum \\( x_i * y_i + x_j * y_j \\) has *two* terms.
+
+\\[ \sum_{i=0}^{n} a_i * b_i \\]
+
+`\\( x_i * y_i \\)` is how it's written.
+";
+    write_file(&temp.path().join("src"), "second.md", second.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_contains_strings(
+        book.join("second.html"),
+        &[
+            r#"<script async type="text/javascript" src="/mathjax/MathJax.js"></script>"#,
+            r"<p>The sum \( x_i * y_i + x_j * y_j \) has <em>two</em> terms.</p>",
+            r"<p>\[ \sum_{i=0}^{n} a_i * b_i \]</p>",
+            r"<p><code>\\( x_i * y_i \\)</code> is how it's written.</p>",
+        ],
+    );
+    assert_doesnt_contain_strings(book.join("second.html"), &["cdnjs", "<em>i"]);
+    assert_doesnt_contain_strings(book.join("intro.html"), &["\u{E000}"]);
+}
+
+#[test]
+fn katex_renders_the_math_between_dollar_signs() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[output.html]\nmath-renderer = \"katex\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    let second = "# Second Chapter\n\nThe sum $x_i * y_i$ costs $5 or $10, not \\$x$.\n\n\
+                  $$\na_i * b_i\n$$\n";
+    write_file(&temp.path().join("src"), "second.md", second.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_contains_strings(
+        book.join("second.html"),
+        &[
+            "katex.min.js",
+            "auto-render.min.js",
+            r"<p>The sum \(x_i * y_i\) costs $5 or $10, not $x$.</p>",
+            "<p>\\[\na_i * b_i\n\\]</p>",
+        ],
+    );
+    assert_doesnt_contain_strings(book.join("second.html"), &["MathJax.js"]);
+
+    let book_toml = "[output.html]\nmath-renderer = \"katex\"\nmathjax-support = true\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    assert!(MDBook::load(temp.path()).unwrap().build().is_err());
+}
+
+#[test]
+fn the_default_theme_renders_with_strict_templates() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[output.html]\nstrict-templates = true\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+}
+
+#[test]
+fn template_errors_say_where_they_are() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[output.html]\nstrict-templates = true\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    let header = "<div>\n  <span>{{ book_titel }}</span>\n</div>\n";
+    write_file(&temp.path().join("theme"), "header.hbs", header.as_bytes()).unwrap();
+
+    let err = MDBook::load(temp.path()).unwrap().build().unwrap_err();
+    let got = format!("{:#}", err);
+    assert!(
+        got.contains("Error in the template header.hbs, line 2, column 9:"),
+        "{}",
+        got
+    );
+    assert!(got.contains("book_titel"), "{}", got);
+
+    let header = "<div>\n  {{#if book_title}}\n</div>\n";
+    write_file(&temp.path().join("theme"), "header.hbs", header.as_bytes()).unwrap();
+
+    let err = MDBook::load(temp.path()).unwrap().build().unwrap_err();
+    let got = format!("{:#}", err);
+    assert!(
+        got.contains("Invalid template header.hbs, line "),
+        "{}",
+        got
+    );
+    assert!(!got.contains("Unable to render"), "{}", got);
+}
+
+#[test]
+fn themes_can_have_partials_and_other_templates() {
+    let temp = DummyBook::new().build().unwrap();
+    let theme = temp.path().join("theme");
+    let mut index = mdbook::theme::INDEX.to_vec();
+    index.extend_from_slice(b"\n{{> footer}}");
+    write_file(&theme, "index.hbs", &index).unwrap();
+    write_file(
+        &theme,
+        "footer.hbs",
+        b"<footer>The end of {{ chapter_title }}</footer>",
+    )
+    .unwrap();
+    write_file(
+        &theme.join("templates"),
+        "landing.hbs",
+        b"<main class=\"landing\">{{{ content }}}</main>\n{{> footer}}",
+    )
+    .unwrap();
+    let second = "+++\ntemplate = \"landing\"\n+++\n\n# Second Chapter\n";
+    write_file(&temp.path().join("src"), "second.md", second.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_contains_strings(
+        book.join("intro.html"),
+        &["<footer>The end of Introduction</footer>"],
+    );
+    assert_contains_strings(
+        book.join("second.html"),
+        &[
+            "<main class=\"landing\"><h1>",
+            "<footer>The end of Second Chapter</footer>",
+        ],
+    );
+    assert_doesnt_contain_strings(book.join("second.html"), &["<nav"]);
+
+    let second = "+++\ntemplate = \"missing\"\n+++\n\n# Second Chapter\n";
+    write_file(&temp.path().join("src"), "second.md", second.as_bytes()).unwrap();
+    let err = MDBook::load(temp.path()).unwrap().build().unwrap_err();
+    assert!(format!("{:#}", err).contains("missing.hbs"));
+
+    write_file(&theme.join("templates"), "footer.hbs", b"again").unwrap();
+    let err = MDBook::load(temp.path()).unwrap().build().unwrap_err();
+    assert!(format!("{:#}", err).contains("The template \"footer\" is both"));
+}
+
+#[test]
+fn footnotes_are_listed_at_the_end_of_each_chapter() {
+    let temp = DummyBook::new().build().unwrap();
+    let nested = "# Nested Chapter\n\nOnce[^1] and twice[^1].\n\n\
+                  [^1]: The footnote.\n\n[^unused]: Never referenced.\n\nThe end.\n";
+    write_file(
+        &temp.path().join("src"),
+        "first/nested.md",
+        nested.as_bytes(),
+    )
+    .unwrap();
+    let second = "# Second Chapter\n\nAnother[^1].\n\n[^1]: Another footnote.\n";
+    write_file(&temp.path().join("src"), "second.md", second.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
+    let nested_footnotes = [
+        r##"<sup class="footnote-reference" id="first-nested-fnref-1"><a href="#first-nested-fn-1">1</a></sup>"##,
+        r##"<sup class="footnote-reference" id="first-nested-fnref-1-2"><a href="#first-nested-fn-1">1</a></sup>"##,
+        "<p>The end.</p>\n<section class=\"footnotes\">",
+        r##"<li id="first-nested-fn-1">"##,
+        r##"<p>The footnote. <a href="#first-nested-fnref-1" class="footnote-backref">↩</a></p>"##,
+    ];
+    assert_contains_strings(book.join("first/nested.html"), &nested_footnotes);
+    assert_doesnt_contain_strings(book.join("first/nested.html"), &["Never referenced"]);
+
+    // The IDs of the parts of the print page start with the chapters' paths
+    let print = book.join("print.html");
+    let print_footnotes: Vec<_> = nested_footnotes
+        .iter()
+        .map(|footnote| footnote.replace("first-nested-", "first-nested-md-"))
+        .collect();
+    let print_footnotes: Vec<_> = print_footnotes.iter().map(String::as_str).collect();
+    assert_contains_strings(&print, &print_footnotes);
+    assert_contains_strings(
+        &print,
+        &[
+            r##"<a href="#second-md-fn-1">1</a>"##,
+            r##"<li id="second-md-fn-1">"##,
+        ],
+    );
+}
+
+#[test]
+fn admonitions_are_rendered_as_callouts() {
+    let temp = DummyBook::new().build().unwrap();
+    let chapter = "# Nested Chapter\n\n> [!WARNING]\n> Mind the gap.\n\n\
+                   > [!BOGUS]\n> Just a quote.\n";
+    write_file(
+        &temp.path().join("src"),
+        "first/nested.md",
+        chapter.as_bytes(),
+    )
+    .unwrap();
+    let book_toml = "[output.html.admonitions.titles]\nwarning = \"Achtung\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let nested = temp.path().join("book/first/nested.html");
+    let print = temp.path().join("book/print.html");
+    for page in &[&nested, &print] {
+        assert_contains_strings(
+            page,
+            &[
+                "<div class=\"admonition warning\">\n\
+                 <p class=\"admonition-title\">Achtung</p>\n<p>Mind the gap.</p>\n</div>",
+                "<blockquote>\n<p>[!BOGUS]\nJust a quote.</p>\n</blockquote>",
+            ],
+        );
+    }
+
+    let book_toml = "[output.html.admonitions]\nenable = false\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    assert_contains_strings(&nested, &["<p>[!WARNING]\nMind the gap.</p>"]);
+    assert_doesnt_contain_strings(&nested, &["admonition warning"]);
+}
+
+#[test]
+#[cfg(feature = "syntect")]
+fn passthrough_code_blocks_are_left_for_javascript() {
+    let temp = DummyBook::new().build().unwrap();
+    let chapter = "# Nested Chapter\n\n\
+                   ```mermaid\ngraph TD;\n  A-->B;\n```\n\n\
+                   ```rust\nfn main() {}\n```\n";
+    write_file(
+        &temp.path().join("src"),
+        "first/nested.md",
+        chapter.as_bytes(),
+    )
+    .unwrap();
+    let book_toml = "[output.html]\nhighlight = \"syntect\"\n\
+                     passthrough-code-languages = [\"mermaid\", \"graphviz\"]\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let nested = temp.path().join("book/first/nested.html");
+    let print = temp.path().join("book/print.html");
+    for page in &[nested, print] {
+        assert_contains_strings(
+            page,
+            &[
+                "<pre class=\"mermaid\">graph TD;\n  A--&gt;B;\n</pre>",
+                r#"<code class="language-rust syn-code"><span class="syn-"#,
+            ],
+        );
+        assert_doesnt_contain_strings(page, &["language-mermaid"]);
+    }
+}
+
+#[test]
+fn only_blocks_are_kept_for_the_renderers_they_name() {
+    let temp = DummyBook::new().build().unwrap();
+    let chapter = "# Nested Chapter\n\n\
+                   {{#only html}}\n\
+                   Open the interactive demo.\n\
+                   {{#endonly}}\n\
+                   {{#only markdown epub}}\n\
+                   See the screenshot of the demo.\n\
+                   {{#endonly}}\n";
+    write_file(
+        &temp.path().join("src"),
+        "first/nested.md",
+        chapter.as_bytes(),
+    )
+    .unwrap();
+    write_file(
+        temp.path(),
+        "book.toml",
+        b"[output.html]\n[output.markdown]\n",
+    )
+    .unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let html = temp.path().join("book/html/first/nested.html");
+    assert_contains_strings(&html, &["Open the interactive demo."]);
+    assert_doesnt_contain_strings(&html, &["See the screenshot", "#only", "#endonly"]);
+
+    let markdown = temp.path().join("book/markdown/first/nested.md");
+    assert_contains_strings(&markdown, &["See the screenshot of the demo."]);
+    assert_doesnt_contain_strings(&markdown, &["interactive demo", "#only", "#endonly"]);
+}
+
+#[test]
+fn only_blocks_are_kept_without_the_default_preprocessors() {
+    let temp = DummyBook::new().build().unwrap();
+    let chapter = "# Nested Chapter\n\n\
+                   {{#only html}}\n\
+                   Open the interactive demo.\n\
+                   {{#endonly}}\n\
+                   {{#only epub}}\n\
+                   See the screenshot of the demo.\n\
+                   {{#endonly}}\n";
+    write_file(
+        &temp.path().join("src"),
+        "first/nested.md",
+        chapter.as_bytes(),
+    )
+    .unwrap();
+    write_file(
+        temp.path(),
+        "book.toml",
+        b"[build]\nuse-default-preprocessors = false\n",
+    )
+    .unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let html = temp.path().join("book/first/nested.html");
+    assert_contains_strings(&html, &["Open the interactive demo."]);
+    assert_doesnt_contain_strings(&html, &["See the screenshot", "#only", "#endonly"]);
+}
+
+#[test]
+fn an_unclosed_only_block_fails_the_build() {
+    let temp = DummyBook::new().build().unwrap();
+    let chapter = "# Nested Chapter\n\n{{#only html}}\nNever closed.\n";
+    write_file(
+        &temp.path().join("src"),
+        "first/nested.md",
+        chapter.as_bytes(),
+    )
+    .unwrap();
+
+    let err = MDBook::load(temp.path()).unwrap().build().unwrap_err();
+    let message = format!("{:?}", err);
+    assert!(
+        message.contains("first/nested.md:3: the `{{#only}}` block isn't closed"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn the_markdown_renderer_writes_the_preprocessed_chapters_and_the_whole_book() {
+    let temp = DummyBook::new().build().unwrap();
+    write_file(temp.path(), "book.toml", b"[output.markdown]\n").unwrap();
+    let second = "+++\ndraft = true\n+++\n# Second Chapter\n";
+    write_file(&temp.path().join("src"), "second.md", second.as_bytes()).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    let book = temp.path().join("book");
+    assert!(!book.join("second.md").exists());
+    assert!(!book.join("second/nested.md").exists());
+    assert_contains_strings(
+        book.join("first/includes.md"),
+        &["# Includes", "# Summary\n\n[Dummy Book](README.md)"],
+    );
+    assert!(!book.join("first/includes.html").exists());
+
+    let combined = fs::read_to_string(book.join("combined.md")).unwrap();
+    let headings: Vec<&str> = combined
+        .lines()
+        .filter(|line| line.starts_with('#') || *line == "---")
+        .collect();
+    assert_eq!(
+        &headings[..6],
+        &[
+            "# Dummy Book",
+            "---",
+            "# Introduction",
+            "# First Chapter",
+            "## Some Section",
+            "## Nested Chapter"
+        ]
+    );
+    assert!(headings.contains(&"## Summary"), "{:?}", headings);
+    assert!(
+        headings.ends_with(&["---", "# Conclusion"]),
+        "{:?}",
+        headings
+    );
+    assert!(!combined.contains("# Second Chapter"));
+    // The links are relative to the root of the book
+    assert!(combined.contains("[Dummy Book](first/README.md)"));
+}
+
+struct Signature;
+
+impl mdbook::renderer::ChapterPostProcessor for Signature {
+    fn name(&self) -> &str {
+        "signature"
+    }
+
+    fn process(&self, chapter: &mdbook::book::Chapter, html: String) -> Result<String> {
+        anyhow::ensure!(!chapter.content.contains("unsigned"), "Not this one");
+        Ok(html.replace("</body>", &format!("<!-- {} --></body>", chapter.name)))
+    }
+}
+
+#[test]
+fn the_pages_go_through_the_post_processors() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[book]\ntitle = \"Signed\"\n\
+                     [output.html]\nincremental = true\n\
+                     [output.html.single-file]\nenable = true\n\
+                     [[output.html.post-process]]\nname = \"lazy-images\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    let src = temp.path().join("src");
+    write_file(&src, "conclusion.md", b"# Conclusion\n\nunsigned\n").unwrap();
+
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.with_post_processor(Signature);
+    let err = md.build().unwrap_err();
+    assert_eq!(
+        format!("{:#}", err),
+        "Rendering failed: Unable to render \"Conclusion\": \
+         The \"signature\" post-processor failed on conclusion.md: Not this one"
+    );
+
+    write_file(&src, "conclusion.md", b"# Conclusion\n").unwrap();
+    md.book = MDBook::load(temp.path()).unwrap().book;
+    md.build().unwrap();
+
+    let nested = temp.path().join("book/second/nested.html");
+    assert_contains_strings(
+        &nested,
+        &[
+            r#"<img loading="lazy" src="../images/picture.png" alt="Some image" />"#,
+            r#"<img loading="lazy" src="../images/picture.png" alt="raw html">"#,
+            "<!-- Nested Chapter --></body>",
+        ],
+    );
+    assert_doesnt_contain_strings(&nested, &["<img src="]);
+    assert_contains_strings(
+        temp.path().join("book/index.html"),
+        &["<!-- Dummy Book --></body>"],
+    );
+    // The pages which aren't of a chapter too
+    for page in &["print.html", "book.html"] {
+        assert_contains_strings(
+            temp.path().join("book").join(page),
+            &["<!-- Signed --></body>"],
+        );
+    }
+    assert_contains_strings(
+        temp.path().join("book/404.html"),
+        &["<!-- Page not found - Signed --></body>"],
+    );
+
+    // The registered post-processors aren't in the hashes, so they turn off
+    // the incremental builds
+    md.build().unwrap();
+    assert!(!temp.path().join("book/.mdbook-cache.json").exists());
+    assert_contains_strings(&nested, &["<!-- Nested Chapter --></body>"]);
+}
+
+#[test]
+fn the_book_is_archived_beside_the_build_directory() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_toml = "[output.html]\narchive = \"zip\"\nincremental = true\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let archive_path = temp.path().join("book.zip");
+    assert_eq!(md.archives(), vec![archive_path.clone()]);
+    let archive = fs::read(&archive_path).unwrap();
+    let contains = |name: &str| archive.windows(name.len()).any(|w| w == name.as_bytes());
+    assert!(contains("index.html"));
+    assert!(contains("first/nested.html"));
+    assert!(contains("css/general.css"));
+    assert!(!contains(".mdbook-cache.json"));
+
+    // Nothing changed, so neither did the archive
+    md.build().unwrap();
+    assert_eq!(fs::read(&archive_path).unwrap(), archive);
+
+    md.clean().unwrap();
+    assert!(!archive_path.exists());
+}
+
+#[test]
+fn the_links_of_every_page_are_checked() {
+    let temp = DummyBook::new().build().unwrap();
+    let src = temp.path().join("src");
+    let summary = "# Summary\n\n- [Intro](intro.md)\n- [Nested](first/nested.md)\n";
+    write_file(&src, "SUMMARY.md", summary.as_bytes()).unwrap();
+    let chapter = "# Nested Chapter\n\n[Back](../intro.md#introduction)\n";
+    write_file(&src, "first/nested.md", chapter.as_bytes()).unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config
+        .set("output.html.link-check.enable", true)
+        .unwrap();
+    md.build().unwrap();
+
+    let chapter = "# Nested Chapter\n\n[Gone](../gone.md) and [nowhere](#nowhere).\n";
+    write_file(&src, "first/nested.md", chapter.as_bytes()).unwrap();
+    write_file(&src, "static/raw.html", b"<a href=\"nope.html\">Nope</a>").unwrap();
+    let md = MDBook::load_with_config(temp.path(), md.config.clone()).unwrap();
+    let err = md.build().unwrap_err();
+
+    // The links of the chapter are reported once, with the chapter, and not
+    // again with the print page
+    assert_eq!(
+        err.root_cause().to_string(),
+        "Found 3 broken links:\n    \
+         \"Nested\" (first/nested.md): ../gone.html (no such file)\n    \
+         \"Nested\" (first/nested.md): #nowhere (no such heading)\n    \
+         static/raw.html:\n        \
+         nope.html (no such file)"
+    );
+}
+
+#[test]
+fn the_whole_book_can_be_rendered_to_a_single_file() {
+    let temp = DummyBook::new().build().unwrap();
+    let src = temp.path().join("src");
+    let chapter = "# Nested Chapter\n\n## Some Section\n\n\
+                   Back to [the introduction](../intro.md), [a section](index.md#some-section) \
+                   and [this one](#some-section).\n\n\
+                   ![Logo](../images/logo.png) ![Photo](../images/photo.png)\n";
+    write_file(&src, "first/nested.md", chapter.as_bytes()).unwrap();
+    write_file(&src, "images/logo.png", b"logo").unwrap();
+    write_file(&src, "images/photo.png", &[0; 2048]).unwrap();
+    let book_toml = "[output.html.single-file]\nenable = true\nmax-image-size = 1024\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let book_html = temp.path().join("book/book.html");
+    assert_contains_strings(
+        &book_html,
+        &[
+            r#"<div id="first-nested-md">"#,
+            r##"<h2><a class="header" href="#first-nested-md-some-section" id="first-nested-md-some-section">Some Section</a></h2>"##,
+            r##"<a href="#intro-md">the introduction</a>"##,
+            r##"<a href="#first-index-md-some-section">a section</a>"##,
+            r##"<a href="#first-nested-md-some-section">this one</a>"##,
+            r#"<img src="data:image/png;base64,bG9nbw==" alt="Logo" />"#,
+            r#"<img src="first/../images/photo.png" alt="Photo" />"#,
+            "<style>",
+        ],
+    );
+    assert_doesnt_contain_strings(
+        &book_html,
+        &[
+            r#"<link rel="stylesheet" href="css/"#,
+            r#"<script src="book.js""#,
+        ],
+    );
+    let warning = Diagnostic::new(
+        DiagnosticKind::Other,
+        "images/photo.png is 2048 bytes, more than output.html.single-file.max-image-size, \
+         so book.html links to it instead of embedding it",
+    );
+    assert!(md.warnings().contains(&warning), "{:?}", md.warnings());
+}
+
+/// A book with a bit of everything `SUMMARY.md` can have.
+fn small_book() -> tempfile::TempDir {
+    let temp = TempFileBuilder::new().prefix("mdbook").tempdir().unwrap();
+    let src = temp.path().join("src");
+    let files: &[(&str, &str)] = &[
+        (
+            "SUMMARY.md",
+            "# Summary\n\n[Welcome](README.md)\n\n# Guide\n\n\
+             - [Installation](guide/install.md)\n  \
+             - [From Source](guide/source.md)\n\
+             - [Upcoming]()\n\n---\n\n[Credits](credits.md)\n",
+        ),
+        ("README.md", "# Welcome\n\nThis is a small book.\n"),
+        (
+            "guide/install.md",
+            "# Installation\n\nRun `cargo install mdbook`.\n\n## With `cargo`\n\n## Usage\n",
+        ),
+        (
+            "guide/source.md",
+            "+++\ndraft = true\n+++\n\n# From Source\n",
+        ),
+        ("credits.md", "Thanks to everyone!\n"),
+    ];
+    for (path, content) in files {
+        write_file(&src, path, content.as_bytes()).unwrap();
+    }
+    let book_toml = "[book]\ntitle = \"A Small Book\"\n\n[output.json]\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    temp
+}
+
+#[test]
+fn the_json_manifest_hasnt_changed_accidentally() {
+    // Set this to `true` to regenerate the fixture after changing the
+    // manifest on purpose, then change it back and commit the fixture.
+    const GENERATE_FIXTURE: bool = false;
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/json_manifest_fixture.json");
+
+    let temp = small_book();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+    let manifest = fs::read_to_string(temp.path().join("book/book.json")).unwrap();
+
+    if GENERATE_FIXTURE {
+        fs::write(&fixture, &manifest).unwrap();
+    }
+    assert_eq!(manifest, fs::read_to_string(&fixture).unwrap());
+}
+
+#[cfg(feature = "search")]
+mod search {
+    use crate::dummy_book::DummyBook;
+    use mdbook::utils::fs::write_file;
+    use mdbook::MDBook;
+    use std::fs::{self, File};
+    use std::path::Path;
+
+    fn read_book_index(root: &Path) -> serde_json::Value {
+        let index = root.join("book/searchindex.js");
+        let index = fs::read_to_string(index).unwrap();
+        let index = index.trim_start_matches("Object.assign(window.search, ");
+        let index = index.trim_end_matches(");");
+        serde_json::from_str(index).unwrap()
+    }
+
+    #[test]
+    fn a_stripped_h1_has_no_anchor_in_the_search_index() {
+        let temp = DummyBook::new().build().unwrap();
+        let book_toml = "[output.html]\nstrip-first-h1 = true\n";
+        write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+        MDBook::load(temp.path()).unwrap().build().unwrap();
+
+        let index = read_book_index(temp.path());
+
+        let doc_urls = index["doc_urls"].as_array().unwrap();
+        assert!(doc_urls.iter().any(|url| url == "first/index.html"));
+        assert!(!doc_urls
+            .iter()
+            .any(|url| url == "first/index.html#first-chapter"));
+        assert!(doc_urls
+            .iter()
+            .any(|url| url == "first/nested.html#some-section"));
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn book_creates_reasonable_search_index() {
+        let temp = DummyBook::new().build().unwrap();
+        let md = MDBook::load(temp.path()).unwrap();
+        md.build().unwrap();
+
+        let index = read_book_index(temp.path());
+
+        let doc_urls = index["doc_urls"].as_array().unwrap();
+        let get_doc_ref =
+            |url: &str| -> String { doc_urls.iter().position(|s| s == url).unwrap().to_string() };
+
+        let first_chapter = get_doc_ref("first/index.html#first-chapter");
+        let introduction = get_doc_ref("intro.html#introduction");
+        let some_section = get_doc_ref("first/index.html#some-section");
+        let summary = get_doc_ref("first/includes.html#summary");
+        let conclusion = get_doc_ref("conclusion.html#conclusion");
+
+        let bodyidx = &index["index"]["index"]["body"]["root"];
+        let textidx = &bodyidx["t"]["e"]["x"]["t"];
+        assert_eq!(textidx["df"], 2);
+        assert_eq!(textidx["docs"][&first_chapter]["tf"], 1.0);
+        assert_eq!(textidx["docs"][&introduction]["tf"], 1.0);
+
+        let docs = &index["index"]["documentStore"]["docs"];
+        assert_eq!(docs[&first_chapter]["body"], "more text.");
+        assert_eq!(docs[&some_section]["body"], "");
+        assert_eq!(
+            docs[&summary]["body"],
+            "Dummy Book Introduction First Chapter Nested Chapter Includes Recursive Markdown Unicode Second Chapter Nested Chapter Conclusion"
+        );
+        assert_eq!(docs[&summary]["breadcrumbs"], "First Chapter » Summary");
+        assert_eq!(docs[&conclusion]["body"], "I put &lt;HTML&gt; in here!");
+    }
+
+    #[test]
+    fn excluded_chapters_are_rendered_but_not_indexed() {
+        let temp = DummyBook::new().build().unwrap();
+        let book_toml = "[output.html.search]\nexclude = [\"second/**\"]\n";
+        write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+        let second = "+++\nsearch = false\n+++\n\n# Second Chapter\n\nZanzibar.\n";
+        write_file(&temp.path().join("src"), "second.md", second.as_bytes()).unwrap();
+        MDBook::load(temp.path()).unwrap().build().unwrap();
+
+        let index = read_book_index(temp.path());
+        let doc_urls = index["doc_urls"].as_array().unwrap();
+        assert!(doc_urls.iter().any(|url| url == "intro.html#introduction"));
+        assert!(!doc_urls
+            .iter()
+            .any(|url| url.as_str().unwrap().starts_with("second")));
+        assert!(index["index"]["index"]["body"]["root"]["z"]["a"]["n"].is_null());
+
+        let book = temp.path().join("book");
+        assert!(fs::read_to_string(book.join("second.html"))
+            .unwrap()
+            .contains("Zanzibar"));
+        assert!(book.join("second/nested.html").exists());
+        assert!(fs::read_to_string(book.join("intro.html"))
+            .unwrap()
+            .contains(r#"<a href="second.html""#));
+    }
+
+    #[test]
+    fn disabled_search_writes_no_index() {
+        let temp = DummyBook::new().build().unwrap();
+        let mut md = MDBook::load(temp.path()).unwrap();
+        md.config.set("output.html.search.enable", false).unwrap();
+        md.build().unwrap();
+
+        let book = temp.path().join("book");
+        assert!(!book.join("searchindex.json").exists());
+        assert!(!book.join("searchindex.js").exists());
+        assert!(!book.join("searcher.js").exists());
+
+        let index = fs::read_to_string(book.join("index.html")).unwrap();
+        assert!(!index.contains("searchindex.js"));
     }
 
     // Setting this to `true` may cause issues with `cargo watch`,
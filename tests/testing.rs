@@ -2,7 +2,9 @@ mod dummy_book;
 
 use crate::dummy_book::DummyBook;
 
+use mdbook::utils::fs::write_file;
 use mdbook::MDBook;
+use tempfile::Builder as TempFileBuilder;
 
 #[test]
 fn mdbook_can_correctly_test_a_passing_book() {
@@ -24,3 +26,69 @@ fn mdbook_detects_book_with_failing_tests() {
 
     assert!(md.test(vec![]).is_err());
 }
+
+#[test]
+fn failing_tests_are_reported_at_their_source_line() {
+    let temp = DummyBook::new().with_passing_test(false).build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+
+    let err = md.test(vec![]).unwrap_err().to_string();
+
+    assert!(err.contains("\"Nested Chapter\""), "{}", err);
+    // a block written inline
+    assert!(err.contains("the code block at line 5"), "{}", err);
+    // a block after a rustdoc_include, which adds lines to the tested file
+    assert!(err.contains("the code block at line 29"), "{}", err);
+}
+
+#[test]
+fn tests_use_the_edition_of_the_playpen() {
+    let temp = DummyBook::new().with_passing_test(true).build().unwrap();
+    // `TryFrom` is only in the prelude of the 2021 edition
+    let chapter = "# Edition\n\n```rust\nlet x = u8::try_from(1u32).unwrap();\n```\n";
+    write_file(
+        &temp.path().join("src"),
+        "first/nested.md",
+        chapter.as_bytes(),
+    )
+    .unwrap();
+
+    let book_toml = "[rust]\nedition = \"2018\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    assert!(MDBook::load(temp.path()).unwrap().test(vec![]).is_err());
+
+    let book_toml = "[rust]\nedition = \"2018\"\n[output.html.playpen]\nedition = \"2021\"\n";
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    let result = MDBook::load(temp.path()).unwrap().test(vec![]);
+    assert!(
+        result.is_ok(),
+        "Tests failed with {}",
+        result.err().unwrap()
+    );
+}
+
+#[test]
+fn every_translation_is_tested() {
+    let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+    let root = temp.path();
+    let book_toml = "[book]\nlanguage = \"en\"\n\n\
+                     [language.en]\nname = \"English\"\n\n\
+                     [language.fr]\nname = \"Français\"\n";
+    write_file(root, "book.toml", book_toml.as_bytes()).unwrap();
+    let src = root.join("src");
+    for &code in &["en", "fr"] {
+        write_file(&src.join(code), "SUMMARY.md", b"- [Intro](intro.md)\n").unwrap();
+        write_file(
+            &src.join(code),
+            "intro.md",
+            b"# Intro\n\n```rust\nassert_eq!(1 + 1, 2);\n```\n",
+        )
+        .unwrap();
+    }
+    assert!(MDBook::load(root).unwrap().test(vec![]).is_ok());
+
+    let failing = "# Intro\n\n```rust\nassert_eq!(1 + 1, 3);\n```\n";
+    write_file(&src.join("fr"), "intro.md", failing.as_bytes()).unwrap();
+    let err = MDBook::load(root).unwrap().test(vec![]).unwrap_err();
+    assert!(err.to_string().contains("\"Intro\" (intro.md)"), "{}", err);
+}